@@ -0,0 +1,12 @@
+-- Create user roles
+
+-- Role assignments (e.g. 'admin'); removed with the user
+CREATE TABLE IF NOT EXISTS user_roles
+(
+    user_id    INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    role       VARCHAR(50) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, role)
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_roles_role ON user_roles (role);
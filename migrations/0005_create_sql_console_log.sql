@@ -0,0 +1,15 @@
+-- Create SQL console audit log
+
+-- Every query run through the admin SQL console, including rejected ones
+CREATE TABLE IF NOT EXISTS sql_console_log
+(
+    id          SERIAL PRIMARY KEY,
+    user_id     INTEGER     REFERENCES users (id) ON DELETE SET NULL,
+    query       TEXT        NOT NULL,
+    row_count   INTEGER              DEFAULT NULL,
+    error       TEXT                 DEFAULT NULL,
+    duration_ms INTEGER     NOT NULL DEFAULT 0,
+    created_at  TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_sql_console_log_created_at ON sql_console_log (created_at DESC);
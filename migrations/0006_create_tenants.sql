@@ -0,0 +1,24 @@
+-- Create tenants and theming
+
+-- Organizations served from their own subdomain, with optional branding
+CREATE TABLE IF NOT EXISTS tenants
+(
+    id            SERIAL PRIMARY KEY,
+    slug          VARCHAR(63)  NOT NULL UNIQUE,
+    name          VARCHAR(100) NOT NULL,
+    primary_color VARCHAR(7)            DEFAULT NULL,
+    accent_color  VARCHAR(7)            DEFAULT NULL,
+    logo_url      VARCHAR(500)          DEFAULT NULL,
+    created_at    TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    updated_at    TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+-- Template partial overrides (e.g. 'partials/footer.html'); removed with the tenant
+CREATE TABLE IF NOT EXISTS tenant_partials
+(
+    tenant_id  INTEGER      NOT NULL REFERENCES tenants (id) ON DELETE CASCADE,
+    name       VARCHAR(100) NOT NULL,
+    body       TEXT         NOT NULL,
+    updated_at TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (tenant_id, name)
+);
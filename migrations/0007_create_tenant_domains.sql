@@ -0,0 +1,14 @@
+-- Create custom domains for tenants
+
+-- Domains routed to a tenant once ownership is proven with a DNS TXT record
+CREATE TABLE IF NOT EXISTS tenant_domains
+(
+    id                 SERIAL PRIMARY KEY,
+    tenant_id          INTEGER      NOT NULL REFERENCES tenants (id) ON DELETE CASCADE,
+    domain             VARCHAR(253) NOT NULL UNIQUE,
+    verification_token VARCHAR(64)  NOT NULL,
+    verified_at        TIMESTAMPTZ           DEFAULT NULL,
+    created_at         TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_tenant_domains_tenant_id ON tenant_domains (tenant_id);
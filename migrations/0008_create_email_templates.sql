@@ -0,0 +1,15 @@
+-- Create email template versions
+
+-- Admin edits of transactional email templates; every save adds a version and
+-- the highest version of a name overrides the bundled file templates
+CREATE TABLE IF NOT EXISTS email_templates
+(
+    id         SERIAL PRIMARY KEY,
+    name       VARCHAR(100) NOT NULL,
+    version    INTEGER      NOT NULL,
+    subject    TEXT         NOT NULL,
+    body       TEXT         NOT NULL,
+    created_by INTEGER      REFERENCES users (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    UNIQUE (name, version)
+);
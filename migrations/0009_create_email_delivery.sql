@@ -0,0 +1,25 @@
+-- Create email outbox and suppression list
+
+-- Every email the application tried to send
+CREATE TABLE IF NOT EXISTS email_outbox
+(
+    id         SERIAL PRIMARY KEY,
+    template   VARCHAR(100) NOT NULL,
+    recipient  VARCHAR(255) NOT NULL,
+    subject    TEXT         NOT NULL,
+    status     VARCHAR(20)  NOT NULL,
+    error      TEXT                  DEFAULT NULL,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_email_outbox_created_at ON email_outbox (created_at DESC);
+
+-- Lowercased addresses reported as bouncing or complaining; nothing is sent to them
+CREATE TABLE IF NOT EXISTS email_suppressions
+(
+    email      VARCHAR(255) PRIMARY KEY,
+    reason     VARCHAR(20)  NOT NULL,
+    provider   VARCHAR(20)  NOT NULL,
+    detail     TEXT                  DEFAULT NULL,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
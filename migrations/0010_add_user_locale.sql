@@ -0,0 +1,4 @@
+-- Add a preferred locale to users
+
+-- BCP 47 tag such as "de" or "pt-BR"; NULL means the default locale
+ALTER TABLE users ADD COLUMN IF NOT EXISTS locale VARCHAR(35);
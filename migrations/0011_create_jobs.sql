@@ -0,0 +1,21 @@
+-- Create the background job queue
+
+-- Work for the job worker. A worker claims a due job by marking it running
+-- and setting locked_by; on shutdown it hands unfinished jobs back.
+CREATE TABLE IF NOT EXISTS jobs
+(
+    id           BIGSERIAL PRIMARY KEY,
+    kind         VARCHAR(100) NOT NULL,
+    payload      TEXT         NOT NULL DEFAULT '{}',
+    status       VARCHAR(20)  NOT NULL DEFAULT 'queued',
+    attempts     INTEGER      NOT NULL DEFAULT 0,
+    max_attempts INTEGER      NOT NULL DEFAULT 5,
+    last_error   TEXT                  DEFAULT NULL,
+    locked_by    VARCHAR(100)          DEFAULT NULL,
+    locked_at    TIMESTAMPTZ           DEFAULT NULL,
+    run_at       TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    created_at   TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    finished_at  TIMESTAMPTZ           DEFAULT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_due ON jobs (run_at) WHERE status = 'queued';
@@ -0,0 +1,20 @@
+-- Track job worker liveness
+
+-- One row per running worker, refreshed every heartbeat and removed when the
+-- worker stops. A row whose heartbeat is older than the stall timeout belongs
+-- to a worker that died or hung; the watchdog sets stalled_at when it first
+-- notices, so each stall is alerted once.
+CREATE TABLE IF NOT EXISTS job_workers
+(
+    id           VARCHAR(100) PRIMARY KEY,
+    hostname     VARCHAR(255) NOT NULL,
+    running_jobs INTEGER      NOT NULL DEFAULT 0,
+    started_at   TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    heartbeat_at TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    stalled_at   TIMESTAMPTZ           DEFAULT NULL
+);
+
+-- Refreshed alongside the worker's heartbeat while a job runs
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS heartbeat_at TIMESTAMPTZ DEFAULT NULL;
+
+CREATE INDEX IF NOT EXISTS idx_jobs_running ON jobs (heartbeat_at) WHERE status = 'running';
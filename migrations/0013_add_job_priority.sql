@@ -0,0 +1,6 @@
+-- Add job priorities
+
+-- 0 = low (batch work), 1 = normal, 2 = high (urgent mail). Claims order by
+-- priority plus one level for every aging interval a job has waited, so low
+-- priority jobs are delayed but never starved.
+ALTER TABLE jobs ADD COLUMN IF NOT EXISTS priority SMALLINT NOT NULL DEFAULT 1;
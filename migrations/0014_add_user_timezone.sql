@@ -0,0 +1,4 @@
+-- Add a preferred time zone to users
+
+-- IANA name such as "Europe/Berlin"; NULL means UTC
+ALTER TABLE users ADD COLUMN IF NOT EXISTS timezone VARCHAR(64);
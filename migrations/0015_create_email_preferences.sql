@@ -0,0 +1,15 @@
+-- Create per-user email subscription preferences
+
+-- A row only exists once a user changes a category from its default
+CREATE TABLE IF NOT EXISTS email_preferences
+(
+    user_id    INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    category   VARCHAR(50) NOT NULL,
+    subscribed BOOLEAN     NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, category)
+);
+
+-- Secret identifying a user in one-click unsubscribe links
+ALTER TABLE users ADD COLUMN IF NOT EXISTS unsubscribe_token UUID NOT NULL DEFAULT gen_random_uuid();
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_unsubscribe_token ON users (unsubscribe_token);
@@ -0,0 +1,15 @@
+-- Create the uploads table for user-uploaded files
+
+-- File contents live in the storage backend under storage_key
+CREATE TABLE IF NOT EXISTS uploads
+(
+    id           UUID PRIMARY KEY      DEFAULT gen_random_uuid(),
+    user_id      INTEGER      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    filename     VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes   BIGINT       NOT NULL,
+    storage_key  VARCHAR(255) NOT NULL UNIQUE,
+    created_at   TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_uploads_user_id ON uploads (user_id, created_at DESC);
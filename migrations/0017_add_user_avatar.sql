@@ -0,0 +1,4 @@
+-- Add a profile picture to users
+
+-- Storage backend key of the resized avatar; NULL means no avatar
+ALTER TABLE users ADD COLUMN IF NOT EXISTS avatar_key VARCHAR(255);
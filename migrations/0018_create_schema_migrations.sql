@@ -0,0 +1,14 @@
+-- Track migrations of plugins and of apps built on axum-base
+
+-- One namespace per migration set, so plugin versions never clash with core
+-- ones or each other. Core migrations stay in _sqlx_migrations.
+CREATE TABLE IF NOT EXISTS schema_migrations
+(
+    namespace    VARCHAR(100) NOT NULL,
+    version      BIGINT       NOT NULL,
+    description  TEXT         NOT NULL,
+    checksum     BYTEA        NOT NULL,
+    execution_ms BIGINT       NOT NULL,
+    applied_at   TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (namespace, version)
+);
@@ -0,0 +1,19 @@
+-- Create the audit log of security-relevant actions
+
+-- Entries outlive their actor: deleting a user keeps what they did.
+-- target_id is text so users, tenants, and email addresses fit alike.
+CREATE TABLE IF NOT EXISTS audit_log
+(
+    id          BIGSERIAL PRIMARY KEY,
+    actor_id    INTEGER               REFERENCES users (id) ON DELETE SET NULL,
+    action      VARCHAR(100) NOT NULL,
+    target_type VARCHAR(50),
+    target_id   VARCHAR(255),
+    details     JSONB        NOT NULL DEFAULT '{}',
+    created_at  TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log (created_at DESC, id DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_id ON audit_log (actor_id, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_action ON audit_log (action, created_at DESC);
+CREATE INDEX IF NOT EXISTS idx_audit_log_target ON audit_log (target_type, target_id);
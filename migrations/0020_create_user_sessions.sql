@@ -0,0 +1,13 @@
+-- Track which sessions are signed in as which user
+
+-- The session store keys sessions by ID and keeps their data opaque, so
+-- deactivating a user looks their sessions up here to end them. Rows for
+-- sessions that expired are pruned with the expired sessions themselves.
+CREATE TABLE IF NOT EXISTS user_sessions
+(
+    session_id VARCHAR(128) PRIMARY KEY,
+    user_id    INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_user_sessions_user_id ON user_sessions (user_id);
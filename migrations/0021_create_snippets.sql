@@ -0,0 +1,14 @@
+-- Create snippets
+
+-- Small Tera templates written by admins and rendered in a sandbox; the name
+-- decides where a snippet shows, e.g. `dashboard.welcome` or `email.footer`
+CREATE TABLE IF NOT EXISTS snippets
+(
+    id          SERIAL PRIMARY KEY,
+    name        VARCHAR(100) NOT NULL UNIQUE,
+    description TEXT,
+    body        TEXT         NOT NULL,
+    updated_by  INTEGER      REFERENCES users (id) ON DELETE SET NULL,
+    created_at  TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    updated_at  TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
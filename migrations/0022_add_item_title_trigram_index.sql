@@ -0,0 +1,7 @@
+-- Add item title trigram index
+
+-- Backs the search-as-you-type suggestions at /api/items/suggest, which match
+-- titles by substring (ILIKE) and rank them by trigram similarity
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS idx_items_title_trgm ON items USING GIN (title gin_trgm_ops);
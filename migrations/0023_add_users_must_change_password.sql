@@ -0,0 +1,5 @@
+-- Add must_change_password to users
+
+-- Set by `set_password --generate --require-change`; the user is sent to their
+-- profile after signing in until they pick a password of their own
+ALTER TABLE users ADD COLUMN IF NOT EXISTS must_change_password BOOLEAN NOT NULL DEFAULT FALSE;
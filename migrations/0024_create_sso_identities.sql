@@ -0,0 +1,14 @@
+-- Link identity provider accounts to local users
+
+-- An account is known by its issuer and the subject the issuer gave it;
+-- emails and usernames can change on either side, these do not.
+CREATE TABLE IF NOT EXISTS sso_identities
+(
+    issuer     VARCHAR(255) NOT NULL,
+    subject    VARCHAR(255) NOT NULL,
+    user_id    INTEGER      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (issuer, subject)
+);
+
+CREATE INDEX IF NOT EXISTS idx_sso_identities_user_id ON sso_identities (user_id);
@@ -0,0 +1,17 @@
+-- Keep the names users had before they were renamed
+
+-- Audit entries, emails, and links elsewhere may still carry an old name;
+-- this table says whose it was. changed_by is the user who made the change,
+-- the user themselves or an admin.
+CREATE TABLE IF NOT EXISTS username_history
+(
+    id           SERIAL PRIMARY KEY,
+    user_id      INTEGER      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    old_username VARCHAR(100) NOT NULL,
+    new_username VARCHAR(100) NOT NULL,
+    changed_by   INTEGER      REFERENCES users (id) ON DELETE SET NULL,
+    changed_at   TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_username_history_user_id ON username_history (user_id);
+CREATE INDEX IF NOT EXISTS idx_username_history_old_username ON username_history (LOWER(old_username));
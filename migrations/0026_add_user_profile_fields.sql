@@ -0,0 +1,7 @@
+-- Add display name, bio, and free-form profile data to users
+
+-- Shown in place of the username where set
+ALTER TABLE users ADD COLUMN IF NOT EXISTS display_name VARCHAR(100);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS bio TEXT;
+-- A JSON object of whatever else an application wants to keep on a profile
+ALTER TABLE users ADD COLUMN IF NOT EXISTS profile_data JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,12 @@
+-- Create per-user preferences
+
+-- One row per preference a user has set; the value's JSON type is checked by
+-- the application per key. Language and time zone stay on `users`.
+CREATE TABLE IF NOT EXISTS user_preferences
+(
+    user_id    INTEGER     NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    key        VARCHAR(64) NOT NULL,
+    value      JSONB       NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, key)
+);
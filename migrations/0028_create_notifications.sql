@@ -0,0 +1,19 @@
+-- Create in-app notifications
+
+CREATE TABLE IF NOT EXISTS notifications
+(
+    id         BIGSERIAL PRIMARY KEY,
+    user_id    INTEGER      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    -- What it is about, e.g. `comment_reply`, for clients to pick an icon
+    kind       VARCHAR(50)  NOT NULL,
+    title      VARCHAR(200) NOT NULL,
+    body       TEXT,
+    -- Page the notification is about, if any
+    link       TEXT,
+    read_at    TIMESTAMPTZ,
+    created_at TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_user_created ON notifications (user_id, created_at DESC);
+-- Unread counts are taken on every page a signed-in user opens
+CREATE INDEX IF NOT EXISTS idx_notifications_unread ON notifications (user_id) WHERE read_at IS NULL;
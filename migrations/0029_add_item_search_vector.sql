@@ -0,0 +1,12 @@
+-- Add item full-text search
+
+-- Backs /api/items/search. Titles weigh more than descriptions when ranking.
+-- The column is generated, so it never goes stale.
+ALTER TABLE items
+    ADD COLUMN IF NOT EXISTS search_vector tsvector
+        GENERATED ALWAYS AS (
+            setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+            setweight(to_tsvector('english', coalesce(description, '')), 'B')
+        ) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_items_search_vector ON items USING GIN (search_vector);
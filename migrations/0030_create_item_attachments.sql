@@ -0,0 +1,17 @@
+-- Create the item_attachments table for files attached to items
+
+-- File contents live in the storage backend under storage_key
+CREATE TABLE IF NOT EXISTS item_attachments
+(
+    id           UUID PRIMARY KEY      DEFAULT gen_random_uuid(),
+    item_id      INTEGER      NOT NULL REFERENCES items (id) ON DELETE CASCADE,
+    -- The uploader, who may delete it along with admins
+    user_id      INTEGER      NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+    filename     VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255) NOT NULL,
+    size_bytes   BIGINT       NOT NULL,
+    storage_key  VARCHAR(255) NOT NULL UNIQUE,
+    created_at   TIMESTAMPTZ  NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_item_attachments_item_id ON item_attachments (item_id, created_at);
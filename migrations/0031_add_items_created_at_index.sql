@@ -0,0 +1,3 @@
+-- Index the default item order, so keyset pages seek instead of scanning
+
+CREATE INDEX IF NOT EXISTS idx_items_created_at ON items (created_at DESC, id DESC);
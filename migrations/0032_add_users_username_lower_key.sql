@@ -0,0 +1,4 @@
+-- Usernames are unique in any letter case; renames and inserts that would
+-- clash with another user's name fail on this index
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_username_lower_key ON users (LOWER(username));
@@ -0,0 +1,324 @@
+//! # ACME TLS
+//!
+//! HTTPS with automatically issued certificates, compiled only with the
+//! `acme` feature. Every domain gets its own certificate, so one tenant's
+//! domain failing validation does not hold up the others. [`CertResolver`]
+//! picks the certificate for a connection by its SNI name and answers
+//! TLS-ALPN-01 challenges; [`AcmeListener`] accepts TCP connections and
+//! hands finished TLS streams to `axum::serve`. rustls-acme renews each
+//! certificate before expiry and caches the account and certificates on disk.
+//!
+//! [`refresh_task`] re-reads the verified tenant domains every
+//! [`REFRESH_INTERVAL`], so a newly verified domain is issued a certificate
+//! without a restart and one that was removed stops being served.
+
+use axum::serve::Listener;
+use futures_util::StreamExt;
+use rustls_acme::acme::ACME_TLS_ALPN_NAME;
+use rustls_acme::caches::DirCache;
+use rustls_acme::futures_rustls::LazyConfigAcceptor;
+use rustls_acme::futures_rustls::rustls::ServerConfig;
+use rustls_acme::futures_rustls::rustls::server::{Acceptor, ClientHello, ResolvesServerCert};
+use rustls_acme::futures_rustls::rustls::sign::CertifiedKey;
+use rustls_acme::{AcmeConfig, ResolvesServerCertAcme, is_tls_alpn_challenge};
+use std::collections::HashMap;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_util::compat::{FuturesAsyncReadCompatExt, TokioAsyncReadCompatExt};
+
+use crate::config::TlsConfig;
+use crate::output;
+use crate::scheduler::ScheduledTask;
+use crate::services::TenantService;
+
+/// ALPN protocols offered to clients (HTTP/2 and HTTP/1.1)
+const ALPN_PROTOCOLS: &[&[u8]] = &[b"h2", b"http/1.1"];
+
+/// How long a client may take to finish the TLS handshake
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handshakes finished but not yet accepted by the server
+const ACCEPT_BACKLOG: usize = 128;
+
+/// How often the verified tenant domains are re-read
+pub const REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// A TLS connection handed to the server
+pub trait TlsIo: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> TlsIo for T {}
+
+type Connection = (Box<dyn TlsIo>, SocketAddr);
+
+/// A domain's certificate, kept current by a background ACME task
+struct ManagedDomain {
+    resolver: Arc<ResolvesServerCertAcme>,
+    task: JoinHandle<()>,
+}
+
+impl Drop for ManagedDomain {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Certificates by domain, chosen by the SNI name a client asks for
+pub struct CertResolver {
+    config: TlsConfig,
+    domains: RwLock<HashMap<String, ManagedDomain>>,
+}
+
+impl CertResolver {
+    /// An empty resolver; [`sync`](Self::sync) adds the domains
+    pub fn new(config: &TlsConfig) -> Self {
+        Self {
+            config: config.clone(),
+            domains: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Manage a certificate for each of `domains` and drop the others.
+    /// Returns the domains that were added.
+    pub fn sync(&self, domains: &[String]) -> Vec<String> {
+        let mut managed = self.domains.write().unwrap_or_else(|e| e.into_inner());
+        managed.retain(|domain, _| domains.contains(domain));
+
+        let mut added = Vec::new();
+        for domain in domains {
+            if !managed.contains_key(domain) {
+                managed.insert(domain.clone(), self.manage(domain));
+                added.push(domain.clone());
+            }
+        }
+        added
+    }
+
+    /// Domains with a managed certificate, sorted
+    pub fn domains(&self) -> Vec<String> {
+        let managed = self.domains.read().unwrap_or_else(|e| e.into_inner());
+        let mut domains: Vec<String> = managed.keys().cloned().collect();
+        domains.sort();
+        domains
+    }
+
+    /// Start ordering and renewing the certificate for `domain`
+    fn manage(&self, domain: &str) -> ManagedDomain {
+        let mut state = AcmeConfig::new([domain])
+            .contact(self.config.acme_contact.iter())
+            .cache(DirCache::new(self.config.acme_cache_dir.clone()))
+            .directory_lets_encrypt(self.config.acme_production)
+            .state();
+        let resolver = state.resolver();
+
+        let domain = domain.to_string();
+        let task = tokio::spawn(async move {
+            while let Some(event) = state.next().await {
+                match event {
+                    Ok(ok) => output::info(format!("ACME {}: {:?}", domain, ok)),
+                    Err(err) => output::warn(format!("ACME {}: {:?}", domain, err)),
+                }
+            }
+        });
+        ManagedDomain { resolver, task }
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        let domain = client_hello.server_name()?.to_ascii_lowercase();
+        let resolver = {
+            let managed = self.domains.read().unwrap_or_else(|e| e.into_inner());
+            managed.get(&domain)?.resolver.clone()
+        };
+        // Serves the domain's certificate, or its challenge key when the
+        // client is the ACME server validating it
+        resolver.resolve(client_hello)
+    }
+}
+
+impl std::fmt::Debug for CertResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.domains()).finish()
+    }
+}
+
+/// Keep `resolver` in step with the public host and verified tenant domains
+pub fn refresh_task(resolver: Arc<CertResolver>) -> ScheduledTask {
+    ScheduledTask::new("acme-domains", REFRESH_INTERVAL, move |state| {
+        let resolver = resolver.clone();
+        async move {
+            let custom_domains = TenantService::verified_domains(&state.pool)
+                .await
+                .map_err(|e| format!("Failed to load custom domains: {}", e))?;
+            let domains = certificate_domains(&state.config.server.public_host(), custom_domains);
+            for domain in resolver.sync(&domains) {
+                output::info(format!("HTTPS via ACME for {}", domain));
+            }
+            Ok(())
+        }
+    })
+}
+
+/// Rustls settings for regular connections and for TLS-ALPN-01 challenges
+struct TlsConfigs {
+    connections: Arc<ServerConfig>,
+    challenges: Arc<ServerConfig>,
+}
+
+impl TlsConfigs {
+    fn new(resolver: Arc<CertResolver>) -> Self {
+        let mut connections = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+        connections.alpn_protocols = ALPN_PROTOCOLS.iter().map(|p| p.to_vec()).collect();
+
+        let mut challenges = ServerConfig::builder()
+            .with_no_client_auth()
+            .with_cert_resolver(resolver);
+        challenges.alpn_protocols = vec![ACME_TLS_ALPN_NAME.to_vec()];
+
+        Self {
+            connections: Arc::new(connections),
+            challenges: Arc::new(challenges),
+        }
+    }
+}
+
+/// Listener serving TLS with the certificates in a [`CertResolver`]
+pub struct AcmeListener {
+    connections: mpsc::Receiver<Connection>,
+    local_addr: SocketAddr,
+}
+
+impl AcmeListener {
+    /// Bind `addr` and serve the certificates in `resolver`
+    pub async fn bind(addr: &str, resolver: Arc<CertResolver>) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let configs = Arc::new(TlsConfigs::new(resolver));
+
+        // Handshakes run in their own tasks so a slow client does not hold
+        // up the others
+        let (sender, connections) = mpsc::channel(ACCEPT_BACKLOG);
+        tokio::spawn(async move {
+            loop {
+                let (tcp, peer_addr) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        output::warn(format!("TCP accept failed: {}", err));
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        continue;
+                    }
+                };
+                let configs = configs.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake(tcp, &configs)).await {
+                        Ok(Ok(Some(tls))) => {
+                            let _ = sender.send((tls, peer_addr)).await;
+                        }
+                        Ok(Ok(None)) => {}
+                        Ok(Err(err)) => output::warn(format!("TLS accept failed: {}", err)),
+                        Err(_) => {
+                            output::warn(format!("TLS handshake with {} timed out", peer_addr))
+                        }
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            connections,
+            local_addr,
+        })
+    }
+}
+
+/// Finish the TLS handshake on `tcp`; `None` for an answered ACME challenge
+async fn handshake(tcp: TcpStream, configs: &TlsConfigs) -> io::Result<Option<Box<dyn TlsIo>>> {
+    let start = LazyConfigAcceptor::new(Acceptor::default(), tcp.compat()).await?;
+    if is_tls_alpn_challenge(&start.client_hello()) {
+        // Completing the handshake is the answer; nothing more is sent
+        start.into_stream(configs.challenges.clone()).await?;
+        return Ok(None);
+    }
+    let tls = start.into_stream(configs.connections.clone()).await?;
+    Ok(Some(Box::new(tls.compat())))
+}
+
+impl Listener for AcmeListener {
+    type Io = Box<dyn TlsIo>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(connection) => connection,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Domains to request a certificate for: the public host, unless it is
+/// local, followed by tenants' verified custom domains
+pub fn certificate_domains(public_host: &str, custom_domains: Vec<String>) -> Vec<String> {
+    let public_host = public_host.split(':').next().unwrap_or_default();
+    let is_local = public_host == "localhost" || public_host.parse::<std::net::IpAddr>().is_ok();
+
+    let mut domains = Vec::new();
+    if !public_host.is_empty() && !is_local {
+        domains.push(public_host.to_string());
+    }
+    for domain in custom_domains {
+        if !domains.contains(&domain) {
+            domains.push(domain);
+        }
+    }
+    domains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_certificate_domains() {
+        let custom = vec!["shop.acme.test".to_string(), "example.com".to_string()];
+        assert_eq!(
+            certificate_domains("example.com:443", custom.clone()),
+            vec!["example.com", "shop.acme.test"]
+        );
+        assert_eq!(
+            certificate_domains("localhost:3093", custom),
+            vec!["shop.acme.test", "example.com"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_adds_and_drops_domains() {
+        let config = TlsConfig {
+            acme_cache_dir: std::env::temp_dir()
+                .join("axum-base-acme-test")
+                .display()
+                .to_string(),
+            ..TlsConfig::default()
+        };
+        let resolver = CertResolver::new(&config);
+
+        let added = resolver.sync(&["a.example.com".to_string(), "b.example.com".to_string()]);
+        assert_eq!(added, vec!["a.example.com", "b.example.com"]);
+
+        let added = resolver.sync(&["b.example.com".to_string(), "c.example.com".to_string()]);
+        assert_eq!(added, vec!["c.example.com"]);
+        assert_eq!(resolver.domains(), vec!["b.example.com", "c.example.com"]);
+    }
+}
@@ -0,0 +1,247 @@
+//! # Activity Feed
+//!
+//! A user's own account history, read from the audit log: their sign-ins,
+//! password, profile, and preference changes, and what admins did to their
+//! account. Users read it at `/profile/activity` and `/api/profile/activity`.
+//!
+//! The audit log is written for admins, so entries pass a privacy filter
+//! before users see them. Only the actions in [`VISIBLE`] are shown, each
+//! keeps only the detail fields listed for it, and the acting admin is never
+//! named.
+
+use axum::{Router, extract::State, routing::get};
+use serde_json::{Map, Value};
+use sqlx::PgPool;
+
+use crate::api::RequestId;
+use crate::audit;
+use crate::auth::CurrentUser;
+use crate::error::AppResult;
+use crate::formats::{Accept, Negotiated};
+use crate::models::{ActivityEntry, AuditEntry, Envelope};
+use crate::pagination::{Paginated, Pagination, PaginationQuery};
+use crate::services::AuditService;
+use crate::state::AppState;
+
+/// An audit action shown in its user's feed
+#[derive(Debug, Clone, Copy)]
+pub struct Visible {
+    pub action: &'static str,
+    /// What the user is told happened
+    pub summary: &'static str,
+    /// Detail fields the user may see; the rest are dropped
+    pub details: &'static [&'static str],
+}
+
+/// Actions users see in their feed
+pub const VISIBLE: &[Visible] = &[
+    Visible {
+        action: audit::LOGIN,
+        summary: "Signed in",
+        details: &[],
+    },
+    Visible {
+        action: audit::LOGOUT,
+        summary: "Signed out",
+        details: &[],
+    },
+    Visible {
+        action: audit::LOGOUT_EVERYWHERE,
+        summary: "Signed out on every device",
+        details: &["sessions_ended"],
+    },
+    Visible {
+        action: audit::PASSWORD_CHANGED,
+        summary: "Changed your password",
+        details: &[],
+    },
+    Visible {
+        action: audit::PROFILE_UPDATED,
+        summary: "Updated your profile",
+        details: &["fields"],
+    },
+    Visible {
+        action: audit::USERNAME_CHANGED,
+        summary: "Changed your username",
+        details: &["from", "to"],
+    },
+    Visible {
+        action: audit::PREFERENCES_UPDATED,
+        summary: "Changed your preferences",
+        details: &["keys", "reset"],
+    },
+    Visible {
+        action: audit::USER_CREATED,
+        summary: "Your account was created",
+        details: &[],
+    },
+    Visible {
+        action: audit::USER_DEACTIVATED,
+        summary: "Your account was deactivated",
+        details: &[],
+    },
+    Visible {
+        action: audit::USER_REACTIVATED,
+        summary: "Your account was reactivated",
+        details: &[],
+    },
+    Visible {
+        action: audit::PASSWORD_SET,
+        summary: "An administrator set your password",
+        details: &[],
+    },
+    Visible {
+        action: audit::ROLES_ASSIGNED,
+        summary: "An administrator changed your roles",
+        details: &["from", "to"],
+    },
+    Visible {
+        action: audit::USERNAME_SET,
+        summary: "An administrator changed your username",
+        details: &["from", "to"],
+    },
+];
+
+/// Actions in [`VISIBLE`]
+pub fn actions() -> Vec<&'static str> {
+    VISIBLE.iter().map(|visible| visible.action).collect()
+}
+
+/// An audit entry as its user sees it; `None` for actions they are not shown
+pub fn present(entry: AuditEntry) -> Option<ActivityEntry> {
+    let visible = VISIBLE
+        .iter()
+        .find(|visible| visible.action == entry.action)?;
+    let details: Map<String, Value> = entry
+        .details
+        .as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(key, _)| visible.details.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    Some(ActivityEntry {
+        id: entry.id,
+        action: entry.action,
+        summary: visible.summary.to_string(),
+        details: Value::Object(details),
+        created_at: entry.created_at,
+    })
+}
+
+/// One page of a user's feed, newest first
+pub async fn list(
+    pool: &PgPool,
+    user_id: i32,
+    pagination: &Pagination,
+) -> Result<Paginated<ActivityEntry>, sqlx::Error> {
+    let page = AuditService::list_for_user(pool, user_id, &actions(), pagination).await?;
+    Ok(Paginated {
+        items: page.items.into_iter().filter_map(present).collect(),
+        page: page.page,
+        per_page: page.per_page,
+        total: page.total,
+        total_pages: page.total_pages,
+    })
+}
+
+/// Details as one line, such as `fields: email, locale`
+pub fn describe(details: &Value) -> String {
+    let Some(details) = details.as_object() else {
+        return String::new();
+    };
+    details
+        .iter()
+        .map(|(key, value)| format!("{}: {}", key.replace('_', " "), plain(value)))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+fn plain(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Array(values) => values.iter().map(plain).collect::<Vec<_>>().join(", "),
+        Value::Null => "none".to_string(),
+        other => other.to_string(),
+    }
+}
+
+/// `/api/profile/activity`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/profile/activity", get(list_activity))
+}
+
+/// The signed-in user's account activity, newest first
+#[utoipa::path(
+    get,
+    path = "/api/profile/activity",
+    tag = "account",
+    params(PaginationQuery),
+    responses(
+        (status = 200, description = "One page of activity", body = Envelope<Vec<ActivityEntry>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_activity(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    pagination: Pagination,
+) -> AppResult<Negotiated<Envelope<Vec<ActivityEntry>>>> {
+    let page = list(&pool, user.id, &pagination).await?;
+    let meta = page.meta();
+    Ok(Negotiated(
+        format,
+        Envelope::success(page.items)
+            .with_pagination(meta)
+            .with_request_id(request_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::json;
+
+    fn entry(action: &str, details: Value) -> AuditEntry {
+        AuditEntry {
+            id: 1,
+            actor_id: Some(7),
+            actor_username: Some("root".to_string()),
+            action: action.to_string(),
+            target_type: Some("user".to_string()),
+            target_id: Some("2".to_string()),
+            details,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_present_filters_details() {
+        let shown = present(entry(
+            audit::USERNAME_SET,
+            json!({ "from": "old", "to": "new", "admin_ip": "10.0.0.1" }),
+        ))
+        .unwrap();
+        assert_eq!(shown.summary, "An administrator changed your username");
+        assert_eq!(shown.details, json!({ "from": "old", "to": "new" }));
+
+        let shown = present(entry(audit::LOGIN, Value::Null)).unwrap();
+        assert_eq!(shown.details, json!({}));
+
+        assert!(present(entry(audit::LOGIN_FAILED, json!({ "username": "x" }))).is_none());
+        assert!(present(entry(audit::SNIPPET_CHANGED, json!({}))).is_none());
+    }
+
+    #[test]
+    fn test_describe() {
+        assert_eq!(
+            describe(&json!({ "fields": ["email", "locale"], "sessions_ended": 3 })),
+            "fields: email, locale; sessions ended: 3"
+        );
+        assert_eq!(describe(&json!({})), "");
+    }
+}
@@ -0,0 +1,190 @@
+//! # ActivityPub (lite)
+//!
+//! Read-only ActivityPub federation, compiled only with the `activitypub`
+//! feature. Each active user is published as a `Person` actor at
+//! `/users/{name}` whose outbox lists the site's public items as `Create`
+//! activities, so other servers can follow and display published content.
+//! There is no inbox: remote interactions are not accepted.
+
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::database::Db;
+use crate::error::{AppError, AppResult};
+use crate::models::User;
+use crate::pagination::Pagination;
+use crate::services::{ItemService, UserService};
+use crate::state::AppState;
+
+/// Media type for ActivityStreams documents
+pub const ACTIVITY_JSON: &str = "application/activity+json";
+
+const ACTIVITY_STREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+
+/// Routes for actor and outbox documents
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/users/{name}", get(serve_actor))
+        .route("/users/{name}/outbox", get(serve_outbox))
+}
+
+/// Canonical actor URL for a username
+pub fn actor_url(config: &AppConfig, username: &str) -> String {
+    format!("{}/users/{}", config.server.public_base_url(), username)
+}
+
+// =============================================================================
+// ActivityStreams Documents
+// =============================================================================
+
+/// `Person` actor document
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Actor {
+    #[serde(rename = "@context")]
+    pub context: &'static str,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub preferred_username: String,
+    pub name: String,
+    pub url: String,
+    pub outbox: String,
+    pub published: String,
+}
+
+/// Outbox collection of `Create` activities
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderedCollection {
+    #[serde(rename = "@context")]
+    pub context: &'static str,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub total_items: i64,
+    pub ordered_items: Vec<Activity>,
+}
+
+/// `Create` activity wrapping a published item
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Activity {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub actor: String,
+    pub published: String,
+    pub to: Vec<&'static str>,
+    pub object: Note,
+}
+
+/// `Note` object for an item
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Note {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub name: String,
+    pub content: String,
+    pub published: String,
+    pub attributed_to: String,
+    pub to: Vec<&'static str>,
+}
+
+/// Audience for public objects
+const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// Look up an active user or return 404
+async fn find_actor(pool: &PgPool, name: &str) -> AppResult<User> {
+    UserService::get_user_by_username(pool, name)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Unknown actor".to_string()))
+}
+
+/// Wrap a document with the ActivityPub content type
+fn activity_json<T: Serialize>(document: T) -> Response {
+    ([(header::CONTENT_TYPE, ACTIVITY_JSON)], Json(document)).into_response()
+}
+
+/// Serve the actor document for `/users/{name}`
+pub async fn serve_actor(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<AppConfig>>,
+    Path(name): Path<String>,
+) -> AppResult<Response> {
+    let user = find_actor(&pool, &name).await?;
+    let id = actor_url(&config, &user.username);
+
+    Ok(activity_json(Actor {
+        context: ACTIVITY_STREAMS_CONTEXT,
+        outbox: format!("{}/outbox", id),
+        url: id.clone(),
+        id,
+        kind: "Person",
+        preferred_username: user.username.clone(),
+        name: user.username,
+        published: user.created_at.to_rfc3339(),
+    }))
+}
+
+/// Serve the outbox for `/users/{name}/outbox`, newest items first
+pub async fn serve_outbox(
+    State(pool): State<PgPool>,
+    State(db): State<Db>,
+    State(config): State<Arc<AppConfig>>,
+    Path(name): Path<String>,
+    pagination: Pagination,
+) -> AppResult<Response> {
+    let user = find_actor(&pool, &name).await?;
+    let actor = actor_url(&config, &user.username);
+    let base_url = config.server.public_base_url();
+
+    let page = ItemService::get_all_items(db.read(), &pagination).await?;
+    let activities: Vec<Activity> = page
+        .items
+        .into_iter()
+        .map(|entry| {
+            let object_id = format!("{}/items/{}", base_url, entry.item.id);
+            let published = entry.item.created_at.to_rfc3339();
+            Activity {
+                id: format!("{}/activity", object_id),
+                kind: "Create",
+                actor: actor.clone(),
+                published: published.clone(),
+                to: vec![PUBLIC],
+                object: Note {
+                    id: object_id,
+                    kind: "Note",
+                    name: entry.item.title,
+                    content: entry.item.description.unwrap_or_default(),
+                    published,
+                    attributed_to: actor.clone(),
+                    to: vec![PUBLIC],
+                },
+            }
+        })
+        .collect();
+
+    Ok(activity_json(OrderedCollection {
+        context: ACTIVITY_STREAMS_CONTEXT,
+        id: format!("{}/outbox", actor),
+        kind: "OrderedCollection",
+        total_items: page.total,
+        ordered_items: activities,
+    }))
+}
@@ -0,0 +1,586 @@
+//! # Admin API
+//!
+//! User management endpoints under `/api/admin/users`, restricted to users
+//! with the `admin` role via the [`AdminUser`] extractor. Responses carry
+//! `UserResponse` so password hashes never leave the server.
+
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post, put},
+};
+use serde_json::json;
+use sqlx::PgPool;
+use tower_sessions::Session;
+
+use crate::api::RequestId;
+use crate::audit::{self, AuditEvent};
+use crate::auth::{
+    ADMIN_ROLE, AdminUser, AuthService, MIN_PASSWORD_LENGTH, USER_SESSION_KEY, renew_session,
+};
+use crate::emails;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated, Payload};
+use crate::i18n;
+use crate::models::{
+    AssignRolesRequest, AuthenticatedUser, ChangeUsernameRequest, CreateUserRequest, Envelope,
+    SetPasswordRequest, User, UserFilter, UserResponse, UsernameChange, UsernameChangeEntry,
+};
+use crate::pagination::{Pagination, PaginationQuery};
+use crate::services::{EmailDeliveryService, UserService};
+use crate::state::AppState;
+
+/// Longest accepted role name
+const MAX_ROLE_LENGTH: usize = 50;
+
+/// Longest accepted username, the width of `users.username`
+pub const MAX_USERNAME_LENGTH: usize = 100;
+
+/// Routes for `/api/admin/users`
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/admin/users", get(list_users).post(create_user))
+        .route("/api/admin/users/{id}", get(get_user))
+        .route("/api/admin/users/{id}/deactivate", post(deactivate_user))
+        .route("/api/admin/users/{id}/reactivate", post(reactivate_user))
+        .route("/api/admin/users/{id}/password", put(set_password))
+        .route("/api/admin/users/{id}/roles", put(assign_roles))
+        .route("/api/admin/users/{id}/username", put(change_username))
+        .route(
+            "/api/admin/users/{id}/username-history",
+            get(username_history),
+        )
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// List users, filtered by `search`, `is_active`, `role`, `email_like`, and
+/// `created_after`, paginated
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    tag = "admin",
+    params(UserFilter, PaginationQuery),
+    responses(
+        (status = 200, description = "One page of users", body = Envelope<Vec<UserResponse>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_users(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(filter): Query<UserFilter>,
+    pagination: Pagination,
+) -> AppResult<Negotiated<Envelope<Vec<UserResponse>>>> {
+    let page = UserService::list_users(&pool, &filter, &pagination).await?;
+    let user_ids: Vec<i32> = page.items.iter().map(|user| user.id).collect();
+    let mut roles = UserService::get_roles_for_users(&pool, &user_ids).await?;
+    let emails: Vec<String> = page.items.iter().map(|user| user.email.clone()).collect();
+    let mut undeliverable = EmailDeliveryService::suppression_reasons(&pool, &emails).await?;
+
+    let meta = page.meta();
+    let users = page
+        .items
+        .into_iter()
+        .map(|user| {
+            let user_roles = roles.remove(&user.id).unwrap_or_default();
+            let reason = undeliverable.remove(&user.email.to_lowercase());
+            UserResponse::from(user)
+                .with_roles(user_roles)
+                .with_email_undeliverable(reason)
+        })
+        .collect();
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(users)
+            .with_pagination(meta)
+            .with_request_id(request_id),
+    ))
+}
+
+/// Get one user, including deactivated users
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "The user", body = Envelope<UserResponse>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn get_user(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+) -> AppResult<Negotiated<Envelope<UserResponse>>> {
+    let user = find_user(&pool, user_id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(user_response(&pool, user).await?).with_request_id(request_id),
+    ))
+}
+
+/// Create a user with a password and optional roles
+#[utoipa::path(
+    post,
+    path = "/api/admin/users",
+    tag = "admin",
+    request_body = CreateUserRequest,
+    responses(
+        (status = 201, description = "User created", body = Envelope<UserResponse>),
+        (status = 400, description = "Invalid input or duplicate user", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn create_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Payload(mut request): Payload<CreateUserRequest>,
+) -> AppResult<(StatusCode, Negotiated<Envelope<UserResponse>>)> {
+    if request.username.trim().is_empty() || request.email.trim().is_empty() {
+        return Err(AppError::Validation(
+            "username and email are required".to_string(),
+        ));
+    }
+    state.password_policy.check(&request.password).await?;
+    validate_roles(&request.roles)?;
+    request.locale =
+        match request.locale.as_deref().filter(|l| !l.trim().is_empty()) {
+            Some(locale) => Some(i18n::normalize(locale).ok_or_else(|| {
+                AppError::Validation(format!("'{}' is not a valid locale", locale))
+            })?),
+            None => None,
+        };
+
+    let pool = &state.pool;
+    let user = UserService::create_user(pool, &request)
+        .await
+        .map_err(|err| match err.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                AppError::Validation("Username or email already exists".to_string())
+            }
+            _ => AppError::from(err),
+        })?;
+    UserService::set_user_roles(pool, user.id, &request.roles).await?;
+    audit::record(
+        pool,
+        AuditEvent::new(audit::USER_CREATED)
+            .by(admin.id)
+            .on("user", user.id)
+            .with_details(json!({ "username": user.username, "roles": request.roles })),
+    )
+    .await;
+
+    let mut context = emails::base_context();
+    context.insert("username", &user.username);
+    context.insert(
+        "login_url",
+        &format!("{}/login", state.config.server.public_base_url()),
+    );
+    emails::send_in_background(
+        &state,
+        "welcome",
+        user.email.clone(),
+        request.locale.clone(),
+        context,
+    )
+    .await;
+
+    let roles = UserService::get_user_roles(pool, user.id).await?;
+    let undeliverable = EmailDeliveryService::suppression_reason(pool, &user.email).await?;
+    let user = user
+        .with_roles(roles)
+        .with_email_undeliverable(undeliverable);
+    Ok((
+        StatusCode::CREATED,
+        Negotiated(
+            format,
+            Envelope::success(user)
+                .with_message("User created")
+                .with_request_id(request_id),
+        ),
+    ))
+}
+
+/// Deactivate a user so they can no longer sign in, and end their sessions
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/deactivate",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "User deactivated", body = Envelope<UserResponse>),
+        (status = 400, description = "Cannot deactivate yourself", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn deactivate_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+) -> AppResult<Negotiated<Envelope<UserResponse>>> {
+    if admin.id == user_id {
+        return Err(AppError::Validation(
+            "You cannot deactivate your own account".to_string(),
+        ));
+    }
+    let pool = &state.pool;
+    if !UserService::deactivate_user(pool, user_id).await? {
+        return Err(user_not_found(user_id));
+    }
+    // Sign them out everywhere, including open WebSockets
+    let sessions = AuthService::end_sessions(pool, user_id).await?;
+    for session_id in &sessions {
+        state.connections.disconnect_session(session_id);
+    }
+    audit::record(
+        pool,
+        AuditEvent::new(audit::USER_DEACTIVATED)
+            .by(admin.id)
+            .on("user", user_id)
+            .with_details(json!({ "sessions_ended": sessions.len() })),
+    )
+    .await;
+
+    let user = find_user(pool, user_id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(user_response(pool, user).await?)
+            .with_message("User deactivated")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Let a deactivated user sign in again
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/reactivate",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "User reactivated", body = Envelope<UserResponse>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn reactivate_user(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+) -> AppResult<Negotiated<Envelope<UserResponse>>> {
+    if !UserService::reactivate_user(&pool, user_id).await? {
+        return Err(user_not_found(user_id));
+    }
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::USER_REACTIVATED)
+            .by(admin.id)
+            .on("user", user_id),
+    )
+    .await;
+
+    let user = find_user(&pool, user_id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(user_response(&pool, user).await?)
+            .with_message("User reactivated")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Set a user's password
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/password",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    request_body = SetPasswordRequest,
+    responses(
+        (status = 200, description = "Password updated", body = Envelope<serde_json::Value>),
+        (status = 400, description = "Password too short or breached", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn set_password(
+    AdminUser(admin): AdminUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+    Payload(request): Payload<SetPasswordRequest>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    state.password_policy.check(&request.password).await?;
+    let pool = &state.pool;
+    let user = find_user(pool, user_id).await?;
+
+    let password_hash = UserService::hash_password(&request.password).await?;
+    UserService::update_user_password(pool, user.id, &password_hash).await?;
+    audit::record(
+        pool,
+        AuditEvent::new(audit::PASSWORD_SET)
+            .by(admin.id)
+            .on("user", user.id),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Password updated").with_request_id(request_id),
+    ))
+}
+
+/// Replace a user's roles
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/roles",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    request_body = AssignRolesRequest,
+    responses(
+        (status = 200, description = "Roles updated", body = Envelope<UserResponse>),
+        (status = 400, description = "Invalid roles", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn assign_roles(
+    AdminUser(admin): AdminUser,
+    State(state): State<AppState>,
+    session: Session,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+    Payload(request): Payload<AssignRolesRequest>,
+) -> AppResult<Negotiated<Envelope<UserResponse>>> {
+    validate_roles(&request.roles)?;
+    if admin.id == user_id && !request.roles.iter().any(|role| role == ADMIN_ROLE) {
+        return Err(AppError::Validation(
+            "You cannot remove your own admin role".to_string(),
+        ));
+    }
+
+    let pool = &state.pool;
+    let user = find_user(pool, user_id).await?;
+    let previous = UserService::get_user_roles(pool, user.id).await?;
+    UserService::set_user_roles(pool, user.id, &request.roles).await?;
+    audit::record(
+        pool,
+        AuditEvent::new(audit::ROLES_ASSIGNED)
+            .by(admin.id)
+            .on("user", user.id)
+            .with_details(json!({ "from": previous, "to": request.roles })),
+    )
+    .await;
+    // The admin's own privileges changed, so their session gets a new ID
+    if admin.id == user_id {
+        renew_session(state.auth.as_ref(), &session, admin.id).await?;
+    }
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(user_response(pool, user).await?)
+            .with_message("Roles updated")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Rename a user; the name must differ from every other user's in any
+/// letter case
+#[utoipa::path(
+    put,
+    path = "/api/admin/users/{id}/username",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    request_body = ChangeUsernameRequest,
+    responses(
+        (status = 200, description = "Username changed", body = Envelope<UserResponse>),
+        (status = 400, description = "Invalid or taken username", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn change_username(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    session: Session,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+    Payload(request): Payload<ChangeUsernameRequest>,
+) -> AppResult<Negotiated<Envelope<UserResponse>>> {
+    let username = request.username.trim();
+    validate_username(username)?;
+
+    match UserService::change_username(&pool, user_id, username, Some(admin.id)).await? {
+        UsernameChange::NotFound => return Err(user_not_found(user_id)),
+        UsernameChange::Taken => return Err(username_taken(username)),
+        UsernameChange::Unchanged => {}
+        UsernameChange::Changed(previous) => {
+            audit::record(
+                &pool,
+                AuditEvent::new(audit::USERNAME_SET)
+                    .by(admin.id)
+                    .on("user", user_id)
+                    .with_details(json!({ "from": previous, "to": username })),
+            )
+            .await;
+            // The admin's own pages show the new name
+            if admin.id == user_id
+                && let Ok(Some(mut session_user)) =
+                    session.get::<AuthenticatedUser>(USER_SESSION_KEY).await
+            {
+                session_user.username = username.to_string();
+                let _ = session.insert(USER_SESSION_KEY, &session_user).await;
+            }
+        }
+    }
+
+    let user = find_user(&pool, user_id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(user_response(&pool, user).await?)
+            .with_message("Username changed")
+            .with_request_id(request_id),
+    ))
+}
+
+/// A user's earlier usernames, most recent rename first
+#[utoipa::path(
+    get,
+    path = "/api/admin/users/{id}/username-history",
+    tag = "admin",
+    params(("id" = i32, Path, description = "User ID")),
+    responses(
+        (status = 200, description = "Renames of the user", body = Envelope<Vec<UsernameChangeEntry>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such user", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn username_history(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(user_id): Path<i32>,
+) -> AppResult<Negotiated<Envelope<Vec<UsernameChangeEntry>>>> {
+    let user = find_user(&pool, user_id).await?;
+    let history = UserService::username_history(&pool, user.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(history).with_request_id(request_id),
+    ))
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn user_not_found(user_id: i32) -> AppError {
+    AppError::NotFound(format!("User {} not found", user_id))
+}
+
+fn username_taken(username: &str) -> AppError {
+    AppError::Validation(format!("The username '{}' is taken", username))
+}
+
+async fn find_user(pool: &PgPool, user_id: i32) -> AppResult<User> {
+    UserService::get_user_any_status(pool, user_id)
+        .await?
+        .ok_or_else(|| user_not_found(user_id))
+}
+
+/// Admin view of a user: roles and whether mail to them is suppressed
+async fn user_response(pool: &PgPool, user: User) -> AppResult<UserResponse> {
+    let roles = UserService::get_user_roles(pool, user.id).await?;
+    let undeliverable = EmailDeliveryService::suppression_reason(pool, &user.email).await?;
+    Ok(UserResponse::from(user)
+        .with_roles(roles)
+        .with_email_undeliverable(undeliverable))
+}
+
+/// Reject passwords shorter than the minimum length
+pub fn validate_password(password: &str) -> AppResult<()> {
+    if password.len() < MIN_PASSWORD_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        )));
+    }
+    Ok(())
+}
+
+/// Check a new username: at most [`MAX_USERNAME_LENGTH`] letters, digits,
+/// `.`, `_` or `-`
+pub fn validate_username(username: &str) -> AppResult<()> {
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Usernames are 1 to {} characters long",
+            MAX_USERNAME_LENGTH
+        )));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err(AppError::Validation(format!(
+            "Invalid username '{}': use letters, digits, '.', '_' or '-'",
+            username
+        )));
+    }
+    Ok(())
+}
+
+/// Roles are short lowercase identifiers such as `admin` or `editor`
+pub fn validate_roles(roles: &[String]) -> AppResult<()> {
+    let invalid = roles.iter().find(|role| {
+        role.is_empty()
+            || role.len() > MAX_ROLE_LENGTH
+            || !role
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    });
+
+    match invalid {
+        Some(role) => Err(AppError::Validation(format!(
+            "Invalid role '{}': use lowercase letters, digits, '_' or '-'",
+            role
+        ))),
+        None => Ok(()),
+    }
+}
@@ -2,48 +2,345 @@
 //!
 //! Handlers for JSON API endpoints.
 
-use axum::{extract::State, response::Json};
+use axum::{
+    extract::{FromRequestParts, Path, State},
+    http::{StatusCode, request::Parts},
+};
+use chrono::Utc;
 use sqlx::PgPool;
+use std::convert::Infallible;
 use std::env;
 
-use crate::database::get_connection_info;
-use crate::models::{ApiResponse, DatabaseHealthInfo, HealthResponse};
+use crate::auth::ADMIN_ROLE;
+use crate::database::{Db, get_connection_info};
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::health;
+use crate::item_filter::{ItemFilter, ItemFilterQuery, ItemSort};
+use crate::migrations;
+use crate::models::{
+    Category, DatabaseHealthInfo, Envelope, HealthResponse, HealthStatus, HealthVerdict,
+    ItemDetail, ItemWithCategory, LivenessResponse, PoolUsage, ReadinessResponse, WorkerLiveness,
+};
+use crate::output;
+use crate::pagination::{CursorQuery, Keyset, Pagination, PaginationQuery};
+use crate::resources;
+use crate::scope::RequestScope;
+use crate::services::{AttachmentService, CategoryService, ItemService};
+use crate::state::AppState;
 
-/// Health check endpoint with database connectivity check
-pub async fn health_check(State(pool): State<PgPool>) -> Json<HealthResponse> {
-    // Check database connectivity
-    let database_info = match get_connection_info(&pool).await {
+/// Header carrying the per-request ID set by the router middleware
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Extractor for the request ID assigned by the request-id middleware
+#[derive(Debug, Clone, Default)]
+pub struct RequestId(pub Option<String>);
+
+impl<S> FromRequestParts<S> for RequestId
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(RequestId(
+            parts
+                .headers
+                .get(REQUEST_ID_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        ))
+    }
+}
+
+/// Health overview: version, database connection details, and every
+/// registered [`HealthCheck`](health::HealthCheck). 503 while a critical
+/// component is down. Probes should use `/health/live` and `/health/ready`.
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "api",
+    responses(
+        (status = 200, description = "Healthy or degraded, with each component's status", body = Envelope<HealthResponse>),
+        (status = 503, description = "A critical component is down", body = Envelope<HealthResponse>)
+    )
+)]
+pub async fn health_check(
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> (StatusCode, Negotiated<Envelope<HealthResponse>>) {
+    let checks: Vec<_> = health::core_checks()
+        .into_iter()
+        .chain(state.plugins.health_checks())
+        .collect();
+    let components = health::run_checks(&state, &checks).await;
+    let status = health::verdict(&components);
+
+    // Connection details for people reading the report
+    let database_info = match get_connection_info(&state.pool).await {
         Ok(info) => Some(DatabaseHealthInfo {
             connected: true,
             database_name: info.database_name,
             pool_connections: info.pool_connections,
             idle_connections: info.idle_connections,
         }),
-        Err(err) => {
-            eprintln!("Database health check failed: {}", err);
-            Some(DatabaseHealthInfo {
-                connected: false,
-                database_name: "unknown".to_string(),
-                pool_connections: 0,
-                idle_connections: 0,
+        Err(_) => Some(DatabaseHealthInfo {
+            connected: false,
+            database_name: "unknown".to_string(),
+            pool_connections: 0,
+            idle_connections: 0,
+        }),
+    };
+
+    let code = if status == HealthVerdict::Unhealthy {
+        StatusCode::SERVICE_UNAVAILABLE
+    } else {
+        StatusCode::OK
+    };
+    (
+        code,
+        Negotiated(
+            format,
+            Envelope::success(HealthResponse {
+                status,
+                service: "axum-base".to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                database: database_info,
+                components,
+                resources: resources::report(
+                    resources::limits(),
+                    &state.config,
+                    tokio::runtime::Handle::current().metrics().num_workers(),
+                ),
             })
+            .with_request_id(request_id),
+        ),
+    )
+}
+
+/// Liveness probe: 200 while the process can answer; never touches the
+/// database, so an outage does not get the instance restarted
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "api",
+    responses((status = 200, description = "Process is up", body = Envelope<LivenessResponse>))
+)]
+pub async fn health_live(
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> Negotiated<Envelope<LivenessResponse>> {
+    Negotiated(
+        format,
+        Envelope::success(LivenessResponse {
+            alive: true,
+            service: "axum-base".to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        })
+        .with_request_id(request_id),
+    )
+}
+
+/// Readiness probe: 503 unless the database answers with every migration
+/// applied, the pool has a connection to spare, a job worker has sent a
+/// heartbeat within `JOB_STALL_TIMEOUT`, and neither has been flapping.
+/// An exhausted pool answers at once instead of queueing for a connection.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "api",
+    responses(
+        (status = 200, description = "Ready for traffic", body = Envelope<ReadinessResponse>),
+        (status = 503, description = "Database unreachable, migrations pending, pool exhausted, no live job worker, or either unstable", body = Envelope<ReadinessResponse>)
+    )
+)]
+pub async fn health_ready(
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> (StatusCode, Negotiated<Envelope<ReadinessResponse>>) {
+    let pool = PoolUsage {
+        size: state.pool.size(),
+        idle: state.pool.num_idle(),
+        max: state.pool.options().get_max_connections(),
+    };
+
+    let response = if pool.exhausted() {
+        let report = state.health.report(Utc::now());
+        ReadinessResponse {
+            ready: false,
+            status: report.status,
+            database: report.samples.last().is_some_and(|sample| sample.database),
+            migrations_pending: None,
+            pool,
+            workers: WorkerLiveness::default(),
+        }
+    } else {
+        let (sample, workers) = health::probe(&state).await;
+        let status = state.health.report(Utc::now()).status;
+        let migrations_pending =
+            match migrations::pending(&state.pool, &state.plugins.migrations()).await {
+                Ok(pending) => Some(pending),
+                Err(err) => {
+                    output::warn(format!("Migration check failed: {}", err));
+                    None
+                }
+            };
+        ReadinessResponse {
+            ready: status == HealthStatus::Healthy && migrations_pending == Some(0),
+            status,
+            database: sample.database,
+            migrations_pending,
+            pool,
+            workers,
         }
     };
 
-    Json(HealthResponse {
-        status: "healthy".to_string(),
-        service: "axum-base".to_string(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
-        database: database_info,
-    })
+    let code = if response.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        code,
+        Negotiated(
+            format,
+            Envelope::success(response).with_request_id(request_id),
+        ),
+    )
 }
 
 /// API hello endpoint
-pub async fn api_hello() -> Json<ApiResponse> {
-    Json(ApiResponse {
-        message: "Hello from Axum Base! A modern Rust web server template built with Axum."
-            .to_string(),
-        status: "success".to_string(),
-        timestamp: chrono::Utc::now().to_rfc3339(),
-    })
+#[utoipa::path(
+    get,
+    path = "/api/hello",
+    tag = "api",
+    responses((status = 200, description = "Greeting message", body = Envelope<serde_json::Value>))
+)]
+pub async fn api_hello(
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> Negotiated<Envelope<()>> {
+    Negotiated(
+        format,
+        Envelope::message(
+            "Hello from Axum Base! A modern Rust web server template built with Axum.",
+        )
+        .with_request_id(request_id),
+    )
+}
+
+/// List visible items, filtered and sorted, paginated by page or by cursor
+#[utoipa::path(
+    get,
+    path = "/api/items",
+    tag = "api",
+    params(ItemFilterQuery, PaginationQuery, CursorQuery),
+    responses(
+        (status = 200, description = "One page of items", body = Envelope<Vec<ItemWithCategory>>),
+        (status = 400, description = "Invalid filter, sort, or pagination parameters, or a cursor with a title sort", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Inactive items asked for without signing in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Inactive items asked for without the admin role", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn list_items(
+    State(db): State<Db>,
+    scope: RequestScope,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    filter: ItemFilter,
+    pagination: Pagination,
+    keyset: Keyset,
+) -> AppResult<Negotiated<Envelope<Vec<ItemWithCategory>>>> {
+    if !filter.is_active && !scope.roles().await.iter().any(|role| role == ADMIN_ROLE) {
+        let message = "Admin role required to list inactive items".to_string();
+        return Err(match scope.user().await {
+            Some(_) => AppError::Forbidden(message),
+            None => AppError::Unauthorized(message),
+        });
+    }
+    let envelope = if keyset.is_on() {
+        // Cursors hold a creation time, so they only page that order
+        if filter.sort != ItemSort::CreatedAt {
+            return Err(AppError::Validation(
+                "cursor works only with sort=created_at".to_string(),
+            ));
+        }
+        let page =
+            ItemService::list_items_after(db.read(), &filter, keyset.after(), &pagination).await?;
+        Envelope::cursor_paginated(page)
+    } else {
+        Envelope::paginated(ItemService::list_items(db.read(), &filter, &pagination).await?)
+    };
+    Ok(Negotiated(format, envelope.with_request_id(request_id)))
+}
+
+/// One item with its category and attachments
+#[utoipa::path(
+    get,
+    path = "/api/items/{id}",
+    tag = "api",
+    params(("id" = i32, Path, description = "Item ID")),
+    responses(
+        (status = 200, description = "The item", body = Envelope<ItemDetail>),
+        (status = 404, description = "No such item, or not visible to the caller", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn get_item(
+    State(db): State<Db>,
+    scope: RequestScope,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(id): Path<i32>,
+) -> AppResult<Negotiated<Envelope<ItemDetail>>> {
+    let item = find_visible_item(db.read(), &scope, id).await?;
+    let attachments = AttachmentService::list_for_item(db.read(), id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(ItemDetail { item, attachments }).with_request_id(request_id),
+    ))
+}
+
+/// An item the caller may see: active and in a visible category, or any
+/// for admins. Others get a 404, as if it did not exist.
+pub(crate) async fn find_visible_item(
+    pool: &PgPool,
+    scope: &RequestScope,
+    id: i32,
+) -> AppResult<ItemWithCategory> {
+    let not_found = || AppError::NotFound(format!("Item {} not found", id));
+    let item = ItemService::get_item(pool, id)
+        .await?
+        .ok_or_else(not_found)?;
+    let visible = item.item.is_active && item.category.is_visible;
+    if visible || scope.roles().await.iter().any(|role| role == ADMIN_ROLE) {
+        Ok(item)
+    } else {
+        Err(not_found())
+    }
+}
+
+/// List visible categories, paginated
+#[utoipa::path(
+    get,
+    path = "/api/categories",
+    tag = "api",
+    params(PaginationQuery),
+    responses(
+        (status = 200, description = "One page of categories", body = Envelope<Vec<Category>>),
+        (status = 400, description = "Invalid pagination parameters", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn list_categories(
+    State(db): State<Db>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    pagination: Pagination,
+) -> AppResult<Negotiated<Envelope<Vec<Category>>>> {
+    let page = CategoryService::get_all_categories(db.read(), &pagination).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::paginated(page).with_request_id(request_id),
+    ))
 }
@@ -0,0 +1,210 @@
+//! # Item Attachments
+//!
+//! Admins attach files to items by posting them as `multipart/form-data` to
+//! `/api/items/{id}/attachments`, under the same size and type rules as
+//! [uploads](crate::uploads). Items have no owner, and attachments are served
+//! to anyone who can see the item, so uploading is not open to every account.
+//! `GET /api/items/{id}` lists an item's attachments, and each is downloaded
+//! from `/attachments/{id}` by anyone who can see the item.
+//!
+//! Contents go to the [`StorageBackend`](crate::uploads::StorageBackend)
+//! under a key of their own and the metadata to `item_attachments`. Only the
+//! uploader and admins can delete an attachment; anyone else gets a 404.
+
+use axum::{
+    Router,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::StatusCode,
+    response::Response,
+    routing::{delete, get, post},
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::api::{RequestId, find_visible_item};
+use crate::auth::{ADMIN_ROLE, AdminUser, CurrentUser};
+use crate::config::UploadsConfig;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::models::{AuthenticatedUser, Envelope, ItemAttachment};
+use crate::scope::RequestScope;
+use crate::services::{AttachmentService, UserService};
+use crate::state::AppState;
+use crate::uploads::{self, UploadForm};
+
+/// Prefix of attachment storage keys, keeping them apart from upload keys
+const KEY_PREFIX: &str = "attachment-";
+
+/// Routes for attaching, downloading, and deleting item files
+pub fn routes(config: &UploadsConfig) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/items/{id}/attachments",
+            post(upload_attachment).layer(DefaultBodyLimit::max(
+                config.max_bytes + uploads::MULTIPART_OVERHEAD,
+            )),
+        )
+        .route(
+            "/api/items/{id}/attachments/{attachment_id}",
+            delete(delete_attachment),
+        )
+        .route("/attachments/{id}", get(download_attachment))
+}
+
+/// Attach a file to an item
+#[utoipa::path(
+    post,
+    path = "/api/items/{id}/attachments",
+    tag = "files",
+    params(("id" = i32, Path, description = "Item ID")),
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "File attached", body = Envelope<ItemAttachment>),
+        (status = 400, description = "Missing file, or a type that is not allowed", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such item", body = Envelope<serde_json::Value>),
+        (status = 413, description = "File over UPLOAD_MAX_BYTES", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn upload_attachment(
+    AdminUser(user): AdminUser,
+    State(state): State<AppState>,
+    scope: RequestScope,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(item_id): Path<i32>,
+    mut multipart: Multipart,
+) -> AppResult<(StatusCode, Negotiated<Envelope<ItemAttachment>>)> {
+    find_visible_item(&state.pool, &scope, item_id).await?;
+    let file = uploads::receive_file(&mut multipart, &state.config.uploads).await?;
+
+    let key = format!("{}{}", KEY_PREFIX, Uuid::new_v4());
+    let size_bytes = file.data.len() as i64;
+    state.storage.put(&key, file.data).await?;
+    let attachment = match AttachmentService::create(
+        &state.pool,
+        item_id,
+        user.id,
+        &file.filename,
+        &file.content_type,
+        size_bytes,
+        &key,
+    )
+    .await
+    {
+        Ok(attachment) => attachment,
+        Err(e) => {
+            let _ = state.storage.delete(&key).await;
+            return Err(e.into());
+        }
+    };
+
+    Ok((
+        StatusCode::CREATED,
+        Negotiated(
+            format,
+            Envelope::success(attachment)
+                .with_message("File attached")
+                .with_request_id(request_id),
+        ),
+    ))
+}
+
+/// Download an attachment of an item the caller can see
+#[utoipa::path(
+    get,
+    path = "/attachments/{id}",
+    tag = "files",
+    params(("id" = Uuid, Path, description = "Attachment ID")),
+    responses(
+        (status = 200, description = "The file contents"),
+        (status = 404, description = "No such attachment, or its item is not visible", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn download_attachment(
+    State(state): State<AppState>,
+    scope: RequestScope,
+    Path(id): Path<Uuid>,
+) -> AppResult<Response> {
+    let not_found = || AppError::NotFound(format!("Attachment {} not found", id));
+    let attachment = AttachmentService::find(&state.pool, id)
+        .await?
+        .ok_or_else(not_found)?;
+    find_visible_item(&state.pool, &scope, attachment.item_id)
+        .await
+        .map_err(|_| not_found())?;
+
+    let data = state.storage.get(&attachment.storage_key).await?;
+    uploads::file_response(&attachment.content_type, &attachment.filename, data)
+}
+
+/// Delete an attachment
+#[utoipa::path(
+    delete,
+    path = "/api/items/{id}/attachments/{attachment_id}",
+    tag = "files",
+    params(
+        ("id" = i32, Path, description = "Item ID"),
+        ("attachment_id" = Uuid, Path, description = "Attachment ID")
+    ),
+    responses(
+        (status = 200, description = "Attachment deleted", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such attachment, or not the caller's", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn delete_attachment(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path((item_id, id)): Path<(i32, Uuid)>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let attachment = find_deletable(&state.pool, &user, item_id, id).await?;
+    AttachmentService::delete(&state.pool, attachment.id).await?;
+    state.storage.delete(&attachment.storage_key).await?;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Attachment deleted").with_request_id(request_id),
+    ))
+}
+
+/// An attachment of `item_id` the user may delete: their own, or any for
+/// admins
+async fn find_deletable(
+    pool: &PgPool,
+    user: &AuthenticatedUser,
+    item_id: i32,
+    id: Uuid,
+) -> AppResult<ItemAttachment> {
+    let not_found = || AppError::NotFound(format!("Attachment {} not found", id));
+    let attachment = AttachmentService::find(pool, id)
+        .await?
+        .filter(|attachment| attachment.item_id == item_id)
+        .ok_or_else(not_found)?;
+    if attachment.user_id == user.id {
+        return Ok(attachment);
+    }
+
+    let roles = UserService::get_user_roles(pool, user.id).await?;
+    if roles.iter().any(|role| role == ADMIN_ROLE) {
+        Ok(attachment)
+    } else {
+        Err(not_found())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_valid_storage_keys() {
+        let key = format!("{}{}", KEY_PREFIX, Uuid::new_v4());
+        assert!(uploads::is_valid_key(&key));
+    }
+}
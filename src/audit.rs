@@ -0,0 +1,222 @@
+//! # Audit Log
+//!
+//! Security-relevant actions recorded in the `audit_log` table through
+//! [`AuditService`]: sign-ins (and failed ones), sign-outs, password and
+//! profile changes, the startup admin bootstrap, and changes made through
+//! the admin API. Admins query it at `/api/admin/audit` and browse it at
+//! `/admin/audit`; users see a filtered view of their own entries through
+//! [`activity`](crate::activity).
+//!
+//! An entry names who acted, what they did, and what they did it to; the
+//! details hold facts such as the changed fields, never passwords. Failing
+//! to write an entry is logged but does not undo or fail the action. SQL
+//! console queries and email template edits keep their own history.
+
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+
+use crate::api::RequestId;
+use crate::auth::AdminUser;
+use crate::error::AppResult;
+use crate::formats::{Accept, Negotiated};
+use crate::models::{AuditEntry, AuditFilter, Envelope};
+use crate::output;
+use crate::pagination::{CursorQuery, Keyset, Pagination, PaginationQuery};
+use crate::services::AuditService;
+use crate::state::AppState;
+
+/// Signed in
+pub const LOGIN: &str = "login";
+/// Sign-in with a wrong password or an unknown or inactive username
+pub const LOGIN_FAILED: &str = "login_failed";
+/// Signed out
+pub const LOGOUT: &str = "logout";
+/// Signed out of every session at once
+pub const LOGOUT_EVERYWHERE: &str = "logout_everywhere";
+/// Changed their own password
+pub const PASSWORD_CHANGED: &str = "password_changed";
+/// Changed their own email, language, or time zone
+pub const PROFILE_UPDATED: &str = "profile_updated";
+/// Changed their own username
+pub const USERNAME_CHANGED: &str = "username_changed";
+/// Changed or reset their own preferences
+pub const PREFERENCES_UPDATED: &str = "preferences_updated";
+/// The admin account was created or repaired at startup
+pub const ADMIN_BOOTSTRAPPED: &str = "admin.bootstrapped";
+/// An admin created a user
+pub const USER_CREATED: &str = "admin.user_created";
+/// An admin deactivated a user, ending their sessions
+pub const USER_DEACTIVATED: &str = "admin.user_deactivated";
+/// An admin let a deactivated user sign in again
+pub const USER_REACTIVATED: &str = "admin.user_reactivated";
+/// An operator permanently deleted a user
+pub const USER_DELETED: &str = "admin.user_deleted";
+/// An admin set a user's password
+pub const PASSWORD_SET: &str = "admin.password_set";
+/// An admin replaced a user's roles
+pub const ROLES_ASSIGNED: &str = "admin.roles_assigned";
+/// An admin renamed a user
+pub const USERNAME_SET: &str = "admin.username_set";
+/// An admin created a tenant
+pub const TENANT_CREATED: &str = "admin.tenant_created";
+/// An admin changed a tenant's theme or template partials
+pub const TENANT_UPDATED: &str = "admin.tenant_updated";
+/// An admin added, verified, or removed a tenant domain
+pub const DOMAIN_CHANGED: &str = "admin.domain_changed";
+/// An admin allowed mail to a suppressed address again
+pub const SUPPRESSION_REMOVED: &str = "admin.suppression_removed";
+/// An admin saved or deleted a snippet
+pub const SNIPPET_CHANGED: &str = "admin.snippet_changed";
+
+/// Every action above, for filtering
+pub const ACTIONS: &[&str] = &[
+    LOGIN,
+    LOGIN_FAILED,
+    LOGOUT,
+    LOGOUT_EVERYWHERE,
+    PASSWORD_CHANGED,
+    PROFILE_UPDATED,
+    USERNAME_CHANGED,
+    PREFERENCES_UPDATED,
+    ADMIN_BOOTSTRAPPED,
+    USER_CREATED,
+    USER_DEACTIVATED,
+    USER_REACTIVATED,
+    USER_DELETED,
+    PASSWORD_SET,
+    ROLES_ASSIGNED,
+    USERNAME_SET,
+    TENANT_CREATED,
+    TENANT_UPDATED,
+    DOMAIN_CHANGED,
+    SUPPRESSION_REMOVED,
+    SNIPPET_CHANGED,
+];
+
+/// An action about to be recorded
+#[derive(Debug, Clone)]
+pub struct AuditEvent {
+    pub action: &'static str,
+    pub actor_id: Option<i32>,
+    pub target_type: Option<&'static str>,
+    pub target_id: Option<String>,
+    pub details: Value,
+}
+
+#[allow(dead_code)]
+impl AuditEvent {
+    /// `action` by nobody in particular, on nothing in particular
+    pub fn new(action: &'static str) -> Self {
+        Self {
+            action,
+            actor_id: None,
+            target_type: None,
+            target_id: None,
+            details: json!({}),
+        }
+    }
+
+    /// Done by the user `actor_id`
+    pub fn by(mut self, actor_id: i32) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    /// Done to the `target_type` identified by `target_id`
+    pub fn on(mut self, target_type: &'static str, target_id: impl ToString) -> Self {
+        self.target_type = Some(target_type);
+        self.target_id = Some(target_id.to_string());
+        self
+    }
+
+    /// Attach facts about the action
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+/// Write `event` to the audit log; a failure is logged, not returned
+pub async fn record(pool: &PgPool, event: AuditEvent) {
+    if let Err(e) = AuditService::record(
+        pool,
+        event.actor_id,
+        event.action,
+        event.target_type,
+        event.target_id.as_deref(),
+        &event.details,
+    )
+    .await
+    {
+        output::error(format!(
+            "Failed to record audit event {}: {}",
+            event.action, e
+        ));
+    }
+}
+
+/// `/api/admin/audit`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/admin/audit", get(list_audit_log))
+}
+
+/// Audit log entries, newest first, filtered by actor, action, and target,
+/// paginated by page or by cursor
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit",
+    tag = "admin",
+    params(AuditFilter, PaginationQuery, CursorQuery),
+    responses(
+        (status = 200, description = "One page of entries", body = Envelope<Vec<AuditEntry>>),
+        (status = 400, description = "Invalid pagination parameters or cursor", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_audit_log(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(filter): Query<AuditFilter>,
+    pagination: Pagination,
+    keyset: Keyset,
+) -> AppResult<Negotiated<Envelope<Vec<AuditEntry>>>> {
+    let envelope = if keyset.is_on() {
+        let page = AuditService::list_after(&pool, &filter, keyset.after(), &pagination).await?;
+        Envelope::cursor_paginated(page)
+    } else {
+        Envelope::paginated(AuditService::list(&pool, &filter, &pagination).await?)
+    };
+    Ok(Negotiated(format, envelope.with_request_id(request_id)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_builder() {
+        let event = AuditEvent::new(ROLES_ASSIGNED)
+            .by(1)
+            .on("user", 42)
+            .with_details(json!({ "roles": ["admin"] }));
+
+        assert_eq!(event.action, "admin.roles_assigned");
+        assert_eq!(event.actor_id, Some(1));
+        assert_eq!(event.target_type, Some("user"));
+        assert_eq!(event.target_id.as_deref(), Some("42"));
+        assert_eq!(event.details["roles"][0], "admin");
+
+        let event = AuditEvent::new(LOGIN_FAILED);
+        assert_eq!(event.actor_id, None);
+        assert_eq!(event.details, json!({}));
+    }
+}
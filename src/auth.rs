@@ -3,34 +3,104 @@
 //! Handles password hashing, session management, and user authentication.
 
 use argon2::{
-    Argon2,
-    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
+    Algorithm, Argon2, Params, Version,
+    password_hash::{
+        PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+        rand_core::{OsRng, RngCore},
+    },
 };
-use chrono::Utc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tower_sessions::{SessionStore, session::Id};
+use tower_sessions_sqlx_store::PostgresStore;
 
-use crate::models::{AuthenticatedUser, User};
+use crate::config::PasswordsConfig;
+use crate::models::{AuthenticatedUser, User, chrono_to_time};
+use crate::output;
+use crate::repositories::UserRepository;
 
 // =============================================================================
 // Password Hashing Service
 // =============================================================================
 
+/// Argon2 parameters new hashes are made with, once configured
+static HASH_PARAMS: OnceLock<Params> = OnceLock::new();
+
 pub struct PasswordService;
 
 impl PasswordService {
-    /// Hash a password using Argon2
+    /// Make new hashes with the Argon2 parameters of `config`; the first call
+    /// wins. Without it the Argon2 defaults are used.
+    pub fn configure(config: &PasswordsConfig) -> Result<(), argon2::Error> {
+        let params = config.argon2_params()?;
+        let _ = HASH_PARAMS.set(params);
+        Ok(())
+    }
+
+    fn params() -> Params {
+        HASH_PARAMS.get().cloned().unwrap_or_default()
+    }
+
+    /// Hash a password using Argon2id with the configured parameters
     pub fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
+        Self::hash_password_with(password, Self::params())
+    }
+
+    /// Hash a password using Argon2id with `params`
+    pub fn hash_password_with(
+        password: &str,
+        params: Params,
+    ) -> Result<String, argon2::password_hash::Error> {
         let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
         let password_hash = argon2.hash_password(password.as_bytes(), &salt)?;
         Ok(password_hash.to_string())
     }
 
-    /// Verify a password against a hash
+    /// Whether `hash` was made with another algorithm, version or parameters
+    /// than new hashes are, so it should be replaced at the next sign-in.
+    /// The PHC string records all of them next to the hash.
+    pub fn needs_rehash(hash: &str) -> bool {
+        Self::needs_rehash_with(hash, &Self::params())
+    }
+
+    /// [`Self::needs_rehash`] against `params` rather than the configured ones
+    pub fn needs_rehash_with(hash: &str, params: &Params) -> bool {
+        if LegacyScheme::of(hash).is_some() {
+            return true;
+        }
+        let Ok(parsed) = PasswordHash::new(hash) else {
+            return false; // Not ours to upgrade; verification fails anyway
+        };
+        if parsed.algorithm != Algorithm::Argon2id.ident()
+            || parsed.version != Some(Version::V0x13.into())
+        {
+            return true;
+        }
+        match Params::try_from(&parsed) {
+            Ok(used) => {
+                used.m_cost() != params.m_cost()
+                    || used.t_cost() != params.t_cost()
+                    || used.p_cost() != params.p_cost()
+            }
+            Err(_) => true,
+        }
+    }
+
+    /// Verify a password against a hash. bcrypt and PBKDF2 hashes imported
+    /// from other systems are verified too when built with
+    /// `--features legacy-hashes`.
     pub fn verify_password(
         password: &str,
         hash: &str,
     ) -> Result<bool, argon2::password_hash::Error> {
+        if let Some(scheme) = LegacyScheme::of(hash) {
+            return scheme.verify(password, hash);
+        }
         let parsed_hash = PasswordHash::new(hash)?;
         let argon2 = Argon2::default();
         match argon2.verify_password(password.as_bytes(), &parsed_hash) {
@@ -39,6 +109,114 @@ impl PasswordService {
             Err(e) => Err(e),
         }
     }
+
+    /// A random password of [`GENERATED_PASSWORD_LENGTH`] letters and digits,
+    /// leaving out look-alikes such as `0`/`O` and `1`/`l` so it can be read
+    /// aloud or retyped
+    #[allow(dead_code)]
+    pub fn generate_password() -> String {
+        const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz23456789";
+        // Bytes past the last whole multiple of the alphabet are rejected so
+        // every character is equally likely
+        let limit = (256 / ALPHABET.len() * ALPHABET.len()) as u8;
+        let mut password = String::with_capacity(GENERATED_PASSWORD_LENGTH);
+        let mut byte = [0u8; 1];
+        while password.len() < GENERATED_PASSWORD_LENGTH {
+            OsRng.fill_bytes(&mut byte);
+            if byte[0] < limit {
+                password.push(ALPHABET[byte[0] as usize % ALPHABET.len()] as char);
+            }
+        }
+        password
+    }
+}
+
+/// Schemes of other systems whose hashes can be signed in with, told apart by
+/// the prefix of the stored hash; they are replaced with Argon2 at sign-in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LegacyScheme {
+    /// `$2a$`, `$2b$` or `$2y$`
+    Bcrypt,
+    /// PHC strings such as `$pbkdf2-sha256$i=...,l=...$salt$hash`
+    Pbkdf2,
+}
+
+impl LegacyScheme {
+    fn of(hash: &str) -> Option<Self> {
+        if ["$2a$", "$2b$", "$2y$"]
+            .iter()
+            .any(|prefix| hash.starts_with(prefix))
+        {
+            Some(Self::Bcrypt)
+        } else if hash.starts_with("$pbkdf2$") || hash.starts_with("$pbkdf2-") {
+            Some(Self::Pbkdf2)
+        } else {
+            None
+        }
+    }
+
+    #[cfg(feature = "legacy-hashes")]
+    fn verify(self, password: &str, hash: &str) -> Result<bool, argon2::password_hash::Error> {
+        match self {
+            Self::Bcrypt => bcrypt::verify(password, hash)
+                .map_err(|_| argon2::password_hash::Error::PhcStringField),
+            Self::Pbkdf2 => {
+                let parsed_hash = PasswordHash::new(hash)?;
+                match pbkdf2::Pbkdf2.verify_password(password.as_bytes(), &parsed_hash) {
+                    Ok(()) => Ok(true),
+                    Err(argon2::password_hash::Error::Password) => Ok(false),
+                    Err(e) => Err(e),
+                }
+            }
+        }
+    }
+
+    #[cfg(not(feature = "legacy-hashes"))]
+    fn verify(self, _password: &str, _hash: &str) -> Result<bool, argon2::password_hash::Error> {
+        Err(argon2::password_hash::Error::Algorithm)
+    }
+}
+
+// =============================================================================
+// Password Policy
+// =============================================================================
+
+/// Rules a password must meet when it is set or changed: the minimum length
+/// and, with the `pwned` feature and `PASSWORD_BREACH_CHECK`, absence from
+/// known breaches
+#[derive(Debug, Default)]
+pub struct PasswordPolicy {
+    #[cfg(feature = "pwned")]
+    breaches: Option<crate::pwned::PwnedPasswords>,
+}
+
+impl PasswordPolicy {
+    #[cfg_attr(not(feature = "pwned"), allow(unused_variables))]
+    pub fn from_config(config: &PasswordsConfig) -> Self {
+        Self {
+            #[cfg(feature = "pwned")]
+            breaches: config.breach_check.then(|| {
+                crate::pwned::PwnedPasswords::new(
+                    &config.breach_api_url,
+                    std::time::Duration::from_millis(config.breach_timeout_ms),
+                )
+            }),
+        }
+    }
+
+    /// Reject `password` with a message for the user
+    pub async fn check(&self, password: &str) -> Result<(), AppError> {
+        crate::admin::validate_password(password)?;
+        #[cfg(feature = "pwned")]
+        if let Some(breaches) = &self.breaches
+            && breaches.is_breached(password).await
+        {
+            return Err(AppError::Validation(
+                "This password has appeared in a data breach; please choose another".to_string(),
+            ));
+        }
+        Ok(())
+    }
 }
 
 // =============================================================================
@@ -51,68 +229,56 @@ pub struct AuthService;
 impl AuthService {
     /// Authenticate a user with username and password
     pub async fn authenticate_user(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         username: &str,
         password: &str,
     ) -> Result<Option<AuthenticatedUser>, sqlx::Error> {
-        // Get user by username
-        let user = sqlx::query_as::<_, User>(
-            "SELECT id, username, email, password_hash, email_verified, is_active, last_login, created_at, updated_at 
-             FROM users 
-             WHERE username = $1 AND is_active = true"
-        )
-        .bind(username)
-        .fetch_optional(pool)
-        .await?;
+        // Only active users are found
+        let Some(user) = users.get_user_by_username(username).await? else {
+            return Ok(None);
+        };
+        let Some(hash) = &user.password_hash else {
+            return Ok(None); // No password set
+        };
 
-        if let Some(user) = user {
-            // Check if user has a password hash
-            if let Some(hash) = &user.password_hash {
-                // Verify password
-                match PasswordService::verify_password(password, hash) {
-                    Ok(true) => {
-                        // Update last login time
-                        let now = Utc::now();
-                        sqlx::query(
-                            "UPDATE users SET last_login = $1, updated_at = $1 WHERE id = $2",
-                        )
-                        .bind(now)
-                        .bind(user.id)
-                        .execute(pool)
-                        .await?;
-
-                        Ok(Some(user.into()))
+        match PasswordService::verify_password(password, hash) {
+            Ok(true) => {
+                users.update_last_login(user.id).await?;
+                // The password is at hand only now, so outdated hashes are
+                // upgraded here; a failure leaves the old, still valid one
+                if PasswordService::needs_rehash(hash) {
+                    match PasswordService::hash_password(password) {
+                        Ok(new_hash) => {
+                            if let Err(e) = users.update_user_password(user.id, &new_hash).await {
+                                output::warn(format!(
+                                    "Could not upgrade password hash of user {}: {}",
+                                    user.id, e
+                                ));
+                            }
+                        }
+                        Err(e) => output::warn(format!(
+                            "Could not upgrade password hash of user {}: {}",
+                            user.id, e
+                        )),
                     }
-                    Ok(false) => Ok(None), // Wrong password
-                    Err(_) => Ok(None),    // Hash verification error
                 }
-            } else {
-                Ok(None) // No password set
+                Ok(Some(user.into()))
             }
-        } else {
-            Ok(None) // User not found or not active
+            Ok(false) => Ok(None), // Wrong password
+            Err(_) => Ok(None),    // Hash verification error
         }
     }
 
     /// Set password for a user (used for initial setup or admin password resets)
     pub async fn set_user_password(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         user_id: i32,
         password: &str,
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let password_hash = PasswordService::hash_password(password)
             .map_err(|e| format!("Password hashing error: {}", e))?;
-        let now = Utc::now();
 
-        let result =
-            sqlx::query("UPDATE users SET password_hash = $1, updated_at = $2 WHERE id = $3")
-                .bind(password_hash)
-                .bind(now)
-                .bind(user_id)
-                .execute(pool)
-                .await?;
-
-        if result.rows_affected() == 0 {
+        if !users.update_user_password(user_id, &password_hash).await? {
             return Err(format!("User with ID {} not found", user_id).into());
         }
 
@@ -121,22 +287,12 @@ impl AuthService {
 
     /// Change user password (requires current password verification)
     pub async fn change_user_password(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         user_id: i32,
         current_password: &str,
         new_password: &str,
     ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-        // Get current user
-        let user = sqlx::query_as::<_, User>(
-            "SELECT id, username, email, password_hash, email_verified, is_active, last_login, created_at, updated_at 
-             FROM users 
-             WHERE id = $1 AND is_active = true"
-        )
-        .bind(user_id)
-        .fetch_optional(pool)
-        .await?;
-
-        if let Some(user) = user
+        if let Some(user) = users.get_user_by_id(user_id).await?
             && let Some(current_hash) = &user.password_hash
         {
             // Verify current password
@@ -146,14 +302,7 @@ impl AuthService {
                 // Hash new password and update
                 let new_hash = PasswordService::hash_password(new_password)
                     .map_err(|e| format!("Password hashing error: {}", e))?;
-                let now = Utc::now();
-
-                sqlx::query("UPDATE users SET password_hash = $1, updated_at = $2 WHERE id = $3")
-                    .bind(new_hash)
-                    .bind(now)
-                    .bind(user_id)
-                    .execute(pool)
-                    .await?;
+                users.update_user_password(user_id, &new_hash).await?;
 
                 return Ok(true);
             }
@@ -162,25 +311,108 @@ impl AuthService {
         Ok(false)
     }
 
-    /// Update user profile (email, etc.)
+    /// Update user profile (email, etc.); returns false for unknown or
+    /// deactivated users
     pub async fn update_user_profile(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         user_id: i32,
         email: &str,
     ) -> Result<bool, sqlx::Error> {
-        let now = Utc::now();
-        let result = sqlx::query(
-            "UPDATE users SET email = $1, updated_at = $2 WHERE id = $3 AND is_active = true",
+        if users.get_user_by_id(user_id).await?.is_none() {
+            return Ok(false);
+        }
+        users.update_user_email(user_id, email).await
+    }
+
+    /// Remember that the session `session_id` is signed in as `user_id`, so
+    /// [`end_sessions`](Self::end_sessions) can find it
+    pub async fn track_session(
+        pool: &PgPool,
+        user_id: i32,
+        session_id: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO user_sessions (session_id, user_id) VALUES ($1, $2)
+             ON CONFLICT (session_id) DO UPDATE SET user_id = EXCLUDED.user_id, created_at = NOW()",
         )
-        .bind(email)
-        .bind(now)
+        .bind(session_id)
         .bind(user_id)
         .execute(pool)
         .await?;
+        Ok(())
+    }
+
+    /// Stop tracking a session that signed out
+    pub async fn forget_session(pool: &PgPool, session_id: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM user_sessions WHERE session_id = $1")
+            .bind(session_id)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
 
+    /// Delete every session signed in as `user_id` from the session store
+    /// and return their IDs. A session the store fails to delete is logged.
+    pub async fn end_sessions(pool: &PgPool, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+        let session_ids: Vec<String> =
+            sqlx::query_scalar("DELETE FROM user_sessions WHERE user_id = $1 RETURNING session_id")
+                .bind(user_id)
+                .fetch_all(pool)
+                .await?;
+
+        let store = PostgresStore::new(pool.clone());
+        for session_id in &session_ids {
+            let Ok(id) = Id::from_str(session_id) else {
+                continue;
+            };
+            if let Err(e) = store.delete(&id).await {
+                output::error(format!("Failed to end session of user {}: {}", user_id, e));
+            }
+        }
+        Ok(session_ids)
+    }
+
+    /// Make the user choose a new password after their next sign-in, or lift
+    /// that once they have; returns false if the user does not exist
+    pub async fn require_password_change(
+        pool: &PgPool,
+        user_id: i32,
+        required: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("UPDATE users SET must_change_password = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(required)
+            .execute(pool)
+            .await?;
         Ok(result.rows_affected() > 0)
     }
 
+    /// Whether the user was asked to choose a new password
+    pub async fn password_change_required(
+        pool: &PgPool,
+        user_id: i32,
+    ) -> Result<bool, sqlx::Error> {
+        let required: Option<bool> =
+            sqlx::query_scalar("SELECT must_change_password FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(pool)
+                .await?;
+        Ok(required.unwrap_or(false))
+    }
+
+    /// Stop tracking sessions the store no longer has, such as expired ones
+    pub async fn prune_sessions(pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "DELETE FROM user_sessions tracked
+             WHERE NOT EXISTS (
+                 SELECT 1 FROM tower_sessions.session stored WHERE stored.id = tracked.session_id
+             )",
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
     /// Create a new user (for admin use since registration is disabled)
     pub async fn create_user(
         pool: &PgPool,
@@ -199,15 +431,20 @@ impl AuthService {
 
         let now = Utc::now();
 
-        let user = sqlx::query_as::<_, User>(
-            "INSERT INTO users (username, email, password_hash, email_verified, is_active, created_at, updated_at) 
-             VALUES ($1, $2, $3, false, true, $4, $4) 
-             RETURNING id, username, email, password_hash, email_verified, is_active, last_login, created_at, updated_at"
+        let user = sqlx::query_as!(
+            User,
+            r#"INSERT INTO users (username, email, password_hash, email_verified, is_active, created_at, updated_at)
+             VALUES ($1, $2, $3, false, true, $4, $4)
+             RETURNING id, username, email, password_hash, email_verified, is_active,
+                       display_name, bio, profile_data,
+                       last_login as "last_login: DateTime<Utc>",
+                       created_at as "created_at: DateTime<Utc>",
+                       updated_at as "updated_at: DateTime<Utc>""#,
+            username,
+            email,
+            password_hash,
+            chrono_to_time(now)
         )
-        .bind(username)
-        .bind(email)
-        .bind(password_hash)
-        .bind(now)
         .fetch_one(pool)
         .await?;
 
@@ -215,17 +452,290 @@ impl AuthService {
     }
 }
 
+// =============================================================================
+// Authenticator
+// =============================================================================
+
+/// The sign-in and session steps handlers take through
+/// [`AppState::auth`](crate::state::AppState::auth). [`PgPool`] implements it
+/// with [`AuthService`]; [`InMemoryAuthenticator`] lets handler tests and
+/// downstream apps exercise login flows without Postgres.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    /// Active user with this username and password
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<AuthenticatedUser>, sqlx::Error>;
+
+    /// Replace the user's password without checking the current one
+    async fn set_user_password(
+        &self,
+        user_id: i32,
+        password: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Replace the user's password; false if `current_password` is wrong
+    async fn change_user_password(
+        &self,
+        user_id: i32,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+
+    /// Change the user's email; false for unknown or deactivated users
+    async fn update_user_profile(&self, user_id: i32, email: &str) -> Result<bool, sqlx::Error>;
+
+    /// Remember that `session_id` is signed in as `user_id`
+    async fn track_session(&self, user_id: i32, session_id: &str) -> Result<(), sqlx::Error>;
+
+    /// Stop tracking a session that signed out
+    async fn forget_session(&self, session_id: &str) -> Result<(), sqlx::Error>;
+
+    /// End every session signed in as `user_id`, returning their IDs
+    async fn end_sessions(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error>;
+
+    /// Set or lift the must-change-password flag; false if the user does not exist
+    async fn require_password_change(
+        &self,
+        user_id: i32,
+        required: bool,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Whether the user was asked to choose a new password
+    async fn password_change_required(&self, user_id: i32) -> Result<bool, sqlx::Error>;
+}
+
+#[async_trait]
+impl Authenticator for PgPool {
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<AuthenticatedUser>, sqlx::Error> {
+        AuthService::authenticate_user(self, username, password).await
+    }
+
+    async fn set_user_password(
+        &self,
+        user_id: i32,
+        password: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        AuthService::set_user_password(self, user_id, password).await
+    }
+
+    async fn change_user_password(
+        &self,
+        user_id: i32,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        AuthService::change_user_password(self, user_id, current_password, new_password).await
+    }
+
+    async fn update_user_profile(&self, user_id: i32, email: &str) -> Result<bool, sqlx::Error> {
+        AuthService::update_user_profile(self, user_id, email).await
+    }
+
+    async fn track_session(&self, user_id: i32, session_id: &str) -> Result<(), sqlx::Error> {
+        AuthService::track_session(self, user_id, session_id).await
+    }
+
+    async fn forget_session(&self, session_id: &str) -> Result<(), sqlx::Error> {
+        AuthService::forget_session(self, session_id).await
+    }
+
+    async fn end_sessions(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+        AuthService::end_sessions(self, user_id).await
+    }
+
+    async fn require_password_change(
+        &self,
+        user_id: i32,
+        required: bool,
+    ) -> Result<bool, sqlx::Error> {
+        AuthService::require_password_change(self, user_id, required).await
+    }
+
+    async fn password_change_required(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+        AuthService::password_change_required(self, user_id).await
+    }
+}
+
+#[allow(dead_code)]
+struct Account {
+    user: AuthenticatedUser,
+    password: String,
+    must_change_password: bool,
+}
+
+/// [`Authenticator`] over users held in memory. Passwords are compared as
+/// given rather than hashed, and sessions are only tracked, not stored.
+///
+/// ```rust,ignore
+/// let auth = InMemoryAuthenticator::new().with_user(alice, "secret");
+/// let state = AppState::new(pool, config, templates).with_authenticator(auth);
+/// ```
+#[derive(Default)]
+#[allow(dead_code)]
+pub struct InMemoryAuthenticator {
+    accounts: Mutex<HashMap<i32, Account>>,
+    sessions: Mutex<HashMap<String, i32>>,
+}
+
+#[allow(dead_code)]
+impl InMemoryAuthenticator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `user`, who signs in with `password` unless deactivated
+    pub fn with_user(self, user: AuthenticatedUser, password: &str) -> Self {
+        self.accounts.lock().unwrap().insert(
+            user.id,
+            Account {
+                user,
+                password: password.to_string(),
+                must_change_password: false,
+            },
+        );
+        self
+    }
+
+    /// IDs of the sessions tracked for `user_id`
+    pub fn sessions(&self, user_id: i32) -> Vec<String> {
+        let mut ids: Vec<String> = self
+            .sessions
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, owner)| **owner == user_id)
+            .map(|(id, _)| id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+}
+
+#[async_trait]
+impl Authenticator for InMemoryAuthenticator {
+    async fn authenticate_user(
+        &self,
+        username: &str,
+        password: &str,
+    ) -> Result<Option<AuthenticatedUser>, sqlx::Error> {
+        let accounts = self.accounts.lock().unwrap();
+        Ok(accounts
+            .values()
+            .find(|account| {
+                account.user.is_active
+                    && account.user.username == username
+                    && account.password == password
+            })
+            .map(|account| account.user.clone()))
+    }
+
+    async fn set_user_password(
+        &self,
+        user_id: i32,
+        password: &str,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        match self.accounts.lock().unwrap().get_mut(&user_id) {
+            Some(account) => {
+                account.password = password.to_string();
+                Ok(())
+            }
+            None => Err(format!("User with ID {} not found", user_id).into()),
+        }
+    }
+
+    async fn change_user_password(
+        &self,
+        user_id: i32,
+        current_password: &str,
+        new_password: &str,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let mut accounts = self.accounts.lock().unwrap();
+        match accounts.get_mut(&user_id) {
+            Some(account) if account.user.is_active && account.password == current_password => {
+                account.password = new_password.to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn update_user_profile(&self, user_id: i32, email: &str) -> Result<bool, sqlx::Error> {
+        let mut accounts = self.accounts.lock().unwrap();
+        match accounts.get_mut(&user_id) {
+            Some(account) if account.user.is_active => {
+                account.user.email = email.to_string();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn track_session(&self, user_id: i32, session_id: &str) -> Result<(), sqlx::Error> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session_id.to_string(), user_id);
+        Ok(())
+    }
+
+    async fn forget_session(&self, session_id: &str) -> Result<(), sqlx::Error> {
+        self.sessions.lock().unwrap().remove(session_id);
+        Ok(())
+    }
+
+    async fn end_sessions(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+        let ended = self.sessions(user_id);
+        self.sessions
+            .lock()
+            .unwrap()
+            .retain(|_, owner| *owner != user_id);
+        Ok(ended)
+    }
+
+    async fn require_password_change(
+        &self,
+        user_id: i32,
+        required: bool,
+    ) -> Result<bool, sqlx::Error> {
+        match self.accounts.lock().unwrap().get_mut(&user_id) {
+            Some(account) => {
+                account.must_change_password = required;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    async fn password_change_required(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+        let accounts = self.accounts.lock().unwrap();
+        Ok(accounts
+            .get(&user_id)
+            .is_some_and(|account| account.must_change_password))
+    }
+}
+
 // =============================================================================
 // Authentication Middleware
 // =============================================================================
 
 use axum::{
-    extract::Request,
+    extract::{FromRef, FromRequestParts, Request},
+    http::request::Parts,
     middleware::Next,
     response::{Redirect, Response},
 };
 use tower_sessions::Session;
 
+use crate::error::AppError;
+use crate::services::UserService;
+
 /// Middleware to require authentication
 #[allow(dead_code)]
 pub async fn require_auth(
@@ -257,8 +767,265 @@ pub async fn inject_user(session: Session, mut request: Request, next: Next) ->
     next.run(request).await
 }
 
+/// Give a signed-in session a new ID after its privileges change, keeping
+/// its data, so an ID learned before the change no longer works. The old ID
+/// is deleted from the store and `auth` tracks the new one for `user_id`.
+pub async fn renew_session(
+    auth: &dyn Authenticator,
+    session: &Session,
+    user_id: i32,
+) -> Result<(), AppError> {
+    let old_id = session.id().map(|id| id.to_string());
+    session
+        .cycle_id()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to renew session: {}", e)))?;
+    session
+        .save()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to save session: {}", e)))?;
+    if let Some(old_id) = old_id {
+        auth.forget_session(&old_id).await?;
+    }
+    if let Some(id) = session.id() {
+        auth.track_session(user_id, &id.to_string()).await?;
+    }
+    Ok(())
+}
+
+/// Extractor for API routes restricted to signed-in, active users
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub AuthenticatedUser);
+
+impl<S> FromRequestParts<S> for CurrentUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|(_, message)| AppError::Internal(message.to_string()))?;
+        let session_user = session
+            .get::<AuthenticatedUser>(USER_SESSION_KEY)
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))?;
+
+        // Re-check the database so deactivation applies immediately
+        let pool = PgPool::from_ref(state);
+        let user = UserService::get_user_by_id(&pool, session_user.id)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))?;
+
+        // The avatar is kept in the session rather than looked up per request
+        let mut user = AuthenticatedUser::from(user);
+        user.avatar_url = session_user.avatar_url;
+        Ok(CurrentUser(user))
+    }
+}
+
+/// Extractor for API routes restricted to active users with the admin role
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthenticatedUser);
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    PgPool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|(_, message)| AppError::Internal(message.to_string()))?;
+        let session_user = session
+            .get::<AuthenticatedUser>(USER_SESSION_KEY)
+            .await
+            .ok()
+            .flatten()
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))?;
+
+        // Re-check the database so deactivation and role changes apply immediately
+        let pool = PgPool::from_ref(state);
+        let user = UserService::get_user_by_id(&pool, session_user.id)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))?;
+        let roles = UserService::get_user_roles(&pool, user.id).await?;
+        if !roles.iter().any(|role| role == ADMIN_ROLE) {
+            return Err(AppError::Forbidden("Admin role required".to_string()));
+        }
+
+        Ok(AdminUser(user.into()))
+    }
+}
+
+/// Compare secrets such as tokens without exiting early on the first
+/// difference, so response times do not reveal how much of a guess matched
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
 // =============================================================================
-// Session Keys
+// Session Keys and Roles
 // =============================================================================
 
-pub const USER_SESSION_KEY: &str = "user";
+pub(crate) const USER_SESSION_KEY: &str = "user";
+
+/// Role granting access to the admin API
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Minimum length for user passwords
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Length of passwords made by [`PasswordService::generate_password`]
+#[allow(dead_code)]
+pub const GENERATED_PASSWORD_LENGTH: usize = 20;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret-token", b"secret-token"));
+        assert!(!constant_time_eq(b"secret-token", b"secret-tokem"));
+        assert!(!constant_time_eq(b"secret", b"secret-token"));
+    }
+
+    #[test]
+    fn test_generate_password() {
+        let password = PasswordService::generate_password();
+        assert_eq!(password.len(), GENERATED_PASSWORD_LENGTH);
+        assert!(password.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert!(!password.contains(['0', 'O', '1', 'l', 'I']));
+        assert_ne!(password, PasswordService::generate_password());
+    }
+
+    #[test]
+    fn test_needs_rehash_on_parameter_change() {
+        let old = Params::new(8 * 1024, 1, 1, None).unwrap();
+        let new = Params::new(16 * 1024, 2, 1, None).unwrap();
+        let hash = PasswordService::hash_password_with("correct horse", old.clone()).unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$m=8192,t=1,p=1$"));
+        assert!(PasswordService::verify_password("correct horse", &hash).unwrap());
+
+        assert!(!PasswordService::needs_rehash_with(&hash, &old));
+        assert!(PasswordService::needs_rehash_with(&hash, &new));
+
+        let argon2i = Argon2::new(Algorithm::Argon2i, Version::V0x13, old.clone())
+            .hash_password(b"correct horse", &SaltString::generate(&mut OsRng))
+            .unwrap()
+            .to_string();
+        assert!(PasswordService::needs_rehash_with(&argon2i, &old));
+        assert!(!PasswordService::needs_rehash_with("not a hash", &old));
+    }
+
+    /// PBKDF2-SHA256 of "password", 1000 rounds
+    const PBKDF2_HASH: &str = "$pbkdf2-sha256$i=1000,l=32$c2FsdHNhbHRzYWx0MTIzNA$\
+                               Gv1ppJ66rBGZ4SMZjQl10XY734zFaJuHAY10Xd6pb+U";
+
+    #[test]
+    fn test_legacy_hashes_need_rehash() {
+        let params = Params::default();
+        assert!(PasswordService::needs_rehash_with(PBKDF2_HASH, &params));
+        assert!(PasswordService::needs_rehash_with(
+            "$2b$04$abcdefghijklmnopqrstuu5u9tdbd6nvgLK6YHiTiPTdBx7hQDGO",
+            &params
+        ));
+    }
+
+    #[cfg(feature = "legacy-hashes")]
+    #[test]
+    fn test_legacy_hashes_verified() {
+        let bcrypt = bcrypt::hash("password", 4).unwrap();
+        assert!(PasswordService::verify_password("password", &bcrypt).unwrap());
+        assert!(!PasswordService::verify_password("wrong", &bcrypt).unwrap());
+
+        assert!(PasswordService::verify_password("password", PBKDF2_HASH).unwrap());
+        assert!(!PasswordService::verify_password("wrong", PBKDF2_HASH).unwrap());
+        let sha1 = "$pbkdf2$i=1000,l=20$c2FsdHNhbHRzYWx0MTIzNA$R1k4MvONNR3wAUqq0wvjbHBLmKc";
+        assert!(PasswordService::verify_password("password", sha1).unwrap());
+    }
+
+    fn alice() -> AuthenticatedUser {
+        AuthenticatedUser {
+            id: 7,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            is_active: true,
+            avatar_url: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_authenticator() {
+        let auth: &dyn Authenticator = &InMemoryAuthenticator::new().with_user(alice(), "secret");
+        assert!(
+            auth.authenticate_user("alice", "wrong")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let user = auth.authenticate_user("alice", "secret").await.unwrap();
+        assert_eq!(user.map(|user| user.id), Some(7));
+
+        assert!(
+            !auth
+                .change_user_password(7, "wrong", "new secret")
+                .await
+                .unwrap()
+        );
+        assert!(
+            auth.change_user_password(7, "secret", "new secret")
+                .await
+                .unwrap()
+        );
+        assert!(
+            auth.authenticate_user("alice", "new secret")
+                .await
+                .unwrap()
+                .is_some()
+        );
+        assert!(auth.set_user_password(8, "secret").await.is_err());
+
+        assert!(auth.require_password_change(7, true).await.unwrap());
+        assert!(auth.password_change_required(7).await.unwrap());
+        assert!(!auth.require_password_change(8, true).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_sessions() {
+        let auth = InMemoryAuthenticator::new().with_user(alice(), "secret");
+        auth.track_session(7, "b").await.unwrap();
+        auth.track_session(7, "a").await.unwrap();
+        auth.track_session(8, "c").await.unwrap();
+        auth.forget_session("b").await.unwrap();
+        assert_eq!(auth.end_sessions(7).await.unwrap(), vec!["a"]);
+        assert!(auth.sessions(7).is_empty());
+        assert_eq!(auth.sessions(8), vec!["c"]);
+
+        // Deactivated users can neither sign in nor change their profile
+        let inactive = AuthenticatedUser {
+            is_active: false,
+            ..alice()
+        };
+        let auth = InMemoryAuthenticator::new().with_user(inactive, "secret");
+        assert!(
+            auth.authenticate_user("alice", "secret")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            !auth
+                .update_user_profile(7, "new@example.com")
+                .await
+                .unwrap()
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! # Avatars
+//!
+//! Signed-in users upload a profile picture from `/profile`, posting it as
+//! `multipart/form-data` to `/profile/avatar`. PNG, JPEG, GIF, and WebP
+//! images up to `UPLOAD_MAX_BYTES` are accepted; they are decoded, cropped
+//! to a square, resized to [`AVATAR_SIZE`] pixels, and re-encoded as PNG, so
+//! nothing the user sent is served back as-is.
+//!
+//! Avatars go to the same [`StorageBackend`](crate::uploads::StorageBackend)
+//! as uploads, under a fresh key each time, and the key is kept in
+//! `users.avatar_key`. They are public at `/avatars/{key}`; as a key is never
+//! reused, responses are cached for good. Templates get the address as
+//! `current_user.avatar_url`.
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Multipart, Path, State},
+    http::header,
+    response::{Redirect, Response},
+    routing::{get, post},
+};
+use bytes::{Bytes, BytesMut};
+use image::{ImageFormat, ImageReader, Limits, imageops::FilterType};
+use std::io::Cursor;
+use tower_sessions::Session;
+use uuid::Uuid;
+
+use crate::auth::USER_SESSION_KEY;
+use crate::config::UploadsConfig;
+use crate::error::{AppError, AppResult};
+use crate::flash::Flash;
+use crate::models::AuthenticatedUser;
+use crate::output;
+use crate::services::UserService;
+use crate::state::AppState;
+use crate::uploads;
+
+/// Multipart field holding the image
+pub const AVATAR_FIELD: &str = "avatar";
+
+/// Width and height of stored avatars, in pixels
+pub const AVATAR_SIZE: u32 = 256;
+
+/// Largest width or height of an image we agree to decode
+const MAX_DIMENSION: u32 = 8192;
+
+/// Prefix of avatar storage keys, keeping them apart from upload keys
+const KEY_PREFIX: &str = "avatar-";
+
+/// Image types accepted as avatars
+const ACCEPTED_TYPES: &[&str] = &["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Routes for changing and serving avatars
+pub fn routes(config: &UploadsConfig) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/profile/avatar",
+            post(handle_avatar_upload).layer(DefaultBodyLimit::max(
+                config.max_bytes + uploads::MULTIPART_OVERHEAD,
+            )),
+        )
+        .route("/profile/avatar/remove", post(handle_avatar_remove))
+        .route("/avatars/{key}", get(serve_avatar))
+}
+
+/// Public address of the avatar stored under `key`
+pub fn url(key: &str) -> String {
+    format!("/avatars/{}", key)
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// Replace the signed-in user's avatar; the outcome is flashed on the
+/// profile page
+pub async fn handle_avatar_upload(
+    State(state): State<AppState>,
+    session: Session,
+    flash: Flash,
+    multipart: Multipart,
+) -> Redirect {
+    let Some(user) = session_user(&session).await else {
+        return Redirect::to("/login");
+    };
+
+    match store_avatar(&state, user.id, multipart).await {
+        Ok(key) => {
+            let _ = session
+                .insert(USER_SESSION_KEY, user.with_avatar(Some(&key)))
+                .await;
+            flash.success("Avatar updated").await;
+        }
+        Err(e) => {
+            if e.status_code().is_server_error() {
+                output::error(format!("Failed to save avatar: {}", e));
+            }
+            flash.error(e.public_message()).await;
+        }
+    }
+    Redirect::to("/profile")
+}
+
+/// Remove the signed-in user's avatar
+pub async fn handle_avatar_remove(
+    State(state): State<AppState>,
+    session: Session,
+    flash: Flash,
+) -> Redirect {
+    let Some(user) = session_user(&session).await else {
+        return Redirect::to("/login");
+    };
+
+    let removed = async {
+        let old_key = UserService::get_avatar_key(&state.pool, user.id).await?;
+        UserService::set_avatar_key(&state.pool, user.id, None).await?;
+        if let Some(old_key) = old_key {
+            state.storage.delete(&old_key).await?;
+        }
+        Ok::<_, AppError>(())
+    }
+    .await;
+
+    match removed {
+        Ok(()) => {
+            let _ = session
+                .insert(USER_SESSION_KEY, user.with_avatar(None))
+                .await;
+            flash.success("Avatar removed").await;
+        }
+        Err(e) => {
+            output::error(format!("Failed to remove avatar: {}", e));
+            flash.error(e.public_message()).await;
+        }
+    }
+    Redirect::to("/profile")
+}
+
+/// Serve an avatar to anyone
+pub async fn serve_avatar(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+) -> AppResult<Response> {
+    // Only avatar keys, so this cannot reach private uploads
+    if !is_avatar_key(&key) {
+        return Err(AppError::NotFound("Avatar not found".to_string()));
+    }
+    let data = state.storage.get(&key).await?;
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CONTENT_LENGTH, data.len())
+        .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .body(Body::from(data))
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+async fn session_user(session: &Session) -> Option<AuthenticatedUser> {
+    session.get(USER_SESSION_KEY).await.ok().flatten()
+}
+
+/// Read, resize, and store the posted image, then point the user at it and
+/// drop their previous avatar. Returns the new storage key.
+async fn store_avatar(
+    state: &AppState,
+    user_id: i32,
+    mut multipart: Multipart,
+) -> AppResult<String> {
+    let max_bytes = state.config.uploads.max_bytes;
+
+    let mut field = loop {
+        match multipart
+            .next_field()
+            .await
+            .map_err(uploads::multipart_error)?
+        {
+            Some(field) if field.name() == Some(AVATAR_FIELD) => break field,
+            Some(_) => continue,
+            None => {
+                return Err(AppError::Validation(
+                    "Choose an image to upload".to_string(),
+                ));
+            }
+        }
+    };
+
+    let mut data = BytesMut::new();
+    while let Some(chunk) = field.chunk().await.map_err(uploads::multipart_error)? {
+        if data.len() + chunk.len() > max_bytes {
+            return Err(AppError::PayloadTooLarge(format!(
+                "Avatars are limited to {} bytes",
+                max_bytes
+            )));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    if data.is_empty() {
+        return Err(AppError::Validation(
+            "Choose an image to upload".to_string(),
+        ));
+    }
+
+    // Decoding is CPU-bound, so keep it off the async workers
+    let data = data.freeze();
+    let png = tokio::task::spawn_blocking(move || resize(&data))
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))??;
+
+    let key = format!("{}{}", KEY_PREFIX, Uuid::new_v4());
+    state.storage.put(&key, png).await?;
+
+    let old_key = UserService::get_avatar_key(&state.pool, user_id).await?;
+    if let Err(e) = UserService::set_avatar_key(&state.pool, user_id, Some(&key)).await {
+        let _ = state.storage.delete(&key).await;
+        return Err(e.into());
+    }
+    if let Some(old_key) = old_key
+        && let Err(e) = state.storage.delete(&old_key).await
+    {
+        output::warn(format!("Failed to delete old avatar {}: {}", old_key, e));
+    }
+
+    Ok(key)
+}
+
+/// Decode an accepted image and re-encode it as an [`AVATAR_SIZE`] square PNG
+fn resize(data: &[u8]) -> AppResult<Bytes> {
+    let invalid =
+        || AppError::Validation("Avatars must be PNG, JPEG, GIF, or WebP images".to_string());
+    let format = uploads::sniff(data)
+        .filter(|mime| ACCEPTED_TYPES.contains(mime))
+        .and_then(ImageFormat::from_mime_type)
+        .ok_or_else(invalid)?;
+
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+    let mut reader = ImageReader::with_format(Cursor::new(data), format);
+    reader.limits(limits);
+    let image = reader.decode().map_err(|_| invalid())?;
+
+    let mut png = Cursor::new(Vec::new());
+    image
+        .resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3)
+        .write_to(&mut png, ImageFormat::Png)
+        .map_err(|e| AppError::Internal(format!("failed to encode avatar: {}", e)))?;
+    Ok(Bytes::from(png.into_inner()))
+}
+
+/// Whether `key` is one [`store_avatar`] could have generated
+fn is_avatar_key(key: &str) -> bool {
+    key.strip_prefix(KEY_PREFIX)
+        .is_some_and(|id| Uuid::parse_str(id).is_ok_and(|uuid| uuid.hyphenated().to_string() == id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, RgbImage};
+
+    fn encode(image: DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, format).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn test_resize_crops_to_a_square_png() {
+        let photo = encode(
+            DynamicImage::ImageRgb8(RgbImage::new(640, 480)),
+            ImageFormat::Jpeg,
+        );
+
+        let png = resize(&photo).unwrap();
+        let avatar = image::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+        assert_eq!(avatar.width(), AVATAR_SIZE);
+        assert_eq!(avatar.height(), AVATAR_SIZE);
+    }
+
+    #[test]
+    fn test_resize_rejects_non_images() {
+        assert!(matches!(
+            resize(b"<svg xmlns=\"http://www.w3.org/2000/svg\"/>"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(resize(b"%PDF-1.7"), Err(AppError::Validation(_))));
+        // A PNG signature with nothing decodable after it
+        assert!(matches!(
+            resize(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_is_avatar_key() {
+        let key = format!("{}{}", KEY_PREFIX, Uuid::new_v4());
+        assert!(is_avatar_key(&key));
+        assert_eq!(url(&key), format!("/avatars/{}", key));
+        assert!(!is_avatar_key(&Uuid::new_v4().to_string()));
+        assert!(!is_avatar_key("avatar-../etc/passwd"));
+    }
+}
@@ -0,0 +1,116 @@
+//! # Database Backups
+//!
+//! Wraps `pg_dump` and `pg_restore` for `admin db dump` and `admin db
+//! restore`, pointing them at the configured `DATABASE_URL`. Dumps use the
+//! custom format so a restore can drop and recreate each object; both tools
+//! come with the PostgreSQL client and must be on `PATH`.
+
+use chrono::{DateTime, Utc};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Directory dumps go to when no file is named
+pub const DEFAULT_DIR: &str = "backups";
+
+/// Name of the database in a connection URL, without its query string
+pub fn database_name(url: &str) -> Option<&str> {
+    let rest = url.split_once("://")?.1;
+    let path = rest.split_once('/')?.1;
+    let name = path.split(['?', '#']).next().unwrap_or_default();
+    (!name.is_empty()).then_some(name)
+}
+
+/// Timestamped dump file in `dir`, e.g. `backups/axum_base-20240501-120000.dump`
+pub fn dump_path(dir: &Path, url: &str, now: DateTime<Utc>) -> PathBuf {
+    dir.join(format!(
+        "{}-{}.dump",
+        database_name(url).unwrap_or("database"),
+        now.format("%Y%m%d-%H%M%S")
+    ))
+}
+
+/// Dump the database at `url` to `output`, creating its directory
+pub fn dump(url: &str, output: &Path) -> io::Result<()> {
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut command = Command::new("pg_dump");
+    command
+        .args(["--format=custom", "--no-owner", "--file"])
+        .arg(output)
+        .arg("--dbname")
+        .arg(url);
+    run("pg_dump", command)
+}
+
+/// Replace the contents of the database at `url` with the dump at `input`.
+/// Runs in one transaction, so a failed restore leaves the database as it was.
+pub fn restore(url: &str, input: &Path) -> io::Result<()> {
+    let mut command = Command::new("pg_restore");
+    command
+        .args([
+            "--clean",
+            "--if-exists",
+            "--no-owner",
+            "--single-transaction",
+            "--exit-on-error",
+            "--dbname",
+        ])
+        .arg(url)
+        .arg(input);
+    run("pg_restore", command)
+}
+
+/// Run a client tool, passing its messages through, and fail on a non-zero exit
+fn run(tool: &str, mut command: Command) -> io::Result<()> {
+    let status = command.status().map_err(|e| {
+        if e.kind() == io::ErrorKind::NotFound {
+            io::Error::new(
+                e.kind(),
+                format!("{} not found; install the PostgreSQL client tools", tool),
+            )
+        } else {
+            e
+        }
+    })?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} failed ({})", tool, status)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_database_name() {
+        assert_eq!(
+            database_name("postgresql://app:secret@db:5432/axum_base?sslmode=require"),
+            Some("axum_base")
+        );
+        assert_eq!(
+            database_name("postgres://localhost/app_test"),
+            Some("app_test")
+        );
+        assert_eq!(database_name("postgres://localhost"), None);
+        assert_eq!(database_name("postgres://localhost/"), None);
+    }
+
+    #[test]
+    fn test_dump_path() {
+        let now = DateTime::parse_from_rfc3339("2024-05-01T12:30:45Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(
+            dump_path(
+                Path::new(DEFAULT_DIR),
+                "postgres://localhost/axum_base",
+                now
+            ),
+            Path::new("backups/axum_base-20240501-123045.dump")
+        );
+    }
+}
@@ -0,0 +1,531 @@
+//! # Admin CLI
+//!
+//! Maintenance commands that run the application in-process.
+//!
+//! ```text
+//! admin completions <bash|zsh|fish>
+//! admin console
+//! admin db dump [file]
+//! admin db restore <file> [--yes]
+//! admin export-static <output_dir> [path ...]
+//! admin export-types --lang ts [output.d.ts]
+//! admin mail check-dns [domain]
+//! admin templates check
+//! admin users list [options]
+//! admin users delete <user> [--soft | --hard --yes]
+//! admin users export [options]
+//! ```
+//!
+//! `--no-color` and `--quiet` before the command work as described in
+//! [`axum_base::output`].
+//!
+//! Exit codes follow [`axum_base::cli`]; failed checks exit with 6.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use chrono::Utc;
+use rustyline::DefaultEditor;
+use rustyline::error::ReadlineError;
+
+use axum_base::backup;
+use axum_base::cli::{self, CliError, CliResult};
+use axum_base::completions::{self, Shell, Spec};
+use axum_base::console::{self, Command, DeleteUser, ExportUsers, ListFormat, ListUsers};
+use axum_base::deliverability::{self, Status};
+use axum_base::export::{self, DEFAULT_SEEDS};
+use axum_base::output;
+use axum_base::routes::create_router;
+use axum_base::state::AppState;
+use axum_base::template_check::check_templates;
+use axum_base::typescript;
+use axum_base::uploads;
+use axum_base::web::{TEMPLATE_GLOB, TEMPLATE_MANIFEST, init_templates, load_templates};
+
+fn usage(program: &str) -> CliError {
+    CliError::Usage(format!(
+        "Usage: {} [--no-color] [--quiet] <command> [args]
+
+Commands:
+  completions <bash|zsh|fish>            Print a shell completion script for admin
+  console                                Interactive prompt for users, jobs, and plugin events
+  db dump [file]                         Back up the database with pg_dump (default: backups/)
+  db restore <file> [--yes]              Replace the database with a dump, after confirming
+  export-static <output_dir> [path ...]  Write a static HTML snapshot of public pages
+  export-types --lang ts [output.d.ts]   Write TypeScript declarations for the JSON API
+  mail check-dns [domain]                Check SPF, DKIM, and DMARC records for the sending domain
+  templates check                        Verify templates against handler expectations
+  users list [options]                   List users as a table or JSON, with filters and pages
+  users delete <user> [--hard --yes]     Deactivate a user, or delete them for good with --hard
+  users export [options]                 Dump users as CSV or JSON, without password hashes",
+        program
+    ))
+}
+
+/// Commands and options offered by `admin completions`
+const COMPLETIONS: Spec = Spec {
+    name: "admin",
+    options: &["--no-color", "--quiet"],
+    subcommands: &[
+        Spec::leaf("completions"),
+        Spec::leaf("console"),
+        Spec {
+            name: "db",
+            options: &[],
+            subcommands: &[
+                Spec::leaf("dump"),
+                Spec {
+                    name: "restore",
+                    options: &["--yes"],
+                    subcommands: &[],
+                },
+            ],
+        },
+        Spec::leaf("export-static"),
+        Spec {
+            name: "export-types",
+            options: &["--lang"],
+            subcommands: &[],
+        },
+        Spec {
+            name: "mail",
+            options: &[],
+            subcommands: &[Spec::leaf("check-dns")],
+        },
+        Spec {
+            name: "templates",
+            options: &[],
+            subcommands: &[Spec::leaf("check")],
+        },
+        Spec {
+            name: "users",
+            options: &[],
+            subcommands: &[
+                Spec {
+                    name: "list",
+                    options: &[
+                        "--active",
+                        "--inactive",
+                        "--email-like",
+                        "--created-after",
+                        "--role",
+                        "--search",
+                        "--format",
+                        "--page",
+                        "--per-page",
+                    ],
+                    subcommands: &[],
+                },
+                Spec {
+                    name: "delete",
+                    options: &["--soft", "--hard", "--yes"],
+                    subcommands: &[],
+                },
+                Spec {
+                    name: "export",
+                    options: &[
+                        "--active",
+                        "--inactive",
+                        "--email-like",
+                        "--created-after",
+                        "--role",
+                        "--search",
+                        "--format",
+                        "--output",
+                    ],
+                    subcommands: &[],
+                },
+            ],
+        },
+    ],
+};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Load environment variables from .env file
+    dotenvy::dotenv().ok();
+
+    let args = output::init(env::args().collect());
+
+    let result = match args.get(1).map(String::as_str) {
+        Some("completions") => completions(&args[0], &args[2..]),
+        Some("console") => console().await,
+        Some("db") if args.get(2).map(String::as_str) == Some("dump") => {
+            db_dump(&args[0], &args[3..])
+        }
+        Some("db") if args.get(2).map(String::as_str) == Some("restore") => {
+            db_restore(&args[0], &args[3..])
+        }
+        Some("export-static") => export_static(&args[0], &args[2..]).await,
+        Some("export-types") => export_types(&args[0], &args[2..]),
+        Some("mail") if args.get(2).map(String::as_str) == Some("check-dns") => {
+            mail_check_dns(args.get(3).map(String::as_str)).await
+        }
+        Some("templates") if args.get(2).map(String::as_str) == Some("check") => templates_check(),
+        Some("users") if args.get(2).map(String::as_str) == Some("list") => {
+            users_list(&args[0], &args[3..]).await
+        }
+        Some("users") if args.get(2).map(String::as_str) == Some("delete") => {
+            users_delete(&args[0], &args[3..]).await
+        }
+        Some("users") if args.get(2).map(String::as_str) == Some("export") => {
+            users_export(&args[0], &args[3..]).await
+        }
+        _ => Err(usage(&args[0])),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => err.report(),
+    }
+}
+
+/// `admin completions <bash|zsh|fish>`
+fn completions(program: &str, args: &[String]) -> CliResult<()> {
+    let usage = format!("Usage: {} completions <bash|zsh|fish>", program);
+    let [shell] = args else {
+        return Err(CliError::Usage(usage));
+    };
+    let shell: Shell = shell
+        .parse()
+        .map_err(|message| CliError::Usage(format!("{}\n\n{}", message, usage)))?;
+    print!("{}", completions::generate(&COMPLETIONS, shell));
+    Ok(())
+}
+
+/// `admin console`
+async fn console() -> CliResult<()> {
+    let config = cli::load_config()?;
+    let pool = cli::connect(&config).await?;
+    let templates = init_templates()
+        .map_err(|e| CliError::Failure(format!("Failed to load templates: {}", e)))?;
+    let mut editor = DefaultEditor::new().map_err(readline_error)?;
+
+    println!(
+        "Axum Base console ({} environment). Type 'help' for commands.",
+        config.environment
+    );
+    let state = AppState::new(pool, config, templates);
+
+    loop {
+        let line = match editor.readline("admin> ") {
+            Ok(line) => line,
+            // Ctrl+C clears the line, Ctrl+D leaves
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(readline_error(e)),
+        };
+
+        let command = match Command::parse(&line) {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(message) => {
+                output::error(message);
+                continue;
+            }
+        };
+        if !command.is_sensitive() {
+            editor
+                .add_history_entry(line.as_str())
+                .map_err(readline_error)?;
+        }
+        if command == Command::Exit {
+            break;
+        }
+
+        match console::execute(&state, &command).await {
+            Ok(reply) => println!("{}", reply),
+            Err(e) => output::error(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn readline_error(err: ReadlineError) -> CliError {
+    CliError::Failure(format!("Console input failed: {}", err))
+}
+
+/// `admin db dump [file]`
+fn db_dump(program: &str, args: &[String]) -> CliResult<()> {
+    if args.len() > 1 {
+        return Err(CliError::Usage(format!(
+            "Usage: {} db dump [file]",
+            program
+        )));
+    }
+
+    let config = cli::load_config()?;
+    let url = &config.database.url;
+    let path = match args.first() {
+        Some(path) => PathBuf::from(path),
+        None => backup::dump_path(Path::new(backup::DEFAULT_DIR), url, Utc::now()),
+    };
+    backup::dump(url, &path).map_err(|e| CliError::Failure(format!("Dump failed: {}", e)))?;
+    output::success(format!("Database dumped to {}", path.display()));
+    Ok(())
+}
+
+/// `admin db restore <file> [--yes]`
+fn db_restore(program: &str, args: &[String]) -> CliResult<()> {
+    let (path, confirmed) = match args {
+        [path] => (path, false),
+        [path, yes] | [yes, path] if yes == "--yes" => (path, true),
+        _ => {
+            return Err(CliError::Usage(format!(
+                "Usage: {} db restore <file> [--yes]",
+                program
+            )));
+        }
+    };
+    let path = Path::new(path);
+    if !path.is_file() {
+        return Err(CliError::NotFound(format!(
+            "No dump file at {}",
+            path.display()
+        )));
+    }
+
+    let config = cli::load_config()?;
+    let url = &config.database.url;
+    let name = backup::database_name(url).unwrap_or("the database");
+    if !confirmed {
+        // Typing the name guards against restoring into the wrong database
+        output::warn(format!(
+            "This replaces everything in '{}' with {}.",
+            name,
+            path.display()
+        ));
+        eprint!("Type the database name to continue: ");
+        let mut answer = String::new();
+        std::io::stdin()
+            .read_line(&mut answer)
+            .map_err(|e| CliError::Failure(format!("Failed to read confirmation: {}", e)))?;
+        if answer.trim() != name {
+            return Err(CliError::Invalid("Restore cancelled".to_string()));
+        }
+    }
+
+    backup::restore(url, path).map_err(|e| CliError::Failure(format!("Restore failed: {}", e)))?;
+    output::success(format!(
+        "Database '{}' restored from {}",
+        name,
+        path.display()
+    ));
+    Ok(())
+}
+
+/// `admin users list [options]`
+async fn users_list(program: &str, args: &[String]) -> CliResult<()> {
+    let options = ListUsers::parse(args).map_err(|message| {
+        CliError::Usage(format!(
+            "{}\n\nUsage: {} users list [options]\n\n{}",
+            message,
+            program,
+            console::LIST_USERS_OPTIONS
+        ))
+    })?;
+    if options.format == ListFormat::Json {
+        // Connection progress would break the JSON on stdout
+        output::set_quiet();
+    }
+
+    let config = cli::load_config()?;
+    let pool = cli::connect(&config).await?;
+    println!("{}", console::list_users(&pool, &options).await?);
+    Ok(())
+}
+
+/// `admin users delete <user> [--soft | --hard --yes]`
+async fn users_delete(program: &str, args: &[String]) -> CliResult<()> {
+    let options = DeleteUser::parse(args).map_err(|message| {
+        CliError::Usage(format!(
+            "{}\n\nUsage: {} users delete <user> [--soft | --hard --yes]\n\n{}",
+            message,
+            program,
+            console::DELETE_USER_OPTIONS
+        ))
+    })?;
+
+    let config = cli::load_config()?;
+    let pool = cli::connect(&config).await?;
+    let storage = uploads::storage_from_config(&config.uploads);
+    println!(
+        "{}",
+        console::delete_user(&pool, storage.as_ref(), &options).await?
+    );
+    Ok(())
+}
+
+/// `admin users export [options]`
+async fn users_export(program: &str, args: &[String]) -> CliResult<()> {
+    let options = ExportUsers::parse(args).map_err(|message| {
+        CliError::Usage(format!(
+            "{}\n\nUsage: {} users export [options]\n\n{}",
+            message,
+            program,
+            console::EXPORT_USERS_OPTIONS
+        ))
+    })?;
+    if options.output.is_none() {
+        // Connection progress would break the export on stdout
+        output::set_quiet();
+    }
+
+    let config = cli::load_config()?;
+    let pool = cli::connect(&config).await?;
+    let users = console::export_users(&pool, &options).await?;
+    let text = console::format_export(&users, options.format)?;
+    match &options.output {
+        Some(path) => {
+            std::fs::write(path, text)
+                .map_err(|e| CliError::Failure(format!("Failed to write {}: {}", path, e)))?;
+            output::success(format!("Exported {} user(s) to {}", users.len(), path));
+        }
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// `admin export-static <output_dir> [path ...]`
+async fn export_static(program: &str, args: &[String]) -> CliResult<()> {
+    let Some((output_dir, extra_paths)) = args.split_first() else {
+        return Err(CliError::Usage(format!(
+            "Usage: {} export-static <output_dir> [path ...]",
+            program
+        )));
+    };
+
+    if let Some(path) = extra_paths.iter().find(|path| !path.starts_with('/')) {
+        return Err(CliError::Invalid(format!(
+            "Paths must start with '/': {}",
+            path
+        )));
+    }
+
+    let mut config = cli::load_config()?;
+    // Snapshots must not reference the dev-only live reload stream
+    config.server.live_reload = Some(false);
+    let pool = cli::connect(&config).await?;
+    let templates = init_templates()
+        .map_err(|e| CliError::Failure(format!("Failed to load templates: {}", e)))?;
+
+    let router = create_router(AppState::new(pool, config, templates)).await;
+
+    let mut seeds: Vec<String> = DEFAULT_SEEDS.iter().map(|seed| seed.to_string()).collect();
+    seeds.extend(extra_paths.iter().cloned());
+
+    let output_dir = Path::new(output_dir);
+    let report = export::export_static(router, output_dir, &seeds)
+        .await
+        .map_err(|e| CliError::Failure(format!("Export failed: {}", e)))?;
+    for file in &report.written {
+        output::detail(file.display());
+    }
+    for (path, status) in &report.skipped {
+        output::warn(format!("Skipped {} ({})", path, status));
+    }
+    output::success(format!(
+        "Exported {} files to {}",
+        report.written.len(),
+        output_dir.display()
+    ));
+
+    Ok(())
+}
+
+/// `admin mail check-dns [domain]`
+async fn mail_check_dns(domain: Option<&str>) -> CliResult<()> {
+    let config = cli::load_config()?;
+    let Some(domain) = domain
+        .map(|domain| domain.trim_end_matches('.').to_ascii_lowercase())
+        .or_else(|| config.mail.signing_domain())
+    else {
+        return Err(CliError::Invalid(
+            "MAIL_FROM has no domain; pass one: admin mail check-dns <domain>".to_string(),
+        ));
+    };
+
+    let checks = deliverability::check_domain(&config.mail, &domain)
+        .await
+        .map_err(|e| CliError::Failure(e.to_string()))?;
+    for check in &checks {
+        println!("{}", check);
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == Status::Fail)
+        .count();
+    if failed > 0 {
+        return Err(CliError::CheckFailed(format!(
+            "{} of {} DNS checks failed for {}",
+            failed,
+            checks.len(),
+            domain
+        )));
+    }
+    Ok(())
+}
+
+/// `admin export-types --lang ts [output.d.ts]`
+fn export_types(program: &str, args: &[String]) -> CliResult<()> {
+    let (lang, output) = match args {
+        [flag, lang, rest @ ..] if flag == "--lang" && rest.len() <= 1 => {
+            (lang.as_str(), rest.first())
+        }
+        _ => return Err(usage(program)),
+    };
+    if !typescript::LANGUAGES.contains(&lang) {
+        return Err(CliError::Invalid(format!(
+            "Unsupported language '{}' (supported: {})",
+            lang,
+            typescript::LANGUAGES.join(", ")
+        )));
+    }
+
+    let declarations = typescript::declarations();
+    match output {
+        None => print!("{}", declarations),
+        Some(path) => {
+            std::fs::write(path, &declarations)
+                .map_err(|e| CliError::Failure(format!("Failed to write {}: {}", path, e)))?;
+            output::success(format!("Wrote TypeScript declarations to {}", path));
+        }
+    }
+    Ok(())
+}
+
+/// `admin templates check`
+fn templates_check() -> CliResult<()> {
+    let tera = load_templates().map_err(|e| {
+        // Tera's useful detail (file, line) is in the source chain
+        let mut message = e.to_string();
+        let mut source = std::error::Error::source(&e);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {}", cause));
+            source = cause.source();
+        }
+        CliError::CheckFailed(format!("Failed to parse templates: {}", message))
+    })?;
+
+    let template_count = tera.get_template_names().count();
+    let issues = check_templates(&tera, TEMPLATE_MANIFEST);
+
+    if issues.is_empty() {
+        output::success(format!(
+            "{} templates in {} passed ({} checked against handlers)",
+            template_count,
+            TEMPLATE_GLOB,
+            TEMPLATE_MANIFEST.len()
+        ));
+        return Ok(());
+    }
+
+    for issue in &issues {
+        output::warn(issue);
+    }
+    Err(CliError::CheckFailed(format!(
+        "Found {} template issues",
+        issues.len()
+    )))
+}
@@ -1,24 +1,35 @@
 //! # User Management CLI
 //!
 //! Command-line utility for creating users since registration is disabled.
+//! Exit codes follow [`axum_base::cli`].
 
 use std::env;
 use std::io::{self, Write};
+use std::process::ExitCode;
 
 use axum_base::auth::AuthService;
-use axum_base::database::init_pool;
+use axum_base::cli::{self, CliError, CliResult};
+use axum_base::output;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     // Load environment variables from .env file
     dotenvy::dotenv().ok();
 
-    let args: Vec<String> = env::args().collect();
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => err.report(),
+    }
+}
+
+async fn run() -> CliResult<()> {
+    let args = output::init(env::args().collect());
 
     if args.len() != 2 && args.len() != 4 {
-        eprintln!("Usage: {} <username> [email] [password]", args[0]);
-        eprintln!("       {} <username>  # Interactive mode", args[0]);
-        std::process::exit(1);
+        return Err(CliError::Usage(format!(
+            "Usage: {0} <username> [email] [password]\n       {0} <username>  # Interactive mode",
+            args[0]
+        )));
     }
 
     let username = &args[1];
@@ -53,35 +64,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     if email.is_empty() {
-        eprintln!("Error: Email cannot be empty");
-        std::process::exit(1);
+        return Err(CliError::Invalid("Email cannot be empty".to_string()));
     }
 
-    // Initialize database connection
-    let pool = init_pool().await?;
+    // Load configuration and initialize database connection
+    let config = cli::load_config()?;
+    let pool = cli::connect(&config).await?;
 
     // Create the user
-    match AuthService::create_user(&pool, username, &email, password.as_deref()).await {
-        Ok(user) => {
-            println!("✅ User created successfully!");
-            println!("   ID: {}", user.id);
-            println!("   Username: {}", user.username);
-            println!("   Email: {}", user.email);
-            println!("   Active: {}", user.is_active);
-
-            if password.is_some() {
-                println!("   Password: Set");
-            } else {
-                println!("   Password: Not set (user will need admin to set password)");
-                println!();
-                println!("💡 To set password later, use:");
-                println!("   cargo run --bin set_password {} <password>", user.id);
-            }
-        }
-        Err(e) => {
-            eprintln!("❌ Failed to create user: {}", e);
-            std::process::exit(1);
-        }
+    let user = AuthService::create_user(&pool, username, &email, password.as_deref())
+        .await
+        .map_err(|e| CliError::from_boxed(e).context("Failed to create user"))?;
+    output::success("User created successfully!");
+    output::detail(format!("ID: {}", user.id));
+    output::detail(format!("Username: {}", user.username));
+    output::detail(format!("Email: {}", user.email));
+    output::detail(format!("Active: {}", user.is_active));
+
+    if password.is_some() {
+        output::detail("Password: Set");
+    } else {
+        output::detail("Password: Not set (user will need admin to set password)");
+        output::info("To set password later, use:");
+        output::detail(format!(
+            "cargo run --bin set_password {} <password>",
+            user.id
+        ));
     }
 
     Ok(())
@@ -0,0 +1,68 @@
+//! # Seed CLI
+//!
+//! Loads development fixtures (see [`axum_base::seed`]) into the configured
+//! database, applying migrations first so a fresh clone only needs this
+//! command before `cargo run`.
+//!
+//! ```text
+//! seed [fixtures.toml]
+//! ```
+//!
+//! The demo passwords are public, so production configurations are refused.
+//! Exit codes follow [`axum_base::cli`].
+
+use std::env;
+use std::path::Path;
+use std::process::ExitCode;
+
+use axum_base::cli::{self, CliError, CliResult};
+use axum_base::config::Environment;
+use axum_base::database::run_migrations;
+use axum_base::output;
+use axum_base::plugins::PluginRegistry;
+use axum_base::seed::{self, DEFAULT_FIXTURES, Fixtures};
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Load environment variables from .env file
+    dotenvy::dotenv().ok();
+
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => err.report(),
+    }
+}
+
+async fn run() -> CliResult<()> {
+    let args = output::init(env::args().collect());
+
+    if args.len() > 2 {
+        return Err(CliError::Usage(format!(
+            "Usage: {} [fixtures.toml]  # defaults to {}",
+            args[0], DEFAULT_FIXTURES
+        )));
+    }
+    let path = Path::new(args.get(1).map_or(DEFAULT_FIXTURES, String::as_str));
+    let fixtures = Fixtures::load(path)?;
+
+    let config = cli::load_config()?;
+    if config.environment == Environment::Production {
+        return Err(CliError::Invalid(
+            "Refusing to seed demo fixtures into a production database".to_string(),
+        ));
+    }
+    let pool = cli::connect(&config).await?;
+    run_migrations(&pool, &PluginRegistry::new())
+        .await
+        .map_err(|e| CliError::Failure(format!("Failed to run database migrations: {}", e)))?;
+
+    let report = seed::seed(&pool, &fixtures)
+        .await
+        .map_err(|e| CliError::from(e).context("Failed to seed the database"))?;
+    output::success(format!("Seeded from {}", path.display()));
+    output::detail(format!("Users: {}", report.users));
+    output::detail(format!("Categories: {}", report.categories));
+    output::detail(format!("Items: {}", report.items));
+
+    Ok(())
+}
@@ -1,51 +1,100 @@
 //! # Password Management CLI
 //!
-//! Command-line utility for setting user passwords.
+//! Command-line utility for setting user passwords. Exit codes follow
+//! [`axum_base::cli`].
+//!
+//! ```text
+//! set_password <user_id> <password>
+//! set_password <user_id> --generate [--require-change]
+//! ```
+//!
+//! `--generate` prints a random password once; `--require-change` sends the
+//! user to their profile to replace it after they next sign in.
 
 use std::env;
+use std::process::ExitCode;
 
-use axum_base::auth::AuthService;
-use axum_base::database::init_pool;
+use axum_base::auth::{AuthService, MIN_PASSWORD_LENGTH, PasswordPolicy, PasswordService};
+use axum_base::cli::{self, CliError, CliResult};
+use axum_base::output;
+use axum_base::services::UserService;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
     // Load environment variables from .env file
     dotenvy::dotenv().ok();
 
-    let args: Vec<String> = env::args().collect();
-
-    if args.len() != 3 {
-        eprintln!("Usage: {} <user_id> <password>", args[0]);
-        std::process::exit(1);
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => err.report(),
     }
+}
+
+async fn run() -> CliResult<()> {
+    let args = output::init(env::args().collect());
+    let usage = || {
+        CliError::Usage(format!(
+            "Usage: {0} <user_id> <password>\n       {0} <user_id> --generate [--require-change]",
+            args[0]
+        ))
+    };
 
-    let user_id: i32 = match args[1].parse() {
-        Ok(id) => id,
-        Err(_) => {
-            eprintln!("Error: User ID must be a valid number");
-            std::process::exit(1);
+    let mut positional = Vec::new();
+    let (mut generate, mut require_change) = (false, false);
+    for arg in &args[1..] {
+        match arg.as_str() {
+            "--generate" => generate = true,
+            "--require-change" => require_change = true,
+            _ => positional.push(arg),
         }
+    }
+    let (user_id, password) = match (positional.as_slice(), generate) {
+        ([user_id], true) => (user_id, PasswordService::generate_password()),
+        ([user_id, password], false) => (user_id, password.to_string()),
+        _ => return Err(usage()),
     };
 
-    let password = &args[2];
+    let user_id: i32 = user_id
+        .parse()
+        .map_err(|_| CliError::Invalid("User ID must be a valid number".to_string()))?;
 
-    if password.len() < 8 {
-        eprintln!("Error: Password must be at least 8 characters long");
-        std::process::exit(1);
+    if password.len() < MIN_PASSWORD_LENGTH {
+        return Err(CliError::Invalid(format!(
+            "Password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        )));
     }
 
-    // Initialize database connection
-    let pool = init_pool().await?;
+    // Load configuration and initialize database connection
+    let config = cli::load_config()?;
+    if !generate {
+        PasswordPolicy::from_config(&config.passwords)
+            .check(&password)
+            .await?;
+    }
+    let pool = cli::connect(&config).await?;
 
     // Set the password
-    match AuthService::set_user_password(&pool, user_id, password).await {
-        Ok(()) => {
-            println!("✅ Password set successfully for user ID {}", user_id);
-        }
-        Err(e) => {
-            eprintln!("❌ Failed to set password: {}", e);
-            std::process::exit(1);
-        }
+    if UserService::get_user_any_status(&pool, user_id)
+        .await?
+        .is_none()
+    {
+        return Err(CliError::NotFound(format!(
+            "Failed to set password: no user with ID {}",
+            user_id
+        )));
+    }
+    AuthService::set_user_password(&pool, user_id, &password)
+        .await
+        .map_err(|e| CliError::from_boxed(e).context("Failed to set password"))?;
+    AuthService::require_password_change(&pool, user_id, require_change).await?;
+    output::success(format!("Password set successfully for user ID {}", user_id));
+    if require_change {
+        output::info("The user must choose a new password after signing in");
+    }
+    if generate {
+        // Printed even with --quiet; it is not stored anywhere else
+        println!("{}", password);
     }
 
     Ok(())
@@ -0,0 +1,241 @@
+//! # Admin Bootstrap
+//!
+//! Creates or updates the admin named by `ADMIN_BOOTSTRAP_USERNAME` at
+//! startup (see [`BootstrapConfig`]). Running it again with the same
+//! settings changes nothing, so it can stay configured on every deploy.
+//! Otherwise it brings the account back in line with the settings: the
+//! password is replaced when it no longer matches, a deactivated account is
+//! reactivated, and the admin role is restored if it was taken away. Every
+//! change is logged and written to the [audit log](crate::audit); the
+//! password never is.
+
+use serde_json::json;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::audit::{self, AuditEvent};
+use crate::auth::{ADMIN_ROLE, PasswordService};
+use crate::config::BootstrapConfig;
+use crate::output;
+
+/// Lock held while the admin is bootstrapped, so instances starting together
+/// do not both create it
+const LOCK_KEY: i64 = 0x6178_756d_6164_6d6e; // "axumadmn"
+
+/// What bootstrapping did to the admin account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Bootstrap {
+    /// The account did not exist and was created
+    Created { user_id: i32 },
+    /// The account existed; these settings were brought up to date
+    Updated {
+        user_id: i32,
+        changes: Vec<&'static str>,
+    },
+    /// The account already matched the settings
+    Unchanged { user_id: i32 },
+}
+
+impl Bootstrap {
+    /// The admin account's ID
+    pub fn user_id(&self) -> i32 {
+        match self {
+            Bootstrap::Created { user_id }
+            | Bootstrap::Updated { user_id, .. }
+            | Bootstrap::Unchanged { user_id } => *user_id,
+        }
+    }
+}
+
+/// Create or update the configured admin; `None` when none is configured
+pub async fn bootstrap_admin(
+    pool: &PgPool,
+    config: &BootstrapConfig,
+) -> Result<Option<Bootstrap>, String> {
+    let (Some(username), Some(password)) = (&config.admin_username, config.admin_password()?)
+    else {
+        return Ok(None);
+    };
+
+    let mut tx = pool.begin().await.map_err(|e| e.to_string())?;
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(LOCK_KEY)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let existing: Option<(i32, String, String, bool)> = sqlx::query_as(
+        "SELECT id, email, password_hash, is_active FROM users WHERE username = $1 FOR UPDATE",
+    )
+    .bind(username)
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(|e| e.to_string())?;
+
+    let outcome = match existing {
+        None => {
+            let email = config
+                .admin_email
+                .clone()
+                .unwrap_or_else(|| format!("{}@localhost", username));
+            let user_id = create(&mut tx, username, &email, &password).await?;
+            Bootstrap::Created { user_id }
+        }
+        Some((user_id, email, password_hash, is_active)) => {
+            let changes = update(
+                &mut tx,
+                user_id,
+                config
+                    .admin_email
+                    .as_deref()
+                    .filter(|wanted| *wanted != email),
+                (!password_matches(&password, &password_hash)).then_some(password.as_str()),
+                !is_active,
+            )
+            .await?;
+            if changes.is_empty() {
+                Bootstrap::Unchanged { user_id }
+            } else {
+                Bootstrap::Updated { user_id, changes }
+            }
+        }
+    };
+
+    tx.commit().await.map_err(|e| e.to_string())?;
+    log(username, &outcome);
+    let details = match &outcome {
+        Bootstrap::Created { .. } => Some(json!({ "created": true })),
+        Bootstrap::Updated { changes, .. } => Some(json!({ "changes": changes })),
+        Bootstrap::Unchanged { .. } => None,
+    };
+    if let Some(details) = details {
+        audit::record(
+            pool,
+            AuditEvent::new(audit::ADMIN_BOOTSTRAPPED)
+                .on("user", outcome.user_id())
+                .with_details(details),
+        )
+        .await;
+    }
+    Ok(Some(outcome))
+}
+
+async fn create(
+    tx: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: &str,
+    password: &str,
+) -> Result<i32, String> {
+    let password_hash = hash(password)?;
+    let user_id: i32 = sqlx::query_scalar(
+        "INSERT INTO users (username, email, password_hash, is_active)
+         VALUES ($1, $2, $3, true)
+         RETURNING id",
+    )
+    .bind(username)
+    .bind(email)
+    .bind(password_hash)
+    .fetch_one(&mut **tx)
+    .await
+    .map_err(|e| format!("failed to create {}: {}", username, e))?;
+
+    grant_admin(tx, user_id).await?;
+    Ok(user_id)
+}
+
+/// Apply the changes that are due and name them
+async fn update(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: i32,
+    email: Option<&str>,
+    password: Option<&str>,
+    reactivate: bool,
+) -> Result<Vec<&'static str>, String> {
+    let mut changes = Vec::new();
+
+    if let Some(email) = email {
+        sqlx::query("UPDATE users SET email = $1, updated_at = NOW() WHERE id = $2")
+            .bind(email)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| format!("failed to update the email: {}", e))?;
+        changes.push("email");
+    }
+    if let Some(password) = password {
+        sqlx::query("UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2")
+            .bind(hash(password)?)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        changes.push("password");
+    }
+    if reactivate {
+        sqlx::query("UPDATE users SET is_active = true, updated_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(|e| e.to_string())?;
+        changes.push("reactivated");
+    }
+    if grant_admin(tx, user_id).await? {
+        changes.push("admin role");
+    }
+
+    Ok(changes)
+}
+
+/// Give the user the admin role; returns whether they lacked it
+async fn grant_admin(tx: &mut Transaction<'_, Postgres>, user_id: i32) -> Result<bool, String> {
+    let result = sqlx::query(
+        "INSERT INTO user_roles (user_id, role) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(ADMIN_ROLE)
+    .execute(&mut **tx)
+    .await
+    .map_err(|e| e.to_string())?;
+    Ok(result.rows_affected() > 0)
+}
+
+fn hash(password: &str) -> Result<String, String> {
+    PasswordService::hash_password(password).map_err(|e| format!("Password hashing error: {}", e))
+}
+
+/// Whether `password` matches the stored hash; an unreadable hash does not
+fn password_matches(password: &str, password_hash: &str) -> bool {
+    PasswordService::verify_password(password, password_hash).unwrap_or(false)
+}
+
+/// Record what happened to the account in the server log
+fn log(username: &str, outcome: &Bootstrap) {
+    match outcome {
+        Bootstrap::Created { user_id } => output::info(format!(
+            "Admin bootstrap: created {} (user {})",
+            username, user_id
+        )),
+        Bootstrap::Updated { user_id, changes } => output::info(format!(
+            "Admin bootstrap: updated {} (user {}): {}",
+            username,
+            user_id,
+            changes.join(", ")
+        )),
+        Bootstrap::Unchanged { user_id } => output::info(format!(
+            "Admin bootstrap: {} (user {}) is up to date",
+            username, user_id
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_matches() {
+        let password_hash = hash("correct horse").unwrap();
+        assert!(password_matches("correct horse", &password_hash));
+        assert!(!password_matches("battery staple", &password_hash));
+        assert!(!password_matches("correct horse", ""));
+    }
+}
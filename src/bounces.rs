@@ -0,0 +1,520 @@
+//! # Bounces and Complaints
+//!
+//! Webhook receivers for delivery reports from Amazon SES (through SNS) and
+//! SendGrid. Permanent bounces and spam complaints put the address on the
+//! suppression list, and [`crate::emails::deliver`] sends nothing more to it.
+//! The webhooks are enabled by `EMAIL_WEBHOOK_TOKEN` and must be called with
+//! `?token=<EMAIL_WEBHOOK_TOKEN>`, since neither provider's signature scheme
+//! is verified here.
+//!
+//! Admins see the outbox and the suppression list under `/api/admin/email`,
+//! and can lift a suppression once the address is fixed.
+//!
+//! [`WebhookMetrics`] counts the calls by provider and outcome at `/metrics`,
+//! so a provider that stopped reporting, or one calling with a stale token,
+//! shows up on a dashboard.
+
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    routing::{delete, get, post},
+};
+use serde::Deserialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::sync::Arc;
+use utoipa::IntoParams;
+
+use crate::api::RequestId;
+use crate::audit::{self, AuditEvent};
+use crate::auth::{AdminUser, constant_time_eq};
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::metrics::{CounterVec, MetricSource};
+use crate::models::{EmailSuppression, Envelope, OutboxEmail};
+use crate::output;
+use crate::pagination::{Pagination, PaginationQuery};
+use crate::services::EmailDeliveryService;
+use crate::state::AppState;
+
+/// Suppression reason for a permanent bounce
+pub const REASON_BOUNCE: &str = "bounce";
+/// Suppression reason for a spam complaint
+pub const REASON_COMPLAINT: &str = "complaint";
+
+/// Routes for the provider webhooks and `/api/admin/email`
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/webhooks/email/ses", post(ses_webhook))
+        .route("/webhooks/email/sendgrid", post(sendgrid_webhook))
+        .route("/api/admin/email/outbox", get(list_outbox))
+        .route("/api/admin/email/suppressions", get(list_suppressions))
+        .route(
+            "/api/admin/email/suppressions/{email}",
+            delete(remove_suppression),
+        )
+}
+
+/// Query parameters for the webhooks
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WebhookParams {
+    /// Must equal `EMAIL_WEBHOOK_TOKEN`
+    pub token: Option<String>,
+}
+
+/// Webhook calls received by this instance, served at `/metrics`
+#[derive(Debug)]
+pub struct WebhookMetrics {
+    /// Calls by provider and outcome: `processed`, `unauthorized` (wrong
+    /// token or webhooks off), `invalid`, or `error`
+    pub calls: CounterVec,
+    /// Addresses suppressed by provider and reason
+    pub suppressed: CounterVec,
+}
+
+impl Default for WebhookMetrics {
+    fn default() -> Self {
+        Self {
+            calls: CounterVec::new(
+                "email_webhook_calls_total",
+                "Bounce and complaint webhook calls by provider and outcome",
+                &["provider", "outcome"],
+            ),
+            suppressed: CounterVec::new(
+                "email_webhook_suppressed_total",
+                "Addresses suppressed after a webhook report, by provider and reason",
+                &["provider", "reason"],
+            ),
+        }
+    }
+}
+
+impl MetricSource for WebhookMetrics {
+    fn render(&self, out: &mut String) {
+        self.calls.render(out);
+        self.suppressed.render(out);
+    }
+}
+
+impl WebhookMetrics {
+    /// Count a call from `provider` by how it ended
+    fn record<T>(&self, provider: &str, result: &AppResult<T>) {
+        let outcome = match result {
+            Ok(_) => "processed",
+            Err(AppError::Unauthorized(_) | AppError::NotFound(_)) => "unauthorized",
+            Err(AppError::Validation(_)) => "invalid",
+            Err(_) => "error",
+        };
+        self.calls.inc(&[provider, outcome]);
+    }
+}
+
+/// An address a provider reported as undeliverable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeliveryReport {
+    pub email: String,
+    /// [`REASON_BOUNCE`] or [`REASON_COMPLAINT`]
+    pub reason: &'static str,
+    pub detail: Option<String>,
+}
+
+// =============================================================================
+// Webhooks
+// =============================================================================
+
+/// Receive SES bounce and complaint notifications delivered by SNS
+#[utoipa::path(
+    post,
+    path = "/webhooks/email/ses",
+    tag = "email",
+    params(WebhookParams),
+    request_body(content = String, description = "SNS message", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Notification processed", body = Envelope<serde_json::Value>),
+        (status = 400, description = "Not an SNS message", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Missing or wrong token", body = Envelope<serde_json::Value>),
+        (status = 404, description = "Webhooks are not configured", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn ses_webhook(
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(params): Query<WebhookParams>,
+    body: String,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let result = receive_ses(&state, params.token.as_deref(), &body).await;
+    state
+        .metrics
+        .source::<WebhookMetrics>()
+        .record("ses", &result);
+    Ok(Negotiated(
+        format,
+        Envelope::message(result?).with_request_id(request_id),
+    ))
+}
+
+async fn receive_ses(state: &AppState, token: Option<&str>, body: &str) -> AppResult<String> {
+    check_token(&state.config, token)?;
+    let message: Value = serde_json::from_str(body)
+        .map_err(|e| AppError::Validation(format!("Invalid SNS message: {}", e)))?;
+
+    // SNS asks for confirmation once, when the subscription is created
+    if message["Type"] == "SubscriptionConfirmation" {
+        let url = message["SubscribeURL"].as_str().unwrap_or_default();
+        output::info(format!(
+            "Confirm the SES bounce subscription by opening: {}",
+            url
+        ));
+        return Ok("Subscription confirmation logged".to_string());
+    }
+
+    let reports = parse_ses(&message)?;
+    let count = suppress_all(state, "ses", &reports).await?;
+    Ok(format!("{} addresses suppressed", count))
+}
+
+/// Receive SendGrid event webhook batches
+#[utoipa::path(
+    post,
+    path = "/webhooks/email/sendgrid",
+    tag = "email",
+    params(WebhookParams),
+    request_body(content = Vec<serde_json::Value>, description = "SendGrid events"),
+    responses(
+        (status = 200, description = "Events processed", body = Envelope<serde_json::Value>),
+        (status = 400, description = "Not a SendGrid event batch", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Missing or wrong token", body = Envelope<serde_json::Value>),
+        (status = 404, description = "Webhooks are not configured", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn sendgrid_webhook(
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(params): Query<WebhookParams>,
+    body: String,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let result = receive_sendgrid(&state, params.token.as_deref(), &body).await;
+    state
+        .metrics
+        .source::<WebhookMetrics>()
+        .record("sendgrid", &result);
+    Ok(Negotiated(
+        format,
+        Envelope::message(result?).with_request_id(request_id),
+    ))
+}
+
+async fn receive_sendgrid(state: &AppState, token: Option<&str>, body: &str) -> AppResult<String> {
+    check_token(&state.config, token)?;
+    let events: Vec<Value> = serde_json::from_str(body)
+        .map_err(|e| AppError::Validation(format!("Invalid SendGrid events: {}", e)))?;
+
+    let reports = parse_sendgrid(&events);
+    let count = suppress_all(state, "sendgrid", &reports).await?;
+    Ok(format!("{} addresses suppressed", count))
+}
+
+// =============================================================================
+// Admin
+// =============================================================================
+
+/// List sent, failed, and suppressed emails, newest first
+#[utoipa::path(
+    get,
+    path = "/api/admin/email/outbox",
+    tag = "admin",
+    params(PaginationQuery),
+    responses(
+        (status = 200, description = "One page of the outbox", body = Envelope<Vec<OutboxEmail>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_outbox(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    pagination: Pagination,
+) -> AppResult<Negotiated<Envelope<Vec<OutboxEmail>>>> {
+    let page = EmailDeliveryService::list_outbox(&pool, &pagination).await?;
+    let meta = page.meta();
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(page.items)
+            .with_pagination(meta)
+            .with_request_id(request_id),
+    ))
+}
+
+/// List addresses that no email is sent to
+#[utoipa::path(
+    get,
+    path = "/api/admin/email/suppressions",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Suppressed addresses", body = Envelope<Vec<EmailSuppression>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_suppressions(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<Vec<EmailSuppression>>>> {
+    let suppressions = EmailDeliveryService::list_suppressions(&pool).await?;
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(suppressions).with_request_id(request_id),
+    ))
+}
+
+/// Allow sending to a suppressed address again
+#[utoipa::path(
+    delete,
+    path = "/api/admin/email/suppressions/{email}",
+    tag = "admin",
+    params(("email" = String, Path, description = "Suppressed address")),
+    responses(
+        (status = 200, description = "Suppression lifted", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "Address is not suppressed", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn remove_suppression(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(email): Path<String>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    if !EmailDeliveryService::unsuppress(&pool, &email).await? {
+        return Err(AppError::NotFound(format!("{} is not suppressed", email)));
+    }
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::SUPPRESSION_REMOVED)
+            .by(admin.id)
+            .on("email", email.to_lowercase()),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Suppression lifted").with_request_id(request_id),
+    ))
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// 404 when webhooks are off, 401 unless the token matches
+fn check_token(config: &Arc<AppConfig>, token: Option<&str>) -> AppResult<()> {
+    let expected = config
+        .mail
+        .webhook_token
+        .as_deref()
+        .ok_or_else(|| AppError::NotFound("Email webhooks are not configured".to_string()))?;
+
+    match token {
+        Some(token) if constant_time_eq(token.as_bytes(), expected.as_bytes()) => Ok(()),
+        _ => Err(AppError::Unauthorized("Invalid webhook token".to_string())),
+    }
+}
+
+async fn suppress_all(
+    state: &AppState,
+    provider: &str,
+    reports: &[DeliveryReport],
+) -> AppResult<usize> {
+    let metrics = state.metrics.source::<WebhookMetrics>();
+    for report in reports {
+        EmailDeliveryService::suppress(
+            &state.pool,
+            &report.email,
+            report.reason,
+            provider,
+            report.detail.as_deref(),
+        )
+        .await?;
+        metrics.suppressed.inc(&[provider, report.reason]);
+    }
+    Ok(reports.len())
+}
+
+/// Reports in an SNS notification, or in a bare SES notification. Transient
+/// bounces and other notification types produce none.
+pub fn parse_ses(message: &Value) -> AppResult<Vec<DeliveryReport>> {
+    let notification = match &message["Message"] {
+        Value::String(inner) => serde_json::from_str(inner)
+            .map_err(|e| AppError::Validation(format!("Invalid SES notification: {}", e)))?,
+        _ => message.clone(),
+    };
+
+    // Event publishing uses `eventType`; identity notifications `notificationType`
+    let kind = notification["notificationType"]
+        .as_str()
+        .or_else(|| notification["eventType"].as_str())
+        .unwrap_or_default();
+
+    let reports = match kind {
+        "Bounce" if notification["bounce"]["bounceType"] == "Permanent" => {
+            recipients(&notification["bounce"]["bouncedRecipients"])
+                .map(|recipient| DeliveryReport {
+                    email: recipient.0,
+                    reason: REASON_BOUNCE,
+                    detail: recipient.1["diagnosticCode"]
+                        .as_str()
+                        .or_else(|| notification["bounce"]["bounceSubType"].as_str())
+                        .map(str::to_string),
+                })
+                .collect()
+        }
+        "Complaint" => recipients(&notification["complaint"]["complainedRecipients"])
+            .map(|recipient| DeliveryReport {
+                email: recipient.0,
+                reason: REASON_COMPLAINT,
+                detail: notification["complaint"]["complaintFeedbackType"]
+                    .as_str()
+                    .map(str::to_string),
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    Ok(reports)
+}
+
+/// Recipient addresses in an SES recipient list, with each entry
+fn recipients(list: &Value) -> impl Iterator<Item = (String, &Value)> {
+    list.as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|recipient| {
+            recipient["emailAddress"]
+                .as_str()
+                .map(|email| (email.to_string(), recipient))
+        })
+}
+
+/// Reports in a SendGrid event batch. Blocks are temporary and ignored.
+pub fn parse_sendgrid(events: &[Value]) -> Vec<DeliveryReport> {
+    events
+        .iter()
+        .filter_map(|event| {
+            let email = event["email"].as_str()?.to_string();
+            let detail = event["reason"].as_str().map(str::to_string);
+            match event["event"].as_str()? {
+                "bounce" if event["type"] != "blocked" => Some(DeliveryReport {
+                    email,
+                    reason: REASON_BOUNCE,
+                    detail,
+                }),
+                "spamreport" => Some(DeliveryReport {
+                    email,
+                    reason: REASON_COMPLAINT,
+                    detail,
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_ses_bounces_and_complaints() {
+        let bounce = json!({
+            "notificationType": "Bounce",
+            "bounce": {
+                "bounceType": "Permanent",
+                "bounceSubType": "General",
+                "bouncedRecipients": [
+                    { "emailAddress": "gone@example.com", "diagnosticCode": "550 5.1.1 unknown user" }
+                ]
+            }
+        });
+        let sns = json!({ "Type": "Notification", "Message": bounce.to_string() });
+        let reports = parse_ses(&sns).unwrap();
+        assert_eq!(
+            reports,
+            vec![DeliveryReport {
+                email: "gone@example.com".to_string(),
+                reason: REASON_BOUNCE,
+                detail: Some("550 5.1.1 unknown user".to_string()),
+            }]
+        );
+
+        let transient = json!({
+            "eventType": "Bounce",
+            "bounce": { "bounceType": "Transient", "bouncedRecipients": [{ "emailAddress": "a@example.com" }] }
+        });
+        assert!(parse_ses(&transient).unwrap().is_empty());
+
+        let complaint = json!({
+            "notificationType": "Complaint",
+            "complaint": {
+                "complaintFeedbackType": "abuse",
+                "complainedRecipients": [{ "emailAddress": "angry@example.com" }]
+            }
+        });
+        let reports = parse_ses(&complaint).unwrap();
+        assert_eq!(reports[0].reason, REASON_COMPLAINT);
+        assert_eq!(reports[0].detail.as_deref(), Some("abuse"));
+
+        assert!(parse_ses(&json!({ "Message": "not json" })).is_err());
+    }
+
+    #[test]
+    fn test_parse_sendgrid_events() {
+        let events = vec![
+            json!({ "email": "gone@example.com", "event": "bounce", "type": "bounce", "reason": "550" }),
+            json!({ "email": "later@example.com", "event": "bounce", "type": "blocked" }),
+            json!({ "email": "angry@example.com", "event": "spamreport" }),
+            json!({ "email": "fine@example.com", "event": "delivered" }),
+        ];
+        let reports = parse_sendgrid(&events);
+
+        let emails: Vec<_> = reports
+            .iter()
+            .map(|r| (r.email.as_str(), r.reason))
+            .collect();
+        assert_eq!(
+            emails,
+            vec![
+                ("gone@example.com", REASON_BOUNCE),
+                ("angry@example.com", REASON_COMPLAINT)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_webhook_outcomes() {
+        let metrics = WebhookMetrics::default();
+        metrics.record("ses", &Ok(()));
+        metrics.record::<()>("ses", &Err(AppError::Unauthorized("bad".to_string())));
+        metrics.record::<()>("ses", &Err(AppError::NotFound("off".to_string())));
+        metrics.record::<()>("sendgrid", &Err(AppError::Validation("bad".to_string())));
+
+        assert_eq!(metrics.calls.get(&["ses", "processed"]), 1);
+        assert_eq!(metrics.calls.get(&["ses", "unauthorized"]), 2);
+        assert_eq!(metrics.calls.get(&["sendgrid", "invalid"]), 1);
+        assert_eq!(metrics.calls.get(&["sendgrid", "processed"]), 0);
+    }
+}
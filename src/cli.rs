@@ -0,0 +1,255 @@
+//! # CLI Errors
+//!
+//! The error type of the command-line tools and the server binary. Each kind
+//! of failure exits with its own code, so scripts can branch on the failure
+//! instead of parsing stderr:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0 | Success |
+//! | 1 | Any other failure |
+//! | 2 | Configuration did not load or validate |
+//! | 3 | The database could not be reached |
+//! | 4 | Invalid arguments or input, such as a duplicate username |
+//! | 5 | The user or record named does not exist |
+//! | 6 | A check ran and found problems (`admin templates check`, `admin mail check-dns`) |
+//! | 64 | Wrong command line; the usage is printed |
+//!
+//! Binaries return their errors from a `run` function and turn them into
+//! the exit code with [`CliError::report`]:
+//!
+//! ```rust,ignore
+//! #[tokio::main]
+//! async fn main() -> ExitCode {
+//!     match run().await {
+//!         Ok(()) => ExitCode::SUCCESS,
+//!         Err(err) => err.report(),
+//!     }
+//! }
+//! ```
+
+use sqlx::PgPool;
+use std::fmt;
+use std::process::ExitCode;
+
+use crate::auth::PasswordService;
+use crate::config::{AppConfig, ConfigError};
+use crate::database::init_pool;
+use crate::error::AppError;
+use crate::output;
+
+/// Result alias for CLI commands
+#[allow(dead_code)]
+pub type CliResult<T> = Result<T, CliError>;
+
+/// Why a command failed, each with its own exit code
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(dead_code)]
+pub enum CliError {
+    /// Any other failure (1)
+    Failure(String),
+    /// Configuration did not load or validate (2)
+    Config(String),
+    /// The database could not be reached (3)
+    Database(String),
+    /// Invalid arguments or input (4)
+    Invalid(String),
+    /// The user or record named does not exist (5)
+    NotFound(String),
+    /// A check ran and found problems (6)
+    CheckFailed(String),
+    /// Wrong command line; holds the usage text (64)
+    Usage(String),
+}
+
+#[allow(dead_code)]
+impl CliError {
+    /// Process exit code for this failure
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            CliError::Failure(_) => 1,
+            CliError::Config(_) => 2,
+            CliError::Database(_) => 3,
+            CliError::Invalid(_) => 4,
+            CliError::NotFound(_) => 5,
+            CliError::CheckFailed(_) => 6,
+            CliError::Usage(_) => 64,
+        }
+    }
+
+    /// Prefix the message with what was being done
+    pub fn context(self, context: &str) -> Self {
+        let prefix = |message: String| format!("{}: {}", context, message);
+        match self {
+            CliError::Failure(message) => CliError::Failure(prefix(message)),
+            CliError::Config(message) => CliError::Config(prefix(message)),
+            CliError::Database(message) => CliError::Database(prefix(message)),
+            CliError::Invalid(message) => CliError::Invalid(prefix(message)),
+            CliError::NotFound(message) => CliError::NotFound(prefix(message)),
+            CliError::CheckFailed(message) => CliError::CheckFailed(prefix(message)),
+            CliError::Usage(usage) => CliError::Usage(usage),
+        }
+    }
+
+    /// Error from a helper that returns a boxed error, classified by its
+    /// database error when it has one
+    pub fn from_boxed(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        match err.downcast::<sqlx::Error>() {
+            Ok(err) => CliError::from(*err),
+            Err(err) => CliError::Failure(err.to_string()),
+        }
+    }
+
+    /// Print the error to stderr and return the exit code for `main`
+    pub fn report(&self) -> ExitCode {
+        self.print();
+        ExitCode::from(self.exit_code())
+    }
+
+    /// Print the error to stderr and exit, for code that cannot return it
+    pub fn exit(&self) -> ! {
+        self.print();
+        std::process::exit(i32::from(self.exit_code()))
+    }
+
+    fn print(&self) {
+        match self {
+            CliError::Usage(usage) => eprintln!("{}", usage),
+            other => output::error(other),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Failure(message)
+            | CliError::Config(message)
+            | CliError::Database(message)
+            | CliError::Invalid(message)
+            | CliError::NotFound(message)
+            | CliError::CheckFailed(message)
+            | CliError::Usage(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+// =============================================================================
+// Conversions
+// =============================================================================
+
+impl From<ConfigError> for CliError {
+    fn from(err: ConfigError) -> Self {
+        CliError::Config(format!("Invalid configuration: {}", err))
+    }
+}
+
+impl From<sqlx::Error> for CliError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => CliError::NotFound(err.to_string()),
+            sqlx::Error::Database(db) if db.is_unique_violation() => {
+                CliError::Invalid(db.message().to_string())
+            }
+            sqlx::Error::Io(_)
+            | sqlx::Error::Tls(_)
+            | sqlx::Error::Configuration(_)
+            | sqlx::Error::PoolTimedOut
+            | sqlx::Error::PoolClosed => CliError::Database(err.to_string()),
+            _ => CliError::Failure(err.to_string()),
+        }
+    }
+}
+
+impl From<AppError> for CliError {
+    fn from(err: AppError) -> Self {
+        match err {
+            AppError::Database(err) => CliError::from(err),
+            AppError::NotFound(message) => CliError::NotFound(message),
+            AppError::Validation(message) | AppError::PayloadTooLarge(message) => {
+                CliError::Invalid(message)
+            }
+            other => CliError::Failure(other.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for CliError {
+    fn from(err: std::io::Error) -> Self {
+        CliError::Failure(err.to_string())
+    }
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Load and validate the configuration
+#[allow(dead_code)]
+pub fn load_config() -> CliResult<AppConfig> {
+    let config = AppConfig::load()?;
+    // Validation has checked the parameters already
+    let _ = PasswordService::configure(&config.passwords);
+    Ok(config)
+}
+
+/// Connect to the configured database; any failure counts as unreachable
+#[allow(dead_code)]
+pub async fn connect(config: &AppConfig) -> CliResult<PgPool> {
+    init_pool(&config.database)
+        .await
+        .map_err(|err| CliError::Database(format!("Failed to connect to the database: {}", err)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes() {
+        let codes: Vec<u8> = [
+            CliError::Failure(String::new()),
+            CliError::Config(String::new()),
+            CliError::Database(String::new()),
+            CliError::Invalid(String::new()),
+            CliError::NotFound(String::new()),
+            CliError::CheckFailed(String::new()),
+            CliError::Usage(String::new()),
+        ]
+        .iter()
+        .map(CliError::exit_code)
+        .collect();
+        assert_eq!(codes, [1, 2, 3, 4, 5, 6, 64]);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let err = CliError::from(ConfigError::Invalid("PORT must be set".to_string()));
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(err.to_string(), "Invalid configuration: PORT must be set");
+
+        assert_eq!(CliError::from(sqlx::Error::PoolTimedOut).exit_code(), 3);
+        assert_eq!(CliError::from(sqlx::Error::RowNotFound).exit_code(), 5);
+        assert_eq!(
+            CliError::from(AppError::Validation("bad".to_string())),
+            CliError::Invalid("bad".to_string())
+        );
+
+        let boxed: Box<dyn std::error::Error + Send + Sync> = Box::new(sqlx::Error::PoolClosed);
+        assert_eq!(CliError::from_boxed(boxed).exit_code(), 3);
+        let boxed: Box<dyn std::error::Error + Send + Sync> = "hashing failed".into();
+        assert_eq!(
+            CliError::from_boxed(boxed),
+            CliError::Failure("hashing failed".to_string())
+        );
+    }
+
+    #[test]
+    fn test_context() {
+        let err = CliError::NotFound("user 7".to_string()).context("Failed to set password");
+        assert_eq!(err.to_string(), "Failed to set password: user 7");
+        assert_eq!(err.exit_code(), 5);
+    }
+}
@@ -0,0 +1,440 @@
+//! # API Client
+//!
+//! Typed async client for the JSON API, built with `--features client`.
+//! Requests and responses use the DTOs in [`crate::models`], the same types
+//! the handlers serialize, so a change to a DTO changes the client with it
+//! and the two cannot drift apart.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), axum_base::client::ClientError> {
+//! use axum_base::client::{ApiClient, PageRequest};
+//!
+//! let client = ApiClient::new("http://localhost:3000");
+//! client.login("admin", "secret123").await?;
+//! let items = client.items().list(PageRequest::page(1, 20)).await?;
+//! println!("{} of {:?} items", items.items.len(), items.pagination);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Signing in keeps the session cookie, which must have the default name,
+//! for later requests. Failed requests
+//! return [`ClientError::Api`] with the status and the error envelope's code.
+
+use reqwest::{Method, RequestBuilder, Response, StatusCode, header, multipart, redirect};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use std::fmt;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+use crate::config::DEFAULT_SESSION_COOKIE;
+use crate::login_guard::CSRF_COOKIE;
+use crate::models::{
+    AssignRolesRequest, Category, CreateUserRequest, Envelope, HealthResponse, ItemSuggestion,
+    ItemWithCategory, PaginationMeta, SetPasswordRequest, SuggestQuery, Upload, UserFilter,
+    UserResponse,
+};
+
+/// Error from an API call
+#[derive(Debug)]
+pub enum ClientError {
+    /// The request could not be sent or the response not read
+    Http(reqwest::Error),
+    /// The server answered with an error envelope
+    Api {
+        status: StatusCode,
+        code: String,
+        message: String,
+    },
+    /// The username or password was rejected
+    Login,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(err) => write!(f, "HTTP error: {}", err),
+            ClientError::Api {
+                status,
+                code,
+                message,
+            } => write!(f, "{} ({}): {}", status, code, message),
+            ClientError::Login => write!(f, "Invalid username or password"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::Http(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(err: reqwest::Error) -> Self {
+        ClientError::Http(err)
+    }
+}
+
+/// Result alias for client calls
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// One page of a list endpoint
+#[derive(Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub pagination: Option<PaginationMeta>,
+}
+
+/// Which page of a list to fetch; the server's defaults when empty
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct PageRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub per_page: Option<u32>,
+}
+
+impl PageRequest {
+    /// Page `page` (from 1) of `per_page` results
+    pub fn page(page: u32, per_page: u32) -> Self {
+        Self {
+            page: Some(page),
+            per_page: Some(per_page),
+        }
+    }
+}
+
+/// Client for one server
+#[derive(Debug)]
+pub struct ApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    /// `id=...` pair from the last sign-in
+    session: Mutex<Option<String>>,
+}
+
+impl ApiClient {
+    /// Client for the server at `base_url`, e.g. `https://app.example.com`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            // Sign-in answers with a redirect that carries the session cookie
+            .redirect(redirect::Policy::none())
+            .build()
+            .expect("default HTTP client settings are valid");
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Sign in; later requests are made as this user
+    pub async fn login(&self, username: &str, password: &str) -> ClientResult<()> {
+        // The login form's token comes with the page, as a cookie to echo
+        let page = self.http.get(self.url("/login")).send().await?;
+        let csrf = set_cookie(&page, CSRF_COOKIE).ok_or(ClientError::Login)?;
+        let token = csrf.split_once('=').map_or("", |(_, token)| token);
+
+        let response = self
+            .http
+            .post(self.url("/login"))
+            .header(header::COOKIE, &csrf)
+            .form(&[
+                ("username", username),
+                ("password", password),
+                ("csrf_token", token),
+            ])
+            .send()
+            .await?;
+        if !response.status().is_redirection() {
+            return Err(ClientError::Login);
+        }
+
+        let cookie = set_cookie(&response, DEFAULT_SESSION_COOKIE).ok_or(ClientError::Login)?;
+        *self.lock_session() = Some(cookie);
+        Ok(())
+    }
+
+    /// Forget the session cookie
+    pub fn logout(&self) {
+        *self.lock_session() = None;
+    }
+
+    /// `GET /health`; an API error while a critical component is down
+    pub async fn health(&self) -> ClientResult<HealthResponse> {
+        self.data(self.request(Method::GET, "/health")).await
+    }
+
+    /// Catalog items
+    pub fn items(&self) -> Items<'_> {
+        Items(self)
+    }
+
+    /// Item categories
+    pub fn categories(&self) -> Categories<'_> {
+        Categories(self)
+    }
+
+    /// User management (admin role)
+    pub fn users(&self) -> Users<'_> {
+        Users(self)
+    }
+
+    /// The signed-in user's files
+    pub fn uploads(&self) -> Uploads<'_> {
+        Uploads(self)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn lock_session(&self) -> std::sync::MutexGuard<'_, Option<String>> {
+        self.session
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let request = self
+            .http
+            .request(method, self.url(path))
+            .header(header::ACCEPT, "application/json");
+        match self.lock_session().as_deref() {
+            Some(cookie) => request.header(header::COOKIE, cookie),
+            None => request,
+        }
+    }
+
+    /// Send a request; error statuses become [`ClientError::Api`]
+    async fn send(&self, request: RequestBuilder) -> ClientResult<Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let (code, message) = match response.json::<Envelope<serde_json::Value>>().await {
+            Ok(envelope) => match envelope.errors.into_iter().next() {
+                Some(detail) => (detail.code, detail.message),
+                None => (String::new(), envelope.message.unwrap_or_default()),
+            },
+            Err(_) => (String::new(), status.to_string()),
+        };
+        Err(ClientError::Api {
+            status,
+            code,
+            message,
+        })
+    }
+
+    /// Send a request and parse the envelope
+    async fn envelope<T: DeserializeOwned>(
+        &self,
+        request: RequestBuilder,
+    ) -> ClientResult<Envelope<T>> {
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    /// Send a request and return the envelope's data
+    async fn data<T: DeserializeOwned>(&self, request: RequestBuilder) -> ClientResult<T> {
+        self.envelope::<T>(request)
+            .await?
+            .data
+            .ok_or_else(|| ClientError::Api {
+                status: StatusCode::OK,
+                code: String::new(),
+                message: "Response has no data".to_string(),
+            })
+    }
+
+    /// Send a request whose response carries only a message
+    async fn empty(&self, request: RequestBuilder) -> ClientResult<()> {
+        self.envelope::<serde_json::Value>(request)
+            .await
+            .map(|_| ())
+    }
+
+    /// Send a list request and return one page
+    async fn page<T: DeserializeOwned>(&self, request: RequestBuilder) -> ClientResult<Page<T>> {
+        let envelope = self.envelope::<Vec<T>>(request).await?;
+        Ok(Page {
+            items: envelope.data.unwrap_or_default(),
+            pagination: envelope.meta.pagination,
+        })
+    }
+}
+
+/// The `name=value` pair of cookie `name` set by `response`
+fn set_cookie(response: &Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .filter_map(|value| value.split(';').next())
+        .find(|pair| pair.starts_with(&format!("{}=", name)))
+        .map(str::to_string)
+}
+
+// =============================================================================
+// Resources
+// =============================================================================
+
+/// `/api/items`
+pub struct Items<'a>(&'a ApiClient);
+
+impl Items<'_> {
+    /// One page of active items with their categories
+    pub async fn list(&self, page: PageRequest) -> ClientResult<Page<ItemWithCategory>> {
+        let request = self.0.request(Method::GET, "/api/items").query(&page);
+        self.0.page(request).await
+    }
+
+    /// Up to `limit` titles containing `text`, best first
+    pub async fn suggest(&self, text: &str, limit: usize) -> ClientResult<Vec<ItemSuggestion>> {
+        let query = SuggestQuery {
+            q: text.to_string(),
+            limit: Some(limit),
+        };
+        let request = self
+            .0
+            .request(Method::GET, "/api/items/suggest")
+            .query(&query);
+        self.0.data(request).await
+    }
+}
+
+/// `/api/categories`
+pub struct Categories<'a>(&'a ApiClient);
+
+impl Categories<'_> {
+    /// One page of visible categories
+    pub async fn list(&self, page: PageRequest) -> ClientResult<Page<Category>> {
+        let request = self.0.request(Method::GET, "/api/categories").query(&page);
+        self.0.page(request).await
+    }
+}
+
+/// `/api/admin/users`
+pub struct Users<'a>(&'a ApiClient);
+
+impl Users<'_> {
+    /// One page of users matching `filter`
+    pub async fn list(
+        &self,
+        filter: &UserFilter,
+        page: PageRequest,
+    ) -> ClientResult<Page<UserResponse>> {
+        let request = self
+            .0
+            .request(Method::GET, "/api/admin/users")
+            .query(filter)
+            .query(&page);
+        self.0.page(request).await
+    }
+
+    /// A user by ID
+    pub async fn get(&self, id: i32) -> ClientResult<UserResponse> {
+        let path = format!("/api/admin/users/{}", id);
+        self.0.data(self.0.request(Method::GET, &path)).await
+    }
+
+    /// Create a user
+    pub async fn create(&self, user: &CreateUserRequest) -> ClientResult<UserResponse> {
+        let request = self.0.request(Method::POST, "/api/admin/users").json(user);
+        self.0.data(request).await
+    }
+
+    /// Deactivate a user
+    pub async fn deactivate(&self, id: i32) -> ClientResult<UserResponse> {
+        let path = format!("/api/admin/users/{}/deactivate", id);
+        self.0.data(self.0.request(Method::POST, &path)).await
+    }
+
+    /// Replace a user's password
+    pub async fn set_password(&self, id: i32, password: &str) -> ClientResult<()> {
+        let path = format!("/api/admin/users/{}/password", id);
+        let body = SetPasswordRequest {
+            password: password.to_string(),
+        };
+        self.0
+            .empty(self.0.request(Method::PUT, &path).json(&body))
+            .await
+    }
+
+    /// Replace a user's roles
+    pub async fn assign_roles(&self, id: i32, roles: &[&str]) -> ClientResult<UserResponse> {
+        let path = format!("/api/admin/users/{}/roles", id);
+        let body = AssignRolesRequest {
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+        };
+        self.0
+            .data(self.0.request(Method::PUT, &path).json(&body))
+            .await
+    }
+}
+
+/// `/api/uploads` and `/files/{id}`
+pub struct Uploads<'a>(&'a ApiClient);
+
+impl Uploads<'_> {
+    /// The signed-in user's uploads, newest first
+    pub async fn list(&self) -> ClientResult<Vec<Upload>> {
+        self.0
+            .data(self.0.request(Method::GET, "/api/uploads"))
+            .await
+    }
+
+    /// Upload a file
+    pub async fn upload(
+        &self,
+        filename: &str,
+        content_type: &str,
+        contents: Vec<u8>,
+    ) -> ClientResult<Upload> {
+        let part = multipart::Part::bytes(contents)
+            .file_name(filename.to_string())
+            .mime_str(content_type)?;
+        let form = multipart::Form::new().part(crate::uploads::FILE_FIELD, part);
+        let request = self.0.request(Method::POST, "/api/uploads").multipart(form);
+        self.0.data(request).await
+    }
+
+    /// A file's contents
+    pub async fn download(&self, id: Uuid) -> ClientResult<Vec<u8>> {
+        let path = format!("/files/{}", id);
+        let response = self.0.send(self.0.request(Method::GET, &path)).await?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
+    /// Delete a file
+    pub async fn delete(&self, id: Uuid) -> ClientResult<()> {
+        let path = format!("/files/{}", id);
+        self.0.empty(self.0.request(Method::DELETE, &path)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_request_query() {
+        let query = serde_json::to_value(PageRequest::page(2, 10)).unwrap();
+        assert_eq!(query, serde_json::json!({"page": 2, "per_page": 10}));
+        let query = serde_json::to_value(PageRequest::default()).unwrap();
+        assert_eq!(query, serde_json::json!({}));
+    }
+
+    #[test]
+    fn test_base_url_trailing_slash() {
+        let client = ApiClient::new("http://localhost:3000/");
+        assert_eq!(client.url("/health"), "http://localhost:3000/health");
+    }
+}
@@ -0,0 +1,241 @@
+//! # Client IP
+//!
+//! Works out which address a request came from. Behind a reverse proxy the
+//! peer of the TCP connection is the proxy, so `X-Forwarded-For` is read, but
+//! only when the peer is one of the configured trusted proxies
+//! (`TRUSTED_PROXIES`); anyone else could put any address in the header.
+//! The header is read from the right, skipping trusted hops, so a client
+//! cannot choose its address by sending a header of its own.
+//!
+//! The server records peers with `ConnectInfo`; requests without it, such as
+//! those of in-process test servers, have no client IP.
+
+use axum::extract::{ConnectInfo, FromRef, FromRequestParts};
+use axum::http::{HeaderMap, request::Parts};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+
+/// Header proxies append the address they received a request from to
+pub const FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// An address or CIDR block, e.g. `10.0.0.0/8`, of proxies whose
+/// `X-Forwarded-For` is believed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedProxy {
+    /// Whether `ip` is in this block
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (value, None),
+        };
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|_| format!("'{}' is not an IP address or CIDR block", value))?
+            .to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= bits)
+                .ok_or_else(|| format!("'{}' has an invalid prefix length", value))?,
+            None => bits,
+        };
+        Ok(Self { network, prefix })
+    }
+}
+
+impl TryFrom<String> for TrustedProxy {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl fmt::Display for TrustedProxy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix)
+    }
+}
+
+/// Address of the client behind `peer`. `X-Forwarded-For` is followed from
+/// the right while the hop it came through is trusted; an entry that does
+/// not parse stops the walk at the last hop known.
+pub fn resolve(peer: IpAddr, headers: &HeaderMap, trusted: &[TrustedProxy]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|proxy| proxy.contains(ip));
+    let mut client = peer.to_canonical();
+    if !is_trusted(client) {
+        return client;
+    }
+
+    let forwarded: Vec<&str> = headers
+        .get_all(FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect();
+    for entry in forwarded.iter().rev() {
+        let Some(ip) = parse_entry(entry) else {
+            break;
+        };
+        client = ip;
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    client
+}
+
+/// An `X-Forwarded-For` entry: an address, possibly bracketed or with a port
+fn parse_entry(entry: &str) -> Option<IpAddr> {
+    let entry = entry.trim();
+    entry
+        .parse::<IpAddr>()
+        .or_else(|_| entry.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .or_else(|_| {
+            entry
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse::<IpAddr>()
+        })
+        .ok()
+        .map(|ip| ip.to_canonical())
+}
+
+/// Extractor for the request's client address, `None` when the server did
+/// not record the peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    Arc<AppConfig>: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let config = Arc::<AppConfig>::from_ref(state);
+        Ok(ClientIp(
+            parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(peer)| {
+                    resolve(peer.ip(), &parts.headers, &config.server.trusted_proxies)
+                }),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn proxies(list: &[&str]) -> Vec<TrustedProxy> {
+        list.iter().map(|proxy| proxy.parse().unwrap()).collect()
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(FORWARDED_FOR, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn test_trusted_proxy_parsing() {
+        let block: TrustedProxy = "10.0.0.0/8".parse().unwrap();
+        assert!(block.contains(ip("10.1.2.3")));
+        assert!(block.contains(ip("::ffff:10.1.2.3")));
+        assert!(!block.contains(ip("11.0.0.1")));
+        assert_eq!(block.to_string(), "10.0.0.0/8");
+
+        let single: TrustedProxy = " 192.168.0.1 ".parse().unwrap();
+        assert!(single.contains(ip("192.168.0.1")));
+        assert!(!single.contains(ip("192.168.0.2")));
+        let all: TrustedProxy = "::/0".parse().unwrap();
+        assert!(all.contains(ip("2001:db8::1")));
+        assert!(!all.contains(ip("10.0.0.1")));
+
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy.internal".parse::<TrustedProxy>().is_err());
+    }
+
+    #[test]
+    fn test_header_ignored_from_untrusted_peers() {
+        let headers = forwarded(&["203.0.113.9"]);
+        assert_eq!(
+            resolve(ip("198.51.100.7"), &headers, &[]),
+            ip("198.51.100.7")
+        );
+        assert_eq!(
+            resolve(ip("198.51.100.7"), &headers, &proxies(&["10.0.0.0/8"])),
+            ip("198.51.100.7")
+        );
+    }
+
+    #[test]
+    fn test_header_followed_through_trusted_proxies() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+        // The client's own header is skipped along with the trusted hops
+        let headers = forwarded(&["1.2.3.4, 203.0.113.9", "10.0.0.2"]);
+        assert_eq!(
+            resolve(ip("10.0.0.1"), &headers, &trusted),
+            ip("203.0.113.9")
+        );
+
+        let headers = forwarded(&["[2001:db8::5]:4711, 10.0.0.2"]);
+        assert_eq!(
+            resolve(ip("10.0.0.1"), &headers, &trusted),
+            ip("2001:db8::5")
+        );
+
+        // Only trusted hops, or garbage, leave the last address known
+        let headers = forwarded(&["10.0.0.3"]);
+        assert_eq!(resolve(ip("10.0.0.1"), &headers, &trusted), ip("10.0.0.3"));
+        let headers = forwarded(&["unknown"]);
+        assert_eq!(resolve(ip("10.0.0.1"), &headers, &trusted), ip("10.0.0.1"));
+        assert_eq!(
+            resolve(ip("10.0.0.1"), &HeaderMap::new(), &trusted),
+            ip("10.0.0.1")
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! # Shell Completions
+//!
+//! Generates bash, zsh, and fish completion scripts for the bundled CLI tools
+//! from a [`Spec`] tree, so `admin completions <shell>` stays in step with the
+//! commands the binary matches on. Commands and long options are completed;
+//! other arguments fall back to the shell's file completion.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// A command, its long options, and its subcommands
+#[derive(Debug)]
+pub struct Spec {
+    pub name: &'static str,
+    pub options: &'static [&'static str],
+    pub subcommands: &'static [Spec],
+}
+
+impl Spec {
+    /// A command with neither options nor subcommands
+    pub const fn leaf(name: &'static str) -> Self {
+        Self {
+            name,
+            options: &[],
+            subcommands: &[],
+        }
+    }
+}
+
+/// Shells completions can be generated for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Shell {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            other => Err(format!(
+                "Unknown shell '{}'; choose bash, zsh, or fish",
+                other
+            )),
+        }
+    }
+}
+
+/// The completion script for `program` in `shell`
+pub fn generate(program: &Spec, shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(program),
+        // zsh runs the bash function through its compatibility layer
+        Shell::Zsh => format!(
+            "#compdef {0}\nautoload -U +X bashcompinit && bashcompinit\n{1}",
+            program.name,
+            bash(program)
+        ),
+        Shell::Fish => fish(program),
+    }
+}
+
+fn bash(program: &Spec) -> String {
+    let function = format!("_{}", program.name.replace('-', "_"));
+    let mut cases = String::new();
+    let mut case = |pattern: String, candidates: Vec<&str>| {
+        let _ = writeln!(
+            cases,
+            "        \"{}\") words=\"{}\" ;;",
+            pattern,
+            candidates.join(" ")
+        );
+    };
+    case(String::from(" "), words(program));
+    for command in program.subcommands {
+        case(format!("{} ", command.name), words(command));
+        for subcommand in command.subcommands {
+            case(
+                format!("{} {}", command.name, subcommand.name),
+                words(subcommand),
+            );
+        }
+    }
+
+    format!(
+        r#"{function}() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}" command="" subcommand="" word words=""
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+        case "$word" in
+            -*) ;;
+            *) if [[ -z $command ]]; then command=$word
+               elif [[ -z $subcommand ]]; then subcommand=$word; fi ;;
+        esac
+    done
+    case "$command $subcommand" in
+{cases}    esac
+    COMPREPLY=($(compgen -W "$words" -- "$cur"))
+}}
+complete -o default -F {function} {name}
+"#,
+        name = program.name,
+    )
+}
+
+/// Options first, then subcommand names
+fn words(spec: &Spec) -> Vec<&str> {
+    spec.options
+        .iter()
+        .copied()
+        .chain(spec.subcommands.iter().map(|subcommand| subcommand.name))
+        .collect()
+}
+
+fn fish(program: &Spec) -> String {
+    let name = program.name;
+    let mut script = String::new();
+    let mut line = |condition: String, completion: String| {
+        let _ = writeln!(
+            script,
+            "complete -c {} -n '{}' {}",
+            name, condition, completion
+        );
+    };
+    let options = |spec: &Spec| {
+        spec.options
+            .iter()
+            .map(|option| format!("-l {}", option.trim_start_matches('-')))
+            .collect::<Vec<_>>()
+    };
+
+    let commands: Vec<&str> = program.subcommands.iter().map(|c| c.name).collect();
+    for option in options(program) {
+        line("__fish_use_subcommand".to_string(), option);
+    }
+    line(
+        "__fish_use_subcommand".to_string(),
+        format!("-f -a '{}'", commands.join(" ")),
+    );
+    for command in program.subcommands {
+        let seen = format!("__fish_seen_subcommand_from {}", command.name);
+        for option in options(command) {
+            line(seen.clone(), option);
+        }
+        if command.subcommands.is_empty() {
+            continue;
+        }
+        let names: Vec<&str> = command.subcommands.iter().map(|s| s.name).collect();
+        line(
+            format!(
+                "{}; and not __fish_seen_subcommand_from {}",
+                seen,
+                names.join(" ")
+            ),
+            format!("-f -a '{}'", names.join(" ")),
+        );
+        for subcommand in command.subcommands {
+            for option in options(subcommand) {
+                line(
+                    format!(
+                        "{}; and __fish_seen_subcommand_from {}",
+                        seen, subcommand.name
+                    ),
+                    option,
+                );
+            }
+        }
+    }
+    script
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOOL: Spec = Spec {
+        name: "tool",
+        options: &["--quiet"],
+        subcommands: &[
+            Spec::leaf("console"),
+            Spec {
+                name: "db",
+                options: &[],
+                subcommands: &[
+                    Spec::leaf("dump"),
+                    Spec {
+                        name: "restore",
+                        options: &["--yes"],
+                        subcommands: &[],
+                    },
+                ],
+            },
+        ],
+    };
+
+    #[test]
+    fn test_bash_completions() {
+        let script = generate(&TOOL, Shell::Bash);
+        assert!(script.contains(r#"" ") words="--quiet console db" ;;"#));
+        assert!(script.contains(r#""db ") words="dump restore" ;;"#));
+        assert!(script.contains(r#""db restore") words="--yes" ;;"#));
+        assert!(script.ends_with("complete -o default -F _tool tool\n"));
+        assert!(generate(&TOOL, Shell::Zsh).starts_with("#compdef tool\n"));
+    }
+
+    #[test]
+    fn test_fish_completions() {
+        let script = generate(&TOOL, Shell::Fish);
+        assert!(script.contains("complete -c tool -n '__fish_use_subcommand' -l quiet\n"));
+        assert!(script.contains("-n '__fish_use_subcommand' -f -a 'console db'"));
+        assert!(script.contains(
+            "-n '__fish_seen_subcommand_from db; and not __fish_seen_subcommand_from dump restore' -f -a 'dump restore'"
+        ));
+        assert!(script.contains(
+            "-n '__fish_seen_subcommand_from db; and __fish_seen_subcommand_from restore' -l yes"
+        ));
+        assert_eq!("fish".parse(), Ok(Shell::Fish));
+        assert!("powershell".parse::<Shell>().is_err());
+    }
+}
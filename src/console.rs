@@ -0,0 +1,975 @@
+//! # Admin Console
+//!
+//! Commands behind `admin console`, an interactive prompt for inspecting and
+//! fixing data through the service layer instead of ad-hoc SQL. Lines are
+//! parsed into a [`Command`] and run with [`execute`]; the line editor itself
+//! lives in the admin binary.
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::admin::{validate_password, validate_roles};
+use crate::audit::{self, AuditEvent};
+use crate::auth::{AuthService, PasswordService};
+use crate::error::{AppError, AppResult};
+use crate::jobs::Priority;
+use crate::models::{User, UserFilter, UserResponse};
+use crate::pagination::{DEFAULT_PER_PAGE, MAX_PER_PAGE, Paginated, Pagination};
+use crate::plugins::Event;
+use crate::services::{JobService, UploadService, UserService};
+use crate::sql_console::csv_field;
+use crate::state::AppState;
+use crate::uploads::StorageBackend;
+
+/// Users shown by `users` before the list is cut off
+const LIST_LIMIT: u32 = 20;
+
+/// Help text printed by `help`
+pub const HELP: &str = "\
+Commands:
+  user <id|username|email>           Show a user, including deactivated ones
+  users [search]                     List users matching a username or email
+  verify <user> <password>           Check a password against the stored hash
+  set-password <user> <password>     Replace a user's password
+  roles <user> [role ...]            Show roles, or replace them when given
+  deactivate <user>                  Deactivate a user
+  enqueue <kind> <json>              Queue a background job with a JSON payload
+  emit <event> <target>              Tell plugins about an event: user-logged-in <user>,
+                                     user-logged-out <user>, or file-uploaded <upload id>
+  help                               Show this help
+  exit                               Leave the console";
+
+/// A parsed console line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Help,
+    Exit,
+    User(String),
+    Users(Option<String>),
+    Verify {
+        user: String,
+        password: String,
+    },
+    SetPassword {
+        user: String,
+        password: String,
+    },
+    Roles {
+        user: String,
+        roles: Option<Vec<String>>,
+    },
+    Deactivate(String),
+    Enqueue {
+        kind: String,
+        payload: serde_json::Value,
+    },
+    Emit(EmitEvent),
+}
+
+/// An [`Event`] named on the console, resolved when it is emitted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmitEvent {
+    UserLoggedIn(String),
+    UserLoggedOut(String),
+    FileUploaded(Uuid),
+}
+
+impl EmitEvent {
+    fn parse(name: &str, target: &str) -> Result<Self, String> {
+        match name {
+            "user-logged-in" => Ok(EmitEvent::UserLoggedIn(target.to_string())),
+            "user-logged-out" => Ok(EmitEvent::UserLoggedOut(target.to_string())),
+            "file-uploaded" => Uuid::parse_str(target)
+                .map(EmitEvent::FileUploaded)
+                .map_err(|_| format!("'{}' is not an upload ID", target)),
+            _ => Err(format!("Unknown event '{}' (try 'help')", name)),
+        }
+    }
+}
+
+impl Command {
+    /// Parse one input line; blank lines yield `Ok(None)`
+    pub fn parse(line: &str) -> Result<Option<Self>, String> {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(None);
+        };
+        let args: Vec<String> = words.map(str::to_string).collect();
+
+        let command = match (name, args.as_slice()) {
+            ("help" | "?", []) => Command::Help,
+            ("exit" | "quit", []) => Command::Exit,
+            ("user", [user]) => Command::User(user.clone()),
+            ("users", []) => Command::Users(None),
+            ("users", [search]) => Command::Users(Some(search.clone())),
+            ("verify", [user, password]) => Command::Verify {
+                user: user.clone(),
+                password: password.clone(),
+            },
+            ("set-password", [user, password]) => Command::SetPassword {
+                user: user.clone(),
+                password: password.clone(),
+            },
+            ("roles", [user]) => Command::Roles {
+                user: user.clone(),
+                roles: None,
+            },
+            ("roles", [user, roles @ ..]) => Command::Roles {
+                user: user.clone(),
+                roles: Some(roles.to_vec()),
+            },
+            ("deactivate", [user]) => Command::Deactivate(user.clone()),
+            // The payload is the rest of the line, so JSON may contain spaces
+            ("enqueue", [kind, _, ..]) => Command::Enqueue {
+                kind: kind.clone(),
+                payload: serde_json::from_str(remainder(line, 2))
+                    .map_err(|e| format!("Invalid JSON payload: {}", e))?,
+            },
+            ("emit", [event, target]) => Command::Emit(EmitEvent::parse(event, target)?),
+            (
+                "help" | "?" | "exit" | "quit" | "user" | "users" | "verify" | "set-password"
+                | "roles" | "deactivate" | "enqueue" | "emit",
+                _,
+            ) => return Err(format!("Wrong arguments for '{}' (try 'help')", name)),
+            _ => return Err(format!("Unknown command '{}' (try 'help')", name)),
+        };
+        Ok(Some(command))
+    }
+
+    /// Whether the line holds a password and must stay out of history
+    pub fn is_sensitive(&self) -> bool {
+        matches!(self, Command::Verify { .. } | Command::SetPassword { .. })
+    }
+}
+
+/// The text of `line` after its first `words` words
+fn remainder(line: &str, words: usize) -> &str {
+    let mut rest = line.trim();
+    for _ in 0..words {
+        rest = rest
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, tail)| tail.trim_start());
+    }
+    rest
+}
+
+/// Run a command and return the text to print. Events go to the plugins in
+/// `state`, so an app with plugins passes a state built with
+/// [`AppState::with_plugins`].
+pub async fn execute(state: &AppState, command: &Command) -> AppResult<String> {
+    let pool = &state.pool;
+    match command {
+        Command::Help => Ok(HELP.to_string()),
+        Command::Exit => Ok(String::new()),
+        Command::User(identifier) => {
+            let user = resolve_user(pool, identifier).await?;
+            let roles = UserService::get_user_roles(pool, user.id).await?;
+            Ok(describe_user(&user, &roles))
+        }
+        Command::Users(search) => {
+            let filter = UserFilter {
+                search: search.clone(),
+                ..UserFilter::default()
+            };
+            let pagination = Pagination::limit_offset(LIST_LIMIT, 0);
+            let page = UserService::list_users(pool, &filter, &pagination).await?;
+
+            let mut lines: Vec<String> = page.items.iter().map(summarize_user).collect();
+            if page.total > page.items.len() as i64 {
+                lines.push(format!("... {} users in total", page.total));
+            }
+            if lines.is_empty() {
+                lines.push("No users found".to_string());
+            }
+            Ok(lines.join("\n"))
+        }
+        Command::Verify { user, password } => {
+            let user = resolve_user(pool, user).await?;
+            let hash = user.password_hash.as_deref().unwrap_or_default();
+            match PasswordService::verify_password(password, hash) {
+                Ok(true) => Ok(format!("✅ Password matches for {}", user.username)),
+                Ok(false) => Ok(format!("❌ Password does not match for {}", user.username)),
+                Err(e) => Err(AppError::Internal(format!(
+                    "Stored hash for {} is invalid: {}",
+                    user.username, e
+                ))),
+            }
+        }
+        Command::SetPassword { user, password } => {
+            validate_password(password)?;
+            let user = resolve_user(pool, user).await?;
+            let hash = UserService::hash_password(password)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to hash password: {}", e)))?;
+            UserService::update_user_password(pool, user.id, &hash).await?;
+            Ok(format!("✅ Password updated for {}", user.username))
+        }
+        Command::Roles { user, roles } => {
+            let user = resolve_user(pool, user).await?;
+            if let Some(roles) = roles {
+                validate_roles(roles)?;
+                UserService::set_user_roles(pool, user.id, roles).await?;
+            }
+            let roles = UserService::get_user_roles(pool, user.id).await?;
+            Ok(format!("{}: {}", user.username, format_roles(&roles)))
+        }
+        Command::Deactivate(user) => {
+            let user = resolve_user(pool, user).await?;
+            deactivate(pool, &user).await
+        }
+        Command::Enqueue { kind, payload } => {
+            let id =
+                JobService::enqueue(pool, kind, payload, Priority::Normal.level(), None).await?;
+            Ok(format!("✅ Queued job #{} ({})", id, kind))
+        }
+        Command::Emit(event) => emit(state, event).await,
+    }
+}
+
+/// Resolve `event` and hand it to every plugin
+async fn emit(state: &AppState, event: &EmitEvent) -> AppResult<String> {
+    let (event, subject) = match event {
+        EmitEvent::UserLoggedIn(user) => {
+            let user = resolve_user(&state.pool, user).await?;
+            (Event::UserLoggedIn { user_id: user.id }, user.username)
+        }
+        EmitEvent::UserLoggedOut(user) => {
+            let user = resolve_user(&state.pool, user).await?;
+            (Event::UserLoggedOut { user_id: user.id }, user.username)
+        }
+        EmitEvent::FileUploaded(id) => {
+            let upload = UploadService::find(&state.pool, *id)
+                .await?
+                .ok_or_else(|| AppError::NotFound(format!("No upload with ID {}", id)))?;
+            let filename = upload.filename.clone();
+            (Event::FileUploaded(upload), filename)
+        }
+    };
+
+    let plugins = state.plugins.names();
+    if plugins.is_empty() {
+        return Ok("No plugins registered; nothing to tell".to_string());
+    }
+    state.plugins.emit(state, event);
+    Ok(format!(
+        "✅ Sent event for {} to {}",
+        subject,
+        plugins.join(", ")
+    ))
+}
+
+/// Deactivate `user` and end their sessions
+async fn deactivate(pool: &PgPool, user: &User) -> AppResult<String> {
+    UserService::deactivate_user(pool, user.id).await?;
+    let sessions = AuthService::end_sessions(pool, user.id).await?;
+    audit::record(
+        pool,
+        AuditEvent::new(audit::USER_DEACTIVATED)
+            .on("user", user.id)
+            .with_details(json!({ "sessions_ended": sessions.len() })),
+    )
+    .await;
+    Ok(format!(
+        "✅ Deactivated {} and ended {} session(s)",
+        user.username,
+        sessions.len()
+    ))
+}
+
+/// Look a user up by ID, username, or email
+async fn resolve_user(pool: &PgPool, identifier: &str) -> AppResult<User> {
+    UserService::find_user(pool, identifier)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("No user matches '{}'", identifier)))
+}
+
+fn describe_user(user: &User, roles: &[String]) -> String {
+    let last_login = user
+        .last_login
+        .map(|time| time.to_rfc3339())
+        .unwrap_or_else(|| "never".to_string());
+    [
+        summarize_user(user),
+        format!("   email verified: {}", yes_no(user.email_verified)),
+        format!("   roles: {}", format_roles(roles)),
+        format!("   last login: {}", last_login),
+        format!("   created: {}", user.created_at.to_rfc3339()),
+        format!("   updated: {}", user.updated_at.to_rfc3339()),
+    ]
+    .join("\n")
+}
+
+fn summarize_user(user: &User) -> String {
+    let status = if user.is_active { "" } else { " (deactivated)" };
+    format!("#{} {} <{}>{}", user.id, user.username, user.email, status)
+}
+
+fn format_roles(roles: &[String]) -> String {
+    if roles.is_empty() {
+        "(none)".to_string()
+    } else {
+        roles.join(", ")
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+// =============================================================================
+// User Listing
+// =============================================================================
+
+/// Options of `admin users list`, shown when they do not parse
+pub const LIST_USERS_OPTIONS: &str = "\
+Options:
+  --active | --inactive      Only active or only deactivated users
+  --email-like <pattern>     Case-insensitive SQL LIKE pattern, e.g. '%@example.com'
+  --created-after <date>     Created on or after a date (2024-05-01) or RFC 3339 time
+  --role <role>              Only users with this role
+  --search <text>            Username or email contains the text
+  --format table|json        Output format (default: table)
+  --page <n>                 Page to show, from 1 (default: 1)
+  --per-page <n>             Users per page, at most 100 (default: 20)";
+
+/// Output format of `admin users list`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ListFormat {
+    #[default]
+    Table,
+    Json,
+}
+
+/// Parsed `admin users list` arguments
+#[derive(Debug)]
+pub struct ListUsers {
+    pub filter: UserFilter,
+    pub pagination: Pagination,
+    pub format: ListFormat,
+}
+
+impl ListUsers {
+    /// Parse the arguments after `users list`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut filter = UserFilter::default();
+        let mut format = ListFormat::default();
+        let mut page = 1;
+        let mut per_page = DEFAULT_PER_PAGE;
+
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--format" => {
+                    format = match value()?.as_str() {
+                        "table" => ListFormat::Table,
+                        "json" => ListFormat::Json,
+                        other => return Err(format!("Unknown format '{}'", other)),
+                    }
+                }
+                "--page" => page = parse_count(flag, &value()?)?,
+                "--per-page" => per_page = parse_count(flag, &value()?)?,
+                other => {
+                    if !apply_filter(&mut filter, other, value)? {
+                        return Err(format!("Unknown option '{}'", other));
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            filter,
+            pagination: Pagination::page(page, per_page),
+            format,
+        })
+    }
+}
+
+/// Apply a filter flag shared by `users list` and `users export`, taking its
+/// value from `value`; false if `flag` is not a filter
+fn apply_filter(
+    filter: &mut UserFilter,
+    flag: &str,
+    mut value: impl FnMut() -> Result<String, String>,
+) -> Result<bool, String> {
+    match flag {
+        "--active" => filter.is_active = Some(true),
+        "--inactive" => filter.is_active = Some(false),
+        "--email-like" => filter.email_like = Some(value()?),
+        "--created-after" => filter.created_after = Some(parse_time(&value()?)?),
+        "--role" => filter.role = Some(value()?),
+        "--search" => filter.search = Some(value()?),
+        _ => return Ok(false),
+    }
+    Ok(true)
+}
+
+/// A date (midnight UTC) or an RFC 3339 time
+fn parse_time(value: &str) -> Result<DateTime<Utc>, String> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(NaiveTime::MIN).and_utc());
+    }
+    DateTime::parse_from_rfc3339(value)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| format!("'{}' is not a date (YYYY-MM-DD) or RFC 3339 time", value))
+}
+
+fn parse_count(flag: &str, value: &str) -> Result<u32, String> {
+    match value.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("{} must be a whole number from 1", flag)),
+    }
+}
+
+/// Run `admin users list` and return the text to print
+pub async fn list_users(pool: &PgPool, options: &ListUsers) -> AppResult<String> {
+    let Paginated {
+        items,
+        page,
+        per_page,
+        total,
+        total_pages,
+    } = UserService::list_users(pool, &options.filter, &options.pagination).await?;
+    let user_ids: Vec<i32> = items.iter().map(|user| user.id).collect();
+    let mut roles = UserService::get_roles_for_users(pool, &user_ids).await?;
+    let page = Paginated {
+        items: items
+            .into_iter()
+            .map(|user| {
+                let user_roles = roles.remove(&user.id).unwrap_or_default();
+                UserResponse::from(user).with_roles(user_roles)
+            })
+            .collect(),
+        page,
+        per_page,
+        total,
+        total_pages,
+    };
+
+    match options.format {
+        ListFormat::Json => serde_json::to_string_pretty(&page)
+            .map_err(|e| AppError::Internal(format!("Failed to encode users: {}", e))),
+        ListFormat::Table => Ok(user_table(&page)),
+    }
+}
+
+/// Aligned columns with a page summary below
+fn user_table(page: &Paginated<UserResponse>) -> String {
+    if page.items.is_empty() {
+        return format!("No users found ({} in total)", page.total);
+    }
+
+    let header = ["ID", "USERNAME", "EMAIL", "ACTIVE", "ROLES", "CREATED"].map(String::from);
+    let rows: Vec<[String; 6]> = page
+        .items
+        .iter()
+        .map(|user| {
+            [
+                user.id.to_string(),
+                user.username.clone(),
+                user.email.clone(),
+                yes_no(user.is_active).to_string(),
+                format_roles(&user.roles),
+                user.created_at.format("%Y-%m-%d %H:%M").to_string(),
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|cell| cell.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut lines: Vec<String> = std::iter::once(&header)
+        .chain(&rows)
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect();
+    lines.push(format!(
+        "Page {} of {} ({} users)",
+        page.page,
+        page.total_pages.max(1),
+        page.total
+    ));
+    lines.join("\n")
+}
+
+// =============================================================================
+// User Export
+// =============================================================================
+
+/// Options of `admin users export`, shown when they do not parse
+pub const EXPORT_USERS_OPTIONS: &str = "\
+Options:
+  --active | --inactive      Only active or only deactivated users
+  --email-like <pattern>     Case-insensitive SQL LIKE pattern, e.g. '%@example.com'
+  --created-after <date>     Created on or after a date (2024-05-01) or RFC 3339 time
+  --role <role>              Only users with this role
+  --search <text>            Username or email contains the text
+  --format csv|json          Output format (default: csv)
+  --output <file>            Write to a file instead of stdout";
+
+/// Output format of `admin users export`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Parsed `admin users export` arguments
+#[derive(Debug)]
+pub struct ExportUsers {
+    pub filter: UserFilter,
+    pub format: ExportFormat,
+    pub output: Option<String>,
+}
+
+impl ExportUsers {
+    /// Parse the arguments after `users export`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut filter = UserFilter::default();
+        let mut format = ExportFormat::default();
+        let mut output = None;
+
+        let mut args = args.iter();
+        while let Some(flag) = args.next() {
+            let mut value = || {
+                args.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag.as_str() {
+                "--format" => {
+                    format = match value()?.as_str() {
+                        "csv" => ExportFormat::Csv,
+                        "json" => ExportFormat::Json,
+                        other => return Err(format!("Unknown format '{}'", other)),
+                    }
+                }
+                "--output" => output = Some(value()?),
+                other => {
+                    if !apply_filter(&mut filter, other, value)? {
+                        return Err(format!("Unknown option '{}'", other));
+                    }
+                }
+            }
+        }
+
+        Ok(Self {
+            filter,
+            format,
+            output,
+        })
+    }
+}
+
+/// A user as exported: account fields and roles, never the password hash
+#[derive(Debug, Serialize)]
+pub struct ExportedUser {
+    pub id: i32,
+    pub username: String,
+    pub email: String,
+    pub email_verified: bool,
+    pub is_active: bool,
+    pub roles: Vec<String>,
+    pub last_login: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl ExportedUser {
+    fn new(user: User, roles: Vec<String>) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            email_verified: user.email_verified,
+            is_active: user.is_active,
+            roles,
+            last_login: user.last_login,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// Every user matching the export filter, in the order `users list` shows
+/// them, fetched a page at a time
+pub async fn export_users(pool: &PgPool, options: &ExportUsers) -> AppResult<Vec<ExportedUser>> {
+    let mut exported = Vec::new();
+    for page in 1.. {
+        let users =
+            UserService::list_users(pool, &options.filter, &Pagination::page(page, MAX_PER_PAGE))
+                .await?;
+        let user_ids: Vec<i32> = users.items.iter().map(|user| user.id).collect();
+        let mut roles = UserService::get_roles_for_users(pool, &user_ids).await?;
+        exported.extend(users.items.into_iter().map(|user| {
+            let user_roles = roles.remove(&user.id).unwrap_or_default();
+            ExportedUser::new(user, user_roles)
+        }));
+        if page >= users.total_pages {
+            break;
+        }
+    }
+    Ok(exported)
+}
+
+/// Render exported users in `format`; CSV roles are separated by `;`
+pub fn format_export(users: &[ExportedUser], format: ExportFormat) -> AppResult<String> {
+    match format {
+        ExportFormat::Json => serde_json::to_string_pretty(users)
+            .map(|json| json + "\n")
+            .map_err(|e| AppError::Internal(format!("Failed to encode users: {}", e))),
+        ExportFormat::Csv => {
+            let mut csv = String::from(
+                "id,username,email,email_verified,is_active,roles,last_login,created_at,updated_at\r\n",
+            );
+            for user in users {
+                let fields = [
+                    user.id.to_string(),
+                    user.username.clone(),
+                    user.email.clone(),
+                    user.email_verified.to_string(),
+                    user.is_active.to_string(),
+                    user.roles.join(";"),
+                    user.last_login
+                        .map(|time| time.to_rfc3339())
+                        .unwrap_or_default(),
+                    user.created_at.to_rfc3339(),
+                    user.updated_at.to_rfc3339(),
+                ];
+                let fields: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                csv.push_str(&fields.join(","));
+                csv.push_str("\r\n");
+            }
+            Ok(csv)
+        }
+    }
+}
+
+// =============================================================================
+// User Deletion
+// =============================================================================
+
+/// Arguments of `admin users delete`, shown when they do not parse
+pub const DELETE_USER_OPTIONS: &str = "\
+Arguments:
+  <user>    ID, username, or email of the user
+  --soft    Deactivate the user and end their sessions (the default)
+  --hard    Delete the user with their uploads and avatar for good; needs --yes
+  --yes     Confirm a hard delete";
+
+/// How `admin users delete` removes a user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeleteMode {
+    /// Deactivate and sign out; the account can be reactivated
+    #[default]
+    Soft,
+    /// Remove the account and everything it owns
+    Hard,
+}
+
+/// Parsed `admin users delete` arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeleteUser {
+    pub user: String,
+    pub mode: DeleteMode,
+}
+
+impl DeleteUser {
+    /// Parse the arguments after `users delete`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut user = None;
+        let mut mode = DeleteMode::default();
+        let mut confirmed = false;
+        for arg in args {
+            match arg.as_str() {
+                "--soft" => mode = DeleteMode::Soft,
+                "--hard" => mode = DeleteMode::Hard,
+                "--yes" => confirmed = true,
+                flag if flag.starts_with("--") => {
+                    return Err(format!("Unknown option '{}'", flag));
+                }
+                name if user.is_none() => user = Some(name.to_string()),
+                extra => return Err(format!("Unexpected argument '{}'", extra)),
+            }
+        }
+
+        let user = user.ok_or_else(|| "Name the user to delete".to_string())?;
+        if mode == DeleteMode::Hard && !confirmed {
+            return Err("--hard deletes the user permanently; add --yes to confirm".to_string());
+        }
+        Ok(Self { user, mode })
+    }
+}
+
+/// Run `admin users delete` and return the text to print. A hard delete
+/// removes stored uploads and the avatar from `storage` after the user is
+/// gone; contents that fail to delete are reported but do not fail it.
+pub async fn delete_user(
+    pool: &PgPool,
+    storage: &dyn StorageBackend,
+    options: &DeleteUser,
+) -> AppResult<String> {
+    let user = resolve_user(pool, &options.user).await?;
+    if options.mode == DeleteMode::Soft {
+        return deactivate(pool, &user).await;
+    }
+
+    // Read before the rows cascade away with the user
+    let uploads = UploadService::list_for_user(pool, user.id).await?;
+    let avatar = UserService::get_avatar_key(pool, user.id).await?;
+    let sessions = AuthService::end_sessions(pool, user.id).await?;
+    if !UserService::delete_user(pool, user.id).await? {
+        return Err(AppError::NotFound(format!("User {} not found", user.id)));
+    }
+    audit::record(
+        pool,
+        AuditEvent::new(audit::USER_DELETED)
+            .on("user", user.id)
+            .with_details(json!({
+                "username": user.username,
+                "uploads_deleted": uploads.len(),
+                "sessions_ended": sessions.len(),
+            })),
+    )
+    .await;
+
+    let mut lines = vec![format!(
+        "✅ Deleted {} (#{}) with {} upload(s) and ended {} session(s)",
+        user.username,
+        user.id,
+        uploads.len(),
+        sessions.len()
+    )];
+    let keys = uploads
+        .iter()
+        .map(|upload| upload.storage_key.as_str())
+        .chain(avatar.as_deref());
+    for key in keys {
+        if let Err(e) = storage.delete(key).await {
+            lines.push(format!("⚠️ Stored file {} was not removed: {}", key, e));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Command::parse("   "), Ok(None));
+        assert_eq!(
+            Command::parse("user alice"),
+            Ok(Some(Command::User("alice".to_string())))
+        );
+        assert_eq!(Command::parse("users"), Ok(Some(Command::Users(None))));
+        assert_eq!(
+            Command::parse("roles 3 admin editor"),
+            Ok(Some(Command::Roles {
+                user: "3".to_string(),
+                roles: Some(vec!["admin".to_string(), "editor".to_string()]),
+            }))
+        );
+        assert_eq!(Command::parse("quit"), Ok(Some(Command::Exit)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(
+            Command::parse("user")
+                .unwrap_err()
+                .contains("Wrong arguments")
+        );
+        assert!(
+            Command::parse("drop users")
+                .unwrap_err()
+                .contains("Unknown command")
+        );
+        assert!(
+            Command::parse("enqueue send_email {oops")
+                .unwrap_err()
+                .contains("Invalid JSON payload")
+        );
+        assert!(
+            Command::parse("emit user-deleted alice")
+                .unwrap_err()
+                .contains("Unknown event")
+        );
+        assert!(
+            Command::parse("emit file-uploaded 42")
+                .unwrap_err()
+                .contains("not an upload ID")
+        );
+        assert!(
+            Command::parse("emit user-logged-in")
+                .unwrap_err()
+                .contains("Wrong arguments")
+        );
+    }
+
+    #[test]
+    fn test_parse_enqueue_and_emit() {
+        assert_eq!(
+            Command::parse("enqueue send_email  { \"to\": \"a@example.com\", \"n\": 2 } "),
+            Ok(Some(Command::Enqueue {
+                kind: "send_email".to_string(),
+                payload: json!({ "to": "a@example.com", "n": 2 }),
+            }))
+        );
+        assert_eq!(
+            Command::parse("enqueue send_email"),
+            Err("Wrong arguments for 'enqueue' (try 'help')".to_string())
+        );
+        assert_eq!(
+            Command::parse("emit user-logged-out alice"),
+            Ok(Some(Command::Emit(EmitEvent::UserLoggedOut(
+                "alice".to_string()
+            ))))
+        );
+        let id = Uuid::new_v4();
+        assert_eq!(
+            Command::parse(&format!("emit file-uploaded {}", id)),
+            Ok(Some(Command::Emit(EmitEvent::FileUploaded(id))))
+        );
+    }
+
+    #[test]
+    fn test_password_commands_are_sensitive() {
+        let verify = Command::parse("verify alice hunter22").unwrap().unwrap();
+        assert!(verify.is_sensitive());
+        assert!(!Command::Users(None).is_sensitive());
+    }
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_parse_list_users() {
+        let options = ListUsers::parse(&args(
+            "--inactive --email-like %@example.com --created-after 2024-05-01 --format json --page 3 --per-page 50",
+        ))
+        .unwrap();
+        assert_eq!(options.filter.is_active, Some(false));
+        assert_eq!(options.filter.email_like.as_deref(), Some("%@example.com"));
+        assert_eq!(
+            options.filter.created_after.map(|time| time.to_rfc3339()),
+            Some("2024-05-01T00:00:00+00:00".to_string())
+        );
+        assert_eq!(options.format, ListFormat::Json);
+        assert_eq!(options.pagination, Pagination::page(3, 50));
+
+        let defaults = ListUsers::parse(&[]).unwrap();
+        assert_eq!(defaults.format, ListFormat::Table);
+        assert_eq!(defaults.pagination, Pagination::default());
+
+        assert!(ListUsers::parse(&args("--page 0")).is_err());
+        assert!(ListUsers::parse(&args("--created-after yesterday")).is_err());
+        assert!(ListUsers::parse(&args("--email-like")).is_err());
+        assert!(ListUsers::parse(&args("--format xml")).is_err());
+    }
+
+    #[test]
+    fn test_user_table() {
+        let user = UserResponse {
+            id: 12,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            email_verified: true,
+            is_active: true,
+            display_name: None,
+            bio: None,
+            profile_data: serde_json::json!({}),
+            created_at: "2024-05-01T09:30:00Z".parse().unwrap(),
+            roles: vec!["admin".to_string()],
+            email_undeliverable: None,
+        };
+        let page = Paginated::new(vec![user], 41, &Pagination::page(1, 20));
+        assert_eq!(
+            user_table(&page),
+            "\
+ID  USERNAME  EMAIL              ACTIVE  ROLES  CREATED
+12  alice     alice@example.com  yes     admin  2024-05-01 09:30
+Page 1 of 3 (41 users)"
+        );
+    }
+
+    #[test]
+    fn test_parse_delete_user() {
+        assert_eq!(
+            DeleteUser::parse(&args("alice")),
+            Ok(DeleteUser {
+                user: "alice".to_string(),
+                mode: DeleteMode::Soft,
+            })
+        );
+        assert_eq!(
+            DeleteUser::parse(&args("--hard 12 --yes")).map(|options| options.mode),
+            Ok(DeleteMode::Hard)
+        );
+        assert!(
+            DeleteUser::parse(&args("12 --hard"))
+                .unwrap_err()
+                .contains("--yes")
+        );
+        assert!(DeleteUser::parse(&args("--soft")).is_err());
+        assert!(DeleteUser::parse(&args("alice bob")).is_err());
+        assert!(DeleteUser::parse(&args("alice --force")).is_err());
+    }
+
+    #[test]
+    fn test_export_users() {
+        let options =
+            ExportUsers::parse(&args("--role admin --format json --output users.json")).unwrap();
+        assert_eq!(options.filter.role.as_deref(), Some("admin"));
+        assert_eq!(options.format, ExportFormat::Json);
+        assert_eq!(options.output.as_deref(), Some("users.json"));
+        assert!(ExportUsers::parse(&args("--format xml")).is_err());
+        assert!(ExportUsers::parse(&args("--page 2")).is_err());
+
+        let created = DateTime::parse_from_rfc3339("2024-05-01T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let user = ExportedUser {
+            id: 7,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            email_verified: true,
+            is_active: true,
+            roles: vec!["admin".to_string(), "editor".to_string()],
+            last_login: None,
+            created_at: created,
+            updated_at: created,
+        };
+        let csv = format_export(&[user], ExportFormat::Csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "id,username,email,email_verified,is_active,roles,last_login,created_at,updated_at"
+        );
+        assert_eq!(
+            lines[1],
+            "7,alice,alice@example.com,true,true,admin;editor,,2024-05-01T12:00:00+00:00,2024-05-01T12:00:00+00:00"
+        );
+        assert!(!csv.contains("password"));
+    }
+}
@@ -7,21 +7,33 @@
 //! - Modular web server split across modules in `src/`
 //! - Axum 0.7 framework with Tokio async runtime
 //! - Tera 1.19 template engine for dynamic HTML generation
-//! - All API responses use consistent JSON structure with message, status, timestamp
+//! - All API responses use the versioned `Envelope<T>` from `models.rs`
 //!
 //! ## Response Pattern
 //! ```rust,ignore
-//! struct ApiResponse {
-//!     message: String,
-//!     status: String,     // "success" or "error"
-//!     timestamp: String,  // chrono::Utc::now().to_rfc3339()
+//! struct Envelope<T> {
+//!     api_version: u32,
+//!     status: String,           // "success" or "error"
+//!     message: Option<String>,
+//!     data: Option<T>,
+//!     meta: ResponseMeta,       // request_id, server_time, pagination
+//!     errors: Vec<ErrorDetail>, // code, message, field
 //! }
+//!
+//! // Build with helpers rather than json! maps
+//! Envelope::success(items).with_request_id(request_id)
 //! ```
 //!
 //! ## Handler Pattern
 //! ```rust,ignore
-//! async fn handler() -> Result<Json<ApiResponse>, (StatusCode, String)> {
-//!     // Implementation with proper error handling
+//! async fn handler() -> AppResult<Json<Envelope<T>>> {
+//!     // `?` converts sqlx/tera/argon2 errors into `AppError`, which renders
+//!     // the standard JSON error envelope with the right status code
+//! }
+//!
+//! // Per-request user, locale, and services come from the request scope
+//! async fn page(scope: RequestScope) -> AppResult<Html<String>> {
+//!     let user = scope.user().await;
 //! }
 //! ```
 //!
@@ -29,24 +41,22 @@
 //! - GET / -> HTML welcome page
 //! - GET /health -> JSON health check
 //! - GET /api/hello -> JSON greeting
+//! - GET /api/items, /api/categories -> paginated lists (`Pagination` extractor)
+//! - /api/admin/users -> user management, guarded by the `AdminUser` extractor
 //! - GET /static/* -> Static file serving
 //! - Fallback -> 404 JSON response
 //!
 //! ## Template Engine (Tera)
 //! ```rust,ignore
 //! // Template handler pattern
-//! pub async fn serve_template() -> Result<Html<String>, (StatusCode, String)> {
+//! pub async fn serve_template() -> AppResult<Html<String>> {
 //!     let tera = get_templates();
 //!     let mut context = Context::new();
 //!     
 //!     context.insert("title", "Page Title");
 //!     context.insert("version", env!("CARGO_PKG_VERSION"));
 //!     
-//!     let rendered = tera.render("template.html", &context)
-//!         .map_err(|err| {
-//!             eprintln!("Template error: {}", err);
-//!             (StatusCode::INTERNAL_SERVER_ERROR, "Failed to render".to_string())
-//!         })?;
+//!     let rendered = tera.render("template.html", &context)?;
 //!     
 //!     Ok(Html(rendered))
 //! }
@@ -75,4 +85,4 @@
 //! - Static files in `static/` directory
 //! - Initialize templates before starting server
 //! - Always run `cargo fmt` before commits
-//! - Use `eprintln!` for error logging
+//! - Use `output::error` and `output::warn` for problems worth logging
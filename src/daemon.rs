@@ -0,0 +1,492 @@
+//! # Run Modes
+//!
+//! How the server binary runs. By default it stays in the foreground and
+//! logs to the terminal, which is what containers and process supervisors
+//! expect. For hosts without either it can detach itself:
+//!
+//! ```text
+//! axum-base                                                  # foreground
+//! axum-base --daemon [--pid-file PATH] [--log-file PATH]     # Unix
+//! axum-base service install [--log-file PATH]                # Windows
+//! axum-base service uninstall
+//! ```
+//!
+//! On Unix `--daemon` forks into the background, writes its process ID to
+//! the pid file, and appends stdout and stderr to the log file. The pid file
+//! stays locked while the server runs, so a second daemon started with the
+//! same file refuses to start; `kill $(cat axum-base.pid)` stops it
+//! gracefully like Ctrl+C. The file is removed on a clean shutdown.
+//!
+//! On Windows `service install` registers the binary with the service
+//! manager to start at boot, recording the current directory (for `.env`,
+//! `templates/`, and `static/`) and the log file. The service manager then
+//! runs it as `service run`, and stopping the service shuts the server down
+//! gracefully.
+
+use std::env;
+use std::future::Future;
+use std::path::PathBuf;
+
+use crate::cli::{CliError, CliResult};
+use crate::config::AppConfig;
+use crate::plugins::PluginRegistry;
+use crate::server::start_server_until;
+
+/// Pid file of `--daemon` unless `--pid-file` is given
+pub const DEFAULT_PID_FILE: &str = "axum-base.pid";
+
+/// Log file of `--daemon` and the service unless `--log-file` is given
+pub const DEFAULT_LOG_FILE: &str = "axum-base.log";
+
+/// Name the Windows service is registered under
+#[allow(dead_code)]
+pub const SERVICE_NAME: &str = "axum-base";
+
+/// How to run, from the command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunMode {
+    /// Stay attached to the terminal
+    Foreground,
+    /// Fork into the background (Unix)
+    Daemon {
+        pid_file: PathBuf,
+        log_file: PathBuf,
+    },
+    /// Register the Windows service
+    ServiceInstall { log_file: PathBuf },
+    /// Stop and remove the Windows service
+    ServiceUninstall,
+    /// Run under the Windows service manager, from `dir`
+    ServiceRun { dir: PathBuf, log_file: PathBuf },
+}
+
+impl RunMode {
+    /// Parse the arguments after the program name
+    pub fn parse(program: &str, args: &[String]) -> CliResult<Self> {
+        let mut args = args.iter().map(String::as_str);
+        let mode = match args.next() {
+            None => return Ok(RunMode::Foreground),
+            Some("--daemon") => "--daemon",
+            Some("service") => match args.next() {
+                Some("install") => "install",
+                Some("uninstall") => "uninstall",
+                Some("run") => "run",
+                _ => return Err(usage(program)),
+            },
+            Some(_) => return Err(usage(program)),
+        };
+
+        let mut pid_file = None;
+        let mut log_file = None;
+        let mut dir = None;
+        while let Some(flag) = args.next() {
+            let slot = match flag {
+                "--pid-file" if mode == "--daemon" => &mut pid_file,
+                "--log-file" if mode != "uninstall" => &mut log_file,
+                "--dir" if mode == "run" => &mut dir,
+                _ => return Err(usage(program)),
+            };
+            let Some(value) = args.next() else {
+                return Err(usage(program));
+            };
+            *slot = Some(PathBuf::from(value));
+        }
+
+        let log_file = log_file.unwrap_or_else(|| PathBuf::from(DEFAULT_LOG_FILE));
+        Ok(match mode {
+            "--daemon" => RunMode::Daemon {
+                pid_file: pid_file.unwrap_or_else(|| PathBuf::from(DEFAULT_PID_FILE)),
+                log_file,
+            },
+            "install" => RunMode::ServiceInstall { log_file },
+            "uninstall" => RunMode::ServiceUninstall,
+            _ => RunMode::ServiceRun {
+                dir: dir.ok_or_else(|| usage(program))?,
+                log_file,
+            },
+        })
+    }
+}
+
+fn usage(program: &str) -> CliError {
+    CliError::Usage(format!(
+        "Usage: {program} [--no-color] [--quiet] [--daemon [--pid-file PATH] [--log-file PATH]]
+       {program} service install [--log-file PATH]
+       {program} service uninstall
+
+Without arguments the server runs in the foreground.
+
+Options:
+  --no-color         Plain output even on a terminal (also NO_COLOR=1)
+  --quiet, -q        Print only warnings and errors
+  --daemon           Run in the background (Unix)
+  --pid-file PATH    Pid file of the daemon (default {DEFAULT_PID_FILE})
+  --log-file PATH    Append output to PATH (default {DEFAULT_LOG_FILE})
+  service install    Register the server as a Windows service started at boot
+  service uninstall  Stop and remove the Windows service",
+    ))
+}
+
+/// Run the server in `mode` with `plugins` installed
+pub fn run(mode: RunMode, plugins: PluginRegistry) -> CliResult<()> {
+    match mode {
+        RunMode::Foreground => {
+            // Load environment variables from .env file
+            dotenvy::dotenv().ok();
+            serve(plugins, std::future::pending())
+        }
+        #[cfg(unix)]
+        RunMode::Daemon { pid_file, log_file } => unix::run(&pid_file, &log_file, plugins),
+        #[cfg(windows)]
+        RunMode::ServiceInstall { log_file } => windows::install(&log_file),
+        #[cfg(windows)]
+        RunMode::ServiceUninstall => windows::uninstall(),
+        #[cfg(windows)]
+        RunMode::ServiceRun { dir, log_file } => windows::run(dir, log_file, plugins),
+        #[cfg(not(unix))]
+        RunMode::Daemon { .. } => Err(CliError::Invalid(
+            "--daemon is only available on Unix; install a service instead".to_string(),
+        )),
+        #[cfg(not(windows))]
+        RunMode::ServiceInstall { .. } | RunMode::ServiceUninstall | RunMode::ServiceRun { .. } => {
+            Err(CliError::Invalid(
+                "Services are only available on Windows; use --daemon instead".to_string(),
+            ))
+        }
+    }
+}
+
+/// Run the server on a new runtime until it stops, or until `stop` resolves
+fn serve<F>(plugins: PluginRegistry, stop: F) -> CliResult<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    // The runtime is sized before the server loads the configuration itself
+    let worker_threads = AppConfig::load()?.server.worker_threads();
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(worker_threads)
+        .enable_all()
+        .build()?;
+    runtime.block_on(start_server_until(plugins, stop));
+    Ok(())
+}
+
+/// `path` relative to the current directory, so it still points to the same
+/// file after the working directory changes
+fn absolute(path: &std::path::Path) -> CliResult<PathBuf> {
+    Ok(env::current_dir()?.join(path))
+}
+
+#[cfg(unix)]
+mod unix {
+    use daemonize::Daemonize;
+    use std::fs::OpenOptions;
+    use std::path::Path;
+
+    use super::{absolute, serve};
+    use crate::cli::{CliError, CliResult};
+    use crate::output;
+    use crate::plugins::PluginRegistry;
+
+    /// Fork into the background, then serve until stopped
+    pub fn run(pid_file: &Path, log_file: &Path, plugins: PluginRegistry) -> CliResult<()> {
+        let pid_file = absolute(pid_file)?;
+        let log_file = absolute(log_file)?;
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_file)
+            .map_err(|err| {
+                CliError::Invalid(format!("Cannot open {}: {}", log_file.display(), err))
+            })?;
+
+        output::info(format!(
+            "Running in the background (pid file {}, log {})",
+            pid_file.display(),
+            log_file.display()
+        ));
+        // The parent exits here; the daemon carries on below
+        Daemonize::new()
+            .pid_file(&pid_file)
+            .working_directory(std::env::current_dir()?)
+            .umask(0o027)
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .start()
+            .map_err(|err| {
+                CliError::Failure(format!(
+                    "Failed to start the daemon (is one already running with {}?): {}",
+                    pid_file.display(),
+                    err
+                ))
+            })?;
+
+        // Load environment variables from .env file
+        dotenvy::dotenv().ok();
+        let result = serve(plugins, std::future::pending());
+        let _ = std::fs::remove_file(&pid_file);
+        result
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::ffi::OsString;
+    use std::fs::OpenOptions;
+    use std::os::windows::io::IntoRawHandle;
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    use windows_service::service::{
+        ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+        ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+    use windows_service::{define_windows_service, service_dispatcher};
+    use windows_sys::Win32::System::Console::{STD_ERROR_HANDLE, STD_OUTPUT_HANDLE, SetStdHandle};
+
+    use super::{SERVICE_NAME, absolute, serve};
+    use crate::cli::{CliError, CliResult};
+    use crate::output;
+    use crate::plugins::PluginRegistry;
+
+    /// What `service run` hands to the service thread, which the dispatcher
+    /// starts without arguments of ours
+    struct Service {
+        dir: PathBuf,
+        log_file: PathBuf,
+        plugins: PluginRegistry,
+    }
+
+    static SERVICE: Mutex<Option<Service>> = Mutex::new(None);
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// `service install`
+    pub fn install(log_file: &Path) -> CliResult<()> {
+        let dir = std::env::current_dir()?;
+        let log_file = absolute(log_file)?;
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )
+        .map_err(service_error)?;
+        let info = ServiceInfo {
+            name: OsString::from(SERVICE_NAME),
+            display_name: OsString::from("Axum Base"),
+            service_type: ServiceType::OWN_PROCESS,
+            start_type: ServiceStartType::AutoStart,
+            error_control: ServiceErrorControl::Normal,
+            executable_path: std::env::current_exe()?,
+            launch_arguments: vec![
+                OsString::from("service"),
+                OsString::from("run"),
+                OsString::from("--dir"),
+                dir.clone().into_os_string(),
+                OsString::from("--log-file"),
+                log_file.clone().into_os_string(),
+            ],
+            dependencies: Vec::new(),
+            account_name: None,
+            account_password: None,
+        };
+        let service = manager
+            .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+            .map_err(service_error)?;
+        service
+            .set_description("Axum Base web server")
+            .map_err(service_error)?;
+
+        output::success(format!(
+            "Installed service '{}' (directory {}, log {})",
+            SERVICE_NAME,
+            dir.display(),
+            log_file.display()
+        ));
+        output::detail(format!("Start it with: sc start {}", SERVICE_NAME));
+        Ok(())
+    }
+
+    /// `service uninstall`
+    pub fn uninstall() -> CliResult<()> {
+        let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+            .map_err(service_error)?;
+        let service = manager
+            .open_service(
+                SERVICE_NAME,
+                ServiceAccess::QUERY_STATUS | ServiceAccess::STOP | ServiceAccess::DELETE,
+            )
+            .map_err(service_error)?;
+        if service.query_status().map_err(service_error)?.current_state != ServiceState::Stopped {
+            service.stop().map_err(service_error)?;
+        }
+        service.delete().map_err(service_error)?;
+        output::success(format!("Removed service '{}'", SERVICE_NAME));
+        Ok(())
+    }
+
+    /// `service run`: hand control to the service manager, which calls
+    /// back into `service_main` and returns once the service has stopped
+    pub fn run(dir: PathBuf, log_file: PathBuf, plugins: PluginRegistry) -> CliResult<()> {
+        *SERVICE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Service {
+            dir,
+            log_file,
+            plugins,
+        });
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(service_error)
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        let Some(service) = SERVICE.lock().unwrap_or_else(|e| e.into_inner()).take() else {
+            return;
+        };
+        if let Err(err) = run_service(service) {
+            output::error(err);
+        }
+    }
+
+    fn run_service(service: Service) -> CliResult<()> {
+        std::env::set_current_dir(&service.dir)?;
+        redirect_output(&service.log_file)?;
+        // Load environment variables from .env file
+        dotenvy::dotenv().ok();
+
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+        let mut stop_tx = Some(stop_tx);
+        let status =
+            service_control_handler::register(SERVICE_NAME, move |control| match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    if let Some(stop_tx) = stop_tx.take() {
+                        let _ = stop_tx.send(());
+                    }
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            })
+            .map_err(service_error)?;
+
+        let report = |state, controls_accepted| {
+            status.set_service_status(ServiceStatus {
+                service_type: ServiceType::OWN_PROCESS,
+                current_state: state,
+                controls_accepted,
+                exit_code: ServiceExitCode::Win32(0),
+                checkpoint: 0,
+                wait_hint: Duration::default(),
+                process_id: None,
+            })
+        };
+        report(
+            ServiceState::Running,
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        )
+        .map_err(service_error)?;
+
+        let result = serve(service.plugins, async {
+            let _ = stop_rx.await;
+        });
+        report(ServiceState::Stopped, ServiceControlAccept::empty()).map_err(service_error)?;
+        result
+    }
+
+    /// Send stdout and stderr to `log_file`, as a service has no console
+    fn redirect_output(log_file: &Path) -> CliResult<()> {
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)?;
+        let err = log.try_clone()?;
+        // The handles stay open for the life of the process
+        unsafe {
+            SetStdHandle(STD_OUTPUT_HANDLE, log.into_raw_handle());
+            SetStdHandle(STD_ERROR_HANDLE, err.into_raw_handle());
+        }
+        Ok(())
+    }
+
+    fn service_error(err: windows_service::Error) -> CliError {
+        CliError::Failure(format!("Service manager: {}", err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> CliResult<RunMode> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        RunMode::parse("axum-base", &args)
+    }
+
+    #[test]
+    fn test_parse_run_modes() {
+        assert_eq!(parse(&[]).unwrap(), RunMode::Foreground);
+        assert_eq!(
+            parse(&["--daemon"]).unwrap(),
+            RunMode::Daemon {
+                pid_file: PathBuf::from(DEFAULT_PID_FILE),
+                log_file: PathBuf::from(DEFAULT_LOG_FILE),
+            }
+        );
+        assert_eq!(
+            parse(&[
+                "--daemon",
+                "--log-file",
+                "/var/log/app.log",
+                "--pid-file",
+                "app.pid"
+            ])
+            .unwrap(),
+            RunMode::Daemon {
+                pid_file: PathBuf::from("app.pid"),
+                log_file: PathBuf::from("/var/log/app.log"),
+            }
+        );
+        assert_eq!(
+            parse(&["service", "install"]).unwrap(),
+            RunMode::ServiceInstall {
+                log_file: PathBuf::from(DEFAULT_LOG_FILE),
+            }
+        );
+        assert_eq!(
+            parse(&["service", "uninstall"]).unwrap(),
+            RunMode::ServiceUninstall
+        );
+        assert_eq!(
+            parse(&[
+                "service",
+                "run",
+                "--dir",
+                "C:\\app",
+                "--log-file",
+                "C:\\app\\app.log"
+            ])
+            .unwrap(),
+            RunMode::ServiceRun {
+                dir: PathBuf::from("C:\\app"),
+                log_file: PathBuf::from("C:\\app\\app.log"),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_arguments() {
+        for args in [
+            &["--daemonize"][..],
+            &["--daemon", "--pid-file"],
+            &["--daemon", "--dir", "/srv"],
+            &["--pid-file", "app.pid"],
+            &["service"],
+            &["service", "start"],
+            &["service", "install", "--pid-file", "app.pid"],
+            &["service", "uninstall", "--log-file", "app.log"],
+            &["service", "run"],
+        ] {
+            let err = parse(args).unwrap_err();
+            assert_eq!(err.exit_code(), 64, "{:?}", args);
+        }
+    }
+}
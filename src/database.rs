@@ -2,48 +2,119 @@
 //!
 //! PostgreSQL database connection and pool management using SQLx.
 
+use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
 use sqlx::{PgPool, Row};
-use std::env;
 use std::time::Duration;
 
-/// Initialize the database connection pool
-pub async fn init_pool() -> Result<PgPool, sqlx::Error> {
-    init_pool_with_url(None).await
+use crate::config::DatabaseConfig;
+use crate::migrations::{self, MigrationError};
+use crate::output;
+use crate::plugins::PluginRegistry;
+
+/// Initialize the database connection pool from configuration
+pub async fn init_pool(config: &DatabaseConfig) -> Result<PgPool, sqlx::Error> {
+    output::info("Connecting to PostgreSQL database...");
+    let pool = connect(config, &config.url).await?;
+    output::success("Database connection pool established");
+    Ok(pool)
 }
 
-/// Initialize the database connection pool with optional URL override
-pub async fn init_pool_with_url(
-    database_url_override: Option<&str>,
-) -> Result<PgPool, sqlx::Error> {
-    let database_url = match database_url_override {
-        Some(url) => url.to_string(),
-        None => {
-            env::var("DATABASE_URL").expect("DATABASE_URL must be set in environment or .env file")
-        }
+/// Initialize the read replica pool, if `DATABASE_READ_URL` is set; it is
+/// sized and timed out like the primary
+pub async fn init_read_pool(config: &DatabaseConfig) -> Result<Option<PgPool>, sqlx::Error> {
+    let Some(url) = &config.read_url else {
+        return Ok(None);
     };
+    output::info("Connecting to the PostgreSQL read replica...");
+    let pool = connect(config, url).await?;
+    output::success("Read replica pool established");
+    Ok(Some(pool))
+}
 
-    println!("🗄️  Connecting to PostgreSQL database...");
+async fn connect(config: &DatabaseConfig, url: &str) -> Result<PgPool, sqlx::Error> {
+    let mut options: PgConnectOptions = url.parse()?;
+    if config.statement_timeout_ms > 0 {
+        options = options.options([("statement_timeout", config.statement_timeout_ms)]);
+    }
 
-    let pool = sqlx::postgres::PgPoolOptions::new()
-        .max_connections(20)
-        .min_connections(5)
-        .acquire_timeout(Duration::from_secs(8))
-        .idle_timeout(Duration::from_secs(8))
-        .max_lifetime(Duration::from_secs(8))
-        .connect(&database_url)
-        .await?;
+    PgPoolOptions::new()
+        .max_connections(config.max_connections())
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+        .idle_timeout(seconds_or_never(config.idle_timeout_secs))
+        .max_lifetime(seconds_or_never(config.max_lifetime_secs))
+        .connect_with(options)
+        .await
+}
 
-    println!("✅ Database connection pool established");
-    Ok(pool)
+/// Initialize the database connection pool for an explicit URL
+#[allow(dead_code)]
+pub async fn init_pool_with_url(database_url: &str) -> Result<PgPool, sqlx::Error> {
+    init_pool(&DatabaseConfig {
+        url: database_url.to_string(),
+        ..DatabaseConfig::default()
+    })
+    .await
+}
+
+/// The primary pool, for writes and reads that must see them, and an
+/// optional read replica for the rest
+///
+/// Services keep taking a `&PgPool`; callers choose one with [`Db::read`] or
+/// [`Db::write`]. Reads go to the primary when no replica is configured. A
+/// replica lags the primary, so reads right after a write in the same flow
+/// (sign-in, saving a form and showing it) belong on [`Db::write`].
+#[derive(Clone, Debug)]
+pub struct Db {
+    primary: PgPool,
+    replica: Option<PgPool>,
+}
+
+impl Db {
+    /// Route everything to `primary`
+    pub fn new(primary: PgPool) -> Self {
+        Self {
+            primary,
+            replica: None,
+        }
+    }
+
+    /// Route read-only queries to `replica`
+    pub fn with_replica(mut self, replica: PgPool) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Pool for read-only queries that tolerate replication lag
+    pub fn read(&self) -> &PgPool {
+        self.replica.as_ref().unwrap_or(&self.primary)
+    }
+
+    /// Pool for writes, and reads that must see them
+    pub fn write(&self) -> &PgPool {
+        &self.primary
+    }
+
+    /// Whether a read replica is configured
+    pub fn has_replica(&self) -> bool {
+        self.replica.is_some()
+    }
+}
+
+/// A pool timeout in seconds, where 0 turns it off
+fn seconds_or_never(secs: u64) -> Option<Duration> {
+    (secs > 0).then(|| Duration::from_secs(secs))
 }
 
-/// Run database migrations
-pub async fn run_migrations(pool: &PgPool) -> Result<(), sqlx::migrate::MigrateError> {
-    println!("🔄 Running database migrations...");
+/// Run the core database migrations, then those of plugins and the app,
+/// each in its own namespace
+pub async fn run_migrations(pool: &PgPool, plugins: &PluginRegistry) -> Result<(), MigrationError> {
+    output::info("Running database migrations...");
 
     sqlx::migrate!("./migrations").run(pool).await?;
+    migrations::run(pool, plugins.migrations()).await?;
 
-    println!("✅ Database migrations completed");
+    output::success("Database migrations completed");
     Ok(())
 }
 
@@ -87,3 +158,27 @@ pub struct DatabaseInfo {
     pub pool_connections: u32,
     pub idle_connections: usize,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lazy_pool(database: &str) -> PgPool {
+        PgPool::connect_lazy(&format!("postgres://localhost/{}", database)).unwrap()
+    }
+
+    fn database(pool: &PgPool) -> Option<String> {
+        pool.connect_options().get_database().map(str::to_string)
+    }
+
+    #[tokio::test]
+    async fn test_reads_go_to_the_replica_when_configured() {
+        let db = Db::new(lazy_pool("primary"));
+        assert_eq!(database(db.read()).as_deref(), Some("primary"));
+        assert!(!db.has_replica());
+
+        let db = db.with_replica(lazy_pool("replica"));
+        assert_eq!(database(db.read()).as_deref(), Some("replica"));
+        assert_eq!(database(db.write()).as_deref(), Some("primary"));
+    }
+}
@@ -0,0 +1,226 @@
+//! # Database Metrics
+//!
+//! Service functions start a [`QueryTimer`] named after themselves
+//! (`ItemService::list_items`), which records how long the call took in
+//! `db_query_duration_seconds` when it ends. The time includes waiting for a
+//! pooled connection. A call that takes `DATABASE_SLOW_QUERY_MS` or longer
+//! (500 by default; 0 turns this off) is counted in `db_slow_queries_total`
+//! and logged as a warning.
+//!
+//! Timings go to the [`DbMetrics`] of the app whose request, job, or
+//! scheduled task is running, scoped to it by the [`track`] middleware and
+//! [`scope`]. Elsewhere, such as in CLI commands, calls are not timed.
+//!
+//! `db_pool_connections` gives each pool's idle and in-use connections as
+//! of the `/metrics` scrape; see [`sample_pools`].
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::PgPool;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::config::DatabaseConfig;
+use crate::metrics::{CounterVec, GaugeVec, HistogramVec, MetricSource};
+use crate::output;
+use crate::state::AppState;
+
+tokio::task_local! {
+    static CURRENT: Recorder;
+}
+
+/// Where the current task's timings go
+#[derive(Clone)]
+struct Recorder {
+    metrics: Arc<DbMetrics>,
+    /// Calls this slow or slower are logged; `None` logs none
+    slow: Option<Duration>,
+}
+
+impl Recorder {
+    fn new(state: &AppState) -> Self {
+        Self {
+            metrics: state.metrics.source::<DbMetrics>(),
+            slow: slow_threshold(&state.config.database),
+        }
+    }
+
+    fn record(&self, name: &'static str, elapsed: Duration) {
+        self.metrics.queries.observe(&[name], elapsed.as_secs_f64());
+        if self.slow.is_some_and(|slow| elapsed >= slow) {
+            self.metrics.slow.inc(&[name]);
+            output::warn(format!(
+                "Slow query: {} took {} ms",
+                name,
+                elapsed.as_millis()
+            ));
+        }
+    }
+}
+
+/// The configured slow query threshold, if logging is on
+fn slow_threshold(config: &DatabaseConfig) -> Option<Duration> {
+    (config.slow_query_ms > 0).then(|| Duration::from_millis(config.slow_query_ms))
+}
+
+/// Query timings and pool use, served at `/metrics`
+#[derive(Debug)]
+pub struct DbMetrics {
+    /// Service function calls by function
+    pub queries: HistogramVec,
+    /// Calls over the slow query threshold by function
+    pub slow: CounterVec,
+    /// Connections by pool and state, `idle` or `in_use`
+    pub connections: GaugeVec,
+}
+
+impl Default for DbMetrics {
+    fn default() -> Self {
+        Self {
+            queries: HistogramVec::new(
+                "db_query_duration_seconds",
+                "Service function query time, waiting for a connection included",
+                &["query"],
+            ),
+            slow: CounterVec::new(
+                "db_slow_queries_total",
+                "Service function calls over DATABASE_SLOW_QUERY_MS",
+                &["query"],
+            ),
+            connections: GaugeVec::new(
+                "db_pool_connections",
+                "Open pool connections by state",
+                &["pool", "state"],
+            ),
+        }
+    }
+}
+
+impl MetricSource for DbMetrics {
+    fn render(&self, out: &mut String) {
+        self.queries.render(out);
+        self.slow.render(out);
+        self.connections.render(out);
+    }
+}
+
+/// Times a service function call from [`start`](Self::start) until it is
+/// dropped, when run inside [`scope`]
+#[must_use = "the call is timed until the timer is dropped"]
+pub struct QueryTimer {
+    started: Option<(Recorder, Instant)>,
+    name: &'static str,
+}
+
+impl QueryTimer {
+    /// Start timing the call `name`
+    pub fn start(name: &'static str) -> Self {
+        Self {
+            started: CURRENT
+                .try_with(Recorder::clone)
+                .ok()
+                .map(|recorder| (recorder, Instant::now())),
+            name,
+        }
+    }
+}
+
+impl Drop for QueryTimer {
+    fn drop(&mut self) {
+        if let Some((recorder, started)) = &self.started {
+            recorder.record(self.name, started.elapsed());
+        }
+    }
+}
+
+/// Await `future` with the [`QueryTimer`]s in it recorded in `state`'s
+/// metrics
+pub async fn scope<F: Future>(state: &AppState, future: F) -> F::Output {
+    CURRENT.scope(Recorder::new(state), future).await
+}
+
+/// Middleware recording the request's query timings; see [`scope`]
+pub async fn track(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    scope(&state, next.run(request)).await
+}
+
+/// Set `db_pool_connections` from each pool's current size and idle count
+pub fn sample_pools(state: &AppState) {
+    let metrics = state.metrics.source::<DbMetrics>();
+    sample_pool(&metrics, "primary", state.db.write());
+    if state.db.has_replica() {
+        sample_pool(&metrics, "replica", state.db.read());
+    }
+}
+
+fn sample_pool(metrics: &DbMetrics, name: &str, pool: &PgPool) {
+    let idle = pool.num_idle() as f64;
+    metrics.connections.set(&[name, "idle"], idle);
+    metrics
+        .connections
+        .set(&[name, "in_use"], (pool.size() as f64 - idle).max(0.0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recorder(slow: Option<Duration>) -> Recorder {
+        Recorder {
+            metrics: Arc::new(DbMetrics::default()),
+            slow,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_timer_records_inside_a_scope() {
+        let recorder = recorder(None);
+        let metrics = recorder.metrics.clone();
+
+        CURRENT
+            .scope(recorder, async {
+                let _timer = QueryTimer::start("ItemService::list_items");
+            })
+            .await;
+
+        assert_eq!(metrics.queries.count(&["ItemService::list_items"]), 1);
+        assert_eq!(metrics.slow.get(&["ItemService::list_items"]), 0);
+    }
+
+    #[test]
+    fn test_timer_outside_a_scope_records_nothing() {
+        let timer = QueryTimer::start("ItemService::list_items");
+        assert!(timer.started.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_slow_calls_are_counted() {
+        let recorder = recorder(Some(Duration::from_millis(5)));
+        let metrics = recorder.metrics.clone();
+
+        CURRENT
+            .scope(recorder, async {
+                {
+                    let _timer = QueryTimer::start("JobService::claim");
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                let _timer = QueryTimer::start("JobService::complete");
+            })
+            .await;
+
+        assert_eq!(metrics.slow.get(&["JobService::claim"]), 1);
+        assert_eq!(metrics.slow.get(&["JobService::complete"]), 0);
+    }
+
+    #[test]
+    fn test_zero_threshold_logs_nothing() {
+        let mut config = DatabaseConfig::default();
+        assert_eq!(slow_threshold(&config), Some(Duration::from_millis(500)));
+        config.slow_query_ms = 0;
+        assert_eq!(slow_threshold(&config), None);
+    }
+}
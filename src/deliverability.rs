@@ -0,0 +1,237 @@
+//! # Mail Deliverability
+//!
+//! DNS checks for the sending domain, run by `admin mail check-dns`. Mailbox
+//! providers judge mail by three TXT records: SPF at the domain lists the
+//! servers allowed to send for it, DKIM at `<selector>._domainkey.<domain>`
+//! publishes the key that verifies signatures, and DMARC at `_dmarc.<domain>`
+//! says what to do with mail failing both. Each check reads the records and
+//! reports whether they match the mail configuration.
+
+use std::fmt;
+
+use crate::config::MailConfig;
+use crate::domains::txt_records;
+
+/// Outcome of one check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Pass,
+    /// Works, but mail may still be filtered
+    Warn,
+    Fail,
+}
+
+/// One DNS record checked
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// `SPF`, `DKIM`, or `DMARC`
+    pub name: &'static str,
+    /// DNS name that was looked up
+    pub record: String,
+    pub status: Status,
+    pub message: String,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let icon = match self.status {
+            Status::Pass => "✅",
+            Status::Warn => "⚠️ ",
+            Status::Fail => "❌",
+        };
+        write!(
+            f,
+            "{} {} ({}): {}",
+            icon, self.name, self.record, self.message
+        )
+    }
+}
+
+/// Look up and check the SPF, DKIM, and DMARC records for `domain`
+pub async fn check_domain(config: &MailConfig, domain: &str) -> Result<Vec<Check>, String> {
+    let mut checks = Vec::new();
+
+    let (status, message) = check_spf(&txt_records(domain).await?);
+    checks.push(Check {
+        name: "SPF",
+        record: domain.to_string(),
+        status,
+        message,
+    });
+
+    match &config.dkim_selector {
+        Some(selector) => {
+            let record = format!("{}._domainkey.{}", selector, domain);
+            let (status, message) =
+                check_dkim(&txt_records(&record).await?, &config.dkim_algorithm);
+            checks.push(Check {
+                name: "DKIM",
+                record,
+                status,
+                message,
+            });
+        }
+        None => checks.push(Check {
+            name: "DKIM",
+            record: format!("<selector>._domainkey.{}", domain),
+            status: Status::Warn,
+            message: "MAIL_DKIM_SELECTOR is not set, so mail is not signed".to_string(),
+        }),
+    }
+
+    let record = format!("_dmarc.{}", domain);
+    let (status, message) = check_dmarc(&txt_records(&record).await?);
+    checks.push(Check {
+        name: "DMARC",
+        record,
+        status,
+        message,
+    });
+
+    Ok(checks)
+}
+
+/// A domain needs exactly one `v=spf1` record, and it must not allow everyone
+pub fn check_spf(records: &[String]) -> (Status, String) {
+    let spf: Vec<&String> = records
+        .iter()
+        .filter(|record| first_term(record).eq_ignore_ascii_case("v=spf1"))
+        .collect();
+
+    match spf.as_slice() {
+        [] => (Status::Fail, "no v=spf1 record".to_string()),
+        [record] => {
+            let all = record
+                .split_whitespace()
+                .find(|term| term.to_ascii_lowercase().ends_with("all"));
+            match all.map(str::to_ascii_lowercase).as_deref() {
+                Some("+all") | Some("all") => (
+                    Status::Fail,
+                    format!("'{}' lets any server send as the domain", record),
+                ),
+                Some("-all") | Some("~all") => (Status::Pass, record.to_string()),
+                _ => (
+                    Status::Warn,
+                    format!("'{}' does not end in -all or ~all", record),
+                ),
+            }
+        }
+        _ => (
+            Status::Fail,
+            format!("{} v=spf1 records; receivers reject all but one", spf.len()),
+        ),
+    }
+}
+
+/// The key record must hold a public key of the configured algorithm
+pub fn check_dkim(records: &[String], algorithm: &str) -> (Status, String) {
+    let Some(record) = records
+        .iter()
+        .find(|record| tag(record, "p").is_some() || tag(record, "v") == Some("DKIM1"))
+    else {
+        return (Status::Fail, "no DKIM key record".to_string());
+    };
+
+    // `k=` defaults to rsa
+    let key_type = tag(record, "k").unwrap_or("rsa");
+    match tag(record, "p") {
+        None | Some("") => (
+            Status::Fail,
+            "the key is empty (p=), which revokes it".to_string(),
+        ),
+        Some(_) if !key_type.eq_ignore_ascii_case(algorithm) => (
+            Status::Fail,
+            format!(
+                "the key is {} but MAIL_DKIM_ALGORITHM is {}",
+                key_type, algorithm
+            ),
+        ),
+        Some(_) => (Status::Pass, format!("{} key published", key_type)),
+    }
+}
+
+/// The policy record must name a policy; `p=none` only monitors
+pub fn check_dmarc(records: &[String]) -> (Status, String) {
+    let Some(record) = records
+        .iter()
+        .find(|record| first_term(record).eq_ignore_ascii_case("v=DMARC1"))
+    else {
+        return (Status::Fail, "no v=DMARC1 record".to_string());
+    };
+
+    match tag(record, "p").map(str::to_ascii_lowercase).as_deref() {
+        Some("reject") | Some("quarantine") => (Status::Pass, record.to_string()),
+        Some("none") => (
+            Status::Warn,
+            "p=none only monitors; failing mail is still delivered".to_string(),
+        ),
+        _ => (Status::Fail, format!("'{}' has no valid p= policy", record)),
+    }
+}
+
+/// First whitespace- or semicolon-separated term of a record
+fn first_term(record: &str) -> &str {
+    record
+        .split(|c: char| c.is_whitespace() || c == ';')
+        .next()
+        .unwrap_or_default()
+}
+
+/// Value of a `name=value` tag in a `;`-separated record
+fn tag<'a>(record: &'a str, name: &str) -> Option<&'a str> {
+    record.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn test_check_spf() {
+        let pass = records(&[
+            "google-site-verification=x",
+            "v=spf1 include:_spf.example.net -all",
+        ]);
+        assert_eq!(check_spf(&pass).0, Status::Pass);
+        assert_eq!(check_spf(&records(&["v=spf1 +all"])).0, Status::Fail);
+        assert_eq!(check_spf(&records(&["v=spf1 mx ?all"])).0, Status::Warn);
+        assert_eq!(check_spf(&records(&[])).0, Status::Fail);
+        assert_eq!(
+            check_spf(&records(&["v=spf1 mx -all", "v=spf1 a -all"])).0,
+            Status::Fail
+        );
+    }
+
+    #[test]
+    fn test_check_dkim() {
+        let rsa = records(&["v=DKIM1; k=rsa; p=MIIBIjAN"]);
+        assert_eq!(check_dkim(&rsa, "rsa").0, Status::Pass);
+        assert_eq!(check_dkim(&rsa, "ed25519").0, Status::Fail);
+        assert_eq!(check_dkim(&records(&["p=MIIBIjAN"]), "rsa").0, Status::Pass);
+        assert_eq!(
+            check_dkim(&records(&["v=DKIM1; k=ed25519; p=11qYAYKx"]), "ed25519").0,
+            Status::Pass
+        );
+        assert_eq!(
+            check_dkim(&records(&["v=DKIM1; p="]), "rsa").0,
+            Status::Fail
+        );
+        assert_eq!(check_dkim(&records(&[]), "rsa").0, Status::Fail);
+    }
+
+    #[test]
+    fn test_check_dmarc() {
+        let reject = records(&["v=DMARC1; p=reject; rua=mailto:dmarc@example.com"]);
+        assert_eq!(check_dmarc(&reject).0, Status::Pass);
+        assert_eq!(check_dmarc(&records(&["v=DMARC1; p=none"])).0, Status::Warn);
+        assert_eq!(check_dmarc(&records(&["v=DMARC1"])).0, Status::Fail);
+        assert_eq!(check_dmarc(&records(&[])).0, Status::Fail);
+    }
+}
@@ -0,0 +1,106 @@
+//! # Custom Domains
+//!
+//! Tenants can serve their pages from their own domains. A domain is attached
+//! unverified with a random token; the tenant publishes the token in a TXT
+//! record at [`VERIFICATION_PREFIX`]`.<domain>`, and once [`verify`] finds it
+//! the theming middleware routes requests for that `Host` to the tenant.
+//! Certificates for verified domains are issued by the `acme` feature.
+
+use hickory_resolver::TokioAsyncResolver;
+use hickory_resolver::error::ResolveErrorKind;
+
+use crate::theming::is_valid_slug;
+
+/// Label prepended to a domain to name its verification TXT record
+pub const VERIFICATION_PREFIX: &str = "_axum-base-verify";
+
+/// Name of the TXT record that proves ownership of `domain`
+pub fn verification_record(domain: &str) -> String {
+    format!("{}.{}", VERIFICATION_PREFIX, domain)
+}
+
+/// Random token the tenant publishes to prove ownership
+pub fn new_verification_token() -> String {
+    uuid::Uuid::new_v4().simple().to_string()
+}
+
+/// Lowercase a domain and check it is a plausible public host name
+pub fn normalize_domain(domain: &str) -> Result<String, String> {
+    let domain = domain.trim().trim_end_matches('.').to_ascii_lowercase();
+    let labels: Vec<&str> = domain.split('.').collect();
+
+    if domain.len() > 253 || labels.len() < 2 || !labels.iter().all(|label| is_valid_slug(label)) {
+        return Err(format!("'{}' is not a valid domain name", domain));
+    }
+    if labels
+        .last()
+        .is_some_and(|tld| tld.chars().all(|c| c.is_ascii_digit()))
+    {
+        return Err("IP addresses cannot be used as custom domains".to_string());
+    }
+    Ok(domain)
+}
+
+/// Whether any TXT record value equals the token
+pub fn records_match<'a>(records: impl IntoIterator<Item = &'a str>, token: &str) -> bool {
+    records.into_iter().any(|record| record.trim() == token)
+}
+
+/// Look up the domain's verification record and check it holds the token
+pub async fn verify(domain: &str, token: &str) -> Result<bool, String> {
+    let records = txt_records(&verification_record(domain)).await?;
+    Ok(records_match(records.iter().map(String::as_str), token))
+}
+
+/// TXT record values at `name`, each joined from its character strings;
+/// empty when there are none
+pub async fn txt_records(name: &str) -> Result<Vec<String>, String> {
+    let resolver = TokioAsyncResolver::tokio_from_system_conf()
+        .map_err(|e| format!("DNS resolver unavailable: {}", e))?;
+
+    let lookup = match resolver.txt_lookup(name).await {
+        Ok(lookup) => lookup,
+        Err(e) if matches!(e.kind(), ResolveErrorKind::NoRecordsFound { .. }) => {
+            return Ok(Vec::new());
+        }
+        Err(e) => return Err(format!("DNS lookup failed: {}", e)),
+    };
+
+    Ok(lookup
+        .iter()
+        .map(|txt| {
+            txt.iter()
+                .map(|part| String::from_utf8_lossy(part))
+                .collect::<String>()
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_domain() {
+        assert_eq!(
+            normalize_domain(" Shop.Example.COM. "),
+            Ok("shop.example.com".to_string())
+        );
+        assert!(normalize_domain("localhost").is_err());
+        assert!(normalize_domain("bad_label.example.com").is_err());
+        assert!(normalize_domain("10.0.0.1").is_err());
+        assert!(normalize_domain("example..com").is_err());
+    }
+
+    #[test]
+    fn test_records_match() {
+        let token = new_verification_token();
+        assert_eq!(token.len(), 32);
+        assert!(records_match(["v=spf1 -all", token.as_str()], &token));
+        assert!(!records_match(["v=spf1 -all"], &token));
+        assert_eq!(
+            verification_record("example.com"),
+            "_axum-base-verify.example.com"
+        );
+    }
+}
@@ -0,0 +1,974 @@
+//! # Email Templates
+//!
+//! Transactional email copy. Each template in [`EMAIL_TEMPLATES`] ships as
+//! Tera files in `templates/email/`: `<name>.subject.txt`, an HTML body
+//! `<name>.html`, and a plain-text body `<name>.txt`. They are rendered with
+//! the app's Tera instance, and the bodies extend `email/layout.html` and
+//! `email/layout.txt`, so the frame around every email lives in one place.
+//! Emails are sent as `multipart/alternative` with both bodies.
+//!
+//! Admins can edit the subject and HTML body at `/admin/emails` without a
+//! redeploy: every save is stored in `email_templates` as a new version, and
+//! the newest version is used instead of the bundled files. Its text body is
+//! derived from the rendered HTML ([`html_to_text`]). A saved version that
+//! fails to render falls back to the bundled files.
+//!
+//! In development `/dev/emails` previews the bundled files with their sample
+//! values in every locale, reloading them on each request.
+//!
+//! Admins can also add a footer to every email with the `email.footer`
+//! snippet (see [`crate::snippets`]); layouts print it as `footer`, and as
+//! plain text as `footer_text`.
+//!
+//! Copy is localized: templates print messages from the `email.<name>` table
+//! of the recipient's locale catalog as `{{ t.<key> }}` (see [`crate::i18n`]),
+//! and `{{ locale }}` is the catalog that was used.
+//!
+//! Emails are queued as [`SEND_EMAIL_JOB`] jobs and sent by the job worker.
+//! Everything is sent through [`deliver`], which skips addresses suppressed
+//! after a bounce or complaint, and users who turned off the email's
+//! [`EmailCategory`], and records each attempt in `email_outbox`. Emails in a
+//! category users can turn off get an `unsubscribe_url` for one-click
+//! unsubscribing. [`EmailMetrics`] counts the attempts by outcome at
+//! `/metrics`.
+
+use axum::{
+    Router,
+    extract::{Path, Query},
+    http::{StatusCode, header},
+    response::{Html, IntoResponse, Response},
+    routing::get,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::error::Error as _;
+use tera::{Context, Tera};
+
+use crate::error::{AppError, AppResult};
+use crate::i18n;
+use crate::jobs::{self, JobOptions, Priority};
+use crate::mailer::Email;
+use crate::metrics::{CounterVec, HistogramVec, MetricSource};
+use crate::output;
+use crate::services::{EmailDeliveryService, EmailPreferenceService, EmailTemplateService};
+use crate::snippets::{self, SnippetContext};
+use crate::state::AppState;
+
+/// Directory holding the bundled templates
+pub const BUNDLED_DIR: &str = "templates/email";
+
+/// Job kind that renders and sends one email
+pub const SEND_EMAIL_JOB: &str = "send_email";
+
+/// Outbox status of a delivered email
+pub const STATUS_SENT: &str = "sent";
+/// Outbox status of an email the mailer failed to send
+pub const STATUS_FAILED: &str = "failed";
+/// Outbox status of an email not sent because the recipient is suppressed
+pub const STATUS_SUPPRESSED: &str = "suppressed";
+/// Outbox status of an email not sent because the recipient turned off its
+/// category
+pub const STATUS_UNSUBSCRIBED: &str = "unsubscribed";
+
+/// Delivery attempts of this instance, served at `/metrics`
+#[derive(Debug)]
+pub struct EmailMetrics {
+    /// Attempts by outbox status
+    pub deliveries: CounterVec,
+    /// Time the mailer took to send, throttling included
+    pub send_time: HistogramVec,
+}
+
+impl Default for EmailMetrics {
+    fn default() -> Self {
+        Self {
+            deliveries: CounterVec::new(
+                "email_deliveries_total",
+                "Email delivery attempts by outcome",
+                &["status"],
+            )
+            .with_series(&[
+                &[STATUS_SENT],
+                &[STATUS_FAILED],
+                &[STATUS_SUPPRESSED],
+                &[STATUS_UNSUBSCRIBED],
+            ]),
+            send_time: HistogramVec::new(
+                "email_send_seconds",
+                "Time the mailer took to send an email, including waits for the send rate",
+                &[],
+            ),
+        }
+    }
+}
+
+impl MetricSource for EmailMetrics {
+    fn render(&self, out: &mut String) {
+        self.deliveries.render(out);
+        self.send_time.render(out);
+    }
+}
+
+/// Names edited sources are registered under; the `.html` suffix turns on
+/// autoescaping for the body only
+const EDITED_SUBJECT: &str = "subject";
+const EDITED_BODY: &str = "body.html";
+
+/// What an email is about. Users choose which categories they get, except
+/// security email, which always goes out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailCategory {
+    /// Account and security notices
+    Security,
+    /// News about the service
+    ProductUpdates,
+    /// Periodic summaries of activity
+    Digests,
+    /// Replies to the user's comments
+    CommentReplies,
+}
+
+impl EmailCategory {
+    /// Every category, in the order the profile page lists them
+    pub const ALL: &[EmailCategory] = &[
+        EmailCategory::Security,
+        EmailCategory::ProductUpdates,
+        EmailCategory::Digests,
+        EmailCategory::CommentReplies,
+    ];
+
+    /// Key stored in `email_preferences` and used in unsubscribe links
+    pub fn key(self) -> &'static str {
+        match self {
+            EmailCategory::Security => "security",
+            EmailCategory::ProductUpdates => "product_updates",
+            EmailCategory::Digests => "digests",
+            EmailCategory::CommentReplies => "comment_replies",
+        }
+    }
+
+    /// Look up a category by key
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|category| category.key() == key)
+    }
+
+    /// Name shown to users
+    pub fn label(self) -> &'static str {
+        match self {
+            EmailCategory::Security => "Security alerts",
+            EmailCategory::ProductUpdates => "Product updates",
+            EmailCategory::Digests => "Activity digests",
+            EmailCategory::CommentReplies => "Comment replies",
+        }
+    }
+
+    /// Whether users can turn the category off
+    pub fn optional(self) -> bool {
+        self != EmailCategory::Security
+    }
+
+    /// Whether users get the category before choosing; product updates are
+    /// opt-in
+    pub fn subscribed_by_default(self) -> bool {
+        self != EmailCategory::ProductUpdates
+    }
+}
+
+/// An email the application sends
+#[derive(Debug, Clone, Copy)]
+pub struct EmailTemplateSpec {
+    /// Template name, also the bundled file stem
+    pub name: &'static str,
+    /// When the email is sent
+    pub description: &'static str,
+    /// Variables passed to the template, with sample values for test sends
+    pub variables: &'static [(&'static str, &'static str)],
+    /// Priority of the job that sends it
+    pub priority: Priority,
+    /// Which subscription it belongs to
+    pub category: EmailCategory,
+}
+
+/// Every email the application sends
+pub const EMAIL_TEMPLATES: &[EmailTemplateSpec] = &[
+    EmailTemplateSpec {
+        name: "welcome",
+        description: "Sent when an admin creates an account",
+        variables: &[
+            ("service_name", "Axum Base"),
+            ("username", "jane"),
+            ("login_url", "http://localhost:3093/login"),
+        ],
+        priority: Priority::Normal,
+        category: EmailCategory::Security,
+    },
+    EmailTemplateSpec {
+        name: "password_changed",
+        description: "Sent after a user changes their password",
+        variables: &[
+            ("service_name", "Axum Base"),
+            ("username", "jane"),
+            ("profile_url", "http://localhost:3093/profile"),
+        ],
+        priority: Priority::High,
+        category: EmailCategory::Security,
+    },
+];
+
+/// Look up an email template by name
+pub fn find(name: &str) -> Option<&'static EmailTemplateSpec> {
+    EMAIL_TEMPLATES.iter().find(|spec| spec.name == name)
+}
+
+impl EmailTemplateSpec {
+    /// Name of the bundled subject template in the Tera instance
+    pub fn subject_template(&self) -> String {
+        format!("email/{}.subject.txt", self.name)
+    }
+
+    /// Name of the bundled body template in the Tera instance
+    pub fn body_template(&self) -> String {
+        format!("email/{}.html", self.name)
+    }
+
+    /// Name of the bundled plain-text template in the Tera instance
+    pub fn text_template(&self) -> String {
+        format!("email/{}.txt", self.name)
+    }
+
+    /// Context filled with the sample values, localized for `locale`
+    pub fn sample_context(&self, locale: Option<&str>) -> Context {
+        let mut context = Context::new();
+        for (variable, value) in self.variables {
+            context.insert(*variable, value);
+        }
+        localize(self, locale, &context)
+    }
+
+    /// Catalog key prefix of this email's messages
+    pub fn message_prefix(&self) -> String {
+        format!("email.{}", self.name)
+    }
+
+    /// Source of the bundled files, shown in the editor as the default
+    pub fn bundled_source(&self) -> std::io::Result<EmailSource> {
+        let read = |file: String| std::fs::read_to_string(format!("{}/{}", BUNDLED_DIR, file));
+        Ok(EmailSource {
+            subject: read(format!("{}.subject.txt", self.name))?,
+            body: read(format!("{}.html", self.name))?,
+        })
+    }
+}
+
+/// Editable source of an email: Tera templates for subject and HTML body
+#[derive(Debug, Clone, Serialize)]
+pub struct EmailSource {
+    pub subject: String,
+    pub body: String,
+}
+
+/// A rendered subject and bodies
+#[derive(Debug, Clone)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl RenderedEmail {
+    /// Address the email to a recipient
+    pub fn to(self, to: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: self.subject,
+            html: self.html,
+            text: Some(self.text),
+            unsubscribe_url: None,
+        }
+    }
+}
+
+/// Render an edited source; it may include or extend the bundled templates
+pub fn render_source(
+    templates: &Tera,
+    source: &EmailSource,
+    context: &Context,
+) -> Result<RenderedEmail, tera::Error> {
+    let mut tera = templates.clone();
+    tera.add_raw_templates(vec![
+        (EDITED_SUBJECT, source.subject.as_str()),
+        (EDITED_BODY, source.body.as_str()),
+    ])?;
+
+    let html = tera.render(EDITED_BODY, context)?;
+    Ok(RenderedEmail {
+        subject: subject_line(&tera.render(EDITED_SUBJECT, context)?),
+        text: html_to_text(&html),
+        html,
+    })
+}
+
+/// Render the bundled files. The text body comes from `<name>.txt`, or from
+/// the HTML body when there is no such file.
+pub fn render_bundled(
+    templates: &Tera,
+    spec: &EmailTemplateSpec,
+    context: &Context,
+) -> Result<RenderedEmail, tera::Error> {
+    let html = templates.render(&spec.body_template(), context)?;
+    let text_template = spec.text_template();
+    let text = if templates
+        .get_template_names()
+        .any(|name| name == text_template)
+    {
+        tidy_text(&templates.render(&text_template, context)?)
+    } else {
+        html_to_text(&html)
+    };
+    Ok(RenderedEmail {
+        subject: subject_line(&templates.render(&spec.subject_template(), context)?),
+        html,
+        text,
+    })
+}
+
+/// Add the `t` messages and `locale` for a recipient's locale to a context
+pub fn localize(spec: &EmailTemplateSpec, locale: Option<&str>, context: &Context) -> Context {
+    let variables = context.clone().into_json();
+    let mut context = context.clone();
+    context.insert(
+        "t",
+        &i18n::messages(locale, &spec.message_prefix(), &variables),
+    );
+    context.insert("locale", i18n::resolve(locale));
+    context
+}
+
+/// Render the newest saved version, or the bundled files if there is none,
+/// in the recipient's locale, with the `email.footer` snippet when there is one
+pub async fn render(
+    pool: &PgPool,
+    templates: &Tera,
+    spec: &EmailTemplateSpec,
+    locale: Option<&str>,
+    context: &Context,
+) -> AppResult<RenderedEmail> {
+    let mut context = localize(spec, locale, context);
+    let snippet_context = SnippetContext {
+        service_name: "Axum Base".to_string(),
+        username: context
+            .get("username")
+            .and_then(|username| username.as_str())
+            .map(str::to_string),
+        locale: i18n::resolve(locale).to_string(),
+    };
+    if let Some(footer) =
+        snippets::render_named(pool, snippets::EMAIL_FOOTER, &snippet_context).await
+    {
+        context.insert("footer_text", html_to_text(&footer).trim_end());
+        context.insert("footer", &footer);
+    }
+    let context = &context;
+    if let Some(saved) = EmailTemplateService::latest(pool, spec.name).await? {
+        let source = EmailSource {
+            subject: saved.subject,
+            body: saved.body,
+        };
+        match render_source(templates, &source, context) {
+            Ok(rendered) => return Ok(rendered),
+            Err(e) => output::warn(format!(
+                "Email template '{}' v{} failed to render, using the bundled copy: {}",
+                spec.name,
+                saved.version,
+                error_chain(&e)
+            )),
+        }
+    }
+    Ok(render_bundled(templates, spec, context)?)
+}
+
+/// Check an edited source before saving it: both parts must render with the
+/// documented variables and the subject must not be blank
+pub fn validate(
+    templates: &Tera,
+    spec: &EmailTemplateSpec,
+    source: &EmailSource,
+) -> Result<RenderedEmail, String> {
+    let rendered = render_source(templates, source, &spec.sample_context(None))
+        .map_err(|e| error_chain(&e))?;
+    if rendered.subject.is_empty() {
+        return Err("The subject must not be empty".to_string());
+    }
+    Ok(rendered)
+}
+
+/// Context with the variables every email gets
+pub fn base_context() -> Context {
+    let mut context = Context::new();
+    context.insert("service_name", "Axum Base");
+    context
+}
+
+/// Link that unsubscribes a token's holder from a category, for both the
+/// email body and one-click `List-Unsubscribe` requests
+pub fn unsubscribe_url(
+    public_base_url: &str,
+    token: uuid::Uuid,
+    category: EmailCategory,
+) -> String {
+    format!(
+        "{}/unsubscribe?token={}&category={}",
+        public_base_url,
+        token,
+        category.key()
+    )
+}
+
+/// Send a rendered email unless the recipient is suppressed or unsubscribed
+/// from the template's category, and record the attempt in the outbox
+pub async fn deliver(state: &AppState, template: &str, email: &Email) -> AppResult<()> {
+    let pool = &state.pool;
+    let metrics = state.metrics.source::<EmailMetrics>();
+    let skipped = match EmailDeliveryService::suppression_reason(pool, &email.to).await? {
+        Some(reason) => Some((
+            STATUS_SUPPRESSED,
+            format!("{} is suppressed after a {}", email.to, reason),
+        )),
+        None => match find(template).map(|spec| spec.category) {
+            Some(category)
+                if category.optional()
+                    && !EmailPreferenceService::is_subscribed(
+                        pool,
+                        &email.to,
+                        category.key(),
+                        category.subscribed_by_default(),
+                    )
+                    .await? =>
+            {
+                Some((
+                    STATUS_UNSUBSCRIBED,
+                    format!("{} unsubscribed from {}", email.to, category.key()),
+                ))
+            }
+            _ => None,
+        },
+    };
+    if let Some((status, error)) = skipped {
+        metrics.deliveries.inc(&[status]);
+        EmailDeliveryService::record(
+            pool,
+            template,
+            &email.to,
+            &email.subject,
+            status,
+            Some(&error),
+        )
+        .await?;
+        return Err(AppError::Validation(error));
+    }
+
+    let started = std::time::Instant::now();
+    let result = state.mailer.send(email).await;
+    metrics
+        .send_time
+        .observe(&[], started.elapsed().as_secs_f64());
+    let (status, error) = match &result {
+        Ok(()) => (STATUS_SENT, None),
+        Err(e) => (STATUS_FAILED, Some(e.to_string())),
+    };
+    metrics.deliveries.inc(&[status]);
+    EmailDeliveryService::record(
+        pool,
+        template,
+        &email.to,
+        &email.subject,
+        status,
+        error.as_deref(),
+    )
+    .await?;
+    result
+}
+
+/// Payload of a [`SEND_EMAIL_JOB`]
+#[derive(Debug, Serialize, Deserialize)]
+struct SendEmailJob {
+    template: String,
+    to: String,
+    locale: Option<String>,
+    context: serde_json::Value,
+}
+
+/// Queue an email for the job worker, which renders it in the recipient's
+/// locale and sends it at the template's priority; failures to queue are
+/// logged
+pub async fn send_in_background(
+    state: &AppState,
+    name: &'static str,
+    to: String,
+    locale: Option<String>,
+    context: Context,
+) {
+    let priority = find(name).map_or(Priority::Normal, |spec| spec.priority);
+    let job = SendEmailJob {
+        template: name.to_string(),
+        to,
+        locale,
+        context: context.into_json(),
+    };
+    let options = JobOptions::default().priority(priority);
+    if let Err(e) = jobs::enqueue_with(&state.pool, SEND_EMAIL_JOB, &job, options).await {
+        output::error(format!(
+            "Failed to queue '{}' email to {}: {}",
+            name, job.to, e
+        ));
+    }
+}
+
+/// Job handler for [`SEND_EMAIL_JOB`]. Suppressed, unsubscribed, or invalid
+/// recipients are not retried; they are already recorded in the outbox.
+pub async fn run_send_job(state: AppState, payload: serde_json::Value) -> Result<(), String> {
+    let job: SendEmailJob = serde_json::from_value(payload).map_err(|e| e.to_string())?;
+    let spec =
+        find(&job.template).ok_or_else(|| format!("Unknown email template '{}'", job.template))?;
+    let mut context = Context::from_value(job.context).map_err(|e| error_chain(&e))?;
+    let mut link = None;
+    if spec.category.optional()
+        && let Some(token) = EmailPreferenceService::unsubscribe_token(&state.pool, &job.to)
+            .await
+            .map_err(|e| e.to_string())?
+    {
+        let base_url = state.config.server.public_base_url();
+        let url = unsubscribe_url(&base_url, token, spec.category);
+        context.insert("unsubscribe_url", &url);
+        link = Some(url);
+    }
+
+    let rendered = render(
+        &state.pool,
+        &state.templates,
+        spec,
+        job.locale.as_deref(),
+        &context,
+    )
+    .await
+    .map_err(|e| e.to_string())?;
+    let email = Email {
+        unsubscribe_url: link,
+        ..rendered.to(&job.to)
+    };
+    match deliver(&state, spec.name, &email).await {
+        Err(AppError::Validation(message)) => {
+            output::warn(format!("Not sending '{}' email: {}", spec.name, message));
+            Ok(())
+        }
+        result => result.map_err(|e| e.to_string()),
+    }
+}
+
+/// Collapse whitespace so the subject is a single header line
+fn subject_line(rendered: &str) -> String {
+    rendered.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Plain-text version of an HTML body: tags are dropped, blocks become
+/// paragraphs, list items get a dash, and links keep their address
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::new();
+    // Address of each open link and where its text starts
+    let mut links: Vec<(Option<String>, usize)> = Vec::new();
+    let mut skipping: Option<&str> = None;
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        if skipping.is_none() {
+            push_text(&mut text, &rest[..start]);
+        }
+        let Some(end) = rest[start..].find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|c: char| c.is_whitespace() || c == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if let Some(skipped) = skipping {
+            if closing && name == skipped {
+                skipping = None;
+            }
+            continue;
+        }
+        match (name.as_str(), closing) {
+            ("head", false) => skipping = Some("head"),
+            ("style", false) => skipping = Some("style"),
+            ("script", false) => skipping = Some("script"),
+            ("br", _) => text.push('\n'),
+            ("li", false) => text.push_str("\n- "),
+            (
+                "p" | "div" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "ul" | "ol" | "table"
+                | "tr" | "blockquote",
+                _,
+            ) => text.push_str("\n\n"),
+            ("a", false) => links.push((attribute(tag, "href"), text.len())),
+            ("a", true) => {
+                if let Some((Some(href), text_start)) = links.pop()
+                    && text[text_start..].trim() != href
+                {
+                    text.push_str(&format!(" ({})", href));
+                }
+            }
+            _ => {}
+        }
+    }
+    if skipping.is_none() {
+        push_text(&mut text, rest);
+    }
+    tidy_text(&text)
+}
+
+/// Append an HTML text node, collapsing its whitespace as a browser would
+fn push_text(text: &mut String, node: &str) {
+    let decoded = decode_entities(node);
+    let mut last_space = text.ends_with(char::is_whitespace);
+    for c in decoded.chars() {
+        if c.is_whitespace() {
+            if !last_space {
+                text.push(' ');
+            }
+            last_space = true;
+        } else {
+            text.push(c);
+            last_space = false;
+        }
+    }
+}
+
+/// Trim each line and keep at most one blank line between paragraphs
+fn tidy_text(text: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !line.is_empty() || lines.last().is_some_and(|last| !last.is_empty()) {
+            lines.push(line);
+        }
+    }
+    while lines.last().is_some_and(String::is_empty) {
+        lines.pop();
+    }
+    let mut text = lines.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Value of a tag's attribute, quoted or not
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let start = tag.to_ascii_lowercase().find(&format!(" {}=", name))? + name.len() + 2;
+    let value = &tag[start..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(char::is_whitespace).next()?,
+    };
+    Some(decode_entities(value))
+}
+
+/// Decode the entities Tera's escaping produces, plus `&nbsp;`
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&#x2F;", "/")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+// =============================================================================
+// Development Previews
+// =============================================================================
+
+/// Routes previewing the bundled emails at `/dev/emails` (development only)
+pub fn preview_routes() -> Router<AppState> {
+    Router::new()
+        .route("/dev/emails", get(preview_index))
+        .route("/dev/emails/{name}", get(preview_email))
+}
+
+/// Query parameters of an email preview
+#[derive(Debug, Deserialize)]
+pub struct PreviewParams {
+    /// Locale to render in; the default locale if unset
+    pub locale: Option<String>,
+    /// `text` for the plain-text body; the HTML body otherwise
+    pub format: Option<String>,
+}
+
+/// Links to every email in every locale
+pub async fn preview_index() -> Html<String> {
+    let mut rows = String::new();
+    for spec in EMAIL_TEMPLATES {
+        let links: String = i18n::SUPPORTED_LOCALES
+            .iter()
+            .map(|(locale, _)| {
+                format!(
+                    "<a href=\"/dev/emails/{name}?locale={locale}\">{locale}</a> \
+                     (<a href=\"/dev/emails/{name}?locale={locale}&amp;format=text\">text</a>) ",
+                    name = spec.name,
+                    locale = locale
+                )
+            })
+            .collect();
+        rows.push_str(&format!(
+            "<li><strong>{}</strong>: {}<br>{}</li>",
+            spec.name,
+            tera::escape_html(spec.description),
+            links
+        ));
+    }
+    Html(format!(
+        "<!DOCTYPE html><html><head><title>Email previews</title></head>\
+         <body style=\"font-family: sans-serif;\"><h1>Email previews</h1><ul>{}</ul></body></html>",
+        rows
+    ))
+}
+
+/// One bundled email rendered with its sample values. Templates are loaded
+/// again for every request, so edits show without a restart, and template
+/// errors are shown in full.
+pub async fn preview_email(
+    Path(name): Path<String>,
+    Query(params): Query<PreviewParams>,
+) -> Response {
+    let Some(spec) = find(&name) else {
+        return AppError::NotFound(format!("No email template named '{}'", name)).into_response();
+    };
+    let context = spec.sample_context(params.locale.as_deref());
+    let rendered = crate::web::load_templates()
+        .and_then(|templates| render_bundled(&templates, spec, &context));
+    let plain_text = [(header::CONTENT_TYPE, "text/plain; charset=utf-8")];
+
+    match (rendered, params.format.as_deref()) {
+        (Err(e), _) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            plain_text,
+            format!("'{}' failed to render: {}", name, error_chain(&e)),
+        )
+            .into_response(),
+        (Ok(rendered), Some("text")) => (
+            plain_text,
+            format!("Subject: {}\n\n{}", rendered.subject, rendered.text),
+        )
+            .into_response(),
+        (Ok(rendered), _) => Html(rendered.html).into_response(),
+    }
+}
+
+/// Tera error with its causes, which hold the useful detail
+pub fn error_chain(error: &tera::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn templates() -> Tera {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(vec![
+            ("email/welcome.subject.txt", "{{ t.subject }}"),
+            ("email/welcome.html", "<p>Hi {{ username }}</p>"),
+        ])
+        .unwrap();
+        tera
+    }
+
+    #[test]
+    fn test_bundled_files_exist_for_every_template() {
+        for spec in EMAIL_TEMPLATES {
+            let source = spec.bundled_source().expect("bundled email template");
+            let tera = crate::web::load_templates().unwrap();
+            assert!(validate(&tera, spec, &source).is_ok(), "{}", spec.name);
+            assert!(
+                tera.get_template_names()
+                    .any(|name| name == spec.text_template()),
+                "{} has no text body",
+                spec.name
+            );
+        }
+    }
+
+    #[test]
+    fn test_every_locale_renders_every_email() {
+        let tera = crate::web::load_templates().unwrap();
+        for spec in EMAIL_TEMPLATES {
+            for (locale, _) in i18n::SUPPORTED_LOCALES {
+                let context = spec.sample_context(Some(locale));
+                let rendered = render_bundled(&tera, spec, &context)
+                    .unwrap_or_else(|e| panic!("{} in {}: {}", spec.name, locale, error_chain(&e)));
+
+                assert!(!rendered.subject.is_empty(), "{} in {}", spec.name, locale);
+                assert!(
+                    rendered
+                        .html
+                        .contains(&format!("<html lang=\"{}\">", locale)),
+                    "{} in {}",
+                    spec.name,
+                    locale
+                );
+                // Every placeholder was filled
+                assert!(
+                    !rendered.subject.contains('{')
+                        && !rendered.html.contains('{')
+                        && !rendered.text.contains('{'),
+                    "{} in {}",
+                    spec.name,
+                    locale
+                );
+                // The text body is plain text, not escaped HTML
+                assert!(
+                    !rendered.text.trim().is_empty()
+                        && !rendered.text.contains('<')
+                        && !rendered.text.contains("&#x"),
+                    "{} in {}",
+                    spec.name,
+                    locale
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_render_follows_the_fallback_chain() {
+        let spec = find("welcome").unwrap();
+        let subject = |locale| {
+            render_bundled(&templates(), spec, &spec.sample_context(locale))
+                .unwrap()
+                .subject
+        };
+
+        assert_eq!(subject(Some("de-AT")), "Willkommen bei Axum Base, jane");
+        assert_eq!(subject(Some("fr")), "Welcome to Axum Base, jane");
+        assert_eq!(subject(None), "Welcome to Axum Base, jane");
+    }
+
+    #[test]
+    fn test_edited_body_is_escaped_and_subject_is_one_line() {
+        let spec = find("welcome").unwrap();
+        let mut context = spec.sample_context(None);
+        context.insert("username", "<b>jane</b>");
+        let source = EmailSource {
+            subject: "Hello\n  {{ username }}".to_string(),
+            body: "<p>{{ username }}</p>".to_string(),
+        };
+
+        let rendered = render_source(&templates(), &source, &context).unwrap();
+        assert_eq!(rendered.subject, "Hello <b>jane</b>");
+        assert_eq!(rendered.html, "<p>&lt;b&gt;jane&lt;&#x2F;b&gt;</p>");
+
+        let bundled = render_bundled(&templates(), spec, &context).unwrap();
+        assert_eq!(bundled.subject, "Welcome to Axum Base, jane");
+    }
+
+    #[test]
+    fn test_text_body_comes_from_the_text_template() {
+        let spec = find("welcome").unwrap();
+        let tera = crate::web::load_templates().unwrap();
+        let mut context = spec.sample_context(None);
+        context.insert("username", "O'Brien");
+        let context = localize(spec, None, &context);
+
+        let rendered = render_bundled(&tera, spec, &context).unwrap();
+        assert!(rendered.text.starts_with("Welcome, O'Brien!\n\n"));
+        assert!(
+            rendered
+                .text
+                .contains("Sign in (http://localhost:3093/login) with")
+        );
+        assert!(rendered.text.ends_with("--\nAxum Base\n"));
+        assert!(rendered.html.contains("Welcome, O&#x27;Brien!"));
+
+        // Without a text template the HTML body is converted
+        let mut html_only = Tera::default();
+        for name in [
+            "email/layout.html".to_string(),
+            spec.body_template(),
+            spec.subject_template(),
+        ] {
+            let source = std::fs::read_to_string(format!("templates/{}", name)).unwrap();
+            html_only.add_raw_template(&name, &source).unwrap();
+        }
+        let rendered = render_bundled(&html_only, spec, &context).unwrap();
+        assert!(rendered.text.starts_with("Welcome, O'Brien!\n\n"));
+        assert!(
+            rendered
+                .text
+                .contains("Sign in (http://localhost:3093/login) with")
+        );
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<!DOCTYPE html><html><head><style>p { color: red; }</style></head>\
+                    <body><h1>Hi &amp; welcome</h1>\n  <p>Line one<br>line\n   two</p>\
+                    <ul><li>First</li><li>Second</li></ul>\
+                    <p><a href=\"https://example.com/a?x=1&amp;y=2\">Open</a> or \
+                    <a href='https://example.com'>https://example.com</a>.</p></body></html>";
+        assert_eq!(
+            html_to_text(html),
+            "Hi & welcome\n\nLine one\nline two\n\n- First\n- Second\n\n\
+             Open (https://example.com/a?x=1&y=2) or https://example.com.\n"
+        );
+        assert_eq!(html_to_text("plain"), "plain\n");
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_sources() {
+        let spec = find("welcome").unwrap();
+        let source = |subject: &str, body: &str| EmailSource {
+            subject: subject.to_string(),
+            body: body.to_string(),
+        };
+
+        assert!(validate(&templates(), spec, &source("Hi", "{{ username }}")).is_ok());
+        assert!(validate(&templates(), spec, &source("Hi", "{% if %}")).is_err());
+        assert!(validate(&templates(), spec, &source("Hi", "{{ unknown_var }}")).is_err());
+        assert!(validate(&templates(), spec, &source("  ", "ok")).is_err());
+        assert!(find("missing").is_none());
+    }
+
+    #[test]
+    fn test_email_categories() {
+        for category in EmailCategory::ALL {
+            assert_eq!(EmailCategory::from_key(category.key()), Some(*category));
+        }
+        assert_eq!(EmailCategory::from_key("marketing"), None);
+
+        assert!(!EmailCategory::Security.optional());
+        assert!(EmailCategory::Security.subscribed_by_default());
+        assert!(!EmailCategory::ProductUpdates.subscribed_by_default());
+
+        let token = uuid::Uuid::nil();
+        assert_eq!(
+            unsubscribe_url("https://example.com", token, EmailCategory::Digests),
+            format!(
+                "https://example.com/unsubscribe?token={}&category=digests",
+                token
+            )
+        );
+    }
+}
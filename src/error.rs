@@ -0,0 +1,243 @@
+//! # Error Handling
+//!
+//! Crate-wide error type for handlers. `AppError` converts from the library
+//! errors handlers run into and renders as the standard error `Envelope`
+//! with a matching status code.
+//! Internal details are logged server-side and never sent to clients.
+
+use axum::{
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use std::fmt;
+use std::time::Duration;
+
+use crate::models::{Envelope, ErrorDetail};
+use crate::output;
+
+/// Result alias for handlers and helpers that fail with `AppError`
+pub type AppResult<T> = Result<T, AppError>;
+
+/// Application error returned by handlers
+#[derive(Debug)]
+#[allow(dead_code)]
+pub enum AppError {
+    /// A database query failed
+    Database(sqlx::Error),
+    /// The requested resource does not exist
+    NotFound(String),
+    /// The caller is not authenticated
+    Unauthorized(String),
+    /// The caller is authenticated but not allowed to do this
+    Forbidden(String),
+    /// The request was malformed or failed validation
+    Validation(String),
+    /// The request body is over a size limit
+    PayloadTooLarge(String),
+    /// The caller is over a rate limit and may retry after the given delay
+    RateLimited(Duration),
+    /// A template failed to render
+    Template(tera::Error),
+    /// Any other server-side failure
+    Internal(String),
+}
+
+impl AppError {
+    /// HTTP status code for this error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Validation(_) => StatusCode::BAD_REQUEST,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::Database(_) | AppError::Template(_) | AppError::Internal(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    /// Stable machine-readable error code
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "database",
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Validation(_) => "validation",
+            AppError::PayloadTooLarge(_) => "payload_too_large",
+            AppError::RateLimited(_) => "rate_limited",
+            AppError::Template(_) => "template",
+            AppError::Internal(_) => "internal",
+        }
+    }
+
+    /// Message that is safe to show to clients
+    pub fn public_message(&self) -> String {
+        match self {
+            AppError::NotFound(message)
+            | AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::Validation(message)
+            | AppError::PayloadTooLarge(message) => message.clone(),
+            AppError::RateLimited(retry_after) => format!(
+                "Too many requests; try again in {} seconds",
+                retry_after_secs(*retry_after)
+            ),
+            AppError::Database(_) => "A database error occurred".to_string(),
+            AppError::Template(_) => "Failed to render page".to_string(),
+            AppError::Internal(_) => "Internal server error".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::Database(err) => write!(f, "Database error: {}", err),
+            AppError::NotFound(message) => write!(f, "Not found: {}", message),
+            AppError::Unauthorized(message) => write!(f, "Unauthorized: {}", message),
+            AppError::Forbidden(message) => write!(f, "Forbidden: {}", message),
+            AppError::Validation(message) => write!(f, "Validation error: {}", message),
+            AppError::PayloadTooLarge(message) => write!(f, "Payload too large: {}", message),
+            AppError::RateLimited(retry_after) => {
+                write!(f, "Rate limited for {:?}", retry_after)
+            }
+            AppError::Template(err) => {
+                write!(f, "Template error: {}", err)?;
+                // Tera nests the useful detail (file, line) in the source chain
+                let mut source = std::error::Error::source(err);
+                while let Some(cause) = source {
+                    write!(f, ": {}", cause)?;
+                    source = cause.source();
+                }
+                Ok(())
+            }
+            AppError::Internal(message) => write!(f, "Internal error: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Database(err) => Some(err),
+            AppError::Template(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status.is_server_error() {
+            output::error(&self);
+        }
+
+        let message = self.public_message();
+        let detail = ErrorDetail {
+            code: self.code().to_string(),
+            message: message.clone(),
+            field: None,
+        };
+
+        let mut response = (status, Json(Envelope::error(message, vec![detail]))).into_response();
+        if let AppError::RateLimited(retry_after) = self {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, retry_after_secs(retry_after).into());
+        }
+        response
+    }
+}
+
+/// Whole seconds for `Retry-After`, rounded up so clients never retry early
+fn retry_after_secs(retry_after: Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
+// =============================================================================
+// Conversions
+// =============================================================================
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match err {
+            sqlx::Error::RowNotFound => AppError::NotFound("Resource not found".to_string()),
+            other => AppError::Database(other),
+        }
+    }
+}
+
+impl From<tera::Error> for AppError {
+    fn from(err: tera::Error) -> Self {
+        AppError::Template(err)
+    }
+}
+
+impl From<argon2::password_hash::Error> for AppError {
+    fn from(err: argon2::password_hash::Error) -> Self {
+        AppError::Internal(format!("Password hashing error: {}", err))
+    }
+}
+
+impl From<Box<dyn std::error::Error + Send + Sync>> for AppError {
+    fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
+        AppError::Internal(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(
+            AppError::NotFound("x".to_string()).status_code(),
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            AppError::Unauthorized("x".to_string()).status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            AppError::Forbidden("x".to_string()).status_code(),
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            AppError::Validation("x".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            AppError::PayloadTooLarge("x".to_string()).status_code(),
+            StatusCode::PAYLOAD_TOO_LARGE
+        );
+        assert_eq!(
+            AppError::Internal("x".to_string()).status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_rate_limited_sets_retry_after() {
+        let response = AppError::RateLimited(Duration::from_millis(2500)).into_response();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "3");
+    }
+
+    #[test]
+    fn test_row_not_found_maps_to_not_found() {
+        let err: AppError = sqlx::Error::RowNotFound.into();
+        assert_eq!(err.status_code(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_internal_details_are_hidden() {
+        let err = AppError::Internal("connection string leaked".to_string());
+        assert_eq!(err.public_message(), "Internal server error");
+        assert!(err.to_string().contains("connection string leaked"));
+    }
+}
@@ -0,0 +1,226 @@
+//! # Static Export
+//!
+//! Crawls the public pages of the application through the in-process router
+//! and writes a static HTML snapshot to disk, for read-only mirrors or
+//! archival. Crawling starts from a set of seed paths and follows local
+//! `href`/`src` links, so new public pages are picked up once linked.
+//! Authenticated, form, and live JSON routes are never exported.
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Request, StatusCode, header},
+};
+use std::collections::{HashSet, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use tower::ServiceExt;
+
+/// Paths crawled when no seeds are given
+pub const DEFAULT_SEEDS: &[&str] = &["/", "/landing"];
+
+/// Route prefixes that are never exported
+const EXCLUDED_PREFIXES: &[&str] = &[
+    "/login",
+    "/logout",
+    "/profile",
+    "/health",
+    "/api",
+    "/.well-known",
+];
+
+/// Outcome of a static export
+#[derive(Debug, Default)]
+pub struct ExportReport {
+    /// Files written, relative to the output directory
+    pub written: Vec<PathBuf>,
+    /// Linked paths that did not return 200 OK
+    pub skipped: Vec<(String, StatusCode)>,
+}
+
+/// Crawl `router` from `seeds` and write every page and asset under `output_dir`
+pub async fn export_static(
+    router: Router,
+    output_dir: &Path,
+    seeds: &[String],
+) -> io::Result<ExportReport> {
+    let mut report = ExportReport::default();
+    let mut queue: VecDeque<String> = seeds.iter().cloned().collect();
+    let mut seen: HashSet<String> = queue.iter().cloned().collect();
+
+    while let Some(path) = queue.pop_front() {
+        if !is_exportable(&path) {
+            continue;
+        }
+        let Some(relative) = output_path(&path) else {
+            continue;
+        };
+
+        let request = Request::builder()
+            .uri(path.as_str())
+            .header(header::HOST, "localhost")
+            // As a browser would, so pages draw their navigation
+            .header(header::ACCEPT, "text/html")
+            .body(Body::empty())
+            .map_err(io::Error::other)?;
+        let response = router
+            .clone()
+            .oneshot(request)
+            .await
+            .map_err(io::Error::other)?;
+
+        if response.status() != StatusCode::OK {
+            report.skipped.push((path, response.status()));
+            continue;
+        }
+
+        let is_html = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"));
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(io::Error::other)?;
+
+        if is_html {
+            for link in extract_links(&String::from_utf8_lossy(&body)) {
+                if seen.insert(link.clone()) {
+                    queue.push_back(link);
+                }
+            }
+        }
+
+        let target = output_dir.join(&relative);
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::write(&target, &body).await?;
+        report.written.push(relative);
+    }
+
+    Ok(report)
+}
+
+/// Whether a path may appear in a public snapshot
+fn is_exportable(path: &str) -> bool {
+    !EXCLUDED_PREFIXES.iter().any(|prefix| {
+        path == *prefix
+            || path
+                .strip_prefix(prefix)
+                .is_some_and(|rest| rest.starts_with('/'))
+    })
+}
+
+/// File a route is written to: `/` and extensionless routes become
+/// `index.html` inside a directory so pretty URLs keep working
+fn output_path(path: &str) -> Option<PathBuf> {
+    let relative = path.trim_start_matches('/');
+    if relative
+        .split('/')
+        .any(|segment| segment == ".." || segment == ".")
+    {
+        return None;
+    }
+
+    let mut file = PathBuf::from(relative);
+    let has_extension = relative
+        .rsplit('/')
+        .next()
+        .is_some_and(|name| name.contains('.'));
+    if !has_extension {
+        file.push("index.html");
+    }
+    Some(file)
+}
+
+/// Local paths referenced by `href="..."` and `src="..."` attributes
+fn extract_links(html: &str) -> Vec<String> {
+    let mut links = Vec::new();
+
+    for attribute in ["href=\"", "src=\""] {
+        for (index, _) in html.match_indices(attribute) {
+            let rest = &html[index + attribute.len()..];
+            let Some(end) = rest.find('"') else {
+                continue;
+            };
+            let link = &rest[..end];
+            if !link.starts_with('/') || link.starts_with("//") {
+                continue;
+            }
+            let path = link.split(['#', '?']).next().unwrap_or_default();
+            if !path.is_empty() {
+                links.push(path.to_string());
+            }
+        }
+    }
+
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{response::Html, routing::get};
+
+    #[test]
+    fn test_extract_links_keeps_local_paths() {
+        let html = r#"<a href="/landing#top">x</a><link href="/static/style.css">
+            <a href="https://example.com/">y</a><img src="//cdn.example.com/a.png">
+            <script src="/static/app.js?v=2"></script>"#;
+        assert_eq!(
+            extract_links(html),
+            vec!["/landing", "/static/style.css", "/static/app.js"]
+        );
+    }
+
+    #[test]
+    fn test_output_paths() {
+        assert_eq!(output_path("/"), Some(PathBuf::from("index.html")));
+        assert_eq!(
+            output_path("/landing"),
+            Some(PathBuf::from("landing/index.html"))
+        );
+        assert_eq!(
+            output_path("/static/style.css"),
+            Some(PathBuf::from("static/style.css"))
+        );
+        assert_eq!(output_path("/../etc/passwd"), None);
+    }
+
+    #[test]
+    fn test_private_routes_are_excluded() {
+        assert!(is_exportable("/landing"));
+        assert!(is_exportable("/profiles-of-the-week"));
+        assert!(!is_exportable("/profile"));
+        assert!(!is_exportable("/api/hello"));
+        assert!(!is_exportable("/login"));
+    }
+
+    #[tokio::test]
+    async fn test_export_follows_links() {
+        let router = Router::new()
+            .route(
+                "/",
+                get(|| async { Html(r#"<a href="/about">About</a><a href="/login">Login</a>"#) }),
+            )
+            .route("/about", get(|| async { Html("<p>About</p>") }));
+
+        let output_dir = std::env::temp_dir().join(format!("export-{}", uuid::Uuid::new_v4()));
+        let report = export_static(router, &output_dir, &["/".to_string()])
+            .await
+            .expect("Export should succeed");
+
+        assert_eq!(
+            report.written,
+            vec![
+                PathBuf::from("index.html"),
+                PathBuf::from("about/index.html")
+            ]
+        );
+        let about = std::fs::read_to_string(output_dir.join("about/index.html")).unwrap();
+        assert_eq!(about, "<p>About</p>");
+
+        std::fs::remove_dir_all(&output_dir).ok();
+    }
+}
@@ -0,0 +1,262 @@
+//! # Flash Messages
+//!
+//! One-shot notices for the next page a session sees, such as "Password
+//! changed" after a form post redirects. Handlers add them with the [`Flash`]
+//! extractor; pages rendered by [`crate::web`] get the pending messages as
+//! `flash` in their context, and `base.html` shows them.
+//!
+//! The [`track`] middleware loads a session's messages when a request starts
+//! and, once the response is ready, removes the ones a rendered page showed.
+//! Messages added during a request appear on a page rendered by that same
+//! request, or else wait for the next one.
+
+use axum::{
+    extract::{FromRequestParts, Request},
+    http::request::Parts,
+    middleware::Next,
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use tower_sessions::Session;
+
+use crate::error::AppError;
+use crate::output;
+
+/// Session key holding messages not yet shown
+pub(crate) const FLASH_SESSION_KEY: &str = "flash";
+
+/// How a message is styled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    Success,
+    Info,
+    Error,
+}
+
+/// A message waiting to be shown
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FlashMessage {
+    pub level: Level,
+    pub text: String,
+}
+
+/// The session's messages as seen by the current request
+#[derive(Debug, Default)]
+struct Pending {
+    messages: Vec<FlashMessage>,
+    /// How many of `messages`, from the front, a rendered page showed
+    shown: usize,
+}
+
+tokio::task_local! {
+    static PENDING: Arc<Mutex<Pending>>;
+}
+
+/// Extractor for adding flash messages to the session
+pub struct Flash {
+    session: Session,
+}
+
+impl<S> FromRequestParts<S> for Flash
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|(_, message)| AppError::Internal(message.to_string()))?;
+        Ok(Self { session })
+    }
+}
+
+impl Flash {
+    /// Report that something worked
+    pub async fn success(&self, text: impl Into<String>) {
+        self.push(Level::Success, text.into()).await;
+    }
+
+    /// Tell the user something neutral
+    pub async fn info(&self, text: impl Into<String>) {
+        self.push(Level::Info, text.into()).await;
+    }
+
+    /// Report that something failed
+    pub async fn error(&self, text: impl Into<String>) {
+        self.push(Level::Error, text.into()).await;
+    }
+
+    async fn push(&self, level: Level, text: String) {
+        let message = FlashMessage { level, text };
+        let mut messages = load(&self.session).await;
+        messages.push(message.clone());
+        if let Err(e) = self.session.insert(FLASH_SESSION_KEY, &messages).await {
+            output::warn(format!("Failed to store flash message: {}", e));
+            return;
+        }
+
+        let _ = PENDING.try_with(|pending| {
+            pending
+                .lock()
+                .expect("flash lock poisoned")
+                .messages
+                .push(message)
+        });
+    }
+}
+
+/// Messages for the page being rendered, which count as shown. Empty outside
+/// [`track`].
+pub fn take() -> Vec<FlashMessage> {
+    PENDING
+        .try_with(|pending| {
+            let mut pending = pending.lock().expect("flash lock poisoned");
+            pending.shown = pending.messages.len();
+            pending.messages.clone()
+        })
+        .unwrap_or_default()
+}
+
+/// Middleware scoping the session's messages to the request and removing
+/// the ones shown. Must run inside the session layer.
+pub async fn track(request: Request, next: Next) -> Response {
+    let Some(session) = request.extensions().get::<Session>().cloned() else {
+        return next.run(request).await;
+    };
+
+    let pending = Arc::new(Mutex::new(Pending {
+        messages: load(&session).await,
+        shown: 0,
+    }));
+    let response = PENDING.scope(pending.clone(), next.run(request)).await;
+
+    let shown = pending.lock().expect("flash lock poisoned").shown;
+    if shown > 0 {
+        // Reload: a handler may have added messages after the page rendered
+        let mut messages = load(&session).await;
+        messages.drain(..shown.min(messages.len()));
+        let saved = if messages.is_empty() {
+            session
+                .remove::<Vec<FlashMessage>>(FLASH_SESSION_KEY)
+                .await
+                .map(|_| ())
+        } else {
+            session.insert(FLASH_SESSION_KEY, &messages).await
+        };
+        if let Err(e) = saved {
+            output::warn(format!("Failed to clear shown flash messages: {}", e));
+        }
+    }
+
+    response
+}
+
+async fn load(session: &Session) -> Vec<FlashMessage> {
+    session
+        .get(FLASH_SESSION_KEY)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    /// Render what `take` returns as `level:text` lines
+    fn page() -> String {
+        take()
+            .iter()
+            .map(|message| format!("{:?}:{}\n", message.level, message.text))
+            .collect()
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/page", get(|| async { page() }))
+            .route(
+                "/save",
+                get(|flash: Flash| async move {
+                    flash.success("Saved").await;
+                    "redirect"
+                }),
+            )
+            .route(
+                "/fail",
+                get(|flash: Flash| async move {
+                    flash.error("Broken").await;
+                    page()
+                }),
+            )
+            .layer(axum::middleware::from_fn(track))
+            .layer(SessionManagerLayer::new(MemoryStore::default()))
+    }
+
+    async fn get_page(app: &Router, uri: &str, cookie: Option<&str>) -> (String, Option<String>) {
+        let mut request = Request::builder().uri(uri);
+        if let Some(cookie) = cookie {
+            request = request.header("cookie", cookie);
+        }
+        let response = app
+            .clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let cookie = response.headers().get("set-cookie").map(|value| {
+            value
+                .to_str()
+                .unwrap()
+                .split(';')
+                .next()
+                .unwrap()
+                .to_string()
+        });
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (String::from_utf8(body.to_vec()).unwrap(), cookie)
+    }
+
+    #[tokio::test]
+    async fn test_messages_are_shown_once() {
+        let app = app();
+
+        let (_, cookie) = get_page(&app, "/save", None).await;
+        let cookie = cookie.expect("Flashing should start a session");
+
+        let (body, _) = get_page(&app, "/page", Some(&cookie)).await;
+        assert_eq!(body, "Success:Saved\n");
+        let (body, _) = get_page(&app, "/page", Some(&cookie)).await;
+        assert_eq!(body, "");
+
+        // Shown on a page rendered by the same request, then gone
+        let (body, _) = get_page(&app, "/fail", Some(&cookie)).await;
+        assert_eq!(body, "Error:Broken\n");
+        let (body, _) = get_page(&app, "/page", Some(&cookie)).await;
+        assert_eq!(body, "");
+    }
+
+    #[tokio::test]
+    async fn test_messages_wait_until_a_page_renders() {
+        let app = app();
+
+        let (_, cookie) = get_page(&app, "/save", None).await;
+        let cookie = cookie.unwrap();
+        get_page(&app, "/save", Some(&cookie)).await;
+
+        let (body, _) = get_page(&app, "/page", Some(&cookie)).await;
+        assert_eq!(body, "Success:Saved\nSuccess:Saved\n");
+    }
+
+    #[test]
+    fn test_take_outside_a_request_is_empty() {
+        assert!(take().is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+//! # Content Negotiation
+//!
+//! Lets API endpoints speak JSON, MessagePack, or CBOR. Responses pick a
+//! format from the `Accept` header via the [`Accept`] extractor and
+//! [`Negotiated`] wrapper; request bodies are decoded according to their
+//! `Content-Type` by the [`Payload`] extractor. JSON stays the default so
+//! existing clients are unaffected.
+
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{HeaderMap, HeaderValue, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use serde::{Serialize, de::DeserializeOwned};
+use std::convert::Infallible;
+
+use crate::error::AppError;
+
+// =============================================================================
+// Wire Formats
+// =============================================================================
+
+/// Serialization formats supported by the API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl Format {
+    /// Canonical `Content-Type` for this format
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Json => "application/json",
+            Format::MessagePack => "application/msgpack",
+            Format::Cbor => "application/cbor",
+        }
+    }
+
+    /// Match a media type (parameters are ignored) to a supported format
+    pub fn from_media_type(media_type: &str) -> Option<Self> {
+        let essence = media_type.split(';').next().unwrap_or("").trim();
+        match essence.to_ascii_lowercase().as_str() {
+            "application/json" => Some(Format::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::MessagePack)
+            }
+            "application/cbor" => Some(Format::Cbor),
+            _ => None,
+        }
+    }
+
+    /// Choose the best format from an `Accept` header value.
+    ///
+    /// Honors `q` weights, treats wildcards as JSON, and falls back to JSON
+    /// when nothing supported is listed.
+    pub fn from_accept(accept: &str) -> Self {
+        let mut best: Option<(Format, f32)> = None;
+
+        for range in accept.split(',') {
+            let mut parts = range.split(';');
+            let media_type = parts.next().unwrap_or("").trim();
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let format = match media_type {
+                "*/*" | "application/*" => Some(Format::Json),
+                other => Format::from_media_type(other),
+            };
+
+            if let Some(format) = format
+                && best.is_none_or(|(_, best_quality)| quality > best_quality)
+            {
+                best = Some((format, quality));
+            }
+        }
+
+        best.map(|(format, _)| format).unwrap_or_default()
+    }
+
+    /// Choose a response format from request headers
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(header::ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .map(Format::from_accept)
+            .unwrap_or_default()
+    }
+
+    /// Encode a value in this format
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, String> {
+        match self {
+            Format::Json => serde_json::to_vec(value).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::to_vec_named(value).map_err(|e| e.to_string()),
+            Format::Cbor => {
+                let mut buffer = Vec::new();
+                ciborium::into_writer(value, &mut buffer).map_err(|e| e.to_string())?;
+                Ok(buffer)
+            }
+        }
+    }
+
+    /// Decode a value from bytes in this format
+    pub fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, String> {
+        match self {
+            Format::Json => serde_json::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::MessagePack => rmp_serde::from_slice(bytes).map_err(|e| e.to_string()),
+            Format::Cbor => ciborium::from_reader(bytes).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+// =============================================================================
+// Extractors and Responses
+// =============================================================================
+
+/// Extractor for the response format requested via `Accept`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Accept(pub Format);
+
+impl<S> FromRequestParts<S> for Accept
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Accept(Format::from_headers(&parts.headers)))
+    }
+}
+
+/// Response body encoded in the negotiated format. It carries `Vary: Accept`
+/// so shared caches keep the formats of one URL apart.
+pub struct Negotiated<T>(pub Format, pub T);
+
+impl<T: Serialize> IntoResponse for Negotiated<T> {
+    fn into_response(self) -> Response {
+        let Negotiated(format, value) = self;
+        match format.encode(&value) {
+            Ok(body) => (
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        HeaderValue::from_static(format.content_type()),
+                    ),
+                    (header::VARY, HeaderValue::from_static("accept")),
+                ],
+                body,
+            )
+                .into_response(),
+            Err(err) => AppError::Internal(format!(
+                "Failed to encode {} response: {}",
+                format.content_type(),
+                err
+            ))
+            .into_response(),
+        }
+    }
+}
+
+/// Request body extractor that decodes JSON, MessagePack, or CBOR by `Content-Type`.
+///
+/// A missing `Content-Type` is treated as JSON.
+pub struct Payload<T>(pub T);
+
+impl<S, T> FromRequest<S> for Payload<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let format = match req.headers().get(header::CONTENT_TYPE) {
+            None => Format::Json,
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(Format::from_media_type)
+                .ok_or_else(|| {
+                    AppError::Validation(
+                        "Expected application/json, application/msgpack, or application/cbor"
+                            .to_string(),
+                    )
+                })?,
+        };
+
+        let bytes = Bytes::from_request(req, state)
+            .await
+            .map_err(|err| AppError::Validation(err.body_text()))?;
+
+        format.decode(&bytes).map(Payload).map_err(|err| {
+            AppError::Validation(format!("Invalid {} body: {}", format.content_type(), err))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Envelope;
+
+    #[test]
+    fn test_accept_defaults_to_json() {
+        assert_eq!(Format::from_accept(""), Format::Json);
+        assert_eq!(Format::from_accept("*/*"), Format::Json);
+        assert_eq!(Format::from_accept("text/html"), Format::Json);
+    }
+
+    #[test]
+    fn test_accept_honors_quality_weights() {
+        assert_eq!(
+            Format::from_accept("application/json;q=0.5, application/msgpack"),
+            Format::MessagePack
+        );
+        assert_eq!(
+            Format::from_accept("application/cbor;q=0.9, application/json;q=0.1"),
+            Format::Cbor
+        );
+        assert_eq!(
+            Format::from_accept("application/msgpack;q=0, */*"),
+            Format::Json
+        );
+    }
+
+    #[test]
+    fn test_media_type_ignores_parameters() {
+        assert_eq!(
+            Format::from_media_type("application/json; charset=utf-8"),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            Format::from_media_type("application/x-msgpack"),
+            Some(Format::MessagePack)
+        );
+        assert_eq!(Format::from_media_type("text/plain"), None);
+    }
+
+    #[test]
+    fn test_negotiated_response_varies_on_accept() {
+        for format in [Format::Json, Format::MessagePack, Format::Cbor] {
+            let response = Negotiated(format, Envelope::message("hello")).into_response();
+            assert_eq!(
+                response.headers()[header::CONTENT_TYPE],
+                format.content_type()
+            );
+            assert_eq!(response.headers()[header::VARY], "accept");
+        }
+    }
+
+    #[test]
+    fn test_round_trip_all_formats() {
+        let response = Envelope::message("hello");
+
+        for format in [Format::Json, Format::MessagePack, Format::Cbor] {
+            let bytes = format.encode(&response).expect("Should encode");
+            let decoded: Envelope<()> = format.decode(&bytes).expect("Should decode");
+            assert_eq!(decoded.message.as_deref(), Some("hello"));
+            assert_eq!(decoded.status, "success");
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! # GraphQL
+//!
+//! A read-only GraphQL endpoint next to the REST API, compiled only with the
+//! `graphql` feature. Queries are posted as JSON to `/graphql`; in
+//! development `GET /graphql` serves GraphiQL to explore the schema.
+//!
+//! ```graphql
+//! {
+//!   me { username roles }
+//!   categories(perPage: 5) { items { displayName items { title } } total }
+//! }
+//! ```
+//!
+//! Resolvers see the signed-in user the same way the REST handlers do: the
+//! session is checked against the database on every request, so
+//! deactivation and role changes apply immediately. `users` needs the
+//! admin role, like `/api/admin/users`.
+
+use async_graphql::http::GraphiQLSource;
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, ErrorExtensions, Json as GraphqlJson, Object,
+    OutputType, Schema, SimpleObject,
+};
+use axum::{
+    Extension, Json, Router,
+    extract::State,
+    response::Html,
+    routing::{get, post},
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::auth::{ADMIN_ROLE, CurrentUser};
+use crate::config::{AppConfig, Environment};
+use crate::database::Db;
+use crate::error::AppError;
+use crate::models::{AuthenticatedUser, Category, ItemWithCategory, UserFilter, UserResponse};
+use crate::output;
+use crate::pagination::{DEFAULT_PER_PAGE, Paginated, Pagination};
+use crate::services::{CategoryService, EmailDeliveryService, ItemService, UserService};
+use crate::state::AppState;
+
+/// Deepest selection a query may make
+const MAX_DEPTH: usize = 8;
+
+/// Most fields a query may select, counting list items once
+const MAX_COMPLEXITY: usize = 500;
+
+/// The executable schema, built once and shared by every request
+pub type AppSchema = Schema<QueryRoot, EmptyMutation, EmptySubscription>;
+
+/// Build the schema
+pub fn schema() -> AppSchema {
+    Schema::build(QueryRoot, EmptyMutation, EmptySubscription)
+        .limit_depth(MAX_DEPTH)
+        .limit_complexity(MAX_COMPLEXITY)
+        .finish()
+}
+
+/// `POST /graphql`, and GraphiQL at `GET /graphql` in development
+pub fn routes(config: &AppConfig) -> Router<AppState> {
+    let endpoint = if config.environment == Environment::Development {
+        get(serve_graphiql).post(execute)
+    } else {
+        post(execute)
+    };
+    Router::new()
+        .route("/graphql", endpoint)
+        .layer(Extension(schema()))
+}
+
+/// Who is asking, handed to resolvers as request data
+struct Viewer {
+    user: Option<AuthenticatedUser>,
+    roles: Vec<String>,
+}
+
+async fn execute(
+    Extension(schema): Extension<AppSchema>,
+    State(pool): State<PgPool>,
+    State(db): State<Db>,
+    user: Result<CurrentUser, AppError>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let user = user.ok().map(|CurrentUser(user)| user);
+    let roles = match &user {
+        Some(user) => UserService::get_user_roles(&pool, user.id)
+            .await
+            .unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let request = request.data(pool).data(db).data(Viewer { user, roles });
+    Json(schema.execute(request).await)
+}
+
+async fn serve_graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+// =============================================================================
+// Query Root
+// =============================================================================
+
+/// Entry points of the schema
+pub struct QueryRoot;
+
+#[Object]
+impl QueryRoot {
+    /// The signed-in user, or null when signed out
+    async fn me(&self, ctx: &Context<'_>) -> async_graphql::Result<Option<UserResponse>> {
+        let viewer = ctx.data_unchecked::<Viewer>();
+        let Some(user) = &viewer.user else {
+            return Ok(None);
+        };
+        let pool = ctx.data_unchecked::<PgPool>();
+        let user = UserService::get_user_by_id(pool, user.id)
+            .await
+            .map_err(to_graphql)?;
+        Ok(user.map(|user| UserResponse::from(user).with_roles(viewer.roles.clone())))
+    }
+
+    /// Users matching `search`, newest first (admins only)
+    async fn users(
+        &self,
+        ctx: &Context<'_>,
+        search: Option<String>,
+        page: Option<u32>,
+        per_page: Option<u32>,
+    ) -> async_graphql::Result<Page<UserResponse>> {
+        require_admin(ctx)?;
+        let pool = ctx.data_unchecked::<PgPool>();
+        let filter = UserFilter {
+            search,
+            ..UserFilter::default()
+        };
+        let users = UserService::list_users(pool, &filter, &pagination(page, per_page))
+            .await
+            .map_err(to_graphql)?;
+
+        let user_ids: Vec<i32> = users.items.iter().map(|user| user.id).collect();
+        let mut roles = UserService::get_roles_for_users(pool, &user_ids)
+            .await
+            .map_err(to_graphql)?;
+        let emails: Vec<String> = users.items.iter().map(|user| user.email.clone()).collect();
+        let mut undeliverable = EmailDeliveryService::suppression_reasons(pool, &emails)
+            .await
+            .map_err(to_graphql)?;
+        Ok(Page::from_paginated(users, |user| {
+            let user_roles = roles.remove(&user.id).unwrap_or_default();
+            let reason = undeliverable.remove(&user.email.to_lowercase());
+            UserResponse::from(user)
+                .with_roles(user_roles)
+                .with_email_undeliverable(reason)
+        }))
+    }
+
+    /// Active items with their categories, newest first
+    async fn items(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<u32>,
+        per_page: Option<u32>,
+    ) -> async_graphql::Result<Page<ItemWithCategory>> {
+        let db = ctx.data_unchecked::<Db>();
+        let items = ItemService::get_all_items(db.read(), &pagination(page, per_page))
+            .await
+            .map_err(to_graphql)?;
+        Ok(Page::from_paginated(items, |item| item))
+    }
+
+    /// Visible categories in display order
+    async fn categories(
+        &self,
+        ctx: &Context<'_>,
+        page: Option<u32>,
+        per_page: Option<u32>,
+    ) -> async_graphql::Result<Page<Category>> {
+        let db = ctx.data_unchecked::<Db>();
+        let categories =
+            CategoryService::get_all_categories(db.read(), &pagination(page, per_page))
+                .await
+                .map_err(to_graphql)?;
+        Ok(Page::from_paginated(categories, |category| category))
+    }
+
+    /// A visible category by ID
+    async fn category(
+        &self,
+        ctx: &Context<'_>,
+        id: i32,
+    ) -> async_graphql::Result<Option<Category>> {
+        let db = ctx.data_unchecked::<Db>();
+        CategoryService::get_category_by_id(db.read(), id)
+            .await
+            .map_err(to_graphql)
+    }
+}
+
+/// Fail unless the viewer holds the admin role
+fn require_admin(ctx: &Context<'_>) -> async_graphql::Result<()> {
+    let viewer = ctx.data_unchecked::<Viewer>();
+    match &viewer.user {
+        None => Err(to_graphql(AppError::Unauthorized(
+            "Authentication required".to_string(),
+        ))),
+        Some(_) if !viewer.roles.iter().any(|role| role == ADMIN_ROLE) => Err(to_graphql(
+            AppError::Forbidden("Admin role required".to_string()),
+        )),
+        Some(_) => Ok(()),
+    }
+}
+
+/// GraphQL error with the message and code the REST envelope would carry;
+/// server errors are logged, not shown
+fn to_graphql(err: impl Into<AppError>) -> async_graphql::Error {
+    let err = err.into();
+    if err.status_code().is_server_error() {
+        output::error(&err);
+    }
+    let code = err.code();
+    async_graphql::Error::new(err.public_message()).extend_with(|_, e| e.set("code", code))
+}
+
+/// Window for the optional `page` and `perPage` arguments
+fn pagination(page: Option<u32>, per_page: Option<u32>) -> Pagination {
+    Pagination::page(page.unwrap_or(1), per_page.unwrap_or(DEFAULT_PER_PAGE))
+}
+
+// =============================================================================
+// Objects
+// =============================================================================
+
+/// One page of results, like the REST envelope's `pagination`
+#[derive(SimpleObject)]
+#[graphql(concrete(name = "UserPage", params(UserResponse)))]
+#[graphql(concrete(name = "ItemPage", params(ItemWithCategory)))]
+#[graphql(concrete(name = "CategoryPage", params(Category)))]
+pub struct Page<T: OutputType> {
+    items: Vec<T>,
+    page: u32,
+    per_page: u32,
+    total: i64,
+    total_pages: u32,
+}
+
+impl<T: OutputType> Page<T> {
+    fn from_paginated<R>(rows: Paginated<R>, map: impl FnMut(R) -> T) -> Self {
+        Self {
+            page: rows.page,
+            per_page: rows.per_page,
+            total: rows.total,
+            total_pages: rows.total_pages,
+            items: rows.items.into_iter().map(map).collect(),
+        }
+    }
+}
+
+#[Object(name = "User")]
+impl UserResponse {
+    async fn id(&self) -> i32 {
+        self.id
+    }
+
+    async fn username(&self) -> &str {
+        &self.username
+    }
+
+    async fn email(&self) -> &str {
+        &self.email
+    }
+
+    async fn email_verified(&self) -> bool {
+        self.email_verified
+    }
+
+    async fn is_active(&self) -> bool {
+        self.is_active
+    }
+
+    async fn display_name(&self) -> Option<&str> {
+        self.display_name.as_deref()
+    }
+
+    async fn bio(&self) -> Option<&str> {
+        self.bio.as_deref()
+    }
+
+    /// Free-form JSON object kept with the profile
+    async fn profile_data(&self) -> async_graphql::Json<&serde_json::Value> {
+        async_graphql::Json(&self.profile_data)
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.created_at
+    }
+
+    async fn roles(&self) -> &[String] {
+        &self.roles
+    }
+
+    /// Why mail to this address is suppressed (`bounce` or `complaint`)
+    async fn email_undeliverable(&self) -> Option<&str> {
+        self.email_undeliverable.as_deref()
+    }
+}
+
+#[Object(name = "Item")]
+impl ItemWithCategory {
+    async fn id(&self) -> i32 {
+        self.item.id
+    }
+
+    async fn title(&self) -> &str {
+        &self.item.title
+    }
+
+    async fn description(&self) -> Option<&str> {
+        self.item.description.as_deref()
+    }
+
+    /// Free-form data attached to the item
+    async fn data(&self) -> Option<GraphqlJson<serde_json::Value>> {
+        self.item.data.clone().map(GraphqlJson)
+    }
+
+    async fn category(&self) -> &Category {
+        &self.category
+    }
+
+    async fn created_at(&self) -> DateTime<Utc> {
+        self.item.created_at
+    }
+
+    async fn updated_at(&self) -> DateTime<Utc> {
+        self.item.updated_at
+    }
+}
+
+#[Object]
+impl Category {
+    async fn id(&self) -> i32 {
+        self.id
+    }
+
+    /// Stable name used in URLs and code
+    async fn name(&self) -> &str {
+        &self.category_name
+    }
+
+    async fn display_name(&self) -> &str {
+        &self.display_name
+    }
+
+    async fn display_order(&self) -> i32 {
+        self.display_order
+    }
+
+    /// Active items in the category, newest first
+    async fn items(&self, ctx: &Context<'_>) -> async_graphql::Result<Vec<ItemWithCategory>> {
+        let db = ctx.data_unchecked::<Db>();
+        let items = ItemService::get_items_by_category(db.read(), self.id)
+            .await
+            .map_err(to_graphql)?;
+        Ok(items
+            .into_iter()
+            .map(|item| ItemWithCategory {
+                item,
+                category: self.clone(),
+            })
+            .collect())
+    }
+}
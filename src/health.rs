@@ -0,0 +1,402 @@
+//! # Health History
+//!
+//! The last [`CAPACITY`] readiness probes of this instance, kept in
+//! [`AppState::health`](crate::state::AppState::health), and the flap
+//! detection that reads them. A probe is taken on every `GET /health/ready`
+//! that finds a free pool connection and by the `health-probe`
+//! [scheduled task](crate::scheduler::core_tasks), so the history fills even
+//! when nothing polls. Admins read it at `/api/admin/health/history`.
+//!
+//! A dependency that went between up and down [`FLAP_THRESHOLD`] times
+//! within [`FLAP_WINDOW`] is `unstable` until it has been steady long enough
+//! for those changes to age out. Readiness stays off while anything is
+//! unstable, so a load balancer sees one outage instead of an instance
+//! dropping in and out of rotation on every poll.
+//!
+//! `GET /health` reports on every component registered as a [`HealthCheck`]:
+//! the [core checks](core_checks) for the database, upload storage, and
+//! outgoing mail, then those of plugins and the app. Each check has
+//! [`CHECK_TIMEOUT`] to answer. A critical component that is down makes the
+//! service `unhealthy`; any other makes it `degraded`.
+//!
+//! ```ignore
+//! struct Redis(redis::Client);
+//!
+//! #[async_trait]
+//! impl HealthCheck for Redis {
+//!     fn name(&self) -> &'static str {
+//!         "redis"
+//!     }
+//!
+//!     async fn check(&self, _state: &AppState) -> Result<(), String> {
+//!         let mut conn = self.0.get_multiplexed_async_connection().await.map_err(|e| e.to_string())?;
+//!         redis::cmd("PING").query_async(&mut conn).await.map_err(|e| e.to_string())
+//!     }
+//! }
+//!
+//! let plugins = PluginRegistry::new().with_health_check(Redis(client));
+//! ```
+
+use async_trait::async_trait;
+use axum::extract::State;
+use axum::{Router, routing::get};
+use chrono::{DateTime, Utc};
+use futures_util::future::join_all;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::RequestId;
+use crate::auth::AdminUser;
+use crate::database::test_connection;
+use crate::error::AppResult;
+use crate::formats::{Accept, Negotiated};
+use crate::jobs;
+use crate::models::{
+    ComponentHealth, Envelope, HealthHistoryResponse, HealthSample, HealthStatus, HealthVerdict,
+    WorkerLiveness,
+};
+use crate::output;
+use crate::services::JobService;
+use crate::state::AppState;
+
+/// Probes kept per instance; at [`PROBE_INTERVAL`] this is an hour
+pub const CAPACITY: usize = 120;
+
+/// How often the scheduler probes when nothing else does
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How far back changes count towards flapping
+pub const FLAP_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Changes within [`FLAP_WINDOW`] that make a dependency unstable
+pub const FLAP_THRESHOLD: usize = 4;
+
+/// Readiness probes, oldest first
+#[derive(Debug, Default)]
+pub struct HealthHistory {
+    samples: Mutex<VecDeque<HealthSample>>,
+}
+
+#[allow(dead_code)]
+impl HealthHistory {
+    /// Keep `sample`, dropping the oldest once [`CAPACITY`] is reached
+    pub fn record(&self, sample: HealthSample) {
+        let mut samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        if samples.len() == CAPACITY {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// The probes kept, oldest first
+    pub fn samples(&self) -> Vec<HealthSample> {
+        let samples = self.samples.lock().unwrap_or_else(|e| e.into_inner());
+        samples.iter().cloned().collect()
+    }
+
+    /// Status of each dependency and the probes they were judged on
+    pub fn report(&self, now: DateTime<Utc>) -> HealthHistoryResponse {
+        let samples = self.samples();
+        let database = status(&samples, now, |sample| sample.database);
+        let workers = status(&samples, now, |sample| sample.workers);
+        HealthHistoryResponse {
+            status: worst(database, workers),
+            database,
+            workers,
+            samples,
+        }
+    }
+}
+
+/// Probe the database and job workers and record the result
+pub async fn probe(state: &AppState) -> (HealthSample, WorkerLiveness) {
+    let workers = JobService::list_workers(&state.pool, state.config.jobs.stall_timeout_secs).await;
+    let (database, workers) = match workers {
+        Ok(workers) => (true, jobs::liveness(&workers)),
+        Err(err) => {
+            output::warn(format!("Readiness check failed: {}", err));
+            (false, WorkerLiveness::default())
+        }
+    };
+
+    let sample = HealthSample {
+        at: Utc::now(),
+        database,
+        workers: workers.alive > 0,
+    };
+    state.health.record(sample.clone());
+    (sample, workers)
+}
+
+/// Scheduled probe, so the history covers quiet periods
+pub async fn record_probe(state: AppState) -> Result<(), String> {
+    probe(&state).await;
+    Ok(())
+}
+
+/// Status of one dependency: unstable when it changed [`FLAP_THRESHOLD`]
+/// times within [`FLAP_WINDOW`] of `now`, otherwise its latest probe
+fn status(
+    samples: &[HealthSample],
+    now: DateTime<Utc>,
+    up: impl Fn(&HealthSample) -> bool,
+) -> HealthStatus {
+    let since = now - chrono::Duration::from_std(FLAP_WINDOW).unwrap_or(chrono::Duration::MAX);
+    let changes = samples
+        .windows(2)
+        .filter(|pair| pair[1].at >= since && up(&pair[0]) != up(&pair[1]))
+        .count();
+
+    if changes >= FLAP_THRESHOLD {
+        HealthStatus::Unstable
+    } else if samples.last().is_some_and(up) {
+        HealthStatus::Healthy
+    } else {
+        HealthStatus::Unhealthy
+    }
+}
+
+/// Unstable over unhealthy over healthy
+pub fn worst(a: HealthStatus, b: HealthStatus) -> HealthStatus {
+    match (a, b) {
+        (HealthStatus::Unstable, _) | (_, HealthStatus::Unstable) => HealthStatus::Unstable,
+        (HealthStatus::Unhealthy, _) | (_, HealthStatus::Unhealthy) => HealthStatus::Unhealthy,
+        _ => HealthStatus::Healthy,
+    }
+}
+
+// =============================================================================
+// Health Checks
+// =============================================================================
+
+/// How long a check may take before its component counts as down
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A component `/health` reports on
+#[async_trait]
+pub trait HealthCheck: Send + Sync + 'static {
+    /// Name shown in the report, such as `database`
+    fn name(&self) -> &'static str;
+
+    /// Whether the service is unhealthy, rather than degraded, while this
+    /// component is down
+    fn critical(&self) -> bool {
+        false
+    }
+
+    /// Ok when the component is usable; the error says what is wrong
+    async fn check(&self, state: &AppState) -> Result<(), String>;
+}
+
+/// Checks of the components every app has, run before those of plugins and
+/// the app
+pub fn core_checks() -> Vec<Arc<dyn HealthCheck>> {
+    vec![
+        Arc::new(DatabaseCheck),
+        Arc::new(StorageCheck),
+        Arc::new(MailCheck),
+    ]
+}
+
+/// Run `checks` at the same time and report on each
+pub async fn run_checks(state: &AppState, checks: &[Arc<dyn HealthCheck>]) -> Vec<ComponentHealth> {
+    join_all(checks.iter().map(|check| async move {
+        let started = Instant::now();
+        let result = match tokio::time::timeout(CHECK_TIMEOUT, check.check(state)).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("no answer within {}s", CHECK_TIMEOUT.as_secs())),
+        };
+        ComponentHealth {
+            name: check.name().to_string(),
+            up: result.is_ok(),
+            critical: check.critical(),
+            latency_ms: started.elapsed().as_millis() as u64,
+            error: result.err(),
+        }
+    }))
+    .await
+}
+
+/// Unhealthy when a critical component is down, degraded when another is
+pub fn verdict(components: &[ComponentHealth]) -> HealthVerdict {
+    let down = |critical: bool| {
+        components
+            .iter()
+            .any(|component| !component.up && component.critical == critical)
+    };
+    if down(true) {
+        HealthVerdict::Unhealthy
+    } else if down(false) {
+        HealthVerdict::Degraded
+    } else {
+        HealthVerdict::Healthy
+    }
+}
+
+/// The database answers a query; critical
+struct DatabaseCheck;
+
+#[async_trait]
+impl HealthCheck for DatabaseCheck {
+    fn name(&self) -> &'static str {
+        "database"
+    }
+
+    fn critical(&self) -> bool {
+        true
+    }
+
+    async fn check(&self, state: &AppState) -> Result<(), String> {
+        match test_connection(&state.pool).await {
+            Ok(true) => Ok(()),
+            Ok(false) => Err("unexpected result".to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+}
+
+/// Upload storage can be reached
+struct StorageCheck;
+
+#[async_trait]
+impl HealthCheck for StorageCheck {
+    fn name(&self) -> &'static str {
+        "storage"
+    }
+
+    async fn check(&self, state: &AppState) -> Result<(), String> {
+        state.storage.check().await.map_err(|e| e.to_string())
+    }
+}
+
+/// The SMTP server accepts connections
+struct MailCheck;
+
+#[async_trait]
+impl HealthCheck for MailCheck {
+    fn name(&self) -> &'static str {
+        "mail"
+    }
+
+    async fn check(&self, state: &AppState) -> Result<(), String> {
+        state.mailer.check().await.map_err(|e| e.to_string())
+    }
+}
+
+/// `/api/admin/health/history`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/admin/health/history", get(health_history))
+}
+
+/// Recent readiness probes of this instance and whether anything is flapping
+#[utoipa::path(
+    get,
+    path = "/api/admin/health/history",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Probes oldest first, with a status per dependency", body = Envelope<HealthHistoryResponse>),
+        (status = 401, description = "Not signed in"),
+        (status = 403, description = "Not an admin")
+    ),
+    security(("session" = []))
+)]
+pub async fn health_history(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<HealthHistoryResponse>>> {
+    // Probe first so the report includes the current state
+    probe(&state).await;
+    Ok(Negotiated(
+        format,
+        Envelope::success(state.health.report(Utc::now())).with_request_id(request_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(seconds_ago: i64, database: bool) -> HealthSample {
+        HealthSample {
+            at: Utc::now() - chrono::Duration::seconds(seconds_ago),
+            database,
+            workers: true,
+        }
+    }
+
+    #[test]
+    fn test_record_keeps_the_latest() {
+        let history = HealthHistory::default();
+        for i in 0..CAPACITY + 5 {
+            history.record(sample(0, i >= 5));
+        }
+        let samples = history.samples();
+        assert_eq!(samples.len(), CAPACITY);
+        // The first five were dropped
+        assert!(samples.iter().all(|sample| sample.database));
+    }
+
+    #[test]
+    fn test_status() {
+        let now = Utc::now();
+        let up = |sample: &HealthSample| sample.database;
+
+        assert_eq!(status(&[], now, up), HealthStatus::Unhealthy);
+        assert_eq!(
+            status(&[sample(20, false), sample(10, true)], now, up),
+            HealthStatus::Healthy
+        );
+        assert_eq!(
+            status(&[sample(20, true), sample(10, false)], now, up),
+            HealthStatus::Unhealthy
+        );
+
+        // Bouncing within the window
+        let flapping: Vec<_> = (0..5).map(|i| sample(50 - i * 10, i % 2 == 0)).collect();
+        assert_eq!(status(&flapping, now, up), HealthStatus::Unstable);
+
+        // The same bouncing, long enough ago to have aged out
+        let settled: Vec<_> = (0..5)
+            .map(|i| sample(1000 - i * 10, i % 2 == 0))
+            .chain([sample(10, true)])
+            .collect();
+        assert_eq!(status(&settled, now, up), HealthStatus::Healthy);
+    }
+
+    #[test]
+    fn test_verdict() {
+        let component = |up: bool, critical: bool| ComponentHealth {
+            name: "component".to_string(),
+            up,
+            critical,
+            latency_ms: 1,
+            error: None,
+        };
+
+        assert_eq!(verdict(&[]), HealthVerdict::Healthy);
+        assert_eq!(
+            verdict(&[component(true, true), component(true, false)]),
+            HealthVerdict::Healthy
+        );
+        assert_eq!(
+            verdict(&[component(true, true), component(false, false)]),
+            HealthVerdict::Degraded
+        );
+        assert_eq!(
+            verdict(&[component(false, true), component(false, false)]),
+            HealthVerdict::Unhealthy
+        );
+    }
+
+    #[test]
+    fn test_worst() {
+        use HealthStatus::*;
+        assert_eq!(worst(Healthy, Healthy), Healthy);
+        assert_eq!(worst(Healthy, Unhealthy), Unhealthy);
+        assert_eq!(worst(Unstable, Unhealthy), Unstable);
+        assert_eq!(worst(Healthy, Unstable), Unstable);
+    }
+}
@@ -0,0 +1,253 @@
+//! # Localization
+//!
+//! Message catalogs for user-facing text. Each supported locale has a TOML
+//! file under `locales/`, compiled into the binary, whose nested tables
+//! flatten to dotted keys such as `email.welcome.subject`. `{name}`
+//! placeholders in a message are filled from the caller's variables.
+//!
+//! A key is looked up along a fallback chain: the requested locale, then its
+//! language without region or script, then [`DEFAULT_LOCALE`]. A user with
+//! locale `de-AT` therefore gets German, and one with `fr` gets English.
+
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::OnceLock;
+
+/// Locale every other catalog falls back to
+pub const DEFAULT_LOCALE: &str = "en";
+
+/// Locales with a catalog, and their names in their own language
+pub const SUPPORTED_LOCALES: &[(&str, &str)] =
+    &[("en", "English"), ("de", "Deutsch"), ("es", "Español")];
+
+/// Longest locale tag accepted (the width of `users.locale`)
+pub const MAX_LOCALE_LEN: usize = 35;
+
+const CATALOG_SOURCES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.toml")),
+    ("de", include_str!("../locales/de.toml")),
+    ("es", include_str!("../locales/es.toml")),
+];
+
+type Catalog = BTreeMap<String, String>;
+
+static CATALOGS: OnceLock<HashMap<&'static str, Catalog>> = OnceLock::new();
+
+fn catalogs() -> &'static HashMap<&'static str, Catalog> {
+    CATALOGS.get_or_init(|| {
+        CATALOG_SOURCES
+            .iter()
+            .map(|(locale, source)| {
+                let table: toml::Table = toml::from_str(source)
+                    .unwrap_or_else(|e| panic!("invalid locales/{}.toml: {}", locale, e));
+                let mut catalog = Catalog::new();
+                flatten("", &toml::Value::Table(table), &mut catalog);
+                (*locale, catalog)
+            })
+            .collect()
+    })
+}
+
+fn flatten(prefix: &str, value: &toml::Value, catalog: &mut Catalog) {
+    match value {
+        toml::Value::Table(table) => {
+            for (key, value) in table {
+                let key = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten(&key, value, catalog);
+            }
+        }
+        toml::Value::String(message) => {
+            catalog.insert(prefix.to_string(), message.clone());
+        }
+        other => {
+            catalog.insert(prefix.to_string(), other.to_string());
+        }
+    }
+}
+
+/// Canonical form of a locale tag (`pt_br` becomes `pt-BR`), or `None` if
+/// it is not a well-formed language tag
+pub fn normalize(tag: &str) -> Option<String> {
+    let tag = tag.trim();
+    if tag.is_empty() || tag.len() > MAX_LOCALE_LEN {
+        return None;
+    }
+
+    let mut subtags = tag.split(['-', '_']);
+    let language = subtags.next()?;
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+
+    let mut normalized = language.to_ascii_lowercase();
+    for subtag in subtags {
+        if subtag.is_empty()
+            || subtag.len() > 8
+            || !subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        {
+            return None;
+        }
+        normalized.push('-');
+        match subtag.len() {
+            // Region, e.g. BR
+            2 => normalized.push_str(&subtag.to_ascii_uppercase()),
+            // Script, e.g. Hant
+            4 if subtag.chars().all(|c| c.is_ascii_alphabetic()) => {
+                normalized.push_str(&subtag[..1].to_ascii_uppercase());
+                normalized.push_str(&subtag[1..].to_ascii_lowercase());
+            }
+            _ => normalized.push_str(&subtag.to_ascii_lowercase()),
+        }
+    }
+    Some(normalized)
+}
+
+/// Locales to try for a requested locale, most specific first and ending
+/// with [`DEFAULT_LOCALE`]
+pub fn fallback_chain(locale: Option<&str>) -> Vec<String> {
+    let mut chain = Vec::new();
+    if let Some(locale) = locale.and_then(normalize) {
+        let subtags: Vec<&str> = locale.split('-').collect();
+        for len in (1..=subtags.len()).rev() {
+            chain.push(subtags[..len].join("-"));
+        }
+    }
+    if !chain.iter().any(|locale| locale == DEFAULT_LOCALE) {
+        chain.push(DEFAULT_LOCALE.to_string());
+    }
+    chain
+}
+
+/// The catalog a requested locale resolves to, for `lang` attributes
+pub fn resolve(locale: Option<&str>) -> &'static str {
+    fallback_chain(locale)
+        .iter()
+        .find_map(|candidate| catalogs().get_key_value(candidate.as_str()))
+        .map_or(DEFAULT_LOCALE, |(locale, _)| *locale)
+}
+
+/// A message in the first locale of the chain that defines it
+pub fn lookup(locale: Option<&str>, key: &str) -> Option<&'static str> {
+    fallback_chain(locale).iter().find_map(|candidate| {
+        catalogs()
+            .get(candidate.as_str())
+            .and_then(|catalog| catalog.get(key))
+            .map(String::as_str)
+    })
+}
+
+/// Every message under `prefix`, keyed by the rest of its key, resolved for
+/// `locale` and with placeholders filled from `variables`
+pub fn messages(locale: Option<&str>, prefix: &str, variables: &Value) -> Map<String, Value> {
+    let prefix = format!("{}.", prefix);
+    catalogs()[DEFAULT_LOCALE]
+        .keys()
+        .filter_map(|key| {
+            let name = key.strip_prefix(&prefix)?;
+            let message = lookup(locale, key)?;
+            Some((
+                name.to_string(),
+                Value::String(interpolate(message, variables)),
+            ))
+        })
+        .collect()
+}
+
+/// Keys of the default catalog that `locale`'s own catalog does not define
+pub fn missing_keys(locale: &str) -> Vec<String> {
+    let Some(catalog) = catalogs().get(locale) else {
+        return catalogs()[DEFAULT_LOCALE].keys().cloned().collect();
+    };
+    catalogs()[DEFAULT_LOCALE]
+        .keys()
+        .filter(|key| !catalog.contains_key(*key))
+        .cloned()
+        .collect()
+}
+
+/// Replace `{name}` with the string or number `variables[name]`; unknown
+/// placeholders are left as they are
+fn interpolate(message: &str, variables: &Value) -> String {
+    let mut result = String::with_capacity(message.len());
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let value = after.find('}').and_then(|end| {
+            let value = match variables.get(&after[..end])? {
+                Value::String(s) => s.clone(),
+                Value::Number(n) => n.to_string(),
+                _ => return None,
+            };
+            Some((value, end))
+        });
+        match value {
+            Some((value, end)) => {
+                result.push_str(&value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_every_locale_defines_every_key() {
+        for (locale, _) in SUPPORTED_LOCALES {
+            assert!(catalogs().contains_key(locale), "no catalog for {}", locale);
+            assert_eq!(missing_keys(locale), Vec::<String>::new(), "{}", locale);
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("pt_br").as_deref(), Some("pt-BR"));
+        assert_eq!(normalize("ZH-hant-tw").as_deref(), Some("zh-Hant-TW"));
+        assert_eq!(normalize(" de ").as_deref(), Some("de"));
+        assert_eq!(normalize(""), None);
+        assert_eq!(normalize("english"), None);
+        assert_eq!(normalize("de-"), None);
+        assert_eq!(normalize("de-<b>"), None);
+    }
+
+    #[test]
+    fn test_fallback_chain() {
+        assert_eq!(fallback_chain(Some("de-AT")), ["de-AT", "de", "en"]);
+        assert_eq!(fallback_chain(Some("en-GB")), ["en-GB", "en"]);
+        assert_eq!(fallback_chain(Some("not a locale")), ["en"]);
+        assert_eq!(fallback_chain(None), ["en"]);
+
+        assert_eq!(resolve(Some("de-AT")), "de");
+        assert_eq!(resolve(Some("fr")), "en");
+        assert_eq!(
+            lookup(Some("es-MX"), "email.password_changed.heading"),
+            Some("Se cambió tu contraseña")
+        );
+        assert_eq!(lookup(Some("de"), "email.missing"), None);
+    }
+
+    #[test]
+    fn test_messages_are_interpolated() {
+        let variables = json!({ "username": "jane", "service_name": "Axum Base" });
+        let messages = messages(Some("de"), "email.welcome", &variables);
+        assert_eq!(messages["subject"], "Willkommen bei Axum Base, jane");
+        assert!(!messages.contains_key("email.welcome.subject"));
+
+        assert_eq!(interpolate("{a} and {b}", &json!({ "a": 1 })), "1 and {b}");
+        assert_eq!(interpolate("{unclosed", &json!({})), "{unclosed");
+    }
+}
@@ -0,0 +1,211 @@
+//! # Item Filters
+//!
+//! The [`ItemFilter`] extractor reads what `/api/items` is narrowed to and
+//! sorted by from the query string:
+//!
+//! - `category_id`: only items in this category
+//! - `is_active=false`: inactive items instead of active ones (admins only)
+//! - `created_after`, `created_before`: RFC 3339 times, inclusive
+//! - `sort=created_at|title` and `direction=asc|desc`: newest first by
+//!   default, and A to Z when sorting by title
+//!
+//! Filter values are bound as query parameters, and so are the sort and
+//! direction, as the fixed names from [`ItemSort::as_str`] and
+//! [`SortDirection::as_str`], so no request text reaches the SQL.
+
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::AppError;
+
+/// What items are sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemSort {
+    #[default]
+    CreatedAt,
+    Title,
+}
+
+impl ItemSort {
+    /// Name of the sort as bound into listing queries
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ItemSort::CreatedAt => "created_at",
+            ItemSort::Title => "title",
+        }
+    }
+
+    /// Direction used when the request names none
+    pub fn default_direction(self) -> SortDirection {
+        match self {
+            ItemSort::CreatedAt => SortDirection::Desc,
+            ItemSort::Title => SortDirection::Asc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+
+impl SortDirection {
+    /// Name of the direction as bound into listing queries
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SortDirection::Asc => "asc",
+            SortDirection::Desc => "desc",
+        }
+    }
+}
+
+/// Filter and sort parameters as they appear in the query string
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ItemFilterQuery {
+    /// Only items in this category
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<i32>,
+    /// `false` lists inactive items instead of active ones (admins only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+    /// Items created at or after this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Items created at or before this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+    /// `created_at` (default) or `title`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[param(inline)]
+    pub sort: Option<ItemSort>,
+    /// `asc` or `desc`; newest first and A to Z by default
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[param(inline)]
+    pub direction: Option<SortDirection>,
+}
+
+/// Checked filters and order for listing items
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemFilter {
+    pub category_id: Option<i32>,
+    /// List active items, or else inactive ones
+    pub is_active: bool,
+    pub created_after: Option<DateTime<Utc>>,
+    pub created_before: Option<DateTime<Utc>>,
+    pub sort: ItemSort,
+    pub direction: SortDirection,
+}
+
+impl Default for ItemFilter {
+    /// Every active item, newest first
+    fn default() -> Self {
+        Self {
+            category_id: None,
+            is_active: true,
+            created_after: None,
+            created_before: None,
+            sort: ItemSort::default(),
+            direction: ItemSort::default().default_direction(),
+        }
+    }
+}
+
+impl ItemFilter {
+    /// Check a query and fill in defaults
+    pub fn from_query(query: ItemFilterQuery) -> Result<Self, String> {
+        if query
+            .created_after
+            .zip(query.created_before)
+            .is_some_and(|(after, before)| after > before)
+        {
+            return Err("created_after must not be later than created_before".to_string());
+        }
+        let sort = query.sort.unwrap_or_default();
+        Ok(Self {
+            category_id: query.category_id,
+            is_active: query.is_active.unwrap_or(true),
+            created_after: query.created_after,
+            created_before: query.created_before,
+            sort,
+            direction: query.direction.unwrap_or_else(|| sort.default_direction()),
+        })
+    }
+}
+
+impl<S> FromRequestParts<S> for ItemFilter
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<ItemFilterQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|err| AppError::Validation(err.body_text()))?;
+
+        ItemFilter::from_query(query).map_err(AppError::Validation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(
+            ItemFilter::from_query(ItemFilterQuery::default()),
+            Ok(ItemFilter::default())
+        );
+        assert_eq!(ItemFilter::default().sort, ItemSort::CreatedAt);
+        assert_eq!(ItemFilter::default().direction, SortDirection::Desc);
+    }
+
+    #[test]
+    fn test_sort_direction_defaults_per_field() {
+        let by_title = ItemFilter::from_query(ItemFilterQuery {
+            sort: Some(ItemSort::Title),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(by_title.direction, SortDirection::Asc);
+
+        let oldest_first = ItemFilter::from_query(ItemFilterQuery {
+            direction: Some(SortDirection::Asc),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(oldest_first.sort, ItemSort::CreatedAt);
+        assert_eq!(oldest_first.direction, SortDirection::Asc);
+    }
+
+    #[test]
+    fn test_bound_names_match_the_query_string() {
+        for sort in [ItemSort::CreatedAt, ItemSort::Title] {
+            assert_eq!(serde_json::to_value(sort).unwrap(), sort.as_str());
+        }
+        for direction in [SortDirection::Asc, SortDirection::Desc] {
+            assert_eq!(serde_json::to_value(direction).unwrap(), direction.as_str());
+        }
+    }
+
+    #[test]
+    fn test_rejects_inverted_date_range() {
+        let now = Utc::now();
+        let query = ItemFilterQuery {
+            created_after: Some(now),
+            created_before: Some(now - chrono::Duration::days(1)),
+            ..Default::default()
+        };
+        assert!(ItemFilter::from_query(query).is_err());
+    }
+}
@@ -0,0 +1,611 @@
+//! # Background Jobs
+//!
+//! A Postgres-backed job queue. [`enqueue`] stores a job in the `jobs` table;
+//! the [`Worker`] started with the server polls for due jobs, claims them with
+//! `FOR UPDATE SKIP LOCKED` so several instances can share one queue, and
+//! runs the handler registered for each job's kind. A failed job is retried
+//! after a growing delay until it has used `max_attempts`.
+//!
+//! [`enqueue_with`] sets a job's [`Priority`] and when it becomes due. Due
+//! jobs are claimed highest priority first, but every `JOB_PRIORITY_AGING_SECS`
+//! a job waits raises it one level, so urgent mail overtakes batch work
+//! without batch work waiting forever behind a steady stream of it.
+//!
+//! Every `JOB_HEARTBEAT_INTERVAL` seconds a worker records a heartbeat for
+//! itself and the jobs it is running, then runs the [`watchdog`]. A worker
+//! silent for `JOB_STALL_TIMEOUT` seconds counts as stalled: its jobs are
+//! requeued (or failed, if that was their last attempt) and an alert is logged
+//! and sent to `JOB_ALERT_EMAIL`. A stalled worker that wakes up again cannot
+//! record results for jobs taken from it.
+//!
+//! Admins see queue sizes and worker liveness at `/admin/jobs` and
+//! `/api/admin/jobs`; `/health/ready` fails while no worker is alive.
+//! [`JobMetrics`] adds queue sizes, outcomes, and wait and run times to
+//! `/metrics`.
+//!
+//! On shutdown the worker stops polling at once and gives running jobs
+//! `JOB_SHUTDOWN_GRACE` seconds to finish. Jobs still running after that are
+//! released back to the queue without counting the interrupted attempt, so
+//! the next instance runs them once instead of the old one finishing them
+//! too.
+
+use axum::{Router, extract::State, routing::get};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::{self, JoinError, JoinSet};
+use tokio::time::MissedTickBehavior;
+
+use crate::api::RequestId;
+use crate::auth::AdminUser;
+use crate::db_metrics;
+use crate::emails;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::mailer::Email;
+use crate::metrics::{CounterVec, GaugeVec, HistogramVec, MetricSource};
+use crate::models::{Envelope, Job, JobWorker, JobsOverview, WorkerLiveness};
+use crate::output;
+use crate::services::JobService;
+use crate::state::AppState;
+
+/// What a job handler returns; errors are stored as the job's `last_error`
+pub type JobFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+type Handler = Arc<dyn Fn(AppState, Value) -> JobFuture + Send + Sync>;
+
+/// ID and kind of the job each running task is working on
+type Claimed = HashMap<task::Id, (i64, String)>;
+
+/// Statuses a job moves through, for the `jobs` gauge
+const STATUSES: &[&str] = &["queued", "running", "done", "failed"];
+
+/// How urgent a job is
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Batch work such as digests
+    Low,
+    #[default]
+    Normal,
+    /// Work a user is waiting for, such as security notices
+    High,
+}
+
+impl Priority {
+    /// Level stored in `jobs.priority`
+    pub fn level(self) -> i16 {
+        match self {
+            Priority::Low => 0,
+            Priority::Normal => 1,
+            Priority::High => 2,
+        }
+    }
+}
+
+/// Priority and schedule of a queued job
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JobOptions {
+    pub priority: Priority,
+    /// When the job becomes due; now if unset
+    pub run_at: Option<DateTime<Utc>>,
+}
+
+#[allow(dead_code)]
+impl JobOptions {
+    /// Run with `priority`
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Run no earlier than `run_at`
+    pub fn run_at(mut self, run_at: DateTime<Utc>) -> Self {
+        self.run_at = Some(run_at);
+        self
+    }
+
+    /// Run no earlier than `delay` from now
+    pub fn delay(self, delay: Duration) -> Self {
+        let delay = chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::MAX);
+        self.run_at(Utc::now() + delay)
+    }
+}
+
+/// Queue a job of `kind` at normal priority, due now; the payload is handed
+/// to its handler
+#[allow(dead_code)]
+pub async fn enqueue(pool: &PgPool, kind: &str, payload: &impl Serialize) -> AppResult<i64> {
+    enqueue_with(pool, kind, payload, JobOptions::default()).await
+}
+
+/// Queue a job of `kind` with a priority and schedule
+pub async fn enqueue_with(
+    pool: &PgPool,
+    kind: &str,
+    payload: &impl Serialize,
+    options: JobOptions,
+) -> AppResult<i64> {
+    let payload = serde_json::to_value(payload)
+        .map_err(|e| AppError::Internal(format!("Failed to encode job payload: {}", e)))?;
+    Ok(JobService::enqueue(
+        pool,
+        kind,
+        &payload,
+        options.priority.level(),
+        options.run_at,
+    )
+    .await?)
+}
+
+/// Routes for `/api/admin/jobs`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/admin/jobs", get(jobs_overview))
+}
+
+/// Outbox name of stalled-worker alerts
+pub const ALERT_TEMPLATE: &str = "job_alert";
+
+/// Queue sizes and the jobs this instance ran, served at `/metrics`
+#[derive(Debug)]
+pub struct JobMetrics {
+    /// Jobs by status, as of the last heartbeat of a worker here
+    pub by_status: GaugeVec,
+    /// Jobs run here by kind and outcome: `done`, `failed`, `discarded`
+    /// (requeued while it ran), or `panicked`
+    pub finished: CounterVec,
+    /// Time from when a job was due until it was claimed
+    pub wait: HistogramVec,
+    /// Time a job's handler ran
+    pub duration: HistogramVec,
+}
+
+impl Default for JobMetrics {
+    fn default() -> Self {
+        Self {
+            by_status: GaugeVec::new(
+                "jobs",
+                "Jobs in the queue by status, as of the last worker heartbeat",
+                &["status"],
+            ),
+            finished: CounterVec::new(
+                "jobs_finished_total",
+                "Jobs run on this instance by kind and outcome",
+                &["kind", "outcome"],
+            ),
+            wait: HistogramVec::new(
+                "job_wait_seconds",
+                "Time from when a job was due until a worker claimed it",
+                &["kind"],
+            ),
+            duration: HistogramVec::new("job_duration_seconds", "Time job handlers ran", &["kind"]),
+        }
+    }
+}
+
+impl MetricSource for JobMetrics {
+    fn render(&self, out: &mut String) {
+        self.by_status.render(out);
+        self.finished.render(out);
+        self.wait.render(out);
+        self.duration.render(out);
+    }
+}
+
+/// Runs queued jobs until shutdown
+pub struct Worker {
+    state: AppState,
+    id: String,
+    hostname: String,
+    handlers: HashMap<&'static str, Handler>,
+    metrics: Arc<JobMetrics>,
+}
+
+impl Worker {
+    /// Worker with the application's and plugins' job handlers registered
+    pub fn new(state: AppState) -> Self {
+        let plugins = state.plugins.clone();
+        let id = format!(
+            "{}-{}",
+            std::process::id(),
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        let metrics = state.metrics.source::<JobMetrics>();
+        let worker = Self {
+            state,
+            id,
+            hostname: hostname(),
+            handlers: HashMap::new(),
+            metrics,
+        }
+        .register(emails::SEND_EMAIL_JOB, emails::run_send_job);
+        plugins.jobs(worker)
+    }
+
+    /// Run `handler` for jobs of `kind`
+    pub fn register<F, Fut>(mut self, kind: &'static str, handler: F) -> Self
+    where
+        F: Fn(AppState, Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        self.handlers.insert(
+            kind,
+            Arc::new(move |state, payload| Box::pin(handler(state, payload))),
+        );
+        self
+    }
+
+    /// Name recorded in `locked_by` for the jobs this worker claims
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Poll for and run jobs until `shutdown` turns true (or its sender is
+    /// dropped), then drain the jobs still running
+    pub async fn run(self, mut shutdown: watch::Receiver<bool>) {
+        let config = self.state.config.jobs.clone();
+        let poll_interval = Duration::from_millis(config.poll_interval_ms);
+        let mut heartbeat = heartbeat_interval(config.heartbeat_interval_secs);
+        let mut running = JoinSet::new();
+        let mut claimed = Claimed::new();
+
+        loop {
+            if *shutdown.borrow() {
+                break;
+            }
+
+            while let Some(result) = running.try_join_next_with_id() {
+                self.finished(result, &mut claimed).await;
+            }
+
+            let free = config.concurrency().saturating_sub(running.len());
+            if free > 0 {
+                let claimed_jobs = JobService::claim(
+                    &self.state.pool,
+                    &self.id,
+                    free as i64,
+                    config.priority_aging_secs,
+                )
+                .await;
+                match claimed_jobs {
+                    Ok(jobs) => {
+                        for job in jobs {
+                            let claim = (job.id, job.kind.clone());
+                            let handle = running.spawn(self.execute(job));
+                            claimed.insert(handle.id(), claim);
+                        }
+                    }
+                    Err(e) => output::error(format!("Failed to claim jobs: {}", e)),
+                }
+            }
+
+            tokio::select! {
+                changed = shutdown.changed() => {
+                    if changed.is_err() {
+                        break;
+                    }
+                }
+                Some(result) = running.join_next_with_id() => {
+                    self.finished(result, &mut claimed).await;
+                }
+                _ = heartbeat.tick() => {
+                    self.heartbeat(&claimed).await;
+                    if let Err(e) = watchdog(&self.state).await {
+                        output::error(format!("Job watchdog failed: {}", e));
+                    }
+                    self.count_jobs().await;
+                }
+                _ = tokio::time::sleep(poll_interval) => {}
+            }
+        }
+
+        self.drain(
+            running,
+            claimed,
+            Duration::from_secs(config.shutdown_grace_secs),
+            heartbeat,
+        )
+        .await;
+        if let Err(e) = JobService::deregister(&self.state.pool, &self.id).await {
+            output::error(format!(
+                "Failed to deregister job worker {}: {}",
+                self.id, e
+            ));
+        }
+    }
+
+    /// Wait up to `grace` for running jobs, then release the rest. Heartbeats
+    /// continue so a long grace period does not look like a stall.
+    async fn drain(
+        &self,
+        mut running: JoinSet<()>,
+        mut claimed: Claimed,
+        grace: Duration,
+        mut heartbeat: tokio::time::Interval,
+    ) {
+        if running.is_empty() {
+            return;
+        }
+        output::info(format!(
+            "Waiting up to {}s for {} running jobs",
+            grace.as_secs(),
+            running.len()
+        ));
+
+        let deadline = tokio::time::sleep(grace);
+        tokio::pin!(deadline);
+        loop {
+            tokio::select! {
+                result = running.join_next_with_id() => match result {
+                    Some(result) => self.finished(result, &mut claimed).await,
+                    None => break,
+                },
+                _ = heartbeat.tick() => self.heartbeat(&claimed).await,
+                _ = &mut deadline => break,
+            }
+        }
+
+        running.abort_all();
+        while let Some(result) = running.join_next_with_id().await {
+            // A job may have finished just before it was aborted
+            if let Ok((task_id, ())) = result {
+                claimed.remove(&task_id);
+            }
+        }
+        if claimed.is_empty() {
+            output::success("Background jobs finished");
+            return;
+        }
+
+        let ids: Vec<i64> = claimed.into_values().map(|(id, _)| id).collect();
+        match JobService::release(&self.state.pool, &self.id, &ids).await {
+            Ok(released) => output::info(format!(
+                "Released {} unfinished jobs to the queue",
+                released
+            )),
+            Err(e) => output::error(format!(
+                "Failed to release unfinished jobs {:?}: {}",
+                ids, e
+            )),
+        }
+    }
+
+    /// Forget a finished task; a panicked job is recorded as failed
+    async fn finished(&self, result: Result<(task::Id, ()), JoinError>, claimed: &mut Claimed) {
+        let (task_id, panicked) = match &result {
+            Ok((task_id, ())) => (*task_id, false),
+            Err(e) => (e.id(), e.is_panic()),
+        };
+        let Some((job_id, kind)) = claimed.remove(&task_id) else {
+            return;
+        };
+        if !panicked {
+            return;
+        }
+        self.metrics.finished.inc(&[kind.as_str(), "panicked"]);
+        if let Err(e) = JobService::fail(&self.state.pool, &self.id, job_id, "job panicked").await {
+            output::error(format!("Failed to record job {} as failed: {}", job_id, e));
+        }
+    }
+
+    /// Record that this worker and its running jobs are alive
+    async fn heartbeat(&self, claimed: &Claimed) {
+        let running: Vec<i64> = claimed.values().map(|(id, _)| *id).collect();
+        if let Err(e) =
+            JobService::heartbeat(&self.state.pool, &self.id, &self.hostname, &running).await
+        {
+            output::error(format!(
+                "Failed to record heartbeat of job worker {}: {}",
+                self.id, e
+            ));
+        }
+    }
+
+    /// Update the `jobs` gauge from the queue
+    async fn count_jobs(&self) {
+        match JobService::count_by_status(&self.state.pool).await {
+            Ok(counts) => {
+                for status in STATUSES {
+                    let count = counts
+                        .iter()
+                        .find(|(name, _)| name == status)
+                        .map_or(0, |(_, count)| *count);
+                    self.metrics.by_status.set(&[*status], count as f64);
+                }
+            }
+            Err(e) => output::error(format!("Failed to count jobs: {}", e)),
+        }
+    }
+
+    /// Run one claimed job and record the outcome
+    fn execute(&self, job: Job) -> impl Future<Output = ()> + Send + 'static {
+        let state = self.state.clone();
+        let worker = self.id.clone();
+        let handler = self.handlers.get(job.kind.as_str()).cloned();
+        let metrics = self.metrics.clone();
+        async move {
+            let waited = (Utc::now() - job.run_at).num_milliseconds().max(0);
+            metrics
+                .wait
+                .observe(&[job.kind.as_str()], waited as f64 / 1000.0);
+            let started = std::time::Instant::now();
+            let result = match handler {
+                Some(handler) => {
+                    db_metrics::scope(&state, handler(state.clone(), job.payload)).await
+                }
+                None => Err(format!("no handler for job kind '{}'", job.kind)),
+            };
+            metrics
+                .duration
+                .observe(&[job.kind.as_str()], started.elapsed().as_secs_f64());
+            let recorded = match &result {
+                Ok(()) => JobService::complete(&state.pool, &worker, job.id).await,
+                Err(e) => {
+                    output::error(format!(
+                        "Job {} ({}) failed on attempt {}: {}",
+                        job.id, job.kind, job.attempts, e
+                    ));
+                    JobService::fail(&state.pool, &worker, job.id, e).await
+                }
+            };
+            let outcome = match (&recorded, &result) {
+                (Ok(false), _) => "discarded",
+                (_, Ok(())) => "done",
+                (_, Err(_)) => "failed",
+            };
+            metrics.finished.inc(&[job.kind.as_str(), outcome]);
+            match recorded {
+                Ok(true) => {}
+                Ok(false) => output::warn(format!(
+                    "Job {} was requeued while it ran here; its result is discarded",
+                    job.id
+                )),
+                Err(e) => output::error(format!(
+                    "Failed to record the result of job {}: {}",
+                    job.id, e
+                )),
+            }
+        }
+    }
+}
+
+/// Queue sizes and job workers with their liveness
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Queue sizes and workers", body = Envelope<JobsOverview>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn jobs_overview(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<JobsOverview>>> {
+    let overview = overview(&state).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(overview).with_request_id(request_id),
+    ))
+}
+
+/// Queue sizes and known workers, for the jobs dashboard
+pub async fn overview(state: &AppState) -> AppResult<JobsOverview> {
+    let counts = JobService::count_by_status(&state.pool).await?;
+    let count = |status: &str| {
+        counts
+            .iter()
+            .find(|(name, _)| name == status)
+            .map_or(0, |(_, count)| *count)
+    };
+    let workers =
+        JobService::list_workers(&state.pool, state.config.jobs.stall_timeout_secs).await?;
+
+    Ok(JobsOverview {
+        queued: count("queued"),
+        running: count("running"),
+        failed: count("failed"),
+        done: count("done"),
+        liveness: liveness(&workers),
+        workers,
+    })
+}
+
+/// Count workers by whether their heartbeat is recent
+pub fn liveness(workers: &[JobWorker]) -> WorkerLiveness {
+    let alive = workers.iter().filter(|worker| worker.alive).count();
+    WorkerLiveness {
+        alive,
+        stalled: workers.len() - alive,
+        last_heartbeat_at: workers.iter().map(|worker| worker.heartbeat_at).max(),
+    }
+}
+
+/// Mark workers without a recent heartbeat as stalled, requeue their jobs,
+/// and alert about both. Returns the jobs taken back.
+pub async fn watchdog(state: &AppState) -> AppResult<Vec<Job>> {
+    let timeout = state.config.jobs.stall_timeout_secs;
+    let workers = JobService::mark_stalled_workers(&state.pool, timeout).await?;
+    let jobs = JobService::requeue_stalled(&state.pool, timeout).await?;
+    if !workers.is_empty() || !jobs.is_empty() {
+        alert(state, &workers, &jobs).await;
+    }
+    Ok(jobs)
+}
+
+/// Log a stall and email it to `JOB_ALERT_EMAIL`. The email is sent directly
+/// rather than queued, since the queue may be what is stuck.
+async fn alert(state: &AppState, workers: &[String], jobs: &[Job]) {
+    let lines = stall_report(workers, jobs, state.config.jobs.stall_timeout_secs);
+    output::error(lines.join("\n   "));
+
+    let Some(to) = &state.config.jobs.alert_email else {
+        return;
+    };
+    let items: String = lines[1..]
+        .iter()
+        .map(|line| format!("<li>{}</li>", tera::escape_html(line)))
+        .collect();
+    let email = Email {
+        to: to.clone(),
+        subject: "Background job workers stalled".to_string(),
+        html: format!("<p>{}</p><ul>{}</ul>", tera::escape_html(&lines[0]), items),
+        text: Some(lines.join("\n")),
+        unsubscribe_url: None,
+    };
+    if let Err(e) = emails::deliver(state, ALERT_TEMPLATE, &email).await {
+        output::error(format!("Failed to send job stall alert to {}: {}", to, e));
+    }
+}
+
+/// A headline followed by one line per stalled worker and requeued job
+fn stall_report(workers: &[String], jobs: &[Job], timeout_secs: u64) -> Vec<String> {
+    let mut lines = vec![format!(
+        "Job watchdog: {} workers stalled and {} jobs taken back after {}s without a heartbeat",
+        workers.len(),
+        jobs.len(),
+        timeout_secs
+    )];
+    lines.extend(workers.iter().map(|id| format!("Worker {} stalled", id)));
+    lines.extend(jobs.iter().map(|job| {
+        let outcome = if job.status == "failed" {
+            "failed after its last attempt"
+        } else {
+            "requeued"
+        };
+        format!(
+            "Job {} ({}) {}, attempt {} of {}",
+            job.id, job.kind, outcome, job.attempts, job.max_attempts
+        )
+    }));
+    lines
+}
+
+/// Ticks every `secs` seconds, starting now; late ticks are not bunched up
+fn heartbeat_interval(secs: u64) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval(Duration::from_secs(secs));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval
+}
+
+/// Name of this machine, shown next to its workers
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
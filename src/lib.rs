@@ -2,11 +2,107 @@
 //!
 //! Shared modules for the Axum Base web application and CLI utilities.
 //! This provides a clean, reusable foundation for Rust web applications.
+//!
+//! Applications should import from [`prelude`], the stable API. Modules
+//! hidden from the docs exist for the bundled binaries and tests; their
+//! contents can change in any release.
 
+#[cfg(feature = "acme")]
+pub mod acme;
+pub mod activity;
+#[cfg(feature = "activitypub")]
+pub mod activitypub;
+pub mod admin;
 pub mod api;
+pub mod attachments;
+pub mod audit;
 pub mod auth;
+pub mod avatars;
+#[doc(hidden)]
+pub mod backup;
+#[doc(hidden)]
+pub mod bootstrap;
+pub mod bounces;
+pub mod cli;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod client_ip;
+#[doc(hidden)]
+pub mod completions;
+pub mod config;
+#[doc(hidden)]
+pub mod console;
+#[doc(hidden)]
 pub mod context;
+pub mod daemon;
 pub mod database;
+pub mod db_metrics;
+#[doc(hidden)]
+pub mod deliverability;
+pub mod domains;
+pub mod emails;
+pub mod error;
+#[doc(hidden)]
+pub mod export;
+pub mod flash;
+pub mod formats;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+pub mod health;
+pub mod i18n;
+pub mod item_filter;
+pub mod jobs;
+#[doc(hidden)]
+pub mod live_reload;
+#[doc(hidden)]
+pub mod login_guard;
+pub mod mailer;
+pub mod metrics;
+pub mod migrations;
 pub mod models;
+pub mod nav;
+pub mod notifications;
+pub mod openapi;
+pub mod output;
+pub mod page_cache;
+pub mod pagination;
+pub mod plugins;
+pub mod preferences;
+pub mod prelude;
+#[cfg(feature = "pwned")]
+pub mod pwned;
+pub mod rate_limit;
+pub mod repositories;
+pub mod resources;
 pub mod routes;
+#[cfg(feature = "s3")]
+pub mod s3;
+pub mod scheduler;
+pub mod scope;
+pub mod search;
+#[doc(hidden)]
+pub mod seed;
+pub mod server;
+pub mod services;
+pub mod snippets;
+#[doc(hidden)]
+pub mod sql_console;
+#[cfg(feature = "sso")]
+pub mod sso;
+pub mod state;
+#[doc(hidden)]
+pub mod static_files;
+pub mod suggest;
+#[doc(hidden)]
+pub mod template_check;
+pub mod tenants;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod theming;
+pub mod timezone;
+#[doc(hidden)]
+pub mod typescript;
+pub mod uploads;
 pub mod web;
+pub mod well_known;
+pub mod ws;
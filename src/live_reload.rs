@@ -0,0 +1,211 @@
+//! # Live Reload
+//!
+//! Development-only browser refresh. A background task polls `templates/`
+//! and `static/` for changes and notifies connected browsers over a
+//! server-sent events stream at [`LIVE_RELOAD_PATH`]. HTML responses get a
+//! small script injected that subscribes to the stream and reloads the page.
+//!
+//! Each server process also announces a boot ID, so pages reload after the
+//! server restarts under `cargo watch` as well.
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    extract::State,
+    http::header,
+    response::{
+        Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::get,
+};
+use futures_util::stream::{self, Stream};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::output;
+
+/// Path of the server-sent events stream
+pub const LIVE_RELOAD_PATH: &str = "/__livereload";
+
+/// Directories watched for changes
+pub const WATCHED_DIRS: &[&str] = &["templates", "static"];
+
+/// How often the watched directories are scanned
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Script injected into HTML pages. Reloads on a change event, or when the
+/// boot ID differs from the first one seen (the server restarted).
+const CLIENT_SCRIPT: &str = r#"<script>
+(() => {
+    let bootId = null;
+    const events = new EventSource("/__livereload");
+    events.addEventListener("hello", (e) => {
+        if (bootId !== null && bootId !== e.data) location.reload();
+        bootId = e.data;
+    });
+    events.addEventListener("reload", () => location.reload());
+})();
+</script>"#;
+
+/// Handle to the file watcher, shared by every live reload stream
+#[derive(Clone)]
+pub struct LiveReload {
+    changes: broadcast::Sender<()>,
+    boot_id: Arc<str>,
+}
+
+impl LiveReload {
+    /// Start polling `dirs` and return a handle for the event stream
+    pub fn watch(dirs: Vec<PathBuf>) -> Self {
+        let (changes, _) = broadcast::channel(16);
+        let sender = changes.clone();
+
+        tokio::spawn(async move {
+            let mut previous = snapshot_dirs(dirs.clone()).await;
+            loop {
+                tokio::time::sleep(POLL_INTERVAL).await;
+                let current = snapshot_dirs(dirs.clone()).await;
+                if current != previous {
+                    // No receivers just means no browser is connected
+                    let _ = sender.send(());
+                    previous = current;
+                }
+            }
+        });
+
+        Self {
+            changes,
+            boot_id: uuid::Uuid::new_v4().to_string().into(),
+        }
+    }
+
+    /// Router serving the event stream
+    pub fn routes<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route(LIVE_RELOAD_PATH, get(events))
+            .with_state(self)
+    }
+}
+
+/// Server-sent events: `hello` with the boot ID, then `reload` on each change
+async fn events(
+    State(reload): State<LiveReload>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let hello = Event::default().event("hello").data(&*reload.boot_id);
+    let receiver = reload.changes.subscribe();
+
+    let changes = stream::unfold(receiver, |mut receiver| async move {
+        match receiver.recv().await {
+            Ok(()) | Err(RecvError::Lagged(_)) => {
+                Some((Ok(Event::default().event("reload").data("")), receiver))
+            }
+            Err(RecvError::Closed) => None,
+        }
+    });
+
+    Sse::new(stream::StreamExt::chain(stream::iter([Ok(hello)]), changes))
+        .keep_alive(KeepAlive::default())
+}
+
+/// Response mapper that injects the client script into HTML pages
+pub async fn inject_script(response: Response) -> Response {
+    let is_html = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/html"));
+    if !is_html {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            output::warn(format!("Live reload could not read response body: {}", err));
+            return Response::from_parts(parts, Body::empty());
+        }
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let html = inject_into_html(&String::from_utf8_lossy(&bytes));
+    Response::from_parts(parts, Body::from(html))
+}
+
+/// Insert the client script before `</body>`, or append it
+fn inject_into_html(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{}{}", &html[..index], CLIENT_SCRIPT, &html[index..]),
+        None => format!("{}{}", html, CLIENT_SCRIPT),
+    }
+}
+
+/// Modification times of every file under `dirs`
+async fn snapshot_dirs(dirs: Vec<PathBuf>) -> HashMap<PathBuf, SystemTime> {
+    tokio::task::spawn_blocking(move || {
+        let mut files = HashMap::new();
+        for dir in &dirs {
+            collect_mtimes(dir, &mut files);
+        }
+        files
+    })
+    .await
+    .unwrap_or_default()
+}
+
+fn collect_mtimes(dir: &Path, files: &mut HashMap<PathBuf, SystemTime>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_mtimes(&path, files);
+        } else if let Ok(modified) = metadata.modified() {
+            files.insert(path, modified);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_is_injected_before_body_end() {
+        let html = inject_into_html("<html><body><p>Hi</p></body></html>");
+        assert!(html.contains(LIVE_RELOAD_PATH));
+        assert!(html.ends_with("</script></body></html>"));
+
+        let fragment = inject_into_html("<p>Hi</p>");
+        assert!(fragment.starts_with("<p>Hi</p><script>"));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_detects_changes() {
+        let dir = std::env::temp_dir().join(format!("live-reload-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("nested")).unwrap();
+        std::fs::write(dir.join("nested/page.html"), "one").unwrap();
+
+        let before = snapshot_dirs(vec![dir.clone()]).await;
+        assert_eq!(before.len(), 1);
+
+        std::fs::write(dir.join("added.css"), "body {}").unwrap();
+        let after = snapshot_dirs(vec![dir.clone()]).await;
+        assert_ne!(before, after);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
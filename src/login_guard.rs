@@ -0,0 +1,168 @@
+//! # Login Form Guard
+//!
+//! Turns away posts to `/login` that did not come from the login form before
+//! anything expensive happens. Credential-stuffing scripts post usernames and
+//! passwords straight to the endpoint; each attempt would otherwise cost a
+//! database lookup, a session, and an Argon2 verification.
+//!
+//! The form's `csrf_token` field must match the [`CSRF_COOKIE`] set when the
+//! page was served (a double-submit cookie, so serving the page creates no
+//! session), and its `website` field, hidden from people, must stay empty;
+//! bots tend to fill in every field. Rejections are counted in
+//! [`Metrics`](crate::metrics::Metrics).
+//!
+//! Posts are also throttled per [`ClientIp`](crate::client_ip::ClientIp):
+//! past [`RATE_LIMIT`] in a [`RATE_WINDOW`], an address is answered with
+//! `429 Too Many Requests` and a `Retry-After` header.
+
+use axum::http::{HeaderMap, header};
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::auth::constant_time_eq;
+
+/// Cookie holding the token the login form must echo
+pub const CSRF_COOKIE: &str = "login_csrf";
+
+/// Login posts each client address may make per [`RATE_WINDOW`]
+pub const RATE_LIMIT: usize = 10;
+
+/// Window for [`RATE_LIMIT`]
+pub const RATE_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// How long a served login form stays usable, in seconds
+const TOKEN_MAX_AGE: u64 = 2 * 60 * 60;
+
+/// Why a login post was turned away
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginRejection {
+    /// The form field or the cookie is missing
+    MissingToken,
+    /// The form field does not match the cookie
+    TokenMismatch,
+    /// The hidden field was filled in
+    Honeypot,
+}
+
+impl LoginRejection {
+    /// Every reason, in metric order
+    pub const ALL: [LoginRejection; 3] = [
+        LoginRejection::MissingToken,
+        LoginRejection::TokenMismatch,
+        LoginRejection::Honeypot,
+    ];
+
+    /// Label used in metrics
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoginRejection::MissingToken => "missing_token",
+            LoginRejection::TokenMismatch => "token_mismatch",
+            LoginRejection::Honeypot => "honeypot",
+        }
+    }
+}
+
+/// The token of the request's cookie, or a new one for a first visit
+pub fn token(headers: &HeaderMap) -> String {
+    cookie_token(headers).unwrap_or_else(|| Uuid::new_v4().simple().to_string())
+}
+
+/// `Set-Cookie` value handing out `token`
+pub fn set_cookie(token: &str, secure: bool) -> String {
+    format!(
+        "{}={}; Path=/login; Max-Age={}; HttpOnly; SameSite=Strict{}",
+        CSRF_COOKIE,
+        token,
+        TOKEN_MAX_AGE,
+        if secure { "; Secure" } else { "" }
+    )
+}
+
+/// Check a login post's token and honeypot without touching the database
+pub fn check(
+    headers: &HeaderMap,
+    token: Option<&str>,
+    honeypot: Option<&str>,
+) -> Result<(), LoginRejection> {
+    if honeypot.is_some_and(|value| !value.is_empty()) {
+        return Err(LoginRejection::Honeypot);
+    }
+    let (Some(token), Some(expected)) = (token.filter(|t| !t.is_empty()), cookie_token(headers))
+    else {
+        return Err(LoginRejection::MissingToken);
+    };
+    if !constant_time_eq(token.as_bytes(), expected.as_bytes()) {
+        return Err(LoginRejection::TokenMismatch);
+    }
+    Ok(())
+}
+
+/// The [`CSRF_COOKIE`] sent with the request
+fn cookie_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(name, value)| *name == CSRF_COOKIE && !value.is_empty())
+        .map(|(_, value)| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn with_cookie(cookie: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_str(cookie).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_token_reuses_the_cookie() {
+        let headers = with_cookie("id=abc; login_csrf=0123456789abcdef");
+        assert_eq!(token(&headers), "0123456789abcdef");
+
+        let fresh = token(&HeaderMap::new());
+        assert_eq!(fresh.len(), 32);
+        assert_ne!(fresh, token(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn test_check() {
+        let headers = with_cookie("login_csrf=0123456789abcdef");
+        assert_eq!(check(&headers, Some("0123456789abcdef"), Some("")), Ok(()));
+        assert_eq!(check(&headers, Some("0123456789abcdef"), None), Ok(()));
+        assert_eq!(
+            check(
+                &headers,
+                Some("0123456789abcdef"),
+                Some("https://spam.example")
+            ),
+            Err(LoginRejection::Honeypot)
+        );
+        assert_eq!(
+            check(&headers, Some("fedcba9876543210"), None),
+            Err(LoginRejection::TokenMismatch)
+        );
+        assert_eq!(
+            check(&headers, None, None),
+            Err(LoginRejection::MissingToken)
+        );
+        assert_eq!(
+            check(&HeaderMap::new(), Some("0123456789abcdef"), None),
+            Err(LoginRejection::MissingToken)
+        );
+    }
+
+    #[test]
+    fn test_set_cookie() {
+        assert_eq!(
+            set_cookie("abc", true),
+            "login_csrf=abc; Path=/login; Max-Age=7200; HttpOnly; SameSite=Strict; Secure"
+        );
+        assert!(!set_cookie("abc", false).contains("Secure"));
+    }
+}
@@ -0,0 +1,488 @@
+//! # Mailer
+//!
+//! Outgoing email. Mail goes through the SMTP relay named by `SMTP_URL`;
+//! without one it is printed to the console, so development and tests never
+//! need a mail server.
+//!
+//! Every message gets a `Message-ID` on the sender's domain, and mail with an
+//! unsubscribe link carries `List-Unsubscribe` and `List-Unsubscribe-Post`
+//! (RFC 8058) so mailbox providers can offer one-click unsubscribe. With
+//! `MAIL_DKIM_SELECTOR` and `MAIL_DKIM_KEY_FILE` set, SMTP mail is DKIM
+//! signed; `admin mail check-dns` checks the matching DNS records.
+
+use lettre::message::dkim::{
+    DkimCanonicalization, DkimCanonicalizationType, DkimConfig, DkimSigningKey,
+};
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart, header::ContentType};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use std::sync::Mutex;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::config::MailConfig;
+use crate::error::{AppError, AppResult};
+use crate::output;
+use crate::rate_limit::RateLimiter;
+
+/// A rendered email ready to send
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+    /// Plain-text version; with one the email is sent as
+    /// `multipart/alternative`
+    pub text: Option<String>,
+    /// One-click unsubscribe link, sent as `List-Unsubscribe`
+    pub unsubscribe_url: Option<String>,
+}
+
+/// Delivers email through SMTP or the console, paced by the configured send
+/// rates
+pub struct Mailer {
+    transport: Transport,
+    throttle: Throttle,
+}
+
+/// Where mail goes
+enum Transport {
+    /// Deliver through an SMTP relay
+    Smtp {
+        transport: AsyncSmtpTransport<Tokio1Executor>,
+        from: Mailbox,
+        /// Signs messages when DKIM is configured
+        dkim: Option<Box<DkimConfig>>,
+    },
+    /// Print mail to stdout (no `SMTP_URL` configured)
+    Console { from: Mailbox },
+}
+
+impl Mailer {
+    /// Build the mailer for the configured transport
+    pub fn from_config(config: &MailConfig) -> Result<Self, String> {
+        let from = config
+            .from
+            .parse::<Mailbox>()
+            .map_err(|e| format!("invalid MAIL_FROM: {}", e))?;
+
+        let transport = match &config.smtp_url {
+            Some(url) => {
+                let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+                    .map_err(|e| format!("invalid SMTP_URL: {}", e))?
+                    .build();
+                let dkim = config
+                    .dkim_signing_key()?
+                    .map(|key| dkim_config(config, key).map(Box::new))
+                    .transpose()?;
+                Transport::Smtp {
+                    transport,
+                    from,
+                    dkim,
+                }
+            }
+            None => Transport::Console { from },
+        };
+        Ok(Self {
+            transport,
+            throttle: Throttle::from_config(config),
+        })
+    }
+
+    /// Short name of the transport for startup logs
+    pub fn transport_name(&self) -> &'static str {
+        match self.transport {
+            Transport::Smtp { .. } => "SMTP",
+            Transport::Console { .. } => "console",
+        }
+    }
+
+    /// Send one email, first waiting for the send rates to allow it
+    pub async fn send(&self, email: &Email) -> AppResult<()> {
+        match &self.transport {
+            Transport::Smtp {
+                transport,
+                from,
+                dkim,
+            } => {
+                let mut message = build_message(from, email)?;
+                if let Some(dkim) = dkim {
+                    message.sign(dkim);
+                }
+                self.throttle.acquire(recipient_domain(&email.to)).await;
+                transport
+                    .send(message)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to send email: {}", e)))?;
+            }
+            Transport::Console { from } => {
+                // Checks the addresses the same way SMTP delivery would
+                build_message(from, email)?;
+                self.throttle.acquire(recipient_domain(&email.to)).await;
+                // The text part is the readable one in a terminal
+                output::info(format!(
+                    "Email to {} from {}\n   Subject: {}\n{}",
+                    email.to,
+                    from,
+                    email.subject,
+                    email.text.as_deref().unwrap_or(&email.html)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether the SMTP server accepts connections, for `/health`; the
+    /// console transport is always up
+    pub async fn check(&self) -> AppResult<()> {
+        match &self.transport {
+            Transport::Smtp { transport, .. } => match transport.test_connection().await {
+                Ok(true) => Ok(()),
+                Ok(false) => Err(AppError::Internal(
+                    "SMTP server refused the connection".to_string(),
+                )),
+                Err(e) => Err(AppError::Internal(format!(
+                    "SMTP server unreachable: {}",
+                    e
+                ))),
+            },
+            Transport::Console { .. } => Ok(()),
+        }
+    }
+}
+
+// ============================================================================
+// Throttling
+// ============================================================================
+
+/// Paces sends to the `MAIL_RATE_*` limits so bursts such as bulk invites
+/// stay under the provider's limits.
+///
+/// Senders wait for a free slot rather than failing. The job workers send
+/// mail, so a long wait holds their slots and the rest of a burst stays
+/// queued in the database.
+#[derive(Debug)]
+struct Throttle {
+    per_second: Option<RateLimiter<()>>,
+    per_minute: Option<RateLimiter<()>>,
+    /// Keyed by recipient domain, since mailbox providers rate-limit senders
+    per_domain: Option<RateLimiter<String>>,
+    /// Makes checking every limiter and recording the send one step
+    lock: Mutex<()>,
+}
+
+impl Throttle {
+    fn from_config(config: &MailConfig) -> Self {
+        Self {
+            per_second: limiter(config.rate_per_second, Duration::from_secs(1)),
+            per_minute: limiter(config.rate_per_minute, Duration::from_secs(60)),
+            per_domain: limiter(config.domain_rate_per_minute, Duration::from_secs(60)),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Wait until every limit allows an email to `domain`, then count it
+    async fn acquire(&self, domain: String) {
+        loop {
+            let wait = {
+                let _guard = self.lock.lock().unwrap_or_else(|e| e.into_inner());
+                let wait = [
+                    self.per_second.as_ref().and_then(|l| l.retry_after(&())),
+                    self.per_minute.as_ref().and_then(|l| l.retry_after(&())),
+                    self.per_domain
+                        .as_ref()
+                        .and_then(|l| l.retry_after(&domain)),
+                ]
+                .into_iter()
+                .flatten()
+                .max();
+
+                match wait {
+                    Some(wait) => wait,
+                    None => {
+                        // Cannot be rejected: nothing else records while the lock is held
+                        if let Some(limiter) = &self.per_second {
+                            let _ = limiter.check(());
+                        }
+                        if let Some(limiter) = &self.per_minute {
+                            let _ = limiter.check(());
+                        }
+                        if let Some(limiter) = &self.per_domain {
+                            let _ = limiter.check(domain);
+                        }
+                        return;
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// A limiter for `rate` sends per `window`, if a rate is set
+fn limiter<K: Eq + std::hash::Hash>(rate: Option<u32>, window: Duration) -> Option<RateLimiter<K>> {
+    rate.map(|rate| RateLimiter::new(rate as usize, window))
+}
+
+/// Lowercased domain of a recipient address (`Jane <jane@Example.com>` gives
+/// `example.com`)
+fn recipient_domain(to: &str) -> String {
+    to.trim()
+        .trim_end_matches('>')
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+// ============================================================================
+// Message Assembly
+// ============================================================================
+
+/// Headers covered by the DKIM signature
+const SIGNED_HEADERS: &[&str] = &[
+    "From",
+    "To",
+    "Subject",
+    "Date",
+    "Message-ID",
+    "Content-Type",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+];
+
+/// Assemble an HTML message
+fn build_message(from: &Mailbox, email: &Email) -> AppResult<Message> {
+    let to = email.to.parse::<Mailbox>().map_err(|_| {
+        AppError::Validation(format!("'{}' is not a valid email address", email.to))
+    })?;
+
+    let mut builder = Message::builder()
+        .message_id(Some(format!(
+            "<{}@{}>",
+            Uuid::new_v4().simple(),
+            from.email.domain()
+        )))
+        .from(from.clone())
+        .to(to)
+        .subject(email.subject.as_str());
+    if let Some(url) = &email.unsubscribe_url {
+        builder = builder
+            .header(ListUnsubscribe(url.clone()))
+            .header(ListUnsubscribePost);
+    }
+
+    let message = match &email.text {
+        Some(text) => builder.multipart(MultiPart::alternative_plain_html(
+            text.clone(),
+            email.html.clone(),
+        )),
+        None => builder
+            .header(ContentType::TEXT_HTML)
+            .body(email.html.clone()),
+    };
+    message.map_err(|e| AppError::Internal(format!("Failed to build email: {}", e)))
+}
+
+/// DKIM signing for the configured selector and domain
+fn dkim_config(config: &MailConfig, key: DkimSigningKey) -> Result<DkimConfig, String> {
+    let selector = config.dkim_selector.clone().unwrap_or_default();
+    let domain = config
+        .signing_domain()
+        .ok_or("MAIL_DKIM_DOMAIN is required when MAIL_FROM has no domain")?;
+    let headers = SIGNED_HEADERS
+        .iter()
+        .map(|name| HeaderName::new_from_ascii_str(name))
+        .collect();
+
+    Ok(DkimConfig::new(
+        selector,
+        domain,
+        key,
+        headers,
+        DkimCanonicalization {
+            header: DkimCanonicalizationType::Relaxed,
+            body: DkimCanonicalizationType::Relaxed,
+        },
+    ))
+}
+
+/// `List-Unsubscribe: <url>`
+#[derive(Debug, Clone)]
+struct ListUnsubscribe(String);
+
+impl Header for ListUnsubscribe {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let url = s.trim().trim_start_matches('<').trim_end_matches('>');
+        Ok(Self(url.to_string()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), format!("<{}>", self.0))
+    }
+}
+
+/// `List-Unsubscribe-Post: List-Unsubscribe=One-Click`, which tells mailbox
+/// providers the link accepts a POST without confirmation (RFC 8058)
+#[derive(Debug, Clone)]
+struct ListUnsubscribePost;
+
+impl Header for ListUnsubscribePost {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("List-Unsubscribe-Post")
+    }
+
+    fn parse(_: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Self)
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), "List-Unsubscribe=One-Click".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(to: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Welcome aboard".to_string(),
+            html: "<p>Hello</p>".to_string(),
+            text: None,
+            unsubscribe_url: None,
+        }
+    }
+
+    #[test]
+    fn test_build_message() {
+        let from: Mailbox = "Axum Base <noreply@example.com>".parse().unwrap();
+        let message = build_message(&from, &email("jane@example.com")).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+
+        assert!(formatted.contains("Subject: Welcome aboard"));
+        assert!(formatted.contains("To: jane@example.com"));
+        assert!(formatted.contains("Content-Type: text/html; charset=utf-8"));
+        assert!(formatted.contains("@example.com>\r\n"));
+        assert!(formatted.contains("Message-ID: <"));
+        assert!(!formatted.contains("List-Unsubscribe"));
+        assert!(matches!(
+            build_message(&from, &email("not an address")),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[test]
+    fn test_text_part_makes_the_message_multipart() {
+        let from: Mailbox = "noreply@example.com".parse().unwrap();
+        let mut email = email("jane@example.com");
+        email.text = Some("Hello".to_string());
+        let message = build_message(&from, &email).unwrap();
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+
+        assert!(formatted.contains("Content-Type: multipart/alternative;"));
+        assert!(formatted.contains("Content-Type: text/plain; charset=utf-8"));
+        assert!(formatted.contains("Content-Type: text/html; charset=utf-8"));
+    }
+
+    #[test]
+    fn test_unsubscribe_headers_and_dkim_signature() {
+        let from: Mailbox = "noreply@example.com".parse().unwrap();
+        let mut email = email("jane@example.com");
+        email.unsubscribe_url = Some("https://example.com/unsubscribe?token=t".to_string());
+        let mut message = build_message(&from, &email).unwrap();
+
+        let config = MailConfig {
+            from: "noreply@example.com".to_string(),
+            dkim_selector: Some("mail".to_string()),
+            ..MailConfig::default()
+        };
+        let key = DkimSigningKey::new(
+            "BwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwcHBwc=",
+            lettre::message::dkim::DkimSigningAlgorithm::Ed25519,
+        )
+        .unwrap();
+        message.sign(&dkim_config(&config, key).unwrap());
+        let formatted = String::from_utf8(message.formatted()).unwrap();
+
+        assert!(formatted.contains("List-Unsubscribe: <https://example.com/unsubscribe?token=t>"));
+        assert!(formatted.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(
+            formatted.contains("DKIM-Signature: v=1; a=ed25519-sha256; d=example.com; s=mail;")
+        );
+    }
+
+    // The SMTP connection pool spawns onto the Tokio runtime
+    #[tokio::test]
+    async fn test_transport_from_config() {
+        let mut config = MailConfig::default();
+        assert_eq!(
+            Mailer::from_config(&config).unwrap().transport_name(),
+            "console"
+        );
+
+        config.smtp_url = Some("smtp://localhost:2525".to_string());
+        assert_eq!(
+            Mailer::from_config(&config).unwrap().transport_name(),
+            "SMTP"
+        );
+
+        config.from = "nobody".to_string();
+        assert!(Mailer::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_recipient_domain() {
+        assert_eq!(recipient_domain("jane@Example.com"), "example.com");
+        assert_eq!(
+            recipient_domain("Jane <jane@mail.example.com>"),
+            "mail.example.com"
+        );
+        assert_eq!(recipient_domain("nobody"), "");
+    }
+
+    #[tokio::test]
+    async fn test_throttle_limits_per_domain() {
+        let config = MailConfig {
+            domain_rate_per_minute: Some(1),
+            ..MailConfig::default()
+        };
+        let throttle = Throttle::from_config(&config);
+        let acquire = |domain: &str| {
+            tokio::time::timeout(
+                Duration::from_millis(100),
+                throttle.acquire(domain.to_string()),
+            )
+        };
+
+        assert!(acquire("example.com").await.is_ok());
+        assert!(acquire("example.org").await.is_ok());
+        assert!(
+            acquire("example.com").await.is_err(),
+            "A second email to the same domain should wait"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_throttle_limits_globally() {
+        let config = MailConfig {
+            rate_per_minute: Some(2),
+            ..MailConfig::default()
+        };
+        let throttle = Throttle::from_config(&config);
+        let acquire = |domain: &str| {
+            tokio::time::timeout(
+                Duration::from_millis(100),
+                throttle.acquire(domain.to_string()),
+            )
+        };
+
+        assert!(acquire("example.com").await.is_ok());
+        assert!(acquire("example.org").await.is_ok());
+        assert!(acquire("example.net").await.is_err());
+    }
+}
@@ -3,22 +3,91 @@
 //! A modular, production-ready web server built with Axum 0.7, SQLx, and Tera templating.
 //! Includes authentication, database migrations, and comprehensive testing.
 
+#[cfg(feature = "acme")]
+mod acme;
+mod activity;
+#[cfg(feature = "activitypub")]
+mod activitypub;
+mod admin;
 mod api;
+mod attachments;
+mod audit;
 mod auth;
+mod avatars;
+mod bootstrap;
+mod bounces;
+mod cli;
+mod client_ip;
+mod config;
 mod context;
+mod daemon;
 mod database;
+mod db_metrics;
+mod domains;
+mod emails;
+mod error;
+mod flash;
+mod formats;
+#[cfg(feature = "graphql")]
+mod graphql;
+mod health;
+mod i18n;
+mod item_filter;
+mod jobs;
+mod live_reload;
+mod login_guard;
+mod mailer;
+mod metrics;
+mod migrations;
 mod models;
+mod nav;
+mod notifications;
+mod openapi;
+mod output;
+mod page_cache;
+mod pagination;
+mod plugins;
+mod preferences;
+#[cfg(feature = "pwned")]
+mod pwned;
+mod rate_limit;
+mod repositories;
+mod resources;
 mod routes;
+#[cfg(feature = "s3")]
+mod s3;
+mod scheduler;
+mod scope;
+mod search;
 mod server;
 mod services;
+mod snippets;
+mod sql_console;
+#[cfg(feature = "sso")]
+mod sso;
+mod state;
+mod static_files;
+mod suggest;
+mod tenants;
+mod theming;
+mod timezone;
+mod uploads;
 mod web;
+mod well_known;
+mod ws;
 
-use server::start_server;
+use std::process::ExitCode;
 
-#[tokio::main]
-async fn main() {
-    // Load environment variables from .env file
-    dotenvy::dotenv().ok();
+use daemon::RunMode;
+use plugins::PluginRegistry;
 
-    start_server().await;
+// No #[tokio::main]: a daemon forks before the runtime starts
+fn main() -> ExitCode {
+    let args = output::init(std::env::args().collect());
+    let result = RunMode::parse(&args[0], &args[1..])
+        .and_then(|mode| daemon::run(mode, PluginRegistry::default()));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => err.report(),
+    }
 }
@@ -0,0 +1,560 @@
+//! # Metrics
+//!
+//! Counters and gauges kept in
+//! [`AppState::metrics`](crate::state::AppState::metrics) and served in the Prometheus text format at `/metrics` when
+//! `METRICS_ENABLED` is set. The endpoint has no authentication; expose it
+//! to the scraper only.
+//!
+//! Subsystems keep their own metrics as a [`MetricSource`] built from
+//! [`CounterVec`], [`GaugeVec`], and [`HistogramVec`], and reach it with
+//! [`Metrics::source`], which registers it on first use:
+//!
+//! | Module | Metrics |
+//! |--------|---------|
+//! | [`jobs`](crate::jobs) | Jobs by status, finished jobs, wait and run time |
+//! | [`scheduler`](crate::scheduler) | Task runs and how late each tick fired |
+//! | [`emails`](crate::emails) | Delivery attempts by outcome and send time |
+//! | [`bounces`](crate::bounces) | Webhook calls by provider and outcome |
+//! | [`suggest`](crate::suggest) | Suggestion cache hits, misses, and size |
+
+use axum::Router;
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use std::any::{Any, TypeId};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+
+use crate::db_metrics;
+use crate::login_guard::LoginRejection;
+use crate::scheduler::Retained;
+use crate::state::AppState;
+
+/// Content type of the Prometheus text format
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Latency buckets in seconds, from a fast query to a slow email
+pub const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0,
+];
+
+/// Counters shared by every request, and the sources modules registered
+#[derive(Debug, Default)]
+pub struct Metrics {
+    login_rejected: [AtomicU64; LoginRejection::ALL.len()],
+    pruned: [AtomicU64; Retained::ALL.len()],
+    retained: [AtomicU64; Retained::ALL.len()],
+    sources: Sources,
+}
+
+/// Metrics a module keeps for itself, registered with [`Metrics::source`]
+pub trait MetricSource: Any + Send + Sync {
+    /// Append every family in the Prometheus text format
+    fn render(&self, out: &mut String);
+}
+
+/// Registered sources in registration order
+#[derive(Default)]
+struct Sources(RwLock<Vec<Registered>>);
+
+struct Registered {
+    type_id: TypeId,
+    any: Arc<dyn Any + Send + Sync>,
+    source: Arc<dyn MetricSource>,
+}
+
+impl fmt::Debug for Sources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let count = self.0.read().map_or(0, |sources| sources.len());
+        f.debug_tuple("Sources").field(&count).finish()
+    }
+}
+
+#[allow(dead_code)]
+impl Metrics {
+    /// Count a login post turned away by the [login guard](crate::login_guard)
+    pub fn login_rejected(&self, reason: LoginRejection) {
+        self.login_rejected[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Login posts turned away for `reason` so far
+    pub fn login_rejections(&self, reason: LoginRejection) -> u64 {
+        self.login_rejected[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// Count rows a cleanup task deleted from `table`, and the rows left
+    pub fn pruned(&self, table: Retained, deleted: u64, left: u64) {
+        self.pruned[table as usize].fetch_add(deleted, Ordering::Relaxed);
+        self.retained[table as usize].store(left, Ordering::Relaxed);
+    }
+
+    /// Rows deleted from `table` by cleanup tasks so far
+    pub fn pruned_rows(&self, table: Retained) -> u64 {
+        self.pruned[table as usize].load(Ordering::Relaxed)
+    }
+
+    /// Rows in `table` after its last cleanup
+    pub fn retained_rows(&self, table: Retained) -> u64 {
+        self.retained[table as usize].load(Ordering::Relaxed)
+    }
+
+    /// The registered `T`, registering a default one first if there is none
+    pub fn source<T: MetricSource + Default>(&self) -> Arc<T> {
+        let type_id = TypeId::of::<T>();
+        let find = |sources: &[Registered]| {
+            sources
+                .iter()
+                .find(|registered| registered.type_id == type_id)
+                .and_then(|registered| registered.any.clone().downcast::<T>().ok())
+        };
+
+        let sources = self
+            .sources
+            .0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some(source) = find(&sources) {
+            return source;
+        }
+        drop(sources);
+
+        let mut sources = self
+            .sources
+            .0
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // Another thread may have registered it in between
+        if let Some(source) = find(&sources) {
+            return source;
+        }
+        let source = Arc::new(T::default());
+        sources.push(Registered {
+            type_id,
+            any: source.clone(),
+            source: source.clone(),
+        });
+        source
+    }
+
+    /// Every metric in the Prometheus text format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        family(
+            &mut out,
+            "counter",
+            "login_rejected_total",
+            "Login posts turned away before the password was checked",
+            "reason",
+            LoginRejection::ALL
+                .iter()
+                .map(|reason| (reason.as_str(), self.login_rejections(*reason))),
+        );
+        family(
+            &mut out,
+            "counter",
+            "rows_pruned_total",
+            "Rows deleted by the cleanup tasks",
+            "table",
+            Retained::ALL
+                .iter()
+                .map(|table| (table.as_str(), self.pruned_rows(*table))),
+        );
+        family(
+            &mut out,
+            "gauge",
+            "rows_retained",
+            "Rows left after the last cleanup",
+            "table",
+            Retained::ALL
+                .iter()
+                .map(|table| (table.as_str(), self.retained_rows(*table))),
+        );
+        let sources = self
+            .sources
+            .0
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        for registered in sources.iter() {
+            registered.source.render(&mut out);
+        }
+        out
+    }
+}
+
+// =============================================================================
+// Families
+// =============================================================================
+
+/// Label values of one series, in the order of the family's label names
+type Series = Vec<String>;
+
+fn series(values: &[&str]) -> Series {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+/// Counters with labels; a series appears once it is first counted
+#[derive(Debug)]
+pub struct CounterVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    series: Mutex<BTreeMap<Series, u64>>,
+}
+
+#[allow(dead_code)]
+impl CounterVec {
+    /// Create the family `name` with `labels`
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Start the series for each of `values` at zero, so they are scraped
+    /// before anything happens
+    pub fn with_series(self, values: &[&[&str]]) -> Self {
+        for values in values {
+            self.add(values, 0);
+        }
+        self
+    }
+
+    /// Add one to the series labeled `values`
+    pub fn inc(&self, values: &[&str]) {
+        self.add(values, 1);
+    }
+
+    /// Add `n` to the series labeled `values`
+    pub fn add(&self, values: &[&str], n: u64) {
+        *lock(&self.series).entry(series(values)).or_default() += n;
+    }
+
+    /// Current count of the series labeled `values`
+    pub fn get(&self, values: &[&str]) -> u64 {
+        lock(&self.series)
+            .get(&series(values))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Append the family to `out`
+    pub fn render(&self, out: &mut String) {
+        header(out, "counter", self.name, self.help);
+        for (values, count) in lock(&self.series).iter() {
+            sample(out, self.name, self.labels, values, None, count);
+        }
+    }
+}
+
+/// Gauges with labels; a series appears once it is first set
+#[derive(Debug)]
+pub struct GaugeVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    series: Mutex<BTreeMap<Series, f64>>,
+}
+
+#[allow(dead_code)]
+impl GaugeVec {
+    /// Create the family `name` with `labels`
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Set the series labeled `values`
+    pub fn set(&self, values: &[&str], value: f64) {
+        lock(&self.series).insert(series(values), value);
+    }
+
+    /// Current value of the series labeled `values`
+    pub fn get(&self, values: &[&str]) -> Option<f64> {
+        lock(&self.series).get(&series(values)).copied()
+    }
+
+    /// Append the family to `out`
+    pub fn render(&self, out: &mut String) {
+        header(out, "gauge", self.name, self.help);
+        for (values, value) in lock(&self.series).iter() {
+            sample(out, self.name, self.labels, values, None, value);
+        }
+    }
+}
+
+/// Histograms with labels, for durations in seconds
+#[derive(Debug)]
+pub struct HistogramVec {
+    name: &'static str,
+    help: &'static str,
+    labels: &'static [&'static str],
+    buckets: &'static [f64],
+    series: Mutex<BTreeMap<Series, Observations>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Observations {
+    /// Observations at or below each bucket's bound, not cumulative
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+#[allow(dead_code)]
+impl HistogramVec {
+    /// Create the family `name` with `labels` and [`LATENCY_BUCKETS`]
+    pub fn new(name: &'static str, help: &'static str, labels: &'static [&'static str]) -> Self {
+        Self {
+            name,
+            help,
+            labels,
+            buckets: LATENCY_BUCKETS,
+            series: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Record `seconds` in the series labeled `values`
+    pub fn observe(&self, values: &[&str], seconds: f64) {
+        let mut series = lock(&self.series);
+        let observations = series.entry(self::series(values)).or_default();
+        observations.buckets.resize(self.buckets.len(), 0);
+        if let Some(bucket) = self.buckets.iter().position(|bound| seconds <= *bound) {
+            observations.buckets[bucket] += 1;
+        }
+        observations.sum += seconds;
+        observations.count += 1;
+    }
+
+    /// Number of observations in the series labeled `values`
+    pub fn count(&self, values: &[&str]) -> u64 {
+        lock(&self.series)
+            .get(&series(values))
+            .map_or(0, |observations| observations.count)
+    }
+
+    /// Append the family to `out`
+    pub fn render(&self, out: &mut String) {
+        header(out, "histogram", self.name, self.help);
+        let bucket_name = format!("{}_bucket", self.name);
+        for (values, observations) in lock(&self.series).iter() {
+            let mut cumulative = 0;
+            for (bound, count) in self.buckets.iter().zip(&observations.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                sample(
+                    out,
+                    &bucket_name,
+                    self.labels,
+                    values,
+                    Some(&le),
+                    &cumulative,
+                );
+            }
+            let count = observations.count;
+            sample(out, &bucket_name, self.labels, values, Some("+Inf"), &count);
+            let sum_name = format!("{}_sum", self.name);
+            sample(out, &sum_name, self.labels, values, None, &observations.sum);
+            let count_name = format!("{}_count", self.name);
+            sample(out, &count_name, self.labels, values, None, &count);
+        }
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn header(out: &mut String, kind: &str, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Append one sample line, with `le` as the last label of a histogram bucket
+fn sample(
+    out: &mut String,
+    name: &str,
+    labels: &[&str],
+    values: &[String],
+    le: Option<&str>,
+    value: &dyn Display,
+) {
+    let mut pairs: Vec<String> = labels
+        .iter()
+        .zip(values)
+        .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, pairs.join(","), value);
+    }
+}
+
+/// Escape a label value for the text format
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Append a metric of `kind` with one `label` to `out`
+fn family<'a>(
+    out: &mut String,
+    kind: &str,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: impl Iterator<Item = (&'a str, u64)>,
+) {
+    header(out, kind, name, help);
+    for (value_label, value) in values {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, value_label, value);
+    }
+}
+
+/// `/metrics`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/metrics", get(serve_metrics))
+}
+
+async fn serve_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    db_metrics::sample_pools(&state);
+    (
+        [(header::CONTENT_TYPE, CONTENT_TYPE)],
+        state.metrics.render(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.login_rejected(LoginRejection::Honeypot);
+        metrics.login_rejected(LoginRejection::Honeypot);
+        metrics.login_rejected(LoginRejection::MissingToken);
+
+        assert!(metrics.render().starts_with(
+            "# HELP login_rejected_total Login posts turned away before the password was checked\n\
+             # TYPE login_rejected_total counter\n\
+             login_rejected_total{reason=\"missing_token\"} 1\n\
+             login_rejected_total{reason=\"token_mismatch\"} 0\n\
+             login_rejected_total{reason=\"honeypot\"} 2\n"
+        ));
+    }
+
+    #[test]
+    fn test_render_retention() {
+        let metrics = Metrics::default();
+        metrics.pruned(Retained::Jobs, 3, 10);
+        metrics.pruned(Retained::Jobs, 2, 8);
+
+        assert_eq!(metrics.pruned_rows(Retained::Jobs), 5);
+        assert_eq!(metrics.retained_rows(Retained::Jobs), 8);
+        let rendered = metrics.render();
+        assert!(rendered.contains("# TYPE rows_pruned_total counter\n"));
+        assert!(rendered.contains("rows_pruned_total{table=\"jobs\"} 5\n"));
+        assert!(rendered.contains("# TYPE rows_retained gauge\n"));
+        assert!(rendered.contains("rows_retained{table=\"jobs\"} 8\n"));
+        assert!(rendered.contains("rows_retained{table=\"sessions\"} 0\n"));
+    }
+
+    #[derive(Debug)]
+    struct Deliveries {
+        sent: CounterVec,
+    }
+
+    impl Default for Deliveries {
+        fn default() -> Self {
+            Self {
+                sent: CounterVec::new("deliveries_total", "Deliveries", &["kind", "outcome"])
+                    .with_series(&[&["parcel", "ok"]]),
+            }
+        }
+    }
+
+    impl MetricSource for Deliveries {
+        fn render(&self, out: &mut String) {
+            self.sent.render(out);
+        }
+    }
+
+    #[test]
+    fn test_sources_register_once() {
+        let metrics = Metrics::default();
+        assert!(!metrics.render().contains("deliveries_total"));
+
+        metrics
+            .source::<Deliveries>()
+            .sent
+            .inc(&["letter", "failed"]);
+        metrics
+            .source::<Deliveries>()
+            .sent
+            .inc(&["letter", "failed"]);
+
+        let rendered = metrics.render();
+        assert_eq!(
+            rendered
+                .matches("# TYPE deliveries_total counter\n")
+                .count(),
+            1
+        );
+        assert!(rendered.contains("deliveries_total{kind=\"letter\",outcome=\"failed\"} 2\n"));
+        assert!(rendered.contains("deliveries_total{kind=\"parcel\",outcome=\"ok\"} 0\n"));
+    }
+
+    #[test]
+    fn test_gauge_and_label_escaping() {
+        let gauge = GaugeVec::new("drift_seconds", "Drift", &["task"]);
+        gauge.set(&["say \"hi\""], 0.25);
+        gauge.set(&["say \"hi\""], 0.5);
+
+        let mut out = String::new();
+        gauge.render(&mut out);
+        assert_eq!(
+            out,
+            "# HELP drift_seconds Drift\n\
+             # TYPE drift_seconds gauge\n\
+             drift_seconds{task=\"say \\\"hi\\\"\"} 0.5\n"
+        );
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = HistogramVec::new("run_seconds", "Run time", &["kind"]);
+        histogram.observe(&["email"], 0.00390625);
+        histogram.observe(&["email"], 0.25);
+        histogram.observe(&["email"], 120.0);
+        assert_eq!(histogram.count(&["email"]), 3);
+
+        let mut out = String::new();
+        histogram.render(&mut out);
+        assert!(out.contains("# TYPE run_seconds histogram\n"));
+        assert!(out.contains("run_seconds_bucket{kind=\"email\",le=\"0.005\"} 1\n"));
+        assert!(out.contains("run_seconds_bucket{kind=\"email\",le=\"0.1\"} 1\n"));
+        assert!(out.contains("run_seconds_bucket{kind=\"email\",le=\"0.25\"} 2\n"));
+        assert!(out.contains("run_seconds_bucket{kind=\"email\",le=\"60\"} 2\n"));
+        assert!(out.contains("run_seconds_bucket{kind=\"email\",le=\"+Inf\"} 3\n"));
+        assert!(out.contains("run_seconds_sum{kind=\"email\"} 120.25390625\n"));
+        assert!(out.contains("run_seconds_count{kind=\"email\"} 3\n"));
+    }
+}
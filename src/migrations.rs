@@ -0,0 +1,445 @@
+//! # Namespaced Migrations
+//!
+//! The core schema in `migrations/` is applied by sqlx as before. Plugins and
+//! the app built on this crate bring their own [`MigrationSet`]s instead of
+//! copying files into that directory. Each set is tracked under its own
+//! namespace in `schema_migrations`, so version numbers only have to be
+//! unique within a set.
+//!
+//! Sets run after core. A set that builds on another set's tables names it
+//! with [`MigrationSet::after`] and runs after it. Otherwise sets run in the
+//! order they were registered. Unknown dependencies and cycles are reported
+//! before anything is applied.
+//!
+//! As with sqlx, an applied migration must not be edited or removed: a
+//! changed checksum or a version missing from its set stops startup.
+
+use sqlx::migrate::{MigrateError, Migration, Migrator};
+use sqlx::{Connection, PgConnection, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::output;
+
+/// Namespace of the core migrations; every set runs after them
+pub const CORE: &str = "core";
+
+/// Advisory lock held while sets are applied, so instances starting together
+/// do not apply the same migration twice
+const LOCK_KEY: i64 = 0x0061_7875_6d6d_6967; // "axummig"
+
+/// Migrations tracked under one namespace
+#[derive(Debug, Clone)]
+pub struct MigrationSet {
+    namespace: &'static str,
+    migrator: Arc<Migrator>,
+    after: Vec<&'static str>,
+}
+
+#[allow(dead_code)]
+impl MigrationSet {
+    /// Track `migrator`'s migrations, usually `sqlx::migrate!("./migrations")`,
+    /// under `namespace`
+    pub fn new(namespace: &'static str, migrator: Migrator) -> Self {
+        Self {
+            namespace,
+            migrator: Arc::new(migrator),
+            after: Vec::new(),
+        }
+    }
+
+    /// Run after the set tracked under `namespace`
+    pub fn after(mut self, namespace: &'static str) -> Self {
+        self.after.push(namespace);
+        self
+    }
+
+    /// Namespace the set is tracked under
+    pub fn namespace(&self) -> &'static str {
+        self.namespace
+    }
+
+    fn migrations(&self) -> impl Iterator<Item = &Migration> {
+        self.migrator
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+    }
+}
+
+/// Why migrations could not be applied
+#[derive(Debug)]
+pub enum MigrationError {
+    /// Applying the core migrations failed
+    Core(MigrateError),
+    /// Two sets use the same namespace, or a set uses [`CORE`]
+    DuplicateNamespace(&'static str),
+    /// A set runs after a namespace no set uses
+    UnknownDependency {
+        namespace: &'static str,
+        after: &'static str,
+    },
+    /// These sets depend on each other in a loop
+    Cycle(Vec<&'static str>),
+    /// An applied migration was edited since
+    ChecksumMismatch {
+        namespace: &'static str,
+        version: i64,
+    },
+    /// An applied migration is no longer in its set
+    Missing {
+        namespace: &'static str,
+        version: i64,
+    },
+    /// A migration failed; it was rolled back unless it opted out of a
+    /// transaction
+    Failed {
+        namespace: &'static str,
+        version: i64,
+        source: sqlx::Error,
+    },
+    /// Reading or recording migrations failed
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::Core(e) => write!(f, "core migrations failed: {}", e),
+            MigrationError::DuplicateNamespace(namespace) => {
+                write!(f, "migration namespace '{}' is used twice", namespace)
+            }
+            MigrationError::UnknownDependency { namespace, after } => write!(
+                f,
+                "migrations of '{}' run after '{}', which has none",
+                namespace, after
+            ),
+            MigrationError::Cycle(namespaces) => write!(
+                f,
+                "migrations of {} depend on each other in a loop",
+                namespaces.join(", ")
+            ),
+            MigrationError::ChecksumMismatch { namespace, version } => write!(
+                f,
+                "migration {} of '{}' was changed after it was applied",
+                version, namespace
+            ),
+            MigrationError::Missing { namespace, version } => write!(
+                f,
+                "migration {} of '{}' was applied but is missing",
+                version, namespace
+            ),
+            MigrationError::Failed {
+                namespace,
+                version,
+                source,
+            } => write!(
+                f,
+                "migration {} of '{}' failed: {}",
+                version, namespace, source
+            ),
+            MigrationError::Database(e) => write!(f, "database error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MigrationError::Core(e) => Some(e),
+            MigrationError::Failed { source, .. } | MigrationError::Database(source) => {
+                Some(source)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl From<MigrateError> for MigrationError {
+    fn from(err: MigrateError) -> Self {
+        MigrationError::Core(err)
+    }
+}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(err: sqlx::Error) -> Self {
+        MigrationError::Database(err)
+    }
+}
+
+/// Apply `sets` in dependency order. Expects the core migrations, which
+/// create `schema_migrations`, to have run.
+pub async fn run(pool: &PgPool, sets: Vec<MigrationSet>) -> Result<(), MigrationError> {
+    let sets = order(sets)?;
+    if sets.is_empty() {
+        return Ok(());
+    }
+
+    let mut conn = pool.acquire().await?;
+    sqlx::query("SELECT pg_advisory_lock($1)")
+        .bind(LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+    let mut result = Ok(());
+    for set in &sets {
+        result = apply(&mut conn, set).await;
+        if result.is_err() {
+            break;
+        }
+    }
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(LOCK_KEY)
+        .execute(&mut *conn)
+        .await?;
+    result
+}
+
+/// Migrations of core and `sets` that have not been applied yet, as seen by
+/// the readiness probe
+pub async fn pending(pool: &PgPool, sets: &[MigrationSet]) -> Result<usize, sqlx::Error> {
+    let core: HashSet<i64> =
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+    let mut count = sqlx::migrate!("./migrations")
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| !core.contains(&migration.version))
+        .count();
+
+    if sets.is_empty() {
+        return Ok(count);
+    }
+    let applied: HashSet<(String, i64)> =
+        sqlx::query_as("SELECT namespace, version FROM schema_migrations")
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+    for set in sets {
+        count += set
+            .migrations()
+            .filter(|migration| !applied.contains(&(set.namespace.to_string(), migration.version)))
+            .count();
+    }
+    Ok(count)
+}
+
+/// Sets sorted so each comes after the ones it names, otherwise keeping
+/// registration order
+fn order(mut pending: Vec<MigrationSet>) -> Result<Vec<MigrationSet>, MigrationError> {
+    let mut namespaces = HashSet::from([CORE]);
+    for set in &pending {
+        if !namespaces.insert(set.namespace) {
+            return Err(MigrationError::DuplicateNamespace(set.namespace));
+        }
+    }
+    for set in &pending {
+        if let Some(after) = set.after.iter().find(|after| !namespaces.contains(*after)) {
+            return Err(MigrationError::UnknownDependency {
+                namespace: set.namespace,
+                after,
+            });
+        }
+    }
+
+    let mut done = HashSet::from([CORE]);
+    let mut ordered = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let Some(ready) = pending
+            .iter()
+            .position(|set| set.after.iter().all(|after| done.contains(after)))
+        else {
+            return Err(MigrationError::Cycle(
+                pending.iter().map(MigrationSet::namespace).collect(),
+            ));
+        };
+        let set = pending.remove(ready);
+        done.insert(set.namespace);
+        ordered.push(set);
+    }
+    Ok(ordered)
+}
+
+/// Apply the migrations of `set` that have not run yet
+async fn apply(conn: &mut PgConnection, set: &MigrationSet) -> Result<(), MigrationError> {
+    let applied: HashMap<i64, Vec<u8>> =
+        sqlx::query_as("SELECT version, checksum FROM schema_migrations WHERE namespace = $1")
+            .bind(set.namespace)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .collect();
+    check_applied(set, &applied)?;
+
+    let mut count = 0;
+    for migration in set.migrations() {
+        if applied.contains_key(&migration.version) {
+            continue;
+        }
+        let failed = |source| MigrationError::Failed {
+            namespace: set.namespace,
+            version: migration.version,
+            source,
+        };
+        let started = Instant::now();
+        if migration.no_tx {
+            sqlx::raw_sql(&migration.sql)
+                .execute(&mut *conn)
+                .await
+                .map_err(failed)?;
+            record(&mut *conn, set.namespace, migration, started).await?;
+        } else {
+            let mut tx = conn.begin().await?;
+            sqlx::raw_sql(&migration.sql)
+                .execute(&mut *tx)
+                .await
+                .map_err(failed)?;
+            record(&mut tx, set.namespace, migration, started).await?;
+            tx.commit().await?;
+        }
+        count += 1;
+    }
+
+    if count > 0 {
+        output::success(format!("Applied {} migrations of {}", count, set.namespace));
+    }
+    Ok(())
+}
+
+/// Fail if an applied migration was edited or dropped from `set`
+fn check_applied(
+    set: &MigrationSet,
+    applied: &HashMap<i64, Vec<u8>>,
+) -> Result<(), MigrationError> {
+    let known: HashMap<i64, &[u8]> = set
+        .migrations()
+        .map(|migration| (migration.version, &*migration.checksum))
+        .collect();
+    let mut versions: Vec<_> = applied.keys().copied().collect();
+    versions.sort_unstable();
+    for version in versions {
+        match known.get(&version) {
+            Some(checksum) if *checksum == applied[&version].as_slice() => {}
+            Some(_) => {
+                return Err(MigrationError::ChecksumMismatch {
+                    namespace: set.namespace,
+                    version,
+                });
+            }
+            None => {
+                return Err(MigrationError::Missing {
+                    namespace: set.namespace,
+                    version,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn record(
+    conn: &mut PgConnection,
+    namespace: &str,
+    migration: &Migration,
+    started: Instant,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO schema_migrations (namespace, version, description, checksum, execution_ms)
+         VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(namespace)
+    .bind(migration.version)
+    .bind(&*migration.description)
+    .bind(&*migration.checksum)
+    .bind(started.elapsed().as_millis() as i64)
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::migrate::MigrationType;
+    use std::borrow::Cow;
+
+    fn set(namespace: &'static str, after: &[&'static str]) -> MigrationSet {
+        let mut set = MigrationSet::new(namespace, Migrator::DEFAULT);
+        set.after = after.to_vec();
+        set
+    }
+
+    fn namespaces(sets: &[MigrationSet]) -> Vec<&'static str> {
+        sets.iter().map(MigrationSet::namespace).collect()
+    }
+
+    #[test]
+    fn test_order_follows_dependencies() {
+        let sets = vec![
+            set("app", &["billing", "forum"]),
+            set("billing", &[]),
+            set("forum", &[CORE]),
+        ];
+        assert_eq!(
+            namespaces(&order(sets).unwrap()),
+            ["billing", "forum", "app"]
+        );
+    }
+
+    #[test]
+    fn test_order_rejects_bad_sets() {
+        assert!(matches!(
+            order(vec![set("billing", &[]), set("billing", &[])]),
+            Err(MigrationError::DuplicateNamespace("billing"))
+        ));
+        assert!(matches!(
+            order(vec![set(CORE, &[])]),
+            Err(MigrationError::DuplicateNamespace(CORE))
+        ));
+        assert!(matches!(
+            order(vec![set("app", &["billing"])]),
+            Err(MigrationError::UnknownDependency {
+                namespace: "app",
+                after: "billing"
+            })
+        ));
+        match order(vec![set("a", &["b"]), set("b", &["a"]), set("c", &[])]) {
+            Err(MigrationError::Cycle(cycle)) => assert_eq!(cycle, ["a", "b"]),
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_check_applied() {
+        let migration = Migration::new(
+            1,
+            Cow::Borrowed("create invoices"),
+            MigrationType::Simple,
+            Cow::Borrowed("CREATE TABLE invoices (id INT)"),
+            false,
+        );
+        let checksum = migration.checksum.to_vec();
+        let set = MigrationSet::new(
+            "billing",
+            Migrator {
+                migrations: Cow::Owned(vec![migration]),
+                ..Migrator::DEFAULT
+            },
+        );
+
+        assert!(check_applied(&set, &HashMap::new()).is_ok());
+        assert!(check_applied(&set, &HashMap::from([(1, checksum)])).is_ok());
+        assert!(matches!(
+            check_applied(&set, &HashMap::from([(1, vec![0])])),
+            Err(MigrationError::ChecksumMismatch { version: 1, .. })
+        ));
+        assert!(matches!(
+            check_applied(&set, &HashMap::from([(2, vec![0])])),
+            Err(MigrationError::Missing { version: 2, .. })
+        ));
+    }
+}
@@ -6,6 +6,11 @@ use chrono::{DateTime, Timelike, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use time::OffsetDateTime;
+use ts_rs::TS;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::pagination::{CursorPage, Paginated};
 
 // =============================================================================
 // Time Conversion Utilities
@@ -13,14 +18,14 @@ use time::OffsetDateTime;
 
 /// Convert time::OffsetDateTime to chrono::DateTime<Utc>
 #[allow(dead_code)]
-pub fn time_to_chrono(dt: OffsetDateTime) -> DateTime<Utc> {
+pub(crate) fn time_to_chrono(dt: OffsetDateTime) -> DateTime<Utc> {
     DateTime::from_timestamp(dt.unix_timestamp(), dt.nanosecond())
         .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap())
 }
 
 /// Convert chrono::DateTime<Utc> to time::OffsetDateTime
 #[allow(dead_code)]
-pub fn chrono_to_time(dt: DateTime<Utc>) -> OffsetDateTime {
+pub(crate) fn chrono_to_time(dt: DateTime<Utc>) -> OffsetDateTime {
     OffsetDateTime::from_unix_timestamp(dt.timestamp())
         .unwrap_or(OffsetDateTime::UNIX_EPOCH)
         .replace_nanosecond(dt.nanosecond())
@@ -29,13 +34,13 @@ pub fn chrono_to_time(dt: DateTime<Utc>) -> OffsetDateTime {
 
 /// Convert Option<time::OffsetDateTime> to Option<chrono::DateTime<Utc>>
 #[allow(dead_code)]
-pub fn time_opt_to_chrono_opt(dt: Option<OffsetDateTime>) -> Option<DateTime<Utc>> {
+pub(crate) fn time_opt_to_chrono_opt(dt: Option<OffsetDateTime>) -> Option<DateTime<Utc>> {
     dt.map(time_to_chrono)
 }
 
 /// Convert Option<chrono::DateTime<Utc>> to Option<time::OffsetDateTime>
 #[allow(dead_code)]
-pub fn chrono_opt_to_time_opt(dt: Option<DateTime<Utc>>) -> Option<OffsetDateTime> {
+pub(crate) fn chrono_opt_to_time_opt(dt: Option<DateTime<Utc>>) -> Option<OffsetDateTime> {
     dt.map(chrono_to_time)
 }
 
@@ -43,29 +48,301 @@ pub fn chrono_opt_to_time_opt(dt: Option<DateTime<Utc>>) -> Option<OffsetDateTim
 // API Response Models
 // =============================================================================
 
-#[derive(Serialize, Deserialize)]
-pub struct ApiResponse {
+/// Current version of the response envelope format
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Standard envelope wrapping every JSON API response
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct Envelope<T> {
+    pub api_version: u32,
+    pub status: String, // "success" or "error"
+    pub message: Option<String>,
+    pub data: Option<T>,
+    pub meta: ResponseMeta,
+    pub errors: Vec<ErrorDetail>,
+}
+
+/// Metadata attached to every envelope
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct ResponseMeta {
+    pub request_id: Option<String>,
+    pub server_time: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[ts(optional)]
+    pub pagination: Option<PaginationMeta>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    #[ts(optional)]
+    pub cursor: Option<CursorMeta>,
+}
+
+/// Pagination details for list responses
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct PaginationMeta {
+    pub page: u32,
+    pub per_page: u32,
+    #[ts(type = "number")]
+    pub total: i64,
+    pub total_pages: u32,
+}
+
+/// Keyset pagination details for lists fetched with `?cursor=`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct CursorMeta {
+    pub per_page: u32,
+    /// Pass as `?cursor=` for the next page; `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// A single machine-readable error entry
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct ErrorDetail {
+    pub code: String,
     pub message: String,
-    pub status: String,
-    pub timestamp: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub field: Option<String>,
+}
+
+impl ResponseMeta {
+    fn now() -> Self {
+        Self {
+            request_id: None,
+            server_time: Utc::now().to_rfc3339(),
+            pagination: None,
+            cursor: None,
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+impl<T> Envelope<T> {
+    /// Successful response carrying `data`
+    pub fn success(data: T) -> Self {
+        Self {
+            api_version: ENVELOPE_VERSION,
+            status: "success".to_string(),
+            message: None,
+            data: Some(data),
+            meta: ResponseMeta::now(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Attach a human-readable message
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Attach the request ID assigned by the request-id middleware
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.meta.request_id = request_id;
+        self
+    }
+
+    /// Attach pagination details
+    pub fn with_pagination(mut self, pagination: PaginationMeta) -> Self {
+        self.meta.pagination = Some(pagination);
+        self
+    }
+
+    /// Attach keyset pagination details
+    pub fn with_cursor(mut self, cursor: CursorMeta) -> Self {
+        self.meta.cursor = Some(cursor);
+        self
+    }
+}
+
+impl<T> Envelope<Vec<T>> {
+    /// Successful response carrying one page of results
+    pub fn paginated(page: Paginated<T>) -> Self {
+        let meta = page.meta();
+        Envelope::success(page.items).with_pagination(meta)
+    }
+
+    /// Successful response carrying one page of a keyset-paginated list
+    pub fn cursor_paginated(page: CursorPage<T>) -> Self {
+        let meta = page.meta();
+        Envelope::success(page.items).with_cursor(meta)
+    }
+}
+
+impl Envelope<()> {
+    /// Successful response with only a message
+    pub fn message(message: impl Into<String>) -> Self {
+        Self {
+            data: None,
+            ..Envelope::success(())
+        }
+        .with_message(message)
+    }
+
+    /// Error response with a summary message and detailed errors
+    pub fn error(message: impl Into<String>, errors: Vec<ErrorDetail>) -> Self {
+        Self {
+            status: "error".to_string(),
+            data: None,
+            errors,
+            ..Envelope::success(())
+        }
+        .with_message(message)
+    }
+}
+
+#[derive(Serialize, Deserialize, ToSchema, TS)]
 pub struct HealthResponse {
-    pub status: String,
+    pub status: HealthVerdict,
     pub service: String,
     pub version: String,
     pub database: Option<DatabaseHealthInfo>,
+    /// Result of each registered health check
+    pub components: Vec<ComponentHealth>,
+    /// Container limits and the sizes chosen from them
+    pub resources: ResourceReport,
+}
+
+/// A setting and whether it came from the configuration
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct TunedValue {
+    #[ts(type = "number")]
+    pub value: u64,
+    /// Set in the configuration rather than derived from the limits
+    pub configured: bool,
+}
+
+/// Limits found and the values chosen from them, for `/health`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct ResourceReport {
+    /// `v1` or `v2` when running under a cgroup with limits
+    pub cgroup: Option<String>,
+    /// CPUs the OS schedules the process on
+    #[ts(type = "number")]
+    pub available_cpus: u64,
+    /// CPU quota of the cgroup, in CPUs
+    pub cpu_quota: Option<f64>,
+    /// Memory limit of the cgroup, in bytes
+    #[ts(type = "number | null")]
+    pub memory_limit_bytes: Option<u64>,
+    pub worker_threads: TunedValue,
+    pub db_max_connections: TunedValue,
+    pub job_concurrency: TunedValue,
 }
 
-#[derive(Serialize, Deserialize)]
+/// Overall verdict of the health checks
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthVerdict {
+    /// Every component is up
+    Healthy,
+    /// A non-critical component is down
+    Degraded,
+    /// A critical component is down
+    Unhealthy,
+}
+
+/// One component checked by `/health`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct ComponentHealth {
+    pub name: String,
+    pub up: bool,
+    /// Whether the service is unhealthy, rather than degraded, while it is down
+    pub critical: bool,
+    #[ts(type = "number")]
+    pub latency_ms: u64,
+    /// What went wrong, when down
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, TS)]
 pub struct DatabaseHealthInfo {
     pub connected: bool,
     pub database_name: String,
     pub pool_connections: u32,
+    #[ts(type = "number")]
     pub idle_connections: usize,
 }
 
+/// The process is up; answered without touching the database
+#[derive(Serialize, Deserialize, ToSchema, TS)]
+pub struct LivenessResponse {
+    pub alive: bool,
+    pub service: String,
+    pub version: String,
+}
+
+/// Whether the service can take traffic: the database answers with every
+/// migration applied, the pool has a connection to spare, a job worker has
+/// sent a heartbeat within the stall timeout, and neither the database nor
+/// the workers have been flapping
+#[derive(Serialize, Deserialize, ToSchema, TS)]
+pub struct ReadinessResponse {
+    pub ready: bool,
+    pub status: HealthStatus,
+    pub database: bool,
+    /// Migrations of core, plugins, and the app not applied yet; null when
+    /// they could not be checked
+    #[ts(type = "number | null")]
+    pub migrations_pending: Option<usize>,
+    pub pool: PoolUsage,
+    pub workers: WorkerLiveness,
+}
+
+/// Connections of the database pool
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema, TS)]
+pub struct PoolUsage {
+    /// Open connections, idle or in use
+    pub size: u32,
+    #[ts(type = "number")]
+    pub idle: usize,
+    pub max: u32,
+}
+
+impl PoolUsage {
+    /// Every connection the pool may open is open and in use
+    pub fn exhausted(&self) -> bool {
+        self.size >= self.max && self.idle == 0
+    }
+}
+
+/// How a dependency has fared over the recent readiness probes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema, TS)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Healthy,
+    Unhealthy,
+    /// Went up and down repeatedly within the flap window
+    Unstable,
+}
+
+/// One readiness probe
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct HealthSample {
+    pub at: DateTime<Utc>,
+    pub database: bool,
+    /// Whether a job worker was alive
+    pub workers: bool,
+}
+
+/// Recent readiness probes of the instance that answered, oldest first
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct HealthHistoryResponse {
+    /// The worse of the dependency statuses
+    pub status: HealthStatus,
+    pub database: HealthStatus,
+    pub workers: HealthStatus,
+    pub samples: Vec<HealthSample>,
+}
+
+/// Job workers by liveness
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, TS)]
+pub struct WorkerLiveness {
+    /// Workers with a recent heartbeat
+    #[ts(type = "number")]
+    pub alive: usize,
+    /// Workers whose heartbeat is older than the stall timeout
+    #[ts(type = "number")]
+    pub stalled: usize,
+    pub last_heartbeat_at: Option<DateTime<Utc>>,
+}
+
 // =============================================================================
 // Database Models
 // =============================================================================
@@ -78,12 +355,16 @@ pub struct User {
     pub password_hash: Option<String>,
     pub email_verified: bool,
     pub is_active: bool,
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// Free-form JSON object kept with the profile
+    pub profile_data: serde_json::Value,
     pub last_login: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
 pub struct Category {
     pub id: i32,
     pub category_name: String,
@@ -94,11 +375,12 @@ pub struct Category {
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
 pub struct Item {
     pub id: i32,
     pub title: String,
     pub description: Option<String>,
+    #[ts(type = "unknown")]
     pub data: Option<serde_json::Value>, // Flexible JSON field for custom data
     pub is_active: bool,
     pub category_id: i32,
@@ -106,19 +388,431 @@ pub struct Item {
     pub updated_at: DateTime<Utc>,
 }
 
+/// An organization served from its own subdomain, with optional branding
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct Tenant {
+    pub id: i32,
+    pub slug: String,
+    pub name: String,
+    pub primary_color: Option<String>,
+    pub accent_color: Option<String>,
+    pub logo_url: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// A custom domain attached to a tenant
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct TenantDomain {
+    pub id: i32,
+    pub tenant_id: i32,
+    pub domain: String,
+    /// Value expected in the domain's verification TXT record
+    pub verification_token: String,
+    pub verified_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
 // =============================================================================
 // Request/Response DTOs
 // =============================================================================
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
 #[allow(dead_code)]
 pub struct CreateUserRequest {
     pub username: String,
     pub email: String,
     pub password: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Locale for emails, e.g. `de` or `pt-BR`; the default locale if omitted
+    #[serde(default)]
+    pub locale: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Filters for listing users
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserFilter {
+    /// Case-insensitive match on username or email
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub search: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// Case-insensitive SQL `LIKE` pattern for the email, e.g. `%@example.com`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email_like: Option<String>,
+    /// Users created at or after this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+}
+
+/// Filters for the audit log
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditFilter {
+    /// Entries of this user
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actor_id: Option<i32>,
+    /// Exact action, such as `login_failed`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub action: Option<String>,
+    /// Kind of target, such as `user` or `tenant`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct SetPasswordRequest {
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct AssignRolesRequest {
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct ChangeUsernameRequest {
+    pub username: String,
+}
+
+/// A rename recorded in a user's username history
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct UsernameChangeEntry {
+    pub old_username: String,
+    pub new_username: String,
+    /// User who renamed them, themselves or an admin; `None` once deleted
+    pub changed_by: Option<i32>,
+    pub changed_at: DateTime<Utc>,
+}
+
+/// How renaming a user turned out
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UsernameChange {
+    /// Renamed; holds the previous name
+    Changed(String),
+    /// The user already has exactly that name
+    Unchanged,
+    /// Another user has the name, in some letter case
+    Taken,
+    /// No such user
+    NotFound,
+}
+
+/// Something a user did, or that was done to their account, as shown to them
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct ActivityEntry {
+    #[ts(type = "number")]
+    pub id: i64,
+    /// Audit action, such as `login` or `profile_updated`
+    pub action: String,
+    /// What happened, such as "Signed in"
+    pub summary: String,
+    /// Facts the user may see, such as the changed fields
+    #[ts(type = "Record<string, unknown>")]
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A user's preferences, with defaults for those they have not set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema, TS)]
+pub struct Preferences {
+    /// Color scheme: `system`, `light`, or `dark`
+    pub theme: String,
+    /// Language of emails, e.g. `de` or `pt-BR`
+    pub locale: String,
+    /// IANA time zone pages show times in; UTC when `None`
+    pub timezone: Option<String>,
+    /// Rows per page of lists that are not given a page size
+    pub items_per_page: u32,
+}
+
+/// Preferences to change; fields left out keep their value
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema, TS)]
+pub struct UpdatePreferencesRequest {
+    #[serde(default)]
+    #[ts(optional)]
+    pub theme: Option<String>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub locale: Option<String>,
+    /// An empty string means UTC
+    #[serde(default)]
+    #[ts(optional)]
+    pub timezone: Option<String>,
+    #[serde(default)]
+    #[ts(optional)]
+    pub items_per_page: Option<u32>,
+}
+
+/// A message shown to one user inside the app
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct Notification {
+    #[ts(type = "number")]
+    pub id: i64,
+    /// What it is about, such as `comment_reply`
+    pub kind: String,
+    pub title: String,
+    pub body: Option<String>,
+    /// Page the notification is about
+    pub link: Option<String>,
+    /// When the user read it; `None` while unread
+    pub read_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A notification to send
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NewNotification {
+    pub kind: String,
+    pub title: String,
+    pub body: Option<String>,
+    pub link: Option<String>,
+}
+
+/// Query of `/api/notifications`
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct NotificationQuery {
+    /// Include notifications already read
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// How many notifications a user has not read
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct UnreadCount {
+    #[ts(type = "number")]
+    pub unread: i64,
+}
+
+/// SQL submitted to the admin SQL console
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct SqlQueryRequest {
+    pub query: String,
+}
+
+/// New tenant with optional branding
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct CreateTenantRequest {
+    pub slug: String,
+    pub name: String,
+    #[serde(flatten)]
+    pub theme: TenantThemeRequest,
+}
+
+/// Tenant branding; colors are `#rrggbb`, missing fields are cleared
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema, TS)]
+pub struct TenantThemeRequest {
+    pub primary_color: Option<String>,
+    pub accent_color: Option<String>,
+    pub logo_url: Option<String>,
+}
+
+/// Custom domain to attach to a tenant
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct AddDomainRequest {
+    pub domain: String,
+}
+
+/// An admin-written Tera snippet, rendered in the sandbox of
+/// [`crate::snippets`]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct Snippet {
+    pub id: i32,
+    /// Where the snippet shows, e.g. `dashboard.welcome` or `email.footer`
+    pub name: String,
+    pub description: Option<String>,
+    pub body: String,
+    /// Admin who saved it last
+    pub updated_by: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Tera source and note for a snippet
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct SaveSnippetRequest {
+    pub body: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// Tera source for a tenant's partial override
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct TenantPartialRequest {
+    pub body: String,
+}
+
+/// A saved version of an email template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailTemplateVersion {
+    pub id: i32,
+    pub name: String,
+    pub version: i32,
+    pub subject: String,
+    pub body: String,
+    /// Username of the admin who saved this version
+    pub author: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Form posted by the email template editor
+#[derive(Debug, Deserialize)]
+pub struct EmailTemplateForm {
+    pub subject: String,
+    pub body: String,
+    /// `test` to send the draft to the signed-in admin instead of saving
+    pub action: Option<String>,
+}
+
+/// Form restoring an earlier email template version
+#[derive(Debug, Deserialize)]
+pub struct EmailTemplateRevertForm {
+    /// Version to restore; 0 restores the bundled template
+    pub version: i32,
+}
+
+/// An email the application tried to send
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct OutboxEmail {
+    pub id: i32,
+    pub template: String,
+    pub recipient: String,
+    pub subject: String,
+    /// `sent`, `failed`, or `suppressed`
+    pub status: String,
+    pub error: Option<String>,
+    /// Why the recipient is now suppressed, if it is
+    pub undeliverable: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A file a user uploaded; its contents are in the storage backend
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct Upload {
+    pub id: Uuid,
+    pub user_id: i32,
+    pub filename: String,
+    pub content_type: String,
+    #[ts(type = "number")]
+    pub size_bytes: i64,
+    /// Key of the contents in the storage backend
+    #[serde(skip)]
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A background job in the `jobs` queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: i64,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    /// 0 (low) to 2 (high); see [`crate::jobs::Priority`]
+    pub priority: i16,
+    /// `queued`, `running`, `done`, or `failed`
+    pub status: String,
+    /// Runs started, not counting ones released at shutdown
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub last_error: Option<String>,
+    /// Worker running the job
+    pub locked_by: Option<String>,
+    /// When the job is next due
+    pub run_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// A job worker and its last heartbeat
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct JobWorker {
+    /// Also recorded in `locked_by` on the jobs it claims
+    pub id: String,
+    pub hostname: String,
+    pub running_jobs: i32,
+    pub started_at: DateTime<Utc>,
+    pub heartbeat_at: DateTime<Utc>,
+    /// Whether the heartbeat is within the stall timeout
+    pub alive: bool,
+}
+
+/// Queue sizes and workers shown on the jobs dashboard
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct JobsOverview {
+    #[ts(type = "number")]
+    pub queued: i64,
+    #[ts(type = "number")]
+    pub running: i64,
+    #[ts(type = "number")]
+    pub failed: i64,
+    #[ts(type = "number")]
+    pub done: i64,
+    pub liveness: WorkerLiveness,
+    pub workers: Vec<JobWorker>,
+}
+
+/// A security-relevant action recorded in the audit log
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct AuditEntry {
+    #[ts(type = "number")]
+    pub id: i64,
+    /// User who acted; `None` for failed sign-ins, startup, and deleted users
+    pub actor_id: Option<i32>,
+    pub actor_username: Option<String>,
+    /// What happened, such as `login` or `admin.user_deactivated`
+    pub action: String,
+    /// Kind of thing acted on, such as `user` or `tenant`
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    /// Action-specific facts; never secrets
+    #[ts(type = "unknown")]
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An address that bounced or complained; nothing more is sent to it
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct EmailSuppression {
+    pub email: String,
+    /// `bounce` or `complaint`
+    pub reason: String,
+    /// Provider that reported it (`ses` or `sendgrid`)
+    pub provider: String,
+    pub detail: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Form posted by the SQL console page
+#[derive(Debug, Deserialize)]
+pub struct SqlConsoleForm {
+    pub query: String,
+    /// `csv` when the export button was used
+    pub export: Option<String>,
+}
+
+/// Rows returned by a read-only SQL console query
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, TS)]
+pub struct SqlQueryResult {
+    pub columns: Vec<String>,
+    /// One array per row, values in column order
+    #[ts(type = "unknown[][]")]
+    pub rows: Vec<Vec<serde_json::Value>>,
+    #[ts(type = "number")]
+    pub row_count: usize,
+    /// More rows matched than were returned
+    pub truncated: bool,
+    #[ts(type = "number")]
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
 #[allow(dead_code)]
 pub struct UserResponse {
     pub id: i32,
@@ -126,7 +820,21 @@ pub struct UserResponse {
     pub email: String,
     pub email_verified: bool,
     pub is_active: bool,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    #[serde(default)]
+    pub bio: Option<String>,
+    /// Free-form JSON object kept with the profile
+    #[serde(default)]
+    #[ts(type = "Record<string, unknown>")]
+    pub profile_data: serde_json::Value,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Why mail to this address is suppressed (`bounce` or `complaint`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[ts(optional)]
+    pub email_undeliverable: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -138,7 +846,7 @@ pub struct CreateItemRequest {
     pub category_id: i32,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
 #[allow(dead_code)]
 pub struct ItemWithCategory {
     #[serde(flatten)]
@@ -146,6 +854,88 @@ pub struct ItemWithCategory {
     pub category: Category,
 }
 
+/// A visible item with its category and attachments
+#[derive(Debug, Serialize, Deserialize, ToSchema, TS)]
+pub struct ItemDetail {
+    #[serde(flatten)]
+    pub item: ItemWithCategory,
+    /// Oldest first; each is downloaded from `/attachments/{id}`
+    pub attachments: Vec<ItemAttachment>,
+}
+
+/// A file attached to an item; its contents are in the storage backend
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct ItemAttachment {
+    pub id: Uuid,
+    pub item_id: i32,
+    /// Who attached it
+    pub user_id: i32,
+    pub filename: String,
+    pub content_type: String,
+    #[ts(type = "number")]
+    pub size_bytes: i64,
+    /// Key of the contents in the storage backend
+    #[serde(skip)]
+    pub storage_key: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// An item title matching what was typed into a search box
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct ItemSuggestion {
+    pub id: i32,
+    pub title: String,
+    /// Display name of the item's category
+    pub category: String,
+}
+
+/// Query of `/api/items/suggest`
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SuggestQuery {
+    /// What was typed so far
+    #[serde(default)]
+    pub q: String,
+    /// Most suggestions to return (1-10, default 8)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// Query of `/api/items/search`
+#[derive(Debug, Default, Serialize, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ItemSearchQuery {
+    /// Words to find; quoted phrases, `or`, and `-word` work as in web search
+    #[serde(default)]
+    pub q: String,
+    /// Only items in this category
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_id: Option<i32>,
+}
+
+/// What to narrow an item search to, besides the words
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ItemSearchFilters {
+    pub category_id: Option<i32>,
+}
+
+/// A visible item matching a full-text search
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema, TS)]
+pub struct ItemSearchHit {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub item: Item,
+    /// Display name of the item's category
+    pub category: String,
+    /// Relevance; higher is better
+    pub rank: f32,
+    /// Title with the matched words in `<mark>`; otherwise HTML-escaped
+    pub title_html: String,
+    /// Passages of the description around the matches, marked like the
+    /// title; `None` without a description
+    pub snippet_html: Option<String>,
+}
+
 // =============================================================================
 // Authentication Models
 // =============================================================================
@@ -154,6 +944,10 @@ pub struct ItemWithCategory {
 pub struct LoginRequest {
     pub username: String,
     pub password: String,
+    /// Token from the login form, checked by [`login_guard`](crate::login_guard)
+    pub csrf_token: Option<String>,
+    /// Honeypot field; people leave it empty
+    pub website: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -170,12 +964,32 @@ pub struct UpdateProfileRequest {
     pub email: String,
 }
 
+/// The parts of a profile users describe themselves with
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileFields {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    /// A JSON object
+    pub profile_data: serde_json::Value,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthenticatedUser {
     pub id: i32,
     pub username: String,
     pub email: String,
     pub is_active: bool,
+    /// Profile picture, if the user uploaded one
+    #[serde(default)]
+    pub avatar_url: Option<String>,
+}
+
+impl AuthenticatedUser {
+    /// Point `avatar_url` at the avatar stored under `key`
+    pub fn with_avatar(mut self, key: Option<&str>) -> Self {
+        self.avatar_url = key.map(crate::avatars::url);
+        self
+    }
 }
 
 // Convert User to UserResponse (hiding sensitive fields)
@@ -187,11 +1001,31 @@ impl From<User> for UserResponse {
             email: user.email,
             email_verified: user.email_verified,
             is_active: user.is_active,
+            display_name: user.display_name,
+            bio: user.bio,
+            profile_data: user.profile_data,
             created_at: user.created_at,
+            roles: Vec::new(),
+            email_undeliverable: None,
         }
     }
 }
 
+#[allow(dead_code)]
+impl UserResponse {
+    /// Attach the user's role names
+    pub fn with_roles(mut self, roles: Vec<String>) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    /// Attach the reason the user's address is suppressed, if it is
+    pub fn with_email_undeliverable(mut self, reason: Option<String>) -> Self {
+        self.email_undeliverable = reason;
+        self
+    }
+}
+
 // Convert User to AuthenticatedUser
 impl From<User> for AuthenticatedUser {
     fn from(user: User) -> Self {
@@ -200,6 +1034,7 @@ impl From<User> for AuthenticatedUser {
             username: user.username,
             email: user.email,
             is_active: user.is_active,
+            avatar_url: None,
         }
     }
 }
@@ -208,6 +1043,7 @@ impl From<User> for AuthenticatedUser {
 mod tests {
     use super::*;
     use chrono::{DateTime, Utc};
+    use serde_json::json;
 
     #[test]
     fn test_user_to_user_response_conversion() {
@@ -218,6 +1054,9 @@ mod tests {
             password_hash: Some("hashed_password".to_string()),
             email_verified: true,
             is_active: true,
+            display_name: Some("Test User".to_string()),
+            bio: None,
+            profile_data: json!({ "pronouns": "they/them" }),
             last_login: None,
             created_at: DateTime::from_timestamp(1640995200, 0).unwrap(), // 2022-01-01
             updated_at: DateTime::from_timestamp(1640995200, 0).unwrap(),
@@ -262,19 +1101,40 @@ mod tests {
     }
 
     #[test]
-    fn test_api_response_serialization() {
-        let response = ApiResponse {
-            message: "Test message".to_string(),
-            status: "success".to_string(),
-            timestamp: "2022-01-01T00:00:00Z".to_string(),
-        };
+    fn test_envelope_serialization() {
+        let response = Envelope::success(vec![1, 2, 3])
+            .with_message("Test message")
+            .with_request_id(Some("req-1".to_string()));
 
         let json = serde_json::to_string(&response).expect("Should serialize");
-        let deserialized: ApiResponse = serde_json::from_str(&json).expect("Should deserialize");
+        let deserialized: Envelope<Vec<i32>> =
+            serde_json::from_str(&json).expect("Should deserialize");
 
-        assert_eq!(deserialized.message, "Test message");
+        assert_eq!(deserialized.api_version, ENVELOPE_VERSION);
         assert_eq!(deserialized.status, "success");
-        assert_eq!(deserialized.timestamp, "2022-01-01T00:00:00Z");
+        assert_eq!(deserialized.message.as_deref(), Some("Test message"));
+        assert_eq!(deserialized.data, Some(vec![1, 2, 3]));
+        assert_eq!(deserialized.meta.request_id.as_deref(), Some("req-1"));
+        assert!(deserialized.errors.is_empty());
+    }
+
+    #[test]
+    fn test_error_envelope_has_no_data() {
+        let response = Envelope::error(
+            "Invalid input",
+            vec![ErrorDetail {
+                code: "validation".to_string(),
+                message: "Email is required".to_string(),
+                field: Some("email".to_string()),
+            }],
+        );
+
+        let json = serde_json::to_value(&response).expect("Should serialize");
+
+        assert_eq!(json["status"], "error");
+        assert!(json["data"].is_null());
+        assert_eq!(json["errors"][0]["field"], "email");
+        assert!(json["meta"]["server_time"].is_string());
     }
 
     #[test]
@@ -0,0 +1,293 @@
+//! # Navigation
+//!
+//! Pages declare their title, icon, nav section, and who may open them where
+//! they are routed, through [`Pages`]. The resulting [`NavTable`] is handed
+//! to the [`track`] middleware, which works out the links the signed-in user
+//! can follow, and `render_template` puts them in every page context as
+//! `nav`. `base.html` draws the navbar, the account menu, and the admin bar
+//! from it, so a new page shows up in the navigation by being routed:
+//!
+//! ```rust,ignore
+//! let (pages, nav) = Pages::new()
+//!     .page("/reports", get(serve_reports), RouteMeta::new("Reports", Section::Admin).role(ADMIN_ROLE))
+//!     .into_parts();
+//! ```
+//!
+//! The metadata only hides links; handlers still check access themselves
+//! with extractors such as `AdminUser`.
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{HeaderMap, header},
+    middleware::Next,
+    response::Response,
+    routing::MethodRouter,
+};
+use serde::Serialize;
+use std::sync::Arc;
+
+use crate::scope::RequestScope;
+use crate::state::AppState;
+
+tokio::task_local! {
+    static CURRENT_NAV: Arc<[NavLink]>;
+}
+
+/// Where a page's link is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Section {
+    /// The top navbar
+    Main,
+    /// The signed-in user's menu
+    Account,
+    /// The admin menu and the bar above admin pages
+    Admin,
+}
+
+/// Who the link is shown to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Anyone,
+    SignedIn,
+    /// Signed-in users holding this role
+    Role(&'static str),
+}
+
+/// What a page declares about itself when routed
+#[derive(Debug, Clone)]
+pub struct RouteMeta {
+    pub title: &'static str,
+    /// SVG path data, drawn in a 24×24 outline icon
+    pub icon: Option<&'static str>,
+    pub section: Section,
+    pub access: Access,
+}
+
+#[allow(dead_code)]
+impl RouteMeta {
+    /// A page anyone may open, linked as `title` in `section`
+    pub fn new(title: &'static str, section: Section) -> Self {
+        Self {
+            title,
+            icon: None,
+            section,
+            access: Access::Anyone,
+        }
+    }
+
+    pub fn icon(mut self, path: &'static str) -> Self {
+        self.icon = Some(path);
+        self
+    }
+
+    /// Only linked for signed-in users
+    pub fn signed_in(mut self) -> Self {
+        self.access = Access::SignedIn;
+        self
+    }
+
+    /// Only linked for users holding `role`
+    pub fn role(mut self, role: &'static str) -> Self {
+        self.access = Access::Role(role);
+        self
+    }
+}
+
+/// A link as templates see it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct NavLink {
+    pub path: &'static str,
+    pub title: &'static str,
+    pub icon: Option<&'static str>,
+    pub section: Section,
+    /// The current page is this one or below it
+    pub active: bool,
+}
+
+/// Routed pages and their metadata, in the order they were declared
+#[derive(Debug, Clone, Default)]
+pub struct NavTable(Arc<[(&'static str, RouteMeta)]>);
+
+#[allow(dead_code)]
+impl NavTable {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Metadata declared for `path`
+    pub fn get(&self, path: &str) -> Option<&RouteMeta> {
+        self.0
+            .iter()
+            .find(|(declared, _)| *declared == path)
+            .map(|(_, meta)| meta)
+    }
+
+    /// Links a visitor may follow from `current`: everyone's when
+    /// `signed_in` is false, otherwise also those for any of `roles`
+    pub fn visible(&self, current: &str, signed_in: bool, roles: &[String]) -> Vec<NavLink> {
+        self.0
+            .iter()
+            .filter(|(_, meta)| match meta.access {
+                Access::Anyone => true,
+                Access::SignedIn => signed_in,
+                Access::Role(role) => signed_in && roles.iter().any(|held| held == role),
+            })
+            .map(|(path, meta)| NavLink {
+                path,
+                title: meta.title,
+                icon: meta.icon,
+                section: meta.section,
+                active: is_within(current, path),
+            })
+            .collect()
+    }
+}
+
+/// Router builder that records [`RouteMeta`] for the pages it routes
+#[derive(Default)]
+pub struct Pages {
+    router: Router<AppState>,
+    table: Vec<(&'static str, RouteMeta)>,
+}
+
+#[allow(dead_code)]
+impl Pages {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Route a page and link it in the navigation
+    pub fn page(
+        mut self,
+        path: &'static str,
+        method_router: MethodRouter<AppState>,
+        meta: RouteMeta,
+    ) -> Self {
+        self.router = self.router.route(path, method_router);
+        self.table.push((path, meta));
+        self
+    }
+
+    /// Route a page that is not linked, such as a form target
+    pub fn route(mut self, path: &'static str, method_router: MethodRouter<AppState>) -> Self {
+        self.router = self.router.route(path, method_router);
+        self
+    }
+
+    /// The routes, to merge into the app, and their navigation
+    pub fn into_parts(self) -> (Router<AppState>, NavTable) {
+        (self.router, NavTable(self.table.into()))
+    }
+}
+
+/// Links for the current request; empty outside [`track`]
+pub fn current() -> Arc<[NavLink]> {
+    CURRENT_NAV
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| Arc::from([]))
+}
+
+/// Middleware scoping the visitor's links to the request. Only requests
+/// that accept HTML pay for the role lookup. Must run inside
+/// [`scope::track`](crate::scope::track).
+pub async fn track(State(table): State<NavTable>, request: Request, next: Next) -> Response {
+    if table.is_empty() || !accepts_html(request.headers()) {
+        return next.run(request).await;
+    }
+    let Some(scope) = request.extensions().get::<RequestScope>().cloned() else {
+        return next.run(request).await;
+    };
+
+    let path = request.uri().path();
+    let links = match scope.user().await {
+        Some(_) => table.visible(path, true, scope.roles().await),
+        None => table.visible(path, false, &[]),
+    };
+    CURRENT_NAV.scope(links.into(), next.run(request)).await
+}
+
+pub(crate) fn accepts_html(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"))
+}
+
+/// `current` is `path` or a page below it; `/` only matches itself
+fn is_within(current: &str, path: &str) -> bool {
+    current == path
+        || (path != "/"
+            && current
+                .strip_prefix(path)
+                .is_some_and(|rest| rest.starts_with('/')))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+
+    fn table() -> NavTable {
+        Pages::new()
+            .page("/", get(|| async {}), RouteMeta::new("Home", Section::Main))
+            .page(
+                "/profile",
+                get(|| async {}),
+                RouteMeta::new("Profile", Section::Account).signed_in(),
+            )
+            .page(
+                "/admin/emails",
+                get(|| async {}),
+                RouteMeta::new("Emails", Section::Admin).role("admin"),
+            )
+            .route("/logout", get(|| async {}))
+            .into_parts()
+            .1
+    }
+
+    fn titles(links: &[NavLink]) -> Vec<&str> {
+        links.iter().map(|link| link.title).collect()
+    }
+
+    #[test]
+    fn test_links_follow_access() {
+        let table = table();
+        assert_eq!(titles(&table.visible("/", false, &[])), ["Home"]);
+        assert_eq!(
+            titles(&table.visible("/", true, &["editor".to_string()])),
+            ["Home", "Profile"]
+        );
+        assert_eq!(
+            titles(&table.visible("/", true, &["admin".to_string()])),
+            ["Home", "Profile", "Emails"]
+        );
+        // A role without a session counts for nothing
+        assert_eq!(
+            titles(&table.visible("/", false, &["admin".to_string()])),
+            ["Home"]
+        );
+        assert!(table.get("/logout").is_none());
+        assert_eq!(table.get("/profile").unwrap().access, Access::SignedIn);
+    }
+
+    #[test]
+    fn test_active_link() {
+        let links = table().visible("/admin/emails/welcome", true, &["admin".to_string()]);
+        let active: Vec<_> = links
+            .iter()
+            .filter(|link| link.active)
+            .map(|link| link.path)
+            .collect();
+        assert_eq!(active, ["/admin/emails"]);
+
+        assert!(is_within("/", "/"));
+        assert!(!is_within("/profiles", "/profile"));
+    }
+
+    #[test]
+    fn test_current_outside_a_request_is_empty() {
+        assert!(current().is_empty());
+    }
+}
@@ -0,0 +1,202 @@
+//! # Notifications
+//!
+//! Messages shown to one user inside the app, such as a reply to their
+//! comment. Code elsewhere sends them with
+//! [`NotificationService::create`](crate::services::NotificationService::create);
+//! users list them and mark them read at `/api/notifications`, or on the
+//! `/notifications` page.
+//!
+//! The [`track`] middleware counts the signed-in user's unread notifications
+//! for pages, and `render_template` puts the count in every page context as
+//! `unread_notifications` for the navbar badge. API requests skip the count.
+
+use axum::{
+    Router,
+    extract::{Path, Query, Request, State},
+    middleware::Next,
+    response::Response,
+    routing::{get, post},
+};
+use sqlx::PgPool;
+
+use crate::api::RequestId;
+use crate::auth::CurrentUser;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::models::{Envelope, Notification, NotificationQuery, UnreadCount};
+use crate::nav;
+use crate::output;
+use crate::pagination::{Pagination, PaginationQuery};
+use crate::scope::RequestScope;
+use crate::services::NotificationService;
+use crate::state::AppState;
+
+tokio::task_local! {
+    static UNREAD_COUNT: i64;
+}
+
+/// Unread notifications of the current request's user; 0 outside [`track`]
+/// or when nobody is signed in
+pub fn unread_count() -> i64 {
+    UNREAD_COUNT.try_with(|count| *count).unwrap_or(0)
+}
+
+/// Middleware scoping the signed-in user's unread count to the request.
+/// Only requests that accept HTML pay for the query. Must run inside
+/// [`scope::track`](crate::scope::track).
+pub async fn track(request: Request, next: Next) -> Response {
+    if !nav::accepts_html(request.headers()) {
+        return next.run(request).await;
+    }
+    let Some(scope) = request.extensions().get::<RequestScope>().cloned() else {
+        return next.run(request).await;
+    };
+    let Some(user_id) = scope.user().await.map(|user| user.id) else {
+        return next.run(request).await;
+    };
+
+    match NotificationService::unread_count(scope.pool(), user_id).await {
+        Ok(count) => UNREAD_COUNT.scope(count, next.run(request)).await,
+        Err(e) => {
+            output::warn(format!("Failed to count unread notifications: {}", e));
+            next.run(request).await
+        }
+    }
+}
+
+/// Routes for `/api/notifications`
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/notifications", get(list_notifications))
+        .route("/api/notifications/unread-count", get(get_unread_count))
+        .route("/api/notifications/read-all", post(mark_all_read))
+        .route("/api/notifications/{id}/read", post(mark_read))
+}
+
+/// The signed-in user's notifications, newest first; unread ones unless
+/// `?all=true`
+#[utoipa::path(
+    get,
+    path = "/api/notifications",
+    tag = "account",
+    params(NotificationQuery, PaginationQuery),
+    responses(
+        (status = 200, description = "One page of notifications", body = Envelope<Vec<Notification>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_notifications(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(query): Query<NotificationQuery>,
+    pagination: Pagination,
+) -> AppResult<Negotiated<Envelope<Vec<Notification>>>> {
+    let page = NotificationService::list(&pool, user.id, !query.all, &pagination).await?;
+    let meta = page.meta();
+    Ok(Negotiated(
+        format,
+        Envelope::success(page.items)
+            .with_pagination(meta)
+            .with_request_id(request_id),
+    ))
+}
+
+/// How many of the signed-in user's notifications are unread
+#[utoipa::path(
+    get,
+    path = "/api/notifications/unread-count",
+    tag = "account",
+    responses(
+        (status = 200, description = "Unread count", body = Envelope<UnreadCount>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn get_unread_count(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<UnreadCount>>> {
+    let unread = NotificationService::unread_count(&pool, user.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(UnreadCount { unread }).with_request_id(request_id),
+    ))
+}
+
+/// Mark one of the signed-in user's notifications read
+#[utoipa::path(
+    post,
+    path = "/api/notifications/{id}/read",
+    tag = "account",
+    params(("id" = i64, Path, description = "Notification ID")),
+    responses(
+        (status = 200, description = "Marked read; the remaining unread count", body = Envelope<UnreadCount>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such notification", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn mark_read(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(id): Path<i64>,
+) -> AppResult<Negotiated<Envelope<UnreadCount>>> {
+    if !NotificationService::mark_read(&pool, user.id, id).await? {
+        return Err(AppError::NotFound(format!("No notification {}", id)));
+    }
+    let unread = NotificationService::unread_count(&pool, user.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(UnreadCount { unread })
+            .with_message("Notification marked read")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Mark all of the signed-in user's notifications read
+#[utoipa::path(
+    post,
+    path = "/api/notifications/read-all",
+    tag = "account",
+    responses(
+        (status = 200, description = "All marked read", body = Envelope<UnreadCount>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn mark_all_read(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<UnreadCount>>> {
+    let marked = NotificationService::mark_all_read(&pool, user.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(UnreadCount { unread: 0 })
+            .with_message(format!("{} notifications marked read", marked))
+            .with_request_id(request_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_unread_count_outside_and_inside_a_request() {
+        assert_eq!(unread_count(), 0);
+        UNREAD_COUNT
+            .scope(3, async {
+                assert_eq!(unread_count(), 3);
+            })
+            .await;
+    }
+}
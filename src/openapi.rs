@@ -0,0 +1,225 @@
+//! # OpenAPI
+//!
+//! Machine-readable contract for the JSON API, generated from the `utoipa`
+//! annotations on handlers and DTOs. The spec is served at
+//! [`OPENAPI_PATH`] and browsable through Swagger UI at [`DOCS_PATH`].
+//! Add new JSON handlers to [`ApiDoc`] so the contract stays complete.
+
+use axum::{
+    Json, Router,
+    response::{Html, IntoResponse},
+    routing::get,
+};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::{
+    activity, admin, api, attachments, audit, bounces, health, jobs, notifications, preferences,
+    search, snippets, sql_console, suggest, tenants, uploads,
+};
+
+/// Path of the generated OpenAPI document
+pub const OPENAPI_PATH: &str = "/api/openapi.json";
+
+/// Path of the Swagger UI page
+pub const DOCS_PATH: &str = "/api/docs";
+
+/// Cookie set by the session layer, used by admin endpoints
+const SESSION_COOKIE: &str = "id";
+
+/// Swagger UI page; assets load from a CDN so nothing is bundled at build time
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta charset="utf-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1">
+    <title>Axum Base API</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+    <script>
+        window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+</body>
+</html>"##;
+
+/// OpenAPI document for the JSON API
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Axum Base API",
+        description = "JSON API. Responses use a standard envelope; send `Accept: application/msgpack` or `application/cbor` for binary encodings."
+    ),
+    paths(
+        api::health_check,
+        api::health_live,
+        api::health_ready,
+        api::api_hello,
+        api::list_items,
+        api::get_item,
+        api::list_categories,
+        suggest::suggest_items,
+        search::search_items,
+        preferences::get_preferences,
+        preferences::update_preferences,
+        preferences::reset_preference,
+        notifications::list_notifications,
+        notifications::get_unread_count,
+        notifications::mark_read,
+        notifications::mark_all_read,
+        activity::list_activity,
+        admin::list_users,
+        admin::create_user,
+        admin::get_user,
+        admin::deactivate_user,
+        admin::reactivate_user,
+        admin::set_password,
+        admin::assign_roles,
+        admin::change_username,
+        admin::username_history,
+        audit::list_audit_log,
+        sql_console::run_query,
+        tenants::list_tenants,
+        tenants::create_tenant,
+        tenants::update_theme,
+        tenants::set_partial,
+        tenants::delete_partial,
+        tenants::list_domains,
+        tenants::add_domain,
+        tenants::verify_domain,
+        tenants::remove_domain,
+        snippets::list_snippets,
+        snippets::get_snippet,
+        snippets::save_snippet,
+        snippets::delete_snippet,
+        bounces::ses_webhook,
+        bounces::sendgrid_webhook,
+        bounces::list_outbox,
+        bounces::list_suppressions,
+        bounces::remove_suppression,
+        jobs::jobs_overview,
+        health::health_history,
+        uploads::upload_file,
+        uploads::list_uploads,
+        uploads::download_file,
+        uploads::delete_file,
+        attachments::upload_attachment,
+        attachments::download_attachment,
+        attachments::delete_attachment,
+    ),
+    modifiers(&SessionAuth),
+    tags(
+        (name = "api", description = "Public endpoints"),
+        (name = "admin", description = "User, tenant, and snippet management; requires a session for a user with the admin role"),
+        (name = "account", description = "The signed-in user's own settings; require a signed-in session"),
+        (name = "files", description = "File uploads and item attachments; require a signed-in session, except attachment downloads"),
+        (name = "email", description = "Bounce and complaint webhooks; require `?token=` matching EMAIL_WEBHOOK_TOKEN")
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the session cookie as the `session` security scheme
+struct SessionAuth;
+
+impl Modify for SessionAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::with_description(
+                SESSION_COOKIE,
+                "Session cookie set by POST /login",
+            ))),
+        );
+    }
+}
+
+/// Routes serving the spec and Swagger UI
+pub fn routes<S>() -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    Router::new()
+        .route(OPENAPI_PATH, get(openapi_json))
+        .route(DOCS_PATH, get(swagger_ui))
+}
+
+async fn openapi_json() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+async fn swagger_ui() -> Html<&'static str> {
+    Html(SWAGGER_UI)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_documents_api_routes() {
+        let spec = ApiDoc::openapi();
+        for path in [
+            "/health",
+            "/health/live",
+            "/health/ready",
+            "/api/hello",
+            "/api/items",
+            "/api/categories",
+            "/api/items/suggest",
+            "/api/items/search",
+            "/api/preferences",
+            "/api/preferences/{key}",
+            "/api/notifications",
+            "/api/notifications/unread-count",
+            "/api/notifications/{id}/read",
+            "/api/notifications/read-all",
+            "/api/profile/activity",
+            "/api/admin/users",
+            "/api/admin/users/{id}",
+            "/api/admin/users/{id}/deactivate",
+            "/api/admin/users/{id}/reactivate",
+            "/api/admin/users/{id}/password",
+            "/api/admin/users/{id}/roles",
+            "/api/admin/audit",
+            "/api/admin/sql",
+            "/api/admin/tenants",
+            "/api/admin/tenants/{slug}/theme",
+            "/api/admin/tenants/{slug}/partials/{name}",
+            "/api/admin/tenants/{slug}/domains",
+            "/api/admin/tenants/{slug}/domains/{domain}/verify",
+            "/api/admin/snippets",
+            "/api/admin/snippets/{name}",
+            "/webhooks/email/ses",
+            "/webhooks/email/sendgrid",
+            "/api/admin/email/outbox",
+            "/api/admin/email/suppressions",
+            "/api/admin/email/suppressions/{email}",
+            "/api/admin/jobs",
+            "/api/admin/health/history",
+            "/api/uploads",
+            "/files/{id}",
+            "/api/items/{id}",
+            "/api/items/{id}/attachments",
+            "/api/items/{id}/attachments/{attachment_id}",
+            "/attachments/{id}",
+        ] {
+            assert!(
+                spec.paths.paths.contains_key(path),
+                "{} is undocumented",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn test_spec_registers_schemas_and_security() {
+        let spec = ApiDoc::openapi();
+        let components = spec.components.expect("Spec should have components");
+        assert!(components.schemas.contains_key("UserResponse"));
+        assert!(components.schemas.contains_key("ItemWithCategory"));
+        assert!(components.security_schemes.contains_key("session"));
+    }
+}
@@ -0,0 +1,193 @@
+//! # Console Output
+//!
+//! Leveled messages for the binaries and the server, used instead of bare
+//! `println!`. On a terminal each message gets a colored mark; piped into
+//! journald or a file it is plain text, with `warning:` and `error:` prefixes
+//! so those still stand out:
+//!
+//! ```text
+//! ✔ Database migrations completed        # terminal (green mark)
+//! Database migrations completed          # piped
+//! error: Failed to bind to address 0.0.0.0:3093: Address in use
+//! ```
+//!
+//! Color is off when `NO_COLOR` is set to anything but an empty string, when
+//! `--no-color` is passed, or when the stream is not a terminal. `--quiet`
+//! (or `-q`) keeps only warnings and errors. Binaries pass their arguments
+//! through [`init`], which applies and removes these flags when they come
+//! before any other argument.
+//!
+//! Success and info messages go to stdout; warnings and errors to stderr.
+
+use std::env;
+use std::fmt::Display;
+use std::io::{self, IsTerminal};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static NO_COLOR: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// How much a message matters
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    /// Something finished as it should
+    Success,
+    /// Progress and where to find things
+    Info,
+    /// Degraded, but carrying on
+    Warn,
+    /// Failed
+    Error,
+}
+
+impl Level {
+    /// Mark shown in front of the message on a color terminal
+    fn mark(self) -> &'static str {
+        match self {
+            Level::Success => "✔",
+            Level::Info => "•",
+            Level::Warn => "warning:",
+            Level::Error => "error:",
+        }
+    }
+
+    /// ANSI color of the mark
+    fn color(self) -> &'static str {
+        match self {
+            Level::Success => "32",
+            Level::Info => "36",
+            Level::Warn => "1;33",
+            Level::Error => "1;31",
+        }
+    }
+
+    fn is_problem(self) -> bool {
+        matches!(self, Level::Warn | Level::Error)
+    }
+}
+
+/// Apply `--no-color` and `--quiet`/`-q` from the front of `args` (after the
+/// program name) and `NO_COLOR`, returning the remaining arguments
+pub fn init(args: Vec<String>) -> Vec<String> {
+    if env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        NO_COLOR.store(true, Ordering::Relaxed);
+    }
+
+    let mut args = args.into_iter();
+    let mut rest: Vec<String> = args.next().into_iter().collect();
+    for arg in args.by_ref() {
+        match arg.as_str() {
+            "--no-color" => NO_COLOR.store(true, Ordering::Relaxed),
+            "--quiet" | "-q" => QUIET.store(true, Ordering::Relaxed),
+            _ => {
+                rest.push(arg);
+                break;
+            }
+        }
+    }
+    rest.extend(args);
+    rest
+}
+
+/// Keep only warnings and errors from now on, as `--quiet` does; for
+/// commands whose stdout is meant for another program
+#[allow(dead_code)]
+pub fn set_quiet() {
+    QUIET.store(true, Ordering::Relaxed);
+}
+
+/// Something finished as it should
+pub fn success(message: impl Display) {
+    write(Level::Success, message);
+}
+
+/// Progress and where to find things
+pub fn info(message: impl Display) {
+    write(Level::Info, message);
+}
+
+/// Degraded, but carrying on
+pub fn warn(message: impl Display) {
+    write(Level::Warn, message);
+}
+
+/// Failed
+pub fn error(message: impl Display) {
+    write(Level::Error, message);
+}
+
+/// An indented line belonging to the message before it, such as one entry
+/// of a list; hidden in quiet mode like info
+pub fn detail(message: impl Display) {
+    if !QUIET.load(Ordering::Relaxed) {
+        println!("  {}", message);
+    }
+}
+
+/// Write `message` at `level`, to stderr for warnings and errors
+pub fn write(level: Level, message: impl Display) {
+    if level.is_problem() {
+        let color = use_color(io::stderr().is_terminal());
+        eprintln!("{}", render(level, &message, color));
+    } else if !QUIET.load(Ordering::Relaxed) {
+        let color = use_color(io::stdout().is_terminal());
+        println!("{}", render(level, &message, color));
+    }
+}
+
+fn use_color(terminal: bool) -> bool {
+    terminal && !NO_COLOR.load(Ordering::Relaxed)
+}
+
+/// The line for `message`: marked and colored, or plain with only problems
+/// prefixed
+fn render(level: Level, message: &dyn Display, color: bool) -> String {
+    if color {
+        format!("\x1b[{}m{}\x1b[0m {}", level.color(), level.mark(), message)
+    } else if level.is_problem() {
+        format!("{} {}", level.mark(), message)
+    } else {
+        message.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render(Level::Success, &"Done", false), "Done");
+        assert_eq!(render(Level::Info, &"Starting", false), "Starting");
+        assert_eq!(render(Level::Warn, &"Slow", false), "warning: Slow");
+        assert_eq!(render(Level::Error, &"Failed", false), "error: Failed");
+        assert_eq!(
+            render(Level::Success, &"Done", true),
+            "\x1b[32m✔\x1b[0m Done"
+        );
+        assert_eq!(
+            render(Level::Error, &"Failed", true),
+            "\x1b[1;31merror:\x1b[0m Failed"
+        );
+    }
+
+    #[test]
+    fn test_init_strips_leading_flags() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(
+            init(args(&["admin", "--no-color", "templates", "check"])),
+            args(&["admin", "templates", "check"])
+        );
+        assert!(NO_COLOR.load(Ordering::Relaxed));
+        assert_eq!(init(args(&["admin"])), args(&["admin"]));
+
+        // Flags after the first argument belong to the command, such as a
+        // password that happens to be "-q"
+        assert_eq!(
+            init(args(&["set_password", "7", "-q"])),
+            args(&["set_password", "7", "-q"])
+        );
+        assert!(!QUIET.load(Ordering::Relaxed));
+    }
+}
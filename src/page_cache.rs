@@ -0,0 +1,317 @@
+//! # Page Cache
+//!
+//! The home and landing pages come out the same for every signed-out visitor
+//! of a host who reads the same language, so the [`serve`] layer on those
+//! routes keeps their HTML in a [`PageCache`] in
+//! [`AppState::pages`](crate::state::AppState::pages) for `PAGE_CACHE_TTL`
+//! seconds (10 by default).
+//!
+//! Only `GET`s without a session cookie are looked up and stored. Signed-in
+//! browsers, and any with a flash message waiting, send one, so they always
+//! get a freshly rendered page. A response is kept only when it is a `200`
+//! page that sets no cookies. Entries are keyed by `Host`, resolved language,
+//! and path; the query string is ignored, as these pages do not read it.
+//!
+//! Served and stored pages carry `X-Cache: HIT` or `MISS`; bypassed requests
+//! get neither. The cache is per process, holds at most [`CACHE_CAPACITY`]
+//! pages, and is off in development and with `PAGE_CACHE_TTL=0`.
+//! [`PageCacheMetrics`] reports its hits, misses, and size at `/metrics`.
+
+use axum::{
+    body::{Body, Bytes, to_bytes},
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::{AppConfig, Environment};
+use crate::error::AppError;
+use crate::metrics::{CounterVec, GaugeVec, MetricSource};
+use crate::scope::RequestScope;
+use crate::state::AppState;
+
+/// Pages the cache holds, across hosts and languages
+pub const CACHE_CAPACITY: usize = 256;
+
+/// Response header telling whether the page came from the cache
+pub const X_CACHE: &str = "x-cache";
+
+/// A rendered page as it was sent
+#[derive(Clone)]
+struct CachedPage {
+    fetched: Instant,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+impl CachedPage {
+    fn response(self, result: &'static str) -> Response {
+        let mut response = Response::new(Body::from(self.body));
+        *response.headers_mut() = self.headers;
+        response
+            .headers_mut()
+            .insert(X_CACHE, HeaderValue::from_static(result));
+        response
+    }
+}
+
+/// Rendered pages for signed-out visitors, the oldest evicted first
+pub struct PageCache {
+    capacity: usize,
+    ttl: Duration,
+    pages: Mutex<HashMap<String, CachedPage>>,
+}
+
+impl PageCache {
+    /// Create a cache of `capacity` pages, each served for `ttl`; a zero
+    /// `ttl` turns it off
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            pages: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Cache for the configured environment: off in development
+    pub fn from_config(config: &AppConfig) -> Self {
+        let ttl = match config.environment {
+            Environment::Development => Duration::ZERO,
+            _ => Duration::from_secs(config.server.page_cache_ttl),
+        };
+        Self::new(CACHE_CAPACITY, ttl)
+    }
+
+    /// Whether pages are cached at all
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0 && !self.ttl.is_zero()
+    }
+
+    /// Number of pages cached, expired ones included
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Whether nothing is cached
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, CachedPage>> {
+        self.pages
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<CachedPage> {
+        let mut pages = self.lock();
+        let page = pages.get(key)?;
+        if now.duration_since(page.fetched) < self.ttl {
+            return Some(page.clone());
+        }
+        pages.remove(key);
+        None
+    }
+
+    fn insert_at(&self, key: String, page: CachedPage, now: Instant) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let mut pages = self.lock();
+        if !pages.contains_key(&key) && pages.len() >= self.capacity {
+            // Expired pages go first, then the one that expires soonest
+            let ttl = self.ttl;
+            pages.retain(|_, page| now.duration_since(page.fetched) < ttl);
+            if pages.len() >= self.capacity {
+                let oldest = pages
+                    .iter()
+                    .min_by_key(|(_, page)| page.fetched)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    pages.remove(&oldest);
+                }
+            }
+        }
+        pages.insert(key, page);
+    }
+}
+
+/// Use of the page cache, served at `/metrics`
+#[derive(Debug)]
+pub struct PageCacheMetrics {
+    /// Lookups by result, `hit` or `miss`
+    pub lookups: CounterVec,
+    /// Pages cached
+    pub entries: GaugeVec,
+}
+
+impl Default for PageCacheMetrics {
+    fn default() -> Self {
+        Self {
+            lookups: CounterVec::new(
+                "page_cache_lookups_total",
+                "Page cache lookups by result",
+                &["result"],
+            )
+            .with_series(&[&["hit"], &["miss"]]),
+            entries: GaugeVec::new("page_cache_entries", "Pages in the page cache", &[]),
+        }
+    }
+}
+
+impl MetricSource for PageCacheMetrics {
+    fn render(&self, out: &mut String) {
+        self.lookups.render(out);
+        self.entries.render(out);
+    }
+}
+
+/// Middleware serving signed-out `GET`s of a page from the cache, and
+/// caching what the page renders on a miss
+pub async fn serve(
+    State(state): State<AppState>,
+    scope: RequestScope,
+    request: Request,
+    next: Next,
+) -> Response {
+    let cookie_name = &state.config.session.cookie_name;
+    if !state.pages.is_enabled()
+        || request.method() != Method::GET
+        || has_cookie(request.headers(), cookie_name)
+    {
+        return next.run(request).await;
+    }
+
+    let key = cache_key(
+        request.headers(),
+        scope.locale().await,
+        request.uri().path(),
+    );
+    let metrics = state.metrics.source::<PageCacheMetrics>();
+    if let Some(page) = state.pages.get_at(&key, Instant::now()) {
+        metrics.lookups.inc(&["hit"]);
+        return page.response("HIT");
+    }
+    metrics.lookups.inc(&["miss"]);
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK || response.headers().contains_key(header::SET_COOKIE) {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return AppError::Internal(format!("Failed to read page: {}", e)).into_response(),
+    };
+    let page = CachedPage {
+        fetched: Instant::now(),
+        headers: parts.headers,
+        body,
+    };
+    state.pages.insert_at(key, page.clone(), page.fetched);
+    metrics.entries.set(&[], state.pages.len() as f64);
+    page.response("MISS")
+}
+
+/// Whether the request sends the cookie called `name`
+fn has_cookie(headers: &HeaderMap, name: &str) -> bool {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+        .any(|(cookie, _)| cookie == name)
+}
+
+/// What a signed-out page varies by: the tenant's host, the language, and
+/// the page
+fn cache_key(headers: &HeaderMap, locale: &str, path: &str) -> String {
+    let host = headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    format!("{}\n{}\n{}", host, locale, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(body: &'static str, fetched: Instant) -> CachedPage {
+        CachedPage {
+            fetched,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn test_pages_expire() {
+        let cache = PageCache::new(4, Duration::from_secs(10));
+        let start = Instant::now();
+
+        cache.insert_at("/".to_string(), page("home", start), start);
+        let hit = cache.get_at("/", start + Duration::from_secs(9)).unwrap();
+        assert_eq!(hit.body, "home");
+        assert!(cache.get_at("/", start + Duration::from_secs(10)).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_page_is_evicted() {
+        let cache = PageCache::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let later = start + Duration::from_secs(1);
+
+        cache.insert_at("a".to_string(), page("a", start), start);
+        cache.insert_at("b".to_string(), page("b", later), later);
+        cache.insert_at("c".to_string(), page("c", later), later);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at("a", later).is_none());
+        assert!(cache.get_at("b", later).is_some());
+        assert!(cache.get_at("c", later).is_some());
+    }
+
+    #[test]
+    fn test_zero_ttl_caches_nothing() {
+        let cache = PageCache::new(4, Duration::ZERO);
+        let now = Instant::now();
+
+        assert!(!cache.is_enabled());
+        cache.insert_at("/".to_string(), page("home", now), now);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_session_cookie_is_found_among_others() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; id=abc"),
+        );
+        assert!(has_cookie(&headers, "id"));
+        assert!(!has_cookie(&headers, "session"));
+        assert!(!has_cookie(&HeaderMap::new(), "id"));
+    }
+
+    #[test]
+    fn test_key_varies_by_host_language_and_path() {
+        let mut acme = HeaderMap::new();
+        acme.insert(header::HOST, HeaderValue::from_static("Acme.example.com"));
+        let key = cache_key(&acme, "en", "/");
+
+        assert_eq!(key, cache_key(&acme, "en", "/"));
+        assert_ne!(key, cache_key(&acme, "de", "/"));
+        assert_ne!(key, cache_key(&acme, "en", "/landing"));
+        assert_ne!(key, cache_key(&HeaderMap::new(), "en", "/"));
+    }
+}
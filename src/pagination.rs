@@ -0,0 +1,444 @@
+//! # Pagination
+//!
+//! The [`Pagination`] extractor reads `?page=&per_page=` or
+//! `?limit=&offset=` from the query string, applying defaults and caps.
+//! List queries take a `Pagination` and return a [`Paginated`] result that
+//! carries the total row count, which handlers turn into envelope metadata.
+//!
+//! Offsets get slower the deeper the page, so long lists ordered by
+//! `(created_at, id)` also take `?cursor=` through the [`Keyset`]
+//! extractor. An empty cursor asks for the first page; each
+//! [`CursorPage`] names where the next one starts, and its query seeks
+//! straight there on the index. The page size still comes from `per_page`
+//! or `limit`.
+
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::request::Parts,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize, Serializer};
+use utoipa::IntoParams;
+
+use crate::error::AppError;
+use crate::models::{CursorMeta, PaginationMeta};
+use crate::preferences;
+
+/// Page size used when neither the request nor the user's preferences
+/// specify one
+pub const DEFAULT_PER_PAGE: u32 = 20;
+
+/// Largest page size a client may request
+pub const MAX_PER_PAGE: u32 = 100;
+
+/// Raw pagination query parameters
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct PaginationQuery {
+    /// Page number, starting at 1 (use with `per_page`)
+    page: Option<u32>,
+    /// Results per page, at most 100 (use with `page`)
+    per_page: Option<u32>,
+    /// Maximum number of results (use with `offset`)
+    limit: Option<u32>,
+    /// Number of results to skip (use with `limit`)
+    offset: Option<u32>,
+}
+
+/// Validated window into a list query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pagination {
+    /// Maximum number of rows to return (1..=MAX_PER_PAGE)
+    pub limit: u32,
+    /// Number of rows to skip
+    pub offset: u32,
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_PER_PAGE,
+            offset: 0,
+        }
+    }
+}
+
+impl Pagination {
+    /// Window for a 1-based page number; `per_page` is capped at `MAX_PER_PAGE`
+    pub fn page(page: u32, per_page: u32) -> Self {
+        let limit = per_page.clamp(1, MAX_PER_PAGE);
+        Self {
+            limit,
+            offset: page.max(1).saturating_sub(1).saturating_mul(limit),
+        }
+    }
+
+    /// Window from a raw limit and offset; `limit` is capped at `MAX_PER_PAGE`
+    pub fn limit_offset(limit: u32, offset: u32) -> Self {
+        Self {
+            limit: limit.clamp(1, MAX_PER_PAGE),
+            offset,
+        }
+    }
+
+    /// 1-based page number containing the first row of this window
+    pub fn page_number(&self) -> u32 {
+        self.offset / self.limit + 1
+    }
+
+    /// `LIMIT` value for SQL queries
+    pub fn sql_limit(&self) -> i64 {
+        i64::from(self.limit)
+    }
+
+    /// `OFFSET` value for SQL queries
+    pub fn sql_offset(&self) -> i64 {
+        i64::from(self.offset)
+    }
+
+    fn from_query(query: PaginationQuery) -> Result<Self, String> {
+        let page_style = query.page.is_some() || query.per_page.is_some();
+        let offset_style = query.limit.is_some() || query.offset.is_some();
+
+        if page_style && offset_style {
+            return Err("Use either page/per_page or limit/offset, not both".to_string());
+        }
+        if query.page == Some(0) {
+            return Err("page starts at 1".to_string());
+        }
+        if query.per_page == Some(0) || query.limit == Some(0) {
+            return Err("Page size must be at least 1".to_string());
+        }
+
+        // The signed-in user's page size, or DEFAULT_PER_PAGE
+        let per_page = preferences::items_per_page();
+        if offset_style {
+            Ok(Self::limit_offset(
+                query.limit.unwrap_or(per_page),
+                query.offset.unwrap_or(0),
+            ))
+        } else {
+            Ok(Self::page(
+                query.page.unwrap_or(1),
+                query.per_page.unwrap_or(per_page),
+            ))
+        }
+    }
+}
+
+impl<S> FromRequestParts<S> for Pagination
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<PaginationQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|err| AppError::Validation(err.body_text()))?;
+
+        Pagination::from_query(query).map_err(AppError::Validation)
+    }
+}
+
+/// One page of results plus the total number of matching rows
+#[derive(Debug, Serialize)]
+pub struct Paginated<T> {
+    pub items: Vec<T>,
+    pub page: u32,
+    pub per_page: u32,
+    pub total: i64,
+    pub total_pages: u32,
+}
+
+impl<T> Paginated<T> {
+    /// Wrap a page of rows fetched with `pagination` out of `total` rows
+    pub fn new(items: Vec<T>, total: i64, pagination: &Pagination) -> Self {
+        let total_pages = u32::try_from(total.max(0))
+            .unwrap_or(u32::MAX)
+            .div_ceil(pagination.limit);
+
+        Self {
+            items,
+            page: pagination.page_number(),
+            per_page: pagination.limit,
+            total,
+            total_pages,
+        }
+    }
+
+    /// Pagination details for the response envelope
+    pub fn meta(&self) -> PaginationMeta {
+        PaginationMeta {
+            page: self.page,
+            per_page: self.per_page,
+            total: self.total,
+            total_pages: self.total_pages,
+        }
+    }
+}
+
+/// Where a keyset page starts: after the row with this `created_at` and
+/// ID. Clients only ever see it [encoded](Cursor::encode).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    pub created_at: DateTime<Utc>,
+    pub id: i64,
+}
+
+impl Cursor {
+    pub fn new(created_at: DateTime<Utc>, id: impl Into<i64>) -> Self {
+        Self {
+            created_at,
+            id: id.into(),
+        }
+    }
+
+    /// Opaque form for `next_cursor`; timestamps keep Postgres' microseconds
+    pub fn encode(&self) -> String {
+        format!("{}:{}", self.created_at.timestamp_micros(), self.id)
+            .bytes()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    /// Read a cursor made by [`encode`](Self::encode)
+    pub fn decode(text: &str) -> Option<Self> {
+        let bytes = (0..text.len())
+            .step_by(2)
+            .map(|i| {
+                text.get(i..i + 2)
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            })
+            .collect::<Option<Vec<u8>>>()?;
+        let raw = String::from_utf8(bytes).ok()?;
+        let (micros, id) = raw.split_once(':')?;
+        Some(Self {
+            created_at: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: id.parse().ok()?,
+        })
+    }
+}
+
+impl Serialize for Cursor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.encode())
+    }
+}
+
+/// Raw keyset query parameter
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct CursorQuery {
+    /// `next_cursor` of the previous page, or empty for the first page;
+    /// replaces `page` and `offset`
+    cursor: Option<String>,
+}
+
+/// `?cursor=` of a list that supports keyset pagination
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Keyset {
+    /// No cursor: the list is paged by offset
+    #[default]
+    Off,
+    /// An empty cursor: the first page
+    First,
+    /// The page after the row the cursor names
+    After(Cursor),
+}
+
+impl Keyset {
+    /// Whether the request asked for keyset pagination
+    pub fn is_on(&self) -> bool {
+        *self != Keyset::Off
+    }
+
+    /// Where the page starts; `None` for the first page
+    pub fn after(&self) -> Option<Cursor> {
+        match self {
+            Keyset::After(cursor) => Some(*cursor),
+            Keyset::Off | Keyset::First => None,
+        }
+    }
+
+    fn from_query(query: CursorQuery, pagination: &PaginationQuery) -> Result<Self, String> {
+        let Some(cursor) = query.cursor else {
+            return Ok(Keyset::Off);
+        };
+        if pagination.page.is_some() || pagination.offset.is_some() {
+            return Err("cursor replaces page and offset".to_string());
+        }
+        if cursor.is_empty() {
+            return Ok(Keyset::First);
+        }
+        Cursor::decode(&cursor)
+            .map(Keyset::After)
+            .ok_or_else(|| "cursor is not valid".to_string())
+    }
+}
+
+impl<S> FromRequestParts<S> for Keyset
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(query) = Query::<CursorQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|err| AppError::Validation(err.body_text()))?;
+        let Query(pagination) = Query::<PaginationQuery>::from_request_parts(parts, state)
+            .await
+            .map_err(|err| AppError::Validation(err.body_text()))?;
+
+        Keyset::from_query(query, &pagination).map_err(AppError::Validation)
+    }
+}
+
+/// One page of a keyset-paginated list
+#[derive(Debug, Serialize)]
+pub struct CursorPage<T> {
+    pub items: Vec<T>,
+    pub per_page: u32,
+    /// Where the next page starts; `None` on the last page
+    pub next_cursor: Option<Cursor>,
+}
+
+impl<T> CursorPage<T> {
+    /// Wrap rows fetched with a limit one over `pagination`'s; the extra
+    /// row only shows that another page follows
+    pub fn new(mut items: Vec<T>, pagination: &Pagination, cursor: impl Fn(&T) -> Cursor) -> Self {
+        let per_page = pagination.limit;
+        let next_cursor = if items.len() > per_page as usize {
+            items.truncate(per_page as usize);
+            items.last().map(cursor)
+        } else {
+            None
+        };
+
+        Self {
+            items,
+            per_page,
+            next_cursor,
+        }
+    }
+
+    /// Keyset pagination details for the response envelope
+    pub fn meta(&self) -> CursorMeta {
+        CursorMeta {
+            per_page: self.per_page,
+            next_cursor: self.next_cursor.map(|cursor| cursor.encode()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(
+        page: Option<u32>,
+        per_page: Option<u32>,
+        limit: Option<u32>,
+        offset: Option<u32>,
+    ) -> Result<Pagination, String> {
+        Pagination::from_query(PaginationQuery {
+            page,
+            per_page,
+            limit,
+            offset,
+        })
+    }
+
+    #[test]
+    fn test_defaults() {
+        assert_eq!(parse(None, None, None, None), Ok(Pagination::default()));
+    }
+
+    #[test]
+    fn test_page_and_limit_styles() {
+        let pagination = parse(Some(3), Some(10), None, None).unwrap();
+        assert_eq!(pagination.offset, 20);
+        assert_eq!(pagination.page_number(), 3);
+
+        let pagination = parse(None, None, Some(5), Some(15)).unwrap();
+        assert_eq!(pagination.limit, 5);
+        assert_eq!(pagination.page_number(), 4);
+    }
+
+    #[test]
+    fn test_page_size_is_capped() {
+        let pagination = parse(None, Some(10_000), None, None).unwrap();
+        assert_eq!(pagination.limit, MAX_PER_PAGE);
+    }
+
+    #[test]
+    fn test_invalid_combinations_are_rejected() {
+        assert!(parse(Some(0), None, None, None).is_err());
+        assert!(parse(None, None, Some(0), None).is_err());
+        assert!(parse(Some(1), None, None, Some(10)).is_err());
+    }
+
+    #[test]
+    fn test_total_pages() {
+        let page = Paginated::new(vec![1, 2], 41, &Pagination::page(1, 20));
+        assert_eq!(page.total_pages, 3);
+
+        let empty: Paginated<i32> = Paginated::new(Vec::new(), 0, &Pagination::default());
+        assert_eq!(empty.total_pages, 0);
+    }
+
+    #[test]
+    fn test_cursor_round_trip() {
+        let cursor = Cursor::new(
+            DateTime::from_timestamp_micros(1_700_000_000_123_456).unwrap(),
+            42,
+        );
+        let encoded = cursor.encode();
+        assert!(encoded.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(Cursor::decode(&encoded), Some(cursor));
+
+        assert_eq!(Cursor::decode("zz"), None);
+        assert_eq!(Cursor::decode("313"), None);
+        assert_eq!(Cursor::decode("é"), None);
+    }
+
+    #[test]
+    fn test_keyset_query() {
+        let keyset = |cursor: Option<&str>, page: Option<u32>| {
+            Keyset::from_query(
+                CursorQuery {
+                    cursor: cursor.map(str::to_string),
+                },
+                &PaginationQuery {
+                    page,
+                    ..Default::default()
+                },
+            )
+        };
+        assert_eq!(keyset(None, Some(2)), Ok(Keyset::Off));
+        assert_eq!(keyset(Some(""), None), Ok(Keyset::First));
+        assert!(keyset(Some(""), Some(2)).is_err());
+        assert!(keyset(Some("not a cursor"), None).is_err());
+
+        let cursor = Cursor::new(Utc::now(), 7_i64);
+        assert_eq!(
+            keyset(Some(&cursor.encode()), None).unwrap().after(),
+            Cursor::decode(&cursor.encode())
+        );
+    }
+
+    #[test]
+    fn test_cursor_page_takes_one_extra_row() {
+        let at = |id: i64| Cursor::new(DateTime::from_timestamp(id, 0).unwrap(), id);
+        let pagination = Pagination::page(1, 2);
+
+        let page = CursorPage::new(vec![3_i64, 2, 1], &pagination, |id| at(*id));
+        assert_eq!(page.items, [3, 2]);
+        assert_eq!(page.next_cursor, Some(at(2)));
+
+        let last = CursorPage::new(vec![1_i64], &pagination, |id| at(*id));
+        assert_eq!(last.next_cursor, None);
+        assert_eq!(last.meta().next_cursor, None);
+    }
+}
@@ -0,0 +1,341 @@
+//! # Plugins
+//!
+//! A [`Plugin`] packages a feature, usually in its own crate, so it can be
+//! added to an axum-base app without editing core modules. A plugin can
+//! contribute:
+//!
+//! - routes, merged into the router by [`create_router`](crate::routes::create_router)
+//! - migrations, applied after the core ones at startup (see [`migrations`](crate::migrations))
+//! - template directories, loaded next to `templates/`
+//! - job handlers, registered on the [`Worker`]
+//! - [`ScheduledTask`]s, run by the [`scheduler`](crate::scheduler) until shutdown
+//! - [`HealthCheck`]s for the components it depends on, reported at `/health`
+//! - [`Event`] listeners, told about logins, uploads, and so on
+//! - start and shutdown hooks
+//!
+//! Every hook has a default that does nothing, so a plugin implements only
+//! what it needs. Plugins are collected in a [`PluginRegistry`] and handed
+//! to [`start_server_with`](crate::server::start_server_with):
+//!
+//! ```ignore
+//! let plugins = PluginRegistry::new().with(Billing::new());
+//! axum_base::server::start_server_with(plugins).await;
+//! ```
+//!
+//! The registry lives in [`AppState::plugins`], so handlers can
+//! [`emit`](PluginRegistry::emit) events and tests can build an app with
+//! plugins through [`AppState::with_plugins`].
+
+use async_trait::async_trait;
+use axum::Router;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+pub use crate::health::HealthCheck;
+use crate::jobs::Worker;
+use crate::migrations::MigrationSet;
+use crate::models::Upload;
+pub use crate::scheduler::ScheduledTask;
+use crate::state::AppState;
+
+/// Something that happened in the core app, passed to [`Plugin::on_event`]
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Event {
+    /// A user signed in through the login form
+    UserLoggedIn { user_id: i32 },
+    /// A user signed out
+    UserLoggedOut { user_id: i32 },
+    /// A file was uploaded and stored
+    FileUploaded(Upload),
+    /// An upload was deleted by its owner or an admin
+    FileDeleted(Upload),
+}
+
+/// A feature that plugs into the app; see the [module docs](self)
+#[async_trait]
+pub trait Plugin: Send + Sync + 'static {
+    /// Short name used in logs
+    fn name(&self) -> &'static str;
+
+    /// Routes to merge into the app. They get the same sessions, flash
+    /// messages, and fallback as core routes; paths must not clash with them.
+    fn routes(&self) -> Router<AppState> {
+        Router::new()
+    }
+
+    /// Migrations to apply after the core ones, usually
+    /// `MigrationSet::new(self.name(), sqlx::migrate!("./migrations"))` in
+    /// the plugin crate
+    fn migrations(&self) -> Option<MigrationSet> {
+        None
+    }
+
+    /// Directories of Tera templates. Templates are named by their path
+    /// inside the directory; a core template of the same name wins.
+    fn template_dirs(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Register handlers for the plugin's job kinds
+    fn jobs(&self, worker: Worker) -> Worker {
+        worker
+    }
+
+    /// Tasks to run periodically while the server is up
+    fn scheduled_tasks(&self) -> Vec<ScheduledTask> {
+        Vec::new()
+    }
+
+    /// Checks of the components the plugin depends on
+    fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        Vec::new()
+    }
+
+    /// Called once the app state is built, before the server accepts
+    /// requests. An error stops the server from starting.
+    async fn on_start(&self, _state: &AppState) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Called after the server has stopped serving requests
+    async fn on_shutdown(&self, _state: &AppState) {}
+
+    /// Called for each [`Event`], in the background
+    async fn on_event(&self, _state: &AppState, _event: &Event) {}
+}
+
+/// The plugins an app was started with, in registration order, and the
+/// app's own migrations, scheduled tasks, and health checks
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Arc<dyn Plugin>>,
+    migrations: Vec<MigrationSet>,
+    tasks: Vec<ScheduledTask>,
+    checks: Vec<Arc<dyn HealthCheck>>,
+}
+
+#[allow(dead_code)]
+impl PluginRegistry {
+    /// A registry without plugins
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `plugin`
+    pub fn with(mut self, plugin: impl Plugin) -> Self {
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    /// Also apply `migrations`, for an app that keeps its own schema next
+    /// to the core one
+    pub fn with_migrations(mut self, migrations: MigrationSet) -> Self {
+        self.migrations.push(migrations);
+        self
+    }
+
+    /// Also run `task`, for an app's own periodic work
+    pub fn with_task(mut self, task: ScheduledTask) -> Self {
+        self.tasks.push(task);
+        self
+    }
+
+    /// Also report `check` at `/health`, for a component the app depends on
+    pub fn with_health_check(mut self, check: impl HealthCheck) -> Self {
+        self.checks.push(Arc::new(check));
+        self
+    }
+
+    /// Whether no plugins are registered
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+
+    /// Names of the registered plugins
+    pub fn names(&self) -> Vec<&'static str> {
+        self.plugins.iter().map(|plugin| plugin.name()).collect()
+    }
+
+    /// Every plugin's routes in one router
+    pub fn routes(&self) -> Router<AppState> {
+        self.plugins.iter().fold(Router::new(), |router, plugin| {
+            router.merge(plugin.routes())
+        })
+    }
+
+    /// Every plugin's migrations, then the app's
+    pub fn migrations(&self) -> Vec<MigrationSet> {
+        self.plugins
+            .iter()
+            .filter_map(|plugin| plugin.migrations())
+            .chain(self.migrations.iter().cloned())
+            .collect()
+    }
+
+    /// Every plugin's template directories
+    pub fn template_dirs(&self) -> Vec<PathBuf> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.template_dirs())
+            .collect()
+    }
+
+    /// Register every plugin's job handlers on `worker`
+    pub fn jobs(&self, worker: Worker) -> Worker {
+        self.plugins
+            .iter()
+            .fold(worker, |worker, plugin| plugin.jobs(worker))
+    }
+
+    /// Every plugin's scheduled tasks, then the app's
+    pub fn scheduled_tasks(&self) -> Vec<ScheduledTask> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.scheduled_tasks())
+            .chain(self.tasks.iter().cloned())
+            .collect()
+    }
+
+    /// Every plugin's health checks, then the app's
+    pub fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.health_checks())
+            .chain(self.checks.iter().cloned())
+            .collect()
+    }
+
+    /// Run every plugin's start hook, stopping at the first failure
+    pub async fn start(&self, state: &AppState) -> Result<(), String> {
+        for plugin in &self.plugins {
+            plugin
+                .on_start(state)
+                .await
+                .map_err(|e| format!("plugin {} failed to start: {}", plugin.name(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Run every plugin's shutdown hook, in reverse registration order
+    pub async fn shutdown(&self, state: &AppState) {
+        for plugin in self.plugins.iter().rev() {
+            plugin.on_shutdown(state).await;
+        }
+    }
+
+    /// Tell every plugin about `event` without waiting for them
+    pub fn emit(&self, state: &AppState, event: Event) {
+        if self.plugins.is_empty() {
+            return;
+        }
+        let plugins = self.plugins.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            for plugin in plugins {
+                plugin.on_event(&state, &event).await;
+            }
+        });
+    }
+}
+
+impl std::fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.names()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    struct Named(&'static str);
+
+    #[async_trait]
+    impl HealthCheck for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        async fn check(&self, _state: &AppState) -> Result<(), String> {
+            Ok(())
+        }
+    }
+
+    impl Plugin for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn template_dirs(&self) -> Vec<PathBuf> {
+            vec![PathBuf::from(format!("plugins/{}/templates", self.0))]
+        }
+
+        fn scheduled_tasks(&self) -> Vec<ScheduledTask> {
+            vec![ScheduledTask::new(
+                self.0,
+                Duration::from_secs(60),
+                |_| async { Ok(()) },
+            )]
+        }
+
+        fn health_checks(&self) -> Vec<Arc<dyn HealthCheck>> {
+            vec![Arc::new(Named(self.0))]
+        }
+    }
+
+    #[test]
+    fn test_registry_collects_in_order() {
+        let registry = PluginRegistry::new()
+            .with(Named("billing"))
+            .with(Named("forum"));
+
+        assert!(!registry.is_empty());
+        assert_eq!(registry.names(), ["billing", "forum"]);
+        assert_eq!(
+            registry.template_dirs(),
+            [
+                PathBuf::from("plugins/billing/templates"),
+                PathBuf::from("plugins/forum/templates")
+            ]
+        );
+        let tasks: Vec<_> = registry
+            .scheduled_tasks()
+            .iter()
+            .map(|task| task.name)
+            .collect();
+        assert_eq!(tasks, ["billing", "forum"]);
+        assert!(registry.migrations().is_empty());
+        assert_eq!(format!("{:?}", registry), r#"["billing", "forum"]"#);
+
+        let registry = registry.with_task(ScheduledTask::new(
+            "digest",
+            Duration::from_secs(3600),
+            |_| async { Ok(()) },
+        ));
+        let tasks: Vec<_> = registry
+            .scheduled_tasks()
+            .iter()
+            .map(|task| task.name)
+            .collect();
+        assert_eq!(tasks, ["billing", "forum", "digest"]);
+
+        let registry = registry.with_health_check(Named("redis"));
+        let checks: Vec<_> = registry
+            .health_checks()
+            .iter()
+            .map(|check| check.name())
+            .collect();
+        assert_eq!(checks, ["billing", "forum", "redis"]);
+    }
+
+    #[test]
+    fn test_empty_registry() {
+        let registry = PluginRegistry::default();
+        assert!(registry.is_empty());
+        assert!(registry.names().is_empty());
+        assert!(registry.scheduled_tasks().is_empty());
+        assert!(registry.health_checks().is_empty());
+    }
+}
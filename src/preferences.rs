@@ -0,0 +1,461 @@
+//! # User Preferences
+//!
+//! Settings each user picks for themselves: a color scheme (`theme`), the
+//! language of their emails (`locale`), the time zone pages show times in
+//! (`timezone`), and how many rows lists show per page (`items_per_page`).
+//! Theme and page size are typed values in `user_preferences`, one row per
+//! key a user has set; language and time zone stay in their `users` columns,
+//! where emails and the profile page read them.
+//! [`PreferencesService`](crate::services::PreferencesService) reads and
+//! writes both.
+//!
+//! The resolved preferences are copied into the session at login and
+//! whenever they change, so the [`track`] middleware can scope them to each
+//! request without a database query. `render_template` puts them in every
+//! page context as `preferences`, and the
+//! [`Pagination`](crate::pagination::Pagination) extractor uses
+//! `items_per_page` when a request gives no page size.
+//!
+//! Users read and change them at `/api/preferences`;
+//! `DELETE /api/preferences/{key}` brings one back to its default.
+
+use axum::{
+    Router,
+    extract::{Path, Request, State},
+    middleware::Next,
+    response::Response,
+    routing::{delete, get},
+};
+use serde_json::{Value, json};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+use crate::api::RequestId;
+use crate::audit::{self, AuditEvent};
+use crate::auth::CurrentUser;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated, Payload};
+use crate::i18n;
+use crate::models::{Envelope, Preferences, UpdatePreferencesRequest};
+use crate::pagination::{DEFAULT_PER_PAGE, MAX_PER_PAGE};
+use crate::services::PreferencesService;
+use crate::state::AppState;
+use crate::timezone;
+
+/// Session key holding the signed-in user's preferences
+pub(crate) const PREFERENCES_SESSION_KEY: &str = "preferences";
+
+pub const THEME: &str = "theme";
+pub const LOCALE: &str = "locale";
+pub const TIMEZONE: &str = "timezone";
+pub const ITEMS_PER_PAGE: &str = "items_per_page";
+
+/// Every preference key
+pub const KEYS: &[&str] = &[THEME, LOCALE, TIMEZONE, ITEMS_PER_PAGE];
+
+/// Color schemes; `system` follows the browser
+pub const THEMES: &[&str] = &["system", "light", "dark"];
+
+/// Fewest rows per page a user may choose
+pub const MIN_ITEMS_PER_PAGE: u32 = 5;
+
+tokio::task_local! {
+    static CURRENT_PREFERENCES: Arc<Preferences>;
+}
+
+impl Default for Preferences {
+    fn default() -> Self {
+        Self {
+            theme: THEMES[0].to_string(),
+            locale: i18n::DEFAULT_LOCALE.to_string(),
+            timezone: None,
+            items_per_page: DEFAULT_PER_PAGE,
+        }
+    }
+}
+
+/// A checked value for one preference
+#[derive(Debug, Clone, PartialEq)]
+pub enum Preference {
+    Theme(String),
+    Locale(String),
+    /// `None` for UTC
+    Timezone(Option<String>),
+    ItemsPerPage(u32),
+}
+
+impl Preference {
+    /// Key the value is stored under
+    pub fn key(&self) -> &'static str {
+        match self {
+            Preference::Theme(_) => THEME,
+            Preference::Locale(_) => LOCALE,
+            Preference::Timezone(_) => TIMEZONE,
+            Preference::ItemsPerPage(_) => ITEMS_PER_PAGE,
+        }
+    }
+
+    /// Value as kept in `user_preferences`
+    pub fn to_json(&self) -> Value {
+        match self {
+            Preference::Theme(theme) => json!(theme),
+            Preference::Locale(locale) => json!(locale),
+            Preference::Timezone(zone) => json!(zone),
+            Preference::ItemsPerPage(count) => json!(count),
+        }
+    }
+}
+
+/// Check the fields of an update, in the order of [`KEYS`]
+pub fn validate(request: &UpdatePreferencesRequest) -> AppResult<Vec<Preference>> {
+    let mut changes = Vec::new();
+    if let Some(theme) = &request.theme {
+        changes.push(parse_theme(theme)?);
+    }
+    if let Some(locale) = &request.locale {
+        let locale = i18n::normalize(locale)
+            .ok_or_else(|| AppError::Validation(format!("Unknown language '{}'", locale)))?;
+        changes.push(Preference::Locale(locale));
+    }
+    if let Some(name) = &request.timezone {
+        let name = name.trim();
+        let zone = if name.is_empty() {
+            None
+        } else {
+            let zone = timezone::parse(name)
+                .ok_or_else(|| AppError::Validation(format!("Unknown time zone '{}'", name)))?;
+            Some(zone.name().to_string())
+        };
+        changes.push(Preference::Timezone(zone));
+    }
+    if let Some(count) = request.items_per_page {
+        if !(MIN_ITEMS_PER_PAGE..=MAX_PER_PAGE).contains(&count) {
+            return Err(AppError::Validation(format!(
+                "items_per_page is {} to {}",
+                MIN_ITEMS_PER_PAGE, MAX_PER_PAGE
+            )));
+        }
+        changes.push(Preference::ItemsPerPage(count));
+    }
+    Ok(changes)
+}
+
+fn parse_theme(theme: &str) -> AppResult<Preference> {
+    THEMES
+        .iter()
+        .find(|known| **known == theme)
+        .map(|known| Preference::Theme(known.to_string()))
+        .ok_or_else(|| {
+            AppError::Validation(format!(
+                "Unknown theme '{}': use {}",
+                theme,
+                THEMES.join(", ")
+            ))
+        })
+}
+
+/// Preferences from a user's stored values; values that no longer check out
+/// get their default
+pub fn resolve(
+    stored: &HashMap<String, Value>,
+    locale: Option<&str>,
+    timezone: Option<&str>,
+) -> Preferences {
+    let mut preferences = Preferences::default();
+    if let Some(Ok(Preference::Theme(theme))) =
+        stored.get(THEME).and_then(Value::as_str).map(parse_theme)
+    {
+        preferences.theme = theme;
+    }
+    if let Some(count) = stored
+        .get(ITEMS_PER_PAGE)
+        .and_then(Value::as_u64)
+        .and_then(|count| u32::try_from(count).ok())
+        .filter(|count| (MIN_ITEMS_PER_PAGE..=MAX_PER_PAGE).contains(count))
+    {
+        preferences.items_per_page = count;
+    }
+    preferences.locale = i18n::resolve(locale).to_string();
+    preferences.timezone = timezone
+        .and_then(timezone::parse)
+        .map(|zone| zone.name().to_string());
+    preferences
+}
+
+/// Preferences of the current request's user; the defaults outside [`track`]
+/// or when nobody is signed in
+pub fn current() -> Arc<Preferences> {
+    CURRENT_PREFERENCES
+        .try_with(Arc::clone)
+        .unwrap_or_else(|_| Arc::new(Preferences::default()))
+}
+
+/// Page size for lists the request gave none for
+pub fn items_per_page() -> u32 {
+    CURRENT_PREFERENCES
+        .try_with(|preferences| preferences.items_per_page)
+        .unwrap_or(DEFAULT_PER_PAGE)
+}
+
+/// Store a user's preferences, and their time zone, in their session
+pub async fn remember(
+    session: &Session,
+    preferences: &Preferences,
+) -> Result<(), tower_sessions::session::Error> {
+    session.insert(PREFERENCES_SESSION_KEY, preferences).await?;
+    timezone::remember(session, preferences.timezone.as_deref()).await
+}
+
+/// Load a user's preferences and store them in their session
+pub async fn refresh(pool: &PgPool, session: &Session, user_id: i32) -> AppResult<Preferences> {
+    let preferences = PreferencesService::get(pool, user_id).await?;
+    remember(session, &preferences)
+        .await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    Ok(preferences)
+}
+
+/// Middleware scoping the session's preferences to the request. Must run
+/// inside the session layer.
+pub async fn track(request: Request, next: Next) -> Response {
+    let Some(session) = request.extensions().get::<Session>().cloned() else {
+        return next.run(request).await;
+    };
+
+    match session
+        .get::<Preferences>(PREFERENCES_SESSION_KEY)
+        .await
+        .ok()
+        .flatten()
+    {
+        Some(preferences) => {
+            CURRENT_PREFERENCES
+                .scope(Arc::new(preferences), next.run(request))
+                .await
+        }
+        None => next.run(request).await,
+    }
+}
+
+/// Routes for `/api/preferences`
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/preferences",
+            get(get_preferences).put(update_preferences),
+        )
+        .route("/api/preferences/{key}", delete(reset_preference))
+}
+
+/// The signed-in user's preferences
+#[utoipa::path(
+    get,
+    path = "/api/preferences",
+    tag = "account",
+    responses(
+        (status = 200, description = "The user's preferences", body = Envelope<Preferences>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn get_preferences(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<Preferences>>> {
+    let preferences = PreferencesService::get(&pool, user.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(preferences).with_request_id(request_id),
+    ))
+}
+
+/// Change some of the signed-in user's preferences; all are checked before
+/// any is saved
+#[utoipa::path(
+    put,
+    path = "/api/preferences",
+    tag = "account",
+    request_body = UpdatePreferencesRequest,
+    responses(
+        (status = 200, description = "Preferences saved", body = Envelope<Preferences>),
+        (status = 400, description = "Invalid value", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn update_preferences(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    session: Session,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Payload(request): Payload<UpdatePreferencesRequest>,
+) -> AppResult<Negotiated<Envelope<Preferences>>> {
+    let changes = validate(&request)?;
+    for change in &changes {
+        PreferencesService::set(&pool, user.id, change).await?;
+    }
+    let preferences = refresh(&pool, &session, user.id).await?;
+
+    if !changes.is_empty() {
+        let keys: Vec<&str> = changes.iter().map(Preference::key).collect();
+        audit::record(
+            &pool,
+            AuditEvent::new(audit::PREFERENCES_UPDATED)
+                .by(user.id)
+                .with_details(json!({ "keys": keys })),
+        )
+        .await;
+    }
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(preferences)
+            .with_message("Preferences saved")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Bring one of the signed-in user's preferences back to its default
+#[utoipa::path(
+    delete,
+    path = "/api/preferences/{key}",
+    tag = "account",
+    params(("key" = String, Path, description = "Preference key, e.g. `theme`")),
+    responses(
+        (status = 200, description = "Preference reset", body = Envelope<Preferences>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such preference", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn reset_preference(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    session: Session,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(key): Path<String>,
+) -> AppResult<Negotiated<Envelope<Preferences>>> {
+    let Some(key) = KEYS.iter().copied().find(|known| *known == key) else {
+        return Err(AppError::NotFound(format!("No preference '{}'", key)));
+    };
+    PreferencesService::reset(&pool, user.id, key).await?;
+    let preferences = refresh(&pool, &session, user.id).await?;
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::PREFERENCES_UPDATED)
+            .by(user.id)
+            .with_details(json!({ "keys": [key], "reset": true })),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(preferences)
+            .with_message("Preference reset")
+            .with_request_id(request_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let request = UpdatePreferencesRequest {
+            theme: Some("dark".to_string()),
+            locale: Some("pt-br".to_string()),
+            timezone: Some(" Europe/Berlin ".to_string()),
+            items_per_page: Some(50),
+        };
+        assert_eq!(
+            validate(&request).unwrap(),
+            vec![
+                Preference::Theme("dark".to_string()),
+                Preference::Locale("pt-BR".to_string()),
+                Preference::Timezone(Some("Europe/Berlin".to_string())),
+                Preference::ItemsPerPage(50),
+            ]
+        );
+
+        let utc = UpdatePreferencesRequest {
+            timezone: Some(String::new()),
+            ..Default::default()
+        };
+        assert_eq!(validate(&utc).unwrap(), vec![Preference::Timezone(None)]);
+        assert!(
+            validate(&UpdatePreferencesRequest::default())
+                .unwrap()
+                .is_empty()
+        );
+
+        for bad in [
+            UpdatePreferencesRequest {
+                theme: Some("neon".to_string()),
+                ..Default::default()
+            },
+            UpdatePreferencesRequest {
+                locale: Some("xx-invalid-".to_string()),
+                ..Default::default()
+            },
+            UpdatePreferencesRequest {
+                timezone: Some("Mars/Olympus_Mons".to_string()),
+                ..Default::default()
+            },
+            UpdatePreferencesRequest {
+                items_per_page: Some(MAX_PER_PAGE + 1),
+                ..Default::default()
+            },
+        ] {
+            assert!(validate(&bad).is_err(), "{:?} should be refused", bad);
+        }
+    }
+
+    #[test]
+    fn test_resolve_ignores_bad_stored_values() {
+        let defaults = resolve(&HashMap::new(), None, None);
+        assert_eq!(defaults, Preferences::default());
+
+        let stored = HashMap::from([
+            (THEME.to_string(), json!("light")),
+            (ITEMS_PER_PAGE.to_string(), json!(25)),
+        ]);
+        let preferences = resolve(&stored, Some("de"), Some("Europe/Berlin"));
+        assert_eq!(preferences.theme, "light");
+        assert_eq!(preferences.items_per_page, 25);
+        assert_eq!(preferences.locale, "de");
+        assert_eq!(preferences.timezone.as_deref(), Some("Europe/Berlin"));
+
+        let stored = HashMap::from([
+            (THEME.to_string(), json!(3)),
+            (ITEMS_PER_PAGE.to_string(), json!(100_000)),
+        ]);
+        assert_eq!(
+            resolve(&stored, None, Some("Nowhere")),
+            Preferences::default()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_current_outside_and_inside_a_request() {
+        assert_eq!(*current(), Preferences::default());
+        assert_eq!(items_per_page(), DEFAULT_PER_PAGE);
+
+        let preferences = Preferences {
+            items_per_page: 42,
+            ..Default::default()
+        };
+        CURRENT_PREFERENCES
+            .scope(Arc::new(preferences), async {
+                assert_eq!(current().items_per_page, 42);
+                assert_eq!(items_per_page(), 42);
+            })
+            .await;
+    }
+}
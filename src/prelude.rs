@@ -0,0 +1,39 @@
+//! # Prelude
+//!
+//! The types an application built on this crate usually needs, in one
+//! import:
+//!
+//! ```rust,ignore
+//! use axum_base::prelude::*;
+//! ```
+//!
+//! Everything here is covered by semver: it is only removed or changed in a
+//! breaking release. `tests/public_api.rs` pins the list and the signatures
+//! that matter, so an accidental change fails the build. Modules marked
+//! `#[doc(hidden)]` in `lib.rs` serve the bundled binaries and tests and may
+//! change at any time.
+
+pub use crate::auth::{
+    ADMIN_ROLE, AdminUser, AuthService, Authenticator, CurrentUser, InMemoryAuthenticator,
+    PasswordService,
+};
+pub use crate::config::{AppConfig, ConfigError, Environment};
+pub use crate::database::init_pool;
+pub use crate::error::{AppError, AppResult};
+pub use crate::flash::Flash;
+pub use crate::jobs::{JobOptions, Priority, Worker};
+pub use crate::mailer::{Email, Mailer};
+pub use crate::migrations::MigrationSet;
+pub use crate::models::{
+    AuthenticatedUser, Category, CreateUserRequest, ENVELOPE_VERSION, Envelope, ErrorDetail, Item,
+    ItemWithCategory, PaginationMeta, ResponseMeta, Upload, User, UserResponse,
+};
+pub use crate::pagination::{Paginated, Pagination};
+pub use crate::plugins::{Event, Plugin, PluginRegistry, ScheduledTask};
+pub use crate::repositories::{CategoryRepository, ItemRepository, UserRepository};
+pub use crate::routes::create_router;
+pub use crate::scope::{Provide, RequestScope, Scoped};
+pub use crate::server::start_server_with;
+pub use crate::services::UserService;
+pub use crate::state::AppState;
+pub use crate::uploads::{LocalStorage, StorageBackend};
@@ -0,0 +1,172 @@
+//! # Breached Passwords
+//!
+//! Checks new passwords against the Have I Been Pwned range API, built with
+//! `--features pwned` and turned on with `PASSWORD_BREACH_CHECK`. Only the
+//! first five hex digits of the password's SHA-1 leave the server
+//! (k-anonymity); the API answers with every hash suffix in that range.
+//!
+//! A lookup that fails or times out lets the password through, so an outage
+//! of the API never stops people from choosing one. Ranges are cached for a
+//! day, since the same few passwords are tried again and again.
+
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::output;
+
+/// How long a fetched range is reused
+const CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Ranges kept at most; the oldest is dropped to make room
+const CACHE_CAPACITY: usize = 1024;
+
+/// Hash suffixes of a range and how often each was seen in breaches
+type Range = Arc<HashMap<String, u64>>;
+
+/// Client for the range API with a cache of the ranges it fetched
+#[derive(Debug)]
+pub struct PwnedPasswords {
+    http: reqwest::Client,
+    api_url: String,
+    cache: Mutex<HashMap<String, (Instant, Range)>>,
+}
+
+impl PwnedPasswords {
+    /// Client for the range API at `api_url`, e.g.
+    /// `https://api.pwnedpasswords.com/range/`, giving up after `timeout`
+    pub fn new(api_url: &str, timeout: Duration) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(timeout)
+            .user_agent(concat!("axum-base/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("default HTTP client settings are valid");
+        Self {
+            http,
+            api_url: format!("{}/", api_url.trim_end_matches('/')),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How often `password` appears in known breaches
+    pub async fn times_seen(&self, password: &str) -> Result<u64, reqwest::Error> {
+        let hash: String = Sha1::digest(password.as_bytes())
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        let (prefix, suffix) = hash.split_at(5);
+
+        let range = match self.cached(prefix, Instant::now()) {
+            Some(range) => range,
+            None => {
+                let body = self
+                    .http
+                    .get(format!("{}{}", self.api_url, prefix))
+                    // Pads the answer so its size says nothing about the range
+                    .header("Add-Padding", "true")
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .text()
+                    .await?;
+                let range = Arc::new(parse_range(&body));
+                self.remember(prefix, range.clone(), Instant::now());
+                range
+            }
+        };
+        Ok(range.get(suffix).copied().unwrap_or(0))
+    }
+
+    /// Whether `password` appears in known breaches; false when the API
+    /// cannot be reached
+    pub async fn is_breached(&self, password: &str) -> bool {
+        match self.times_seen(password).await {
+            Ok(count) => count > 0,
+            Err(e) => {
+                output::warn(format!(
+                    "Breached password check failed, allowing the password: {}",
+                    e
+                ));
+                false
+            }
+        }
+    }
+
+    fn cached(&self, prefix: &str, now: Instant) -> Option<Range> {
+        let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache
+            .get(prefix)
+            .filter(|(fetched, _)| now.duration_since(*fetched) < CACHE_TTL)
+            .map(|(_, range)| range.clone())
+    }
+
+    fn remember(&self, prefix: &str, range: Range, now: Instant) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if cache.len() >= CACHE_CAPACITY {
+            cache.retain(|_, (fetched, _)| now.duration_since(*fetched) < CACHE_TTL);
+        }
+        if cache.len() >= CACHE_CAPACITY
+            && let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (fetched, _))| *fetched)
+                .map(|(prefix, _)| prefix.clone())
+        {
+            cache.remove(&oldest);
+        }
+        cache.insert(prefix.to_string(), (now, range));
+    }
+}
+
+/// `SUFFIX:COUNT` lines of a range answer; padding lines have a count of 0
+fn parse_range(body: &str) -> HashMap<String, u64> {
+    body.lines()
+        .filter_map(|line| line.trim().split_once(':'))
+        .filter_map(|(suffix, count)| Some((suffix.to_ascii_uppercase(), count.parse().ok()?)))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let range = parse_range(
+            "1E4C9B93F3F0682250B6CF8331B7EE68FD8:10434004\r\n\
+             00d4f6e8fa6eecad2a3aa3e8a9a7f4a5b1c:0\r\n\
+             not a line\r\n",
+        );
+        assert_eq!(range.len(), 1);
+        assert_eq!(range["1E4C9B93F3F0682250B6CF8331B7EE68FD8"], 10434004);
+    }
+
+    #[test]
+    fn test_cache_expires_and_stays_bounded() {
+        let pwned = PwnedPasswords::new("http://127.0.0.1:9", Duration::from_secs(1));
+        let start = Instant::now();
+        pwned.remember("5BAA6", Arc::new(HashMap::new()), start);
+        assert!(
+            pwned
+                .cached("5BAA6", start + Duration::from_secs(60))
+                .is_some()
+        );
+        assert!(pwned.cached("5BAA6", start + CACHE_TTL).is_none());
+
+        for n in 0..CACHE_CAPACITY + 10 {
+            let fetched = start + Duration::from_millis(n as u64);
+            pwned.remember(&format!("{:05X}", n), Arc::new(HashMap::new()), fetched);
+        }
+        assert_eq!(pwned.cache.lock().unwrap().len(), CACHE_CAPACITY);
+        assert!(pwned.cached("00000", start).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_api_fails_open() {
+        // Nothing listens on the discard port
+        let pwned = PwnedPasswords::new("http://127.0.0.1:9/range", Duration::from_secs(2));
+        assert!(pwned.times_seen("password").await.is_err());
+        assert!(!pwned.is_breached("password").await);
+    }
+}
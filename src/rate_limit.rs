@@ -0,0 +1,139 @@
+//! # Rate Limiting
+//!
+//! In-memory sliding-window rate limiter keyed by anything hashable (a user
+//! ID, a client IP). Limits are per process, which is enough for guarding
+//! expensive admin tools on a single instance, and for pacing outbound
+//! email.
+
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Allows at most `limit` hits per key within any `window`
+#[derive(Debug)]
+pub struct RateLimiter<K> {
+    limit: usize,
+    window: Duration,
+    hits: Mutex<HashMap<K, VecDeque<Instant>>>,
+}
+
+impl<K: Eq + Hash> RateLimiter<K> {
+    /// Create a limiter allowing `limit` hits per `window`
+    pub fn new(limit: usize, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            hits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Record a hit for `key`.
+    ///
+    /// Returns how long to wait before retrying when the key is over its limit;
+    /// rejected hits are not counted.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    /// How long `key` must wait before its next hit would be allowed, without
+    /// recording one. `None` when a hit would be allowed now.
+    pub fn retry_after(&self, key: &K) -> Option<Duration> {
+        self.retry_after_at(key, Instant::now())
+    }
+
+    fn retry_after_at(&self, key: &K, now: Instant) -> Option<Duration> {
+        let hits = self
+            .hits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let times = hits.get(key)?;
+
+        let expired = times
+            .iter()
+            .take_while(|time| now.duration_since(**time) >= self.window)
+            .count();
+        if times.len() - expired < self.limit {
+            return None;
+        }
+        let oldest = times.get(expired)?;
+        Some(self.window.saturating_sub(now.duration_since(*oldest)))
+    }
+
+    fn check_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let mut hits = self
+            .hits
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+        // Drop keys whose hits have all expired so the map does not grow forever
+        hits.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|time| now.duration_since(*time) >= self.window)
+            {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = hits.entry(key).or_default();
+        if times.len() >= self.limit {
+            let oldest = times.front().copied().unwrap_or(now);
+            return Err(self.window.saturating_sub(now.duration_since(oldest)));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_applies_per_key() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert!(limiter.check_at(1, now).is_ok());
+        assert!(limiter.check_at(1, now).is_ok());
+        assert!(limiter.check_at(1, now).is_err());
+        assert!(limiter.check_at(2, now).is_ok());
+    }
+
+    #[test]
+    fn test_window_slides() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert!(limiter.check_at("ip", start).is_ok());
+        let retry_after = limiter
+            .check_at("ip", start + Duration::from_secs(4))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(6));
+        assert!(
+            limiter
+                .check_at("ip", start + Duration::from_secs(10))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_retry_after_does_not_count() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(10));
+        let start = Instant::now();
+
+        assert_eq!(limiter.retry_after_at(&"ip", start), None);
+        assert_eq!(limiter.retry_after_at(&"ip", start), None);
+        assert!(limiter.check_at("ip", start).is_ok());
+        assert_eq!(
+            limiter.retry_after_at(&"ip", start + Duration::from_secs(3)),
+            Some(Duration::from_secs(7))
+        );
+        assert_eq!(
+            limiter.retry_after_at(&"ip", start + Duration::from_secs(10)),
+            None
+        );
+    }
+}
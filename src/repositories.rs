@@ -0,0 +1,1553 @@
+//! # Repositories
+//!
+//! Traits for the storage the services read and write, with SQLx
+//! implementations on [`PgPool`]. [`UserService`](crate::services::UserService),
+//! [`AuthService`](crate::auth::AuthService), [`ItemService`](crate::services::ItemService),
+//! and [`CategoryService`](crate::services::CategoryService) take any
+//! implementation, so handlers keep passing the pool while unit tests and
+//! downstream apps can pass an in-memory one instead:
+//!
+//! ```rust,ignore
+//! let user = AuthService::authenticate_user(&MockUsers::with(alice), "alice", "secret").await?;
+//! ```
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::collections::HashMap;
+
+use crate::item_filter::ItemFilter;
+use crate::models::{
+    Category, CreateItemRequest, CreateUserRequest, Item, ItemSearchFilters, ItemSearchHit,
+    ItemSuggestion, ItemWithCategory, ProfileFields, User, UserFilter, UsernameChange,
+    UsernameChangeEntry, chrono_opt_to_time_opt, time_to_chrono,
+};
+use crate::pagination::{Cursor, CursorPage, Paginated, Pagination};
+use crate::search;
+
+// =============================================================================
+// User Repository
+// =============================================================================
+
+/// Storage of users, their roles, and their preferences
+#[allow(dead_code)]
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Active user by ID
+    async fn get_user_by_id(&self, user_id: i32) -> Result<Option<User>, sqlx::Error>;
+
+    /// Active user by username
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error>;
+
+    /// Get user by ID, including deactivated users
+    async fn get_user_any_status(&self, user_id: i32) -> Result<Option<User>, sqlx::Error>;
+
+    /// Find a user by username or email, including deactivated users
+    async fn find_user_by_name_or_email(
+        &self,
+        identifier: &str,
+    ) -> Result<Option<User>, sqlx::Error>;
+
+    /// Get a page of users matching `filter`, newest first
+    async fn list_users(
+        &self,
+        filter: &UserFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<User>, sqlx::Error>;
+
+    /// Insert a user with an already hashed password
+    async fn insert_user(
+        &self,
+        request: &CreateUserRequest,
+        password_hash: &str,
+    ) -> Result<User, sqlx::Error>;
+
+    /// Update user's last login time
+    async fn update_last_login(&self, user_id: i32) -> Result<(), sqlx::Error>;
+
+    /// Update user's email; returns false if the user does not exist
+    async fn update_user_email(&self, user_id: i32, new_email: &str) -> Result<bool, sqlx::Error>;
+
+    /// Update user's password; returns false if the user does not exist
+    async fn update_user_password(
+        &self,
+        user_id: i32,
+        new_password_hash: &str,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Mark a user inactive; returns false if the user does not exist
+    async fn deactivate_user(&self, user_id: i32) -> Result<bool, sqlx::Error>;
+
+    /// Mark a user active again; returns false if the user does not exist
+    async fn reactivate_user(&self, user_id: i32) -> Result<bool, sqlx::Error>;
+
+    /// Delete a user and everything that belongs to them; returns false if
+    /// the user does not exist
+    async fn delete_user(&self, user_id: i32) -> Result<bool, sqlx::Error>;
+
+    /// Role names assigned to a user
+    async fn get_user_roles(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error>;
+
+    /// Role names for several users at once, keyed by user ID
+    async fn get_roles_for_users(
+        &self,
+        user_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<String>>, sqlx::Error>;
+
+    /// A user's preferred locale, if they chose one
+    async fn get_locale(&self, user_id: i32) -> Result<Option<String>, sqlx::Error>;
+
+    /// Set or clear a user's preferred locale
+    async fn set_locale(&self, user_id: i32, locale: Option<&str>) -> Result<bool, sqlx::Error>;
+
+    /// A user's preferred time zone, if they chose one
+    async fn get_timezone(&self, user_id: i32) -> Result<Option<String>, sqlx::Error>;
+
+    /// Set or clear a user's preferred time zone
+    async fn set_timezone(&self, user_id: i32, timezone: Option<&str>)
+    -> Result<bool, sqlx::Error>;
+
+    /// Storage key of a user's avatar, if they uploaded one
+    async fn get_avatar_key(&self, user_id: i32) -> Result<Option<String>, sqlx::Error>;
+
+    /// Set or clear a user's avatar
+    async fn set_avatar_key(&self, user_id: i32, key: Option<&str>) -> Result<bool, sqlx::Error>;
+
+    /// Replace a user's display name, bio, and profile data
+    async fn set_profile_fields(
+        &self,
+        user_id: i32,
+        fields: &ProfileFields,
+    ) -> Result<bool, sqlx::Error>;
+
+    /// Replace a user's roles with `roles`
+    async fn set_user_roles(&self, user_id: i32, roles: &[String]) -> Result<(), sqlx::Error>;
+
+    /// Rename a user unless another user has the name in any letter case,
+    /// recording the old name in their history
+    async fn rename_user(
+        &self,
+        user_id: i32,
+        new_username: &str,
+        changed_by: Option<i32>,
+    ) -> Result<UsernameChange, sqlx::Error>;
+
+    /// A user's earlier usernames, most recent rename first
+    async fn get_username_history(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<UsernameChangeEntry>, sqlx::Error>;
+}
+
+#[async_trait]
+impl UserRepository for PgPool {
+    async fn get_user_by_id(&self, user_id: i32) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id, username, email, password_hash, email_verified, is_active,
+                    display_name, bio, profile_data,
+                    last_login as "last_login: DateTime<Utc>",
+                    created_at as "created_at: DateTime<Utc>",
+                    updated_at as "updated_at: DateTime<Utc>"
+             FROM users
+             WHERE id = $1 AND is_active = true"#,
+            user_id
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id, username, email, password_hash, email_verified, is_active,
+                    display_name, bio, profile_data,
+                    last_login as "last_login: DateTime<Utc>",
+                    created_at as "created_at: DateTime<Utc>",
+                    updated_at as "updated_at: DateTime<Utc>"
+             FROM users
+             WHERE username = $1 AND is_active = true"#,
+            username
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn get_user_any_status(&self, user_id: i32) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id, username, email, password_hash, email_verified, is_active,
+                    display_name, bio, profile_data,
+                    last_login as "last_login: DateTime<Utc>",
+                    created_at as "created_at: DateTime<Utc>",
+                    updated_at as "updated_at: DateTime<Utc>"
+             FROM users
+             WHERE id = $1"#,
+            user_id
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn find_user_by_name_or_email(
+        &self,
+        identifier: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        sqlx::query_as!(
+            User,
+            r#"SELECT id, username, email, password_hash, email_verified, is_active,
+                    display_name, bio, profile_data,
+                    last_login as "last_login: DateTime<Utc>",
+                    created_at as "created_at: DateTime<Utc>",
+                    updated_at as "updated_at: DateTime<Utc>"
+             FROM users
+             WHERE username = $1 OR LOWER(email) = LOWER($1)
+             ORDER BY username = $1 DESC
+             LIMIT 1"#,
+            identifier
+        )
+        .fetch_optional(self)
+        .await
+    }
+
+    async fn list_users(
+        &self,
+        filter: &UserFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<User>, sqlx::Error> {
+        let search = filter.search.as_ref().map(|term| format!("%{}%", term));
+        let created_after = chrono_opt_to_time_opt(filter.created_after);
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!"
+             FROM users u
+             WHERE ($1::text IS NULL OR u.username ILIKE $1 OR u.email ILIKE $1)
+               AND ($2::bool IS NULL OR u.is_active = $2)
+               AND ($3::text IS NULL OR EXISTS (
+                    SELECT 1 FROM user_roles r WHERE r.user_id = u.id AND r.role = $3))
+               AND ($4::text IS NULL OR u.email ILIKE $4)
+               AND ($5::timestamptz IS NULL OR u.created_at >= $5)"#,
+            search,
+            filter.is_active,
+            filter.role,
+            filter.email_like,
+            created_after
+        )
+        .fetch_one(self)
+        .await?;
+
+        let users = sqlx::query_as!(
+            User,
+            r#"SELECT u.id, u.username, u.email, u.password_hash, u.email_verified, u.is_active,
+                    u.display_name, u.bio, u.profile_data,
+                    u.last_login as "last_login: DateTime<Utc>",
+                    u.created_at as "created_at: DateTime<Utc>",
+                    u.updated_at as "updated_at: DateTime<Utc>"
+             FROM users u
+             WHERE ($1::text IS NULL OR u.username ILIKE $1 OR u.email ILIKE $1)
+               AND ($2::bool IS NULL OR u.is_active = $2)
+               AND ($3::text IS NULL OR EXISTS (
+                    SELECT 1 FROM user_roles r WHERE r.user_id = u.id AND r.role = $3))
+               AND ($4::text IS NULL OR u.email ILIKE $4)
+               AND ($5::timestamptz IS NULL OR u.created_at >= $5)
+             ORDER BY u.created_at DESC, u.id DESC
+             LIMIT $6 OFFSET $7"#,
+            search,
+            filter.is_active,
+            filter.role,
+            filter.email_like,
+            created_after,
+            pagination.sql_limit(),
+            pagination.sql_offset()
+        )
+        .fetch_all(self)
+        .await?;
+
+        Ok(Paginated::new(users, total, pagination))
+    }
+
+    async fn insert_user(
+        &self,
+        request: &CreateUserRequest,
+        password_hash: &str,
+    ) -> Result<User, sqlx::Error> {
+        sqlx::query_as!(
+            User,
+            r#"INSERT INTO users (username, email, password_hash, locale)
+             VALUES ($1, $2, $3, $4)
+             RETURNING id, username, email, password_hash, email_verified, is_active,
+                       display_name, bio, profile_data,
+                       last_login as "last_login: DateTime<Utc>",
+                       created_at as "created_at: DateTime<Utc>",
+                       updated_at as "updated_at: DateTime<Utc>""#,
+            request.username,
+            request.email,
+            password_hash,
+            request.locale
+        )
+        .fetch_one(self)
+        .await
+    }
+
+    async fn update_last_login(&self, user_id: i32) -> Result<(), sqlx::Error> {
+        sqlx::query!("UPDATE users SET last_login = NOW() WHERE id = $1", user_id)
+            .execute(self)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn update_user_email(&self, user_id: i32, new_email: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET email = $1, updated_at = NOW() WHERE id = $2",
+            new_email,
+            user_id
+        )
+        .execute(self)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn update_user_password(
+        &self,
+        user_id: i32,
+        new_password_hash: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2",
+            new_password_hash,
+            user_id
+        )
+        .execute(self)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn deactivate_user(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET is_active = false, updated_at = NOW() WHERE id = $1",
+            user_id
+        )
+        .execute(self)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn reactivate_user(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+        let result =
+            sqlx::query("UPDATE users SET is_active = true, updated_at = NOW() WHERE id = $1")
+                .bind(user_id)
+                .execute(self)
+                .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn delete_user(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user_id)
+            .execute(self)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_user_roles(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar!(
+            "SELECT role FROM user_roles WHERE user_id = $1 ORDER BY role",
+            user_id
+        )
+        .fetch_all(self)
+        .await
+    }
+
+    async fn get_roles_for_users(
+        &self,
+        user_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<String>>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT user_id, role FROM user_roles WHERE user_id = ANY($1) ORDER BY role",
+            user_ids
+        )
+        .fetch_all(self)
+        .await?;
+
+        let mut roles: HashMap<i32, Vec<String>> = HashMap::new();
+        for row in rows {
+            roles.entry(row.user_id).or_default().push(row.role);
+        }
+        Ok(roles)
+    }
+
+    async fn get_locale(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+        let locale = sqlx::query_scalar!("SELECT locale FROM users WHERE id = $1", user_id)
+            .fetch_optional(self)
+            .await?;
+        Ok(locale.flatten())
+    }
+
+    async fn set_locale(&self, user_id: i32, locale: Option<&str>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET locale = $1, updated_at = NOW() WHERE id = $2",
+            locale,
+            user_id
+        )
+        .execute(self)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_timezone(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+        let timezone = sqlx::query_scalar!("SELECT timezone FROM users WHERE id = $1", user_id)
+            .fetch_optional(self)
+            .await?;
+        Ok(timezone.flatten())
+    }
+
+    async fn set_timezone(
+        &self,
+        user_id: i32,
+        timezone: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET timezone = $1, updated_at = NOW() WHERE id = $2",
+            timezone,
+            user_id
+        )
+        .execute(self)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn get_avatar_key(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+        let key = sqlx::query_scalar!("SELECT avatar_key FROM users WHERE id = $1", user_id)
+            .fetch_optional(self)
+            .await?;
+        Ok(key.flatten())
+    }
+
+    async fn set_avatar_key(&self, user_id: i32, key: Option<&str>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET avatar_key = $1, updated_at = NOW() WHERE id = $2",
+            key,
+            user_id
+        )
+        .execute(self)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_profile_fields(
+        &self,
+        user_id: i32,
+        fields: &ProfileFields,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "UPDATE users SET display_name = $1, bio = $2, profile_data = $3, updated_at = NOW()
+             WHERE id = $4",
+            fields.display_name,
+            fields.bio,
+            fields.profile_data,
+            user_id
+        )
+        .execute(self)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn set_user_roles(&self, user_id: i32, roles: &[String]) -> Result<(), sqlx::Error> {
+        let mut tx = self.begin().await?;
+
+        sqlx::query!("DELETE FROM user_roles WHERE user_id = $1", user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query!(
+            "INSERT INTO user_roles (user_id, role)
+             SELECT $1, role FROM UNNEST($2::text[]) AS role
+             ON CONFLICT DO NOTHING",
+            user_id,
+            roles
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await
+    }
+
+    async fn rename_user(
+        &self,
+        user_id: i32,
+        new_username: &str,
+        changed_by: Option<i32>,
+    ) -> Result<UsernameChange, sqlx::Error> {
+        let mut tx = self.begin().await?;
+
+        let current = sqlx::query_scalar!(
+            "SELECT username FROM users WHERE id = $1 FOR UPDATE",
+            user_id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(current) = current else {
+            return Ok(UsernameChange::NotFound);
+        };
+        if current == new_username {
+            return Ok(UsernameChange::Unchanged);
+        }
+
+        // users_username_lower_key turns away names another user has in any case
+        let renamed = sqlx::query!(
+            "UPDATE users SET username = $1, updated_at = NOW() WHERE id = $2",
+            new_username,
+            user_id
+        )
+        .execute(&mut *tx)
+        .await;
+        if let Err(sqlx::Error::Database(db)) = &renamed
+            && db.is_unique_violation()
+        {
+            return Ok(UsernameChange::Taken);
+        }
+        renamed?;
+
+        sqlx::query!(
+            "INSERT INTO username_history (user_id, old_username, new_username, changed_by)
+             VALUES ($1, $2, $3, $4)",
+            user_id,
+            current,
+            new_username,
+            changed_by
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        Ok(UsernameChange::Changed(current))
+    }
+
+    async fn get_username_history(
+        &self,
+        user_id: i32,
+    ) -> Result<Vec<UsernameChangeEntry>, sqlx::Error> {
+        sqlx::query_as!(
+            UsernameChangeEntry,
+            r#"SELECT old_username, new_username, changed_by,
+                    changed_at as "changed_at: DateTime<Utc>"
+             FROM username_history
+             WHERE user_id = $1
+             ORDER BY changed_at DESC, id DESC"#,
+            user_id
+        )
+        .fetch_all(self)
+        .await
+    }
+}
+
+// =============================================================================
+// Category Repository
+// =============================================================================
+
+/// Storage of item categories
+#[allow(dead_code)]
+#[async_trait]
+pub trait CategoryRepository: Send + Sync {
+    /// Get a page of visible categories
+    async fn get_all_categories(
+        &self,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Category>, sqlx::Error>;
+
+    /// Get category by ID
+    async fn get_category_by_id(&self, category_id: i32) -> Result<Option<Category>, sqlx::Error>;
+}
+
+#[async_trait]
+impl CategoryRepository for PgPool {
+    async fn get_all_categories(
+        &self,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Category>, sqlx::Error> {
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!" FROM category WHERE is_visible = true"#
+        )
+        .fetch_one(self)
+        .await?;
+
+        let rows = sqlx::query!(
+            "SELECT id, category_name, display_name, is_visible, display_order, created_at, updated_at 
+             FROM category 
+             WHERE is_visible = true 
+             ORDER BY display_order, display_name
+             LIMIT $1 OFFSET $2",
+            pagination.sql_limit(),
+            pagination.sql_offset()
+        )
+        .fetch_all(self)
+        .await?;
+
+        let categories: Vec<Category> = rows
+            .into_iter()
+            .map(|row| Category {
+                id: row.id,
+                category_name: row.category_name,
+                display_name: row.display_name,
+                is_visible: row.is_visible,
+                display_order: row.display_order,
+                created_at: time_to_chrono(row.created_at),
+                updated_at: time_to_chrono(row.updated_at),
+            })
+            .collect();
+
+        Ok(Paginated::new(categories, total, pagination))
+    }
+
+    async fn get_category_by_id(&self, category_id: i32) -> Result<Option<Category>, sqlx::Error> {
+        let row = sqlx::query!(
+            "SELECT id, category_name, display_name, is_visible, display_order, created_at, updated_at 
+             FROM category 
+             WHERE id = $1 AND is_visible = true",
+            category_id
+        )
+        .fetch_optional(self)
+        .await?;
+
+        if let Some(row) = row {
+            let category = Category {
+                id: row.id,
+                category_name: row.category_name,
+                display_name: row.display_name,
+                is_visible: row.is_visible,
+                display_order: row.display_order,
+                created_at: time_to_chrono(row.created_at),
+                updated_at: time_to_chrono(row.updated_at),
+            };
+            Ok(Some(category))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+// =============================================================================
+// Item Repository
+// =============================================================================
+
+/// Storage of items
+#[allow(dead_code)]
+#[async_trait]
+pub trait ItemRepository: Send + Sync {
+    /// Get a page of items with their categories
+    async fn get_all_items(
+        &self,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemWithCategory>, sqlx::Error>;
+
+    /// A page of items in visible categories matching `filter`, in its order
+    async fn list_items(
+        &self,
+        filter: &ItemFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemWithCategory>, sqlx::Error>;
+
+    /// A keyset page of items in visible categories matching `filter`,
+    /// starting after `after`. `filter` must sort by creation time.
+    async fn list_items_after(
+        &self,
+        filter: &ItemFilter,
+        after: Option<Cursor>,
+        pagination: &Pagination,
+    ) -> Result<CursorPage<ItemWithCategory>, sqlx::Error>;
+
+    /// An item with its category, whether or not either is visible
+    async fn get_item(&self, id: i32) -> Result<Option<ItemWithCategory>, sqlx::Error>;
+
+    /// Visible items whose title contains `text`, titles starting with it
+    /// first, then by trigram similarity
+    async fn suggest(&self, text: &str, limit: i64) -> Result<Vec<ItemSuggestion>, sqlx::Error>;
+
+    /// Visible items matching the web-search style `query`, most relevant
+    /// first. Matched words in the headlines are wrapped in
+    /// [`MARK_START`](crate::search::MARK_START) and
+    /// [`MARK_END`](crate::search::MARK_END).
+    async fn search(
+        &self,
+        query: &str,
+        filters: &ItemSearchFilters,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemSearchHit>, sqlx::Error>;
+
+    /// Get items by category
+    async fn get_items_by_category(&self, category_id: i32) -> Result<Vec<Item>, sqlx::Error>;
+
+    /// Create new item
+    async fn create_item(&self, request: &CreateItemRequest) -> Result<Item, sqlx::Error>;
+
+    /// Create many items, all or nothing, returned in the order given
+    async fn create_items(&self, requests: &[CreateItemRequest]) -> Result<Vec<Item>, sqlx::Error>;
+}
+
+#[async_trait]
+impl ItemRepository for PgPool {
+    async fn get_all_items(
+        &self,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemWithCategory>, sqlx::Error> {
+        self.list_items(&ItemFilter::default(), pagination).await
+    }
+
+    async fn list_items(
+        &self,
+        filter: &ItemFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemWithCategory>, sqlx::Error> {
+        let created_after = chrono_opt_to_time_opt(filter.created_after);
+        let created_before = chrono_opt_to_time_opt(filter.created_before);
+
+        let total = sqlx::query_scalar!(
+            r#"SELECT COUNT(*) as "count!"
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             WHERE c.is_visible = true AND i.is_active = $1
+               AND ($2::int IS NULL OR i.category_id = $2)
+               AND ($3::timestamptz IS NULL OR i.created_at >= $3)
+               AND ($4::timestamptz IS NULL OR i.created_at <= $4)"#,
+            filter.is_active,
+            filter.category_id,
+            created_after,
+            created_before
+        )
+        .fetch_one(self)
+        .await?;
+
+        // The sort and direction are bound names, matched against fixed
+        // orders; ties go by ID so pages do not overlap
+        let rows = sqlx::query_as!(
+            ItemCategoryRow,
+            r#"SELECT i.id, i.title, i.description, i.data, i.is_active, i.category_id,
+                    i.created_at as "created_at: DateTime<Utc>",
+                    i.updated_at as "updated_at: DateTime<Utc>",
+                    c.id as cat_id, c.category_name, c.display_name, c.is_visible,
+                    c.display_order,
+                    c.created_at as "cat_created_at: DateTime<Utc>",
+                    c.updated_at as "cat_updated_at: DateTime<Utc>"
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             WHERE c.is_visible = true AND i.is_active = $1
+               AND ($2::int IS NULL OR i.category_id = $2)
+               AND ($3::timestamptz IS NULL OR i.created_at >= $3)
+               AND ($4::timestamptz IS NULL OR i.created_at <= $4)
+             ORDER BY
+               CASE WHEN $7 = 'title' AND $8 = 'asc' THEN lower(i.title) END ASC,
+               CASE WHEN $7 = 'title' AND $8 = 'desc' THEN lower(i.title) END DESC,
+               CASE WHEN $7 = 'created_at' AND $8 = 'asc' THEN i.created_at END ASC,
+               CASE WHEN $7 = 'created_at' AND $8 = 'desc' THEN i.created_at END DESC,
+               CASE WHEN $8 = 'asc' THEN i.id END ASC,
+               i.id DESC
+             LIMIT $5 OFFSET $6"#,
+            filter.is_active,
+            filter.category_id,
+            created_after,
+            created_before,
+            pagination.sql_limit(),
+            pagination.sql_offset(),
+            filter.sort.as_str(),
+            filter.direction.as_str()
+        )
+        .fetch_all(self)
+        .await?;
+
+        let items = rows.into_iter().map(ItemWithCategory::from).collect();
+
+        Ok(Paginated::new(items, total, pagination))
+    }
+
+    async fn list_items_after(
+        &self,
+        filter: &ItemFilter,
+        after: Option<Cursor>,
+        pagination: &Pagination,
+    ) -> Result<CursorPage<ItemWithCategory>, sqlx::Error> {
+        // Rows past the cursor in the direction of the sort
+        let rows = sqlx::query_as!(
+            ItemCategoryRow,
+            r#"SELECT i.id, i.title, i.description, i.data, i.is_active, i.category_id,
+                    i.created_at as "created_at: DateTime<Utc>",
+                    i.updated_at as "updated_at: DateTime<Utc>",
+                    c.id as cat_id, c.category_name, c.display_name, c.is_visible,
+                    c.display_order,
+                    c.created_at as "cat_created_at: DateTime<Utc>",
+                    c.updated_at as "cat_updated_at: DateTime<Utc>"
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             WHERE c.is_visible = true AND i.is_active = $1
+               AND ($2::int IS NULL OR i.category_id = $2)
+               AND ($3::timestamptz IS NULL OR i.created_at >= $3)
+               AND ($4::timestamptz IS NULL OR i.created_at <= $4)
+               AND ($5::timestamptz IS NULL
+                    OR ($8 = 'asc' AND (i.created_at, i.id) > ($5, $6::bigint))
+                    OR ($8 = 'desc' AND (i.created_at, i.id) < ($5, $6::bigint)))
+             ORDER BY
+               CASE WHEN $7 = 'title' AND $8 = 'asc' THEN lower(i.title) END ASC,
+               CASE WHEN $7 = 'title' AND $8 = 'desc' THEN lower(i.title) END DESC,
+               CASE WHEN $7 = 'created_at' AND $8 = 'asc' THEN i.created_at END ASC,
+               CASE WHEN $7 = 'created_at' AND $8 = 'desc' THEN i.created_at END DESC,
+               CASE WHEN $8 = 'asc' THEN i.id END ASC,
+               i.id DESC
+             LIMIT $9"#,
+            filter.is_active,
+            filter.category_id,
+            chrono_opt_to_time_opt(filter.created_after),
+            chrono_opt_to_time_opt(filter.created_before),
+            chrono_opt_to_time_opt(after.map(|cursor| cursor.created_at)),
+            after.map(|cursor| cursor.id),
+            filter.sort.as_str(),
+            filter.direction.as_str(),
+            pagination.sql_limit() + 1
+        )
+        .fetch_all(self)
+        .await?;
+
+        let items = rows.into_iter().map(ItemWithCategory::from).collect();
+        Ok(CursorPage::new(
+            items,
+            pagination,
+            |item: &ItemWithCategory| Cursor::new(item.item.created_at, item.item.id),
+        ))
+    }
+
+    async fn get_item(&self, id: i32) -> Result<Option<ItemWithCategory>, sqlx::Error> {
+        let row = sqlx::query_as!(
+            ItemCategoryRow,
+            r#"SELECT i.id, i.title, i.description, i.data, i.is_active, i.category_id,
+                    i.created_at as "created_at: DateTime<Utc>",
+                    i.updated_at as "updated_at: DateTime<Utc>",
+                    c.id as cat_id, c.category_name, c.display_name, c.is_visible,
+                    c.display_order,
+                    c.created_at as "cat_created_at: DateTime<Utc>",
+                    c.updated_at as "cat_updated_at: DateTime<Utc>"
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             WHERE i.id = $1"#,
+            id
+        )
+        .fetch_optional(self)
+        .await?;
+
+        Ok(row.map(ItemWithCategory::from))
+    }
+
+    async fn suggest(&self, text: &str, limit: i64) -> Result<Vec<ItemSuggestion>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT i.id, i.title, c.display_name AS category
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             WHERE c.is_visible = true AND i.is_active = true
+               AND i.title ILIKE '%' || $1 || '%'
+             ORDER BY starts_with(lower(i.title), lower($2)) DESC,
+                      similarity(i.title, $2) DESC, i.title
+             LIMIT $3",
+        )
+        .bind(escape_like(text))
+        .bind(text)
+        .bind(limit)
+        .fetch_all(self)
+        .await
+    }
+
+    async fn search(
+        &self,
+        query: &str,
+        filters: &ItemSearchFilters,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemSearchHit>, sqlx::Error> {
+        const WHERE: &str = "WHERE c.is_visible = true AND i.is_active = true
+               AND i.search_vector @@ q.query
+               AND ($2::int IS NULL OR i.category_id = $2)";
+
+        let total: i64 = sqlx::query_scalar(&format!(
+            "SELECT COUNT(*)
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             CROSS JOIN websearch_to_tsquery('english', $1) AS q(query)
+             {}",
+            WHERE
+        ))
+        .bind(query)
+        .bind(filters.category_id)
+        .fetch_one(self)
+        .await?;
+
+        let hits = sqlx::query_as(&format!(
+            "SELECT i.id, i.title, i.description, i.data, i.is_active, i.category_id,
+                    i.created_at, i.updated_at, c.display_name AS category,
+                    ts_rank_cd(i.search_vector, q.query) AS rank,
+                    ts_headline('english', i.title, q.query, $3) AS title_html,
+                    CASE WHEN i.description IS NULL THEN NULL
+                         ELSE ts_headline('english', i.description, q.query, $4)
+                    END AS snippet_html
+             FROM items i
+             JOIN category c ON i.category_id = c.id
+             CROSS JOIN websearch_to_tsquery('english', $1) AS q(query)
+             {}
+             ORDER BY rank DESC, i.id
+             LIMIT $5 OFFSET $6",
+            WHERE
+        ))
+        .bind(query)
+        .bind(filters.category_id)
+        .bind(search::title_headline_options())
+        .bind(search::snippet_headline_options())
+        .bind(pagination.sql_limit())
+        .bind(pagination.sql_offset())
+        .fetch_all(self)
+        .await?;
+
+        Ok(Paginated::new(hits, total, pagination))
+    }
+
+    async fn get_items_by_category(&self, category_id: i32) -> Result<Vec<Item>, sqlx::Error> {
+        let rows = sqlx::query!(
+            "SELECT id, title, description, data, is_active, category_id, created_at, updated_at
+             FROM items 
+             WHERE category_id = $1 AND is_active = true
+             ORDER BY created_at DESC",
+            category_id
+        )
+        .fetch_all(self)
+        .await?;
+
+        let items: Vec<Item> = rows
+            .into_iter()
+            .map(|row| Item {
+                id: row.id,
+                title: row.title,
+                description: row.description,
+                data: row.data,
+                is_active: row.is_active,
+                category_id: row.category_id,
+                created_at: time_to_chrono(row.created_at),
+                updated_at: time_to_chrono(row.updated_at),
+            })
+            .collect();
+
+        Ok(items)
+    }
+
+    async fn create_item(&self, request: &CreateItemRequest) -> Result<Item, sqlx::Error> {
+        let row = sqlx::query!(
+            "INSERT INTO items (title, description, data, category_id) 
+             VALUES ($1, $2, $3, $4) 
+             RETURNING id, title, description, data, is_active, category_id, created_at, updated_at",
+            request.title,
+            request.description,
+            request.data,
+            request.category_id
+        )
+        .fetch_one(self)
+        .await?;
+
+        let item = Item {
+            id: row.id,
+            title: row.title,
+            description: row.description,
+            data: row.data,
+            is_active: row.is_active,
+            category_id: row.category_id,
+            created_at: time_to_chrono(row.created_at),
+            updated_at: time_to_chrono(row.updated_at),
+        };
+
+        Ok(item)
+    }
+
+    async fn create_items(&self, requests: &[CreateItemRequest]) -> Result<Vec<Item>, sqlx::Error> {
+        let mut tx = self.begin().await?;
+        let mut items = Vec::with_capacity(requests.len());
+
+        // One statement per chunk, each column bound as an array; ORDINALITY
+        // keeps IDs in input order
+        for chunk in requests.chunks(ITEM_INSERT_CHUNK) {
+            let titles: Vec<&str> = chunk.iter().map(|r| r.title.as_str()).collect();
+            let descriptions: Vec<Option<&str>> =
+                chunk.iter().map(|r| r.description.as_deref()).collect();
+            let data: Vec<Option<serde_json::Value>> =
+                chunk.iter().map(|r| r.data.clone()).collect();
+            let category_ids: Vec<i32> = chunk.iter().map(|r| r.category_id).collect();
+
+            let mut inserted = sqlx::query_as!(
+                Item,
+                r#"INSERT INTO items (title, description, data, category_id)
+                 SELECT title, description, data, category_id
+                 FROM UNNEST($1::text[], $2::text[], $3::jsonb[], $4::int[])
+                      WITH ORDINALITY AS input (title, description, data, category_id, n)
+                 ORDER BY n
+                 RETURNING id, title, description, data, is_active, category_id,
+                           created_at as "created_at: DateTime<Utc>",
+                           updated_at as "updated_at: DateTime<Utc>""#,
+                &titles as &[&str],
+                &descriptions as &[Option<&str>],
+                &data as &[Option<serde_json::Value>],
+                &category_ids
+            )
+            .fetch_all(&mut *tx)
+            .await?;
+            inserted.sort_by_key(|item| item.id);
+            items.append(&mut inserted);
+        }
+
+        tx.commit().await?;
+        Ok(items)
+    }
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Most rows [`ItemRepository::create_items`] sends in one statement
+#[cfg(not(feature = "testing"))]
+pub const ITEM_INSERT_CHUNK: usize = 5_000;
+
+/// Small with the `testing` feature, so tests cross chunk boundaries with
+/// a few hundred rows
+#[cfg(feature = "testing")]
+pub const ITEM_INSERT_CHUNK: usize = 100;
+
+/// An `items` row joined with its category, the category's columns prefixed
+struct ItemCategoryRow {
+    id: i32,
+    title: String,
+    description: Option<String>,
+    data: Option<serde_json::Value>,
+    is_active: bool,
+    category_id: i32,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    cat_id: i32,
+    category_name: String,
+    display_name: String,
+    is_visible: bool,
+    display_order: i32,
+    cat_created_at: DateTime<Utc>,
+    cat_updated_at: DateTime<Utc>,
+}
+
+impl From<ItemCategoryRow> for ItemWithCategory {
+    fn from(row: ItemCategoryRow) -> Self {
+        ItemWithCategory {
+            item: Item {
+                id: row.id,
+                title: row.title,
+                description: row.description,
+                data: row.data,
+                is_active: row.is_active,
+                category_id: row.category_id,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            category: Category {
+                id: row.cat_id,
+                category_name: row.category_name,
+                display_name: row.display_name,
+                is_visible: row.is_visible,
+                display_order: row.display_order,
+                created_at: row.cat_created_at,
+                updated_at: row.cat_updated_at,
+            },
+        }
+    }
+}
+
+/// Escape `%`, `_` and the escape character itself so `text` matches
+/// literally inside a LIKE pattern
+fn escape_like(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthService, PasswordService};
+    use crate::services::UserService;
+    use chrono::Utc;
+    use std::sync::Mutex;
+
+    /// Users kept in memory, with the columns [`User`] leaves out
+    #[derive(Default)]
+    struct MockUsers(Mutex<Vec<MockUser>>);
+
+    #[derive(Clone)]
+    struct MockUser {
+        user: User,
+        roles: Vec<String>,
+        locale: Option<String>,
+        timezone: Option<String>,
+        avatar_key: Option<String>,
+        history: Vec<UsernameChangeEntry>,
+    }
+
+    impl MockUser {
+        fn matches(&self, filter: &UserFilter) -> bool {
+            let user = &self.user;
+            let contains =
+                |text: &str, term: &str| text.to_lowercase().contains(&term.to_lowercase());
+
+            let search = filter
+                .search
+                .as_ref()
+                .is_none_or(|term| contains(&user.username, term) || contains(&user.email, term));
+            let active = filter
+                .is_active
+                .is_none_or(|active| user.is_active == active);
+            let role = filter
+                .role
+                .as_ref()
+                .is_none_or(|role| self.roles.contains(role));
+            let email = (filter.email_like.as_ref())
+                .is_none_or(|pattern| matches_like(&user.email, pattern));
+            let created = (filter.created_after).is_none_or(|after| user.created_at >= after);
+            search && active && role && email && created
+        }
+    }
+
+    impl MockUsers {
+        fn with(user: User) -> Self {
+            let users = Self::default();
+            users.0.lock().unwrap().push(MockUser {
+                user,
+                roles: Vec::new(),
+                locale: None,
+                timezone: None,
+                avatar_key: None,
+                history: Vec::new(),
+            });
+            users
+        }
+
+        fn find(&self, matches: impl Fn(&User) -> bool) -> Option<User> {
+            self.find_row(|row| matches(&row.user)).map(|row| row.user)
+        }
+
+        fn find_row(&self, matches: impl Fn(&MockUser) -> bool) -> Option<MockUser> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|row| matches(row))
+                .cloned()
+        }
+
+        fn update(&self, user_id: i32, change: impl FnOnce(&mut User)) -> bool {
+            self.update_row(user_id, |row| change(&mut row.user))
+        }
+
+        fn update_row(&self, user_id: i32, change: impl FnOnce(&mut MockUser)) -> bool {
+            let mut users = self.0.lock().unwrap();
+            match users.iter_mut().find(|row| row.user.id == user_id) {
+                Some(row) => {
+                    change(row);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    #[async_trait]
+    impl UserRepository for MockUsers {
+        async fn get_user_by_id(&self, user_id: i32) -> Result<Option<User>, sqlx::Error> {
+            Ok(self.find(|user| user.id == user_id && user.is_active))
+        }
+
+        async fn get_user_by_username(&self, username: &str) -> Result<Option<User>, sqlx::Error> {
+            Ok(self.find(|user| user.username == username && user.is_active))
+        }
+
+        async fn get_user_any_status(&self, user_id: i32) -> Result<Option<User>, sqlx::Error> {
+            Ok(self.find(|user| user.id == user_id))
+        }
+
+        async fn find_user_by_name_or_email(
+            &self,
+            identifier: &str,
+        ) -> Result<Option<User>, sqlx::Error> {
+            Ok(self.find(|user| {
+                user.username == identifier || user.email.eq_ignore_ascii_case(identifier)
+            }))
+        }
+
+        async fn list_users(
+            &self,
+            filter: &UserFilter,
+            pagination: &Pagination,
+        ) -> Result<Paginated<User>, sqlx::Error> {
+            let mut users: Vec<MockUser> = self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|row| row.matches(filter))
+                .cloned()
+                .collect();
+            users.sort_by_key(|row| std::cmp::Reverse((row.user.created_at, row.user.id)));
+
+            let total = users.len() as i64;
+            let page = users
+                .into_iter()
+                .skip(pagination.offset as usize)
+                .take(pagination.limit as usize)
+                .map(|row| row.user)
+                .collect();
+            Ok(Paginated::new(page, total, pagination))
+        }
+
+        async fn insert_user(
+            &self,
+            request: &CreateUserRequest,
+            password_hash: &str,
+        ) -> Result<User, sqlx::Error> {
+            let mut users = self.0.lock().unwrap();
+            let now = Utc::now();
+            let user = User {
+                id: users.iter().map(|row| row.user.id).max().unwrap_or(0) + 1,
+                username: request.username.clone(),
+                email: request.email.clone(),
+                password_hash: Some(password_hash.to_string()),
+                email_verified: false,
+                is_active: true,
+                display_name: None,
+                bio: None,
+                profile_data: serde_json::json!({}),
+                last_login: None,
+                created_at: now,
+                updated_at: now,
+            };
+            users.push(MockUser {
+                user: user.clone(),
+                roles: Vec::new(),
+                locale: request.locale.clone(),
+                timezone: None,
+                avatar_key: None,
+                history: Vec::new(),
+            });
+            Ok(user)
+        }
+
+        async fn update_last_login(&self, user_id: i32) -> Result<(), sqlx::Error> {
+            self.update(user_id, |user| user.last_login = Some(Utc::now()));
+            Ok(())
+        }
+
+        async fn update_user_email(
+            &self,
+            user_id: i32,
+            new_email: &str,
+        ) -> Result<bool, sqlx::Error> {
+            Ok(self.update(user_id, |user| user.email = new_email.to_string()))
+        }
+
+        async fn update_user_password(
+            &self,
+            user_id: i32,
+            new_password_hash: &str,
+        ) -> Result<bool, sqlx::Error> {
+            Ok(self.update(user_id, |user| {
+                user.password_hash = Some(new_password_hash.to_string())
+            }))
+        }
+
+        async fn deactivate_user(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+            Ok(self.update(user_id, |user| user.is_active = false))
+        }
+
+        async fn reactivate_user(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+            Ok(self.update(user_id, |user| user.is_active = true))
+        }
+
+        async fn delete_user(&self, user_id: i32) -> Result<bool, sqlx::Error> {
+            let mut users = self.0.lock().unwrap();
+            let before = users.len();
+            users.retain(|row| row.user.id != user_id);
+            Ok(users.len() < before)
+        }
+
+        async fn get_user_roles(&self, user_id: i32) -> Result<Vec<String>, sqlx::Error> {
+            Ok(self
+                .find_row(|row| row.user.id == user_id)
+                .map(|row| row.roles)
+                .unwrap_or_default())
+        }
+
+        async fn get_roles_for_users(
+            &self,
+            user_ids: &[i32],
+        ) -> Result<HashMap<i32, Vec<String>>, sqlx::Error> {
+            Ok(self
+                .0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|row| user_ids.contains(&row.user.id) && !row.roles.is_empty())
+                .map(|row| (row.user.id, row.roles.clone()))
+                .collect())
+        }
+
+        async fn get_locale(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+            Ok(self
+                .find_row(|row| row.user.id == user_id)
+                .and_then(|row| row.locale))
+        }
+
+        async fn set_locale(
+            &self,
+            user_id: i32,
+            locale: Option<&str>,
+        ) -> Result<bool, sqlx::Error> {
+            Ok(self.update_row(user_id, |row| row.locale = locale.map(str::to_string)))
+        }
+
+        async fn get_timezone(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+            Ok(self
+                .find_row(|row| row.user.id == user_id)
+                .and_then(|row| row.timezone))
+        }
+
+        async fn set_timezone(
+            &self,
+            user_id: i32,
+            timezone: Option<&str>,
+        ) -> Result<bool, sqlx::Error> {
+            Ok(self.update_row(user_id, |row| row.timezone = timezone.map(str::to_string)))
+        }
+
+        async fn get_avatar_key(&self, user_id: i32) -> Result<Option<String>, sqlx::Error> {
+            Ok(self
+                .find_row(|row| row.user.id == user_id)
+                .and_then(|row| row.avatar_key))
+        }
+
+        async fn set_avatar_key(
+            &self,
+            user_id: i32,
+            key: Option<&str>,
+        ) -> Result<bool, sqlx::Error> {
+            Ok(self.update_row(user_id, |row| row.avatar_key = key.map(str::to_string)))
+        }
+
+        async fn set_profile_fields(
+            &self,
+            user_id: i32,
+            fields: &ProfileFields,
+        ) -> Result<bool, sqlx::Error> {
+            Ok(self.update(user_id, |user| {
+                user.display_name = fields.display_name.clone();
+                user.bio = fields.bio.clone();
+                user.profile_data = fields.profile_data.clone();
+            }))
+        }
+
+        async fn set_user_roles(&self, user_id: i32, roles: &[String]) -> Result<(), sqlx::Error> {
+            let mut roles = roles.to_vec();
+            roles.sort();
+            roles.dedup();
+            self.update_row(user_id, |row| row.roles = roles);
+            Ok(())
+        }
+
+        async fn rename_user(
+            &self,
+            user_id: i32,
+            new_username: &str,
+            changed_by: Option<i32>,
+        ) -> Result<UsernameChange, sqlx::Error> {
+            let mut users = self.0.lock().unwrap();
+            if users.iter().any(|row| {
+                row.user.id != user_id && row.user.username.eq_ignore_ascii_case(new_username)
+            }) {
+                return Ok(UsernameChange::Taken);
+            }
+            let Some(row) = users.iter_mut().find(|row| row.user.id == user_id) else {
+                return Ok(UsernameChange::NotFound);
+            };
+            if row.user.username == new_username {
+                return Ok(UsernameChange::Unchanged);
+            }
+
+            let previous = std::mem::replace(&mut row.user.username, new_username.to_string());
+            row.history.insert(
+                0,
+                UsernameChangeEntry {
+                    old_username: previous.clone(),
+                    new_username: new_username.to_string(),
+                    changed_by,
+                    changed_at: Utc::now(),
+                },
+            );
+            Ok(UsernameChange::Changed(previous))
+        }
+
+        async fn get_username_history(
+            &self,
+            user_id: i32,
+        ) -> Result<Vec<UsernameChangeEntry>, sqlx::Error> {
+            Ok(self
+                .find_row(|row| row.user.id == user_id)
+                .map(|row| row.history)
+                .unwrap_or_default())
+        }
+    }
+
+    /// Case-insensitive SQL `LIKE`, honouring only the `%` wildcard
+    fn matches_like(text: &str, pattern: &str) -> bool {
+        let (text, pattern) = (text.to_lowercase(), pattern.to_lowercase());
+        let parts: Vec<&str> = pattern.split('%').collect();
+        let (first, last) = (parts[0], parts[parts.len() - 1]);
+        if parts.len() == 1 {
+            return text == pattern;
+        }
+        if text.len() < first.len() + last.len()
+            || !text.starts_with(first)
+            || !text.ends_with(last)
+        {
+            return false;
+        }
+
+        let mut rest = &text[first.len()..text.len() - last.len()];
+        for part in &parts[1..parts.len() - 1] {
+            match rest.find(part) {
+                Some(at) => rest = &rest[at + part.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
+
+    fn alice() -> User {
+        User {
+            id: 7,
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            password_hash: Some(PasswordService::hash_password("correct horse").unwrap()),
+            email_verified: true,
+            is_active: true,
+            display_name: None,
+            bio: None,
+            profile_data: serde_json::json!({}),
+            last_login: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_services_run_against_a_mock_repository() {
+        let users = MockUsers::with(alice());
+
+        assert!(
+            AuthService::authenticate_user(&users, "alice", "wrong")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        let user = AuthService::authenticate_user(&users, "alice", "correct horse")
+            .await
+            .unwrap()
+            .expect("The right password should sign in");
+        assert_eq!(user.id, 7);
+        assert!(
+            users
+                .find(|user| user.id == 7)
+                .unwrap()
+                .last_login
+                .is_some()
+        );
+
+        assert!(
+            AuthService::change_user_password(&users, 7, "correct horse", "battery staple")
+                .await
+                .unwrap()
+        );
+        assert!(
+            AuthService::authenticate_user(&users, "alice", "battery staple")
+                .await
+                .unwrap()
+                .is_some()
+        );
+
+        // Lookups by ID go through the same repository, and deactivated
+        // users can no longer sign in or change their profile
+        let found = UserService::find_user(&users, "7").await.unwrap();
+        assert_eq!(found.map(|user| user.username).as_deref(), Some("alice"));
+        assert!(UserService::deactivate_user(&users, 7).await.unwrap());
+        assert!(
+            AuthService::authenticate_user(&users, "alice", "battery staple")
+                .await
+                .unwrap()
+                .is_none()
+        );
+        assert!(
+            !AuthService::update_user_profile(&users, 7, "new@example.com")
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mock_repository_keeps_what_it_is_given() {
+        let users = MockUsers::with(alice());
+        let request = CreateUserRequest {
+            username: "bob".to_string(),
+            email: "bob@test.org".to_string(),
+            password: String::new(),
+            roles: Vec::new(),
+            locale: Some("de".to_string()),
+        };
+        let bob = users.insert_user(&request, "hash").await.unwrap();
+        assert_eq!(bob.id, 8);
+        assert_eq!(users.get_locale(8).await.unwrap().as_deref(), Some("de"));
+
+        users
+            .set_user_roles(8, &["admin".to_string()])
+            .await
+            .unwrap();
+        assert!(users.set_timezone(8, Some("Europe/Berlin")).await.unwrap());
+        assert!(!users.set_avatar_key(99, Some("avatar")).await.unwrap());
+        assert_eq!(users.get_user_roles(8).await.unwrap(), vec!["admin"]);
+        assert_eq!(
+            users.get_timezone(8).await.unwrap().as_deref(),
+            Some("Europe/Berlin")
+        );
+
+        let admins = UserFilter {
+            role: Some("admin".to_string()),
+            ..Default::default()
+        };
+        let page = users
+            .list_users(&admins, &Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+        assert_eq!(page.items[0].username, "bob");
+
+        let example = UserFilter {
+            email_like: Some("%@EXAMPLE.com".to_string()),
+            ..Default::default()
+        };
+        let page = users
+            .list_users(&example, &Pagination::default())
+            .await
+            .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert_eq!(page.items[0].username, "alice");
+    }
+}
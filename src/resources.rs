@@ -0,0 +1,256 @@
+//! # Resource Tuning
+//!
+//! Defaults sized to what the server is allowed to use rather than to the
+//! host. A container limited to one CPU on a 64-core node would otherwise
+//! start 64 Tokio workers and a pool sized for them. [`Limits::detect`]
+//! reads the cgroup CPU quota and memory limit (cgroup v2, then v1) once at
+//! startup, and the settings left unset in the configuration derive from it:
+//!
+//! | Setting | Override | Default |
+//! |---------|----------|---------|
+//! | Tokio worker threads | `WORKER_THREADS` | One per CPU |
+//! | Database pool size | `DATABASE_MAX_CONNECTIONS` | 5 per CPU, from 5 to 20 |
+//! | Job concurrency | `JOB_CONCURRENCY` | 2 per CPU, from 1 to 8, at most one per 128 MiB of memory |
+//!
+//! A fractional quota rounds up, so 1.5 CPUs counts as 2. Outside a cgroup
+//! (or on other platforms) the CPUs are those the OS lets the process use.
+//! `/health` reports the limits found and the values chosen.
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::config::AppConfig;
+use crate::models::{ResourceReport, TunedValue};
+
+/// Where the cgroup filesystem is mounted
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// Memory budgeted for each concurrently running job
+const MEMORY_PER_JOB: u64 = 128 * 1024 * 1024;
+
+/// cgroup v1 reports no memory limit as a number close to `i64::MAX`
+const UNLIMITED_MEMORY: u64 = 1 << 60;
+
+/// What the process may use, as far as it can tell
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// CPUs the OS schedules this process on
+    pub available_cpus: usize,
+    /// CPU quota of the cgroup, in CPUs
+    pub cpu_quota: Option<f64>,
+    /// Memory limit of the cgroup, in bytes
+    pub memory_bytes: Option<u64>,
+    /// `v1` or `v2` when the limits came from a cgroup
+    pub cgroup: Option<&'static str>,
+}
+
+impl Limits {
+    /// Read the limits of this process
+    pub fn detect() -> Self {
+        let available_cpus = std::thread::available_parallelism().map_or(1, usize::from);
+        Self::detect_in(Path::new(CGROUP_ROOT), available_cpus)
+    }
+
+    fn detect_in(root: &Path, available_cpus: usize) -> Self {
+        let read = |path: &str| fs::read_to_string(root.join(path)).ok();
+        let none = Self {
+            available_cpus,
+            cpu_quota: None,
+            memory_bytes: None,
+            cgroup: None,
+        };
+
+        if root.join("cgroup.controllers").exists() {
+            return Self {
+                cpu_quota: read("cpu.max").and_then(|max| parse_cpu_max(&max)),
+                memory_bytes: read("memory.max").and_then(|max| parse_memory_max(&max)),
+                cgroup: Some("v2"),
+                ..none
+            };
+        }
+
+        let quota = read("cpu/cpu.cfs_quota_us");
+        let memory = read("memory/memory.limit_in_bytes");
+        if quota.is_none() && memory.is_none() {
+            return none;
+        }
+        Self {
+            cpu_quota: quota
+                .zip(read("cpu/cpu.cfs_period_us"))
+                .and_then(|(quota, period)| parse_cfs_quota(&quota, &period)),
+            memory_bytes: memory.and_then(|limit| parse_memory_limit(&limit)),
+            cgroup: Some("v1"),
+            ..none
+        }
+    }
+
+    /// Whole CPUs to size things by: the quota rounded up, or the available
+    /// CPUs when that is lower or there is no quota
+    pub fn cpus(&self) -> usize {
+        let quota = self
+            .cpu_quota
+            .map_or(usize::MAX, |quota| quota.ceil() as usize);
+        quota.min(self.available_cpus).max(1)
+    }
+
+    /// Default Tokio worker threads
+    pub fn worker_threads(&self) -> usize {
+        self.cpus()
+    }
+
+    /// Default size of the database pool
+    pub fn db_max_connections(&self) -> u32 {
+        (self.cpus() as u32).saturating_mul(5).clamp(5, 20)
+    }
+
+    /// Default number of jobs a worker runs at once
+    pub fn job_concurrency(&self) -> usize {
+        let by_cpu = self.cpus().saturating_mul(2).clamp(1, 8);
+        match self.memory_bytes {
+            Some(bytes) => by_cpu.min((bytes / MEMORY_PER_JOB).max(1) as usize),
+            None => by_cpu,
+        }
+    }
+}
+
+/// Limits of this process, detected on first use
+pub fn limits() -> &'static Limits {
+    static LIMITS: OnceLock<Limits> = OnceLock::new();
+    LIMITS.get_or_init(Limits::detect)
+}
+
+/// cgroup v2 `cpu.max`: `<quota> <period>`, or `max <period>` for none
+fn parse_cpu_max(max: &str) -> Option<f64> {
+    let mut parts = max.split_whitespace();
+    let quota: f64 = parts.next()?.parse().ok()?;
+    let period: f64 = parts.next()?.parse().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// cgroup v2 `memory.max`: bytes, or `max` for none
+fn parse_memory_max(max: &str) -> Option<u64> {
+    max.trim().parse().ok()
+}
+
+/// cgroup v1 `cpu.cfs_quota_us` and `cpu.cfs_period_us`; a quota of -1 is none
+fn parse_cfs_quota(quota: &str, period: &str) -> Option<f64> {
+    let quota: f64 = quota.trim().parse().ok()?;
+    let period: f64 = period.trim().parse().ok()?;
+    (quota > 0.0 && period > 0.0).then(|| quota / period)
+}
+
+/// cgroup v1 `memory.limit_in_bytes`; huge values mean no limit
+fn parse_memory_limit(limit: &str) -> Option<u64> {
+    limit
+        .trim()
+        .parse()
+        .ok()
+        .filter(|bytes| *bytes < UNLIMITED_MEMORY)
+}
+
+/// Report `limits` and the values `config` resolves to, with the worker
+/// threads the runtime actually has
+pub fn report(limits: &Limits, config: &AppConfig, worker_threads: usize) -> ResourceReport {
+    let tuned = |value: u64, configured: bool| TunedValue { value, configured };
+    ResourceReport {
+        cgroup: limits.cgroup.map(str::to_string),
+        available_cpus: limits.available_cpus as u64,
+        cpu_quota: limits.cpu_quota,
+        memory_limit_bytes: limits.memory_bytes,
+        worker_threads: tuned(
+            worker_threads as u64,
+            config.server.worker_threads.is_some(),
+        ),
+        db_max_connections: tuned(
+            u64::from(config.database.max_connections()),
+            config.database.max_connections.is_some(),
+        ),
+        job_concurrency: tuned(
+            config.jobs.concurrency() as u64,
+            config.jobs.concurrency.is_some(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(cpus: usize, quota: Option<f64>, memory: Option<u64>) -> Limits {
+        Limits {
+            available_cpus: cpus,
+            cpu_quota: quota,
+            memory_bytes: memory,
+            cgroup: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_cgroup_files() {
+        assert_eq!(parse_cpu_max("150000 100000\n"), Some(1.5));
+        assert_eq!(parse_cpu_max("max 100000\n"), None);
+        assert_eq!(parse_memory_max("536870912\n"), Some(536870912));
+        assert_eq!(parse_memory_max("max\n"), None);
+        assert_eq!(parse_cfs_quota("200000\n", "100000\n"), Some(2.0));
+        assert_eq!(parse_cfs_quota("-1\n", "100000\n"), None);
+        assert_eq!(parse_memory_limit("1073741824\n"), Some(1073741824));
+        assert_eq!(parse_memory_limit("9223372036854771712\n"), None);
+    }
+
+    #[test]
+    fn test_detect_cgroup_v2_and_v1() {
+        let root = std::env::temp_dir().join(format!("axum-base-cgroup-{}", uuid::Uuid::new_v4()));
+
+        let v2 = root.join("v2");
+        fs::create_dir_all(&v2).unwrap();
+        fs::write(v2.join("cgroup.controllers"), "cpu memory").unwrap();
+        fs::write(v2.join("cpu.max"), "50000 100000").unwrap();
+        fs::write(v2.join("memory.max"), "268435456").unwrap();
+        assert_eq!(
+            Limits::detect_in(&v2, 16),
+            Limits {
+                available_cpus: 16,
+                cpu_quota: Some(0.5),
+                memory_bytes: Some(268435456),
+                cgroup: Some("v2"),
+            }
+        );
+
+        let v1 = root.join("v1");
+        fs::create_dir_all(v1.join("cpu")).unwrap();
+        fs::write(v1.join("cpu/cpu.cfs_quota_us"), "300000").unwrap();
+        fs::write(v1.join("cpu/cpu.cfs_period_us"), "100000").unwrap();
+        let detected = Limits::detect_in(&v1, 16);
+        assert_eq!(detected.cpu_quota, Some(3.0));
+        assert_eq!(detected.memory_bytes, None);
+        assert_eq!(detected.cgroup, Some("v1"));
+
+        assert_eq!(
+            Limits::detect_in(&root.join("none"), 4),
+            limits(4, None, None)
+        );
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_defaults_follow_the_limits() {
+        // Half a CPU of a big host counts as one
+        let small = limits(64, Some(0.5), Some(256 * 1024 * 1024));
+        assert_eq!(small.cpus(), 1);
+        assert_eq!(small.worker_threads(), 1);
+        assert_eq!(small.db_max_connections(), 5);
+        assert_eq!(small.job_concurrency(), 2);
+
+        let tight_memory = limits(64, Some(4.0), Some(200 * 1024 * 1024));
+        assert_eq!(tight_memory.db_max_connections(), 20);
+        assert_eq!(tight_memory.job_concurrency(), 1);
+
+        // Without a quota the available CPUs count
+        let host = limits(3, None, None);
+        assert_eq!(host.cpus(), 3);
+        assert_eq!(host.db_max_connections(), 15);
+        assert_eq!(host.job_concurrency(), 6);
+        assert_eq!(limits(2, Some(8.0), None).cpus(), 2);
+    }
+}
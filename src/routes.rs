@@ -3,59 +3,349 @@
 //! Configures all routes and middleware for the application.
 
 use axum::{
-    Router,
-    routing::{get, get_service, post},
+    Router, middleware,
+    routing::{get, post},
 };
-use sqlx::PgPool;
+use std::path::PathBuf;
 use tower::ServiceBuilder;
-use tower_http::services::ServeDir;
-use tower_sessions::{Expiry, SessionManagerLayer};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer};
+use tower_sessions::cookie::SameSite;
+use tower_sessions::{Expiry, SessionManagerLayer, SessionStore};
 use tower_sessions_sqlx_store::PostgresStore;
 
-use crate::api::{api_hello, health_check};
+use crate::activity;
+use crate::admin;
+use crate::api::{
+    api_hello, get_item, health_check, health_live, health_ready, list_categories, list_items,
+};
+use crate::attachments;
+use crate::audit;
+use crate::auth::ADMIN_ROLE;
+use crate::avatars;
+use crate::bounces;
+use crate::config::AppConfig;
+use crate::db_metrics;
+use crate::emails;
+use crate::flash;
+use crate::health;
+use crate::jobs;
+use crate::live_reload::{self, LiveReload, WATCHED_DIRS};
+use crate::metrics;
+use crate::nav::{self, Pages, RouteMeta, Section};
+use crate::notifications;
+use crate::openapi;
+use crate::output;
+use crate::page_cache;
+use crate::preferences;
+use crate::scope;
+use crate::search;
+use crate::snippets;
+use crate::sql_console;
+use crate::state::AppState;
+use crate::static_files;
+use crate::suggest;
+use crate::tenants;
+use crate::theming;
+use crate::timezone;
+use crate::uploads;
 use crate::web::{
-    handle_login, handle_logout, handle_profile_update, handler_404, serve_index, serve_landing,
-    serve_login, serve_profile,
+    self, handle_email_template, handle_email_template_revert, handle_login, handle_logout,
+    handle_logout_all, handle_notification_read, handle_notifications_read_all,
+    handle_profile_update, handle_sql_console, handle_unsubscribe, handler_404, serve_activity,
+    serve_audit_log, serve_email_template, serve_email_templates, serve_index,
+    serve_jobs_dashboard, serve_landing, serve_login, serve_notifications, serve_profile,
+    serve_sql_console, serve_unsubscribe,
 };
+use crate::well_known;
+use crate::ws;
+
+/// Outline person icon for the profile link
+const PROFILE_ICON: &str = "M16 7a4 4 0 11-8 0 4 4 0 018 0zM12 14a7 7 0 00-7 7h14a7 7 0 00-7-7z";
+
+/// Outline clock icon for the activity link
+const ACTIVITY_ICON: &str = "M12 8v4l3 3m6-3a9 9 0 11-18 0 9 9 0 0118 0z";
+
+/// Outline bell icon for the notifications link
+const NOTIFICATIONS_ICON: &str = "M15 17h5l-1.405-1.405A2.032 2.032 0 0118 14.158V11a6.002 6.002 0 00-4-5.659V5a2 2 0 10-4 0v.341C7.67 6.165 6 8.388 6 11v3.159c0 .538-.214 1.055-.595 1.436L4 17h5m6 0v1a3 3 0 11-6 0v-1m6 0H9";
 
 /// Creates the main application router with all routes and middleware
-pub async fn create_router(pool: PgPool) -> Router {
+pub async fn create_router(state: AppState) -> Router {
     // Create session store using the database
-    let session_store = PostgresStore::new(pool.clone());
+    let session_store = PostgresStore::new(state.pool.clone());
     if let Err(e) = session_store.migrate().await {
-        eprintln!("❌ Failed to migrate session store: {}", e);
+        output::error(format!("Failed to migrate session store: {}", e));
         std::process::exit(1);
     }
 
-    let session_layer = SessionManagerLayer::new(session_store)
-        .with_secure(false) // Set to true in production with HTTPS
-        .with_expiry(Expiry::OnInactivity(
-            tower_sessions::cookie::time::Duration::days(30),
-        )); // 30 days
+    let session_layer = session_layer(session_store, &state.config);
+
+    let well_known_routes = well_known::routes(&state.config);
+    let upload_routes = uploads::routes(&state.config.uploads);
+    let avatar_routes = avatars::routes(&state.config.uploads);
+    let attachment_routes = attachments::routes(&state.config.uploads);
+    let plugin_routes = state.plugins.routes();
+    let cached = middleware::from_fn_with_state(state.clone(), page_cache::serve);
+    let live_reload_enabled = state.config.live_reload_enabled();
+    let email_previews_enabled = state.config.email_previews_enabled();
+    let metrics_enabled = state.config.server.metrics;
 
-    Router::new()
-        // Root route serves the welcome page
-        .route("/", get(serve_index))
-        // Landing page route
-        .route("/landing", get(serve_landing))
+    // Pages and the navigation drawn from them
+    let (pages, nav_table) = Pages::new()
+        // Root route serves the welcome page, cached for signed-out visitors
+        .route("/", get(serve_index).layer(cached.clone()))
+        // Landing page route, cached the same way
+        .page(
+            "/landing",
+            get(serve_landing).layer(cached),
+            RouteMeta::new("Landing", Section::Main),
+        )
+        // Health check endpoint
+        .page(
+            "/health",
+            get(health_check),
+            RouteMeta::new("Health", Section::Main),
+        )
+        // Greeting, linked as a first API call to try
+        .page(
+            "/api/hello",
+            get(api_hello),
+            RouteMeta::new("API", Section::Main),
+        )
         // Authentication routes
         .route("/login", get(serve_login).post(handle_login))
         .route("/logout", post(handle_logout))
-        .route("/profile", get(serve_profile).post(handle_profile_update))
-        // Health check endpoint
-        .route("/health", get(health_check))
+        .route("/profile/logout-all", post(handle_logout_all))
+        .page(
+            "/profile/activity",
+            get(serve_activity),
+            RouteMeta::new("Activity", Section::Account)
+                .icon(ACTIVITY_ICON)
+                .signed_in(),
+        )
+        .page(
+            "/profile",
+            get(serve_profile).post(handle_profile_update),
+            RouteMeta::new("Edit Profile", Section::Account)
+                .icon(PROFILE_ICON)
+                .signed_in(),
+        )
+        // In-app notifications
+        .page(
+            "/notifications",
+            get(serve_notifications),
+            RouteMeta::new("Notifications", Section::Account)
+                .icon(NOTIFICATIONS_ICON)
+                .signed_in(),
+        )
+        .route(
+            "/notifications/read-all",
+            post(handle_notifications_read_all),
+        )
+        .route("/notifications/{id}/read", post(handle_notification_read))
+        // Email unsubscribe links, including RFC 8058 one-click POSTs
+        .route(
+            "/unsubscribe",
+            get(serve_unsubscribe).post(handle_unsubscribe),
+        )
+        // Read-only SQL console (admins only)
+        .page(
+            "/admin/sql",
+            get(serve_sql_console).post(handle_sql_console),
+            RouteMeta::new("SQL Console", Section::Admin).role(ADMIN_ROLE),
+        )
+        // Transactional email template editor (admins only)
+        .page(
+            "/admin/emails",
+            get(serve_email_templates),
+            RouteMeta::new("Emails", Section::Admin).role(ADMIN_ROLE),
+        )
+        .route(
+            "/admin/emails/{name}",
+            get(serve_email_template).post(handle_email_template),
+        )
+        .route(
+            "/admin/emails/{name}/revert",
+            post(handle_email_template_revert),
+        )
+        // Job queue and worker liveness (admins only)
+        .page(
+            "/admin/jobs",
+            get(serve_jobs_dashboard),
+            RouteMeta::new("Jobs", Section::Admin).role(ADMIN_ROLE),
+        )
+        // Audit log (admins only)
+        .page(
+            "/admin/audit",
+            get(serve_audit_log),
+            RouteMeta::new("Audit Log", Section::Admin).role(ADMIN_ROLE),
+        )
+        .into_parts();
+
+    let router = Router::new()
+        .merge(pages)
+        // Liveness and readiness probes
+        .route("/health/live", get(health_live))
+        .route("/health/ready", get(health_ready))
         // API routes
-        .route("/api/hello", get(api_hello))
-        // Serve static files from the static directory
-        .nest_service("/static", get_service(ServeDir::new("static")))
+        .route("/api/items", get(list_items))
+        .route("/api/items/{id}", get(get_item))
+        .route("/api/categories", get(list_categories))
+        // Search-as-you-type item titles
+        .merge(suggest::routes())
+        // Full-text item search
+        .merge(search::routes())
+        // The signed-in user's theme, language, time zone, and page size
+        .merge(preferences::routes())
+        // The signed-in user's notifications
+        .merge(notifications::routes())
+        // The signed-in user's account activity
+        .merge(activity::routes())
+        // OpenAPI document and Swagger UI
+        .merge(openapi::routes())
+        // Admin user management (requires the admin role)
+        .merge(admin::routes())
+        // Audit log of sign-ins and admin changes
+        .merge(audit::routes())
+        .merge(sql_console::routes())
+        // Tenant branding (colors, logo, partial overrides)
+        .merge(tenants::routes())
+        // Admin-written snippets rendered in a sandbox
+        .merge(snippets::routes())
+        // Bounce/complaint webhooks, email outbox and suppressions
+        .merge(bounces::routes())
+        // Background job queue and worker liveness
+        .merge(jobs::routes())
+        // Readiness probe history and flap detection
+        .merge(health::routes())
+        // security.txt, change-password redirect, optional WebFinger
+        .merge(well_known_routes)
+        // File uploads and downloads for signed-in users
+        .merge(upload_routes)
+        // Profile pictures: uploaded from /profile, served publicly
+        .merge(avatar_routes)
+        // Files attached to items, downloaded by whoever can see the item
+        .merge(attachment_routes)
+        // WebSocket push to signed-in browsers
+        .merge(ws::routes());
+
+    // ActivityPub actors and outboxes when built with the feature
+    #[cfg(feature = "activitypub")]
+    let router = router.merge(crate::activitypub::routes());
+
+    // GraphQL next to the REST API when built with the feature
+    #[cfg(feature = "graphql")]
+    let router = router.merge(crate::graphql::routes(&state.config));
+
+    // Single sign-on through the identity provider when built and enabled
+    #[cfg(feature = "sso")]
+    let router = if state.config.sso.enabled {
+        router.merge(crate::sso::routes(&state.config))
+    } else {
+        router
+    };
+
+    // Routes contributed by plugins
+    let router = router.merge(plugin_routes);
+
+    // Bundled emails rendered with sample values (development)
+    let router = if email_previews_enabled {
+        router.merge(emails::preview_routes())
+    } else {
+        router
+    };
+
+    // Prometheus scrape endpoint
+    let router = if metrics_enabled {
+        router.merge(metrics::routes())
+    } else {
+        router
+    };
+
+    let router = router
+        // Load flash messages, the user's time zone and preferences, and
+        // their links for pages, and start the request scope; static files
+        // skip the session lookup
+        .layer(middleware::from_fn(flash::track))
+        .layer(middleware::from_fn(timezone::track))
+        .layer(middleware::from_fn(preferences::track))
+        .layer(middleware::from_fn(notifications::track))
+        .layer(middleware::from_fn_with_state(nav_table, nav::track))
+        .layer(middleware::from_fn_with_state(state.clone(), scope::track))
+        // Time the request's service queries into /metrics
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            db_metrics::track,
+        ))
+        // Static files with Cache-Control, ETags, and precompressed variants
+        .nest_service(
+            "/static",
+            static_files::service(static_files::STATIC_DIR, &state.config),
+        )
         // 404 fallback for any other routes
         .fallback(handler_404)
+        // Brand pages for the tenant named by the request's subdomain
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            theming::resolve_theme,
+        ));
+
+    // Apps built around their own template engine, such as in tests, render
+    // with it instead of the one installed at startup
+    let router = if web::is_installed(&state.templates) {
+        router
+    } else {
+        router.layer(middleware::from_fn_with_state(
+            state.templates.clone(),
+            web::scope_templates,
+        ))
+    };
+
+    // Reload the browser when templates or static files change (development)
+    let router = if live_reload_enabled {
+        let watcher = LiveReload::watch(WATCHED_DIRS.iter().map(PathBuf::from).collect());
+        router
+            .merge(watcher.routes())
+            .layer(middleware::map_response(live_reload::inject_script))
+    } else {
+        router
+    };
+
+    router
         // Add middleware for sessions, error handling and logging
         .layer(
             ServiceBuilder::new()
+                // Tag every request with an x-request-id (echoed in API envelopes)
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(PropagateRequestIdLayer::x_request_id())
                 .layer(tower_http::trace::TraceLayer::new_for_http())
                 .layer(tower_http::cors::CorsLayer::permissive())
                 .layer(session_layer),
         )
-        .with_state(pool)
+        .with_state(state)
+}
+
+/// Session cookies with the configured attributes, kept for 30 days of
+/// inactivity
+fn session_layer<Store: SessionStore>(
+    store: Store,
+    config: &AppConfig,
+) -> SessionManagerLayer<Store> {
+    let session = &config.session;
+    let same_site = match session.same_site.as_str() {
+        "lax" => SameSite::Lax,
+        "none" => SameSite::None,
+        _ => SameSite::Strict,
+    };
+    let layer = SessionManagerLayer::new(store)
+        .with_name(session.cookie_name.clone())
+        .with_secure(config.secure_cookies())
+        .with_same_site(same_site)
+        .with_path(session.path.clone())
+        .with_expiry(Expiry::OnInactivity(
+            tower_sessions::cookie::time::Duration::days(30),
+        ));
+    match &session.domain {
+        Some(domain) => layer.with_domain(domain.clone()),
+        None => layer,
+    }
 }
@@ -0,0 +1,171 @@
+//! # S3 Storage
+//!
+//! Upload storage in an S3-compatible bucket, compiled only with the `s3`
+//! feature and selected with `UPLOAD_STORAGE=s3`. Works with AWS S3 and with
+//! services speaking its API (MinIO, Cloudflare R2, Backblaze B2, ...) by
+//! pointing `S3_ENDPOINT` at them. Deployments without a persistent disk can
+//! keep uploads and avatars here instead of in `UPLOAD_DIR`.
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use s3::creds::Credentials;
+use s3::{Bucket, Region};
+
+use crate::config::UploadsConfig;
+use crate::error::{AppError, AppResult};
+use crate::uploads::{self, StorageBackend};
+
+/// Key looked up by the health check; it is never written
+const HEALTH_KEY: &str = "health-check";
+
+/// Objects in one bucket, keyed by the storage key
+pub struct S3Storage {
+    bucket: Box<Bucket>,
+}
+
+impl S3Storage {
+    /// Connect to the bucket described by the `S3_*` settings
+    pub fn new(config: &UploadsConfig) -> Result<Self, String> {
+        let bucket_name = config.s3_bucket.as_deref().ok_or("S3_BUCKET must be set")?;
+        let region = Region::Custom {
+            region: config.s3_region.clone(),
+            endpoint: match &config.s3_endpoint {
+                Some(endpoint) => endpoint.trim_end_matches('/').to_string(),
+                None => format!("https://s3.{}.amazonaws.com", config.s3_region),
+            },
+        };
+        let credentials = Credentials::new(
+            config.s3_access_key_id.as_deref(),
+            config.s3_secret_access_key.as_deref(),
+            None,
+            None,
+            None,
+        )
+        .map_err(|e| format!("invalid S3 credentials: {}", e))?;
+
+        let bucket = Bucket::new(bucket_name, region, credentials)
+            .map_err(|e| format!("invalid S3 bucket '{}': {}", bucket_name, e))?;
+        let bucket = if config.s3_path_style {
+            bucket.with_path_style()
+        } else {
+            bucket
+        };
+        Ok(Self { bucket })
+    }
+
+    fn check_key(key: &str) -> AppResult<()> {
+        if uploads::is_valid_key(key) {
+            Ok(())
+        } else {
+            Err(AppError::Internal(format!("invalid storage key '{}'", key)))
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Storage {
+    fn name(&self) -> &'static str {
+        "S3"
+    }
+
+    async fn put(&self, key: &str, data: Bytes) -> AppResult<()> {
+        Self::check_key(key)?;
+        let response = self
+            .bucket
+            .put_object(key, &data)
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        match response.status_code() {
+            200..=299 => Ok(()),
+            status => Err(status_error(key, status)),
+        }
+    }
+
+    async fn get(&self, key: &str) -> AppResult<Bytes> {
+        Self::check_key(key)?;
+        let response = self
+            .bucket
+            .get_object(key)
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        match response.status_code() {
+            200..=299 => Ok(response.bytes().clone()),
+            404 => Err(AppError::NotFound("File contents are missing".to_string())),
+            status => Err(status_error(key, status)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        Self::check_key(key)?;
+        let response = self
+            .bucket
+            .delete_object(key)
+            .await
+            .map_err(|e| s3_error(key, e))?;
+        match response.status_code() {
+            200..=299 | 404 => Ok(()),
+            status => Err(status_error(key, status)),
+        }
+    }
+
+    async fn check(&self) -> AppResult<()> {
+        // Any answer but an error means the bucket is reachable and the
+        // credentials work; the key need not exist
+        let (_, status) = self
+            .bucket
+            .head_object(HEALTH_KEY)
+            .await
+            .map_err(|e| s3_error(HEALTH_KEY, e))?;
+        match status {
+            200..=299 | 404 => Ok(()),
+            status => Err(status_error(HEALTH_KEY, status)),
+        }
+    }
+}
+
+fn s3_error(key: &str, err: s3::error::S3Error) -> AppError {
+    AppError::Internal(format!("S3 error for {}: {}", key, err))
+}
+
+fn status_error(key: &str, status: u16) -> AppError {
+    AppError::Internal(format!("S3 returned HTTP {} for {}", status, key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> UploadsConfig {
+        UploadsConfig {
+            storage: "s3".to_string(),
+            s3_bucket: Some("uploads".to_string()),
+            s3_access_key_id: Some("access".to_string()),
+            s3_secret_access_key: Some("secret".to_string()),
+            ..UploadsConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_bucket_url() {
+        let storage = S3Storage::new(&config()).unwrap();
+        assert_eq!(
+            storage.bucket.url(),
+            "https://uploads.s3.us-east-1.amazonaws.com"
+        );
+
+        let mut config = config();
+        config.s3_endpoint = Some("http://localhost:9000/".to_string());
+        config.s3_path_style = true;
+        let storage = S3Storage::new(&config).unwrap();
+        assert_eq!(storage.bucket.url(), "http://localhost:9000/uploads");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_keys() {
+        let storage = S3Storage::new(&config()).unwrap();
+        assert!(matches!(
+            storage.get("../secrets").await,
+            Err(AppError::Internal(_))
+        ));
+    }
+}
@@ -0,0 +1,303 @@
+//! # Scheduler
+//!
+//! Runs [`ScheduledTask`]s every so often while the server is up: the
+//! built-in [`core_tasks`], then those of plugins and the app (see
+//! [`Plugin::scheduled_tasks`](crate::plugins::Plugin::scheduled_tasks) and
+//! [`PluginRegistry::with_task`](crate::plugins::PluginRegistry::with_task)).
+//!
+//! Each task runs on its own timer. A run that takes longer than the period
+//! delays the next one instead of overlapping it, and a failed run is logged
+//! and tried again next period. Every instance runs every task, so tasks
+//! must be safe to run concurrently across instances.
+//!
+//! [`SchedulerMetrics`] reports each task's runs and how late its last tick
+//! fired at `/metrics`; drift that keeps growing means the runtime is
+//! starved or a task hogs its thread.
+
+use sqlx::PgPool;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time::{Instant, MissedTickBehavior};
+use tower_sessions::session_store::ExpiredDeletion;
+use tower_sessions_sqlx_store::PostgresStore;
+
+use crate::auth::AuthService;
+use crate::db_metrics;
+use crate::health;
+use crate::metrics::{CounterVec, GaugeVec, HistogramVec, MetricSource};
+use crate::output;
+use crate::services::{AuditService, EmailDeliveryService, JobService};
+use crate::state::AppState;
+
+/// How often expired sessions are deleted
+pub const SESSION_CLEANUP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// How often rows past their retention are deleted
+pub const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// What a scheduled task returns; errors are logged
+pub type TaskFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+type Task = Arc<dyn Fn(AppState) -> TaskFuture + Send + Sync>;
+
+/// A task run every `every`
+#[derive(Clone)]
+pub struct ScheduledTask {
+    pub name: &'static str,
+    pub every: Duration,
+    run: Task,
+}
+
+#[allow(dead_code)]
+impl ScheduledTask {
+    /// Run `task` every `every`, the first time one period after startup
+    pub fn new<F, Fut>(name: &'static str, every: Duration, task: F) -> Self
+    where
+        F: Fn(AppState) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        Self {
+            name,
+            every,
+            run: Arc::new(move |state| Box::pin(task(state))),
+        }
+    }
+
+    /// Run the task once now
+    pub async fn run(&self, state: AppState) -> Result<(), String> {
+        (self.run)(state).await
+    }
+}
+
+impl std::fmt::Debug for ScheduledTask {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ScheduledTask")
+            .field("name", &self.name)
+            .field("every", &self.every)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Housekeeping every server runs
+pub fn core_tasks() -> Vec<ScheduledTask> {
+    vec![
+        ScheduledTask::new(
+            "session-cleanup",
+            SESSION_CLEANUP_INTERVAL,
+            delete_expired_sessions,
+        ),
+        ScheduledTask::new("retention-cleanup", RETENTION_INTERVAL, delete_retired_rows),
+        ScheduledTask::new("health-probe", health::PROBE_INTERVAL, health::record_probe),
+    ]
+}
+
+/// Tables that grow with traffic and are kept in check by the core tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Retained {
+    Sessions,
+    UserSessions,
+    Jobs,
+    EmailOutbox,
+    AuditLog,
+}
+
+impl Retained {
+    pub const ALL: [Retained; 5] = [
+        Retained::Sessions,
+        Retained::UserSessions,
+        Retained::Jobs,
+        Retained::EmailOutbox,
+        Retained::AuditLog,
+    ];
+
+    /// Label used in metrics
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Retained::Sessions => "sessions",
+            Retained::UserSessions => "user_sessions",
+            Retained::Jobs => "jobs",
+            Retained::EmailOutbox => "email_outbox",
+            Retained::AuditLog => "audit_log",
+        }
+    }
+
+    fn table(self) -> &'static str {
+        match self {
+            Retained::Sessions => "tower_sessions.session",
+            other => other.as_str(),
+        }
+    }
+
+    /// Rows in the table now
+    pub async fn count(self, pool: &PgPool) -> Result<u64, sqlx::Error> {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", self.table()))
+            .fetch_one(pool)
+            .await?;
+        Ok(count as u64)
+    }
+}
+
+/// Delete sessions past their expiry. The session layer ignores them on
+/// read, but without this they stay in the table forever.
+pub async fn delete_expired_sessions(state: AppState) -> Result<(), String> {
+    let pool = &state.pool;
+    // The store does not report what it deleted; sessions created meanwhile
+    // make the count low
+    let before = Retained::Sessions
+        .count(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    PostgresStore::new(pool.clone())
+        .delete_expired()
+        .await
+        .map_err(|e| e.to_string())?;
+    let after = Retained::Sessions
+        .count(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    state
+        .metrics
+        .pruned(Retained::Sessions, before.saturating_sub(after), after);
+
+    let pruned = AuthService::prune_sessions(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    record(&state, Retained::UserSessions, pruned).await
+}
+
+/// Delete finished jobs, outbox entries, and audit log entries older than
+/// their [retention](crate::config::RetentionConfig)
+pub async fn delete_retired_rows(state: AppState) -> Result<(), String> {
+    let pool = &state.pool;
+    let retention = &state.config.retention;
+    if retention.jobs_days > 0 {
+        let pruned = JobService::delete_finished(pool, retention.jobs_days)
+            .await
+            .map_err(|e| e.to_string())?;
+        record(&state, Retained::Jobs, pruned).await?;
+    }
+    if retention.outbox_days > 0 {
+        let pruned = EmailDeliveryService::delete_outbox_older_than(pool, retention.outbox_days)
+            .await
+            .map_err(|e| e.to_string())?;
+        record(&state, Retained::EmailOutbox, pruned).await?;
+    }
+    if retention.audit_days > 0 {
+        let pruned = AuditService::delete_older_than(pool, retention.audit_days)
+            .await
+            .map_err(|e| e.to_string())?;
+        record(&state, Retained::AuditLog, pruned).await?;
+    }
+    Ok(())
+}
+
+/// Count `pruned` rows of `table` and the rows left in it
+async fn record(state: &AppState, table: Retained, pruned: u64) -> Result<(), String> {
+    let rows = table.count(&state.pool).await.map_err(|e| e.to_string())?;
+    state.metrics.pruned(table, pruned, rows);
+    Ok(())
+}
+
+/// Run `tasks` until `shutdown` turns true. A run in progress is allowed to
+/// finish.
+pub fn spawn(
+    tasks: impl IntoIterator<Item = ScheduledTask>,
+    state: &AppState,
+    shutdown: watch::Receiver<bool>,
+) -> JoinSet<()> {
+    let mut running = JoinSet::new();
+    for task in tasks {
+        running.spawn(run_every(task, state.clone(), shutdown.clone()));
+    }
+    running
+}
+
+/// Runs of scheduled tasks on this instance, served at `/metrics`
+#[derive(Debug)]
+pub struct SchedulerMetrics {
+    /// Runs by task and outcome, `ok` or `failed`
+    pub runs: CounterVec,
+    /// How long after it was due each task's last tick fired
+    pub drift: GaugeVec,
+    /// Time runs took, by task
+    pub duration: HistogramVec,
+}
+
+impl Default for SchedulerMetrics {
+    fn default() -> Self {
+        Self {
+            runs: CounterVec::new(
+                "scheduled_task_runs_total",
+                "Scheduled task runs by outcome",
+                &["task", "outcome"],
+            ),
+            drift: GaugeVec::new(
+                "scheduled_task_drift_seconds",
+                "How long after it was due the last tick of a task fired",
+                &["task"],
+            ),
+            duration: HistogramVec::new(
+                "scheduled_task_duration_seconds",
+                "Time scheduled task runs took",
+                &["task"],
+            ),
+        }
+    }
+}
+
+impl MetricSource for SchedulerMetrics {
+    fn render(&self, out: &mut String) {
+        self.runs.render(out);
+        self.drift.render(out);
+        self.duration.render(out);
+    }
+}
+
+/// Run `task` every period until shutdown
+async fn run_every(task: ScheduledTask, state: AppState, mut shutdown: watch::Receiver<bool>) {
+    let metrics = state.metrics.source::<SchedulerMetrics>();
+    // Scraped as zero until the first run
+    metrics.runs.add(&[task.name, "ok"], 0);
+    metrics.runs.add(&[task.name, "failed"], 0);
+    let mut interval = tokio::time::interval_at(Instant::now() + task.every, task.every);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        let due = tokio::select! {
+            _ = shutdown.wait_for(|stop| *stop) => return,
+            due = interval.tick() => due,
+        };
+        let started = Instant::now();
+        let drift = started.saturating_duration_since(due);
+        metrics.drift.set(&[task.name], drift.as_secs_f64());
+        let outcome = match db_metrics::scope(&state, task.run(state.clone())).await {
+            Ok(()) => "ok",
+            Err(e) => {
+                output::error(format!("Scheduled task {} failed: {}", task.name, e));
+                "failed"
+            }
+        };
+        metrics.runs.inc(&[task.name, outcome]);
+        metrics
+            .duration
+            .observe(&[task.name], started.elapsed().as_secs_f64());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_core_tasks() {
+        let tasks = core_tasks();
+        assert_eq!(tasks.len(), 3);
+        assert_eq!(tasks[0].name, "session-cleanup");
+        assert_eq!(tasks[0].every, SESSION_CLEANUP_INTERVAL);
+        assert_eq!(tasks[1].name, "retention-cleanup");
+        assert_eq!(tasks[2].name, "health-probe");
+    }
+}
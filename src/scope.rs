@@ -0,0 +1,316 @@
+//! # Request Scope
+//!
+//! Services built at most once per request and shared by every extractor,
+//! middleware, and handler that asks for them. A handler takes a
+//! [`RequestScope`] instead of threading the session, pool, request ID, and
+//! tenant through as separate arguments:
+//!
+//! ```rust,ignore
+//! pub async fn serve_page(scope: RequestScope) -> AppResult<Html<String>> {
+//!     let user = scope.user().await;
+//!     let locale = scope.locale().await;
+//!     let stats = scope.measure("db", Stats::load(scope.pool())).await?;
+//!     // ...
+//! }
+//! ```
+//!
+//! The signed-in user, their roles, and the locale are looked up on first
+//! use and cached for the rest of the request. Other services implement
+//! [`Provide`] and are built the same way, through [`RequestScope::get`] or
+//! the [`Scoped`] extractor. The [`track`] middleware creates the scope when
+//! the request arrives so its clock starts there, and in development reports
+//! the [`measure`](RequestScope::measure)d spans in a `Server-Timing` header.
+//! Without the middleware the first extractor creates it.
+
+use async_trait::async_trait;
+use axum::{
+    extract::{FromRef, FromRequestParts, Request, State},
+    http::{HeaderMap, HeaderValue, header, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use sqlx::PgPool;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::OnceCell;
+use tower_sessions::Session;
+
+use crate::api::REQUEST_ID_HEADER;
+use crate::auth::USER_SESSION_KEY;
+use crate::config::{AppConfig, Environment};
+use crate::error::{AppError, AppResult};
+use crate::i18n;
+use crate::models::AuthenticatedUser;
+use crate::services::UserService;
+use crate::state::AppState;
+use crate::theming::{self, Theme};
+
+/// A service built once per request from its [`RequestScope`]
+#[async_trait]
+pub trait Provide: Send + Sync + Sized + 'static {
+    async fn provide(scope: &RequestScope) -> AppResult<Self>;
+}
+
+type Slot = Arc<OnceCell<Arc<dyn Any + Send + Sync>>>;
+
+/// Per-request services; cloning shares them
+#[derive(Clone)]
+pub struct RequestScope(Arc<Inner>);
+
+struct Inner {
+    state: AppState,
+    session: Option<Session>,
+    request_id: Option<String>,
+    accept_language: Option<String>,
+    theme: Option<Arc<Theme>>,
+    started: Instant,
+    user: OnceCell<Option<AuthenticatedUser>>,
+    roles: OnceCell<Vec<String>>,
+    locale: OnceCell<&'static str>,
+    timings: Mutex<Vec<(&'static str, Duration)>>,
+    services: Mutex<HashMap<TypeId, Slot>>,
+}
+
+#[allow(dead_code)]
+impl RequestScope {
+    /// Scope for a request with these headers and session
+    fn new(state: AppState, headers: &HeaderMap, session: Option<Session>) -> Self {
+        let value = |name: &str| {
+            headers
+                .get(name)
+                .and_then(|header| header.to_str().ok())
+                .map(str::to_string)
+        };
+        Self(Arc::new(Inner {
+            state,
+            session,
+            request_id: value(REQUEST_ID_HEADER),
+            accept_language: value(header::ACCEPT_LANGUAGE.as_str()),
+            theme: theming::current(),
+            started: Instant::now(),
+            user: OnceCell::new(),
+            roles: OnceCell::new(),
+            locale: OnceCell::new(),
+            timings: Mutex::new(Vec::new()),
+            services: Mutex::new(HashMap::new()),
+        }))
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.0.state
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.0.state.pool
+    }
+
+    pub fn config(&self) -> &AppConfig {
+        &self.0.state.config
+    }
+
+    /// The session, when the request went through the session layer
+    pub fn session(&self) -> Option<&Session> {
+        self.0.session.as_ref()
+    }
+
+    /// ID assigned by the request-id middleware
+    pub fn request_id(&self) -> Option<&str> {
+        self.0.request_id.as_deref()
+    }
+
+    /// Branding of the tenant the request is for, if any
+    pub fn tenant(&self) -> Option<&Theme> {
+        self.0.theme.as_deref()
+    }
+
+    /// Time since the request arrived
+    pub fn elapsed(&self) -> Duration {
+        self.0.started.elapsed()
+    }
+
+    /// The signed-in user, as stored in the session at login
+    pub async fn user(&self) -> Option<&AuthenticatedUser> {
+        self.0
+            .user
+            .get_or_init(|| async {
+                let session = self.0.session.as_ref()?;
+                session.get(USER_SESSION_KEY).await.ok().flatten()
+            })
+            .await
+            .as_ref()
+    }
+
+    /// Roles of the signed-in user, read from the database so changes apply
+    /// without signing in again; none when signed out
+    pub async fn roles(&self) -> &[String] {
+        self.0
+            .roles
+            .get_or_init(|| async {
+                match self.user().await {
+                    Some(user) => UserService::get_user_roles(self.pool(), user.id)
+                        .await
+                        .unwrap_or_default(),
+                    None => Vec::new(),
+                }
+            })
+            .await
+    }
+
+    /// Catalog locale for the request: the user's choice when signed in,
+    /// otherwise the browser's `Accept-Language`
+    pub async fn locale(&self) -> &'static str {
+        self.0
+            .locale
+            .get_or_init(|| async {
+                let chosen = match self.user().await {
+                    Some(user) => UserService::get_locale(self.pool(), user.id)
+                        .await
+                        .unwrap_or_default(),
+                    None => None,
+                };
+                let wanted = chosen.or_else(|| {
+                    self.0
+                        .accept_language
+                        .as_deref()
+                        .and_then(preferred_language)
+                });
+                i18n::resolve(wanted.as_deref())
+            })
+            .await
+    }
+
+    /// The request's `T`, built by [`Provide::provide`] on first use. A
+    /// failed build is not cached.
+    pub async fn get<T: Provide>(&self) -> AppResult<Arc<T>> {
+        let slot = self
+            .0
+            .services
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .clone();
+        let service = slot
+            .get_or_try_init(|| async {
+                let service: Arc<dyn Any + Send + Sync> = Arc::new(T::provide(self).await?);
+                Ok::<_, AppError>(service)
+            })
+            .await?;
+        Ok(service
+            .clone()
+            .downcast::<T>()
+            .expect("slots are keyed by the type they hold"))
+    }
+
+    /// Await `future`, recording how long it took under `name`
+    pub async fn measure<F: Future>(&self, name: &'static str, future: F) -> F::Output {
+        let started = Instant::now();
+        let output = future.await;
+        self.0
+            .timings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((name, started.elapsed()));
+        output
+    }
+
+    /// Spans recorded by [`measure`](Self::measure), in order
+    pub fn timings(&self) -> Vec<(&'static str, Duration)> {
+        self.0
+            .timings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// `Server-Timing` value with the recorded spans and the total so far
+    pub fn server_timing(&self) -> String {
+        self.timings()
+            .into_iter()
+            .chain([("total", self.elapsed())])
+            .map(|(name, duration)| format!("{};dur={:.1}", name, duration.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl<S> FromRequestParts<S> for RequestScope
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(scope) = parts.extensions.get::<RequestScope>() {
+            return Ok(scope.clone());
+        }
+        let session = parts.extensions.get::<Session>().cloned();
+        let scope = RequestScope::new(AppState::from_ref(state), &parts.headers, session);
+        parts.extensions.insert(scope.clone());
+        Ok(scope)
+    }
+}
+
+/// Extractor for a [`Provide`]d service of the request
+#[allow(dead_code)]
+pub struct Scoped<T>(pub Arc<T>);
+
+impl<S, T> FromRequestParts<S> for Scoped<T>
+where
+    AppState: FromRef<S>,
+    S: Send + Sync,
+    T: Provide,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let scope = RequestScope::from_request_parts(parts, state).await?;
+        Ok(Scoped(scope.get::<T>().await?))
+    }
+}
+
+/// Middleware creating the request's scope when it arrives. Must run inside
+/// the session layer and the tenant resolution.
+pub async fn track(State(state): State<AppState>, mut request: Request, next: Next) -> Response {
+    let session = request.extensions().get::<Session>().cloned();
+    let development = state.config.environment == Environment::Development;
+    let scope = RequestScope::new(state, request.headers(), session);
+    request.extensions_mut().insert(scope.clone());
+
+    let mut response = next.run(request).await;
+    if development && let Ok(value) = HeaderValue::from_str(&scope.server_timing()) {
+        response.headers_mut().insert("server-timing", value);
+    }
+    response
+}
+
+/// First language tag of an `Accept-Language` header; browsers list the
+/// preferred one first
+fn preferred_language(header: &str) -> Option<String> {
+    header
+        .split(',')
+        .filter_map(|item| item.split(';').next())
+        .map(str::trim)
+        .find(|tag| *tag != "*")
+        .and_then(i18n::normalize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preferred_language() {
+        assert_eq!(
+            preferred_language("de-AT,de;q=0.9,en;q=0.8").as_deref(),
+            Some("de-AT")
+        );
+        assert_eq!(preferred_language(" es ").as_deref(), Some("es"));
+        assert_eq!(preferred_language("*, fr;q=0.5").as_deref(), Some("fr"));
+        assert_eq!(preferred_language(""), None);
+    }
+}
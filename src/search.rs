@@ -0,0 +1,126 @@
+//! # Item Search
+//!
+//! `GET /api/items/search?q=` finds visible items by the words in their
+//! title and description, using the `search_vector` column and its GIN index
+//! (migration 0029). `q` is read like a web search box: `"quoted phrases"`,
+//! `or`, and `-excluded` words work, and nothing the user types is a syntax
+//! error. Titles weigh more than descriptions; results come most relevant
+//! first.
+//!
+//! Each hit carries its title and a snippet of its description with the
+//! matched words in `<mark>`. Postgres wraps them in private-use characters
+//! ([`MARK_START`], [`MARK_END`]) and [`highlight`] escapes the rest as HTML
+//! before swapping those for tags, so item text cannot inject markup.
+//! [`suggest`](crate::suggest) stays the cheaper choice for typeahead.
+
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+
+use crate::api::RequestId;
+use crate::database::Db;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::models::{Envelope, ItemSearchFilters, ItemSearchHit, ItemSearchQuery};
+use crate::pagination::{Pagination, PaginationQuery};
+use crate::services::ItemService;
+use crate::state::AppState;
+
+/// Most characters of a query
+pub const MAX_QUERY_CHARS: usize = 200;
+
+/// Put before each matched word by `ts_headline`
+pub const MARK_START: char = '\u{E000}';
+
+/// Put after each matched word by `ts_headline`
+pub const MARK_END: char = '\u{E001}';
+
+/// `ts_headline` options for titles: the whole title, every match marked
+pub fn title_headline_options() -> String {
+    format!(
+        "StartSel=\"{}\", StopSel=\"{}\", HighlightAll=true",
+        MARK_START, MARK_END
+    )
+}
+
+/// `ts_headline` options for descriptions: up to two short passages
+pub fn snippet_headline_options() -> String {
+    format!(
+        "StartSel=\"{}\", StopSel=\"{}\", MaxFragments=2, MinWords=10, MaxWords=30, \
+         FragmentDelimiter=\" … \"",
+        MARK_START, MARK_END
+    )
+}
+
+/// HTML for headline text: escaped, with the marked words in `<mark>`
+pub fn highlight(text: &str) -> String {
+    tera::escape_html(text)
+        .replace(MARK_START, "<mark>")
+        .replace(MARK_END, "</mark>")
+}
+
+/// `/api/items/search`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/items/search", get(search_items))
+}
+
+/// Visible items matching `q`, most relevant first
+#[utoipa::path(
+    get,
+    path = "/api/items/search",
+    tag = "api",
+    params(ItemSearchQuery, PaginationQuery),
+    responses(
+        (status = 200, description = "One page of matching items", body = Envelope<Vec<ItemSearchHit>>),
+        (status = 400, description = "Missing or overlong query, or invalid pagination", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn search_items(
+    State(db): State<Db>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(query): Query<ItemSearchQuery>,
+    pagination: Pagination,
+) -> AppResult<Negotiated<Envelope<Vec<ItemSearchHit>>>> {
+    let text = query.q.trim();
+    if text.is_empty() {
+        return Err(AppError::Validation("q is required".to_string()));
+    }
+    if text.chars().count() > MAX_QUERY_CHARS {
+        return Err(AppError::Validation(format!(
+            "q must be at most {} characters",
+            MAX_QUERY_CHARS
+        )));
+    }
+
+    let filters = ItemSearchFilters {
+        category_id: query.category_id,
+    };
+    let page = ItemService::search(db.read(), text, &filters, &pagination).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::paginated(page).with_request_id(request_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight_escapes_and_marks() {
+        let text = format!("{}Rust{} & <script>", MARK_START, MARK_END);
+        assert_eq!(highlight(&text), "<mark>Rust</mark> &amp; &lt;script&gt;");
+        assert_eq!(highlight("plain"), "plain");
+    }
+
+    #[test]
+    fn test_headline_options_name_the_marks() {
+        for options in [title_headline_options(), snippet_headline_options()] {
+            assert!(options.contains(&format!("StartSel=\"{}\"", MARK_START)));
+            assert!(options.contains(&format!("StopSel=\"{}\"", MARK_END)));
+        }
+    }
+}
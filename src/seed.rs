@@ -0,0 +1,277 @@
+//! # Development Fixtures
+//!
+//! Demo users, categories, and items that make a fresh checkout usable
+//! right away, loaded by the `seed` binary from [`DEFAULT_FIXTURES`] or a
+//! TOML file given to it:
+//!
+//! ```toml
+//! [[users]]
+//! username = "alice"
+//! email = "alice@example.com"
+//! password = "alice-password"
+//! roles = ["admin"]
+//!
+//! [[categories]]
+//! name = "news"
+//! display_name = "News"
+//!
+//! [[items]]
+//! title = "Hello"
+//! category = "news"
+//! data = { pinned = true }
+//! ```
+//!
+//! Seeding only adds what is missing, so it can run again after the
+//! fixtures grow: users are matched by username, categories by name, and
+//! items by title within their category. Existing rows are never changed.
+
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt;
+use std::path::Path;
+
+use crate::auth::{MIN_PASSWORD_LENGTH, PasswordService};
+use crate::error::{AppError, AppResult};
+
+/// Fixtures loaded when no file is given
+pub const DEFAULT_FIXTURES: &str = "fixtures/dev.toml";
+
+/// Everything a fixtures file declares
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Fixtures {
+    #[serde(default)]
+    pub users: Vec<UserFixture>,
+    #[serde(default)]
+    pub categories: Vec<CategoryFixture>,
+    #[serde(default)]
+    pub items: Vec<ItemFixture>,
+}
+
+/// A user with a plain-text password, hashed when inserted
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserFixture {
+    pub username: String,
+    pub email: String,
+    pub password: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryFixture {
+    pub name: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub display_order: i32,
+}
+
+/// An item in the category named `category`, declared in the fixtures or
+/// already in the database
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ItemFixture {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub category: String,
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+impl Fixtures {
+    /// Read and check the fixtures file at `path`
+    pub fn load(path: &Path) -> AppResult<Self> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| AppError::NotFound(format!("Failed to read {}: {}", path.display(), e)))?;
+        Self::parse(&source)
+            .map_err(|message| AppError::Validation(format!("{}: {}", path.display(), message)))
+    }
+
+    /// Parse fixtures from TOML and check what can be checked without the
+    /// database
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let fixtures: Self = toml::from_str(source).map_err(|e| e.to_string())?;
+        for user in &fixtures.users {
+            if user.username.trim().is_empty() || !user.email.contains('@') {
+                return Err(format!(
+                    "user '{}' needs a username and an email address",
+                    user.username
+                ));
+            }
+            if user.password.len() < MIN_PASSWORD_LENGTH {
+                return Err(format!(
+                    "the password of user '{}' must be at least {} characters",
+                    user.username, MIN_PASSWORD_LENGTH
+                ));
+            }
+        }
+        Ok(fixtures)
+    }
+}
+
+/// How many rows of one kind were added and how many were already there
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub created: usize,
+    pub existing: usize,
+}
+
+impl Counts {
+    fn record(&mut self, created: bool) {
+        if created {
+            self.created += 1;
+        } else {
+            self.existing += 1;
+        }
+    }
+}
+
+impl fmt::Display for Counts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} created, {} already present",
+            self.created, self.existing
+        )
+    }
+}
+
+/// What [`seed`] did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    pub users: Counts,
+    pub categories: Counts,
+    pub items: Counts,
+}
+
+/// Insert the fixtures that are missing, all or nothing
+pub async fn seed(pool: &PgPool, fixtures: &Fixtures) -> AppResult<SeedReport> {
+    let mut tx = pool.begin().await?;
+    let mut report = SeedReport::default();
+
+    for category in &fixtures.categories {
+        let result = sqlx::query(
+            "INSERT INTO category (category_name, display_name, display_order)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (category_name) DO NOTHING",
+        )
+        .bind(&category.name)
+        .bind(&category.display_name)
+        .bind(category.display_order)
+        .execute(&mut *tx)
+        .await?;
+        report.categories.record(result.rows_affected() > 0);
+    }
+
+    for item in &fixtures.items {
+        report.items.record(seed_item(&mut tx, item).await?);
+    }
+
+    for user in &fixtures.users {
+        report.users.record(seed_user(&mut tx, user).await?);
+    }
+
+    tx.commit().await?;
+    Ok(report)
+}
+
+async fn seed_item(tx: &mut Transaction<'_, Postgres>, item: &ItemFixture) -> AppResult<bool> {
+    let category_id: Option<i32> =
+        sqlx::query_scalar("SELECT id FROM category WHERE category_name = $1")
+            .bind(&item.category)
+            .fetch_optional(&mut **tx)
+            .await?;
+    let Some(category_id) = category_id else {
+        return Err(AppError::Validation(format!(
+            "item '{}' names unknown category '{}'",
+            item.title, item.category
+        )));
+    };
+
+    let result = sqlx::query(
+        "INSERT INTO items (title, description, data, category_id)
+         SELECT $1, $2, $3, $4
+         WHERE NOT EXISTS (SELECT 1 FROM items WHERE category_id = $4 AND title = $1)",
+    )
+    .bind(&item.title)
+    .bind(&item.description)
+    .bind(&item.data)
+    .bind(category_id)
+    .execute(&mut **tx)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn seed_user(tx: &mut Transaction<'_, Postgres>, user: &UserFixture) -> AppResult<bool> {
+    let existing: Option<i32> = sqlx::query_scalar("SELECT id FROM users WHERE username = $1")
+        .bind(&user.username)
+        .fetch_optional(&mut **tx)
+        .await?;
+    if existing.is_some() {
+        return Ok(false);
+    }
+
+    let password_hash = PasswordService::hash_password(&user.password)?;
+    let user_id: i32 = sqlx::query_scalar(
+        "INSERT INTO users (username, email, password_hash, email_verified)
+         VALUES ($1, $2, $3, true)
+         RETURNING id",
+    )
+    .bind(&user.username)
+    .bind(&user.email)
+    .bind(password_hash)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    sqlx::query(
+        "INSERT INTO user_roles (user_id, role)
+         SELECT $1, role FROM UNNEST($2::text[]) AS role
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(user_id)
+    .bind(&user.roles)
+    .execute(&mut **tx)
+    .await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_fixtures_parse() {
+        let source = std::fs::read_to_string(DEFAULT_FIXTURES).unwrap();
+        let fixtures = Fixtures::parse(&source).unwrap();
+        assert!(
+            fixtures
+                .users
+                .iter()
+                .any(|user| user.roles.iter().any(|r| r == "admin"))
+        );
+
+        // Every item names a category the fixtures declare
+        for item in &fixtures.items {
+            assert!(
+                fixtures.categories.iter().any(|c| c.name == item.category),
+                "{} names an undeclared category",
+                item.title
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_fixtures() {
+        assert!(
+            Fixtures::parse(
+                "[[users]]\nusername = \"a\"\nemail = \"a@example.com\"\npassword = \"short\""
+            )
+            .is_err()
+        );
+        assert!(Fixtures::parse("[[widgets]]\nname = \"x\"").is_err());
+        assert_eq!(Fixtures::parse("").unwrap().items.len(), 0);
+    }
+}
@@ -2,12 +2,36 @@
 //!
 //! Server startup and configuration logic.
 
-use std::env;
-use std::net::IpAddr;
+use axum::Router;
+use axum::serve::{Listener, ListenerExt};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(feature = "acme")]
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::{JoinHandle, JoinSet};
 
-use crate::database::{init_pool, run_migrations, test_connection};
+#[cfg(feature = "acme")]
+use crate::acme::{self, AcmeListener, CertResolver, certificate_domains};
+use crate::auth::PasswordService;
+use crate::bootstrap::bootstrap_admin;
+use crate::cli::CliError;
+use crate::config::{AppConfig, Environment};
+use crate::database::{init_pool, init_read_pool, run_migrations, test_connection};
+use crate::i18n;
+use crate::jobs::Worker;
+use crate::output;
+use crate::plugins::PluginRegistry;
+use crate::resources;
 use crate::routes::create_router;
-use crate::web::init_templates;
+use crate::scheduler;
+#[cfg(feature = "acme")]
+use crate::services::TenantService;
+use crate::state::AppState;
+use crate::web::init_templates_with;
+#[cfg(debug_assertions)]
+use crate::web::watch_templates;
 
 /// Gets all available network interfaces and their IP addresses
 fn get_network_addresses() -> Vec<String> {
@@ -41,87 +65,351 @@ fn get_network_addresses() -> Vec<String> {
     addresses
 }
 
+/// Resolve on Ctrl+C, SIGTERM, or `stop` and tell the job worker and server
+/// to stop
+async fn shutdown_signal<F>(shutdown: watch::Sender<bool>, stop: F)
+where
+    F: Future<Output = ()>,
+{
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+        _ = stop => {}
+    }
+    output::info("Shutting down: no new jobs will start");
+    let _ = shutdown.send(true);
+}
+
+/// Resolve once shutdown has been requested
+async fn shutdown_requested(mut shutdown: watch::Receiver<bool>) {
+    let _ = shutdown.wait_for(|stop| *stop).await;
+}
+
+/// Serve until shutdown is requested. Open connections then get `grace` to
+/// finish; long-lived ones such as the live reload stream are cut off after.
+/// Handlers see each connection's peer as `ConnectInfo<SocketAddr>`.
+async fn serve<L>(listener: L, app: Router, shutdown: watch::Receiver<bool>, grace: Duration)
+where
+    L: Listener<Addr = SocketAddr>,
+{
+    // Through `tap_io`, any listener's peer addresses reach `ConnectInfo`,
+    // not only a `TcpListener`'s
+    let server = axum::serve(
+        listener.tap_io(|_| {}),
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_requested(shutdown.clone()));
+    let deadline = async {
+        shutdown_requested(shutdown).await;
+        tokio::time::sleep(grace).await;
+    };
+
+    tokio::select! {
+        result = server => {
+            if let Err(err) = result {
+                output::error(format!("Server error: {}", err));
+                std::process::exit(1);
+            }
+        }
+        _ = deadline => {
+            output::warn(format!("Closing connections still open after {}s", grace.as_secs()));
+        }
+    }
+}
+
+/// Wait for the job worker and scheduled tasks to stop, then run the
+/// plugins' shutdown hooks
+async fn finish(state: AppState, worker: JoinHandle<()>, mut scheduled: JoinSet<()>) {
+    let _ = worker.await;
+    while scheduled.join_next().await.is_some() {}
+    state.plugins.shutdown(&state).await;
+    output::info("Server stopped");
+}
+
 /// Starts the Axum Base server
+#[allow(dead_code)]
 pub async fn start_server() {
-    // Get port from environment variable, default to 3093
-    let port = env::var("PORT")
-        .unwrap_or_else(|_| "3093".to_string())
-        .parse::<u16>()
-        .unwrap_or(3093);
+    start_server_with(PluginRegistry::default()).await;
+}
 
-    let addr = format!("0.0.0.0:{}", port);
+/// Starts the Axum Base server with `plugins` installed
+#[allow(dead_code)]
+pub async fn start_server_with(plugins: PluginRegistry) {
+    start_server_until(plugins, std::future::pending()).await;
+}
+
+/// Starts the Axum Base server with `plugins` installed; it shuts down
+/// gracefully when `stop` resolves, as on Ctrl+C or SIGTERM
+pub async fn start_server_until<F>(plugins: PluginRegistry, stop: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    // Load and validate configuration before touching any resources
+    let config = match AppConfig::load() {
+        Ok(config) => config,
+        Err(err) => CliError::from(err).exit(),
+    };
+    // Validation has checked the parameters already
+    let _ = PasswordService::configure(&config.passwords);
+    output::success(format!(
+        "Configuration loaded ({} environment)",
+        config.environment
+    ));
+    // Development mode turns on live reload, GraphiQL, and email previews and
+    // turns off caching and secure cookies, which a release build never wants
+    #[cfg(not(debug_assertions))]
+    if config.environment == Environment::Development {
+        output::warn(
+            "RUNNING A RELEASE BUILD IN DEVELOPMENT MODE: live reload, GraphiQL, and email \
+             previews are on and caching is off. Set APP_ENV=production to serve real traffic.",
+        );
+    }
+
+    let limits = resources::limits();
+    output::info(format!(
+        "Sized for {} CPU(s){}: {} worker threads, {} database connections, {} concurrent jobs",
+        limits.cpus(),
+        limits
+            .cgroup
+            .map_or(String::new(), |version| format!(" (cgroup {})", version)),
+        tokio::runtime::Handle::current().metrics().num_workers(),
+        config.database.max_connections(),
+        config.jobs.concurrency()
+    ));
+
+    let port = config.server.port;
+    let addr = config.server.bind_address();
 
     // Initialize database connection pool
-    let db_pool = match init_pool().await {
+    let db_pool = match init_pool(&config.database).await {
         Ok(pool) => pool,
         Err(err) => {
-            eprintln!("❌ Failed to initialize database pool: {}", err);
-            std::process::exit(1);
+            CliError::Database(format!("Failed to initialize database pool: {}", err)).exit()
+        }
+    };
+
+    let read_pool = match init_read_pool(&config.database).await {
+        Ok(pool) => pool,
+        Err(err) => {
+            CliError::Database(format!("Failed to initialize read replica pool: {}", err)).exit()
         }
     };
 
     // Test database connectivity
     match test_connection(&db_pool).await {
-        Ok(true) => println!("✅ Database connectivity verified"),
+        Ok(true) => output::success("Database connectivity verified"),
         Ok(false) => {
-            eprintln!("❌ Database connectivity test failed: unexpected result");
-            std::process::exit(1);
+            CliError::Database("Database connectivity test failed: unexpected result".to_string())
+                .exit()
         }
         Err(err) => {
-            eprintln!("❌ Database connectivity test failed: {}", err);
-            std::process::exit(1);
+            CliError::Database(format!("Database connectivity test failed: {}", err)).exit()
         }
     }
 
     // Run database migrations
-    if let Err(err) = run_migrations(&db_pool).await {
-        eprintln!("❌ Failed to run database migrations: {}", err);
+    if let Err(err) = run_migrations(&db_pool, &plugins).await {
+        output::error(format!("Failed to run database migrations: {}", err));
         std::process::exit(1);
     }
-    println!("✅ Database migrations completed successfully");
+    output::success("Database migrations completed successfully");
 
-    // Initialize template engine
-    if let Err(err) = init_templates() {
-        eprintln!("❌ Failed to initialize templates: {}", err);
+    // Create or update the admin named in ADMIN_BOOTSTRAP_USERNAME
+    if let Err(err) = bootstrap_admin(&db_pool, &config.bootstrap).await {
+        output::error(format!("Failed to bootstrap the admin user: {}", err));
         std::process::exit(1);
     }
 
+    // Initialize template engine
+    let templates = match init_templates_with(plugins.template_dirs()) {
+        Ok(templates) => templates,
+        Err(err) => {
+            output::error(format!("Failed to initialize templates: {}", err));
+            std::process::exit(1);
+        }
+    };
+
+    if config.live_reload_enabled() {
+        output::info("Live reload enabled (watching templates/ and static/)");
+    }
+
+    // Pick up template edits without a restart (development, debug builds)
+    #[cfg(debug_assertions)]
+    let _template_watcher = if config.environment == Environment::Development {
+        match watch_templates() {
+            Ok(watcher) => {
+                output::info("Reloading templates when files in templates/ change");
+                Some(watcher)
+            }
+            Err(err) => {
+                output::warn(format!("Template hot reload unavailable: {}", err));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Certificates for the public host and verified tenant domains (ACME)
+    #[cfg(feature = "acme")]
+    let cert_resolver = if config.tls.acme {
+        let custom_domains = TenantService::verified_domains(&db_pool)
+            .await
+            .unwrap_or_else(|err| {
+                output::error(format!("Failed to load custom domains: {}", err));
+                std::process::exit(1);
+            });
+        let domains = certificate_domains(&config.server.public_host(), custom_domains);
+        if domains.is_empty() {
+            output::error("ACME needs a public PUBLIC_URL host or verified custom domains");
+            std::process::exit(1);
+        }
+        output::info(format!("HTTPS via ACME for {}", domains.join(", ")));
+        let resolver = Arc::new(CertResolver::new(&config.tls));
+        resolver.sync(&domains);
+        Some(resolver)
+    } else {
+        None
+    };
+
     // Create the Axum router with all routes and session management
-    let app = create_router(db_pool).await;
+    let mut state = AppState::new(db_pool, config, templates).with_plugins(plugins);
+    if let Some(read_pool) = read_pool {
+        state = state.with_read_pool(read_pool);
+    }
+    let grace = Duration::from_secs(state.config.jobs.shutdown_grace_secs);
+    output::info(format!(
+        "Outgoing email via {}",
+        state.mailer.transport_name()
+    ));
+    output::info(format!("Uploads stored on {}", state.storage.name()));
+    for (locale, _) in i18n::SUPPORTED_LOCALES {
+        let missing = i18n::missing_keys(locale);
+        if !missing.is_empty() {
+            output::warn(format!(
+                "locales/{}.toml is missing {} messages, which fall back to {}",
+                locale,
+                missing.len(),
+                i18n::DEFAULT_LOCALE
+            ));
+        }
+    }
+
+    if !state.plugins.is_empty() {
+        if let Err(err) = state.plugins.start(&state).await {
+            output::error(err);
+            std::process::exit(1);
+        }
+        output::info(format!("Plugins: {}", state.plugins.names().join(", ")));
+    }
+
+    // Run queued jobs in the background until shutdown
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(shutdown_signal(shutdown_tx, stop));
+    let worker = Worker::new(state.clone());
+    output::info(format!(
+        "Job worker {} running up to {} jobs at a time",
+        worker.id(),
+        state.config.jobs.concurrency()
+    ));
+    let worker = tokio::spawn(worker.run(shutdown_rx.clone()));
+    // Housekeeping first, then the plugins' and the app's tasks
+    let tasks = scheduler::core_tasks().into_iter();
+    // Newly verified tenant domains get certificates without a restart
+    #[cfg(feature = "acme")]
+    let tasks = tasks.chain(cert_resolver.clone().map(acme::refresh_task));
+    let tasks = tasks.chain(state.plugins.scheduled_tasks());
+    let scheduled = scheduler::spawn(tasks, &state, shutdown_rx.clone());
+
+    let app = create_router(state.clone()).await;
 
     // Start the server
-    println!("🚀 Axum Base server starting...");
-    println!("🌟 Server ready! Access via:");
+    output::info("Axum Base server starting...");
+    output::info("Server ready! Access via:");
 
     // Get all available network addresses
     let addresses = get_network_addresses();
     for address in addresses {
-        println!("   http://{}:{}", address, port);
+        output::detail(format!("http://{}:{}", address, port));
     }
 
-    println!();
-    println!("📡 Available endpoints:");
-    println!("   GET  /         - Welcome page (using base template)");
-    println!("   GET  /landing  - Landing page");
-    println!("   GET  /login    - Login page");
-    println!("   POST /login    - Login form submission");
-    println!("   POST /logout   - Logout");
-    println!("   GET  /profile  - User profile (authenticated)");
-    println!("   POST /profile  - Update profile (authenticated)");
-    println!("   GET  /health   - Health check");
-    println!("   GET  /api/hello - JSON API endpoint");
-    println!("   GET  /static/* - Static file serving");
-    println!("💡 Press Ctrl+C to stop the server");
+    output::info("Available endpoints:");
+    output::detail("GET  /         - Welcome page (using base template)");
+    output::detail("GET  /landing  - Landing page");
+    output::detail("GET  /login    - Login page");
+    output::detail("POST /login    - Login form submission");
+    output::detail("POST /logout   - Logout");
+    output::detail("GET  /ws       - WebSocket push (authenticated)");
+    output::detail("GET  /profile  - User profile (authenticated)");
+    output::detail("POST /profile  - Update profile (authenticated)");
+    output::detail("GET  /health   - Health overview");
+    output::detail("GET  /health/live - Liveness (no database access)");
+    output::detail("GET  /health/ready - Readiness (database, migrations, pool, job workers)");
+    output::detail("GET  /metrics  - Prometheus counters (when enabled)");
+    output::detail("GET  /api/hello - JSON API endpoint");
+    output::detail("GET  /api/items - Paginated items (?page=&per_page= or ?limit=&offset=)");
+    output::detail("GET  /api/categories - Paginated categories");
+    output::detail("*    /api/uploads - Upload and list your files (authenticated)");
+    output::detail("*    /files/{id} - Download or delete a file (owner or admin)");
+    output::detail("*    /api/admin/users - User management (admin role)");
+    output::detail("GET  /admin/audit - Audit log (admin role)");
+    output::detail("GET  /api/admin/audit - Audit log entries (admin role)");
+    output::detail("POST /api/admin/sql - Read-only SQL console (admin role)");
+    output::detail("*    /api/admin/tenants - Tenants, branding, custom domains (admin role)");
+    output::detail("GET  /admin/sql - SQL console page (admin role)");
+    output::detail("GET  /admin/emails - Email template editor and outbox (admin role)");
+    output::detail("*    /api/admin/email - Email outbox and suppressions (admin role)");
+    output::detail("GET  /admin/jobs - Background jobs and worker liveness (admin role)");
+    output::detail("GET  /api/admin/jobs - Job queue sizes and workers (admin role)");
+    output::detail("GET  /api/admin/health/history - Readiness probes and flapping (admin role)");
+    output::detail(
+        "POST /webhooks/email/{ses,sendgrid} - Bounce/complaint webhooks (when configured)",
+    );
+    output::detail("GET  /api/openapi.json - OpenAPI spec");
+    output::detail("GET  /api/docs - Swagger UI");
+    output::detail("GET  /.well-known/security.txt - Security contact (when configured)");
+    output::detail("GET  /.well-known/change-password - Redirect to password change");
+    output::detail("GET  /static/* - Static file serving");
+    output::detail("GET  /dev/emails - Email previews (development)");
+    output::info("Press Ctrl+C to stop the server");
+
+    #[cfg(feature = "acme")]
+    if let Some(resolver) = cert_resolver {
+        let listener = AcmeListener::bind(&addr, resolver)
+            .await
+            .unwrap_or_else(|err| {
+                output::error(format!("Failed to bind to address {}: {}", addr, err));
+                std::process::exit(1);
+            });
+
+        serve(listener, app, shutdown_rx, grace).await;
+        finish(state, worker, scheduled).await;
+        return;
+    }
 
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .unwrap_or_else(|err| {
-            eprintln!("❌ Failed to bind to address {}: {}", addr, err);
+            output::error(format!("Failed to bind to address {}: {}", addr, err));
             std::process::exit(1);
         });
 
-    if let Err(err) = axum::serve(listener, app).await {
-        eprintln!("❌ Server error: {}", err);
-        std::process::exit(1);
-    }
+    serve(listener, app, shutdown_rx, grace).await;
+    finish(state, worker, scheduled).await;
 }
@@ -1,15 +1,32 @@
 //! # Business Logic Services
 //!
 //! Service layer for handling business logic and database operations.
+//! Users, items, and categories are read and written through the traits in
+//! [`repositories`](crate::repositories), so any implementation can stand in
+//! for the database.
 
-use argon2::password_hash::{SaltString, rand_core::OsRng};
-use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
-use sqlx::PgPool;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgPool};
 
+use std::collections::HashMap;
+use std::time::Instant;
+
+use crate::auth::PasswordService;
+use crate::db_metrics::QueryTimer;
+use crate::item_filter::ItemFilter;
 use crate::models::{
-    Category, CreateItemRequest, CreateUserRequest, Item, ItemWithCategory, User, UserResponse,
+    AuditEntry, AuditFilter, Category, CreateItemRequest, CreateUserRequest, EmailSuppression,
+    EmailTemplateVersion, Item, ItemAttachment, ItemSearchFilters, ItemSearchHit, ItemSuggestion,
+    ItemWithCategory, Job, JobWorker, NewNotification, Notification, OutboxEmail, Preferences,
+    ProfileFields, Snippet, SqlQueryResult, Tenant, TenantDomain, TenantThemeRequest, Upload, User,
+    UserFilter, UserResponse, UsernameChange, UsernameChangeEntry, chrono_to_time,
     time_opt_to_chrono_opt, time_to_chrono,
 };
+use crate::pagination::{Cursor, CursorPage, Paginated, Pagination};
+use crate::preferences::{self, Preference};
+use crate::repositories::{CategoryRepository, ItemRepository, UserRepository};
+use crate::search;
 
 // =============================================================================
 // User Service
@@ -21,64 +38,21 @@ pub struct UserService;
 #[allow(dead_code)]
 impl UserService {
     /// Get user by ID
-    pub async fn get_user_by_id(pool: &PgPool, user_id: i32) -> Result<Option<User>, sqlx::Error> {
-        let row = sqlx::query!(
-            "SELECT id, username, email, password_hash, email_verified, is_active, last_login, created_at, updated_at 
-             FROM users 
-             WHERE id = $1 AND is_active = true",
-            user_id
-        )
-        .fetch_optional(pool)
-        .await?;
-
-        if let Some(row) = row {
-            let user = User {
-                id: row.id,
-                username: row.username,
-                email: row.email,
-                password_hash: Some(row.password_hash),
-                email_verified: row.email_verified,
-                is_active: row.is_active,
-                last_login: time_opt_to_chrono_opt(row.last_login),
-                created_at: time_to_chrono(row.created_at),
-                updated_at: time_to_chrono(row.updated_at),
-            };
-            Ok(Some(user))
-        } else {
-            Ok(None)
-        }
+    pub async fn get_user_by_id(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_user_by_id");
+        users.get_user_by_id(user_id).await
     }
 
     /// Get user by username
     pub async fn get_user_by_username(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         username: &str,
     ) -> Result<Option<User>, sqlx::Error> {
-        let row = sqlx::query!(
-            "SELECT id, username, email, password_hash, email_verified, is_active, last_login, created_at, updated_at 
-             FROM users 
-             WHERE username = $1 AND is_active = true",
-            username
-        )
-        .fetch_optional(pool)
-        .await?;
-
-        if let Some(row) = row {
-            let user = User {
-                id: row.id,
-                username: row.username,
-                email: row.email,
-                password_hash: Some(row.password_hash),
-                email_verified: row.email_verified,
-                is_active: row.is_active,
-                last_login: time_opt_to_chrono_opt(row.last_login),
-                created_at: time_to_chrono(row.created_at),
-                updated_at: time_to_chrono(row.updated_at),
-            };
-            Ok(Some(user))
-        } else {
-            Ok(None)
-        }
+        let _timer = QueryTimer::start("UserService::get_user_by_username");
+        users.get_user_by_username(username).await
     }
 
     /// Verify user password
@@ -94,88 +68,241 @@ impl UserService {
 
     /// Hash password
     pub async fn hash_password(password: &str) -> Result<String, argon2::password_hash::Error> {
-        let salt = SaltString::generate(&mut OsRng);
-        let argon2 = Argon2::default();
-        let password_hash = argon2.hash_password(password.as_bytes(), &salt)?;
-        Ok(password_hash.to_string())
+        PasswordService::hash_password(password)
     }
 
     /// Update user's last login time
-    pub async fn update_last_login(pool: &PgPool, user_id: i32) -> Result<(), sqlx::Error> {
-        sqlx::query!("UPDATE users SET last_login = NOW() WHERE id = $1", user_id)
-            .execute(pool)
-            .await?;
-
-        Ok(())
+    pub async fn update_last_login(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::update_last_login");
+        users.update_last_login(user_id).await
     }
 
     /// Update user's email
     pub async fn update_user_email(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         user_id: i32,
         new_email: &str,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query!(
-            "UPDATE users SET email = $1, updated_at = NOW() WHERE id = $2",
-            new_email,
-            user_id
-        )
-        .execute(pool)
-        .await?;
-
-        Ok(())
+        let _timer = QueryTimer::start("UserService::update_user_email");
+        users
+            .update_user_email(user_id, new_email)
+            .await
+            .map(|_| ())
     }
 
     /// Update user's password
     pub async fn update_user_password(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         user_id: i32,
         new_password_hash: &str,
     ) -> Result<(), sqlx::Error> {
-        sqlx::query!(
-            "UPDATE users SET password_hash = $1, updated_at = NOW() WHERE id = $2",
-            new_password_hash,
-            user_id
-        )
-        .execute(pool)
-        .await?;
-
-        Ok(())
+        let _timer = QueryTimer::start("UserService::update_user_password");
+        users
+            .update_user_password(user_id, new_password_hash)
+            .await
+            .map(|_| ())
     }
 
     /// Create new user
     pub async fn create_user(
-        pool: &PgPool,
+        users: &(impl UserRepository + ?Sized),
         request: &CreateUserRequest,
     ) -> Result<UserResponse, sqlx::Error> {
         let password_hash = Self::hash_password(&request.password)
             .await
             .map_err(|e| sqlx::Error::Protocol(format!("Password hashing failed: {}", e)))?;
 
-        let row = sqlx::query!(
-            "INSERT INTO users (username, email, password_hash) 
-             VALUES ($1, $2, $3) 
-             RETURNING id, username, email, password_hash, email_verified, is_active, last_login, created_at, updated_at",
-            request.username,
-            request.email,
-            password_hash
-        )
-        .fetch_one(pool)
-        .await?;
+        let _timer = QueryTimer::start("UserService::create_user");
+        users
+            .insert_user(request, &password_hash)
+            .await
+            .map(UserResponse::from)
+    }
+}
 
-        let user = User {
-            id: row.id,
-            username: row.username,
-            email: row.email,
-            password_hash: Some(row.password_hash),
-            email_verified: row.email_verified,
-            is_active: row.is_active,
-            last_login: time_opt_to_chrono_opt(row.last_login),
-            created_at: time_to_chrono(row.created_at),
-            updated_at: time_to_chrono(row.updated_at),
-        };
+// =============================================================================
+// User Administration
+// =============================================================================
+
+#[allow(dead_code)]
+impl UserService {
+    /// Get user by ID, including deactivated users
+    pub async fn get_user_any_status(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_user_any_status");
+        users.get_user_any_status(user_id).await
+    }
+
+    /// Find a user by ID, username, or email, including deactivated users
+    pub async fn find_user(
+        users: &(impl UserRepository + ?Sized),
+        identifier: &str,
+    ) -> Result<Option<User>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::find_user");
+        if let Ok(user_id) = identifier.parse::<i32>() {
+            return users.get_user_any_status(user_id).await;
+        }
+        users.find_user_by_name_or_email(identifier).await
+    }
+
+    /// Get a page of users matching `filter`, newest first
+    pub async fn list_users(
+        users: &(impl UserRepository + ?Sized),
+        filter: &UserFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<User>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::list_users");
+        users.list_users(filter, pagination).await
+    }
+
+    /// Mark a user inactive; returns false if the user does not exist
+    pub async fn deactivate_user(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::deactivate_user");
+        users.deactivate_user(user_id).await
+    }
+
+    /// Mark a user active again; returns false if the user does not exist
+    pub async fn reactivate_user(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::reactivate_user");
+        users.reactivate_user(user_id).await
+    }
+
+    /// Permanently delete a user; returns false if the user does not exist.
+    /// Stored upload and avatar contents are left to the caller.
+    pub async fn delete_user(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::delete_user");
+        users.delete_user(user_id).await
+    }
+
+    /// Role names assigned to a user
+    pub async fn get_user_roles(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_user_roles");
+        users.get_user_roles(user_id).await
+    }
+
+    /// Role names for several users at once, keyed by user ID
+    pub async fn get_roles_for_users(
+        users: &(impl UserRepository + ?Sized),
+        user_ids: &[i32],
+    ) -> Result<HashMap<i32, Vec<String>>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_roles_for_users");
+        users.get_roles_for_users(user_ids).await
+    }
+
+    /// A user's preferred locale, if they chose one
+    pub async fn get_locale(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_locale");
+        users.get_locale(user_id).await
+    }
+
+    /// Set or clear a user's preferred locale
+    pub async fn set_locale(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+        locale: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::set_locale");
+        users.set_locale(user_id, locale).await
+    }
+
+    /// A user's preferred time zone, if they chose one
+    pub async fn get_timezone(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_timezone");
+        users.get_timezone(user_id).await
+    }
+
+    /// Set or clear a user's preferred time zone
+    pub async fn set_timezone(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+        timezone: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::set_timezone");
+        users.set_timezone(user_id, timezone).await
+    }
+
+    /// Storage key of a user's avatar, if they uploaded one
+    pub async fn get_avatar_key(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::get_avatar_key");
+        users.get_avatar_key(user_id).await
+    }
+
+    /// Set or clear a user's avatar
+    pub async fn set_avatar_key(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+        key: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::set_avatar_key");
+        users.set_avatar_key(user_id, key).await
+    }
+
+    /// Replace a user's display name, bio, and profile data
+    pub async fn set_profile_fields(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+        fields: &ProfileFields,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::set_profile_fields");
+        users.set_profile_fields(user_id, fields).await
+    }
+
+    /// Replace a user's roles with `roles`
+    pub async fn set_user_roles(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+        roles: &[String],
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::set_user_roles");
+        users.set_user_roles(user_id, roles).await
+    }
+
+    /// Rename a user unless another user has the name in any letter case,
+    /// recording the old name in their history. Sessions stay signed in, as
+    /// they hold the user ID.
+    pub async fn change_username(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+        new_username: &str,
+        changed_by: Option<i32>,
+    ) -> Result<UsernameChange, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::change_username");
+        users.rename_user(user_id, new_username, changed_by).await
+    }
 
-        Ok(UserResponse::from(user))
+    /// A user's earlier usernames, most recent rename first
+    pub async fn username_history(
+        users: &(impl UserRepository + ?Sized),
+        user_id: i32,
+    ) -> Result<Vec<UsernameChangeEntry>, sqlx::Error> {
+        let _timer = QueryTimer::start("UserService::username_history");
+        users.get_username_history(user_id).await
     }
 }
 
@@ -188,61 +315,22 @@ pub struct CategoryService;
 
 #[allow(dead_code)]
 impl CategoryService {
-    /// Get all visible categories
-    pub async fn get_all_categories(pool: &PgPool) -> Result<Vec<Category>, sqlx::Error> {
-        let rows = sqlx::query!(
-            "SELECT id, category_name, display_name, is_visible, display_order, created_at, updated_at 
-             FROM category 
-             WHERE is_visible = true 
-             ORDER BY display_order, display_name"
-        )
-        .fetch_all(pool)
-        .await?;
-
-        let categories: Vec<Category> = rows
-            .into_iter()
-            .map(|row| Category {
-                id: row.id,
-                category_name: row.category_name,
-                display_name: row.display_name,
-                is_visible: row.is_visible,
-                display_order: row.display_order,
-                created_at: time_to_chrono(row.created_at),
-                updated_at: time_to_chrono(row.updated_at),
-            })
-            .collect();
-
-        Ok(categories)
+    /// Get a page of visible categories
+    pub async fn get_all_categories(
+        categories: &(impl CategoryRepository + ?Sized),
+        pagination: &Pagination,
+    ) -> Result<Paginated<Category>, sqlx::Error> {
+        let _timer = QueryTimer::start("CategoryService::get_all_categories");
+        categories.get_all_categories(pagination).await
     }
 
     /// Get category by ID
     pub async fn get_category_by_id(
-        pool: &PgPool,
+        categories: &(impl CategoryRepository + ?Sized),
         category_id: i32,
     ) -> Result<Option<Category>, sqlx::Error> {
-        let row = sqlx::query!(
-            "SELECT id, category_name, display_name, is_visible, display_order, created_at, updated_at 
-             FROM category 
-             WHERE id = $1 AND is_visible = true",
-            category_id
-        )
-        .fetch_optional(pool)
-        .await?;
-
-        if let Some(row) = row {
-            let category = Category {
-                id: row.id,
-                category_name: row.category_name,
-                display_name: row.display_name,
-                is_visible: row.is_visible,
-                display_order: row.display_order,
-                created_at: time_to_chrono(row.created_at),
-                updated_at: time_to_chrono(row.updated_at),
-            };
-            Ok(Some(category))
-        } else {
-            Ok(None)
-        }
+        let _timer = QueryTimer::start("CategoryService::get_category_by_id");
+        categories.get_category_by_id(category_id).await
     }
 }
 
@@ -255,110 +343,1963 @@ pub struct ItemService;
 
 #[allow(dead_code)]
 impl ItemService {
-    /// Get all items with their categories
-    pub async fn get_all_items(pool: &PgPool) -> Result<Vec<ItemWithCategory>, sqlx::Error> {
-        let items = sqlx::query!(
-            "SELECT 
-                i.id, i.title, i.description, i.data, i.is_active, i.category_id, 
-                i.created_at, i.updated_at,
-                c.id as cat_id, c.category_name, c.display_name, c.is_visible,
-                c.display_order, c.created_at as cat_created_at, c.updated_at as cat_updated_at
-             FROM items i 
-             JOIN category c ON i.category_id = c.id 
-             WHERE c.is_visible = true AND i.is_active = true
-             ORDER BY i.created_at DESC"
-        )
-        .fetch_all(pool)
-        .await?;
+    /// Get a page of items with their categories
+    pub async fn get_all_items(
+        items: &(impl ItemRepository + ?Sized),
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemWithCategory>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::get_all_items");
+        items.get_all_items(pagination).await
+    }
 
-        let result = items
-            .into_iter()
-            .map(|row| ItemWithCategory {
-                item: Item {
-                    id: row.id,
-                    title: row.title,
-                    description: row.description,
-                    data: row.data,
-                    is_active: row.is_active,
-                    category_id: row.category_id,
-                    created_at: time_to_chrono(row.created_at),
-                    updated_at: time_to_chrono(row.updated_at),
-                },
-                category: Category {
-                    id: row.cat_id,
-                    category_name: row.category_name,
-                    display_name: row.display_name,
-                    is_visible: row.is_visible,
-                    display_order: row.display_order,
-                    created_at: time_to_chrono(row.cat_created_at),
-                    updated_at: time_to_chrono(row.cat_updated_at),
-                },
-            })
-            .collect();
+    /// A page of items in visible categories matching `filter`, in its order
+    pub async fn list_items(
+        items: &(impl ItemRepository + ?Sized),
+        filter: &ItemFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemWithCategory>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::list_items");
+        items.list_items(filter, pagination).await
+    }
+
+    /// A keyset page of items in visible categories matching `filter`,
+    /// starting after `after`; `filter` must sort by creation time
+    pub async fn list_items_after(
+        items: &(impl ItemRepository + ?Sized),
+        filter: &ItemFilter,
+        after: Option<Cursor>,
+        pagination: &Pagination,
+    ) -> Result<CursorPage<ItemWithCategory>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::list_items_after");
+        items.list_items_after(filter, after, pagination).await
+    }
+
+    /// An item with its category, whether or not either is visible
+    pub async fn get_item(
+        items: &(impl ItemRepository + ?Sized),
+        id: i32,
+    ) -> Result<Option<ItemWithCategory>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::get_item");
+        items.get_item(id).await
+    }
+
+    /// Visible items whose title contains `text`, titles starting with it
+    /// first, then by trigram similarity
+    pub async fn suggest(
+        items: &(impl ItemRepository + ?Sized),
+        text: &str,
+        limit: i64,
+    ) -> Result<Vec<ItemSuggestion>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::suggest");
+        items.suggest(text, limit).await
+    }
 
-        Ok(result)
+    /// Visible items matching `query`, most relevant first, with the
+    /// matched words in their title and description snippet in `<mark>`
+    pub async fn search(
+        items: &(impl ItemRepository + ?Sized),
+        query: &str,
+        filters: &ItemSearchFilters,
+        pagination: &Pagination,
+    ) -> Result<Paginated<ItemSearchHit>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::search");
+        let mut page = items.search(query, filters, pagination).await?;
+        for hit in &mut page.items {
+            hit.title_html = search::highlight(&hit.title_html);
+            hit.snippet_html = hit.snippet_html.as_deref().map(search::highlight);
+        }
+        Ok(page)
     }
 
     /// Get items by category
     pub async fn get_items_by_category(
-        pool: &PgPool,
+        items: &(impl ItemRepository + ?Sized),
         category_id: i32,
     ) -> Result<Vec<Item>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::get_items_by_category");
+        items.get_items_by_category(category_id).await
+    }
+
+    /// Create new item
+    pub async fn create_item(
+        items: &(impl ItemRepository + ?Sized),
+        request: &CreateItemRequest,
+    ) -> Result<Item, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::create_item");
+        items.create_item(request).await
+    }
+
+    /// Create many items in a few multi-row inserts, all or nothing; far
+    /// faster than [`create_item`](Self::create_item) per row for imports
+    pub async fn create_items(
+        items: &(impl ItemRepository + ?Sized),
+        requests: &[CreateItemRequest],
+    ) -> Result<Vec<Item>, sqlx::Error> {
+        let _timer = QueryTimer::start("ItemService::create_items");
+        items.create_items(requests).await
+    }
+}
+
+// =============================================================================
+// Tenant Service
+// =============================================================================
+
+pub struct TenantService;
+
+#[allow(dead_code)]
+impl TenantService {
+    /// All tenants, by slug
+    pub async fn list_tenants(pool: &PgPool) -> Result<Vec<Tenant>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::list_tenants");
         let rows = sqlx::query!(
-            "SELECT id, title, description, data, is_active, category_id, created_at, updated_at
-             FROM items 
-             WHERE category_id = $1 AND is_active = true
-             ORDER BY created_at DESC",
-            category_id
+            "SELECT id, slug, name, primary_color, accent_color, logo_url, created_at, updated_at
+             FROM tenants
+             ORDER BY slug"
         )
         .fetch_all(pool)
         .await?;
 
-        let items: Vec<Item> = rows
+        Ok(rows
             .into_iter()
-            .map(|row| Item {
+            .map(|row| Tenant {
                 id: row.id,
-                title: row.title,
-                description: row.description,
-                data: row.data,
-                is_active: row.is_active,
-                category_id: row.category_id,
+                slug: row.slug,
+                name: row.name,
+                primary_color: row.primary_color,
+                accent_color: row.accent_color,
+                logo_url: row.logo_url,
                 created_at: time_to_chrono(row.created_at),
                 updated_at: time_to_chrono(row.updated_at),
             })
-            .collect();
+            .collect())
+    }
+
+    /// Get tenant by slug
+    pub async fn get_tenant_by_slug(
+        pool: &PgPool,
+        slug: &str,
+    ) -> Result<Option<Tenant>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::get_tenant_by_slug");
+        let row = sqlx::query!(
+            "SELECT id, slug, name, primary_color, accent_color, logo_url, created_at, updated_at
+             FROM tenants
+             WHERE slug = $1",
+            slug
+        )
+        .fetch_optional(pool)
+        .await?;
 
-        Ok(items)
+        Ok(row.map(|row| Tenant {
+            id: row.id,
+            slug: row.slug,
+            name: row.name,
+            primary_color: row.primary_color,
+            accent_color: row.accent_color,
+            logo_url: row.logo_url,
+            created_at: time_to_chrono(row.created_at),
+            updated_at: time_to_chrono(row.updated_at),
+        }))
     }
 
-    /// Create new item
-    pub async fn create_item(
+    /// Create a tenant
+    pub async fn create_tenant(
         pool: &PgPool,
-        request: &CreateItemRequest,
-    ) -> Result<Item, sqlx::Error> {
+        slug: &str,
+        name: &str,
+        theme: &TenantThemeRequest,
+    ) -> Result<Tenant, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::create_tenant");
         let row = sqlx::query!(
-            "INSERT INTO items (title, description, data, category_id) 
-             VALUES ($1, $2, $3, $4) 
-             RETURNING id, title, description, data, is_active, category_id, created_at, updated_at",
-            request.title,
-            request.description,
-            request.data,
-            request.category_id
+            "INSERT INTO tenants (slug, name, primary_color, accent_color, logo_url)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING id, slug, name, primary_color, accent_color, logo_url, created_at, updated_at",
+            slug,
+            name,
+            theme.primary_color,
+            theme.accent_color,
+            theme.logo_url
         )
         .fetch_one(pool)
         .await?;
 
-        let item = Item {
+        Ok(Tenant {
+            id: row.id,
+            slug: row.slug,
+            name: row.name,
+            primary_color: row.primary_color,
+            accent_color: row.accent_color,
+            logo_url: row.logo_url,
+            created_at: time_to_chrono(row.created_at),
+            updated_at: time_to_chrono(row.updated_at),
+        })
+    }
+
+    /// Replace a tenant's branding; returns false if the tenant does not exist
+    pub async fn update_theme(
+        pool: &PgPool,
+        tenant_id: i32,
+        theme: &TenantThemeRequest,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::update_theme");
+        let result = sqlx::query!(
+            "UPDATE tenants
+             SET primary_color = $2, accent_color = $3, logo_url = $4, updated_at = NOW()
+             WHERE id = $1",
+            tenant_id,
+            theme.primary_color,
+            theme.accent_color,
+            theme.logo_url
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Partial overrides stored for a tenant, as (name, body)
+    pub async fn get_partials(
+        pool: &PgPool,
+        tenant_id: i32,
+    ) -> Result<Vec<(String, String)>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::get_partials");
+        let rows = sqlx::query!(
+            "SELECT name, body FROM tenant_partials WHERE tenant_id = $1 ORDER BY name",
+            tenant_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| (row.name, row.body)).collect())
+    }
+
+    /// Create or replace a partial override
+    pub async fn set_partial(
+        pool: &PgPool,
+        tenant_id: i32,
+        name: &str,
+        body: &str,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::set_partial");
+        sqlx::query!(
+            "INSERT INTO tenant_partials (tenant_id, name, body)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (tenant_id, name) DO UPDATE SET body = EXCLUDED.body, updated_at = NOW()",
+            tenant_id,
+            name,
+            body
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove a partial override; returns false if there was none
+    pub async fn delete_partial(
+        pool: &PgPool,
+        tenant_id: i32,
+        name: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::delete_partial");
+        let result = sqlx::query!(
+            "DELETE FROM tenant_partials WHERE tenant_id = $1 AND name = $2",
+            tenant_id,
+            name
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Tenant served on a verified custom domain
+    pub async fn get_tenant_by_domain(
+        pool: &PgPool,
+        domain: &str,
+    ) -> Result<Option<Tenant>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::get_tenant_by_domain");
+        let row = sqlx::query!(
+            "SELECT t.id, t.slug, t.name, t.primary_color, t.accent_color, t.logo_url,
+                    t.created_at, t.updated_at
+             FROM tenants t
+             JOIN tenant_domains d ON d.tenant_id = t.id
+             WHERE d.domain = $1 AND d.verified_at IS NOT NULL",
+            domain
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| Tenant {
             id: row.id,
-            title: row.title,
-            description: row.description,
-            data: row.data,
-            is_active: row.is_active,
-            category_id: row.category_id,
+            slug: row.slug,
+            name: row.name,
+            primary_color: row.primary_color,
+            accent_color: row.accent_color,
+            logo_url: row.logo_url,
             created_at: time_to_chrono(row.created_at),
             updated_at: time_to_chrono(row.updated_at),
-        };
+        }))
+    }
+
+    /// Custom domains attached to a tenant
+    pub async fn list_domains(
+        pool: &PgPool,
+        tenant_id: i32,
+    ) -> Result<Vec<TenantDomain>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::list_domains");
+        let rows = sqlx::query!(
+            "SELECT id, tenant_id, domain, verification_token, verified_at, created_at
+             FROM tenant_domains
+             WHERE tenant_id = $1
+             ORDER BY domain",
+            tenant_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TenantDomain {
+                id: row.id,
+                tenant_id: row.tenant_id,
+                domain: row.domain,
+                verification_token: row.verification_token,
+                verified_at: time_opt_to_chrono_opt(row.verified_at),
+                created_at: time_to_chrono(row.created_at),
+            })
+            .collect())
+    }
+
+    /// Get one of a tenant's custom domains
+    pub async fn get_domain(
+        pool: &PgPool,
+        tenant_id: i32,
+        domain: &str,
+    ) -> Result<Option<TenantDomain>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::get_domain");
+        let row = sqlx::query!(
+            "SELECT id, tenant_id, domain, verification_token, verified_at, created_at
+             FROM tenant_domains
+             WHERE tenant_id = $1 AND domain = $2",
+            tenant_id,
+            domain
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| TenantDomain {
+            id: row.id,
+            tenant_id: row.tenant_id,
+            domain: row.domain,
+            verification_token: row.verification_token,
+            verified_at: time_opt_to_chrono_opt(row.verified_at),
+            created_at: time_to_chrono(row.created_at),
+        }))
+    }
 
-        Ok(item)
+    /// Attach an unverified domain to a tenant
+    pub async fn add_domain(
+        pool: &PgPool,
+        tenant_id: i32,
+        domain: &str,
+        verification_token: &str,
+    ) -> Result<TenantDomain, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::add_domain");
+        let row = sqlx::query!(
+            "INSERT INTO tenant_domains (tenant_id, domain, verification_token)
+             VALUES ($1, $2, $3)
+             RETURNING id, tenant_id, domain, verification_token, verified_at, created_at",
+            tenant_id,
+            domain,
+            verification_token
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(TenantDomain {
+            id: row.id,
+            tenant_id: row.tenant_id,
+            domain: row.domain,
+            verification_token: row.verification_token,
+            verified_at: time_opt_to_chrono_opt(row.verified_at),
+            created_at: time_to_chrono(row.created_at),
+        })
+    }
+
+    /// Record that a domain's ownership was proven
+    pub async fn mark_domain_verified(pool: &PgPool, domain_id: i32) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::mark_domain_verified");
+        sqlx::query!(
+            "UPDATE tenant_domains SET verified_at = NOW() WHERE id = $1",
+            domain_id
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Detach a domain; returns false if the tenant did not have it
+    pub async fn delete_domain(
+        pool: &PgPool,
+        tenant_id: i32,
+        domain: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::delete_domain");
+        let result = sqlx::query!(
+            "DELETE FROM tenant_domains WHERE tenant_id = $1 AND domain = $2",
+            tenant_id,
+            domain
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// All verified custom domains, for certificate issuance
+    pub async fn verified_domains(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("TenantService::verified_domains");
+        let rows = sqlx::query!(
+            "SELECT domain FROM tenant_domains WHERE verified_at IS NOT NULL ORDER BY domain"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows.into_iter().map(|row| row.domain).collect())
+    }
+}
+
+// =============================================================================
+// Email Template Service
+// =============================================================================
+
+pub struct EmailTemplateService;
+
+#[allow(dead_code)]
+impl EmailTemplateService {
+    /// Newest saved version of a template, if it was ever edited
+    pub async fn latest(
+        pool: &PgPool,
+        name: &str,
+    ) -> Result<Option<EmailTemplateVersion>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailTemplateService::latest");
+        let row = sqlx::query!(
+            r#"SELECT t.id, t.name, t.version, t.subject, t.body,
+                      u.username AS "author?", t.created_at
+               FROM email_templates t
+               LEFT JOIN users u ON u.id = t.created_by
+               WHERE t.name = $1
+               ORDER BY t.version DESC
+               LIMIT 1"#,
+            name
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| EmailTemplateVersion {
+            id: row.id,
+            name: row.name,
+            version: row.version,
+            subject: row.subject,
+            body: row.body,
+            author: row.author,
+            created_at: time_to_chrono(row.created_at),
+        }))
+    }
+
+    /// A specific saved version of a template
+    pub async fn get_version(
+        pool: &PgPool,
+        name: &str,
+        version: i32,
+    ) -> Result<Option<EmailTemplateVersion>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailTemplateService::get_version");
+        let row = sqlx::query!(
+            r#"SELECT t.id, t.name, t.version, t.subject, t.body,
+                      u.username AS "author?", t.created_at
+               FROM email_templates t
+               LEFT JOIN users u ON u.id = t.created_by
+               WHERE t.name = $1 AND t.version = $2"#,
+            name,
+            version
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| EmailTemplateVersion {
+            id: row.id,
+            name: row.name,
+            version: row.version,
+            subject: row.subject,
+            body: row.body,
+            author: row.author,
+            created_at: time_to_chrono(row.created_at),
+        }))
+    }
+
+    /// All saved versions of a template, newest first
+    pub async fn history(
+        pool: &PgPool,
+        name: &str,
+    ) -> Result<Vec<EmailTemplateVersion>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailTemplateService::history");
+        let rows = sqlx::query!(
+            r#"SELECT t.id, t.name, t.version, t.subject, t.body,
+                      u.username AS "author?", t.created_at
+               FROM email_templates t
+               LEFT JOIN users u ON u.id = t.created_by
+               WHERE t.name = $1
+               ORDER BY t.version DESC"#,
+            name
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| EmailTemplateVersion {
+                id: row.id,
+                name: row.name,
+                version: row.version,
+                subject: row.subject,
+                body: row.body,
+                author: row.author,
+                created_at: time_to_chrono(row.created_at),
+            })
+            .collect())
+    }
+
+    /// Newest version of every edited template
+    pub async fn latest_versions(pool: &PgPool) -> Result<Vec<EmailTemplateVersion>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailTemplateService::latest_versions");
+        let rows = sqlx::query!(
+            r#"SELECT DISTINCT ON (t.name) t.id, t.name, t.version, t.subject, t.body,
+                      u.username AS "author?", t.created_at
+               FROM email_templates t
+               LEFT JOIN users u ON u.id = t.created_by
+               ORDER BY t.name, t.version DESC"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| EmailTemplateVersion {
+                id: row.id,
+                name: row.name,
+                version: row.version,
+                subject: row.subject,
+                body: row.body,
+                author: row.author,
+                created_at: time_to_chrono(row.created_at),
+            })
+            .collect())
+    }
+
+    /// Save a new version of a template and return its version number
+    pub async fn create_version(
+        pool: &PgPool,
+        name: &str,
+        subject: &str,
+        body: &str,
+        created_by: i32,
+    ) -> Result<i32, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailTemplateService::create_version");
+        let version = sqlx::query_scalar!(
+            r#"INSERT INTO email_templates (name, version, subject, body, created_by)
+               SELECT $1::VARCHAR, COALESCE(MAX(version), 0) + 1, $2, $3, $4
+               FROM email_templates
+               WHERE name = $1::VARCHAR
+               RETURNING version"#,
+            name,
+            subject,
+            body,
+            created_by
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(version)
+    }
+}
+
+// =============================================================================
+// Email Delivery Service
+// =============================================================================
+
+pub struct EmailDeliveryService;
+
+#[allow(dead_code)]
+impl EmailDeliveryService {
+    /// Record a send attempt in the outbox
+    pub async fn record(
+        pool: &PgPool,
+        template: &str,
+        recipient: &str,
+        subject: &str,
+        status: &str,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::record");
+        sqlx::query!(
+            "INSERT INTO email_outbox (template, recipient, subject, status, error)
+             VALUES ($1, $2, $3, $4, $5)",
+            template,
+            recipient,
+            subject,
+            status,
+            error
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Delete outbox entries older than `days`
+    pub async fn delete_outbox_older_than(pool: &PgPool, days: u64) -> Result<u64, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::delete_outbox_older_than");
+        let result = sqlx::query(
+            "DELETE FROM email_outbox WHERE created_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(days as i32)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Outbox entries, newest first, with the recipient's suppression reason
+    pub async fn list_outbox(
+        pool: &PgPool,
+        pagination: &Pagination,
+    ) -> Result<Paginated<OutboxEmail>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::list_outbox");
+        let total = sqlx::query_scalar!(r#"SELECT COUNT(*) as "count!" FROM email_outbox"#)
+            .fetch_one(pool)
+            .await?;
+
+        let rows = sqlx::query!(
+            r#"SELECT o.id, o.template, o.recipient, o.subject, o.status, o.error,
+                      s.reason AS "undeliverable?", o.created_at
+               FROM email_outbox o
+               LEFT JOIN email_suppressions s ON s.email = LOWER(o.recipient)
+               ORDER BY o.created_at DESC, o.id DESC
+               LIMIT $1 OFFSET $2"#,
+            pagination.sql_limit(),
+            pagination.sql_offset()
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let emails = rows
+            .into_iter()
+            .map(|row| OutboxEmail {
+                id: row.id,
+                template: row.template,
+                recipient: row.recipient,
+                subject: row.subject,
+                status: row.status,
+                error: row.error,
+                undeliverable: row.undeliverable,
+                created_at: time_to_chrono(row.created_at),
+            })
+            .collect();
+
+        Ok(Paginated::new(emails, total, pagination))
+    }
+
+    /// Stop sending to an address; a later report replaces the earlier one
+    pub async fn suppress(
+        pool: &PgPool,
+        email: &str,
+        reason: &str,
+        provider: &str,
+        detail: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::suppress");
+        sqlx::query!(
+            "INSERT INTO email_suppressions (email, reason, provider, detail)
+             VALUES (LOWER($1), $2, $3, $4)
+             ON CONFLICT (email) DO UPDATE
+             SET reason = EXCLUDED.reason, provider = EXCLUDED.provider,
+                 detail = EXCLUDED.detail, created_at = NOW()",
+            email,
+            reason,
+            provider,
+            detail
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Allow sending to an address again; returns false if it was not suppressed
+    pub async fn unsuppress(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::unsuppress");
+        let result = sqlx::query!(
+            "DELETE FROM email_suppressions WHERE email = LOWER($1)",
+            email
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Suppression reason for an address, if it is suppressed
+    pub async fn suppression_reason(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::suppression_reason");
+        sqlx::query_scalar!(
+            "SELECT reason FROM email_suppressions WHERE email = LOWER($1)",
+            email
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Suppression reasons keyed by lowercased address, for a set of addresses
+    pub async fn suppression_reasons(
+        pool: &PgPool,
+        emails: &[String],
+    ) -> Result<HashMap<String, String>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::suppression_reasons");
+        let emails: Vec<String> = emails.iter().map(|email| email.to_lowercase()).collect();
+        let rows = sqlx::query!(
+            "SELECT email, reason FROM email_suppressions WHERE email = ANY($1)",
+            &emails
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.email, row.reason))
+            .collect())
+    }
+
+    /// Every suppressed address, newest first
+    pub async fn list_suppressions(pool: &PgPool) -> Result<Vec<EmailSuppression>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailDeliveryService::list_suppressions");
+        let rows = sqlx::query!(
+            "SELECT email, reason, provider, detail, created_at
+             FROM email_suppressions
+             ORDER BY created_at DESC"
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| EmailSuppression {
+                email: row.email,
+                reason: row.reason,
+                provider: row.provider,
+                detail: row.detail,
+                created_at: time_to_chrono(row.created_at),
+            })
+            .collect())
+    }
+}
+
+// =============================================================================
+// Preferences Service
+// =============================================================================
+
+/// Per-user preferences: typed values in `user_preferences`, plus the
+/// language and time zone kept on `users`
+pub struct PreferencesService;
+
+impl PreferencesService {
+    /// A user's preferences, with defaults for those not set
+    pub async fn get(pool: &PgPool, user_id: i32) -> Result<Preferences, sqlx::Error> {
+        let _timer = QueryTimer::start("PreferencesService::get");
+        let stored: HashMap<String, serde_json::Value> =
+            sqlx::query_as::<_, (String, serde_json::Value)>(
+                "SELECT key, value FROM user_preferences WHERE user_id = $1",
+            )
+            .bind(user_id)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+        let locale = UserService::get_locale(pool, user_id).await?;
+        let zone = UserService::get_timezone(pool, user_id).await?;
+        Ok(preferences::resolve(
+            &stored,
+            locale.as_deref(),
+            zone.as_deref(),
+        ))
+    }
+
+    /// Save one preference
+    pub async fn set(
+        pool: &PgPool,
+        user_id: i32,
+        preference: &Preference,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("PreferencesService::set");
+        match preference {
+            Preference::Locale(locale) => {
+                UserService::set_locale(pool, user_id, Some(locale.as_str())).await?;
+            }
+            Preference::Timezone(zone) => {
+                UserService::set_timezone(pool, user_id, zone.as_deref()).await?;
+            }
+            Preference::Theme(_) | Preference::ItemsPerPage(_) => {
+                sqlx::query(
+                    "INSERT INTO user_preferences (user_id, key, value)
+                     VALUES ($1, $2, $3)
+                     ON CONFLICT (user_id, key) DO UPDATE
+                     SET value = EXCLUDED.value, updated_at = NOW()",
+                )
+                .bind(user_id)
+                .bind(preference.key())
+                .bind(preference.to_json())
+                .execute(pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget a user's value for `key`, so its default applies again
+    pub async fn reset(pool: &PgPool, user_id: i32, key: &str) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("PreferencesService::reset");
+        match key {
+            preferences::LOCALE => {
+                UserService::set_locale(pool, user_id, None).await?;
+            }
+            preferences::TIMEZONE => {
+                UserService::set_timezone(pool, user_id, None).await?;
+            }
+            _ => {
+                sqlx::query("DELETE FROM user_preferences WHERE user_id = $1 AND key = $2")
+                    .bind(user_id)
+                    .bind(key)
+                    .execute(pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// =============================================================================
+// Notification Service
+// =============================================================================
+
+/// In-app notifications, read and marked read by the user they were sent to
+pub struct NotificationService;
+
+#[allow(dead_code)]
+impl NotificationService {
+    /// Send a notification to a user
+    pub async fn create(
+        pool: &PgPool,
+        user_id: i32,
+        notification: &NewNotification,
+    ) -> Result<Notification, sqlx::Error> {
+        let _timer = QueryTimer::start("NotificationService::create");
+        sqlx::query_as(&format!(
+            "INSERT INTO notifications (user_id, kind, title, body, link)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING {}",
+            NOTIFICATION_COLUMNS
+        ))
+        .bind(user_id)
+        .bind(&notification.kind)
+        .bind(&notification.title)
+        .bind(&notification.body)
+        .bind(&notification.link)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// A user's notifications, newest first; only unread ones when
+    /// `unread_only`
+    pub async fn list(
+        pool: &PgPool,
+        user_id: i32,
+        unread_only: bool,
+        pagination: &Pagination,
+    ) -> Result<Paginated<Notification>, sqlx::Error> {
+        let _timer = QueryTimer::start("NotificationService::list");
+        const WHERE: &str = "WHERE user_id = $1 AND (NOT $2 OR read_at IS NULL)";
+
+        let total: i64 =
+            sqlx::query_scalar(&format!("SELECT COUNT(*) FROM notifications {}", WHERE))
+                .bind(user_id)
+                .bind(unread_only)
+                .fetch_one(pool)
+                .await?;
+
+        let notifications = sqlx::query_as(&format!(
+            "SELECT {} FROM notifications {}
+             ORDER BY created_at DESC, id DESC
+             LIMIT $3 OFFSET $4",
+            NOTIFICATION_COLUMNS, WHERE
+        ))
+        .bind(user_id)
+        .bind(unread_only)
+        .bind(pagination.sql_limit())
+        .bind(pagination.sql_offset())
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Paginated::new(notifications, total, pagination))
+    }
+
+    /// How many of a user's notifications are unread
+    pub async fn unread_count(pool: &PgPool, user_id: i32) -> Result<i64, sqlx::Error> {
+        let _timer = QueryTimer::start("NotificationService::unread_count");
+        sqlx::query_scalar(
+            "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Mark one of a user's notifications read. Returns false if they have
+    /// no notification `id`; reading one twice keeps the first time.
+    pub async fn mark_read(pool: &PgPool, user_id: i32, id: i64) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("NotificationService::mark_read");
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = COALESCE(read_at, NOW())
+             WHERE id = $1 AND user_id = $2",
+        )
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Mark all of a user's notifications read; returns how many were unread
+    pub async fn mark_all_read(pool: &PgPool, user_id: i32) -> Result<u64, sqlx::Error> {
+        let _timer = QueryTimer::start("NotificationService::mark_all_read");
+        let result = sqlx::query(
+            "UPDATE notifications SET read_at = NOW()
+             WHERE user_id = $1 AND read_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// Columns read into [`Notification`]
+const NOTIFICATION_COLUMNS: &str = "id, kind, title, body, link, read_at, created_at";
+
+// =============================================================================
+// Email Preference Service
+// =============================================================================
+
+/// Per-user email subscriptions, by category key. Categories without a
+/// stored choice use the caller's default.
+pub struct EmailPreferenceService;
+
+#[allow(dead_code)]
+impl EmailPreferenceService {
+    /// A user's stored choices, keyed by category
+    pub async fn choices(
+        pool: &PgPool,
+        user_id: i32,
+    ) -> Result<HashMap<String, bool>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailPreferenceService::choices");
+        let rows = sqlx::query!(
+            "SELECT category, subscribed FROM email_preferences WHERE user_id = $1",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.category, row.subscribed))
+            .collect())
+    }
+
+    /// Subscribe a user to a category or unsubscribe them
+    pub async fn set(
+        pool: &PgPool,
+        user_id: i32,
+        category: &str,
+        subscribed: bool,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("EmailPreferenceService::set");
+        sqlx::query!(
+            "INSERT INTO email_preferences (user_id, category, subscribed)
+             VALUES ($1, $2, $3)
+             ON CONFLICT (user_id, category) DO UPDATE
+             SET subscribed = EXCLUDED.subscribed, updated_at = NOW()",
+            user_id,
+            category,
+            subscribed
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Whether the account with this address wants a category. Addresses of
+    /// no account, and accounts without a stored choice, get `default`.
+    pub async fn is_subscribed(
+        pool: &PgPool,
+        email: &str,
+        category: &str,
+        default: bool,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailPreferenceService::is_subscribed");
+        let subscribed = sqlx::query_scalar!(
+            "SELECT p.subscribed
+             FROM users u
+             JOIN email_preferences p ON p.user_id = u.id AND p.category = $2
+             WHERE LOWER(u.email) = LOWER($1)",
+            email,
+            category
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(subscribed.unwrap_or(default))
+    }
+
+    /// Unsubscribe token of the account with this address
+    pub async fn unsubscribe_token(
+        pool: &PgPool,
+        email: &str,
+    ) -> Result<Option<uuid::Uuid>, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailPreferenceService::unsubscribe_token");
+        sqlx::query_scalar!(
+            "SELECT unsubscribe_token FROM users WHERE LOWER(email) = LOWER($1)",
+            email
+        )
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Unsubscribe the holder of a token from a category; returns false if no
+    /// account has the token
+    pub async fn unsubscribe(
+        pool: &PgPool,
+        token: uuid::Uuid,
+        category: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("EmailPreferenceService::unsubscribe");
+        let result = sqlx::query!(
+            "INSERT INTO email_preferences (user_id, category, subscribed)
+             SELECT id, $2, FALSE FROM users WHERE unsubscribe_token = $1
+             ON CONFLICT (user_id, category) DO UPDATE
+             SET subscribed = FALSE, updated_at = NOW()",
+            token,
+            category
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+// =============================================================================
+// Upload Service
+// =============================================================================
+
+/// Metadata of uploaded files; contents are kept by the storage backend
+pub struct UploadService;
+
+#[allow(dead_code)]
+impl UploadService {
+    /// Record a stored file
+    pub async fn create(
+        pool: &PgPool,
+        user_id: i32,
+        filename: &str,
+        content_type: &str,
+        size_bytes: i64,
+        storage_key: &str,
+    ) -> Result<Upload, sqlx::Error> {
+        let _timer = QueryTimer::start("UploadService::create");
+        let row = sqlx::query!(
+            "INSERT INTO uploads (user_id, filename, content_type, size_bytes, storage_key)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING id, created_at",
+            user_id,
+            filename,
+            content_type,
+            size_bytes,
+            storage_key
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(Upload {
+            id: row.id,
+            user_id,
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            size_bytes,
+            storage_key: storage_key.to_string(),
+            created_at: time_to_chrono(row.created_at),
+        })
+    }
+
+    /// Look up an upload by ID
+    pub async fn find(pool: &PgPool, id: uuid::Uuid) -> Result<Option<Upload>, sqlx::Error> {
+        let _timer = QueryTimer::start("UploadService::find");
+        let row = sqlx::query!(
+            "SELECT id, user_id, filename, content_type, size_bytes, storage_key, created_at
+             FROM uploads WHERE id = $1",
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| Upload {
+            id: row.id,
+            user_id: row.user_id,
+            filename: row.filename,
+            content_type: row.content_type,
+            size_bytes: row.size_bytes,
+            storage_key: row.storage_key,
+            created_at: time_to_chrono(row.created_at),
+        }))
+    }
+
+    /// A user's uploads, newest first
+    pub async fn list_for_user(pool: &PgPool, user_id: i32) -> Result<Vec<Upload>, sqlx::Error> {
+        let _timer = QueryTimer::start("UploadService::list_for_user");
+        let rows = sqlx::query!(
+            "SELECT id, user_id, filename, content_type, size_bytes, storage_key, created_at
+             FROM uploads WHERE user_id = $1
+             ORDER BY created_at DESC",
+            user_id
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Upload {
+                id: row.id,
+                user_id: row.user_id,
+                filename: row.filename,
+                content_type: row.content_type,
+                size_bytes: row.size_bytes,
+                storage_key: row.storage_key,
+                created_at: time_to_chrono(row.created_at),
+            })
+            .collect())
+    }
+
+    /// Delete an upload's record; returns false if it did not exist
+    pub async fn delete(pool: &PgPool, id: uuid::Uuid) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("UploadService::delete");
+        let result = sqlx::query!("DELETE FROM uploads WHERE id = $1", id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+// =============================================================================
+// Attachment Service
+// =============================================================================
+
+/// Metadata of files attached to items; contents are kept by the storage
+/// backend
+pub struct AttachmentService;
+
+#[allow(dead_code)]
+impl AttachmentService {
+    /// Record a stored file attached to an item
+    pub async fn create(
+        pool: &PgPool,
+        item_id: i32,
+        user_id: i32,
+        filename: &str,
+        content_type: &str,
+        size_bytes: i64,
+        storage_key: &str,
+    ) -> Result<ItemAttachment, sqlx::Error> {
+        let _timer = QueryTimer::start("AttachmentService::create");
+        sqlx::query_as(&format!(
+            "INSERT INTO item_attachments
+                 (item_id, user_id, filename, content_type, size_bytes, storage_key)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             RETURNING {}",
+            ATTACHMENT_COLUMNS
+        ))
+        .bind(item_id)
+        .bind(user_id)
+        .bind(filename)
+        .bind(content_type)
+        .bind(size_bytes)
+        .bind(storage_key)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Look up an attachment by ID
+    pub async fn find(
+        pool: &PgPool,
+        id: uuid::Uuid,
+    ) -> Result<Option<ItemAttachment>, sqlx::Error> {
+        let _timer = QueryTimer::start("AttachmentService::find");
+        sqlx::query_as(&format!(
+            "SELECT {} FROM item_attachments WHERE id = $1",
+            ATTACHMENT_COLUMNS
+        ))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// An item's attachments, oldest first
+    pub async fn list_for_item(
+        pool: &PgPool,
+        item_id: i32,
+    ) -> Result<Vec<ItemAttachment>, sqlx::Error> {
+        let _timer = QueryTimer::start("AttachmentService::list_for_item");
+        sqlx::query_as(&format!(
+            "SELECT {} FROM item_attachments
+             WHERE item_id = $1
+             ORDER BY created_at, id",
+            ATTACHMENT_COLUMNS
+        ))
+        .bind(item_id)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Delete an attachment's record; returns false if it did not exist
+    pub async fn delete(pool: &PgPool, id: uuid::Uuid) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("AttachmentService::delete");
+        let result = sqlx::query("DELETE FROM item_attachments WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Columns read into [`ItemAttachment`]
+const ATTACHMENT_COLUMNS: &str =
+    "id, item_id, user_id, filename, content_type, size_bytes, storage_key, created_at";
+
+// =============================================================================
+// Job Service
+// =============================================================================
+
+pub struct JobService;
+
+#[allow(dead_code)]
+impl JobService {
+    /// Add a job with a priority level, due at `run_at` (now if `None`)
+    pub async fn enqueue(
+        pool: &PgPool,
+        kind: &str,
+        payload: &serde_json::Value,
+        priority: i16,
+        run_at: Option<DateTime<Utc>>,
+    ) -> Result<i64, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::enqueue");
+        sqlx::query_scalar!(
+            "INSERT INTO jobs (kind, payload, priority, run_at)
+             VALUES ($1, $2, $3, COALESCE($4, NOW()))
+             RETURNING id",
+            kind,
+            payload.to_string(),
+            priority,
+            run_at.map(chrono_to_time)
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Claim up to `limit` due jobs for a worker, counting an attempt for
+    /// each. Higher priorities go first, but a due job gains one level for
+    /// every `aging_secs` it has waited, so low priorities are not starved.
+    /// Jobs locked by another worker's claim are skipped.
+    pub async fn claim(
+        pool: &PgPool,
+        worker: &str,
+        limit: i64,
+        aging_secs: u64,
+    ) -> Result<Vec<Job>, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::claim");
+        let rows = sqlx::query!(
+            "UPDATE jobs
+             SET status = 'running', attempts = attempts + 1, locked_by = $1, locked_at = NOW(),
+                 heartbeat_at = NOW()
+             WHERE id IN (
+                 SELECT id FROM jobs
+                 WHERE status = 'queued' AND run_at <= NOW()
+                 ORDER BY priority + FLOOR(EXTRACT(EPOCH FROM NOW() - run_at) / $3) DESC,
+                          run_at, id
+                 LIMIT $2
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING id, kind, payload, priority, status, attempts, max_attempts, last_error,
+                       locked_by, run_at, created_at, finished_at",
+            worker,
+            limit,
+            aging_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Job {
+                id: row.id,
+                kind: row.kind,
+                payload: serde_json::from_str(&row.payload).unwrap_or_default(),
+                priority: row.priority,
+                status: row.status,
+                attempts: row.attempts,
+                max_attempts: row.max_attempts,
+                last_error: row.last_error,
+                locked_by: row.locked_by,
+                run_at: time_to_chrono(row.run_at),
+                created_at: time_to_chrono(row.created_at),
+                finished_at: time_opt_to_chrono_opt(row.finished_at),
+            })
+            .collect())
+    }
+
+    /// Mark a job finished. Returns false if the job is no longer the
+    /// worker's, because the watchdog requeued it in the meantime.
+    pub async fn complete(pool: &PgPool, worker: &str, id: i64) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::complete");
+        let result = sqlx::query!(
+            "UPDATE jobs
+             SET status = 'done', locked_by = NULL, locked_at = NULL, heartbeat_at = NULL,
+                 finished_at = NOW()
+             WHERE id = $2 AND locked_by = $1 AND status = 'running'",
+            worker,
+            id
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record a failed run: retry after a delay that grows with each attempt,
+    /// or give up once the job has used all its attempts. Returns false if
+    /// the job is no longer the worker's.
+    pub async fn fail(
+        pool: &PgPool,
+        worker: &str,
+        id: i64,
+        error: &str,
+    ) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::fail");
+        let result = sqlx::query!(
+            "UPDATE jobs
+             SET status = CASE WHEN attempts >= max_attempts THEN 'failed' ELSE 'queued' END,
+                 run_at = CASE WHEN attempts >= max_attempts THEN run_at
+                               ELSE NOW() + attempts * INTERVAL '30 seconds' END,
+                 finished_at = CASE WHEN attempts >= max_attempts THEN NOW() END,
+                 last_error = $3, locked_by = NULL, locked_at = NULL, heartbeat_at = NULL
+             WHERE id = $2 AND locked_by = $1 AND status = 'running'",
+            worker,
+            id,
+            error
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Hand a worker's unfinished jobs back to the queue. The interrupted
+    /// run does not count as an attempt.
+    pub async fn release(pool: &PgPool, worker: &str, ids: &[i64]) -> Result<u64, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::release");
+        let result = sqlx::query!(
+            "UPDATE jobs
+             SET status = 'queued', attempts = attempts - 1, locked_by = NULL, locked_at = NULL,
+                 heartbeat_at = NULL
+             WHERE id = ANY($2) AND locked_by = $1 AND status = 'running'",
+            worker,
+            ids
+        )
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Record that a worker is alive and still running the jobs in `running`
+    pub async fn heartbeat(
+        pool: &PgPool,
+        worker: &str,
+        hostname: &str,
+        running: &[i64],
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::heartbeat");
+        let mut tx = pool.begin().await?;
+        sqlx::query!(
+            "INSERT INTO job_workers (id, hostname, running_jobs) VALUES ($1, $2, $3)
+             ON CONFLICT (id) DO UPDATE
+             SET running_jobs = EXCLUDED.running_jobs, heartbeat_at = NOW(), stalled_at = NULL",
+            worker,
+            hostname,
+            running.len() as i32
+        )
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query!(
+            "UPDATE jobs SET heartbeat_at = NOW()
+             WHERE id = ANY($2) AND locked_by = $1 AND status = 'running'",
+            worker,
+            running
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await
+    }
+
+    /// Remove a worker that is shutting down
+    pub async fn deregister(pool: &PgPool, worker: &str) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::deregister");
+        sqlx::query!("DELETE FROM job_workers WHERE id = $1", worker)
+            .execute(pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Mark workers without a heartbeat for `timeout_secs` as stalled and
+    /// return the ones not marked before. Workers stalled for a day are
+    /// forgotten.
+    pub async fn mark_stalled_workers(
+        pool: &PgPool,
+        timeout_secs: u64,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::mark_stalled_workers");
+        sqlx::query!("DELETE FROM job_workers WHERE heartbeat_at < NOW() - INTERVAL '1 day'")
+            .execute(pool)
+            .await?;
+        sqlx::query_scalar!(
+            "UPDATE job_workers SET stalled_at = NOW()
+             WHERE stalled_at IS NULL AND heartbeat_at < NOW() - make_interval(secs => $1)
+             RETURNING id",
+            timeout_secs as f64
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Take back running jobs without a heartbeat for `timeout_secs`: they
+    /// are queued again, or failed if the lost run was their last attempt.
+    /// The lost run counts as an attempt, so a job that kills its worker
+    /// cannot do so forever.
+    pub async fn requeue_stalled(
+        pool: &PgPool,
+        timeout_secs: u64,
+    ) -> Result<Vec<Job>, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::requeue_stalled");
+        let rows = sqlx::query!(
+            "UPDATE jobs
+             SET status = CASE WHEN attempts >= max_attempts THEN 'failed' ELSE 'queued' END,
+                 finished_at = CASE WHEN attempts >= max_attempts THEN NOW() END,
+                 last_error = 'worker ' || COALESCE(locked_by, 'unknown')
+                              || ' stopped sending heartbeats',
+                 locked_by = NULL, locked_at = NULL, heartbeat_at = NULL
+             WHERE status = 'running'
+               AND COALESCE(heartbeat_at, locked_at, run_at) < NOW() - make_interval(secs => $1)
+             RETURNING id, kind, payload, priority, status, attempts, max_attempts, last_error,
+                       locked_by, run_at, created_at, finished_at",
+            timeout_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Job {
+                id: row.id,
+                kind: row.kind,
+                payload: serde_json::from_str(&row.payload).unwrap_or_default(),
+                priority: row.priority,
+                status: row.status,
+                attempts: row.attempts,
+                max_attempts: row.max_attempts,
+                last_error: row.last_error,
+                locked_by: row.locked_by,
+                run_at: time_to_chrono(row.run_at),
+                created_at: time_to_chrono(row.created_at),
+                finished_at: time_opt_to_chrono_opt(row.finished_at),
+            })
+            .collect())
+    }
+
+    /// Every known worker, newest first, with `alive` judged by `timeout_secs`
+    pub async fn list_workers(
+        pool: &PgPool,
+        timeout_secs: u64,
+    ) -> Result<Vec<JobWorker>, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::list_workers");
+        let rows = sqlx::query!(
+            r#"SELECT id, hostname, running_jobs, started_at, heartbeat_at,
+                      heartbeat_at >= NOW() - make_interval(secs => $1) AS "alive!"
+               FROM job_workers
+               ORDER BY started_at DESC, id"#,
+            timeout_secs as f64
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| JobWorker {
+                id: row.id,
+                hostname: row.hostname,
+                running_jobs: row.running_jobs,
+                started_at: time_to_chrono(row.started_at),
+                heartbeat_at: time_to_chrono(row.heartbeat_at),
+                alive: row.alive,
+            })
+            .collect())
+    }
+
+    /// Number of jobs in each status
+    pub async fn count_by_status(pool: &PgPool) -> Result<Vec<(String, i64)>, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::count_by_status");
+        let rows = sqlx::query!(r#"SELECT status, COUNT(*) AS "count!" FROM jobs GROUP BY status"#)
+            .fetch_all(pool)
+            .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.status, row.count))
+            .collect())
+    }
+
+    /// Delete jobs that finished, done or failed, more than `days` ago
+    pub async fn delete_finished(pool: &PgPool, days: u64) -> Result<u64, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::delete_finished");
+        let result = sqlx::query(
+            "DELETE FROM jobs
+             WHERE status IN ('done', 'failed') AND finished_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(days as i32)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// One job by ID
+    pub async fn get(pool: &PgPool, id: i64) -> Result<Option<Job>, sqlx::Error> {
+        let _timer = QueryTimer::start("JobService::get");
+        let row = sqlx::query!(
+            "SELECT id, kind, payload, priority, status, attempts, max_attempts, last_error,
+                    locked_by, run_at, created_at, finished_at
+             FROM jobs WHERE id = $1",
+            id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(row.map(|row| Job {
+            id: row.id,
+            kind: row.kind,
+            payload: serde_json::from_str(&row.payload).unwrap_or_default(),
+            priority: row.priority,
+            status: row.status,
+            attempts: row.attempts,
+            max_attempts: row.max_attempts,
+            last_error: row.last_error,
+            locked_by: row.locked_by,
+            run_at: time_to_chrono(row.run_at),
+            created_at: time_to_chrono(row.created_at),
+            finished_at: time_opt_to_chrono_opt(row.finished_at),
+        }))
+    }
+}
+
+// =============================================================================
+// SQL Console Service
+// =============================================================================
+
+pub struct SqlConsoleService;
+
+impl SqlConsoleService {
+    /// Run a single query in a read-only transaction that is always rolled back.
+    ///
+    /// At most `max_rows` rows are returned and the statement is cancelled
+    /// after `timeout_ms`. Only row-returning queries (SELECT, WITH, VALUES,
+    /// TABLE) can run, because the query is wrapped as a subquery.
+    pub async fn run_read_only(
+        pool: &PgPool,
+        query: &str,
+        max_rows: usize,
+        timeout_ms: u64,
+    ) -> Result<SqlQueryResult, sqlx::Error> {
+        let _timer = QueryTimer::start("SqlConsoleService::run_read_only");
+        let started = Instant::now();
+        let mut tx = pool.begin().await?;
+        sqlx::query("SET TRANSACTION READ ONLY")
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(&format!("SET LOCAL statement_timeout = {}", timeout_ms))
+            .execute(&mut *tx)
+            .await?;
+
+        // Column names in query order; rows come back as JSON objects
+        let described = (&mut *tx).describe(query).await?;
+        let columns: Vec<String> = described
+            .columns()
+            .iter()
+            .map(|column| sqlx::Column::name(column).to_string())
+            .collect();
+
+        // The newline keeps a trailing `--` comment from swallowing the wrapper
+        let wrapped = format!(
+            "SELECT row_to_json(q)::text FROM ({}\n) q LIMIT {}",
+            query,
+            max_rows + 1
+        );
+        let mut json_rows: Vec<String> = sqlx::query_scalar(&wrapped).fetch_all(&mut *tx).await?;
+        tx.rollback().await?;
+
+        let truncated = json_rows.len() > max_rows;
+        json_rows.truncate(max_rows);
+
+        let rows: Vec<Vec<serde_json::Value>> = json_rows
+            .iter()
+            .map(|json| {
+                let object: serde_json::Map<String, serde_json::Value> =
+                    serde_json::from_str(json).unwrap_or_default();
+                columns
+                    .iter()
+                    .map(|column| object.get(column).cloned().unwrap_or_default())
+                    .collect()
+            })
+            .collect();
+
+        Ok(SqlQueryResult {
+            columns,
+            row_count: rows.len(),
+            rows,
+            truncated,
+            duration_ms: started.elapsed().as_millis() as u64,
+        })
+    }
+
+    /// Record a console query in the audit log
+    pub async fn log_query(
+        pool: &PgPool,
+        user_id: i32,
+        query: &str,
+        row_count: Option<i32>,
+        error: Option<&str>,
+        duration_ms: i32,
+    ) -> Result<(), sqlx::Error> {
+        let _timer = QueryTimer::start("SqlConsoleService::log_query");
+        sqlx::query!(
+            "INSERT INTO sql_console_log (user_id, query, row_count, error, duration_ms)
+             VALUES ($1, $2, $3, $4, $5)",
+            user_id,
+            query,
+            row_count,
+            error,
+            duration_ms
+        )
+        .execute(pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+// =============================================================================
+// Audit Service
+// =============================================================================
+
+/// The audit log of security-relevant actions; see [`crate::audit`]
+pub struct AuditService;
+
+#[allow(dead_code)]
+impl AuditService {
+    /// Append an entry
+    pub async fn record(
+        pool: &PgPool,
+        actor_id: Option<i32>,
+        action: &str,
+        target_type: Option<&str>,
+        target_id: Option<&str>,
+        details: &serde_json::Value,
+    ) -> Result<i64, sqlx::Error> {
+        let _timer = QueryTimer::start("AuditService::record");
+        sqlx::query_scalar(
+            "INSERT INTO audit_log (actor_id, action, target_type, target_id, details)
+             VALUES ($1, $2, $3, $4, $5::jsonb)
+             RETURNING id",
+        )
+        .bind(actor_id)
+        .bind(action)
+        .bind(target_type)
+        .bind(target_id)
+        .bind(details.to_string())
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Entries matching `filter`, newest first
+    pub async fn list(
+        pool: &PgPool,
+        filter: &AuditFilter,
+        pagination: &Pagination,
+    ) -> Result<Paginated<AuditEntry>, sqlx::Error> {
+        let _timer = QueryTimer::start("AuditService::list");
+        const WHERE: &str = "WHERE ($1::int IS NULL OR a.actor_id = $1)
+               AND ($2::text IS NULL OR a.action = $2)
+               AND ($3::text IS NULL OR a.target_type = $3)
+               AND ($4::text IS NULL OR a.target_id = $4)";
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM audit_log a {}", WHERE))
+            .bind(filter.actor_id)
+            .bind(&filter.action)
+            .bind(&filter.target_type)
+            .bind(&filter.target_id)
+            .fetch_one(pool)
+            .await?;
+
+        let rows: Vec<AuditRow> = sqlx::query_as(&format!(
+            "SELECT a.id, a.actor_id, u.username AS actor_username, a.action, a.target_type,
+                    a.target_id, a.details::text AS details, a.created_at
+             FROM audit_log a
+             LEFT JOIN users u ON u.id = a.actor_id
+             {}
+             ORDER BY a.created_at DESC, a.id DESC
+             LIMIT $5 OFFSET $6",
+            WHERE
+        ))
+        .bind(filter.actor_id)
+        .bind(&filter.action)
+        .bind(&filter.target_type)
+        .bind(&filter.target_id)
+        .bind(pagination.sql_limit())
+        .bind(pagination.sql_offset())
+        .fetch_all(pool)
+        .await?;
+
+        let entries = rows.into_iter().map(AuditEntry::from).collect();
+
+        Ok(Paginated::new(entries, total, pagination))
+    }
+
+    /// A keyset page of entries matching `filter`, newest first, starting
+    /// after `after`
+    pub async fn list_after(
+        pool: &PgPool,
+        filter: &AuditFilter,
+        after: Option<Cursor>,
+        pagination: &Pagination,
+    ) -> Result<CursorPage<AuditEntry>, sqlx::Error> {
+        let _timer = QueryTimer::start("AuditService::list_after");
+        let rows: Vec<AuditRow> = sqlx::query_as(
+            "SELECT a.id, a.actor_id, u.username AS actor_username, a.action, a.target_type,
+                    a.target_id, a.details::text AS details, a.created_at
+             FROM audit_log a
+             LEFT JOIN users u ON u.id = a.actor_id
+             WHERE ($1::int IS NULL OR a.actor_id = $1)
+               AND ($2::text IS NULL OR a.action = $2)
+               AND ($3::text IS NULL OR a.target_type = $3)
+               AND ($4::text IS NULL OR a.target_id = $4)
+               AND ($5::timestamptz IS NULL OR (a.created_at, a.id) < ($5, $6))
+             ORDER BY a.created_at DESC, a.id DESC
+             LIMIT $7",
+        )
+        .bind(filter.actor_id)
+        .bind(&filter.action)
+        .bind(&filter.target_type)
+        .bind(&filter.target_id)
+        .bind(after.map(|cursor| cursor.created_at))
+        .bind(after.map(|cursor| cursor.id))
+        .bind(pagination.sql_limit() + 1)
+        .fetch_all(pool)
+        .await?;
+
+        let entries = rows.into_iter().map(AuditEntry::from).collect();
+        Ok(CursorPage::new(
+            entries,
+            pagination,
+            |entry: &AuditEntry| Cursor::new(entry.created_at, entry.id),
+        ))
+    }
+
+    /// Entries of `actions` a user took or that were taken on their
+    /// account, newest first
+    pub async fn list_for_user(
+        pool: &PgPool,
+        user_id: i32,
+        actions: &[&str],
+        pagination: &Pagination,
+    ) -> Result<Paginated<AuditEntry>, sqlx::Error> {
+        let _timer = QueryTimer::start("AuditService::list_for_user");
+        const WHERE: &str = "WHERE (a.actor_id = $1
+                    OR (a.target_type = 'user' AND a.target_id = $1::text))
+               AND a.action = ANY($2)";
+
+        let total: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM audit_log a {}", WHERE))
+            .bind(user_id)
+            .bind(actions)
+            .fetch_one(pool)
+            .await?;
+
+        let rows: Vec<AuditRow> = sqlx::query_as(&format!(
+            "SELECT a.id, a.actor_id, u.username AS actor_username, a.action, a.target_type,
+                    a.target_id, a.details::text AS details, a.created_at
+             FROM audit_log a
+             LEFT JOIN users u ON u.id = a.actor_id
+             {}
+             ORDER BY a.created_at DESC, a.id DESC
+             LIMIT $3 OFFSET $4",
+            WHERE
+        ))
+        .bind(user_id)
+        .bind(actions)
+        .bind(pagination.sql_limit())
+        .bind(pagination.sql_offset())
+        .fetch_all(pool)
+        .await?;
+
+        let entries = rows.into_iter().map(AuditEntry::from).collect();
+
+        Ok(Paginated::new(entries, total, pagination))
+    }
+
+    /// Delete entries older than `days`
+    pub async fn delete_older_than(pool: &PgPool, days: u64) -> Result<u64, sqlx::Error> {
+        let _timer = QueryTimer::start("AuditService::delete_older_than");
+        let result = sqlx::query(
+            "DELETE FROM audit_log WHERE created_at < NOW() - make_interval(days => $1)",
+        )
+        .bind(days as i32)
+        .execute(pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+/// `audit_log` row with `details` read as text, as sqlx is built without JSON
+#[derive(sqlx::FromRow)]
+struct AuditRow {
+    id: i64,
+    actor_id: Option<i32>,
+    actor_username: Option<String>,
+    action: String,
+    target_type: Option<String>,
+    target_id: Option<String>,
+    details: String,
+    created_at: DateTime<Utc>,
+}
+
+impl From<AuditRow> for AuditEntry {
+    fn from(row: AuditRow) -> Self {
+        AuditEntry {
+            id: row.id,
+            actor_id: row.actor_id,
+            actor_username: row.actor_username,
+            action: row.action,
+            target_type: row.target_type,
+            target_id: row.target_id,
+            details: serde_json::from_str(&row.details).unwrap_or_default(),
+            created_at: row.created_at,
+        }
+    }
+}
+
+// =============================================================================
+// Snippet Service
+// =============================================================================
+
+/// Admin-written snippets; see [`crate::snippets`]
+pub struct SnippetService;
+
+#[allow(dead_code)]
+impl SnippetService {
+    /// Every snippet, by name
+    pub async fn list(pool: &PgPool) -> Result<Vec<Snippet>, sqlx::Error> {
+        let _timer = QueryTimer::start("SnippetService::list");
+        sqlx::query_as(
+            "SELECT id, name, description, body, updated_by, created_at, updated_at
+             FROM snippets
+             ORDER BY name",
+        )
+        .fetch_all(pool)
+        .await
+    }
+
+    /// Snippets whose name starts with `prefix`, by name
+    pub async fn with_prefix(pool: &PgPool, prefix: &str) -> Result<Vec<Snippet>, sqlx::Error> {
+        let _timer = QueryTimer::start("SnippetService::with_prefix");
+        sqlx::query_as(
+            "SELECT id, name, description, body, updated_by, created_at, updated_at
+             FROM snippets
+             WHERE starts_with(name, $1)
+             ORDER BY name",
+        )
+        .bind(prefix)
+        .fetch_all(pool)
+        .await
+    }
+
+    /// A snippet by name
+    pub async fn get(pool: &PgPool, name: &str) -> Result<Option<Snippet>, sqlx::Error> {
+        let _timer = QueryTimer::start("SnippetService::get");
+        sqlx::query_as(
+            "SELECT id, name, description, body, updated_by, created_at, updated_at
+             FROM snippets
+             WHERE name = $1",
+        )
+        .bind(name)
+        .fetch_optional(pool)
+        .await
+    }
+
+    /// Create or replace a snippet
+    pub async fn save(
+        pool: &PgPool,
+        name: &str,
+        description: Option<&str>,
+        body: &str,
+        updated_by: i32,
+    ) -> Result<Snippet, sqlx::Error> {
+        let _timer = QueryTimer::start("SnippetService::save");
+        sqlx::query_as(
+            "INSERT INTO snippets (name, description, body, updated_by)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (name) DO UPDATE
+             SET description = EXCLUDED.description, body = EXCLUDED.body,
+                 updated_by = EXCLUDED.updated_by, updated_at = NOW()
+             RETURNING id, name, description, body, updated_by, created_at, updated_at",
+        )
+        .bind(name)
+        .bind(description)
+        .bind(body)
+        .bind(updated_by)
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Delete a snippet; returns false if there was none
+    pub async fn delete(pool: &PgPool, name: &str) -> Result<bool, sqlx::Error> {
+        let _timer = QueryTimer::start("SnippetService::delete");
+        let result = sqlx::query("DELETE FROM snippets WHERE name = $1")
+            .bind(name)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
     }
 }
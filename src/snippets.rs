@@ -0,0 +1,553 @@
+//! # Snippets
+//!
+//! Small Tera templates admins save in the database, such as dashboard
+//! widgets and an email footer. They are written by people, not shipped with
+//! the code, so they render in a sandbox instead of the app's Tera instance:
+//!
+//! - Each render uses a fresh engine holding only the snippet, so it cannot
+//!   include, extend, or import the app's templates.
+//! - Only the filters in [`ALLOWED_FILTERS`] and functions in
+//!   [`ALLOWED_FUNCTIONS`] may be called; `get_env`, `range`, `safe`, and the
+//!   app's own functions are rejected. Macros, blocks, and `set` are too.
+//! - The context is a [`SnippetContext`] built by the caller, never the
+//!   page's context or the session.
+//! - The source is capped at [`MAX_SOURCE_BYTES`], loops cannot nest, the
+//!   output is capped at [`MAX_OUTPUT_BYTES`], and a render still running
+//!   after [`RENDER_TIMEOUT`] is abandoned.
+//!
+//! The rules are checked when a snippet is saved and again before every
+//! render. A snippet that fails is logged and left out of the page.
+//!
+//! The name decides where a snippet shows: `dashboard.<anything>` on the home
+//! page, in name order, and `email.footer` at the end of every email. Admins
+//! manage them under `/api/admin/snippets`:
+//!
+//! ```bash
+//! curl -X PUT http://localhost:3093/api/admin/snippets/dashboard.welcome \
+//!   -H 'Content-Type: application/json' -b cookies.txt \
+//!   -d '{"body": "<p>Hello {{ username | default(value=\"there\") }}!</p>"}'
+//! ```
+
+use axum::{
+    Router,
+    extract::{Path, State},
+    routing::get,
+};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::PgPool;
+use std::time::Duration;
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+use tera::{Context, Tera};
+
+use crate::api::RequestId;
+use crate::audit::{self, AuditEvent};
+use crate::auth::AdminUser;
+use crate::emails::error_chain;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated, Payload};
+use crate::models::{Envelope, SaveSnippetRequest, Snippet};
+use crate::output;
+use crate::services::SnippetService;
+use crate::state::AppState;
+
+/// Name prefix of snippets shown on the home page
+pub const DASHBOARD_PREFIX: &str = "dashboard.";
+
+/// Snippet appended to every email
+pub const EMAIL_FOOTER: &str = "email.footer";
+
+/// Filters a snippet may use; none can grow a value by more than a small
+/// constant factor
+pub const ALLOWED_FILTERS: &[&str] = &[
+    "capitalize",
+    "date",
+    "default",
+    "filesizeformat",
+    "first",
+    "join",
+    "last",
+    "length",
+    "lower",
+    "pluralize",
+    "round",
+    "striptags",
+    "title",
+    "trim",
+    "truncate",
+    "upper",
+    "wordcount",
+];
+
+/// Functions a snippet may call
+pub const ALLOWED_FUNCTIONS: &[&str] = &["now"];
+
+/// Largest accepted snippet source
+pub const MAX_SOURCE_BYTES: usize = 8 * 1024;
+
+/// Largest output a render may produce
+pub const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// How long a render may run before it is abandoned
+pub const RENDER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Longest accepted snippet name
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Name the snippet is registered under; the suffix turns on autoescaping
+const TEMPLATE_NAME: &str = "snippet.html";
+
+/// Everything a snippet can see
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SnippetContext {
+    pub service_name: String,
+    /// Username of the signed-in user or the email's recipient
+    pub username: Option<String>,
+    /// Catalog locale of the page or email
+    pub locale: String,
+}
+
+/// A snippet rendered for a page
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedSnippet {
+    pub name: String,
+    pub html: String,
+}
+
+// =============================================================================
+// Sandbox
+// =============================================================================
+
+/// Check a snippet source against the sandbox rules
+#[allow(dead_code)]
+pub fn check(body: &str) -> Result<(), String> {
+    compile(body).map(|_| ())
+}
+
+/// Render a snippet in the sandbox
+pub async fn render(body: &str, context: &SnippetContext) -> Result<String, String> {
+    let tera = compile(body)?;
+    let context = Context::from_serialize(context).map_err(|e| error_chain(&e))?;
+
+    let render = tokio::task::spawn_blocking(move || tera.render(TEMPLATE_NAME, &context));
+    let html = match tokio::time::timeout(RENDER_TIMEOUT, render).await {
+        Ok(Ok(result)) => result.map_err(|e| error_chain(&e))?,
+        Ok(Err(e)) => return Err(format!("render failed: {}", e)),
+        Err(_) => {
+            return Err(format!(
+                "render took longer than {}s",
+                RENDER_TIMEOUT.as_secs()
+            ));
+        }
+    };
+    if html.len() > MAX_OUTPUT_BYTES {
+        return Err(format!(
+            "output is larger than {} KiB",
+            MAX_OUTPUT_BYTES / 1024
+        ));
+    }
+    Ok(html)
+}
+
+/// Render every snippet whose name starts with `prefix`, leaving out and
+/// logging those that fail
+pub async fn render_all(
+    pool: &PgPool,
+    prefix: &str,
+    context: &SnippetContext,
+) -> Vec<RenderedSnippet> {
+    let snippets = match SnippetService::with_prefix(pool, prefix).await {
+        Ok(snippets) => snippets,
+        Err(e) => {
+            output::warn(format!("Failed to load '{}' snippets: {}", prefix, e));
+            return Vec::new();
+        }
+    };
+
+    let mut rendered = Vec::new();
+    for snippet in snippets {
+        match render(&snippet.body, context).await {
+            Ok(html) => rendered.push(RenderedSnippet {
+                name: snippet.name,
+                html,
+            }),
+            Err(e) => output::warn(format!("Snippet '{}' failed: {}", snippet.name, e)),
+        }
+    }
+    rendered
+}
+
+/// Render one snippet by name, if it exists and renders
+pub async fn render_named(pool: &PgPool, name: &str, context: &SnippetContext) -> Option<String> {
+    let snippet = match SnippetService::get(pool, name).await {
+        Ok(snippet) => snippet?,
+        Err(e) => {
+            output::warn(format!("Failed to load snippet '{}': {}", name, e));
+            return None;
+        }
+    };
+    render(&snippet.body, context)
+        .await
+        .inspect_err(|e| output::warn(format!("Snippet '{}' failed: {}", name, e)))
+        .ok()
+}
+
+/// Parse a source into a fresh engine holding only it, after checking it
+/// against the rules
+fn compile(body: &str) -> Result<Tera, String> {
+    if body.len() > MAX_SOURCE_BYTES {
+        return Err(format!(
+            "snippets are limited to {} KiB",
+            MAX_SOURCE_BYTES / 1024
+        ));
+    }
+    let mut tera = Tera::default();
+    tera.add_raw_template(TEMPLATE_NAME, body)
+        .map_err(|e| error_chain(&e))?;
+    let template = tera
+        .get_template(TEMPLATE_NAME)
+        .map_err(|e| error_chain(&e))?;
+    check_nodes(&template.ast, false)?;
+    Ok(tera)
+}
+
+fn check_nodes(nodes: &[Node], in_loop: bool) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::VariableBlock(_, expr) => check_expr(expr)?,
+            Node::FilterSection(_, section, _) => {
+                check_call(&section.filter, ALLOWED_FILTERS, "filter")?;
+                check_nodes(&section.body, in_loop)?;
+            }
+            Node::Forloop(_, forloop, _) => {
+                if in_loop {
+                    return Err("loops cannot be nested in snippets".to_string());
+                }
+                check_expr(&forloop.container)?;
+                check_nodes(&forloop.body, true)?;
+                if let Some(empty_body) = &forloop.empty_body {
+                    check_nodes(empty_body, true)?;
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, expr, body) in &condition.conditions {
+                    check_expr(expr)?;
+                    check_nodes(body, in_loop)?;
+                }
+                if let Some((_, body)) = &condition.otherwise {
+                    check_nodes(body, in_loop)?;
+                }
+            }
+            Node::Set(..) => return Err("set is not allowed in snippets".to_string()),
+            Node::Extends(..) | Node::Include(..) | Node::ImportMacro(..) => {
+                return Err("snippets cannot use other templates".to_string());
+            }
+            Node::MacroDefinition(..) => {
+                return Err("macros are not allowed in snippets".to_string());
+            }
+            Node::Block(..) | Node::Super => {
+                return Err("blocks are not allowed in snippets".to_string());
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn check_expr(expr: &Expr) -> Result<(), String> {
+    for filter in &expr.filters {
+        check_call(filter, ALLOWED_FILTERS, "filter")?;
+    }
+    check_value(&expr.val)
+}
+
+fn check_value(value: &ExprVal) -> Result<(), String> {
+    match value {
+        ExprVal::FunctionCall(call) => check_call(call, ALLOWED_FUNCTIONS, "function"),
+        ExprVal::MacroCall(_) => Err("macros are not allowed in snippets".to_string()),
+        ExprVal::Math(math) => {
+            check_expr(&math.lhs)?;
+            check_expr(&math.rhs)
+        }
+        ExprVal::Logic(logic) => {
+            check_expr(&logic.lhs)?;
+            check_expr(&logic.rhs)
+        }
+        ExprVal::In(contains) => {
+            check_expr(&contains.lhs)?;
+            check_expr(&contains.rhs)
+        }
+        ExprVal::Test(test) => test.args.iter().try_for_each(check_expr),
+        ExprVal::Array(items) => items.iter().try_for_each(check_expr),
+        ExprVal::StringConcat(concat) => concat.values.iter().try_for_each(check_value),
+        _ => Ok(()),
+    }
+}
+
+fn check_call(call: &FunctionCall, allowed: &[&str], kind: &str) -> Result<(), String> {
+    if !allowed.contains(&call.name.as_str()) {
+        return Err(format!(
+            "the {} '{}' is not allowed in snippets (allowed: {})",
+            kind,
+            call.name,
+            allowed.join(", ")
+        ));
+    }
+    call.args.values().try_for_each(check_expr)
+}
+
+// =============================================================================
+// Admin API
+// =============================================================================
+
+/// Routes for `/api/admin/snippets`
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/admin/snippets", get(list_snippets))
+        .route(
+            "/api/admin/snippets/{name}",
+            get(get_snippet).put(save_snippet).delete(delete_snippet),
+        )
+}
+
+/// List snippets
+#[utoipa::path(
+    get,
+    path = "/api/admin/snippets",
+    tag = "admin",
+    responses(
+        (status = 200, description = "All snippets, by name", body = Envelope<Vec<Snippet>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_snippets(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<Vec<Snippet>>>> {
+    let snippets = SnippetService::list(&pool).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(snippets).with_request_id(request_id),
+    ))
+}
+
+/// Get one snippet
+#[utoipa::path(
+    get,
+    path = "/api/admin/snippets/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Snippet name, e.g. `dashboard.welcome`")),
+    responses(
+        (status = 200, description = "The snippet", body = Envelope<Snippet>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such snippet", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn get_snippet(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(name): Path<String>,
+) -> AppResult<Negotiated<Envelope<Snippet>>> {
+    let snippet = SnippetService::get(&pool, &name)
+        .await?
+        .ok_or_else(|| snippet_not_found(&name))?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(snippet).with_request_id(request_id),
+    ))
+}
+
+/// Create or replace a snippet. It must pass the sandbox rules and render
+/// with sample values.
+#[utoipa::path(
+    put,
+    path = "/api/admin/snippets/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Snippet name, e.g. `dashboard.welcome`")),
+    request_body = SaveSnippetRequest,
+    responses(
+        (status = 200, description = "Snippet saved", body = Envelope<Snippet>),
+        (status = 400, description = "Invalid name or template, or a template the sandbox rejects", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn save_snippet(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(name): Path<String>,
+    Payload(request): Payload<SaveSnippetRequest>,
+) -> AppResult<Negotiated<Envelope<Snippet>>> {
+    validate_name(&name)?;
+    render(&request.body, &sample_context())
+        .await
+        .map_err(|e| AppError::Validation(format!("Invalid snippet: {}", e)))?;
+
+    let description = request
+        .description
+        .as_deref()
+        .map(str::trim)
+        .filter(|description| !description.is_empty());
+    let snippet = SnippetService::save(&pool, &name, description, &request.body, admin.id).await?;
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::SNIPPET_CHANGED)
+            .by(admin.id)
+            .on("snippet", &name)
+            .with_details(json!({ "change": "set" })),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(snippet)
+            .with_message("Snippet saved")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Delete a snippet
+#[utoipa::path(
+    delete,
+    path = "/api/admin/snippets/{name}",
+    tag = "admin",
+    params(("name" = String, Path, description = "Snippet name, e.g. `dashboard.welcome`")),
+    responses(
+        (status = 200, description = "Snippet deleted", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such snippet", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn delete_snippet(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(name): Path<String>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    if !SnippetService::delete(&pool, &name).await? {
+        return Err(snippet_not_found(&name));
+    }
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::SNIPPET_CHANGED)
+            .by(admin.id)
+            .on("snippet", &name)
+            .with_details(json!({ "change": "delete" })),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Snippet deleted").with_request_id(request_id),
+    ))
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+fn snippet_not_found(name: &str) -> AppError {
+    AppError::NotFound(format!("Snippet '{}' not found", name))
+}
+
+/// Names are lowercase letters, digits, `.`, `_`, and `-`
+fn validate_name(name: &str) -> AppResult<()> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '.' | '_' | '-'));
+    if !valid {
+        return Err(AppError::Validation(format!(
+            "Invalid snippet name '{}': use lowercase letters, digits, '.', '_' or '-'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Values a snippet is checked against before it is saved
+fn sample_context() -> SnippetContext {
+    SnippetContext {
+        service_name: "Axum Base".to_string(),
+        username: Some("jane".to_string()),
+        locale: crate::i18n::resolve(None).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_render_escapes_and_sees_only_its_context() {
+        let context = SnippetContext {
+            username: Some("<b>jane</b>".to_string()),
+            ..sample_context()
+        };
+
+        let html = render("<p>Hi {{ username | upper }}</p>", &context)
+            .await
+            .unwrap();
+        assert_eq!(html, "<p>Hi &lt;B&gt;JANE&lt;&#x2F;B&gt;</p>");
+
+        let html = render(
+            "{% for word in ['a', 'b'] %}{{ word }}{% endfor %}{{ secret | default(value='none') }}",
+            &context,
+        )
+        .await
+        .unwrap();
+        assert_eq!(html, "abnone");
+        assert!(render("{{ session }}", &context).await.is_err());
+    }
+
+    #[test]
+    fn test_sandbox_rejects_unsafe_templates() {
+        assert!(check("<p>{{ service_name | title }} {{ now() | date }}</p>").is_ok());
+
+        for body in [
+            "{{ get_env(name='DATABASE_URL') }}",
+            "{% for i in range(end=1000000) %}{% endfor %}",
+            "{{ username | safe }}",
+            "{{ username | replace(from='a', to='aaaa') }}",
+            "{{ username | default(value=get_env(name='HOME')) }}",
+            "{% if username is containing(get_env(name='HOME')) %}{% endif %}",
+            "{% include \"base.html\" %}",
+            "{% extends \"base.html\" %}",
+            "{% macro m() %}{% endmacro m %}",
+            "{% block content %}{% endblock %}",
+            "{% set x = username ~ username %}",
+            "{% set_global x = 1 %}",
+            "{% for a in [1] %}{% for b in [2] %}{% endfor %}{% endfor %}",
+            "{% filter safe %}x{% endfilter %}",
+        ] {
+            assert!(check(body).is_err(), "{}", body);
+        }
+
+        assert!(check(&"x".repeat(MAX_SOURCE_BYTES + 1)).is_err());
+        assert!(check("{% if %}").is_err());
+    }
+
+    #[test]
+    fn test_validate_name() {
+        assert!(validate_name("dashboard.welcome").is_ok());
+        assert!(validate_name("email.footer").is_ok());
+        assert!(validate_name("").is_err());
+        assert!(validate_name("Dashboard").is_err());
+        assert!(validate_name("../base.html").is_err());
+    }
+}
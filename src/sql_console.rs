@@ -0,0 +1,252 @@
+//! # SQL Console
+//!
+//! Read-only SQL for production triage, available to admins at
+//! `POST /api/admin/sql` and on the `/admin/sql` page. Queries run in a
+//! read-only transaction with a statement timeout and are always rolled
+//! back. Every attempt, including rejected and failed ones, is written to
+//! `sql_console_log`, and each admin is rate limited.
+
+use axum::{
+    Router,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+    routing::post,
+};
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+use utoipa::IntoParams;
+
+use crate::api::RequestId;
+use crate::auth::AdminUser;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated, Payload};
+use crate::models::{Envelope, SqlQueryRequest, SqlQueryResult};
+use crate::services::SqlConsoleService;
+use crate::state::AppState;
+
+/// Most rows returned by one query
+pub const MAX_ROWS: usize = 1000;
+
+/// Statement timeout for console queries
+pub const STATEMENT_TIMEOUT_MS: u64 = 5000;
+
+/// Queries each admin may run per [`RATE_WINDOW`]
+pub const RATE_LIMIT: usize = 10;
+
+/// Window for [`RATE_LIMIT`]
+pub const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Leading keywords of the queries the console accepts
+const ALLOWED_KEYWORDS: &[&str] = &["select", "with", "values", "table"];
+
+/// Routes for `/api/admin/sql`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/admin/sql", post(run_query))
+}
+
+/// Query parameters for `POST /api/admin/sql`
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SqlQueryParams {
+    /// `csv` to download the result as CSV instead of an envelope
+    pub format: Option<String>,
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// Run a read-only query and return the rows, or CSV with `?format=csv`
+#[utoipa::path(
+    post,
+    path = "/api/admin/sql",
+    tag = "admin",
+    params(SqlQueryParams),
+    request_body = SqlQueryRequest,
+    responses(
+        (status = 200, description = "Query result", body = Envelope<SqlQueryResult>),
+        (status = 400, description = "Rejected or failed query", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 429, description = "Rate limit exceeded", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn run_query(
+    AdminUser(admin): AdminUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(params): Query<SqlQueryParams>,
+    Payload(request): Payload<SqlQueryRequest>,
+) -> AppResult<Response> {
+    let result = execute(&state, admin.id, &request.query).await?;
+
+    if params.format.as_deref() == Some("csv") {
+        return Ok(csv_response(&result));
+    }
+    Ok(Negotiated(
+        format,
+        Envelope::success(result).with_request_id(request_id),
+    )
+    .into_response())
+}
+
+// =============================================================================
+// Execution
+// =============================================================================
+
+/// Rate limit, validate, run, and audit one console query
+pub async fn execute(state: &AppState, user_id: i32, query: &str) -> AppResult<SqlQueryResult> {
+    let started = Instant::now();
+    let query = normalize(query);
+
+    let outcome = match state.sql_console_limiter.check(user_id) {
+        Err(retry_after) => Err(AppError::RateLimited(retry_after)),
+        Ok(()) => match check_query(query) {
+            Err(e) => Err(e),
+            Ok(()) => {
+                SqlConsoleService::run_read_only(&state.pool, query, MAX_ROWS, STATEMENT_TIMEOUT_MS)
+                    .await
+                    .map_err(query_error)
+            }
+        },
+    };
+
+    let (row_count, error) = match &outcome {
+        Ok(result) => (Some(result.row_count as i32), None),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let duration_ms = started.elapsed().as_millis() as i32;
+    SqlConsoleService::log_query(
+        &state.pool,
+        user_id,
+        query,
+        row_count,
+        error.as_deref(),
+        duration_ms,
+    )
+    .await?;
+
+    outcome
+}
+
+/// Trim whitespace and trailing semicolons
+fn normalize(query: &str) -> &str {
+    query.trim().trim_end_matches(';').trim_end()
+}
+
+/// Reject empty input and statements that cannot return rows
+fn check_query(query: &str) -> AppResult<()> {
+    let keyword = query
+        .trim_start_matches('(')
+        .split(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+
+    if query.is_empty() {
+        Err(AppError::Validation("Query is empty".to_string()))
+    } else if !ALLOWED_KEYWORDS.contains(&keyword.as_str()) {
+        Err(AppError::Validation(
+            "Only SELECT, WITH, VALUES, and TABLE queries are allowed".to_string(),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Show Postgres errors to the admin instead of hiding them as internal errors
+fn query_error(err: sqlx::Error) -> AppError {
+    match err.as_database_error() {
+        Some(db_err) => AppError::Validation(format!("Query failed: {}", db_err.message())),
+        None => AppError::from(err),
+    }
+}
+
+// =============================================================================
+// Output
+// =============================================================================
+
+/// Text shown for a value in tables and CSV
+pub fn cell_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Render a result as CSV with a header row
+pub fn to_csv(result: &SqlQueryResult) -> String {
+    let mut csv = String::new();
+    let header: Vec<String> = result.columns.iter().map(|c| csv_field(c)).collect();
+    csv.push_str(&header.join(","));
+    csv.push_str("\r\n");
+
+    for row in &result.rows {
+        let fields: Vec<String> = row.iter().map(|v| csv_field(&cell_text(v))).collect();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// CSV download response for a result
+pub fn csv_response(result: &SqlQueryResult) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"query.csv\"",
+            ),
+        ],
+        to_csv(result),
+    )
+        .into_response()
+}
+
+/// Quote a field when it contains a delimiter, quote, or line break
+pub(crate) fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_only_row_returning_queries_pass() {
+        assert!(check_query(normalize("  select 1;  ")).is_ok());
+        assert!(check_query("WITH x AS (SELECT 1) SELECT * FROM x").is_ok());
+        assert!(check_query("(select 1) union (select 2)").is_ok());
+        assert!(check_query("DELETE FROM users").is_err());
+        assert!(check_query(normalize(" ; ")).is_err());
+    }
+
+    #[test]
+    fn test_csv_output() {
+        let result = SqlQueryResult {
+            columns: vec!["id".to_string(), "note".to_string()],
+            rows: vec![
+                vec![json!(1), json!("plain")],
+                vec![json!(2), json!("has, \"quotes\"")],
+                vec![json!(3), json!(null)],
+            ],
+            row_count: 3,
+            truncated: false,
+            duration_ms: 1,
+        };
+        assert_eq!(
+            to_csv(&result),
+            "id,note\r\n1,plain\r\n2,\"has, \"\"quotes\"\"\"\r\n3,\r\n"
+        );
+    }
+}
@@ -0,0 +1,629 @@
+//! # Single Sign-On
+//!
+//! OpenID Connect sign-in against an identity provider such as Keycloak or
+//! Okta, compiled only with the `sso` feature and turned on with
+//! `SSO_ENABLED`. `/sso/login` sends the browser to the provider with a
+//! fresh state, nonce, and PKCE challenge, kept in a short-lived cookie;
+//! `/sso/callback` exchanges the code for an ID token, checks its signature
+//! against the keys the provider publishes, and signs the matching local user
+//! in. The provider's discovery document and keys are cached for an hour and
+//! fetched again early when a token names a key not seen yet.
+//!
+//! A provider account is linked to a local user on its first sign-in: to the
+//! user with its email when the provider says the email is verified,
+//! otherwise to a new user named by `SSO_USERNAME_CLAIM` if
+//! `SSO_CREATE_USERS` allows. With `SSO_ROLES_CLAIM` set, the provider roles
+//! mapped by `SSO_ROLE_MAP` replace the user's local roles at every sign-in.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::{
+    Extension, Router,
+    extract::{Query, State},
+    http::{HeaderMap, header},
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::get,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation, jwk::JwkSet};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+use tower_sessions::Session;
+
+use crate::audit::{self, AuditEvent};
+use crate::auth::PasswordService;
+use crate::config::{AppConfig, SsoConfig};
+use crate::error::{AppError, AppResult};
+use crate::flash::Flash;
+use crate::models::{AuthenticatedUser, CreateUserRequest, User};
+use crate::output;
+use crate::repositories::UserRepository;
+use crate::services::UserService;
+use crate::state::AppState;
+use crate::web::sign_in;
+
+/// Cookie carrying the state, nonce, and PKCE verifier of a sign-in under way
+pub const PENDING_COOKIE: &str = "sso_pending";
+
+/// How long a sign-in may take at the provider, in seconds
+const PENDING_MAX_AGE: u64 = 10 * 60;
+
+/// How long the discovery document and signing keys are reused
+const PROVIDER_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Routes starting and finishing a sign-in at the provider
+pub fn routes(config: &AppConfig) -> Router<AppState> {
+    Router::new()
+        .route("/sso/login", get(start_login))
+        .route("/sso/callback", get(finish_login))
+        .layer(Extension(Arc::new(OidcClient::new(config))))
+}
+
+// =============================================================================
+// Provider
+// =============================================================================
+
+/// Endpoints from the provider's discovery document
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+/// What the provider publishes about itself, as fetched at one time
+#[derive(Debug)]
+struct Provider {
+    metadata: Metadata,
+    keys: JwkSet,
+    fetched: Instant,
+}
+
+/// Client of the configured provider
+#[derive(Debug)]
+pub struct OidcClient {
+    http: reqwest::Client,
+    config: SsoConfig,
+    redirect_url: String,
+    provider: RwLock<Option<Arc<Provider>>>,
+}
+
+impl OidcClient {
+    /// Client for the provider in `config.sso`
+    pub fn new(config: &AppConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .user_agent(concat!("axum-base/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .expect("default HTTP client settings are valid");
+        let redirect_url = config
+            .sso
+            .redirect_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/sso/callback", config.server.public_base_url()));
+        Self {
+            http,
+            config: config.sso.clone(),
+            redirect_url,
+            provider: RwLock::new(None),
+        }
+    }
+
+    /// The provider's endpoints and keys, fetched again once stale or when
+    /// `refresh` is set
+    async fn provider(&self, refresh: bool) -> AppResult<Arc<Provider>> {
+        if !refresh
+            && let Some(provider) = self.provider.read().await.as_ref()
+            && provider.fetched.elapsed() < PROVIDER_TTL
+        {
+            return Ok(provider.clone());
+        }
+
+        let issuer = self.config.issuer_url.trim_end_matches('/');
+        let metadata: Metadata = self
+            .get_json(&format!("{}/.well-known/openid-configuration", issuer))
+            .await?;
+        // The document must be the issuer's own, or its keys prove nothing
+        if metadata.issuer.trim_end_matches('/') != issuer {
+            return Err(AppError::Internal(format!(
+                "SSO provider reports issuer {} instead of {}",
+                metadata.issuer, issuer
+            )));
+        }
+        let keys: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+
+        let provider = Arc::new(Provider {
+            metadata,
+            keys,
+            fetched: Instant::now(),
+        });
+        *self.provider.write().await = Some(provider.clone());
+        Ok(provider)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> AppResult<T> {
+        let response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("SSO provider request failed: {}", e)))?;
+        response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("SSO provider sent invalid JSON: {}", e)))
+    }
+
+    /// URL of the provider's sign-in page for `pending`
+    async fn authorization_url(&self, pending: &Pending) -> AppResult<String> {
+        let provider = self.provider(false).await?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(pending.verifier.as_bytes()));
+        let mut url = reqwest::Url::parse(&provider.metadata.authorization_endpoint)
+            .map_err(|e| AppError::Internal(format!("Invalid SSO authorization URL: {}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.redirect_url)
+            .append_pair("scope", &self.config.scopes)
+            .append_pair("state", &pending.state)
+            .append_pair("nonce", &pending.nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// Trade `code` for an ID token and return its verified claims
+    async fn redeem(&self, code: &str, pending: &Pending) -> AppResult<Map<String, Value>> {
+        let provider = self.provider(false).await?;
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.redirect_url.as_str()),
+            ("client_id", self.config.client_id.as_str()),
+            ("code_verifier", pending.verifier.as_str()),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            id_token: String,
+        }
+        let tokens: TokenResponse = self
+            .http
+            .post(&provider.metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::Internal(format!("SSO token request failed: {}", e)))?
+            .json()
+            .await
+            .map_err(|e| {
+                AppError::Internal(format!("SSO token response has no ID token: {}", e))
+            })?;
+
+        let claims = self.verify(&tokens.id_token, provider).await?;
+        if claims.get("nonce").and_then(Value::as_str) != Some(pending.nonce.as_str()) {
+            return Err(AppError::Unauthorized(
+                "SSO ID token was issued for another sign-in".to_string(),
+            ));
+        }
+        Ok(claims)
+    }
+
+    /// Claims of `token` once its signature, issuer, audience, and expiry
+    /// check out
+    async fn verify(
+        &self,
+        token: &str,
+        mut provider: Arc<Provider>,
+    ) -> AppResult<Map<String, Value>> {
+        let invalid = |e: jsonwebtoken::errors::Error| {
+            AppError::Unauthorized(format!("Invalid SSO ID token: {}", e))
+        };
+        let token_header = jsonwebtoken::decode_header(token).map_err(invalid)?;
+        // Only keys the provider publishes may sign; HMAC would use our secret
+        if matches!(
+            token_header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(AppError::Unauthorized(format!(
+                "SSO ID token signed with unsupported {:?}",
+                token_header.alg
+            )));
+        }
+
+        let mut jwk = find_key(&provider.keys, token_header.kid.as_deref()).cloned();
+        if jwk.is_none() {
+            // Keys rotate; look again before giving up
+            provider = self.provider(true).await?;
+            jwk = find_key(&provider.keys, token_header.kid.as_deref()).cloned();
+        }
+        let jwk = jwk.ok_or_else(|| {
+            AppError::Unauthorized("SSO ID token signed with an unknown key".to_string())
+        })?;
+        let key = DecodingKey::from_jwk(&jwk).map_err(invalid)?;
+
+        let mut validation = Validation::new(token_header.alg);
+        validation.set_issuer(&[&provider.metadata.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let data = jsonwebtoken::decode::<Map<String, Value>>(token, &key, &validation)
+            .map_err(invalid)?;
+        Ok(data.claims)
+    }
+}
+
+/// The key `kid` names, or the only key when the token names none
+fn find_key<'a>(keys: &'a JwkSet, kid: Option<&str>) -> Option<&'a jsonwebtoken::jwk::Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }
+}
+
+// =============================================================================
+// Sign-in Under Way
+// =============================================================================
+
+/// Secrets of one sign-in, checked when the provider sends the browser back
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Pending {
+    state: String,
+    nonce: String,
+    verifier: String,
+}
+
+impl Pending {
+    fn generate() -> Self {
+        Self {
+            state: random_token(),
+            nonce: random_token(),
+            verifier: random_token(),
+        }
+    }
+
+    /// `Set-Cookie` value remembering this sign-in. `SameSite=Lax` so the
+    /// cookie comes back with the provider's redirect, which a strict
+    /// session cookie does not.
+    fn set_cookie(&self, secure: bool) -> String {
+        format!(
+            "{}={}.{}.{}; Path=/sso; Max-Age={}; HttpOnly; SameSite=Lax{}",
+            PENDING_COOKIE,
+            self.state,
+            self.nonce,
+            self.verifier,
+            PENDING_MAX_AGE,
+            if secure { "; Secure" } else { "" }
+        )
+    }
+
+    /// The sign-in the request's cookie remembers
+    fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let value = headers
+            .get_all(header::COOKIE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(name, _)| *name == PENDING_COOKIE)
+            .map(|(_, value)| value)?;
+        let mut parts = value.split('.');
+        let pending = Self {
+            state: parts.next()?.to_string(),
+            nonce: parts.next()?.to_string(),
+            verifier: parts.next()?.to_string(),
+        };
+        (parts.next().is_none() && !pending.state.is_empty()).then_some(pending)
+    }
+}
+
+/// 256 random bits, URL-safe
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// `Set-Cookie` value removing the [`PENDING_COOKIE`]
+fn clear_cookie() -> String {
+    format!(
+        "{}=; Path=/sso; Max-Age=0; HttpOnly; SameSite=Lax",
+        PENDING_COOKIE
+    )
+}
+
+/// Page sending the browser on to `path`. The callback is reached through
+/// the provider's redirect, so a redirect from it would be cross-site too
+/// and arrive without the new session cookie; a page load starts a
+/// same-site navigation that carries it.
+fn continue_to(path: &str) -> Response {
+    (
+        [(header::SET_COOKIE, clear_cookie())],
+        Html(format!(
+            "<!DOCTYPE html><meta http-equiv=\"refresh\" content=\"0;url={0}\">\
+             <a href=\"{0}\">Continue</a>",
+            path
+        )),
+    )
+        .into_response()
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+async fn start_login(
+    State(state): State<AppState>,
+    Extension(client): Extension<Arc<OidcClient>>,
+    flash: Flash,
+) -> Response {
+    let pending = Pending::generate();
+    match client.authorization_url(&pending).await {
+        Ok(url) => (
+            [(
+                header::SET_COOKIE,
+                pending.set_cookie(state.config.secure_cookies()),
+            )],
+            Redirect::to(&url),
+        )
+            .into_response(),
+        Err(e) => {
+            output::error(format!("SSO sign-in could not start: {}", e));
+            flash
+                .error("Single sign-on is unavailable. Please try again later.")
+                .await;
+            Redirect::to("/login").into_response()
+        }
+    }
+}
+
+/// Query the provider sends the browser back with
+#[derive(Debug, Deserialize)]
+struct Callback {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+async fn finish_login(
+    State(state): State<AppState>,
+    Extension(client): Extension<Arc<OidcClient>>,
+    session: Session,
+    flash: Flash,
+    headers: HeaderMap,
+    Query(callback): Query<Callback>,
+) -> Response {
+    if let Some(error) = &callback.error {
+        output::warn(format!(
+            "SSO provider refused sign-in: {} {}",
+            error,
+            callback.error_description.as_deref().unwrap_or_default()
+        ));
+        flash
+            .error("Single sign-on was cancelled or refused.")
+            .await;
+        return continue_to("/login");
+    }
+
+    let result = match (Pending::from_headers(&headers), &callback.code) {
+        (Some(pending), Some(code))
+            if callback.state.as_deref() == Some(pending.state.as_str()) =>
+        {
+            sign_in_with(&state, &client, code, &pending).await
+        }
+        _ => Err(AppError::Unauthorized(
+            "SSO callback does not match a sign-in under way".to_string(),
+        )),
+    };
+
+    match result {
+        Ok(Some(user)) => {
+            if sign_in(&state, &session, &flash, user).await {
+                return continue_to("/");
+            }
+            flash.error("Session error. Please try again.").await;
+        }
+        Ok(None) => {
+            flash
+                .error("Your account cannot sign in here. Ask an administrator for access.")
+                .await;
+        }
+        Err(e) => {
+            output::error(format!("SSO sign-in failed: {}", e));
+            flash
+                .error("Single sign-on failed. Please try again.")
+                .await;
+        }
+    }
+    continue_to("/login")
+}
+
+/// The local user the provider vouches for, `None` when there is none or
+/// they are deactivated
+async fn sign_in_with(
+    state: &AppState,
+    client: &OidcClient,
+    code: &str,
+    pending: &Pending,
+) -> AppResult<Option<AuthenticatedUser>> {
+    let claims = client.redeem(code, pending).await?;
+    let pool = &state.pool;
+    let config = &state.config.sso;
+    let issuer = config.issuer_url.trim_end_matches('/');
+    let subject = claims
+        .get("sub")
+        .and_then(Value::as_str)
+        .ok_or_else(|| AppError::Unauthorized("SSO ID token has no subject".to_string()))?;
+
+    let Some(user) = link_user(pool, config, issuer, subject, &claims).await? else {
+        return Ok(None);
+    };
+    if !user.is_active {
+        return Ok(None);
+    }
+
+    if let Some(path) = &config.roles_claim {
+        let roles = map_roles(config, claim_at(&claims, path));
+        UserService::set_user_roles(pool, user.id, &roles).await?;
+    }
+    Ok(Some(user.into()))
+}
+
+/// The user linked to `subject`, linking or creating one on first sign-in
+async fn link_user(
+    pool: &PgPool,
+    config: &SsoConfig,
+    issuer: &str,
+    subject: &str,
+    claims: &Map<String, Value>,
+) -> AppResult<Option<User>> {
+    let linked: Option<i32> =
+        sqlx::query_scalar("SELECT user_id FROM sso_identities WHERE issuer = $1 AND subject = $2")
+            .bind(issuer)
+            .bind(subject)
+            .fetch_optional(pool)
+            .await?;
+    if let Some(user_id) = linked {
+        return Ok(UserService::get_user_any_status(pool, user_id).await?);
+    }
+
+    let email = claims.get("email").and_then(Value::as_str);
+    let email_verified = claims.get("email_verified").and_then(Value::as_bool) == Some(true);
+    let existing = match email {
+        Some(email) if email_verified => pool
+            .find_user_by_name_or_email(email)
+            .await?
+            .filter(|user| user.email.eq_ignore_ascii_case(email)),
+        _ => None,
+    };
+
+    let user = match existing {
+        Some(user) => user,
+        None if config.create_users => {
+            let username = claims.get(&config.username_claim).and_then(Value::as_str);
+            let (Some(username), Some(email)) = (username, email) else {
+                return Err(AppError::Validation(format!(
+                    "SSO ID token lacks the {} or email claim needed to create a user",
+                    config.username_claim
+                )));
+            };
+            let request = CreateUserRequest {
+                username: username.to_string(),
+                email: email.to_string(),
+                // Nobody knows it; the user signs in through the provider
+                password: PasswordService::generate_password(),
+                roles: Vec::new(),
+                locale: None,
+            };
+            let created = UserService::create_user(pool, &request).await?;
+            audit::record(
+                pool,
+                AuditEvent::new(audit::USER_CREATED)
+                    .on("user", created.id)
+                    .with_details(json!({ "via": "sso", "issuer": issuer })),
+            )
+            .await;
+            match UserService::get_user_any_status(pool, created.id).await? {
+                Some(user) => user,
+                None => return Ok(None),
+            }
+        }
+        None => return Ok(None),
+    };
+
+    sqlx::query("INSERT INTO sso_identities (issuer, subject, user_id) VALUES ($1, $2, $3)")
+        .bind(issuer)
+        .bind(subject)
+        .bind(user.id)
+        .execute(pool)
+        .await?;
+    Ok(Some(user))
+}
+
+/// The claim at a dotted `path`, e.g. `realm_access.roles`
+fn claim_at<'a>(claims: &'a Map<String, Value>, path: &str) -> Option<&'a Value> {
+    let mut parts = path.split('.');
+    let first = claims.get(parts.next()?)?;
+    parts.try_fold(first, |value, part| value.get(part))
+}
+
+/// Local roles for the provider roles in `claim`, a list or a single name;
+/// roles without a mapping are dropped
+fn map_roles(config: &SsoConfig, claim: Option<&Value>) -> Vec<String> {
+    let names: Vec<&str> = match claim {
+        Some(Value::Array(values)) => values.iter().filter_map(Value::as_str).collect(),
+        Some(Value::String(name)) => vec![name.as_str()],
+        _ => Vec::new(),
+    };
+    let mut roles: Vec<String> = names
+        .into_iter()
+        .filter_map(|name| config.role_map.get(name).cloned())
+        .collect();
+    roles.sort();
+    roles.dedup();
+    roles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn test_pending_round_trips_through_the_cookie() {
+        let pending = Pending::generate();
+        let set_cookie = pending.set_cookie(true);
+        assert!(set_cookie.contains("SameSite=Lax; Secure"));
+
+        let cookie = set_cookie.split(';').next().unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_str(&format!("id=abc; {}", cookie)).unwrap(),
+        );
+        assert_eq!(Pending::from_headers(&headers), Some(pending.clone()));
+        assert_ne!(pending, Pending::generate());
+
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("sso_pending=only.two"),
+        );
+        assert_eq!(Pending::from_headers(&headers), None);
+    }
+
+    #[test]
+    fn test_roles_mapped_from_nested_claim() {
+        let mut config = SsoConfig::default();
+        config
+            .role_map
+            .insert("realm-admin".to_string(), "admin".to_string());
+        config
+            .role_map
+            .insert("staff".to_string(), "editor".to_string());
+        let claims = json!({
+            "sub": "f81d4fae",
+            "realm_access": { "roles": ["staff", "offline_access", "realm-admin", "staff"] },
+            "group": "staff",
+        });
+        let claims = claims.as_object().unwrap();
+
+        let roles = map_roles(&config, claim_at(claims, "realm_access.roles"));
+        assert_eq!(roles, vec!["admin".to_string(), "editor".to_string()]);
+        assert_eq!(
+            map_roles(&config, claim_at(claims, "group")),
+            vec!["editor"]
+        );
+        assert!(map_roles(&config, claim_at(claims, "realm_access.missing")).is_empty());
+    }
+}
@@ -0,0 +1,147 @@
+//! # Application State
+//!
+//! Shared state handed to every handler through Axum's `State` extractor.
+//! `FromRef` impls let handlers keep extracting just the piece they need
+//! (e.g. `State<PgPool>`) while new shared services are added here.
+
+use axum::extract::FromRef;
+use sqlx::PgPool;
+use std::net::IpAddr;
+use std::sync::Arc;
+use tera::Tera;
+
+use crate::auth::{Authenticator, PasswordPolicy};
+use crate::config::AppConfig;
+use crate::database::Db;
+use crate::health::HealthHistory;
+use crate::login_guard;
+use crate::mailer::Mailer;
+use crate::metrics::Metrics;
+use crate::page_cache::PageCache;
+use crate::plugins::PluginRegistry;
+use crate::rate_limit::RateLimiter;
+use crate::sql_console;
+use crate::suggest::SuggestionCache;
+use crate::uploads::{self, StorageBackend};
+use crate::ws::Connections;
+
+/// Shared application state
+#[derive(Clone)]
+pub struct AppState {
+    /// Database connection pool (the primary)
+    pub pool: PgPool,
+    /// The primary and, with `DATABASE_READ_URL`, a read replica
+    pub db: Db,
+    /// Validated application configuration
+    pub config: Arc<AppConfig>,
+    /// Tera template engine
+    pub templates: Arc<Tera>,
+    /// Per-admin limit on SQL console queries
+    pub sql_console_limiter: Arc<RateLimiter<i32>>,
+    /// Per-address limit on login posts
+    pub login_limiter: Arc<RateLimiter<IpAddr>>,
+    /// Outgoing email transport
+    pub mailer: Arc<Mailer>,
+    /// Open WebSockets, for pushing to signed-in browsers
+    pub connections: Arc<Connections>,
+    /// Where uploaded files are kept
+    pub storage: Arc<dyn StorageBackend>,
+    /// Plugins the app was started with
+    pub plugins: Arc<PluginRegistry>,
+    /// Counters served at `/metrics`
+    pub metrics: Arc<Metrics>,
+    /// Recent readiness probes of this instance
+    pub health: Arc<HealthHistory>,
+    /// Recent search-as-you-type answers
+    pub suggestions: Arc<SuggestionCache>,
+    /// Home and landing pages rendered for signed-out visitors
+    pub pages: Arc<PageCache>,
+    /// Password checks and session tracking; the pool unless replaced
+    pub auth: Arc<dyn Authenticator>,
+    /// Rules for new passwords
+    pub password_policy: Arc<PasswordPolicy>,
+}
+
+impl AppState {
+    /// Create application state from its parts
+    pub fn new(pool: PgPool, config: AppConfig, templates: Arc<Tera>) -> Self {
+        let mailer = Mailer::from_config(&config.mail)
+            .expect("mail settings are checked by AppConfig::validate");
+        let storage = uploads::storage_from_config(&config.uploads);
+        let password_policy = PasswordPolicy::from_config(&config.passwords);
+        let pages = PageCache::from_config(&config);
+        Self {
+            auth: Arc::new(pool.clone()),
+            db: Db::new(pool.clone()),
+            pool,
+            config: Arc::new(config),
+            templates,
+            sql_console_limiter: Arc::new(RateLimiter::new(
+                sql_console::RATE_LIMIT,
+                sql_console::RATE_WINDOW,
+            )),
+            login_limiter: Arc::new(RateLimiter::new(
+                login_guard::RATE_LIMIT,
+                login_guard::RATE_WINDOW,
+            )),
+            mailer: Arc::new(mailer),
+            connections: Arc::new(Connections::new()),
+            storage,
+            plugins: Arc::new(PluginRegistry::default()),
+            metrics: Arc::new(Metrics::default()),
+            health: Arc::new(HealthHistory::default()),
+            suggestions: Arc::new(SuggestionCache::default()),
+            pages: Arc::new(pages),
+            password_policy: Arc::new(password_policy),
+        }
+    }
+
+    /// Send read-only queries to `replica` instead of the primary
+    pub fn with_read_pool(mut self, replica: PgPool) -> Self {
+        self.db = Db::new(self.pool.clone()).with_replica(replica);
+        self
+    }
+
+    /// Use `plugins` instead of an empty registry
+    pub fn with_plugins(mut self, plugins: PluginRegistry) -> Self {
+        self.plugins = Arc::new(plugins);
+        self
+    }
+
+    /// Sign users in through `auth` instead of the pool, e.g. an
+    /// [`InMemoryAuthenticator`](crate::auth::InMemoryAuthenticator) in tests
+    pub fn with_authenticator(mut self, auth: impl Authenticator + 'static) -> Self {
+        self.auth = Arc::new(auth);
+        self
+    }
+}
+
+impl FromRef<AppState> for PgPool {
+    fn from_ref(state: &AppState) -> Self {
+        state.pool.clone()
+    }
+}
+
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<AppConfig> {
+    fn from_ref(state: &AppState) -> Self {
+        state.config.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Tera> {
+    fn from_ref(state: &AppState) -> Self {
+        state.templates.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<Connections> {
+    fn from_ref(state: &AppState) -> Self {
+        state.connections.clone()
+    }
+}
@@ -0,0 +1,312 @@
+//! # Static Files
+//!
+//! `/static` is served from [`STATIC_DIR`] with caching headers. Clients
+//! that accept Brotli or gzip get a precompressed `.br` or `.gz` sibling
+//! when one exists, every file gets a weak ETag, and `If-None-Match` is
+//! answered with `304 Not Modified`.
+//!
+//! URLs carrying a `?v=` version are cached for a year as immutable.
+//! Templates add `?v={{ asset_version }}`, a hash of the files in
+//! [`STATIC_DIR`] taken at startup, so changed assets get new URLs without a
+//! crate version bump. Other files may be cached for `STATIC_MAX_AGE`
+//! seconds. Development always revalidates so edits show up on the next
+//! reload.
+
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get_service,
+};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tower_http::services::ServeDir;
+
+use crate::config::{AppConfig, Environment};
+
+/// Directory served under `/static`
+pub const STATIC_DIR: &str = "static";
+
+/// Lifetime of versioned (`?v=`) URLs: one year
+pub const IMMUTABLE_MAX_AGE: u64 = 31_536_000;
+
+/// Version for `?v=` URLs: a hash of the files under [`STATIC_DIR`],
+/// computed on first use
+pub fn asset_version() -> &'static str {
+    static VERSION: OnceLock<String> = OnceLock::new();
+    VERSION.get_or_init(|| content_version(Path::new(STATIC_DIR)))
+}
+
+/// Hash of the relative paths and contents of every file under `dir`
+fn content_version(dir: &Path) -> String {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files);
+    files.sort();
+
+    let hash = files.iter().fold(FNV_OFFSET, |hash, path| {
+        let name = path.strip_prefix(dir).unwrap_or(path).to_string_lossy();
+        let contents = std::fs::read(path).unwrap_or_default();
+        fnv1a(fnv1a(hash, name.as_bytes()), &contents)
+    });
+    format!("{:016x}", hash)
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+/// How long browsers may reuse a static file without revalidating
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+    /// Seconds for unversioned URLs; `None` means always revalidate
+    max_age: Option<u64>,
+}
+
+impl CachePolicy {
+    /// Policy for the configured environment
+    pub fn from_config(config: &AppConfig) -> Self {
+        let max_age = match config.environment {
+            Environment::Development => None,
+            _ => Some(config.server.static_max_age),
+        };
+        Self { max_age }
+    }
+
+    /// `Cache-Control` for a response, depending on whether the URL is versioned
+    pub fn cache_control(&self, versioned: bool) -> HeaderValue {
+        match self.max_age {
+            None => HeaderValue::from_static("no-cache"),
+            Some(_) if versioned => {
+                HeaderValue::from_str(&format!("public, max-age={}, immutable", IMMUTABLE_MAX_AGE))
+                    .expect("valid header value")
+            }
+            Some(max_age) => HeaderValue::from_str(&format!("public, max-age={}", max_age))
+                .expect("valid header value"),
+        }
+    }
+}
+
+/// Service for `/static`, serving `dir` with caching headers
+pub fn service(dir: impl AsRef<Path>, config: &AppConfig) -> Router {
+    let files = ServeDir::new(dir).precompressed_br().precompressed_gzip();
+
+    Router::new()
+        .fallback_service(get_service(files))
+        .layer(middleware::from_fn_with_state(
+            CachePolicy::from_config(config),
+            cache_headers,
+        ))
+}
+
+/// Add `Cache-Control`, `Vary`, and `ETag`, and answer matching
+/// `If-None-Match` requests with 304
+async fn cache_headers(
+    State(policy): State<CachePolicy>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let versioned = request
+        .uri()
+        .query()
+        .is_some_and(|query| query.split('&').any(|pair| pair.starts_with("v=")));
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+
+    let mut response = next.run(request).await;
+    let status = response.status();
+    if !status.is_success() && status != StatusCode::NOT_MODIFIED {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, policy.cache_control(versioned));
+    headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+    // Ranges report a partial length, so only whole files get an ETag
+    if status != StatusCode::OK {
+        return response;
+    }
+    let Some(etag) = etag(headers) else {
+        return response;
+    };
+
+    if if_none_match.is_some_and(|value| etag_matches(&value, &etag)) {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        for name in [
+            header::CACHE_CONTROL,
+            header::VARY,
+            header::LAST_MODIFIED,
+            header::CONTENT_ENCODING,
+        ] {
+            if let Some(value) = headers.get(&name) {
+                not_modified.headers_mut().insert(name, value.clone());
+            }
+        }
+        not_modified.headers_mut().insert(header::ETAG, etag);
+        return not_modified;
+    }
+
+    headers.insert(header::ETAG, etag);
+    response
+}
+
+/// Weak validator from the file's size, modification time, and encoding, so
+/// each precompressed variant gets its own tag
+fn etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let length = headers.get(header::CONTENT_LENGTH)?.to_str().ok()?;
+    let modified = headers.get(header::LAST_MODIFIED)?.as_bytes();
+    let encoding = headers
+        .get(header::CONTENT_ENCODING)
+        .map_or(&b""[..], HeaderValue::as_bytes);
+
+    let hash = fnv1a(fnv1a(fnv1a(FNV_OFFSET, modified), b"|"), encoding);
+
+    HeaderValue::from_str(&format!("W/\"{}-{:x}\"", length, hash)).ok()
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// FNV-1a: stable across builds, unlike the std hasher
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Whether an `If-None-Match` header names the tag (weak comparison)
+fn etag_matches(if_none_match: &HeaderValue, etag: &HeaderValue) -> bool {
+    let Ok(candidates) = if_none_match.to_str() else {
+        return false;
+    };
+    let strip = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    let etag = strip(etag.to_str().unwrap_or_default());
+
+    candidates
+        .split(',')
+        .any(|candidate| candidate.trim() == "*" || strip(candidate) == etag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use tower::ServiceExt;
+
+    fn production() -> AppConfig {
+        AppConfig {
+            environment: Environment::Production,
+            ..AppConfig::default()
+        }
+    }
+
+    /// Static directory with `app.css` and a gzip variant
+    fn static_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("axum-base-static-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app.css"), "body { color: red; }").unwrap();
+        std::fs::write(dir.join("app.css.gz"), "pretend gzip").unwrap();
+        dir
+    }
+
+    async fn get(app: &Router, uri: &str, headers: &[(header::HeaderName, &str)]) -> Response {
+        let mut request = Request::builder().uri(uri);
+        for (name, value) in headers {
+            request = request.header(name, *value);
+        }
+        app.clone()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_cache_control_policy() {
+        let policy = CachePolicy::from_config(&production());
+        assert_eq!(policy.cache_control(false), "public, max-age=3600");
+        assert_eq!(
+            policy.cache_control(true),
+            "public, max-age=31536000, immutable"
+        );
+
+        let development = CachePolicy::from_config(&AppConfig::default());
+        assert_eq!(development.cache_control(true), "no-cache");
+    }
+
+    #[test]
+    fn test_etag_matching() {
+        let etag = HeaderValue::from_static("W/\"20-abc\"");
+        assert!(etag_matches(
+            &HeaderValue::from_static("W/\"20-abc\""),
+            &etag
+        ));
+        assert!(etag_matches(
+            &HeaderValue::from_static("\"x\", \"20-abc\""),
+            &etag
+        ));
+        assert!(etag_matches(&HeaderValue::from_static("*"), &etag));
+        assert!(!etag_matches(
+            &HeaderValue::from_static("W/\"21-abc\""),
+            &etag
+        ));
+    }
+
+    #[test]
+    fn test_content_version_follows_file_contents() {
+        let dir = static_dir();
+        let version = content_version(&dir);
+        assert_eq!(version.len(), 16);
+        assert_eq!(content_version(&dir), version);
+
+        std::fs::write(dir.join("app.css"), "body { color: blue; }").unwrap();
+        assert_ne!(content_version(&dir), version);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_static_files_are_cached_and_revalidated() {
+        let dir = static_dir();
+        let app = service(&dir, &production());
+
+        let response = get(&app, "/app.css?v=1.0.0", &[]).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=31536000, immutable"
+        );
+        assert_eq!(response.headers()[header::VARY], "accept-encoding");
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(etag.starts_with("W/\"20-"));
+
+        let response = get(&app, "/app.css", &[(header::IF_NONE_MATCH, &etag)]).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(
+            response.headers()[header::CACHE_CONTROL],
+            "public, max-age=3600"
+        );
+
+        // The precompressed variant is served, with its own ETag
+        let response = get(&app, "/app.css", &[(header::ACCEPT_ENCODING, "gzip")]).await;
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_ne!(response.headers()[header::ETAG], etag.as_str());
+
+        let response = get(&app, "/missing.css", &[]).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert!(response.headers().get(header::CACHE_CONTROL).is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,362 @@
+//! # Search Suggestions
+//!
+//! `GET /api/items/suggest?q=` returns the titles of visible items containing
+//! what was typed, for the search box on the home page. Matching uses the
+//! trigram index on `items.title`; titles starting with the text come first,
+//! then the most similar.
+//!
+//! A search box asks on every keystroke, so answers are kept in a
+//! [`SuggestionCache`] in [`AppState::suggestions`](crate::state::AppState::suggestions):
+//! at most [`CACHE_CAPACITY`] queries for [`CACHE_TTL`], keyed by the
+//! [normalized](normalize) text so `"Rust  "` and `"rust"` share an entry,
+//! dropping the least recently used first. The cache is per process; an
+//! item renamed or hidden drops out of suggestions within the TTL.
+//!
+//! Text shorter than [`MIN_QUERY_CHARS`] gets no suggestions and no query.
+//! [`SuggestMetrics`] reports the cache's hits, misses, and size at
+//! `/metrics`.
+
+use axum::{
+    Router,
+    extract::{Query, State},
+    routing::get,
+};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::api::RequestId;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::metrics::{CounterVec, GaugeVec, MetricSource};
+use crate::models::{Envelope, ItemSuggestion, SuggestQuery};
+use crate::services::ItemService;
+use crate::state::AppState;
+
+/// Fewest characters worth suggesting for
+pub const MIN_QUERY_CHARS: usize = 2;
+
+/// Most characters of a query
+pub const MAX_QUERY_CHARS: usize = 100;
+
+/// Suggestions returned when the request gives no `limit`
+pub const DEFAULT_LIMIT: usize = 8;
+
+/// Most suggestions a request may ask for; the cache always holds this many
+pub const MAX_LIMIT: usize = 10;
+
+/// Queries the cache remembers
+pub const CACHE_CAPACITY: usize = 512;
+
+/// How long a cached answer is served
+pub const CACHE_TTL: Duration = Duration::from_secs(30);
+
+/// Lowercase `text`, trim it, and collapse runs of whitespace
+pub fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+struct Entry {
+    fetched: Instant,
+    last_used: u64,
+    suggestions: Arc<Vec<ItemSuggestion>>,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    /// Bumped on every hit and insert to order entries by use
+    clock: u64,
+}
+
+/// Recent suggestions by normalized query, least recently used evicted first
+pub struct SuggestionCache {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+}
+
+impl Default for SuggestionCache {
+    fn default() -> Self {
+        Self::new(CACHE_CAPACITY, CACHE_TTL)
+    }
+}
+
+impl SuggestionCache {
+    /// Create a cache of `capacity` queries, each served for `ttl`
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// Suggestions cached for `key`, unless missing or expired
+    pub fn get(&self, key: &str) -> Option<Arc<Vec<ItemSuggestion>>> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Remember `suggestions` for `key`
+    pub fn insert(
+        &self,
+        key: String,
+        suggestions: Vec<ItemSuggestion>,
+    ) -> Arc<Vec<ItemSuggestion>> {
+        self.insert_at(key, suggestions, Instant::now())
+    }
+
+    /// Number of queries cached, expired ones included
+    pub fn len(&self) -> usize {
+        self.lock().map.len()
+    }
+
+    /// Whether nothing is cached
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn get_at(&self, key: &str, now: Instant) -> Option<Arc<Vec<ItemSuggestion>>> {
+        let mut entries = self.lock();
+        let fresh = now.duration_since(entries.map.get(key)?.fetched) < self.ttl;
+        if !fresh {
+            entries.map.remove(key);
+            return None;
+        }
+        entries.clock += 1;
+        let clock = entries.clock;
+        let entry = entries.map.get_mut(key)?;
+        entry.last_used = clock;
+        Some(entry.suggestions.clone())
+    }
+
+    fn insert_at(
+        &self,
+        key: String,
+        suggestions: Vec<ItemSuggestion>,
+        now: Instant,
+    ) -> Arc<Vec<ItemSuggestion>> {
+        let suggestions = Arc::new(suggestions);
+        if self.capacity == 0 {
+            return suggestions;
+        }
+
+        let mut entries = self.lock();
+        if !entries.map.contains_key(&key) && entries.map.len() >= self.capacity {
+            // Expired entries go first, then the least recently used
+            let ttl = self.ttl;
+            entries
+                .map
+                .retain(|_, entry| now.duration_since(entry.fetched) < ttl);
+            if entries.map.len() >= self.capacity {
+                let oldest = entries
+                    .map
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.map.remove(&oldest);
+                }
+            }
+        }
+
+        entries.clock += 1;
+        let last_used = entries.clock;
+        entries.map.insert(
+            key,
+            Entry {
+                fetched: now,
+                last_used,
+                suggestions: suggestions.clone(),
+            },
+        );
+        suggestions
+    }
+}
+
+/// Use of the suggestion cache, served at `/metrics`
+#[derive(Debug)]
+pub struct SuggestMetrics {
+    /// Lookups by result, `hit` or `miss`
+    pub lookups: CounterVec,
+    /// Queries cached
+    pub entries: GaugeVec,
+}
+
+impl Default for SuggestMetrics {
+    fn default() -> Self {
+        Self {
+            lookups: CounterVec::new(
+                "suggestion_cache_lookups_total",
+                "Search suggestion cache lookups by result",
+                &["result"],
+            )
+            .with_series(&[&["hit"], &["miss"]]),
+            entries: GaugeVec::new(
+                "suggestion_cache_entries",
+                "Queries in the search suggestion cache",
+                &[],
+            ),
+        }
+    }
+}
+
+impl MetricSource for SuggestMetrics {
+    fn render(&self, out: &mut String) {
+        self.lookups.render(out);
+        self.entries.render(out);
+    }
+}
+
+/// `/api/items/suggest`
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/api/items/suggest", get(suggest_items))
+}
+
+/// Titles of visible items containing `q`, for search-as-you-type
+#[utoipa::path(
+    get,
+    path = "/api/items/suggest",
+    tag = "api",
+    params(SuggestQuery),
+    responses(
+        (status = 200, description = "Matching items, best first; empty for fewer than two characters", body = Envelope<Vec<ItemSuggestion>>),
+        (status = 400, description = "Limit out of range or query too long", body = Envelope<serde_json::Value>)
+    )
+)]
+pub async fn suggest_items(
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Query(query): Query<SuggestQuery>,
+) -> AppResult<Negotiated<Envelope<Vec<ItemSuggestion>>>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(AppError::Validation(format!(
+            "limit must be between 1 and {}",
+            MAX_LIMIT
+        )));
+    }
+    let key = normalize(&query.q);
+    if key.chars().count() > MAX_QUERY_CHARS {
+        return Err(AppError::Validation(format!(
+            "q must be at most {} characters",
+            MAX_QUERY_CHARS
+        )));
+    }
+
+    let suggestions = if key.chars().count() < MIN_QUERY_CHARS {
+        Vec::new()
+    } else {
+        let metrics = state.metrics.source::<SuggestMetrics>();
+        let cached = match state.suggestions.get(&key) {
+            Some(cached) => {
+                metrics.lookups.inc(&["hit"]);
+                cached
+            }
+            None => {
+                metrics.lookups.inc(&["miss"]);
+                let found = ItemService::suggest(state.db.read(), &key, MAX_LIMIT as i64).await?;
+                let cached = state.suggestions.insert(key, found);
+                metrics.entries.set(&[], state.suggestions.len() as f64);
+                cached
+            }
+        };
+        cached.iter().take(limit).cloned().collect()
+    };
+
+    Ok(Negotiated(
+        format,
+        Envelope::success(suggestions).with_request_id(request_id),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn suggestion(id: i32) -> ItemSuggestion {
+        ItemSuggestion {
+            id,
+            title: format!("Item {}", id),
+            category: "Samples".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        assert_eq!(normalize("  Rust   Web\tApps "), "rust web apps");
+        assert_eq!(normalize("ÉCLAIR"), "éclair");
+        assert_eq!(normalize("   "), "");
+    }
+
+    #[test]
+    fn test_entries_expire() {
+        let cache = SuggestionCache::new(4, Duration::from_secs(30));
+        let start = Instant::now();
+
+        cache.insert_at("ru".to_string(), vec![suggestion(1)], start);
+        let hit = cache.get_at("ru", start + Duration::from_secs(29)).unwrap();
+        assert_eq!(hit[0].id, 1);
+        assert!(
+            cache
+                .get_at("ru", start + Duration::from_secs(30))
+                .is_none()
+        );
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_least_recently_used_is_evicted() {
+        let cache = SuggestionCache::new(2, Duration::from_secs(30));
+        let now = Instant::now();
+
+        cache.insert_at("ru".to_string(), vec![suggestion(1)], now);
+        cache.insert_at("rus".to_string(), vec![suggestion(2)], now);
+        // Using "ru" makes "rus" the least recently used
+        assert!(cache.get_at("ru", now).is_some());
+        cache.insert_at("rust".to_string(), vec![suggestion(3)], now);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get_at("ru", now).is_some());
+        assert!(cache.get_at("rus", now).is_none());
+        assert!(cache.get_at("rust", now).is_some());
+    }
+
+    #[test]
+    fn test_expired_entries_are_evicted_first() {
+        let cache = SuggestionCache::new(2, Duration::from_secs(30));
+        let start = Instant::now();
+
+        cache.insert_at("old".to_string(), vec![], start);
+        cache.insert_at("new".to_string(), vec![], start + Duration::from_secs(20));
+        assert!(
+            cache
+                .get_at("old", start + Duration::from_secs(20))
+                .is_some()
+        );
+        // "old" has expired, so it goes even though "new" was used less recently
+        cache.insert_at("newer".to_string(), vec![], start + Duration::from_secs(40));
+
+        assert!(
+            cache
+                .get_at("new", start + Duration::from_secs(40))
+                .is_some()
+        );
+        assert!(
+            cache
+                .get_at("newer", start + Duration::from_secs(40))
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,377 @@
+//! # Template Checks
+//!
+//! Static verification of Tera templates, run by `admin templates check`.
+//! Every loaded template is scanned for filters, functions, and tests that
+//! Tera does not know about. Templates listed in the web module's
+//! [`TEMPLATE_MANIFEST`](crate::web::TEMPLATE_MANIFEST) are also checked
+//! against their handler: required blocks must be defined and every variable
+//! the rendered page reads must be one the handler provides. Locale catalogs
+//! must translate every message of the default locale. These are the errors
+//! Tera would otherwise only raise at request time.
+
+use std::collections::BTreeSet;
+use std::fmt;
+use tera::Tera;
+use tera::ast::{Expr, ExprVal, FunctionCall, Node};
+
+use crate::i18n;
+use crate::web::TemplateSpec;
+
+/// A problem found in a template
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TemplateIssue {
+    pub template: String,
+    pub message: String,
+}
+
+impl fmt::Display for TemplateIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.template, self.message)
+    }
+}
+
+/// Check all loaded templates and the handler manifest.
+///
+/// Returns issues sorted by template; an empty list means everything passed.
+pub fn check_templates(tera: &Tera, manifest: &[TemplateSpec]) -> Vec<TemplateIssue> {
+    let mut issues = BTreeSet::new();
+
+    for name in tera.get_template_names() {
+        let Ok(template) = tera.get_template(name) else {
+            continue;
+        };
+        let mut walker = Walker::new(tera, name, None);
+        walker.nodes(&template.ast);
+        issues.extend(walker.issues);
+    }
+
+    for spec in manifest {
+        check_spec(tera, spec, &mut issues);
+    }
+
+    for (locale, _) in i18n::SUPPORTED_LOCALES {
+        for key in i18n::missing_keys(locale) {
+            issues.insert(TemplateIssue {
+                template: format!("locales/{}.toml", locale),
+                message: format!("missing message '{}'", key),
+            });
+        }
+    }
+
+    issues.into_iter().collect()
+}
+
+/// Check one handler's template contract
+fn check_spec(tera: &Tera, spec: &TemplateSpec, issues: &mut BTreeSet<TemplateIssue>) {
+    let issue = |message: String| TemplateIssue {
+        template: spec.name.to_string(),
+        message,
+    };
+
+    let Ok(template) = tera.get_template(spec.name) else {
+        issues.insert(issue(
+            "template is rendered by a handler but does not exist".to_string(),
+        ));
+        return;
+    };
+
+    for block in spec.blocks {
+        if !template.blocks.contains_key(*block) {
+            issues.insert(issue(format!("missing required block '{}'", block)));
+        }
+    }
+
+    // Walk the page as it renders: the root layout with each block replaced
+    // by its most specific definition
+    let mut chain = vec![template];
+    chain.extend(
+        template
+            .parents
+            .iter()
+            .filter_map(|parent| tera.get_template(parent).ok()),
+    );
+    let root = chain.last().copied().unwrap_or(template);
+
+    let mut walker = Walker::new(tera, spec.name, Some(spec.variables));
+    walker.chain = chain;
+    walker.nodes(&root.ast);
+    issues.extend(
+        walker
+            .issues
+            .into_iter()
+            .filter(|issue| issue.message.starts_with("unknown variable")),
+    );
+}
+
+/// AST visitor collecting unknown filters, functions, tests, and variables
+struct Walker<'a> {
+    tera: &'a Tera,
+    template: &'a str,
+    /// Variables provided by the handler; `None` skips variable checks
+    provided: Option<&'static [&'static str]>,
+    /// Template followed by its parents, used to resolve blocks
+    chain: Vec<&'a tera::Template>,
+    /// Names bound by `set`, `for`, and macro arguments
+    locals: Vec<String>,
+    issues: Vec<TemplateIssue>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(tera: &'a Tera, template: &'a str, provided: Option<&'static [&'static str]>) -> Self {
+        Self {
+            tera,
+            template,
+            provided,
+            chain: Vec::new(),
+            locals: Vec::new(),
+            issues: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, message: String) {
+        self.issues.push(TemplateIssue {
+            template: self.template.to_string(),
+            message,
+        });
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::VariableBlock(_, expr) => self.expr(expr, true),
+            Node::Set(_, set) => {
+                self.expr(&set.value, true);
+                self.locals.push(set.key.clone());
+            }
+            Node::FilterSection(_, section, _) => {
+                self.filter(&section.filter);
+                self.nodes(&section.body);
+            }
+            Node::Block(_, block, _) => {
+                // Use the most specific definition when walking a rendered page
+                let body = self
+                    .chain
+                    .iter()
+                    .find_map(|template| template.blocks.get(&block.name))
+                    .map(|definition| &definition.body)
+                    .unwrap_or(&block.body);
+                self.nodes(body);
+            }
+            Node::Forloop(_, forloop, _) => {
+                self.expr(&forloop.container, true);
+                let scope = self.locals.len();
+                self.locals.push("loop".to_string());
+                self.locals.push(forloop.value.clone());
+                if let Some(key) = &forloop.key {
+                    self.locals.push(key.clone());
+                }
+                self.nodes(&forloop.body);
+                self.locals.truncate(scope);
+                if let Some(empty_body) = &forloop.empty_body {
+                    self.nodes(empty_body);
+                }
+            }
+            Node::If(condition, _) => {
+                for (_, expr, body) in &condition.conditions {
+                    // Tera treats undefined variables in conditions as false
+                    self.expr(expr, false);
+                    self.nodes(body);
+                }
+                if let Some((_, body)) = &condition.otherwise {
+                    self.nodes(body);
+                }
+            }
+            Node::MacroDefinition(_, definition, _) => {
+                let scope = self.locals.len();
+                for (arg, default) in &definition.args {
+                    if let Some(default) = default {
+                        self.expr(default, true);
+                    }
+                    self.locals.push(arg.clone());
+                }
+                self.nodes(&definition.body);
+                self.locals.truncate(scope);
+            }
+            _ => {}
+        }
+    }
+
+    /// Visit an expression; `required` is false where undefined values are tolerated
+    fn expr(&mut self, expr: &Expr, required: bool) {
+        let mut required = required;
+        for filter in &expr.filters {
+            self.filter(filter);
+            if filter.name == "default" {
+                required = false;
+            }
+        }
+        self.value(&expr.val, required);
+    }
+
+    fn value(&mut self, value: &ExprVal, required: bool) {
+        match value {
+            ExprVal::Ident(ident) if required => self.variable(ident),
+            ExprVal::Math(math) => {
+                self.expr(&math.lhs, required);
+                self.expr(&math.rhs, required);
+            }
+            ExprVal::Logic(logic) => {
+                self.expr(&logic.lhs, required);
+                self.expr(&logic.rhs, required);
+            }
+            ExprVal::Test(test) => {
+                if self.tera.get_tester(&test.name).is_err() {
+                    self.report(format!("unknown test '{}'", test.name));
+                }
+                for arg in &test.args {
+                    self.expr(arg, true);
+                }
+            }
+            ExprVal::MacroCall(call) => {
+                for arg in call.args.values() {
+                    self.expr(arg, true);
+                }
+            }
+            ExprVal::FunctionCall(call) => {
+                if self.tera.get_function(&call.name).is_err() {
+                    self.report(format!("unknown function '{}'", call.name));
+                }
+                for arg in call.args.values() {
+                    self.expr(arg, true);
+                }
+            }
+            ExprVal::Array(items) => {
+                for item in items {
+                    self.expr(item, required);
+                }
+            }
+            ExprVal::StringConcat(concat) => {
+                for value in &concat.values {
+                    self.value(value, required);
+                }
+            }
+            ExprVal::In(contains) => {
+                self.expr(&contains.lhs, required);
+                self.expr(&contains.rhs, required);
+            }
+            _ => {}
+        }
+    }
+
+    fn filter(&mut self, call: &FunctionCall) {
+        // `safe` and `default` are handled by the renderer, not registered
+        let builtin = matches!(call.name.as_str(), "safe" | "default");
+        if !builtin && self.tera.get_filter(&call.name).is_err() {
+            self.report(format!("unknown filter '{}'", call.name));
+        }
+        for arg in call.args.values() {
+            self.expr(arg, true);
+        }
+    }
+
+    fn variable(&mut self, ident: &str) {
+        let Some(provided) = self.provided else {
+            return;
+        };
+        let root = ident.split(['.', '[']).next().unwrap_or(ident);
+        let known = root == "__tera_context"
+            || provided.contains(&root)
+            || self.locals.iter().any(|local| local == root);
+        if !known {
+            self.report(format!(
+                "unknown variable '{}' (not provided by the handler)",
+                root
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: TemplateSpec = TemplateSpec {
+        name: "page.html",
+        blocks: &["content"],
+        variables: &["title", "items"],
+    };
+
+    fn tera(templates: &[(&str, &str)]) -> Tera {
+        let mut tera = Tera::default();
+        tera.add_raw_templates(templates.to_vec())
+            .expect("Templates should parse");
+        tera
+    }
+
+    #[test]
+    fn test_valid_templates_pass() {
+        let tera = tera(&[
+            (
+                "base.html",
+                "<title>{{ title }}</title>{% block content %}{% endblock %}",
+            ),
+            (
+                "page.html",
+                r#"{% extends "base.html" %}{% block content %}{% for item in items %}{{ item.name | upper }} {{ loop.index }}{% endfor %}{% if missing %}x{% endif %}{% endblock %}"#,
+            ),
+        ]);
+        assert_eq!(check_templates(&tera, &[PAGE]), Vec::new());
+    }
+
+    #[test]
+    fn test_unknown_filters_and_functions_are_reported() {
+        let tera = tera(&[(
+            "page.html",
+            "{% block content %}{{ title | uper }}{{ nope() }}{% endblock %}",
+        )]);
+        let messages: Vec<String> = check_templates(&tera, &[PAGE])
+            .into_iter()
+            .map(|issue| issue.message)
+            .collect();
+        assert!(messages.contains(&"unknown filter 'uper'".to_string()));
+        assert!(messages.contains(&"unknown function 'nope'".to_string()));
+    }
+
+    #[test]
+    fn test_manifest_mismatches_are_reported() {
+        let tera = tera(&[("page.html", "{{ titel }}{{ subtitle | default(value='') }}")]);
+        let messages: Vec<String> = check_templates(&tera, &[PAGE])
+            .into_iter()
+            .map(|issue| issue.message)
+            .collect();
+        assert_eq!(
+            messages,
+            vec![
+                "missing required block 'content'".to_string(),
+                "unknown variable 'titel' (not provided by the handler)".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_template_is_reported() {
+        let issues = check_templates(&Tera::default(), &[PAGE]);
+        assert_eq!(issues.len(), 1);
+        assert!(issues[0].message.contains("does not exist"));
+    }
+
+    #[test]
+    fn test_overridden_blocks_are_not_checked_against_the_parent() {
+        let tera = tera(&[
+            (
+                "base.html",
+                "{% block nav %}{{ current_user.username }}{% endblock %}{% block content %}{% endblock %}",
+            ),
+            (
+                "page.html",
+                r#"{% extends "base.html" %}{% block nav %}{% endblock %}{% block content %}{{ title }}{% endblock %}"#,
+            ),
+        ]);
+        assert_eq!(check_templates(&tera, &[PAGE]), Vec::new());
+    }
+}
@@ -0,0 +1,573 @@
+//! # Tenant Admin API
+//!
+//! Endpoints under `/api/admin/tenants` for managing tenants, their
+//! branding, and their custom domains. Branding covers colors, logo, and
+//! overrides for the partials in [`crate::theming::THEMEABLE_PARTIALS`].
+//! Partials are addressed by file name, so `PUT .../partials/footer.html`
+//! overrides `partials/footer.html`.
+
+use axum::{
+    Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, post, put},
+};
+use serde_json::json;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::api::RequestId;
+use crate::audit::{self, AuditEvent};
+use crate::auth::AdminUser;
+use crate::config::AppConfig;
+use crate::domains;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated, Payload};
+use crate::models::{
+    AddDomainRequest, CreateTenantRequest, Envelope, Tenant, TenantDomain, TenantPartialRequest,
+    TenantThemeRequest,
+};
+use crate::services::TenantService;
+use crate::state::AppState;
+use crate::theming::{is_hex_color, is_safe_logo_url, is_valid_slug, validate_partial};
+
+/// Longest accepted tenant name
+const MAX_NAME_LENGTH: usize = 100;
+
+/// Routes for `/api/admin/tenants`
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/api/admin/tenants", get(list_tenants).post(create_tenant))
+        .route("/api/admin/tenants/{slug}/theme", put(update_theme))
+        .route(
+            "/api/admin/tenants/{slug}/partials/{name}",
+            put(set_partial).delete(delete_partial),
+        )
+        .route(
+            "/api/admin/tenants/{slug}/domains",
+            get(list_domains).post(add_domain),
+        )
+        .route(
+            "/api/admin/tenants/{slug}/domains/{domain}",
+            delete(remove_domain),
+        )
+        .route(
+            "/api/admin/tenants/{slug}/domains/{domain}/verify",
+            post(verify_domain),
+        )
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// List tenants
+#[utoipa::path(
+    get,
+    path = "/api/admin/tenants",
+    tag = "admin",
+    responses(
+        (status = 200, description = "All tenants", body = Envelope<Vec<Tenant>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_tenants(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<Vec<Tenant>>>> {
+    let tenants = TenantService::list_tenants(&pool).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(tenants).with_request_id(request_id),
+    ))
+}
+
+/// Create a tenant, served from `<slug>.<public host>`
+#[utoipa::path(
+    post,
+    path = "/api/admin/tenants",
+    tag = "admin",
+    request_body = CreateTenantRequest,
+    responses(
+        (status = 201, description = "Tenant created", body = Envelope<Tenant>),
+        (status = 400, description = "Invalid input or duplicate slug", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn create_tenant(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Payload(request): Payload<CreateTenantRequest>,
+) -> AppResult<(StatusCode, Negotiated<Envelope<Tenant>>)> {
+    if !is_valid_slug(&request.slug) {
+        return Err(AppError::Validation(
+            "slug must be a DNS label: lowercase letters, digits, and hyphens".to_string(),
+        ));
+    }
+    let name = request.name.trim();
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "name is required and at most {} characters",
+            MAX_NAME_LENGTH
+        )));
+    }
+    validate_theme(&request.theme)?;
+
+    let tenant = TenantService::create_tenant(&pool, &request.slug, name, &request.theme)
+        .await
+        .map_err(|err| match err.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                AppError::Validation(format!("Tenant '{}' already exists", request.slug))
+            }
+            _ => AppError::from(err),
+        })?;
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::TENANT_CREATED)
+            .by(admin.id)
+            .on("tenant", &tenant.slug),
+    )
+    .await;
+
+    Ok((
+        StatusCode::CREATED,
+        Negotiated(
+            format,
+            Envelope::success(tenant)
+                .with_message("Tenant created")
+                .with_request_id(request_id),
+        ),
+    ))
+}
+
+/// Replace a tenant's colors and logo
+#[utoipa::path(
+    put,
+    path = "/api/admin/tenants/{slug}/theme",
+    tag = "admin",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    request_body = TenantThemeRequest,
+    responses(
+        (status = 200, description = "Theme updated", body = Envelope<Tenant>),
+        (status = 400, description = "Invalid color or logo URL", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn update_theme(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(slug): Path<String>,
+    Payload(request): Payload<TenantThemeRequest>,
+) -> AppResult<Negotiated<Envelope<Tenant>>> {
+    validate_theme(&request)?;
+    let tenant = find_tenant(&pool, &slug).await?;
+    TenantService::update_theme(&pool, tenant.id, &request).await?;
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::TENANT_UPDATED)
+            .by(admin.id)
+            .on("tenant", &slug)
+            .with_details(json!({ "theme": request })),
+    )
+    .await;
+
+    let tenant = find_tenant(&pool, &slug).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(tenant)
+            .with_message("Theme updated")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Override one of a tenant's partials with Tera source
+#[utoipa::path(
+    put,
+    path = "/api/admin/tenants/{slug}/partials/{name}",
+    tag = "admin",
+    params(
+        ("slug" = String, Path, description = "Tenant slug"),
+        ("name" = String, Path, description = "Partial file name, e.g. `footer.html`")
+    ),
+    request_body = TenantPartialRequest,
+    responses(
+        (status = 200, description = "Partial saved", body = Envelope<serde_json::Value>),
+        (status = 400, description = "Unknown partial or invalid template", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn set_partial(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path((slug, name)): Path<(String, String)>,
+    Payload(request): Payload<TenantPartialRequest>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let name = partial_name(&name);
+    validate_partial(&name, &request.body).map_err(AppError::Validation)?;
+    let tenant = find_tenant(&pool, &slug).await?;
+    TenantService::set_partial(&pool, tenant.id, &name, &request.body).await?;
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::TENANT_UPDATED)
+            .by(admin.id)
+            .on("tenant", &slug)
+            .with_details(json!({ "partial": name, "change": "set" })),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Partial saved").with_request_id(request_id),
+    ))
+}
+
+/// Remove a partial override, restoring the default
+#[utoipa::path(
+    delete,
+    path = "/api/admin/tenants/{slug}/partials/{name}",
+    tag = "admin",
+    params(
+        ("slug" = String, Path, description = "Tenant slug"),
+        ("name" = String, Path, description = "Partial file name, e.g. `footer.html`")
+    ),
+    responses(
+        (status = 200, description = "Override removed", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant or override", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn delete_partial(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path((slug, name)): Path<(String, String)>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let name = partial_name(&name);
+    let tenant = find_tenant(&pool, &slug).await?;
+    if !TenantService::delete_partial(&pool, tenant.id, &name).await? {
+        return Err(AppError::NotFound(format!(
+            "Tenant '{}' does not override {}",
+            slug, name
+        )));
+    }
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::TENANT_UPDATED)
+            .by(admin.id)
+            .on("tenant", &slug)
+            .with_details(json!({ "partial": name, "change": "removed" })),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Override removed").with_request_id(request_id),
+    ))
+}
+
+/// List a tenant's custom domains
+#[utoipa::path(
+    get,
+    path = "/api/admin/tenants/{slug}/domains",
+    tag = "admin",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    responses(
+        (status = 200, description = "The tenant's domains", body = Envelope<Vec<TenantDomain>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_domains(
+    _admin: AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(slug): Path<String>,
+) -> AppResult<Negotiated<Envelope<Vec<TenantDomain>>>> {
+    let tenant = find_tenant(&pool, &slug).await?;
+    let domains = TenantService::list_domains(&pool, tenant.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(domains).with_request_id(request_id),
+    ))
+}
+
+/// Attach a custom domain; it is routed to the tenant once verified
+#[utoipa::path(
+    post,
+    path = "/api/admin/tenants/{slug}/domains",
+    tag = "admin",
+    params(("slug" = String, Path, description = "Tenant slug")),
+    request_body = AddDomainRequest,
+    responses(
+        (status = 201, description = "Domain added; publish its token in a TXT record", body = Envelope<TenantDomain>),
+        (status = 400, description = "Invalid or already attached domain", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn add_domain(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    State(config): State<Arc<AppConfig>>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(slug): Path<String>,
+    Payload(request): Payload<AddDomainRequest>,
+) -> AppResult<(StatusCode, Negotiated<Envelope<TenantDomain>>)> {
+    let domain = domains::normalize_domain(&request.domain).map_err(AppError::Validation)?;
+    let public_host = config.server.public_host();
+    let public_host = public_host.split(':').next().unwrap_or_default();
+    if domain == public_host || domain.ends_with(&format!(".{}", public_host)) {
+        return Err(AppError::Validation(format!(
+            "{} is served by this site already; tenants get <slug>.{} automatically",
+            domain, public_host
+        )));
+    }
+
+    let tenant = find_tenant(&pool, &slug).await?;
+    let token = domains::new_verification_token();
+    let domain = TenantService::add_domain(&pool, tenant.id, &domain, &token)
+        .await
+        .map_err(|err| match err.as_database_error() {
+            Some(db_err) if db_err.is_unique_violation() => {
+                AppError::Validation(format!("{} is already attached to a tenant", domain))
+            }
+            _ => AppError::from(err),
+        })?;
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::DOMAIN_CHANGED)
+            .by(admin.id)
+            .on("tenant", &slug)
+            .with_details(json!({ "domain": domain.domain, "change": "added" })),
+    )
+    .await;
+
+    let message = format!(
+        "Domain added. Create a TXT record at {} with the value {}, then verify.",
+        domains::verification_record(&domain.domain),
+        domain.verification_token
+    );
+    Ok((
+        StatusCode::CREATED,
+        Negotiated(
+            format,
+            Envelope::success(domain)
+                .with_message(message)
+                .with_request_id(request_id),
+        ),
+    ))
+}
+
+/// Check the domain's TXT record and start routing it to the tenant
+#[utoipa::path(
+    post,
+    path = "/api/admin/tenants/{slug}/domains/{domain}/verify",
+    tag = "admin",
+    params(
+        ("slug" = String, Path, description = "Tenant slug"),
+        ("domain" = String, Path, description = "Custom domain")
+    ),
+    responses(
+        (status = 200, description = "Domain verified", body = Envelope<TenantDomain>),
+        (status = 400, description = "TXT record missing or wrong", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant or domain", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn verify_domain(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path((slug, domain)): Path<(String, String)>,
+) -> AppResult<Negotiated<Envelope<TenantDomain>>> {
+    let tenant = find_tenant(&pool, &slug).await?;
+    let domain = find_domain(&pool, &tenant, &domain).await?;
+
+    if domain.verified_at.is_none() {
+        let verified = domains::verify(&domain.domain, &domain.verification_token)
+            .await
+            .map_err(AppError::Validation)?;
+        if !verified {
+            return Err(AppError::Validation(format!(
+                "No TXT record at {} contains {}",
+                domains::verification_record(&domain.domain),
+                domain.verification_token
+            )));
+        }
+        TenantService::mark_domain_verified(&pool, domain.id).await?;
+        audit::record(
+            &pool,
+            AuditEvent::new(audit::DOMAIN_CHANGED)
+                .by(admin.id)
+                .on("tenant", &slug)
+                .with_details(json!({ "domain": domain.domain, "change": "verified" })),
+        )
+        .await;
+    }
+
+    let domain = find_domain(&pool, &tenant, &domain.domain).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(domain)
+            .with_message("Domain verified")
+            .with_request_id(request_id),
+    ))
+}
+
+/// Detach a custom domain from a tenant
+#[utoipa::path(
+    delete,
+    path = "/api/admin/tenants/{slug}/domains/{domain}",
+    tag = "admin",
+    params(
+        ("slug" = String, Path, description = "Tenant slug"),
+        ("domain" = String, Path, description = "Custom domain")
+    ),
+    responses(
+        (status = 200, description = "Domain removed", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 403, description = "Signed in without the admin role", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such tenant or domain", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn remove_domain(
+    AdminUser(admin): AdminUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path((slug, domain)): Path<(String, String)>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let tenant = find_tenant(&pool, &slug).await?;
+    let domain = domain.to_ascii_lowercase();
+    if !TenantService::delete_domain(&pool, tenant.id, &domain).await? {
+        return Err(domain_not_found(&tenant, &domain));
+    }
+    audit::record(
+        &pool,
+        AuditEvent::new(audit::DOMAIN_CHANGED)
+            .by(admin.id)
+            .on("tenant", &slug)
+            .with_details(json!({ "domain": domain, "change": "removed" })),
+    )
+    .await;
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("Domain removed").with_request_id(request_id),
+    ))
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// Reject colors that are not `#rrggbb` and logo URLs that are not local or HTTPS
+fn validate_theme(theme: &TenantThemeRequest) -> AppResult<()> {
+    for color in [&theme.primary_color, &theme.accent_color]
+        .into_iter()
+        .flatten()
+    {
+        if !is_hex_color(color) {
+            return Err(AppError::Validation(format!(
+                "'{}' is not a #rrggbb color",
+                color
+            )));
+        }
+    }
+    if let Some(url) = &theme.logo_url
+        && !is_safe_logo_url(url)
+    {
+        return Err(AppError::Validation(
+            "logo_url must be a site path or an https:// URL".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Template name for a partial file name
+fn partial_name(file: &str) -> String {
+    format!("partials/{}", file)
+}
+
+async fn find_tenant(pool: &PgPool, slug: &str) -> AppResult<Tenant> {
+    TenantService::get_tenant_by_slug(pool, slug)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Tenant '{}' not found", slug)))
+}
+
+async fn find_domain(pool: &PgPool, tenant: &Tenant, domain: &str) -> AppResult<TenantDomain> {
+    let domain = domain.to_ascii_lowercase();
+    TenantService::get_domain(pool, tenant.id, &domain)
+        .await?
+        .ok_or_else(|| domain_not_found(tenant, &domain))
+}
+
+fn domain_not_found(tenant: &Tenant, domain: &str) -> AppError {
+    AppError::NotFound(format!("Tenant '{}' has no domain {}", tenant.slug, domain))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::theming::THEMEABLE_PARTIALS;
+
+    #[test]
+    fn test_validate_theme() {
+        let theme = TenantThemeRequest {
+            primary_color: Some("#0f766e".to_string()),
+            accent_color: None,
+            logo_url: Some("/static/acme.svg".to_string()),
+        };
+        assert!(validate_theme(&theme).is_ok());
+
+        let theme = TenantThemeRequest {
+            accent_color: Some("teal".to_string()),
+            ..TenantThemeRequest::default()
+        };
+        assert!(validate_theme(&theme).is_err());
+
+        let theme = TenantThemeRequest {
+            logo_url: Some("http://example.com/logo.png".to_string()),
+            ..TenantThemeRequest::default()
+        };
+        assert!(validate_theme(&theme).is_err());
+    }
+
+    #[test]
+    fn test_partial_names_map_to_themeable_templates() {
+        for file in ["brand.html", "footer.html"] {
+            assert!(THEMEABLE_PARTIALS.contains(&partial_name(file).as_str()));
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! # Test Data Factories
+//!
+//! Builders that insert users, categories, and items for integration tests,
+//! filling in unique defaults for whatever a test does not care about:
+//!
+//! ```ignore
+//! let admin = UserFactory::new().admin().with_password("secret123").create(&pool).await;
+//! let news = CategoryFactory::new().name("news").create(&pool).await;
+//! let item = ItemFactory::new().title("Hello").category(&news).create(&pool).await;
+//! ```
+//!
+//! Built with the `testing` feature, which the crate's own tests enable.
+//! `create` panics when the insert fails, as a test should.
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use crate::auth::{ADMIN_ROLE, PasswordService};
+use crate::models::{Category, Item, User};
+
+/// Password of users whose factory was not given one
+pub const DEFAULT_PASSWORD: &str = "password123";
+
+/// Suffix keeping default names unique within a run and across runs that
+/// share a database
+fn unique(prefix: &str) -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let run = uuid::Uuid::new_v4().simple().to_string();
+    format!(
+        "{}_{}_{}",
+        prefix,
+        &run[..8],
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+// =============================================================================
+// Users
+// =============================================================================
+
+/// Builds and inserts a [`User`]: active, unverified, with
+/// [`DEFAULT_PASSWORD`] and no roles unless told otherwise
+#[derive(Debug, Clone)]
+pub struct UserFactory {
+    username: Option<String>,
+    email: Option<String>,
+    password: String,
+    email_verified: bool,
+    is_active: bool,
+    roles: Vec<String>,
+}
+
+impl Default for UserFactory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UserFactory {
+    pub fn new() -> Self {
+        Self {
+            username: None,
+            email: None,
+            password: DEFAULT_PASSWORD.to_string(),
+            email_verified: false,
+            is_active: true,
+            roles: Vec::new(),
+        }
+    }
+
+    /// Use this username; the email defaults to `<username>@example.com`
+    pub fn username(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn email(mut self, email: &str) -> Self {
+        self.email = Some(email.to_string());
+        self
+    }
+
+    pub fn with_password(mut self, password: &str) -> Self {
+        self.password = password.to_string();
+        self
+    }
+
+    pub fn verified(mut self) -> Self {
+        self.email_verified = true;
+        self
+    }
+
+    /// Create the user deactivated
+    pub fn inactive(mut self) -> Self {
+        self.is_active = false;
+        self
+    }
+
+    /// Grant [`ADMIN_ROLE`]
+    pub fn admin(self) -> Self {
+        self.role(ADMIN_ROLE)
+    }
+
+    pub fn role(mut self, role: &str) -> Self {
+        self.roles.push(role.to_string());
+        self
+    }
+
+    /// Insert the user and their roles
+    pub async fn create(self, pool: &PgPool) -> User {
+        let username = self.username.unwrap_or_else(|| unique("user"));
+        let email = self
+            .email
+            .unwrap_or_else(|| format!("{}@example.com", username));
+        let password_hash =
+            PasswordService::hash_password(&self.password).expect("Failed to hash password");
+
+        let user = sqlx::query_as!(
+            User,
+            r#"INSERT INTO users (username, email, password_hash, email_verified, is_active)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING id, username, email, password_hash, email_verified, is_active,
+                       display_name, bio, profile_data,
+                       last_login as "last_login: DateTime<Utc>",
+                       created_at as "created_at: DateTime<Utc>",
+                       updated_at as "updated_at: DateTime<Utc>""#,
+            username,
+            email,
+            password_hash,
+            self.email_verified,
+            self.is_active
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to create user {}: {}", username, e));
+
+        for role in &self.roles {
+            sqlx::query("INSERT INTO user_roles (user_id, role) VALUES ($1, $2)")
+                .bind(user.id)
+                .bind(role)
+                .execute(pool)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to grant {} to {}: {}", role, username, e));
+        }
+        user
+    }
+}
+
+// =============================================================================
+// Categories
+// =============================================================================
+
+/// Builds and inserts a visible [`Category`]
+#[derive(Debug, Clone)]
+pub struct CategoryFactory {
+    name: Option<String>,
+    display_name: Option<String>,
+    display_order: i32,
+    is_visible: bool,
+}
+
+impl Default for CategoryFactory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CategoryFactory {
+    pub fn new() -> Self {
+        Self {
+            name: None,
+            display_name: None,
+            display_order: 0,
+            is_visible: true,
+        }
+    }
+
+    /// Use this `category_name`; the display name defaults to it
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn display_name(mut self, display_name: &str) -> Self {
+        self.display_name = Some(display_name.to_string());
+        self
+    }
+
+    pub fn display_order(mut self, display_order: i32) -> Self {
+        self.display_order = display_order;
+        self
+    }
+
+    pub fn hidden(mut self) -> Self {
+        self.is_visible = false;
+        self
+    }
+
+    pub async fn create(self, pool: &PgPool) -> Category {
+        let name = self.name.unwrap_or_else(|| unique("category"));
+        let display_name = self.display_name.unwrap_or_else(|| name.clone());
+        sqlx::query_as::<_, Category>(
+            "INSERT INTO category (category_name, display_name, display_order, is_visible)
+             VALUES ($1, $2, $3, $4)
+             RETURNING id, category_name, display_name, is_visible, display_order,
+                       created_at, updated_at",
+        )
+        .bind(&name)
+        .bind(display_name)
+        .bind(self.display_order)
+        .bind(self.is_visible)
+        .fetch_one(pool)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to create category {}: {}", name, e))
+    }
+}
+
+// =============================================================================
+// Items
+// =============================================================================
+
+/// Builds and inserts an active [`Item`], in a new category unless given one
+#[derive(Debug, Clone)]
+pub struct ItemFactory {
+    title: Option<String>,
+    description: Option<String>,
+    data: Option<Value>,
+    category_id: Option<i32>,
+    is_active: bool,
+}
+
+impl Default for ItemFactory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ItemFactory {
+    pub fn new() -> Self {
+        Self {
+            title: None,
+            description: None,
+            data: None,
+            category_id: None,
+            is_active: true,
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_string());
+        self
+    }
+
+    pub fn data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+
+    pub fn category(self, category: &Category) -> Self {
+        self.category_id(category.id)
+    }
+
+    pub fn category_id(mut self, category_id: i32) -> Self {
+        self.category_id = Some(category_id);
+        self
+    }
+
+    pub fn inactive(mut self) -> Self {
+        self.is_active = false;
+        self
+    }
+
+    pub async fn create(self, pool: &PgPool) -> Item {
+        let category_id = match self.category_id {
+            Some(id) => id,
+            None => CategoryFactory::new().create(pool).await.id,
+        };
+        let title = self.title.unwrap_or_else(|| unique("item"));
+        sqlx::query_as::<_, Item>(
+            "INSERT INTO items (title, description, data, category_id, is_active)
+             VALUES ($1, $2, $3, $4, $5)
+             RETURNING id, title, description, data, is_active, category_id,
+                       created_at, updated_at",
+        )
+        .bind(&title)
+        .bind(self.description)
+        .bind(self.data)
+        .bind(category_id)
+        .bind(self.is_active)
+        .fetch_one(pool)
+        .await
+        .unwrap_or_else(|e| panic!("Failed to create item {}: {}", title, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_names_are_unique() {
+        let (first, second) = (unique("user"), unique("user"));
+        assert!(first.starts_with("user_"));
+        assert_ne!(first, second);
+        // Fits the 50 characters allowed for category names
+        assert!(unique("category").len() <= 50);
+    }
+}
@@ -0,0 +1,390 @@
+//! # Tenant Theming
+//!
+//! Per-tenant branding for server-rendered pages. A tenant is picked from the
+//! request's subdomain (`acme.example.com` → `acme`) or a verified custom
+//! domain by [`resolve_theme`], which loads its colors, logo, and template
+//! partial overrides for the rest of the request. Overrides come from `tenants/<slug>/partials/` on disk and
+//! from the `tenant_partials` table, the database winning when both exist.
+//! `render_template` picks the theme up through [`current`] and renders with
+//! [`templates_for`], so handlers need no changes.
+
+use axum::{
+    extract::{Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use tera::Tera;
+
+use crate::models::Tenant;
+use crate::output;
+use crate::services::TenantService;
+use crate::state::AppState;
+
+/// Partials a tenant may override
+pub const THEMEABLE_PARTIALS: &[&str] = &["partials/brand.html", "partials/footer.html"];
+
+/// Directory holding per-tenant assets (`tenants/<slug>/partials/...`)
+pub const TENANT_ASSET_DIR: &str = "tenants";
+
+tokio::task_local! {
+    static CURRENT_THEME: Arc<Theme>;
+}
+
+/// Branding applied to pages rendered for a tenant; unset fields are left
+/// out of the template context so `default` filters apply
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Theme {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub slug: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub primary_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub accent_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub logo_url: Option<String>,
+    /// Template overrides by name, e.g. `partials/footer.html`
+    #[serde(skip)]
+    pub partials: BTreeMap<String, String>,
+}
+
+impl Theme {
+    /// Theme for a tenant; invalid colors and logo URLs are dropped
+    pub fn from_tenant(tenant: &Tenant) -> Self {
+        Self {
+            slug: Some(tenant.slug.clone()),
+            name: Some(tenant.name.clone()),
+            primary_color: tenant.primary_color.clone().filter(|c| is_hex_color(c)),
+            accent_color: tenant.accent_color.clone().filter(|c| is_hex_color(c)),
+            logo_url: tenant.logo_url.clone().filter(|url| is_safe_logo_url(url)),
+            partials: BTreeMap::new(),
+        }
+    }
+}
+
+/// Theme of the tenant the current request is for, if any
+pub fn current() -> Option<Arc<Theme>> {
+    CURRENT_THEME.try_with(Arc::clone).ok()
+}
+
+/// Whether a value is a `#rrggbb` color
+pub fn is_hex_color(value: &str) -> bool {
+    value.len() == 7 && value.starts_with('#') && value[1..].chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether a logo URL is site-relative or HTTPS
+pub fn is_safe_logo_url(value: &str) -> bool {
+    (value.starts_with('/') && !value.starts_with("//")) || value.starts_with("https://")
+}
+
+/// Whether a slug is a valid DNS label (lowercase letters, digits, hyphens)
+pub fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 63
+        && !slug.starts_with('-')
+        && !slug.ends_with('-')
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// Tenant slug from a `Host` header, when it is one label below the public host
+pub fn tenant_slug_from_host(host: &str, public_host: &str) -> Option<String> {
+    let host = strip_port(host).to_ascii_lowercase();
+    let public_host = strip_port(public_host).to_ascii_lowercase();
+    let label = host.strip_suffix(&public_host)?.strip_suffix('.')?;
+    (is_valid_slug(label) && label != "www").then(|| label.to_string())
+}
+
+/// How a request's host names a tenant
+#[derive(Debug, PartialEq, Eq)]
+pub enum TenantHost {
+    /// `<slug>.<public host>`
+    Subdomain(String),
+    /// Any other DNS name, possibly a tenant's custom domain
+    CustomDomain(String),
+}
+
+/// Classify a `Host` header; the public host itself, IPs, and single-label
+/// names like `localhost` name no tenant
+pub fn tenant_host(host: &str, public_host: &str) -> Option<TenantHost> {
+    if let Some(slug) = tenant_slug_from_host(host, public_host) {
+        return Some(TenantHost::Subdomain(slug));
+    }
+
+    let name = strip_port(host).trim_end_matches('.').to_ascii_lowercase();
+    let public_host = strip_port(public_host).to_ascii_lowercase();
+    let is_ours = name == public_host || name.ends_with(&format!(".{}", public_host));
+    if is_ours || !name.contains('.') || name.parse::<IpAddr>().is_ok() {
+        return None;
+    }
+    Some(TenantHost::CustomDomain(name))
+}
+
+fn strip_port(host: &str) -> &str {
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    }
+}
+
+// =============================================================================
+// Middleware
+// =============================================================================
+
+/// Resolve the tenant for the request's host and scope its theme to the request
+pub async fn resolve_theme(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let tenant_host = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|host| tenant_host(host, &state.config.server.public_host()));
+
+    let Some(tenant_host) = tenant_host else {
+        return next.run(request).await;
+    };
+
+    match load_theme(&state, &tenant_host).await {
+        Ok(Some(theme)) => {
+            CURRENT_THEME
+                .scope(Arc::new(theme), next.run(request))
+                .await
+        }
+        Ok(None) => next.run(request).await,
+        Err(e) => {
+            output::warn(format!("Failed to load theme for {:?}: {}", tenant_host, e));
+            next.run(request).await
+        }
+    }
+}
+
+async fn load_theme(
+    state: &AppState,
+    tenant_host: &TenantHost,
+) -> Result<Option<Theme>, sqlx::Error> {
+    let tenant = match tenant_host {
+        TenantHost::Subdomain(slug) => TenantService::get_tenant_by_slug(&state.pool, slug).await?,
+        TenantHost::CustomDomain(domain) => {
+            TenantService::get_tenant_by_domain(&state.pool, domain).await?
+        }
+    };
+    let Some(tenant) = tenant else {
+        return Ok(None);
+    };
+
+    let mut theme = Theme::from_tenant(&tenant);
+    let partials_dir = Path::new(TENANT_ASSET_DIR)
+        .join(&tenant.slug)
+        .join("partials");
+    for name in THEMEABLE_PARTIALS {
+        let file = name.trim_start_matches("partials/");
+        if let Ok(body) = tokio::fs::read_to_string(partials_dir.join(file)).await {
+            theme.partials.insert(name.to_string(), body);
+        }
+    }
+    for (name, body) in TenantService::get_partials(&state.pool, tenant.id).await? {
+        if THEMEABLE_PARTIALS.contains(&name.as_str()) {
+            theme.partials.insert(name, body);
+        }
+    }
+
+    Ok(Some(theme))
+}
+
+// =============================================================================
+// Templates
+// =============================================================================
+
+/// Themed engines by tenant slug, tagged with a hash of their overrides
+type ThemeCache = Mutex<HashMap<String, (u64, Arc<Tera>)>>;
+
+static THEMED_TEMPLATES: OnceLock<ThemeCache> = OnceLock::new();
+
+/// Engine to render with for a theme: `base` itself, or a cached copy with the
+/// theme's partials swapped in. Overrides that fail to parse fall back to `base`.
+pub fn templates_for(base: &Arc<Tera>, theme: &Theme) -> Arc<Tera> {
+    let Some(slug) = theme.slug.as_ref().filter(|_| !theme.partials.is_empty()) else {
+        return base.clone();
+    };
+
+    // Apps with their own engine (see `web::scope_templates`) get their own copies
+    let mut hasher = DefaultHasher::new();
+    Arc::as_ptr(base).hash(&mut hasher);
+    theme.partials.hash(&mut hasher);
+    let hash = hasher.finish();
+
+    let cache = THEMED_TEMPLATES.get_or_init(Default::default);
+    if let Some((cached_hash, tera)) = cache.lock().expect("theme cache poisoned").get(slug)
+        && *cached_hash == hash
+    {
+        return tera.clone();
+    }
+
+    let mut tera = Tera::clone(base);
+    let overrides = theme
+        .partials
+        .iter()
+        .map(|(name, body)| (name.as_str(), body.as_str()));
+    if let Err(e) = tera.add_raw_templates(overrides) {
+        output::warn(format!(
+            "Ignoring invalid partials for tenant {}: {}",
+            slug, e
+        ));
+        return base.clone();
+    }
+
+    let tera = Arc::new(tera);
+    cache
+        .lock()
+        .expect("theme cache poisoned")
+        .insert(slug.clone(), (hash, tera.clone()));
+    tera
+}
+
+/// Forget every themed engine, after the base templates were reloaded
+#[cfg(debug_assertions)]
+pub fn clear_template_cache() {
+    if let Some(cache) = THEMED_TEMPLATES.get() {
+        cache.lock().expect("theme cache poisoned").clear();
+    }
+}
+
+/// Check that a partial may be overridden and that its body parses
+pub fn validate_partial(name: &str, body: &str) -> Result<(), String> {
+    if !THEMEABLE_PARTIALS.contains(&name) {
+        return Err(format!(
+            "'{}' cannot be overridden (allowed: {})",
+            name,
+            THEMEABLE_PARTIALS.join(", ")
+        ));
+    }
+    Tera::default()
+        .add_raw_template(name, body)
+        .map_err(|e| format!("Invalid template: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tera::Context;
+
+    #[test]
+    fn test_tenant_slug_from_host() {
+        let slug = |host| tenant_slug_from_host(host, "example.com");
+        assert_eq!(slug("acme.example.com"), Some("acme".to_string()));
+        assert_eq!(slug("ACME.example.com:8080"), Some("acme".to_string()));
+        assert_eq!(slug("example.com"), None);
+        assert_eq!(slug("www.example.com"), None);
+        assert_eq!(slug("a.b.example.com"), None);
+        assert_eq!(slug("acmeexample.com"), None);
+        assert_eq!(
+            tenant_slug_from_host("acme.localhost:3093", "localhost:3093"),
+            Some("acme".to_string())
+        );
+    }
+
+    #[test]
+    fn test_tenant_host() {
+        let classify = |host| tenant_host(host, "example.com:443");
+        assert_eq!(
+            classify("acme.example.com"),
+            Some(TenantHost::Subdomain("acme".to_string()))
+        );
+        assert_eq!(
+            classify("Shop.Acme.test:8443"),
+            Some(TenantHost::CustomDomain("shop.acme.test".to_string()))
+        );
+        assert_eq!(classify("example.com"), None);
+        assert_eq!(classify("www.example.com"), None);
+        assert_eq!(classify("localhost:3093"), None);
+        assert_eq!(classify("127.0.0.1:3093"), None);
+    }
+
+    #[test]
+    fn test_unsafe_branding_is_dropped() {
+        assert!(is_hex_color("#1a2B3c"));
+        assert!(!is_hex_color("red;}body{"));
+        assert!(is_safe_logo_url("/static/logo.png"));
+        assert!(is_safe_logo_url("https://cdn.example.com/logo.svg"));
+        assert!(!is_safe_logo_url("//evil.example.com/logo.svg"));
+        assert!(!is_safe_logo_url("javascript:alert(1)"));
+    }
+
+    #[test]
+    fn test_partials_override_base_templates() {
+        let mut base = Tera::default();
+        base.add_raw_templates(vec![
+            ("partials/footer.html", "default footer"),
+            ("page.html", "[{% include \"partials/footer.html\" %}]"),
+        ])
+        .unwrap();
+        let base = Arc::new(base);
+
+        let mut theme = Theme {
+            slug: Some("test-override".to_string()),
+            ..Theme::default()
+        };
+        let render = |tera: &Tera| tera.render("page.html", &Context::new()).unwrap();
+        assert_eq!(render(&templates_for(&base, &theme)), "[default footer]");
+
+        theme.partials.insert(
+            "partials/footer.html".to_string(),
+            "acme footer".to_string(),
+        );
+        assert_eq!(render(&templates_for(&base, &theme)), "[acme footer]");
+        assert_eq!(render(&base), "[default footer]");
+
+        theme
+            .partials
+            .insert("partials/footer.html".to_string(), "{% if %}".to_string());
+        assert_eq!(render(&templates_for(&base, &theme)), "[default footer]");
+    }
+
+    #[test]
+    fn test_reloaded_base_templates_reach_themes_after_clearing() {
+        let base = |page: &str| {
+            let mut tera = Tera::default();
+            tera.add_raw_templates(vec![
+                ("partials/footer.html", "default footer"),
+                ("page.html", page),
+            ])
+            .unwrap();
+            Arc::new(tera)
+        };
+        let mut theme = Theme {
+            slug: Some("test-reload".to_string()),
+            ..Theme::default()
+        };
+        theme.partials.insert(
+            "partials/footer.html".to_string(),
+            "acme footer".to_string(),
+        );
+        let render = |tera: &Tera| tera.render("page.html", &Context::new()).unwrap();
+
+        let old = base("<{% include \"partials/footer.html\" %}>");
+        assert_eq!(render(&templates_for(&old, &theme)), "<acme footer>");
+
+        let new = base("({% include \"partials/footer.html\" %})");
+        clear_template_cache();
+        assert_eq!(render(&templates_for(&new, &theme)), "(acme footer)");
+    }
+
+    #[test]
+    fn test_validate_partial() {
+        assert!(validate_partial("partials/footer.html", "<p>{{ theme.name }}</p>").is_ok());
+        assert!(validate_partial("partials/footer.html", "{% if %}").is_err());
+        assert!(validate_partial("base.html", "<html></html>").is_err());
+    }
+}
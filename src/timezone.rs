@@ -0,0 +1,141 @@
+//! # Time Zones
+//!
+//! Users may pick an IANA time zone such as `Europe/Berlin` on their profile
+//! page, and pages show times in it. The choice is copied into the session at
+//! login and whenever it changes, so the [`track`] middleware can scope it to
+//! each request without a database query. `render_template` helpers convert
+//! timestamps with [`current`]; without a choice, times are shown in UTC.
+
+use axum::{extract::Request, middleware::Next, response::Response};
+use chrono_tz::{TZ_VARIANTS, Tz};
+use tower_sessions::Session;
+
+/// Session key holding the signed-in user's time zone name
+pub(crate) const TIMEZONE_SESSION_KEY: &str = "timezone";
+
+tokio::task_local! {
+    static CURRENT_ZONE: Tz;
+}
+
+/// Parse an IANA time zone name
+pub fn parse(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+/// Names of all known time zones, for pickers
+pub fn names() -> impl Iterator<Item = &'static str> {
+    TZ_VARIANTS.iter().map(|zone| zone.name())
+}
+
+/// Time zone of the current request's user; UTC outside [`track`] or when
+/// the user has not chosen one
+pub fn current() -> Tz {
+    CURRENT_ZONE.try_with(|zone| *zone).unwrap_or(Tz::UTC)
+}
+
+/// Store a user's time zone in their session, or clear it for UTC
+pub async fn remember(
+    session: &Session,
+    timezone: Option<&str>,
+) -> Result<(), tower_sessions::session::Error> {
+    match timezone {
+        Some(timezone) => session.insert(TIMEZONE_SESSION_KEY, timezone).await,
+        None => session
+            .remove::<String>(TIMEZONE_SESSION_KEY)
+            .await
+            .map(|_| ()),
+    }
+}
+
+/// Middleware scoping the session's time zone to the request. Must run
+/// inside the session layer.
+pub async fn track(request: Request, next: Next) -> Response {
+    let Some(session) = request.extensions().get::<Session>().cloned() else {
+        return next.run(request).await;
+    };
+
+    let zone = session
+        .get::<String>(TIMEZONE_SESSION_KEY)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|name| parse(&name));
+    match zone {
+        Some(zone) => CURRENT_ZONE.scope(zone, next.run(request)).await,
+        None => next.run(request).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("Europe/Berlin"), Some(Tz::Europe__Berlin));
+        assert_eq!(parse(" UTC "), Some(Tz::UTC));
+        assert_eq!(parse("Mars/Olympus_Mons"), None);
+        assert!(names().any(|name| name == "America/New_York"));
+    }
+
+    #[tokio::test]
+    async fn test_zone_follows_the_session() {
+        let app = Router::new()
+            .route("/zone", get(|| async { current().name().to_string() }))
+            .route(
+                "/choose",
+                get(|session: Session| async move {
+                    remember(&session, Some("Asia/Tokyo")).await.unwrap();
+                }),
+            )
+            .layer(axum::middleware::from_fn(track))
+            .layer(SessionManagerLayer::new(MemoryStore::default()));
+
+        let get_zone = |cookie: Option<String>| {
+            let app = app.clone();
+            async move {
+                let mut request = Request::builder().uri("/zone");
+                if let Some(cookie) = cookie {
+                    request = request.header("cookie", cookie);
+                }
+                let response = app
+                    .oneshot(request.body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                String::from_utf8(body.to_vec()).unwrap()
+            }
+        };
+
+        assert_eq!(get_zone(None).await, "UTC");
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::builder()
+                    .uri("/choose")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let cookie = response.headers()["set-cookie"]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+        assert_eq!(get_zone(Some(cookie)).await, "Asia/Tokyo");
+    }
+
+    #[test]
+    fn test_current_outside_a_request_is_utc() {
+        assert_eq!(current(), Tz::UTC);
+    }
+}
@@ -0,0 +1,152 @@
+//! # TypeScript Types
+//!
+//! Declarations for the JSON API, written by `admin export-types --lang ts`.
+//! Models derive [`TS`] next to `ToSchema`, so the interfaces follow the serde
+//! representation: renamed, skipped, and flattened fields match what the API
+//! sends. The bundle is one `.d.ts` file a frontend can check in or generate
+//! in its build.
+//!
+//! Integers that are `i64` or `usize` in Rust are declared as `number`, as
+//! that is what `JSON.parse` gives; JSON fields are `unknown`.
+
+use ts_rs::TS;
+
+use crate::models::{
+    ActivityEntry, AddDomainRequest, AssignRolesRequest, AuditEntry, Category,
+    ChangeUsernameRequest, ComponentHealth, CreateTenantRequest, CreateUserRequest, CursorMeta,
+    DatabaseHealthInfo, EmailSuppression, Envelope, ErrorDetail, HealthHistoryResponse,
+    HealthResponse, HealthSample, HealthStatus, HealthVerdict, Item, ItemAttachment, ItemDetail,
+    ItemSearchHit, ItemSuggestion, ItemWithCategory, JobWorker, JobsOverview, LivenessResponse,
+    Notification, OutboxEmail, PaginationMeta, PoolUsage, Preferences, ReadinessResponse,
+    ResourceReport, ResponseMeta, SaveSnippetRequest, SetPasswordRequest, Snippet, SqlQueryRequest,
+    SqlQueryResult, Tenant, TenantDomain, TenantPartialRequest, TenantThemeRequest, TunedValue,
+    UnreadCount, UpdatePreferencesRequest, Upload, UserResponse, UsernameChangeEntry,
+    WorkerLiveness,
+};
+
+/// Languages `admin export-types` can emit
+pub const LANGUAGES: &[&str] = &["ts"];
+
+/// The `.d.ts` bundle with every API type
+pub fn declarations() -> String {
+    let mut out = String::from(
+        "// Generated by `admin export-types --lang ts`. Do not edit by hand.\n\
+         // Every JSON response is an `Envelope<T>` whose `data` is the payload.\n",
+    );
+
+    // Envelope and error shapes first, then resources, then requests
+    declare::<Envelope<()>>(&mut out);
+    declare::<ResponseMeta>(&mut out);
+    declare::<PaginationMeta>(&mut out);
+    declare::<CursorMeta>(&mut out);
+    declare::<ErrorDetail>(&mut out);
+
+    declare::<HealthResponse>(&mut out);
+    declare::<HealthVerdict>(&mut out);
+    declare::<ComponentHealth>(&mut out);
+    declare::<DatabaseHealthInfo>(&mut out);
+    declare::<ResourceReport>(&mut out);
+    declare::<TunedValue>(&mut out);
+    declare::<LivenessResponse>(&mut out);
+    declare::<ReadinessResponse>(&mut out);
+    declare::<PoolUsage>(&mut out);
+    declare::<WorkerLiveness>(&mut out);
+    declare::<HealthStatus>(&mut out);
+    declare::<HealthSample>(&mut out);
+    declare::<HealthHistoryResponse>(&mut out);
+    declare::<UserResponse>(&mut out);
+    declare::<Category>(&mut out);
+    declare::<Item>(&mut out);
+    declare::<ItemWithCategory>(&mut out);
+    declare::<ItemDetail>(&mut out);
+    declare::<ItemAttachment>(&mut out);
+    declare::<ItemSuggestion>(&mut out);
+    declare::<ItemSearchHit>(&mut out);
+    declare::<Upload>(&mut out);
+    declare::<Tenant>(&mut out);
+    declare::<TenantDomain>(&mut out);
+    declare::<OutboxEmail>(&mut out);
+    declare::<EmailSuppression>(&mut out);
+    declare::<JobWorker>(&mut out);
+    declare::<JobsOverview>(&mut out);
+    declare::<AuditEntry>(&mut out);
+    declare::<ActivityEntry>(&mut out);
+    declare::<UsernameChangeEntry>(&mut out);
+    declare::<Preferences>(&mut out);
+    declare::<Notification>(&mut out);
+    declare::<UnreadCount>(&mut out);
+    declare::<Snippet>(&mut out);
+    declare::<SqlQueryResult>(&mut out);
+
+    declare::<CreateUserRequest>(&mut out);
+    declare::<SetPasswordRequest>(&mut out);
+    declare::<AssignRolesRequest>(&mut out);
+    declare::<ChangeUsernameRequest>(&mut out);
+    declare::<UpdatePreferencesRequest>(&mut out);
+    declare::<SqlQueryRequest>(&mut out);
+    declare::<CreateTenantRequest>(&mut out);
+    declare::<TenantThemeRequest>(&mut out);
+    declare::<TenantPartialRequest>(&mut out);
+    declare::<AddDomainRequest>(&mut out);
+    declare::<SaveSnippetRequest>(&mut out);
+
+    out
+}
+
+/// Append `export type ...` for `T`, with its doc comment
+fn declare<T: TS>(out: &mut String) {
+    out.push('\n');
+    if let Some(docs) = T::docs() {
+        out.push_str(&docs);
+    }
+    out.push_str("export ");
+    out.push_str(&T::decl());
+    out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_declarations() {
+        let bundle = declarations();
+
+        assert!(bundle.contains("export type Envelope<T> = {"));
+        assert!(bundle.contains("data: T | null"));
+        assert!(bundle.contains("export type ErrorDetail = {"));
+        assert!(bundle.contains("export type UserResponse = {"));
+        assert!(bundle.contains("export type ItemWithCategory = "));
+        assert!(bundle.contains("export type CreateUserRequest = {"));
+        // Skipped by serde, so not sent
+        assert!(!bundle.contains("storage_key"));
+        // Everything is declared in the bundle, not imported
+        assert!(!bundle.contains("import "));
+        assert!(!bundle.contains("bigint"));
+    }
+
+    #[test]
+    fn test_declarations_reference_declared_types() {
+        let bundle = declarations();
+
+        for name in [
+            "Item",
+            "Category",
+            "ResponseMeta",
+            "PaginationMeta",
+            "CursorMeta",
+            "WorkerLiveness",
+            "PoolUsage",
+            "HealthVerdict",
+            "ComponentHealth",
+            "HealthStatus",
+            "HealthSample",
+        ] {
+            assert!(
+                bundle.contains(&format!("export type {} =", name)),
+                "{} is used but not declared",
+                name
+            );
+        }
+    }
+}
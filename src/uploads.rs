@@ -0,0 +1,608 @@
+//! # File Uploads
+//!
+//! Signed-in users upload files as `multipart/form-data` to `/api/uploads`
+//! and download them from `/files/{id}`. Uploads over `UPLOAD_MAX_BYTES` are
+//! rejected while they stream in, and only types in `UPLOAD_ALLOWED_TYPES`
+//! are accepted; well-known binary types must also match their leading
+//! bytes, so a script cannot pass as an image.
+//!
+//! File contents go to a [`StorageBackend`] under a random key and the
+//! metadata to the `uploads` table. Files are private: only the uploader and
+//! admins can download or delete them, and anyone else gets a 404.
+
+use async_trait::async_trait;
+use axum::{
+    Router,
+    body::Body,
+    extract::{DefaultBodyLimit, Multipart, Path, State, multipart::MultipartError},
+    http::{StatusCode, header},
+    response::Response,
+    routing::{get, post},
+};
+use bytes::{Bytes, BytesMut};
+use sqlx::PgPool;
+use std::path::PathBuf;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::api::RequestId;
+use crate::auth::{ADMIN_ROLE, CurrentUser};
+use crate::config::UploadsConfig;
+use crate::error::{AppError, AppResult};
+use crate::formats::{Accept, Negotiated};
+use crate::models::{AuthenticatedUser, Envelope, Upload};
+use crate::plugins::Event;
+use crate::services::{UploadService, UserService};
+use crate::state::AppState;
+
+/// Multipart field holding the file
+pub const FILE_FIELD: &str = "file";
+
+/// Longest stored file name
+const MAX_FILENAME_LENGTH: usize = 255;
+
+/// Room for multipart boundaries and part headers on top of the file limit
+pub(crate) const MULTIPART_OVERHEAD: usize = 16 * 1024;
+
+/// Routes for uploading, listing, downloading, and deleting files
+pub fn routes(config: &UploadsConfig) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/uploads",
+            post(upload_file)
+                .get(list_uploads)
+                .layer(DefaultBodyLimit::max(config.max_bytes + MULTIPART_OVERHEAD)),
+        )
+        .route("/files/{id}", get(download_file).delete(delete_file))
+}
+
+// =============================================================================
+// Storage Backends
+// =============================================================================
+
+/// Where upload contents are kept. Keys are generated by this module and
+/// contain only letters, digits, and hyphens.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Short name for startup logs
+    fn name(&self) -> &'static str;
+
+    /// Store `data` under `key`, replacing anything already there
+    async fn put(&self, key: &str, data: Bytes) -> AppResult<()>;
+
+    /// Read the contents stored under `key`
+    async fn get(&self, key: &str) -> AppResult<Bytes>;
+
+    /// Remove the contents under `key`; missing keys are not an error
+    async fn delete(&self, key: &str) -> AppResult<()>;
+
+    /// Whether the backend can be reached, for `/health`
+    async fn check(&self) -> AppResult<()> {
+        Ok(())
+    }
+}
+
+/// Build the configured storage backend
+pub fn storage_from_config(config: &UploadsConfig) -> Arc<dyn StorageBackend> {
+    #[cfg(feature = "s3")]
+    if config.storage == "s3" {
+        return Arc::new(
+            crate::s3::S3Storage::new(config)
+                .expect("S3 settings are checked by AppConfig::validate"),
+        );
+    }
+    Arc::new(LocalStorage::new(&config.dir))
+}
+
+/// Files in a directory on local disk (`UPLOAD_DIR`)
+#[derive(Debug, Clone)]
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    /// Store files under `root`, which is created on first write
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> AppResult<PathBuf> {
+        if !is_valid_key(key) {
+            return Err(AppError::Internal(format!("invalid storage key '{}'", key)));
+        }
+        Ok(self.root.join(key))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalStorage {
+    fn name(&self) -> &'static str {
+        "local disk"
+    }
+
+    async fn put(&self, key: &str, data: Bytes) -> AppResult<()> {
+        let path = self.path(key)?;
+        tokio::fs::create_dir_all(&self.root)
+            .await
+            .map_err(|e| storage_error(&self.root, e))?;
+
+        // Write then rename so readers never see a partial file
+        let partial = path.with_extension("partial");
+        tokio::fs::write(&partial, &data)
+            .await
+            .map_err(|e| storage_error(&partial, e))?;
+        tokio::fs::rename(&partial, &path)
+            .await
+            .map_err(|e| storage_error(&path, e))
+    }
+
+    async fn get(&self, key: &str) -> AppResult<Bytes> {
+        let path = self.path(key)?;
+        match tokio::fs::read(&path).await {
+            Ok(data) => Ok(Bytes::from(data)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Err(AppError::NotFound("File contents are missing".to_string()))
+            }
+            Err(e) => Err(storage_error(&path, e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> AppResult<()> {
+        let path = self.path(key)?;
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(storage_error(&path, e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn check(&self) -> AppResult<()> {
+        // The directory is created on the first upload, so missing is fine
+        match tokio::fs::metadata(&self.root).await {
+            Ok(metadata) if metadata.is_dir() => Ok(()),
+            Ok(_) => Err(AppError::Internal(format!(
+                "{} is not a directory",
+                self.root.display()
+            ))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(storage_error(&self.root, e)),
+        }
+    }
+}
+
+fn storage_error(path: &std::path::Path, err: std::io::Error) -> AppError {
+    AppError::Internal(format!("storage error at {}: {}", path.display(), err))
+}
+
+/// Keys are generated here, so anything else is a bug or tampering
+pub(crate) fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// Multipart body of an upload (for the OpenAPI document)
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct UploadForm {
+    /// The file; its part must carry a file name and content type
+    #[schema(value_type = String, format = Binary)]
+    file: Vec<u8>,
+}
+
+/// Upload a file
+#[utoipa::path(
+    post,
+    path = "/api/uploads",
+    tag = "files",
+    request_body(content = UploadForm, content_type = "multipart/form-data"),
+    responses(
+        (status = 201, description = "File stored", body = Envelope<Upload>),
+        (status = 400, description = "Missing file, or a type that is not allowed", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 413, description = "File over UPLOAD_MAX_BYTES", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn upload_file(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    mut multipart: Multipart,
+) -> AppResult<(StatusCode, Negotiated<Envelope<Upload>>)> {
+    let file = receive_file(&mut multipart, &state.config.uploads).await?;
+
+    let key = Uuid::new_v4().to_string();
+    let size_bytes = file.data.len() as i64;
+    state.storage.put(&key, file.data).await?;
+    let upload = match UploadService::create(
+        &state.pool,
+        user.id,
+        &file.filename,
+        &file.content_type,
+        size_bytes,
+        &key,
+    )
+    .await
+    {
+        Ok(upload) => upload,
+        Err(e) => {
+            let _ = state.storage.delete(&key).await;
+            return Err(e.into());
+        }
+    };
+    state
+        .plugins
+        .emit(&state, Event::FileUploaded(upload.clone()));
+
+    Ok((
+        StatusCode::CREATED,
+        Negotiated(
+            format,
+            Envelope::success(upload)
+                .with_message("File uploaded")
+                .with_request_id(request_id),
+        ),
+    ))
+}
+
+/// List the signed-in user's uploads, newest first
+#[utoipa::path(
+    get,
+    path = "/api/uploads",
+    tag = "files",
+    responses(
+        (status = 200, description = "The user's uploads", body = Envelope<Vec<Upload>>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn list_uploads(
+    CurrentUser(user): CurrentUser,
+    State(pool): State<PgPool>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+) -> AppResult<Negotiated<Envelope<Vec<Upload>>>> {
+    let uploads = UploadService::list_for_user(&pool, user.id).await?;
+    Ok(Negotiated(
+        format,
+        Envelope::success(uploads).with_request_id(request_id),
+    ))
+}
+
+/// Download a file
+#[utoipa::path(
+    get,
+    path = "/files/{id}",
+    tag = "files",
+    params(("id" = Uuid, Path, description = "Upload ID")),
+    responses(
+        (status = 200, description = "The file contents"),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such file, or not the caller's", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn download_file(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> AppResult<Response> {
+    let upload = find_accessible(&state.pool, &user, id).await?;
+    let data = state.storage.get(&upload.storage_key).await?;
+    file_response(&upload.content_type, &upload.filename, data)
+}
+
+/// Delete a file
+#[utoipa::path(
+    delete,
+    path = "/files/{id}",
+    tag = "files",
+    params(("id" = Uuid, Path, description = "Upload ID")),
+    responses(
+        (status = 200, description = "File deleted", body = Envelope<serde_json::Value>),
+        (status = 401, description = "Not signed in", body = Envelope<serde_json::Value>),
+        (status = 404, description = "No such file, or not the caller's", body = Envelope<serde_json::Value>)
+    ),
+    security(("session" = []))
+)]
+pub async fn delete_file(
+    CurrentUser(user): CurrentUser,
+    State(state): State<AppState>,
+    Accept(format): Accept,
+    RequestId(request_id): RequestId,
+    Path(id): Path<Uuid>,
+) -> AppResult<Negotiated<Envelope<()>>> {
+    let upload = find_accessible(&state.pool, &user, id).await?;
+    UploadService::delete(&state.pool, upload.id).await?;
+    state.storage.delete(&upload.storage_key).await?;
+    state.plugins.emit(&state, Event::FileDeleted(upload));
+
+    Ok(Negotiated(
+        format,
+        Envelope::message("File deleted").with_request_id(request_id),
+    ))
+}
+
+// =============================================================================
+// Helpers
+// =============================================================================
+
+/// A file read from a multipart body and checked against the upload
+/// settings
+pub(crate) struct ReceivedFile {
+    pub filename: String,
+    pub content_type: String,
+    pub data: Bytes,
+}
+
+/// Read the [`FILE_FIELD`] part, rejecting types not in
+/// `UPLOAD_ALLOWED_TYPES`, contents over `UPLOAD_MAX_BYTES` as they stream
+/// in, and contents that contradict their type
+pub(crate) async fn receive_file(
+    multipart: &mut Multipart,
+    config: &UploadsConfig,
+) -> AppResult<ReceivedFile> {
+    let mut field = loop {
+        match multipart.next_field().await.map_err(multipart_error)? {
+            Some(field) if field.name() == Some(FILE_FIELD) => break field,
+            Some(_) => continue,
+            None => {
+                return Err(AppError::Validation(format!(
+                    "A '{}' file field is required",
+                    FILE_FIELD
+                )));
+            }
+        }
+    };
+
+    let filename = sanitize_filename(field.file_name().unwrap_or_default());
+    let content_type = field
+        .content_type()
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map(|mime| mime.essence_str().to_ascii_lowercase())
+        .unwrap_or_default();
+    if !config
+        .allowed_types
+        .iter()
+        .any(|allowed| allowed == &content_type)
+    {
+        return Err(AppError::Validation(format!(
+            "File type '{}' is not allowed",
+            content_type
+        )));
+    }
+
+    let mut data = BytesMut::new();
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        if data.len() + chunk.len() > config.max_bytes {
+            return Err(too_large(config.max_bytes));
+        }
+        data.extend_from_slice(&chunk);
+    }
+    if data.is_empty() {
+        return Err(AppError::Validation("The file is empty".to_string()));
+    }
+    check_contents(&content_type, &data)?;
+
+    Ok(ReceivedFile {
+        filename,
+        content_type,
+        data: data.freeze(),
+    })
+}
+
+/// A stored file as a download that the browser will not sniff or run
+pub(crate) fn file_response(
+    content_type: &str,
+    filename: &str,
+    data: Bytes,
+) -> AppResult<Response> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, data.len())
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(content_type, filename),
+        )
+        .header(header::CACHE_CONTROL, "private, no-cache")
+        // Never let the browser reinterpret or run what users uploaded
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(header::CONTENT_SECURITY_POLICY, "sandbox")
+        .body(Body::from(data))
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// An upload the user may access: their own, or any for admins
+async fn find_accessible(pool: &PgPool, user: &AuthenticatedUser, id: Uuid) -> AppResult<Upload> {
+    let not_found = || AppError::NotFound(format!("File {} not found", id));
+    let upload = UploadService::find(pool, id).await?.ok_or_else(not_found)?;
+    if upload.user_id == user.id {
+        return Ok(upload);
+    }
+
+    let roles = UserService::get_user_roles(pool, user.id).await?;
+    if roles.iter().any(|role| role == ADMIN_ROLE) {
+        Ok(upload)
+    } else {
+        Err(not_found())
+    }
+}
+
+fn too_large(max_bytes: usize) -> AppError {
+    AppError::PayloadTooLarge(format!("Files are limited to {} bytes", max_bytes))
+}
+
+/// Body-limit failures become 413s; anything else is a malformed request
+pub(crate) fn multipart_error(err: MultipartError) -> AppError {
+    if err.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge(err.body_text())
+    } else {
+        AppError::Validation(err.body_text())
+    }
+}
+
+/// Type indicated by the leading bytes, for types with a fixed signature
+pub(crate) fn sniff(data: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+    ];
+    if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| data.starts_with(signature))
+        .map(|(_, mime)| *mime)
+}
+
+/// Reject contents whose signature contradicts the declared type, and
+/// well-known binary types without their signature
+fn check_contents(content_type: &str, data: &[u8]) -> AppResult<()> {
+    const SIGNED_TYPES: &[&str] = &[
+        "image/png",
+        "image/jpeg",
+        "image/gif",
+        "image/webp",
+        "application/pdf",
+    ];
+
+    let sniffed = sniff(data);
+    let mismatch = match sniffed {
+        Some(sniffed) => sniffed != content_type,
+        None => SIGNED_TYPES.contains(&content_type),
+    };
+    if mismatch {
+        return Err(AppError::Validation(format!(
+            "The file contents are not {}",
+            content_type
+        )));
+    }
+    Ok(())
+}
+
+/// Keep the final path component of a client file name, without control
+/// characters, at most [`MAX_FILENAME_LENGTH`] bytes
+fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base.chars().filter(|c| !c.is_control()).collect();
+    let mut cleaned = cleaned.trim().to_string();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        return "file".to_string();
+    }
+    while cleaned.len() > MAX_FILENAME_LENGTH {
+        cleaned.pop();
+    }
+    cleaned
+}
+
+/// Show raster images inline and download everything else, with an ASCII
+/// fallback name and the exact one in RFC 5987 encoding
+fn content_disposition(content_type: &str, filename: &str) -> String {
+    let inline = content_type.starts_with("image/") && content_type != "image/svg+xml";
+    let ascii: String = filename
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let encoded: String = filename
+        .bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+                (b as char).to_string()
+            } else {
+                format!("%{:02X}", b)
+            }
+        })
+        .collect();
+
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        if inline { "inline" } else { "attachment" },
+        ascii,
+        encoded
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_storage_round_trip() {
+        let root = std::env::temp_dir().join(format!("axum-base-storage-{}", Uuid::new_v4()));
+        let storage = LocalStorage::new(&root);
+        let key = Uuid::new_v4().to_string();
+
+        storage
+            .put(&key, Bytes::from_static(b"hello"))
+            .await
+            .unwrap();
+        assert_eq!(
+            storage.get(&key).await.unwrap(),
+            Bytes::from_static(b"hello")
+        );
+
+        storage.delete(&key).await.unwrap();
+        storage.delete(&key).await.unwrap();
+        assert!(matches!(
+            storage.get(&key).await,
+            Err(AppError::NotFound(_))
+        ));
+        assert!(storage.get("../etc/passwd").await.is_err());
+
+        std::fs::remove_dir_all(&root).ok();
+    }
+
+    #[test]
+    fn test_check_contents() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        assert!(check_contents("image/png", png).is_ok());
+        assert!(check_contents("image/jpeg", png).is_err());
+        assert!(check_contents("text/plain", png).is_err());
+        assert!(check_contents("image/png", b"<script>alert(1)</script>").is_err());
+        assert!(check_contents("text/plain", b"just text").is_ok());
+        assert!(check_contents("image/webp", b"RIFF\0\0\0\0WEBPVP8 ").is_ok());
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        assert_eq!(
+            sanitize_filename("C:\\Users\\jane\\report.pdf"),
+            "report.pdf"
+        );
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("bad\r\nname.txt"), "badname.txt");
+        assert_eq!(sanitize_filename(".."), "file");
+        assert_eq!(sanitize_filename(""), "file");
+        assert_eq!(
+            sanitize_filename(&"a".repeat(300)).len(),
+            MAX_FILENAME_LENGTH
+        );
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("image/png", "cat.png"),
+            "inline; filename=\"cat.png\"; filename*=UTF-8''cat.png"
+        );
+        assert_eq!(
+            content_disposition("application/pdf", "Résumé \"v2\".pdf"),
+            "attachment; filename=\"R_sum_ _v2_.pdf\"; filename*=UTF-8''R%C3%A9sum%C3%A9%20%22v2%22.pdf"
+        );
+        assert!(content_disposition("image/svg+xml", "x.svg").starts_with("attachment"));
+    }
+}
@@ -3,42 +3,531 @@
 //! Handlers for HTML pages, static files, and error responses.
 
 use axum::{
-    extract::{Form, State},
-    http::{StatusCode, Uri},
-    response::{Html, Json, Redirect},
+    extract::{Form, Path, Query, Request, State},
+    http::{HeaderMap, StatusCode, Uri, header},
+    middleware::Next,
+    response::{Html, IntoResponse, Redirect, Response},
 };
 use chrono::{DateTime, Datelike, Timelike, Utc};
+use serde::Deserialize;
 use serde_json::json;
 use sqlx::PgPool;
-use std::collections::HashMap;
-use std::sync::OnceLock;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path as FsPath, PathBuf};
+#[cfg(debug_assertions)]
+use std::sync::RwLock;
+use std::sync::{Arc, OnceLock};
 use tera::{Context, Tera};
 use tower_sessions::Session;
 
-use crate::auth::{AuthService, USER_SESSION_KEY};
-use crate::models::{ApiResponse, AuthenticatedUser, LoginRequest};
+use crate::activity;
+use crate::admin;
+use crate::audit::{self, AuditEvent};
+use crate::auth::{AdminUser, USER_SESSION_KEY, renew_session};
+use crate::client_ip::ClientIp;
+use crate::emails::{self, EmailCategory};
+use crate::error::{AppError, AppResult};
+use crate::flash::{self, Flash};
+use crate::i18n;
+use crate::jobs;
+use crate::login_guard;
+use crate::models::{
+    AuditFilter, AuthenticatedUser, EmailTemplateForm, EmailTemplateRevertForm, LoginRequest,
+    ProfileFields, SqlConsoleForm, SqlQueryResult, UsernameChange,
+};
+use crate::nav;
+use crate::notifications;
+use crate::output;
+use crate::pagination::Pagination;
+use crate::plugins::Event;
+use crate::preferences;
+use crate::scope::RequestScope;
+use crate::services::{
+    AuditService, EmailDeliveryService, EmailPreferenceService, EmailTemplateService,
+    NotificationService, PreferencesService, UserService,
+};
+use crate::snippets::{self, SnippetContext};
+use crate::sql_console;
+use crate::static_files;
+use crate::state::AppState;
+use crate::theming;
+use crate::timezone;
 
-/// Global Tera instance
-static TEMPLATES: OnceLock<Tera> = OnceLock::new();
+/// Global Tera instance (shared with `AppState::templates`). Release builds
+/// read it without locking; debug builds keep it behind a lock so
+/// [`watch_templates`] can swap in edited templates.
+#[cfg(not(debug_assertions))]
+static TEMPLATES: OnceLock<Arc<Tera>> = OnceLock::new();
+#[cfg(debug_assertions)]
+static TEMPLATES: OnceLock<RwLock<Arc<Tera>>> = OnceLock::new();
 
-/// Initialize the template engine
-pub fn init_templates() -> Result<(), tera::Error> {
-    let tera = Tera::new("templates/**/*")?;
-    TEMPLATES
-        .set(tera)
+tokio::task_local! {
+    /// Engine of the app serving the request, when it is not the installed one
+    static SCOPED_TEMPLATES: Arc<Tera>;
+}
+
+/// Directory templates are loaded from, and watched for changes
+pub const TEMPLATE_DIR: &str = "templates";
+
+/// Glob the template engine loads templates from
+#[allow(dead_code)]
+pub const TEMPLATE_GLOB: &str = "templates/**/*";
+
+/// Template directories contributed by plugins, set by [`init_templates_with`]
+static PLUGIN_TEMPLATE_DIRS: OnceLock<Vec<PathBuf>> = OnceLock::new();
+
+/// How long to wait for a burst of file events to settle before reloading
+#[cfg(debug_assertions)]
+const RELOAD_DEBOUNCE: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Load and parse all templates without installing them globally
+pub fn load_templates() -> Result<Tera, tera::Error> {
+    load_templates_from(FsPath::new(TEMPLATE_DIR))
+}
+
+/// Load and parse the templates under `dir` instead of [`TEMPLATE_DIR`], for
+/// callers whose working directory is elsewhere, such as tests
+pub fn load_templates_from(dir: &FsPath) -> Result<Tera, tera::Error> {
+    let mut tera = Tera::new(&dir.join("**").join("*").to_string_lossy())?;
+    for dir in PLUGIN_TEMPLATE_DIRS.get().into_iter().flatten() {
+        add_template_dir(&mut tera, dir)?;
+    }
+    Ok(tera)
+}
+
+/// Initialize the template engine and return a handle for application state
+#[allow(dead_code)]
+pub fn init_templates() -> Result<Arc<Tera>, tera::Error> {
+    init_templates_with(Vec::new())
+}
+
+/// Initialize the template engine, also loading the templates in
+/// `plugin_dirs` (see [`Plugin::template_dirs`](crate::plugins::Plugin::template_dirs))
+pub fn init_templates_with(plugin_dirs: Vec<PathBuf>) -> Result<Arc<Tera>, tera::Error> {
+    PLUGIN_TEMPLATE_DIRS
+        .set(plugin_dirs)
         .map_err(|_| tera::Error::msg("Failed to initialize template engine"))?;
+    let tera = Arc::new(load_templates()?);
+    #[cfg(debug_assertions)]
+    let installed = TEMPLATES.set(RwLock::new(tera.clone()));
+    #[cfg(not(debug_assertions))]
+    let installed = TEMPLATES.set(tera.clone());
+    installed.map_err(|_| tera::Error::msg("Failed to initialize template engine"))?;
+    Ok(tera)
+}
+
+/// Reload page templates whenever a file under [`TEMPLATE_DIR`] changes
+/// (debug builds only). Templates that fail to parse are reported and the
+/// previous ones stay in use. Keep the returned watcher alive.
+#[cfg(debug_assertions)]
+pub fn watch_templates() -> notify::Result<notify::RecommendedWatcher> {
+    use notify::{RecursiveMode, Watcher};
+
+    let (changes, mut received) = tokio::sync::mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok_and(|event| !event.kind.is_access()) {
+            let _ = changes.send(());
+        }
+    })?;
+    watcher.watch(std::path::Path::new(TEMPLATE_DIR), RecursiveMode::Recursive)?;
+    for dir in PLUGIN_TEMPLATE_DIRS.get().into_iter().flatten() {
+        watcher.watch(dir, RecursiveMode::Recursive)?;
+    }
+
+    tokio::spawn(async move {
+        while received.recv().await.is_some() {
+            // Editors save in several steps; reload once they are done
+            tokio::time::sleep(RELOAD_DEBOUNCE).await;
+            while received.try_recv().is_ok() {}
+            reload_templates();
+        }
+    });
+    Ok(watcher)
+}
+
+/// Parse the templates again and install them if they are valid
+#[cfg(debug_assertions)]
+fn reload_templates() {
+    let Some(templates) = TEMPLATES.get() else {
+        return;
+    };
+    match load_templates() {
+        Ok(tera) => {
+            *templates.write().expect("template lock poisoned") = Arc::new(tera);
+            theming::clear_template_cache();
+            output::info("Templates reloaded");
+        }
+        Err(e) => output::error(format!(
+            "Keeping the previous templates, reload failed: {}",
+            emails::error_chain(&e)
+        )),
+    }
+}
+
+/// Add the templates under `dir` that `tera` does not have yet, named by
+/// their path inside `dir`
+fn add_template_dir(tera: &mut Tera, dir: &FsPath) -> Result<(), tera::Error> {
+    let existing: HashSet<&str> = tera.get_template_names().collect();
+    let mut files = Vec::new();
+    collect_template_files(dir, dir, &mut files).map_err(|e| {
+        tera::Error::msg(format!(
+            "Failed to read templates in {}: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+    files.retain(|(_, name)| !existing.contains(name.as_str()));
+    tera.add_template_files(files.into_iter().map(|(path, name)| (path, Some(name))))
+}
+
+fn collect_template_files(
+    root: &FsPath,
+    dir: &FsPath,
+    files: &mut Vec<(PathBuf, String)>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_template_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let name = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            files.push((path, name));
+        }
+    }
     Ok(())
 }
 
-/// Get the global Tera instance
-fn get_templates() -> &'static Tera {
-    TEMPLATES.get().expect("Templates not initialized")
+// =============================================================================
+// Template Manifest
+// =============================================================================
+
+/// What a handler expects from the template it renders
+#[derive(Debug, Clone, Copy)]
+#[allow(dead_code)]
+pub struct TemplateSpec {
+    /// Template name passed to `render_template`
+    pub name: &'static str,
+    /// Blocks the template must define
+    pub blocks: &'static [&'static str],
+    /// Variables the handler puts in the context
+    pub variables: &'static [&'static str],
+}
+
+/// Templates rendered by the handlers in this module, verified by
+/// `admin templates check`. Keep in sync when handlers change their context.
+#[allow(dead_code)]
+pub const TEMPLATE_MANIFEST: &[TemplateSpec] = &[
+    TemplateSpec {
+        name: "index.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "description",
+            "features",
+            "endpoints",
+            "widgets",
+        ],
+    },
+    TemplateSpec {
+        name: "landing.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "page_title",
+            "page_description",
+            "landing_features",
+        ],
+    },
+    TemplateSpec {
+        name: "login.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "title",
+            "username",
+            "csrf_token",
+            "error",
+        ],
+    },
+    TemplateSpec {
+        name: "profile.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "user",
+            "locale",
+            "locales",
+            "timezone",
+            "timezones",
+            "display_name",
+            "bio",
+            "profile_data",
+            "email_categories",
+        ],
+    },
+    TemplateSpec {
+        name: "unsubscribe.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "category",
+            "action",
+            "done",
+        ],
+    },
+    TemplateSpec {
+        name: "admin_sql.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "query",
+            "result",
+            "error",
+        ],
+    },
+    TemplateSpec {
+        name: "admin_emails.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "templates",
+            "outbox",
+        ],
+    },
+    TemplateSpec {
+        name: "admin_email_edit.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "template",
+            "source",
+            "history",
+            "preview",
+            "success",
+            "error",
+        ],
+    },
+    TemplateSpec {
+        name: "admin_jobs.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "counts",
+            "liveness",
+            "workers",
+            "stall_timeout_secs",
+        ],
+    },
+    TemplateSpec {
+        name: "admin_audit.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "entries",
+            "action",
+            "actions",
+            "page",
+            "total_pages",
+        ],
+    },
+    TemplateSpec {
+        name: "profile_activity.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "entries",
+            "page",
+            "total_pages",
+        ],
+    },
+    TemplateSpec {
+        name: "notifications.html",
+        blocks: &["title", "content"],
+        variables: &[
+            "service_name",
+            "version",
+            "asset_version",
+            "server_time",
+            "theme",
+            "flash",
+            "nav",
+            "preferences",
+            "unread_notifications",
+            "current_user",
+            "is_authenticated",
+            "title",
+            "notifications",
+            "page",
+            "total_pages",
+        ],
+    },
+];
+
+/// Get the engine scoped to this request by [`scope_templates`], or else the
+/// global Tera instance
+fn get_templates() -> Arc<Tera> {
+    SCOPED_TEMPLATES
+        .try_with(Arc::clone)
+        .ok()
+        .or_else(installed_templates)
+        .expect("Templates not initialized")
 }
 
-/// Format a UTC DateTime to a human-readable format
-/// Example: "Sept 27th, 2025 @ 4:13pm"
+/// The global Tera instance
+#[cfg(not(debug_assertions))]
+fn installed_templates() -> Option<Arc<Tera>> {
+    TEMPLATES.get().cloned()
+}
+
+/// The current global Tera instance
+#[cfg(debug_assertions)]
+fn installed_templates() -> Option<Arc<Tera>> {
+    TEMPLATES
+        .get()
+        .map(|templates| templates.read().expect("template lock poisoned").clone())
+}
+
+/// Whether `templates` is the engine [`init_templates`] installed. Routers
+/// built around another engine render with it through [`scope_templates`].
+pub fn is_installed(templates: &Arc<Tera>) -> bool {
+    installed_templates().is_some_and(|installed| Arc::ptr_eq(&installed, templates))
+}
+
+/// Middleware rendering the request's pages with `templates` instead of the
+/// global engine
+pub async fn scope_templates(
+    State(templates): State<Arc<Tera>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    SCOPED_TEMPLATES.scope(templates, next.run(request)).await
+}
+
+/// Format a UTC DateTime to a human-readable format in the user's time zone
+/// Example: "Sept 27th, 2025 @ 4:13pm CEST"
 fn format_human_time(dt: DateTime<Utc>) -> String {
-    // Convert to local time if needed, but for now use UTC
+    let dt = dt.with_timezone(&timezone::current());
     let month = match dt.month() {
         1 => "Jan",
         2 => "Feb",
@@ -75,14 +564,15 @@ fn format_human_time(dt: DateTime<Utc>) -> String {
     };
 
     format!(
-        "{} {}{}, {} @ {}:{:02}{}",
+        "{} {}{}, {} @ {}:{:02}{} {}",
         month,
         day,
         day_suffix,
         dt.year(),
         hour_12,
         dt.minute(),
-        am_pm
+        am_pm,
+        dt.format("%Z")
     )
 }
 
@@ -94,6 +584,7 @@ fn create_base_context(additional_vars: HashMap<&str, serde_json::Value>) -> Con
     // Add common variables that appear in all templates
     context.insert("service_name", "Axum Base");
     context.insert("version", env!("CARGO_PKG_VERSION"));
+    context.insert("asset_version", static_files::asset_version());
     context.insert("server_time", &format_human_time(Utc::now()));
 
     // Add any additional variables passed in
@@ -114,6 +605,7 @@ fn create_base_context_with_user(
     // Add common variables that appear in all templates
     context.insert("service_name", "Axum Base");
     context.insert("version", env!("CARGO_PKG_VERSION"));
+    context.insert("asset_version", static_files::asset_version());
     context.insert("server_time", &format_human_time(Utc::now()));
 
     // Add user information if available
@@ -129,24 +621,28 @@ fn create_base_context_with_user(
 }
 
 /// Render a template with error handling
-fn render_template(
-    template_name: &str,
-    context: &Context,
-) -> Result<Html<String>, (StatusCode, String)> {
-    let tera = get_templates();
-    let rendered = tera.render(template_name, context).map_err(|err| {
-        eprintln!("Failed to render template '{}': {}", template_name, err);
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to render {}", template_name),
-        )
-    })?;
+fn render_template(template_name: &str, context: &Context) -> AppResult<Html<String>> {
+    // Brand the page for the request's tenant, if any
+    let theme = theming::current().unwrap_or_default();
+    let tera = theming::templates_for(&get_templates(), &theme);
+
+    let mut context = context.clone();
+    context.insert("theme", theme.as_ref());
+    // Pending flash messages; rendering marks them as shown
+    context.insert("flash", &flash::take());
+    // Links the visitor may follow, for the navbar and menus
+    context.insert("nav", nav::current().as_ref());
+    // Theme, language, time zone, and page size the user picked
+    context.insert("preferences", preferences::current().as_ref());
+    // Unread count for the navbar badge
+    context.insert("unread_notifications", &notifications::unread_count());
+    let rendered = tera.render(template_name, &context)?;
 
     Ok(Html(rendered))
 }
 
 /// Handler for the landing page - serves a generic landing page
-pub async fn serve_landing(session: Session) -> Result<Html<String>, (StatusCode, String)> {
+pub async fn serve_landing(scope: RequestScope) -> AppResult<Html<String>> {
     // Define landing page specific features
     let landing_features = json!([
         {
@@ -175,13 +671,12 @@ pub async fn serve_landing(session: Session) -> Result<Html<String>, (StatusCode
     page_vars.insert("page_description", json!("A production-ready foundation for building fast, secure web applications with Rust and Axum."));
     page_vars.insert("landing_features", landing_features);
 
-    let current_user = get_current_user(&session).await;
-    let context = create_base_context_with_user(page_vars, current_user.as_ref());
+    let context = create_base_context_with_user(page_vars, scope.user().await);
     render_template("landing.html", &context)
 }
 
 /// Handler for the root path - serves the welcome page using Tera templates
-pub async fn serve_index(session: Session) -> Result<Html<String>, (StatusCode, String)> {
+pub async fn serve_index(scope: RequestScope) -> AppResult<Html<String>> {
     // Define index page specific features
     let features = json!([
         {
@@ -221,8 +716,18 @@ pub async fn serve_index(session: Session) -> Result<Html<String>, (StatusCode,
     page_vars.insert("features", features);
     page_vars.insert("endpoints", endpoints);
 
-    let current_user = get_current_user(&session).await;
-    let context = create_base_context_with_user(page_vars, current_user.as_ref());
+    // Widgets admins wrote, rendered in the snippet sandbox
+    let user = scope.user().await;
+    let snippet_context = SnippetContext {
+        service_name: "Axum Base".to_string(),
+        username: user.map(|user| user.username.clone()),
+        locale: scope.locale().await.to_string(),
+    };
+    let widgets =
+        snippets::render_all(scope.pool(), snippets::DASHBOARD_PREFIX, &snippet_context).await;
+    page_vars.insert("widgets", json!(widgets));
+
+    let context = create_base_context_with_user(page_vars, user);
     render_template("index.html", &context)
 }
 
@@ -236,97 +741,292 @@ async fn get_current_user(session: &Session) -> Option<AuthenticatedUser> {
 }
 
 /// Login page handler
-pub async fn serve_login(session: Session) -> Result<Html<String>, Redirect> {
+pub async fn serve_login(
+    State(state): State<AppState>,
+    session: Session,
+    headers: HeaderMap,
+) -> Result<Response, Redirect> {
     // If user is already logged in, redirect to home
     if get_current_user(&session).await.is_some() {
         return Err(Redirect::to("/"));
     }
 
+    render_login(&state, &headers, None, None).map_err(|_| Redirect::to("/"))
+}
+
+/// Render the login form and hand out its token cookie, keeping the
+/// username of a failed attempt
+fn render_login(
+    state: &AppState,
+    headers: &HeaderMap,
+    username: Option<&str>,
+    error: Option<&str>,
+) -> AppResult<Response> {
+    let token = login_guard::token(headers);
     let mut page_vars = HashMap::new();
     page_vars.insert("title", json!("Login"));
-    page_vars.insert("error", json!(null));
+    page_vars.insert("username", json!(username));
+    page_vars.insert("csrf_token", json!(token));
+    page_vars.insert("error", json!(error));
+    page_vars.insert("sso_enabled", json!(state.config.sso.enabled));
 
     let context = create_base_context(page_vars);
+    let page = render_template("login.html", &context)?;
+    let secure = state.config.secure_cookies();
+    Ok((
+        [(header::SET_COOKIE, login_guard::set_cookie(&token, secure))],
+        page,
+    )
+        .into_response())
+}
 
-    match render_template("login.html", &context) {
-        Ok(html) => Ok(html),
-        Err(_) => Err(Redirect::to("/")),
+/// Signs `user` in on `session`, whichever way they proved who they are;
+/// false when the session could not be written
+pub(crate) async fn sign_in(
+    state: &AppState,
+    session: &Session,
+    flash: &Flash,
+    user: AuthenticatedUser,
+) -> bool {
+    let pool = &state.pool;
+    let settings = PreferencesService::get(pool, user.id)
+        .await
+        .unwrap_or_default();
+    let avatar = UserService::get_avatar_key(pool, user.id)
+        .await
+        .unwrap_or_default();
+    let user = user.with_avatar(avatar.as_deref());
+    // Store user (with avatar), their preferences, and their time zone in
+    // session, under a new ID so one planted before sign-in is not signed
+    // in too
+    if session.cycle_id().await.is_err()
+        || session.insert(USER_SESSION_KEY, &user).await.is_err()
+        || preferences::remember(session, &settings).await.is_err()
+    {
+        return false;
+    }
+    // Saved now so it has the ID that deactivation looks up
+    if session.save().await.is_ok()
+        && let Some(id) = session.id()
+        && let Err(e) = state.auth.track_session(user.id, &id.to_string()).await
+    {
+        output::error(format!(
+            "Failed to track session of user {}: {}",
+            user.id, e
+        ));
     }
+    flash
+        .success(format!("Welcome back, {}!", user.username))
+        .await;
+    audit::record(pool, AuditEvent::new(audit::LOGIN).by(user.id)).await;
+    state
+        .plugins
+        .emit(state, Event::UserLoggedIn { user_id: user.id });
+    true
 }
 
 /// Login form handler
 pub async fn handle_login(
-    State(pool): State<PgPool>,
+    State(state): State<AppState>,
     session: Session,
+    flash: Flash,
+    headers: HeaderMap,
+    ClientIp(client_ip): ClientIp,
     Form(login_data): Form<LoginRequest>,
-) -> Result<Redirect, Html<String>> {
+) -> Result<Redirect, Response> {
+    // Slow down guessing from one address, whatever the post holds
+    if let Some(ip) = client_ip
+        && let Err(retry_after) = state.login_limiter.check(ip)
+    {
+        return Err(AppError::RateLimited(retry_after).into_response());
+    }
+
+    // Turn away posts that did not come from the form before the session,
+    // the database, or Argon2 are touched
+    if let Err(reason) = login_guard::check(
+        &headers,
+        login_data.csrf_token.as_deref(),
+        login_data.website.as_deref(),
+    ) {
+        state.metrics.login_rejected(reason);
+        let page = render_login(
+            &state,
+            &headers,
+            Some(&login_data.username),
+            Some("Your sign-in form expired. Please try again."),
+        )
+        .unwrap_or_else(|_| Html("Login error".to_string()).into_response());
+        return Err((StatusCode::BAD_REQUEST, page).into_response());
+    }
+
+    let pool = &state.pool;
     // Attempt to authenticate the user
-    match AuthService::authenticate_user(&pool, &login_data.username, &login_data.password).await {
+    let error = match state
+        .auth
+        .authenticate_user(&login_data.username, &login_data.password)
+        .await
+    {
         Ok(Some(user)) => {
-            // Store user in session
-            if (session.insert(USER_SESSION_KEY, &user).await).is_err() {
-                let mut page_vars = HashMap::new();
-                page_vars.insert("title", json!("Login"));
-                page_vars.insert("error", json!("Session error. Please try again."));
-                page_vars.insert("username", json!(login_data.username));
-
-                let context = create_base_context(page_vars);
-                return Err(render_template("login.html", &context)
-                    .unwrap_or_else(|_| Html("Login error".to_string())));
+            let user_id = user.id;
+            if sign_in(&state, &session, &flash, user).await {
+                if state
+                    .auth
+                    .password_change_required(user_id)
+                    .await
+                    .unwrap_or(false)
+                {
+                    flash
+                        .info("Your password was reset. Please choose a new one.")
+                        .await;
+                    return Ok(Redirect::to("/profile"));
+                }
+                return Ok(Redirect::to("/"));
             }
-
-            Ok(Redirect::to("/"))
+            "Session error. Please try again."
         }
+        // Authentication failed
         Ok(None) => {
-            // Authentication failed
-            let mut page_vars = HashMap::new();
-            page_vars.insert("title", json!("Login"));
-            page_vars.insert("error", json!("Invalid username or password"));
-            page_vars.insert("username", json!(login_data.username));
-
-            let context = create_base_context(page_vars);
-            Err(render_template("login.html", &context)
-                .unwrap_or_else(|_| Html("Login error".to_string())))
-        }
-        Err(_) => {
-            // Database error
-            let mut page_vars = HashMap::new();
-            page_vars.insert("title", json!("Login"));
-            page_vars.insert("error", json!("System error. Please try again later."));
-            page_vars.insert("username", json!(login_data.username));
-
-            let context = create_base_context(page_vars);
-            Err(render_template("login.html", &context)
-                .unwrap_or_else(|_| Html("Login error".to_string())))
+            audit::record(
+                pool,
+                AuditEvent::new(audit::LOGIN_FAILED)
+                    .with_details(json!({ "username": login_data.username })),
+            )
+            .await;
+            "Invalid username or password"
         }
-    }
+        // Database error
+        Err(_) => "System error. Please try again later.",
+    };
+
+    flash.error(error).await;
+    Err(
+        render_login(&state, &headers, Some(&login_data.username), None)
+            .unwrap_or_else(|_| Html("Login error".to_string()).into_response()),
+    )
 }
 
 /// Logout handler
-pub async fn handle_logout(session: Session) -> Redirect {
+pub async fn handle_logout(
+    State(state): State<AppState>,
+    session: Session,
+    flash: Flash,
+) -> Redirect {
+    // Close the session's WebSockets and stop tracking it
+    if let Some(id) = session.id() {
+        let id = id.to_string();
+        state.connections.disconnect_session(&id);
+        if let Err(e) = state.auth.forget_session(&id).await {
+            output::error(format!("Failed to forget session: {}", e));
+        }
+    }
     // Remove user from session
-    let _ = session.remove::<AuthenticatedUser>(USER_SESSION_KEY).await;
+    if let Ok(Some(user)) = session.remove::<AuthenticatedUser>(USER_SESSION_KEY).await {
+        audit::record(&state.pool, AuditEvent::new(audit::LOGOUT).by(user.id)).await;
+        state
+            .plugins
+            .emit(&state, Event::UserLoggedOut { user_id: user.id });
+    }
     // Clear the entire session
     let _ = session.clear().await;
 
+    flash.info("You have been signed out.").await;
+    Redirect::to("/login")
+}
+
+/// Sign the current user out of every session, this one included, as after
+/// a suspected account compromise
+pub async fn handle_logout_all(
+    State(state): State<AppState>,
+    session: Session,
+    flash: Flash,
+) -> Redirect {
+    let Some(user) = get_current_user(&session).await else {
+        return Redirect::to("/login");
+    };
+    let sessions = match state.auth.end_sessions(user.id).await {
+        Ok(sessions) => sessions,
+        Err(e) => {
+            output::error(format!("Failed to end sessions of user {}: {}", user.id, e));
+            flash
+                .error("Could not sign you out everywhere. Please try again.")
+                .await;
+            return Redirect::to("/profile");
+        }
+    };
+    for session_id in &sessions {
+        state.connections.disconnect_session(session_id);
+    }
+    audit::record(
+        &state.pool,
+        AuditEvent::new(audit::LOGOUT_EVERYWHERE)
+            .by(user.id)
+            .with_details(json!({ "sessions_ended": sessions.len() })),
+    )
+    .await;
+    state
+        .plugins
+        .emit(&state, Event::UserLoggedOut { user_id: user.id });
+    // The store no longer has this session; drop what the request still
+    // holds so it is not saved back
+    let _ = session.flush().await;
+
+    flash
+        .info("You have been signed out on every device.")
+        .await;
     Redirect::to("/login")
 }
 
 /// Profile page handler
-pub async fn serve_profile(session: Session) -> Result<Html<String>, Redirect> {
+pub async fn serve_profile(scope: RequestScope) -> Result<Html<String>, Redirect> {
     // Check if user is authenticated
-    let user = match get_current_user(&session).await {
-        Some(user) => user,
-        None => return Err(Redirect::to("/login")),
+    let Some(user) = scope.user().await else {
+        return Err(Redirect::to("/login"));
     };
+    let pool = scope.pool();
+    let (locale, zone, stored) = scope
+        .measure("settings", async {
+            tokio::join!(
+                UserService::get_locale(pool, user.id),
+                UserService::get_timezone(pool, user.id),
+                UserService::get_user_by_id(pool, user.id)
+            )
+        })
+        .await;
+    let (locale, zone) = (locale.unwrap_or_default(), zone.unwrap_or_default());
+    let fields = stored
+        .ok()
+        .flatten()
+        .map(|stored| ProfileFields {
+            display_name: stored.display_name,
+            bio: stored.bio,
+            profile_data: stored.profile_data,
+        })
+        .unwrap_or_default();
 
     let mut page_vars = HashMap::new();
     page_vars.insert("title", json!("Profile"));
     page_vars.insert("user", json!(user));
-    page_vars.insert("success", json!(null));
-    page_vars.insert("error", json!(null));
+    page_vars.insert("locale", json!(i18n::resolve(locale.as_deref())));
+    page_vars.insert("locales", locale_options());
+    page_vars.insert("timezone", json!(zone));
+    page_vars.insert("timezones", json!(timezone::names().collect::<Vec<_>>()));
+    page_vars.insert(
+        "display_name",
+        json!(fields.display_name.unwrap_or_default()),
+    );
+    page_vars.insert("bio", json!(fields.bio.unwrap_or_default()));
+    page_vars.insert(
+        "profile_data",
+        json!(profile_data_text(&fields.profile_data)),
+    );
+    page_vars.insert(
+        "email_categories",
+        scope
+            .measure("categories", email_category_options(pool, user.id))
+            .await,
+    );
 
-    let context = create_base_context_with_user(page_vars, Some(&user));
+    let context = create_base_context_with_user(page_vars, Some(user));
 
     match render_template("profile.html", &context) {
         Ok(html) => Ok(html),
@@ -334,20 +1034,122 @@ pub async fn serve_profile(session: Session) -> Result<Html<String>, Redirect> {
     }
 }
 
-/// Profile update handler
+/// Longest accepted display name, the width of `users.display_name`
+pub const MAX_DISPLAY_NAME_LENGTH: usize = 100;
+
+/// Longest accepted bio, in characters
+pub const MAX_BIO_LENGTH: usize = 2000;
+
+/// Largest accepted profile data, in bytes of JSON
+pub const MAX_PROFILE_DATA_BYTES: usize = 16 * 1024;
+
+/// Profile fields from the text of the profile form; blank fields are
+/// cleared and blank profile data is an empty object
+pub(crate) fn parse_profile_fields(
+    display_name: &str,
+    bio: &str,
+    profile_data: &str,
+) -> AppResult<ProfileFields> {
+    let display_name = display_name.trim();
+    if display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Display names are at most {} characters long",
+            MAX_DISPLAY_NAME_LENGTH
+        )));
+    }
+    if display_name.chars().any(char::is_control) {
+        return Err(AppError::Validation(
+            "Display names cannot contain control characters".to_string(),
+        ));
+    }
+    let bio = bio.trim();
+    if bio.chars().count() > MAX_BIO_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Bios are at most {} characters long",
+            MAX_BIO_LENGTH
+        )));
+    }
+
+    let profile_data = profile_data.trim();
+    if profile_data.len() > MAX_PROFILE_DATA_BYTES {
+        return Err(AppError::Validation(format!(
+            "Profile data is at most {} KiB",
+            MAX_PROFILE_DATA_BYTES / 1024
+        )));
+    }
+    let profile_data = if profile_data.is_empty() {
+        json!({})
+    } else {
+        serde_json::from_str::<serde_json::Value>(profile_data)
+            .map_err(|e| AppError::Validation(format!("Profile data is not valid JSON: {}", e)))?
+    };
+    if !profile_data.is_object() {
+        return Err(AppError::Validation(
+            "Profile data must be a JSON object".to_string(),
+        ));
+    }
+
+    let non_empty = |text: &str| (!text.is_empty()).then(|| text.to_string());
+    Ok(ProfileFields {
+        display_name: non_empty(display_name),
+        bio: non_empty(bio),
+        profile_data,
+    })
+}
+
+/// Profile data as shown in the profile form; an empty object as nothing
+fn profile_data_text(profile_data: &serde_json::Value) -> String {
+    match profile_data.as_object() {
+        Some(object) if object.is_empty() => String::new(),
+        _ => serde_json::to_string_pretty(profile_data).unwrap_or_default(),
+    }
+}
+
+/// Languages offered on the profile page
+fn locale_options() -> serde_json::Value {
+    i18n::SUPPORTED_LOCALES
+        .iter()
+        .map(|(code, name)| json!({ "code": code, "name": name }))
+        .collect()
+}
+
+/// Email categories with the user's choices, for the profile page
+async fn email_category_options(pool: &PgPool, user_id: i32) -> serde_json::Value {
+    let choices = EmailPreferenceService::choices(pool, user_id)
+        .await
+        .unwrap_or_default();
+    EmailCategory::ALL
+        .iter()
+        .map(|category| {
+            let subscribed = !category.optional()
+                || choices
+                    .get(category.key())
+                    .copied()
+                    .unwrap_or(category.subscribed_by_default());
+            json!({
+                "key": category.key(),
+                "label": category.label(),
+                "optional": category.optional(),
+                "subscribed": subscribed,
+            })
+        })
+        .collect()
+}
+
+/// Profile update handler; the outcome is flashed on the profile page
 pub async fn handle_profile_update(
-    State(pool): State<PgPool>,
+    State(state): State<AppState>,
     session: Session,
+    flash: Flash,
     Form(form_data): Form<serde_json::Value>,
-) -> Result<Html<String>, Redirect> {
+) -> Redirect {
     // Check if user is authenticated
     let user = match get_current_user(&session).await {
         Some(user) => user,
-        None => return Err(Redirect::to("/login")),
+        None => return Redirect::to("/login"),
     };
 
-    let mut success_message = None;
-    let mut error_message = None;
+    let pool = &state.pool;
 
     // Handle profile update (email)
     if let (Some(email), Some(action)) = (
@@ -355,16 +1157,184 @@ pub async fn handle_profile_update(
         form_data.get("action").and_then(|v| v.as_str()),
     ) && action == "update_profile"
     {
-        match AuthService::update_user_profile(&pool, user.id, email).await {
+        let mut changed = Vec::new();
+        match state.auth.update_user_profile(user.id, email).await {
             Ok(true) => {
-                success_message = Some("Profile updated successfully!".to_string());
+                flash.success("Profile updated successfully!").await;
+                if email != user.email {
+                    changed.push("email");
+                }
                 // Update session with new email
                 let mut updated_user = user.clone();
                 updated_user.email = email.to_string();
                 let _ = session.insert(USER_SESSION_KEY, &updated_user).await;
             }
-            Ok(false) => error_message = Some("Failed to update profile".to_string()),
-            Err(_) => error_message = Some("Database error".to_string()),
+            Ok(false) => flash.error("Failed to update profile").await,
+            Err(_) => flash.error("Database error").await,
+        }
+
+        if let Some(locale) = form_data.get("locale").and_then(|v| v.as_str()) {
+            match i18n::normalize(locale) {
+                Some(locale) => {
+                    let previous = UserService::get_locale(pool, user.id)
+                        .await
+                        .unwrap_or_default();
+                    if UserService::set_locale(pool, user.id, Some(&locale))
+                        .await
+                        .is_err()
+                    {
+                        flash.error("Database error").await;
+                    } else if previous.as_deref() != Some(locale.as_str()) {
+                        changed.push("locale");
+                    }
+                }
+                None => flash.error("Unknown language").await,
+            }
+        }
+
+        // An empty choice means UTC
+        if let Some(name) = form_data.get("timezone").and_then(|v| v.as_str()) {
+            let name = name.trim();
+            let zone = if name.is_empty() {
+                Ok(None)
+            } else {
+                timezone::parse(name).map(Some).ok_or(())
+            };
+            match zone {
+                Ok(zone) => {
+                    let name = zone.map(|zone| zone.name());
+                    let previous = UserService::get_timezone(pool, user.id)
+                        .await
+                        .unwrap_or_default();
+                    if UserService::set_timezone(pool, user.id, name)
+                        .await
+                        .is_err()
+                        || timezone::remember(&session, name).await.is_err()
+                    {
+                        flash.error("Database error").await;
+                    } else if previous.as_deref() != name {
+                        changed.push("timezone");
+                    }
+                }
+                Err(()) => flash.error("Unknown time zone").await,
+            }
+        }
+
+        if let Some(username) = form_data
+            .get("username")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            && username != user.username
+        {
+            let outcome = match admin::validate_username(username) {
+                Err(e) => Err(e.public_message()),
+                Ok(()) => {
+                    match UserService::change_username(pool, user.id, username, Some(user.id)).await
+                    {
+                        Ok(UsernameChange::Changed(previous)) => {
+                            audit::record(
+                                pool,
+                                AuditEvent::new(audit::USERNAME_CHANGED)
+                                    .by(user.id)
+                                    .with_details(json!({ "from": previous, "to": username })),
+                            )
+                            .await;
+                            Ok(())
+                        }
+                        Ok(UsernameChange::Unchanged) => Ok(()),
+                        Ok(UsernameChange::Taken) => {
+                            Err(format!("The username '{}' is taken", username))
+                        }
+                        Ok(UsernameChange::NotFound) | Err(_) => {
+                            Err("Failed to change username".to_string())
+                        }
+                    }
+                }
+            };
+            match outcome {
+                // Update session with the new name, keeping the rest as stored
+                Ok(()) => {
+                    if let Ok(Some(mut session_user)) =
+                        session.get::<AuthenticatedUser>(USER_SESSION_KEY).await
+                    {
+                        session_user.username = username.to_string();
+                        let _ = session.insert(USER_SESSION_KEY, &session_user).await;
+                    }
+                }
+                Err(message) => flash.error(message).await,
+            }
+        }
+
+        if let (Some(display_name), Some(bio), Some(profile_data)) = (
+            form_data.get("display_name").and_then(|v| v.as_str()),
+            form_data.get("bio").and_then(|v| v.as_str()),
+            form_data.get("profile_data").and_then(|v| v.as_str()),
+        ) {
+            match parse_profile_fields(display_name, bio, profile_data) {
+                Ok(fields) => {
+                    let previous = UserService::get_user_by_id(pool, user.id)
+                        .await
+                        .ok()
+                        .flatten();
+                    if UserService::set_profile_fields(pool, user.id, &fields)
+                        .await
+                        .is_err()
+                    {
+                        flash.error("Database error").await;
+                    } else if let Some(previous) = previous {
+                        if previous.display_name != fields.display_name {
+                            changed.push("display_name");
+                        }
+                        if previous.bio != fields.bio {
+                            changed.push("bio");
+                        }
+                        if previous.profile_data != fields.profile_data {
+                            changed.push("profile_data");
+                        }
+                    }
+                }
+                Err(e) => flash.error(e.public_message()).await,
+            }
+        }
+
+        // Pages pick up the new language and time zone
+        if changed
+            .iter()
+            .any(|field| matches!(*field, "locale" | "timezone"))
+            && preferences::refresh(pool, &session, user.id).await.is_err()
+        {
+            flash.error("Database error").await;
+        }
+
+        if !changed.is_empty() {
+            audit::record(
+                pool,
+                AuditEvent::new(audit::PROFILE_UPDATED)
+                    .by(user.id)
+                    .with_details(json!({ "fields": changed })),
+            )
+            .await;
+        }
+    }
+
+    // Handle email subscriptions; unchecked boxes are left out of the form
+    if form_data.get("action").and_then(|v| v.as_str()) == Some("update_email_preferences") {
+        let mut saved = true;
+        for category in EmailCategory::ALL
+            .iter()
+            .filter(|category| category.optional())
+        {
+            let subscribed = form_data
+                .get(format!("subscribe_{}", category.key()))
+                .is_some();
+            saved &= EmailPreferenceService::set(pool, user.id, category.key(), subscribed)
+                .await
+                .is_ok();
+        }
+        if saved {
+            flash.success("Email preferences saved").await;
+        } else {
+            flash.error("Database error").await;
         }
     }
 
@@ -377,45 +1347,760 @@ pub async fn handle_profile_update(
     ) && action == "change_password"
     {
         if new_password != confirm_password {
-            error_message = Some("New passwords do not match".to_string());
-        } else if new_password.len() < 8 {
-            error_message = Some("Password must be at least 8 characters".to_string());
+            flash.error("New passwords do not match").await;
+        } else if let Err(e) = state.password_policy.check(new_password).await {
+            flash.error(e.public_message()).await;
         } else {
-            match AuthService::change_user_password(&pool, user.id, current_password, new_password)
+            match state
+                .auth
+                .change_user_password(user.id, current_password, new_password)
                 .await
             {
-                Ok(true) => success_message = Some("Password changed successfully!".to_string()),
-                Ok(false) => error_message = Some("Current password is incorrect".to_string()),
-                Err(_) => error_message = Some("Error changing password".to_string()),
+                Ok(true) => {
+                    if let Err(e) = state.auth.require_password_change(user.id, false).await {
+                        output::error(format!(
+                            "Failed to clear password reset of user {}: {}",
+                            user.id, e
+                        ));
+                    }
+                    if let Err(e) = renew_session(state.auth.as_ref(), &session, user.id).await {
+                        output::error(format!(
+                            "Failed to renew session of user {}: {}",
+                            user.id, e
+                        ));
+                    }
+                    flash.success("Password changed successfully!").await;
+                    audit::record(pool, AuditEvent::new(audit::PASSWORD_CHANGED).by(user.id)).await;
+
+                    let locale = UserService::get_locale(pool, user.id)
+                        .await
+                        .unwrap_or_default();
+                    let mut context = emails::base_context();
+                    context.insert("username", &user.username);
+                    context.insert(
+                        "profile_url",
+                        &format!("{}/profile", state.config.server.public_base_url()),
+                    );
+                    emails::send_in_background(
+                        &state,
+                        "password_changed",
+                        user.email.clone(),
+                        locale,
+                        context,
+                    )
+                    .await;
+                }
+                Ok(false) => flash.error("Current password is incorrect").await,
+                Err(_) => flash.error("Error changing password").await,
             }
         }
     }
 
+    Redirect::to("/profile")
+}
+
+// =============================================================================
+// Unsubscribe Page
+// =============================================================================
+
+/// Query of the unsubscribe links in emails
+#[derive(Debug, Deserialize)]
+pub struct UnsubscribeQuery {
+    token: String,
+    category: String,
+}
+
+impl UnsubscribeQuery {
+    /// The token and category, if both are well formed and the category can
+    /// be turned off
+    fn parse(&self) -> Option<(uuid::Uuid, EmailCategory)> {
+        let token = self.token.parse().ok()?;
+        let category = EmailCategory::from_key(&self.category).filter(|c| c.optional())?;
+        Some((token, category))
+    }
+}
+
+/// Confirmation page for an unsubscribe link; opening a link (or a mail
+/// scanner prefetching it) changes nothing
+pub async fn serve_unsubscribe(Query(query): Query<UnsubscribeQuery>) -> AppResult<Html<String>> {
+    let (_, category) = query
+        .parse()
+        .ok_or_else(|| AppError::NotFound("This unsubscribe link is not valid".to_string()))?;
+    render_unsubscribe(&query, category, false)
+}
+
+/// Unsubscribe without signing in. Handles both the confirmation form and
+/// RFC 8058 one-click requests, whose `List-Unsubscribe=One-Click` body
+/// needs no checking.
+pub async fn handle_unsubscribe(
+    State(pool): State<PgPool>,
+    Query(query): Query<UnsubscribeQuery>,
+) -> AppResult<Html<String>> {
+    let not_valid = || AppError::NotFound("This unsubscribe link is not valid".to_string());
+    let (token, category) = query.parse().ok_or_else(not_valid)?;
+    if !EmailPreferenceService::unsubscribe(&pool, token, category.key()).await? {
+        return Err(not_valid());
+    }
+    render_unsubscribe(&query, category, true)
+}
+
+fn render_unsubscribe(
+    query: &UnsubscribeQuery,
+    category: EmailCategory,
+    done: bool,
+) -> AppResult<Html<String>> {
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!("Unsubscribe"));
+    page_vars.insert("category", json!(category.label()));
+    page_vars.insert(
+        "action",
+        json!(format!(
+            "/unsubscribe?token={}&category={}",
+            query.token, query.category
+        )),
+    );
+    page_vars.insert("done", json!(done));
+
+    let context = create_base_context_with_user(page_vars, None);
+    render_template("unsubscribe.html", &context)
+}
+
+// =============================================================================
+// SQL Console Page
+// =============================================================================
+
+/// SQL console page (admins only)
+pub async fn serve_sql_console(
+    admin: Result<AdminUser, AppError>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+    render_sql_console(&user, "", None, None)
+}
+
+/// Run a query from the SQL console page, or download its result as CSV
+pub async fn handle_sql_console(
+    admin: Result<AdminUser, AppError>,
+    State(state): State<AppState>,
+    Form(form): Form<SqlConsoleForm>,
+) -> Result<Response, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+
+    match sql_console::execute(&state, user.id, &form.query).await {
+        Ok(result) if form.export.as_deref() == Some("csv") => {
+            Ok(sql_console::csv_response(&result))
+        }
+        Ok(result) => render_sql_console(&user, &form.query, Some(&result), None)
+            .map(IntoResponse::into_response),
+        Err(e) => render_sql_console(&user, &form.query, None, Some(e.public_message()))
+            .map(IntoResponse::into_response),
+    }
+}
+
+fn render_sql_console(
+    user: &AuthenticatedUser,
+    query: &str,
+    result: Option<&SqlQueryResult>,
+    error: Option<String>,
+) -> Result<Html<String>, Redirect> {
+    // Cells are pre-rendered as text; Tera cannot print nested JSON values
+    let result = result.map(|result| {
+        let rows: Vec<Vec<String>> = result
+            .rows
+            .iter()
+            .map(|row| row.iter().map(sql_console::cell_text).collect())
+            .collect();
+        json!({
+            "columns": result.columns,
+            "rows": rows,
+            "row_count": result.row_count,
+            "truncated": result.truncated,
+            "duration_ms": result.duration_ms,
+        })
+    });
+
     let mut page_vars = HashMap::new();
-    page_vars.insert("title", json!("Profile"));
-    page_vars.insert("user", json!(user));
-    page_vars.insert("success", json!(success_message));
-    page_vars.insert("error", json!(error_message));
+    page_vars.insert("title", json!("SQL Console"));
+    page_vars.insert("query", json!(query));
+    page_vars.insert("result", json!(result));
+    page_vars.insert("error", json!(error));
+
+    let context = create_base_context_with_user(page_vars, Some(user));
+
+    render_template("admin_sql.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+// =============================================================================
+// Email Template Pages
+// =============================================================================
+
+/// Query parameters for the email template editor
+#[derive(Debug, Deserialize)]
+pub struct EmailTemplateQuery {
+    /// Saved version to load into the editor instead of the newest
+    pub version: Option<i32>,
+}
+
+/// Recent outbox entries shown under the email templates
+const OUTBOX_PAGE_SIZE: u32 = 25;
+
+/// List of editable emails and the recent outbox (admins only)
+pub async fn serve_email_templates(
+    admin: Result<AdminUser, AppError>,
+    State(pool): State<PgPool>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+
+    let saved = EmailTemplateService::latest_versions(&pool)
+        .await
+        .map_err(|_| Redirect::to("/"))?;
+    let templates: Vec<serde_json::Value> = emails::EMAIL_TEMPLATES
+        .iter()
+        .map(|spec| {
+            let latest = saved.iter().find(|version| version.name == spec.name);
+            json!({
+                "name": spec.name,
+                "description": spec.description,
+                "version": latest.map(|version| version.version),
+                "author": latest.and_then(|version| version.author.clone()),
+                "updated_at": latest.map(|version| format_human_time(version.created_at)),
+            })
+        })
+        .collect();
+
+    let outbox = EmailDeliveryService::list_outbox(&pool, &Pagination::page(1, OUTBOX_PAGE_SIZE))
+        .await
+        .map_err(|_| Redirect::to("/"))?;
+    let outbox: Vec<serde_json::Value> = outbox
+        .items
+        .iter()
+        .map(|email| {
+            json!({
+                "template": email.template,
+                "recipient": email.recipient,
+                "subject": email.subject,
+                "status": email.status,
+                "error": email.error,
+                "undeliverable": email.undeliverable,
+                "created_at": format_human_time(email.created_at),
+            })
+        })
+        .collect();
+
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!("Email Templates"));
+    page_vars.insert("templates", json!(templates));
+    page_vars.insert("outbox", json!(outbox));
 
     let context = create_base_context_with_user(page_vars, Some(&user));
 
-    match render_template("profile.html", &context) {
-        Ok(html) => Ok(html),
-        Err(_) => Err(Redirect::to("/")),
+    render_template("admin_emails.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+/// Email template editor, showing the newest version or `?version=N`
+pub async fn serve_email_template(
+    admin: Result<AdminUser, AppError>,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Query(query): Query<EmailTemplateQuery>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+    let spec = emails::find(&name).ok_or_else(|| Redirect::to("/admin/emails"))?;
+
+    let saved = match query.version {
+        Some(version) => EmailTemplateService::get_version(&state.pool, spec.name, version).await,
+        None => EmailTemplateService::latest(&state.pool, spec.name).await,
     }
+    .map_err(|_| Redirect::to("/admin/emails"))?;
+    let source = match saved {
+        Some(saved) => emails::EmailSource {
+            subject: saved.subject,
+            body: saved.body,
+        },
+        None => spec
+            .bundled_source()
+            .map_err(|_| Redirect::to("/admin/emails"))?,
+    };
+
+    render_email_editor(&state, &user, spec, &source, None, None).await
 }
 
-/// 404 handler
-pub async fn handler_404(uri: Uri) -> (StatusCode, Json<ApiResponse>) {
-    (
-        StatusCode::NOT_FOUND,
-        Json(ApiResponse {
-            message: format!(
-                "The requested path '{}' was not found on this server",
-                uri.path()
-            ),
-            status: "error".to_string(),
-            timestamp: chrono::Utc::now().to_rfc3339(),
+/// Save the editor's draft as a new version, or send it as a test email
+pub async fn handle_email_template(
+    admin: Result<AdminUser, AppError>,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Form(form): Form<EmailTemplateForm>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+    let spec = emails::find(&name).ok_or_else(|| Redirect::to("/admin/emails"))?;
+    let source = emails::EmailSource {
+        subject: form.subject,
+        body: form.body,
+    };
+
+    let rendered = match emails::validate(&state.templates, spec, &source) {
+        Ok(rendered) => rendered,
+        Err(e) => return render_email_editor(&state, &user, spec, &source, None, Some(e)).await,
+    };
+
+    let (success, error) = if form.action.as_deref() == Some("test") {
+        match emails::deliver(&state, spec.name, &rendered.to(&user.email)).await {
+            Ok(()) => (Some(format!("Test email sent to {}", user.email)), None),
+            Err(e) => (None, Some(e.public_message())),
+        }
+    } else {
+        match EmailTemplateService::create_version(
+            &state.pool,
+            spec.name,
+            &source.subject,
+            &source.body,
+            user.id,
+        )
+        .await
+        {
+            Ok(version) => (Some(format!("Saved as version {}", version)), None),
+            Err(_) => (None, Some("Failed to save the template".to_string())),
+        }
+    };
+
+    render_email_editor(&state, &user, spec, &source, success, error).await
+}
+
+/// Restore an earlier version (or the bundled template) as a new version
+pub async fn handle_email_template_revert(
+    admin: Result<AdminUser, AppError>,
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    Form(form): Form<EmailTemplateRevertForm>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+    let spec = emails::find(&name).ok_or_else(|| Redirect::to("/admin/emails"))?;
+
+    let source = if form.version == 0 {
+        spec.bundled_source().ok()
+    } else {
+        EmailTemplateService::get_version(&state.pool, spec.name, form.version)
+            .await
+            .ok()
+            .flatten()
+            .map(|saved| emails::EmailSource {
+                subject: saved.subject,
+                body: saved.body,
+            })
+    }
+    .ok_or_else(|| Redirect::to(&format!("/admin/emails/{}", spec.name)))?;
+
+    let (success, error) = match EmailTemplateService::create_version(
+        &state.pool,
+        spec.name,
+        &source.subject,
+        &source.body,
+        user.id,
+    )
+    .await
+    {
+        Ok(version) if form.version == 0 => (
+            Some(format!(
+                "Restored the bundled template as version {}",
+                version
+            )),
+            None,
+        ),
+        Ok(version) => (
+            Some(format!(
+                "Restored version {} as version {}",
+                form.version, version
+            )),
+            None,
+        ),
+        Err(_) => (None, Some("Failed to restore the template".to_string())),
+    };
+
+    render_email_editor(&state, &user, spec, &source, success, error).await
+}
+
+async fn render_email_editor(
+    state: &AppState,
+    user: &AuthenticatedUser,
+    spec: &emails::EmailTemplateSpec,
+    source: &emails::EmailSource,
+    success: Option<String>,
+    error: Option<String>,
+) -> Result<Html<String>, Redirect> {
+    let history = EmailTemplateService::history(&state.pool, spec.name)
+        .await
+        .map_err(|_| Redirect::to("/admin/emails"))?;
+    let history: Vec<serde_json::Value> = history
+        .iter()
+        .map(|version| {
+            json!({
+                "version": version.version,
+                "author": version.author,
+                "created_at": format_human_time(version.created_at),
+            })
+        })
+        .collect();
+
+    // Preview the draft with the sample values; shown in a sandboxed iframe
+    let preview = emails::render_source(&state.templates, source, &spec.sample_context(None))
+        .ok()
+        .map(|rendered| rendered.html);
+    let variables: Vec<serde_json::Value> = spec
+        .variables
+        .iter()
+        .map(|(name, sample)| json!({ "name": name, "sample": sample }))
+        .collect();
+    // Default-locale text of each message, placeholders left in
+    let messages: Vec<serde_json::Value> = i18n::messages(None, &spec.message_prefix(), &json!({}))
+        .into_iter()
+        .map(|(key, text)| json!({ "key": key, "text": text }))
+        .collect();
+
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!(format!("Email: {}", spec.name)));
+    page_vars.insert(
+        "template",
+        json!({
+            "name": spec.name,
+            "description": spec.description,
+            "variables": variables,
+            "messages": messages,
         }),
+    );
+    page_vars.insert("source", json!(source));
+    page_vars.insert("history", json!(history));
+    page_vars.insert("preview", json!(preview));
+    page_vars.insert("success", json!(success));
+    page_vars.insert("error", json!(error));
+
+    let context = create_base_context_with_user(page_vars, Some(user));
+
+    render_template("admin_email_edit.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+// =============================================================================
+// Background Jobs Page
+// =============================================================================
+
+/// Job queue sizes and worker liveness (admins only)
+pub async fn serve_jobs_dashboard(
+    admin: Result<AdminUser, AppError>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+
+    let overview = jobs::overview(&state)
+        .await
+        .map_err(|_| Redirect::to("/"))?;
+    let workers: Vec<serde_json::Value> = overview
+        .workers
+        .iter()
+        .map(|worker| {
+            json!({
+                "id": worker.id,
+                "hostname": worker.hostname,
+                "running_jobs": worker.running_jobs,
+                "alive": worker.alive,
+                "started_at": format_human_time(worker.started_at),
+                "heartbeat_at": format_human_time(worker.heartbeat_at),
+            })
+        })
+        .collect();
+
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!("Background Jobs"));
+    page_vars.insert(
+        "counts",
+        json!({
+            "queued": overview.queued,
+            "running": overview.running,
+            "failed": overview.failed,
+            "done": overview.done,
+        }),
+    );
+    page_vars.insert("liveness", json!(overview.liveness));
+    page_vars.insert("workers", json!(workers));
+    page_vars.insert(
+        "stall_timeout_secs",
+        json!(state.config.jobs.stall_timeout_secs),
+    );
+
+    let context = create_base_context_with_user(page_vars, Some(&user));
+
+    render_template("admin_jobs.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+// =============================================================================
+// Audit Log Page
+// =============================================================================
+
+/// The signed-in user's account activity, newest first
+pub async fn serve_activity(
+    scope: RequestScope,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Html<String>, Redirect> {
+    let Some(user) = scope.user().await else {
+        return Err(Redirect::to("/login"));
+    };
+
+    let page_number = query
+        .get("page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1);
+    let page = activity::list(
+        scope.pool(),
+        user.id,
+        &Pagination::page(page_number, preferences::items_per_page()),
+    )
+    .await
+    .map_err(|_| Redirect::to("/"))?;
+    let entries: Vec<serde_json::Value> = page
+        .items
+        .iter()
+        .map(|entry| {
+            json!({
+                "summary": entry.summary,
+                "details": activity::describe(&entry.details),
+                "created_at": format_human_time(entry.created_at),
+            })
+        })
+        .collect();
+
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!("Activity"));
+    page_vars.insert("entries", json!(entries));
+    page_vars.insert("page", json!(page.page));
+    page_vars.insert("total_pages", json!(page.total_pages));
+
+    let context = create_base_context_with_user(page_vars, Some(user));
+
+    render_template("profile_activity.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+/// Notifications shown per page
+const NOTIFICATION_PAGE_SIZE: u32 = 20;
+
+/// The signed-in user's notifications, newest first, read ones included
+pub async fn serve_notifications(
+    scope: RequestScope,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Html<String>, Redirect> {
+    let Some(user) = scope.user().await else {
+        return Err(Redirect::to("/login"));
+    };
+
+    let page_number = query
+        .get("page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1);
+    let page = NotificationService::list(
+        scope.pool(),
+        user.id,
+        false,
+        &Pagination::page(page_number, NOTIFICATION_PAGE_SIZE),
+    )
+    .await
+    .map_err(|_| Redirect::to("/"))?;
+    let notifications: Vec<serde_json::Value> = page
+        .items
+        .iter()
+        .map(|notification| {
+            json!({
+                "id": notification.id,
+                "kind": notification.kind,
+                "title": notification.title,
+                "body": notification.body,
+                "link": notification.link,
+                "unread": notification.read_at.is_none(),
+                "created_at": format_human_time(notification.created_at),
+            })
+        })
+        .collect();
+
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!("Notifications"));
+    page_vars.insert("notifications", json!(notifications));
+    page_vars.insert("page", json!(page.page));
+    page_vars.insert("total_pages", json!(page.total_pages));
+
+    let context = create_base_context_with_user(page_vars, Some(user));
+
+    render_template("notifications.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+/// Mark one of the signed-in user's notifications read
+pub async fn handle_notification_read(scope: RequestScope, Path(id): Path<i64>) -> Redirect {
+    let Some(user) = scope.user().await else {
+        return Redirect::to("/login");
+    };
+    if let Err(e) = NotificationService::mark_read(scope.pool(), user.id, id).await {
+        output::error(format!("Failed to mark notification {} read: {}", id, e));
+    }
+    Redirect::to("/notifications")
+}
+
+/// Mark all of the signed-in user's notifications read
+pub async fn handle_notifications_read_all(scope: RequestScope, flash: Flash) -> Redirect {
+    let Some(user) = scope.user().await else {
+        return Redirect::to("/login");
+    };
+    match NotificationService::mark_all_read(scope.pool(), user.id).await {
+        Ok(_) => flash.info("All notifications marked read.").await,
+        Err(e) => {
+            output::error(format!(
+                "Failed to mark notifications of user {} read: {}",
+                user.id, e
+            ));
+            flash
+                .error("Could not mark your notifications read. Please try again.")
+                .await;
+        }
+    }
+    Redirect::to("/notifications")
+}
+
+/// Audit entries shown per page
+const AUDIT_PAGE_SIZE: u32 = 50;
+
+/// Audit log, newest first, optionally narrowed to one `?action=` (admins only)
+pub async fn serve_audit_log(
+    admin: Result<AdminUser, AppError>,
+    State(pool): State<PgPool>,
+    Query(query): Query<HashMap<String, String>>,
+) -> Result<Html<String>, Redirect> {
+    let AdminUser(user) = admin.map_err(|_| Redirect::to("/login"))?;
+
+    let page_number = query
+        .get("page")
+        .and_then(|page| page.parse().ok())
+        .unwrap_or(1);
+    let filter = AuditFilter {
+        action: query
+            .get("action")
+            .filter(|action| !action.is_empty())
+            .cloned(),
+        ..AuditFilter::default()
+    };
+    let page = AuditService::list(
+        &pool,
+        &filter,
+        &Pagination::page(page_number, AUDIT_PAGE_SIZE),
     )
+    .await
+    .map_err(|_| Redirect::to("/"))?;
+    let entries: Vec<serde_json::Value> = page
+        .items
+        .iter()
+        .map(|entry| {
+            json!({
+                "action": entry.action,
+                "actor": entry.actor_username,
+                "actor_id": entry.actor_id,
+                "target_type": entry.target_type,
+                "target_id": entry.target_id,
+                "details": entry.details.to_string(),
+                "created_at": format_human_time(entry.created_at),
+            })
+        })
+        .collect();
+
+    let mut page_vars = HashMap::new();
+    page_vars.insert("title", json!("Audit Log"));
+    page_vars.insert("entries", json!(entries));
+    page_vars.insert("action", json!(filter.action));
+    page_vars.insert("actions", json!(audit::ACTIONS));
+    page_vars.insert("page", json!(page.page));
+    page_vars.insert("total_pages", json!(page.total_pages));
+
+    let context = create_base_context_with_user(page_vars, Some(&user));
+
+    render_template("admin_audit.html", &context).map_err(|_| Redirect::to("/"))
+}
+
+/// 404 handler
+pub async fn handler_404(uri: Uri) -> AppError {
+    AppError::NotFound(format!(
+        "The requested path '{}' was not found on this server",
+        uri.path()
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_profile_fields() {
+        let fields = parse_profile_fields("  Ada L. ", "", "").unwrap();
+        assert_eq!(fields.display_name.as_deref(), Some("Ada L."));
+        assert_eq!(fields.bio, None);
+        assert_eq!(fields.profile_data, json!({}));
+
+        let fields = parse_profile_fields("", "Writes code", r#"{"site": "ada.dev"}"#).unwrap();
+        assert_eq!(fields.display_name, None);
+        assert_eq!(fields.profile_data, json!({ "site": "ada.dev" }));
+        assert_eq!(profile_data_text(&json!({})), "");
+
+        assert!(parse_profile_fields(&"x".repeat(MAX_DISPLAY_NAME_LENGTH + 1), "", "").is_err());
+        assert!(parse_profile_fields("Ada\u{7}", "", "").is_err());
+        assert!(parse_profile_fields("", &"x".repeat(MAX_BIO_LENGTH + 1), "").is_err());
+        assert!(parse_profile_fields("", "", "[1, 2]").is_err());
+        assert!(parse_profile_fields("", "", "{not json").is_err());
+        let large = format!(r#"{{"a": "{}"}}"#, "x".repeat(MAX_PROFILE_DATA_BYTES));
+        assert!(parse_profile_fields("", "", &large).is_err());
+    }
+
+    #[test]
+    fn test_add_template_dir() {
+        let dir = std::env::temp_dir().join(format!("axum-base-plugin-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(dir.join("billing")).unwrap();
+        std::fs::write(dir.join("base.html"), "plugin base").unwrap();
+        std::fs::write(
+            dir.join("billing/invoice.html"),
+            "{% extends \"base.html\" %}{% block content %}invoice{% endblock content %}",
+        )
+        .unwrap();
+
+        let mut tera = Tera::default();
+        tera.add_raw_template(
+            "base.html",
+            "core {% block content %}{% endblock content %}",
+        )
+        .unwrap();
+        add_template_dir(&mut tera, &dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Plugin templates extend core ones, which win on a name clash
+        let page = tera
+            .render("billing/invoice.html", &Context::new())
+            .unwrap();
+        assert_eq!(page, "core invoice");
+    }
+
+    #[test]
+    fn test_form_macros_describe_fields() {
+        let mut tera = load_templates().unwrap();
+        tera.add_raw_template(
+            "form.html",
+            r#"{% import "macros/forms.html" as forms %}{% import "macros/components.html" as components %}
+            {{ forms::input(name="email", label="Email", type="email", required=true, help="We never share it", error="Enter an email address") }}
+            {{ forms::select(name="locale", label="Language", options=locales, selected="de", value_key="code", label_key="name") }}
+            {{ components::alert(message="Saved", level="success") }}
+            {{ components::pagination(page=2, total_pages=3, path="/admin/audit", query="action=login") }}"#,
+        )
+        .unwrap();
+        let mut context = Context::new();
+        context.insert("locales", &locale_options());
+        let html = tera.render("form.html", &context).unwrap();
+
+        assert!(html.contains(r#"<label for="email""#));
+        assert!(html.contains(r#"aria-describedby="email-error email-help""#));
+        assert!(html.contains(r#"aria-invalid="true""#));
+        assert!(html.contains(r#"<p id="email-error""#));
+        assert!(html.contains(r#"<option value="de" selected>"#));
+        assert!(html.contains(r#"role="status">Saved</div>"#));
+        assert!(html.contains(r#"aria-label="Pagination""#));
+        assert!(html.contains(r#"?page=1&amp;action=login" rel="prev""#));
+        assert!(html.contains(r#"?page=3&amp;action=login" rel="next""#));
+    }
 }
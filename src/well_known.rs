@@ -0,0 +1,137 @@
+//! # Well-Known Endpoints
+//!
+//! Handlers under `/.well-known/`: `security.txt` (RFC 9116) built from
+//! configuration, the `change-password` redirect used by password managers,
+//! and opt-in WebFinger (RFC 7033) account discovery.
+
+use axum::{
+    Json, Router,
+    extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+};
+use chrono::{Duration, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::error::{AppError, AppResult};
+use crate::services::UserService;
+use crate::state::AppState;
+
+/// Routes for `/.well-known/*`, honoring the well-known configuration
+pub fn routes(config: &AppConfig) -> Router<AppState> {
+    let router = Router::new()
+        .route("/.well-known/security.txt", get(security_txt))
+        .route("/.well-known/change-password", get(change_password));
+
+    if config.well_known.webfinger {
+        router.route("/.well-known/webfinger", get(webfinger))
+    } else {
+        router
+    }
+}
+
+/// Serve `security.txt`, or 404 when no security contact is configured
+pub async fn security_txt(State(config): State<Arc<AppConfig>>) -> AppResult<Response> {
+    let well_known = &config.well_known;
+    let contact = well_known
+        .security_contact
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("security.txt is not configured".to_string()))?;
+
+    let expires = well_known.security_expires.clone().unwrap_or_else(|| {
+        (Utc::now() + Duration::days(365)).to_rfc3339_opts(SecondsFormat::Secs, true)
+    });
+
+    let mut body = format!("Contact: {}\nExpires: {}\n", contact, expires);
+    if let Some(policy) = &well_known.security_policy {
+        body.push_str(&format!("Policy: {}\n", policy));
+    }
+    body.push_str(&format!(
+        "Canonical: {}/.well-known/security.txt\n",
+        config.server.public_base_url()
+    ));
+
+    Ok(([(header::CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+/// Send password managers to the password section of the profile page
+pub async fn change_password() -> Redirect {
+    Redirect::to("/profile#change-password")
+}
+
+/// WebFinger query string (`?resource=acct:user@host`)
+#[derive(Debug, Deserialize)]
+pub struct WebFingerQuery {
+    pub resource: String,
+}
+
+/// JSON Resource Descriptor returned by WebFinger
+#[derive(Debug, Serialize)]
+pub struct WebFingerResponse {
+    pub subject: String,
+    pub links: Vec<WebFingerLink>,
+}
+
+/// A single JRD link relation
+#[derive(Debug, Serialize)]
+#[cfg_attr(not(feature = "activitypub"), allow(dead_code))]
+pub struct WebFingerLink {
+    pub rel: String,
+    #[serde(rename = "type")]
+    pub media_type: String,
+    pub href: String,
+}
+
+/// Resolve `acct:username@host` to a JRD for active local users
+pub async fn webfinger(
+    State(pool): State<PgPool>,
+    State(config): State<Arc<AppConfig>>,
+    Query(query): Query<WebFingerQuery>,
+) -> AppResult<Response> {
+    let account = query
+        .resource
+        .strip_prefix("acct:")
+        .ok_or_else(|| AppError::Validation("resource must be an acct: URI".to_string()))?;
+    let (username, domain) = account.rsplit_once('@').ok_or_else(|| {
+        AppError::Validation("resource must look like acct:user@host".to_string())
+    })?;
+
+    if !domain.eq_ignore_ascii_case(&config.server.public_host()) {
+        return Err(AppError::NotFound("Unknown account".to_string()));
+    }
+
+    let user = UserService::get_user_by_username(&pool, username)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Unknown account".to_string()))?;
+
+    let descriptor = WebFingerResponse {
+        subject: format!("acct:{}@{}", user.username, domain),
+        links: account_links(&config, &user.username),
+    };
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/jrd+json")],
+        Json(descriptor),
+    )
+        .into_response())
+}
+
+/// Links for an account: the ActivityPub actor when federation is compiled in
+#[cfg(feature = "activitypub")]
+fn account_links(config: &AppConfig, username: &str) -> Vec<WebFingerLink> {
+    vec![WebFingerLink {
+        rel: "self".to_string(),
+        media_type: crate::activitypub::ACTIVITY_JSON.to_string(),
+        href: crate::activitypub::actor_url(config, username),
+    }]
+}
+
+/// Links for an account: none without federation
+#[cfg(not(feature = "activitypub"))]
+fn account_links(_config: &AppConfig, _username: &str) -> Vec<WebFingerLink> {
+    Vec::new()
+}
@@ -0,0 +1,467 @@
+//! # WebSockets
+//!
+//! Real-time push to signed-in browsers. `GET /ws` upgrades a request from a
+//! signed-in session, and the socket is registered in [`Connections`] under
+//! its user and session. The session cookie goes along with cross-site
+//! handshakes too, so an `Origin` other than the public base URL or a
+//! tenant's host is refused with a 403. Handlers push JSON to all of a user's tabs with
+//! [`Connections::send_to_user`], or to everyone with
+//! [`Connections::broadcast`], through `AppState::connections`.
+//!
+//! Messages flow from server to browser; text from clients is ignored. The
+//! server pings every [`PING_INTERVAL`] and closes sockets silent for longer
+//! than [`IDLE_TIMEOUT`], so connections dropped by proxies or sleeping
+//! laptops leave the registry. A client more than [`SEND_BUFFER`] messages
+//! behind is disconnected rather than buffered without bound.
+//!
+//! The registry is per process: with several instances, each one reaches only
+//! the sockets connected to it.
+
+use axum::{
+    Router,
+    extract::{
+        State, WebSocketUpgrade,
+        ws::{Message, WebSocket, rejection::WebSocketUpgradeRejection},
+    },
+    http::{HeaderMap, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tower_sessions::Session;
+
+use crate::auth::USER_SESSION_KEY;
+use crate::config::AppConfig;
+use crate::error::AppError;
+use crate::models::AuthenticatedUser;
+use crate::services::TenantService;
+use crate::state::AppState;
+use crate::theming::{TenantHost, tenant_host};
+
+/// How often the server pings each socket
+pub const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a socket may go without any frame from the client, pongs
+/// included, before it is closed
+pub const IDLE_TIMEOUT: Duration = Duration::from_secs(75);
+
+/// Messages queued for a socket before it counts as too slow
+pub const SEND_BUFFER: usize = 64;
+
+/// WebSocket routes
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/ws", get(upgrade))
+}
+
+// ============================================================================
+// Connection Registry
+// ============================================================================
+
+/// One open socket
+#[derive(Debug)]
+struct Connection {
+    id: u64,
+    /// Session the socket was opened from
+    session_id: Option<String>,
+    sender: mpsc::Sender<String>,
+}
+
+/// Open sockets, by user
+#[derive(Debug, Default)]
+pub struct Connections {
+    next_id: AtomicU64,
+    by_user: Mutex<HashMap<i32, Vec<Connection>>>,
+}
+
+#[allow(dead_code)]
+impl Connections {
+    /// Create an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send a JSON message to every socket of a user; returns how many
+    /// sockets it was queued on
+    pub fn send_to_user<T: Serialize>(
+        &self,
+        user_id: i32,
+        message: &T,
+    ) -> Result<usize, serde_json::Error> {
+        let text = serde_json::to_string(message)?;
+        let mut by_user = self.lock();
+        let Some(connections) = by_user.get_mut(&user_id) else {
+            return Ok(0);
+        };
+        let sent = queue(connections, &text);
+        if connections.is_empty() {
+            by_user.remove(&user_id);
+        }
+        Ok(sent)
+    }
+
+    /// Send a JSON message to every open socket; returns how many sockets it
+    /// was queued on
+    pub fn broadcast<T: Serialize>(&self, message: &T) -> Result<usize, serde_json::Error> {
+        let text = serde_json::to_string(message)?;
+        let mut sent = 0;
+        self.lock().retain(|_, connections| {
+            sent += queue(connections, &text);
+            !connections.is_empty()
+        });
+        Ok(sent)
+    }
+
+    /// Close the sockets opened from a session, e.g. when it signs out;
+    /// returns how many were closed
+    pub fn disconnect_session(&self, session_id: &str) -> usize {
+        let mut closed = 0;
+        self.lock().retain(|_, connections| {
+            let before = connections.len();
+            connections.retain(|connection| connection.session_id.as_deref() != Some(session_id));
+            closed += before - connections.len();
+            !connections.is_empty()
+        });
+        closed
+    }
+
+    /// Number of users with at least one open socket
+    pub fn user_count(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Number of open sockets
+    pub fn connection_count(&self) -> usize {
+        self.lock().values().map(Vec::len).sum()
+    }
+
+    /// Add a socket; returns its id and the receiver of its messages, which
+    /// ends once the socket is dropped from the registry
+    fn register(&self, user_id: i32, session_id: Option<String>) -> (u64, mpsc::Receiver<String>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(SEND_BUFFER);
+        self.lock().entry(user_id).or_default().push(Connection {
+            id,
+            session_id,
+            sender,
+        });
+        (id, receiver)
+    }
+
+    /// Remove a closed socket
+    fn unregister(&self, user_id: i32, id: u64) {
+        let mut by_user = self.lock();
+        if let Some(connections) = by_user.get_mut(&user_id) {
+            connections.retain(|connection| connection.id != id);
+            if connections.is_empty() {
+                by_user.remove(&user_id);
+            }
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<i32, Vec<Connection>>> {
+        self.by_user
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Queue `text` on each connection, dropping the ones that are closed or too
+/// far behind; returns how many it was queued on
+fn queue(connections: &mut Vec<Connection>, text: &str) -> usize {
+    connections.retain(|connection| connection.sender.try_send(text.to_string()).is_ok());
+    connections.len()
+}
+
+// ============================================================================
+// Handlers
+// ============================================================================
+
+/// Upgrade a signed-in session's request to a WebSocket
+pub async fn upgrade(
+    State(connections): State<Arc<Connections>>,
+    State(config): State<Arc<AppConfig>>,
+    State(pool): State<PgPool>,
+    headers: HeaderMap,
+    session: Session,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Result<Response, AppError> {
+    if let Some(origin) = headers.get(header::ORIGIN) {
+        let origin = origin.to_str().unwrap_or_default();
+        if !is_allowed_origin(&pool, &config, origin).await? {
+            return Err(AppError::Forbidden(
+                "WebSocket origin not allowed".to_string(),
+            ));
+        }
+    }
+    let user = session
+        .get::<AuthenticatedUser>(USER_SESSION_KEY)
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| AppError::Unauthorized("Authentication required".to_string()))?;
+    let ws = match ws {
+        Ok(ws) => ws,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+
+    let session_id = session.id().map(|id| id.to_string());
+    Ok(ws.on_upgrade(move |socket| serve(socket, connections, user.id, session_id)))
+}
+
+/// Whether a handshake's `Origin` is the public base URL or a tenant's host
+/// under the same scheme: a subdomain of an existing tenant, or a verified
+/// custom domain. Browsers always send `Origin` on handshakes, so a request
+/// without one cannot come from another site.
+async fn is_allowed_origin(
+    pool: &PgPool,
+    config: &AppConfig,
+    origin: &str,
+) -> Result<bool, sqlx::Error> {
+    let public_url = config.server.public_base_url().to_ascii_lowercase();
+    let public_host = config.server.public_host().to_ascii_lowercase();
+    let scheme = public_url
+        .split_once("://")
+        .map_or("http", |(scheme, _)| scheme);
+    let Some(host) = origin
+        .to_ascii_lowercase()
+        .strip_prefix(&format!("{}://", scheme))
+        .map(|host| host.trim_end_matches('/').to_string())
+    else {
+        return Ok(false);
+    };
+    if host == public_host {
+        return Ok(true);
+    }
+
+    let tenant = match tenant_host(&host, &public_host) {
+        Some(TenantHost::Subdomain(slug)) => TenantService::get_tenant_by_slug(pool, &slug).await?,
+        Some(TenantHost::CustomDomain(domain)) => {
+            TenantService::get_tenant_by_domain(pool, &domain).await?
+        }
+        None => None,
+    };
+    Ok(tenant.is_some())
+}
+
+/// Forward queued messages to the socket and keep it alive until either side
+/// closes it
+async fn serve(
+    mut socket: WebSocket,
+    connections: Arc<Connections>,
+    user_id: i32,
+    session_id: Option<String>,
+) {
+    let (id, mut outgoing) = connections.register(user_id, session_id);
+    let mut ping = tokio::time::interval_at(Instant::now() + PING_INTERVAL, PING_INTERVAL);
+    let mut last_seen = Instant::now();
+
+    loop {
+        tokio::select! {
+            message = outgoing.recv() => match message {
+                Some(text) => {
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        break;
+                    }
+                }
+                // Dropped from the registry: too slow, or the session signed out
+                None => {
+                    let _ = socket.send(Message::Close(None)).await;
+                    break;
+                }
+            },
+            incoming = socket.recv() => match incoming {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                // Any frame shows the client is there; pings are answered by
+                // the protocol layer
+                Some(Ok(_)) => last_seen = Instant::now(),
+            },
+            _ = ping.tick() => {
+                if last_seen.elapsed() > IDLE_TIMEOUT
+                    || socket.send(Message::Ping(Default::default())).await.is_err()
+                {
+                    break;
+                }
+            }
+        }
+    }
+
+    connections.unregister(user_id, id);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::FromRef;
+    use futures_util::StreamExt;
+    use serde_json::json;
+    use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest};
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    #[test]
+    fn test_send_to_user_and_broadcast() {
+        let connections = Connections::new();
+        let (_, mut first) = connections.register(1, Some("a".to_string()));
+        let (_, mut second) = connections.register(1, Some("b".to_string()));
+        let (_, mut other) = connections.register(2, None);
+        assert_eq!(connections.user_count(), 2);
+        assert_eq!(connections.connection_count(), 3);
+
+        assert_eq!(connections.send_to_user(1, &json!({"n": 1})).unwrap(), 2);
+        assert_eq!(connections.send_to_user(3, &json!({"n": 1})).unwrap(), 0);
+        assert_eq!(first.try_recv().unwrap(), r#"{"n":1}"#);
+        assert_eq!(second.try_recv().unwrap(), r#"{"n":1}"#);
+        assert!(other.try_recv().is_err());
+
+        assert_eq!(connections.broadcast(&"hello").unwrap(), 3);
+        assert_eq!(other.try_recv().unwrap(), r#""hello""#);
+    }
+
+    #[test]
+    fn test_slow_and_signed_out_sockets_are_dropped() {
+        let connections = Connections::new();
+        let (_, mut fast) = connections.register(1, Some("a".to_string()));
+        let (_, _slow) = connections.register(1, Some("b".to_string()));
+
+        for n in 0..SEND_BUFFER {
+            connections.send_to_user(1, &n).unwrap();
+            fast.try_recv().unwrap();
+        }
+        // The unread socket's buffer is full
+        assert_eq!(connections.send_to_user(1, &"more").unwrap(), 1);
+        assert_eq!(connections.connection_count(), 1);
+
+        assert_eq!(connections.disconnect_session("a"), 1);
+        assert_eq!(connections.user_count(), 0);
+        fast.try_recv().unwrap();
+        assert!(fast.try_recv().is_err(), "The receiver should end");
+    }
+
+    /// What the handler extracts, without a database behind the pool
+    #[derive(Clone)]
+    struct TestState {
+        connections: Arc<Connections>,
+        config: Arc<AppConfig>,
+        pool: PgPool,
+    }
+
+    impl FromRef<TestState> for Arc<Connections> {
+        fn from_ref(state: &TestState) -> Self {
+            state.connections.clone()
+        }
+    }
+
+    impl FromRef<TestState> for Arc<AppConfig> {
+        fn from_ref(state: &TestState) -> Self {
+            state.config.clone()
+        }
+    }
+
+    impl FromRef<TestState> for PgPool {
+        fn from_ref(state: &TestState) -> Self {
+            state.pool.clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_push_to_a_signed_in_socket() {
+        let connections = Arc::new(Connections::new());
+        let mut config = AppConfig::default();
+        config.server.public_url = Some("http://app.example.com".to_string());
+        let state = TestState {
+            connections: connections.clone(),
+            config: Arc::new(config),
+            pool: PgPool::connect_lazy("postgres://localhost/unused").unwrap(),
+        };
+        let app = Router::new()
+            .route("/ws", get(upgrade))
+            .route(
+                "/login",
+                get(|session: Session| async move {
+                    let user = AuthenticatedUser {
+                        id: 7,
+                        username: "jane".to_string(),
+                        email: "jane@example.com".to_string(),
+                        is_active: true,
+                        avatar_url: None,
+                    };
+                    session.insert(USER_SESSION_KEY, user).await.unwrap();
+                }),
+            )
+            .layer(SessionManagerLayer::new(MemoryStore::default()).with_secure(false))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let url = format!("ws://{}/ws", addr);
+        match tokio_tungstenite::connect_async(url.as_str()).await {
+            Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 401),
+            other => panic!(
+                "Signed-out upgrade should be refused: {:?}",
+                other.map(|_| ())
+            ),
+        }
+
+        let login = reqwest::get(format!("http://{}/login", addr))
+            .await
+            .unwrap();
+        let cookie = login.headers()["set-cookie"]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+        let handshake = |origin: &str| {
+            let mut request = url.as_str().into_client_request().unwrap();
+            request
+                .headers_mut()
+                .insert("cookie", cookie.parse().unwrap());
+            request
+                .headers_mut()
+                .insert("origin", origin.parse().unwrap());
+            request
+        };
+
+        // The session cookie alone is not enough from another origin
+        for origin in ["http://localhost:8080", "https://app.example.com"] {
+            match tokio_tungstenite::connect_async(handshake(origin)).await {
+                Err(tungstenite::Error::Http(response)) => assert_eq!(response.status(), 403),
+                other => panic!(
+                    "Upgrade from {} should be refused: {:?}",
+                    origin,
+                    other.map(|_| ())
+                ),
+            }
+        }
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(handshake("http://app.example.com"))
+            .await
+            .unwrap();
+
+        // Registration happens once the upgrade completes on the server
+        while connections.connection_count() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(
+            connections
+                .send_to_user(7, &json!({"event": "ping"}))
+                .unwrap(),
+            1
+        );
+        let message = socket.next().await.unwrap().unwrap();
+        assert_eq!(message.into_text().unwrap(), r#"{"event":"ping"}"#);
+
+        drop(socket);
+        while connections.connection_count() > 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+}
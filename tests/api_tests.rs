@@ -1,18 +1,17 @@
 mod common;
 
 use axum::http::StatusCode;
-use serial_test::serial;
 use axum_test::TestServer;
 use chrono;
 use common::{TestDatabase, assert_json_response_structure, setup_test_env};
+use serial_test::serial;
 
 /// Test that the health endpoint returns expected JSON structure
 #[tokio::test]
-#[serial]
 async fn test_health_endpoint() {
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
+    let test_db = TestDatabase::isolated().await;
     let app = test_db.create_test_app().await;
     let server = TestServer::new(app);
 
@@ -29,23 +28,98 @@ async fn test_health_endpoint() {
     );
 
     let body = response.text();
-    assert_json_response_structure(&body, &["status", "service", "version"]);
+    assert_json_response_structure(&body, &["api_version", "status", "data", "meta", "errors"]);
 
     // Parse and verify specific content
     let json: serde_json::Value = serde_json::from_str(&body).unwrap();
-    assert_eq!(json["status"], "healthy");
-    assert_eq!(json["service"], "axum-base");
+    assert_eq!(json["status"], "success");
+    assert_eq!(json["data"]["status"], "healthy");
+    assert_eq!(json["data"]["service"], "axum-base");
+
+    // Each core component is checked and timed
+    let components = json["data"]["components"].as_array().unwrap();
+    let names: Vec<&str> = components
+        .iter()
+        .map(|c| c["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["database", "storage", "mail"]);
+    assert!(
+        components
+            .iter()
+            .all(|c| c["up"] == true && c["latency_ms"].is_u64())
+    );
+    assert_eq!(components[0]["critical"], true);
+
+    // Sizes derived from the container limits unless configured
+    let resources = &json["data"]["resources"];
+    assert!(resources["available_cpus"].as_u64().unwrap() >= 1);
+    assert!(resources["db_max_connections"]["value"].as_u64().unwrap() >= 5);
+    assert_eq!(resources["job_concurrency"]["configured"], false);
+}
+
+/// Test that liveness answers without the database and readiness checks
+/// migrations and the pool
+#[tokio::test]
+async fn test_liveness_and_readiness_probes() {
+    use axum_base::services::JobService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
 
-    test_db.cleanup().await;
+    let response = server.get("/health/live").await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["alive"], true);
+    assert!(json["data"].get("database").is_none());
+
+    JobService::heartbeat(pool, "probe-worker", "host-a", &[])
+        .await
+        .unwrap();
+    let response = server.get("/health/ready").await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["ready"], true);
+    assert_eq!(json["data"]["migrations_pending"], 0);
+    assert_eq!(
+        json["data"]["pool"]["max"],
+        pool.options().get_max_connections()
+    );
+
+    // A migration that did not finish keeps the instance out of rotation
+    let latest: i64 = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations")
+        .fetch_one(pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE _sqlx_migrations SET success = FALSE WHERE version = $1")
+        .bind(latest)
+        .execute(pool)
+        .await
+        .unwrap();
+    let response = server.get("/health/ready").await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["ready"], false);
+    assert_eq!(json["data"]["database"], true);
+    assert_eq!(json["data"]["migrations_pending"], 1);
+    sqlx::query("UPDATE _sqlx_migrations SET success = TRUE WHERE version = $1")
+        .bind(latest)
+        .execute(pool)
+        .await
+        .unwrap();
+
+    JobService::deregister(pool, "probe-worker").await.unwrap();
 }
 
 /// Test 404 handling for unknown routes
 #[tokio::test]
-#[serial]
 async fn test_404_endpoint() {
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
+    let test_db = TestDatabase::isolated().await;
     let app = test_db.create_test_app().await;
     let server = TestServer::new(app);
 
@@ -62,23 +136,21 @@ async fn test_404_endpoint() {
     );
 
     let body = response.text();
-    assert_json_response_structure(&body, &["message", "status", "timestamp"]);
+    assert_json_response_structure(&body, &["message", "status", "meta", "errors"]);
 
     // Parse and verify specific content
     let json: serde_json::Value = serde_json::from_str(&body).unwrap();
     assert_eq!(json["status"], "error");
     assert!(json["message"].as_str().unwrap().contains("not found"));
-
-    test_db.cleanup().await;
+    assert_eq!(json["errors"][0]["code"], "not_found");
 }
 
 /// Test the API hello endpoint
 #[tokio::test]
-#[serial]
 async fn test_api_hello_endpoint() {
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
+    let test_db = TestDatabase::isolated().await;
     let app = test_db.create_test_app().await;
     let server = TestServer::new(app);
 
@@ -95,7 +167,7 @@ async fn test_api_hello_endpoint() {
     );
 
     let body = response.text();
-    assert_json_response_structure(&body, &["message", "status", "timestamp"]);
+    assert_json_response_structure(&body, &["message", "status", "meta"]);
 
     // Parse and verify specific content
     let json: serde_json::Value = serde_json::from_str(&body).unwrap();
@@ -107,116 +179,3930 @@ async fn test_api_hello_endpoint() {
             .contains("Hello from Axum Base")
     );
 
-    // Verify timestamp is in RFC3339 format
-    let timestamp = json["timestamp"].as_str().unwrap();
+    // Verify server time is in RFC3339 format
+    let timestamp = json["meta"]["server_time"].as_str().unwrap();
     chrono::DateTime::parse_from_rfc3339(timestamp)
         .expect("Timestamp should be valid RFC3339 format");
-
-    test_db.cleanup().await;
 }
 
-/// Test the root endpoint serves HTML
-/// NOTE: This test is disabled because template initialization doesn't work in test environment
-/// TODO: Fix template testing infrastructure
-/*
+/// Test that API endpoints honor Accept for MessagePack and CBOR
 #[tokio::test]
-async fn test_root_endpoint() {
+async fn test_api_hello_content_negotiation() {
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
+    let test_db = TestDatabase::isolated().await;
     let app = test_db.create_test_app().await;
-    let server = TestServer::new(app).unwrap();
+    let server = TestServer::new(app);
 
-    let response = server.get("/").await;
+    let response = server
+        .get("/api/hello")
+        .add_header("accept", "application/msgpack")
+        .await;
     response.assert_status(StatusCode::OK);
+    assert_eq!(response.header("content-type"), "application/msgpack");
+    let json: serde_json::Value =
+        rmp_serde::from_slice(response.as_bytes()).expect("Body should be MessagePack");
+    assert_eq!(json["status"], "success");
 
-    let body = response.text();
-    // Verify it's HTML content
-    assert!(body.contains("<html") || body.contains("<!DOCTYPE"));
-    assert!(body.contains("</html>"));
+    let response = server
+        .get("/api/hello")
+        .add_header("accept", "application/cbor")
+        .await;
+    response.assert_status(StatusCode::OK);
+    assert_eq!(response.header("content-type"), "application/cbor");
+    let json: serde_json::Value =
+        ciborium::from_reader(&response.as_bytes()[..]).expect("Body should be CBOR");
+    assert_eq!(json["status"], "success");
+}
+
+/// Test paginated list endpoints
+#[tokio::test]
+async fn test_paginated_lists() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
 
-    test_db.cleanup().await;
+    let response = server
+        .get("/api/categories")
+        .add_query_param("per_page", "2")
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let returned = json["data"].as_array().unwrap().len() as i64;
+    let pagination = &json["meta"]["pagination"];
+    assert_eq!(pagination["page"], 1);
+    assert_eq!(pagination["per_page"], 2);
+    assert!(returned <= 2);
+    assert!(pagination["total"].as_i64().unwrap() >= returned);
+
+    let response = server
+        .get("/api/items")
+        .add_query_param("limit", "1")
+        .add_query_param("offset", "0")
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["meta"]["pagination"]["per_page"], 1);
+
+    // Page numbers start at 1
+    let response = server.get("/api/items").add_query_param("page", "0").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["errors"][0]["code"], "validation");
 }
-*/
 
-/// Test login page endpoint  
-/// NOTE: This test is disabled because template initialization doesn't work in test environment
-/// TODO: Fix template testing infrastructure
-/*
+/// Test search-as-you-type suggestions against the seeded items
 #[tokio::test]
-async fn test_login_page_endpoint() {
+async fn test_item_suggestions() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    // Titles starting with the text come before those containing it
+    let response = server
+        .get("/api/items/suggest")
+        .add_query_param("q", "  EXAMPLE ")
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let titles: Vec<&str> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|suggestion| suggestion["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["Example Project", "Database Query Example"]);
+    assert_eq!(json["data"][0]["category"], "Projects");
+
+    // Served from the cache the second time, same answer
+    let response = server
+        .get("/api/items/suggest")
+        .add_query_param("q", "example")
+        .add_query_param("limit", "1")
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+    assert_eq!(json["data"][0]["title"], "Example Project");
+
+    // One character is too short to suggest for; LIKE wildcards match literally
+    for q in ["e", "%%", "__"] {
+        let response = server
+            .get("/api/items/suggest")
+            .add_query_param("q", q)
+            .await;
+        response.assert_status_ok();
+        let json: serde_json::Value = response.json();
+        assert_eq!(json["data"], serde_json::json!([]), "q={}", q);
+    }
+
+    let response = server
+        .get("/api/items/suggest")
+        .add_query_param("q", "example")
+        .add_query_param("limit", "50")
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+/// Test full-text item search ranks, filters, and highlights matches
+#[tokio::test]
+async fn test_item_search() {
+    use axum_base::testing::{CategoryFactory, ItemFactory};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let tools = CategoryFactory::new().create(&test_db.pool).await;
+    let other = CategoryFactory::new().create(&test_db.pool).await;
+    ItemFactory::new()
+        .title("Zephyrine tuning guide")
+        .description("Notes on tuning engines.")
+        .category(&tools)
+        .create(&test_db.pool)
+        .await;
+    ItemFactory::new()
+        .title("Engine notes")
+        .description("Mentions zephyrine once, in passing.")
+        .category(&other)
+        .create(&test_db.pool)
+        .await;
+    ItemFactory::new()
+        .title("Zephyrine secrets")
+        .category(&tools)
+        .inactive()
+        .create(&test_db.pool)
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    // Title matches outrank description matches; hidden items never show
+    let response = server
+        .get("/api/items/search")
+        .add_query_param("q", "Zephyrines")
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let titles: Vec<&str> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| hit["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["Zephyrine tuning guide", "Engine notes"]);
+    assert_eq!(json["meta"]["pagination"]["total"], 2);
+    assert_eq!(
+        json["data"][0]["title_html"],
+        "<mark>Zephyrine</mark> tuning guide"
+    );
+    assert!(
+        json["data"][1]["snippet_html"]
+            .as_str()
+            .unwrap()
+            .contains("<mark>zephyrine</mark>")
+    );
+
+    let response = server
+        .get("/api/items/search")
+        .add_query_param("q", "zephyrine -tuning")
+        .add_query_param("category_id", other.id.to_string())
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+    assert_eq!(json["data"][0]["title"], "Engine notes");
+
+    server
+        .get("/api/items/search")
+        .add_query_param("q", "  ")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+/// Test item listing filters and sorts, and that inactive items are admin-only
+#[tokio::test]
+async fn test_item_filters() {
+    use axum_base::testing::{CategoryFactory, ItemFactory, UserFactory};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let fruit = CategoryFactory::new().create(pool).await;
+    let banana = ItemFactory::new()
+        .title("banana")
+        .category(&fruit)
+        .create(pool)
+        .await;
+    ItemFactory::new()
+        .title("Apple")
+        .category(&fruit)
+        .create(pool)
+        .await;
+    ItemFactory::new()
+        .title("cherry")
+        .category(&fruit)
+        .create(pool)
+        .await;
+    ItemFactory::new()
+        .title("Durian")
+        .category(&fruit)
+        .inactive()
+        .create(pool)
+        .await;
+    ItemFactory::new().title("Elsewhere").create(pool).await;
+    sqlx::query("UPDATE items SET created_at = NOW() - INTERVAL '10 days' WHERE id = $1")
+        .bind(banana.id)
+        .execute(pool)
+        .await
+        .unwrap();
+    UserFactory::new()
+        .username("filteradmin")
+        .with_password("password123")
+        .admin()
+        .create(pool)
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let titles = |json: serde_json::Value| -> Vec<String> {
+        json["data"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let category = fruit.id.to_string();
+
+    // Newest first by default, A to Z by title ignoring case
+    let response = server
+        .get("/api/items")
+        .add_query_param("category_id", &category)
+        .await;
+    response.assert_status_ok();
+    assert_eq!(titles(response.json())[2], "banana");
+    let response = server
+        .get("/api/items")
+        .add_query_param("category_id", &category)
+        .add_query_param("sort", "title")
+        .await;
+    assert_eq!(titles(response.json()), ["Apple", "banana", "cherry"]);
+    let response = server
+        .get("/api/items")
+        .add_query_param("category_id", &category)
+        .add_query_param("sort", "title")
+        .add_query_param("direction", "desc")
+        .await;
+    assert_eq!(titles(response.json()), ["cherry", "banana", "Apple"]);
+
+    // Date bounds
+    let week_ago = (chrono::Utc::now() - chrono::Duration::days(7)).to_rfc3339();
+    let response = server
+        .get("/api/items")
+        .add_query_param("category_id", &category)
+        .add_query_param("created_before", &week_ago)
+        .await;
+    assert_eq!(titles(response.json()), ["banana"]);
+    let response = server
+        .get("/api/items")
+        .add_query_param("category_id", &category)
+        .add_query_param("created_after", &week_ago)
+        .add_query_param("sort", "title")
+        .await;
+    assert_eq!(titles(response.json()), ["Apple", "cherry"]);
+    server
+        .get("/api/items")
+        .add_query_param("created_after", chrono::Utc::now().to_rfc3339())
+        .add_query_param("created_before", &week_ago)
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/api/items")
+        .add_query_param("sort", "id; DROP TABLE items")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    // Inactive items are for admins
+    server
+        .get("/api/items")
+        .add_query_param("is_active", "false")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    let cookie = login_cookie(&server, "filteradmin", "password123").await;
+    let response = server
+        .get("/api/items")
+        .add_header("cookie", &cookie)
+        .add_query_param("category_id", &category)
+        .add_query_param("is_active", "false")
+        .await;
+    response.assert_status_ok();
+    assert_eq!(titles(response.json()), ["Durian"]);
+}
+
+/// Test cursor pagination of items and the audit log: pages chain without
+/// gaps or overlaps, and cursors are checked
+#[tokio::test]
+async fn test_cursor_pagination() {
+    use axum_base::services::AuditService;
+    use axum_base::testing::{CategoryFactory, ItemFactory};
+
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let category = CategoryFactory::new().create(pool).await;
+    let mut created = Vec::new();
+    for n in 0..5 {
+        created.push(
+            ItemFactory::new()
+                .title(&format!("Paged {}", n))
+                .category(&category)
+                .create(pool)
+                .await
+                .id,
+        );
+    }
+    // Two items share a creation time; the ID breaks the tie
+    sqlx::query(
+        "UPDATE items SET created_at = (SELECT created_at FROM items WHERE id = $1) WHERE id = $2",
+    )
+    .bind(created[1])
+    .bind(created[2])
+    .execute(pool)
+    .await
+    .unwrap();
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    for n in 0..3 {
+        AuditService::record(
+            pool,
+            None,
+            "test.paged",
+            Some("test"),
+            Some(&n.to_string()),
+            &serde_json::json!({}),
+        )
+        .await
+        .unwrap();
+    }
+
     let app = test_db.create_test_app().await;
-    let server = TestServer::new(app).unwrap();
+    let server = TestServer::new(app);
+
+    let mut seen = Vec::new();
+    let mut cursor = String::new();
+    loop {
+        let response = server
+            .get("/api/items")
+            .add_query_param("category_id", category.id.to_string())
+            .add_query_param("per_page", "2")
+            .add_query_param("cursor", &cursor)
+            .await;
+        response.assert_status_ok();
+        let json: serde_json::Value = response.json();
+        assert!(json["meta"].get("pagination").is_none());
+        assert_eq!(json["meta"]["cursor"]["per_page"], 2);
+        for item in json["data"].as_array().unwrap() {
+            seen.push(item["id"].as_i64().unwrap() as i32);
+        }
+        match json["meta"]["cursor"]["next_cursor"].as_str() {
+            Some(next) => cursor = next.to_string(),
+            None => break,
+        }
+    }
+    let newest_first: Vec<i32> = created.iter().rev().copied().collect();
+    assert_eq!(seen, newest_first);
+
+    // Oldest first works too; title sorts and page numbers do not mix
+    let response = server
+        .get("/api/items")
+        .add_query_param("category_id", category.id.to_string())
+        .add_query_param("direction", "asc")
+        .add_query_param("cursor", "")
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"][0]["title"], "Paged 0");
+    assert!(json["meta"]["cursor"]["next_cursor"].is_null());
+    let oldest_page = |cursor: String| {
+        server
+            .get("/api/items")
+            .add_query_param("category_id", category.id.to_string())
+            .add_query_param("direction", "asc")
+            .add_query_param("per_page", "2")
+            .add_query_param("cursor", cursor)
+    };
+    let json: serde_json::Value = oldest_page(String::new()).await.json();
+    let next = json["meta"]["cursor"]["next_cursor"].as_str().unwrap();
+    let json: serde_json::Value = oldest_page(next.to_string()).await.json();
+    assert_eq!(json["data"][0]["title"], "Paged 2");
+    assert_eq!(json["data"][1]["title"], "Paged 3");
+    for (name, value) in [("sort", "title"), ("page", "2")] {
+        server
+            .get("/api/items")
+            .add_query_param(name, value)
+            .add_query_param("cursor", "")
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+    server
+        .get("/api/items")
+        .add_query_param("cursor", "not-a-cursor")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
 
+    // The audit log pages the same way
+    let cookie = login_cookie(&server, "admin", "password123").await;
+    let page = |cursor: String| {
+        server
+            .get("/api/admin/audit")
+            .add_header("cookie", &cookie)
+            .add_query_param("action", "test.paged")
+            .add_query_param("limit", "2")
+            .add_query_param("cursor", cursor)
+    };
+    let first: serde_json::Value = page(String::new()).await.json();
+    assert_eq!(first["data"][0]["target_id"], "2");
+    assert_eq!(first["data"][1]["target_id"], "1");
+    let next = first["meta"]["cursor"]["next_cursor"].as_str().unwrap();
+    let second: serde_json::Value = page(next.to_string()).await.json();
+    assert_eq!(second["data"].as_array().unwrap().len(), 1);
+    assert_eq!(second["data"][0]["target_id"], "0");
+    assert!(second["meta"]["cursor"]["next_cursor"].is_null());
+}
+
+/// Test batch item creation keeps input order and is all or nothing
+#[tokio::test]
+async fn test_create_items_batch() {
+    use axum_base::models::CreateItemRequest;
+    use axum_base::repositories::ITEM_INSERT_CHUNK;
+    use axum_base::services::ItemService;
+    use axum_base::testing::CategoryFactory;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let category = CategoryFactory::new().create(pool).await;
+    let request = |title: &str, category_id: i32| CreateItemRequest {
+        title: title.to_string(),
+        description: Some(format!("About {}", title)),
+        data: Some(serde_json::json!({ "title": title })),
+        category_id,
+    };
+
+    assert!(
+        ItemService::create_items(pool, &[])
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Two full chunks and one row in a third
+    let rows = 2 * ITEM_INSERT_CHUNK + 1;
+    let requests: Vec<_> = (0..rows)
+        .map(|n| request(&format!("Batch {}", n), category.id))
+        .collect();
+    let items = ItemService::create_items(pool, &requests).await.unwrap();
+    assert_eq!(items.len(), rows);
+    assert_eq!(items[0].title, "Batch 0");
+    assert_eq!(items[rows - 1].title, format!("Batch {}", rows - 1));
+    assert_eq!(
+        items[ITEM_INSERT_CHUNK].data,
+        Some(serde_json::json!({ "title": format!("Batch {}", ITEM_INSERT_CHUNK) }))
+    );
+    assert!(items.windows(2).all(|pair| pair[0].id < pair[1].id));
+
+    // A bad row in any chunk leaves nothing behind
+    let mut requests: Vec<_> = (0..ITEM_INSERT_CHUNK + 1)
+        .map(|n| request(&format!("Rolled back {}", n), category.id))
+        .collect();
+    requests.push(request("Orphan", -1));
+    assert!(ItemService::create_items(pool, &requests).await.is_err());
+    let count: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM items WHERE title LIKE 'Rolled back%'")
+            .fetch_one(pool)
+            .await
+            .unwrap();
+    assert_eq!(count, 0);
+}
+
+/// Fetch the login form and return its token cookie (`login_csrf=...`) and token
+async fn login_form(server: &TestServer) -> (String, String) {
     let response = server.get("/login").await;
     response.assert_status(StatusCode::OK);
 
-    let body = response.text();
-    // Verify it's HTML content and likely contains form elements
-    assert!(body.contains("<html") || body.contains("<!DOCTYPE"));
+    let set_cookie = response.header("set-cookie");
+    let set_cookie = set_cookie.to_str().expect("Cookie should be ASCII");
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+    let token = cookie.split_once('=').unwrap().1.to_string();
+    assert!(
+        response.text().contains(&token),
+        "The form should carry the token"
+    );
+    (cookie, token)
+}
+
+/// Log in through the form handler and return the session cookie
+async fn login_cookie(server: &TestServer, username: &str, password: &str) -> String {
+    let (csrf_cookie, token) = login_form(server).await;
+    let response = server
+        .post("/login")
+        .add_header("cookie", &csrf_cookie)
+        .form(&[
+            ("username", username),
+            ("password", password),
+            ("csrf_token", token.as_str()),
+        ])
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
 
-    test_db.cleanup().await;
+    let set_cookie = response.header("set-cookie");
+    let set_cookie = set_cookie.to_str().expect("Cookie should be ASCII");
+    set_cookie.split(';').next().unwrap().to_string()
 }
-*/
 
-/// Test database connection in test environment
+/// Test the admin users API: access control, create, list, roles, deactivate
 #[tokio::test]
-#[serial]
-async fn test_database_connection() {
+async fn test_admin_users_api() {
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    test_db
+        .create_test_user("regular", "regular@example.com", "password123")
+        .await;
 
-    // Test basic database connectivity
-    let result = sqlx::query("SELECT 1 as test")
-        .fetch_one(&test_db.pool)
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    // Anonymous and non-admin users are rejected
+    let response = server.get("/api/admin/users").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    let regular_cookie = login_cookie(&server, "regular", "password123").await;
+    let response = server
+        .get("/api/admin/users")
+        .add_header("cookie", &regular_cookie)
         .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["errors"][0]["code"], "forbidden");
 
-    assert!(result.is_ok(), "Database connection should work");
+    let cookie = login_cookie(&server, "admin", "password123").await;
+
+    // Create a user with a role
+    let response = server
+        .post("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "username": "newbie",
+            "email": "newbie@example.com",
+            "password": "password123",
+            "roles": ["editor"]
+        }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let json: serde_json::Value = response.json();
+    let new_id = json["data"]["id"].as_i64().unwrap();
+    assert_eq!(json["data"]["roles"], serde_json::json!(["editor"]));
+    assert!(json["data"].get("password_hash").is_none());
+
+    // Short passwords are rejected
+    let response = server
+        .post("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "username": "shorty",
+            "email": "shorty@example.com",
+            "password": "short"
+        }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    // List with a role filter
+    let response = server
+        .get("/api/admin/users")
+        .add_query_param("role", "editor")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["meta"]["pagination"]["total"], 1);
+    assert_eq!(json["data"][0]["username"], "newbie");
+
+    // Assign roles, set a password, then deactivate
+    let response = server
+        .put(&format!("/api/admin/users/{}/roles", new_id))
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "roles": ["admin", "editor"] }))
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(
+        json["data"]["roles"],
+        serde_json::json!(["admin", "editor"])
+    );
+
+    let response = server
+        .put(&format!("/api/admin/users/{}/password", new_id))
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "password": "newpassword456" }))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .post(&format!("/api/admin/users/{}/deactivate", new_id))
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["is_active"], false);
 
-    // Clean up
-    test_db.cleanup().await;
+    let response = server
+        .get("/api/admin/users")
+        .add_query_param("is_active", "false")
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["meta"]["pagination"]["total"], 1);
+
+    let response = server
+        .get("/api/admin/users/999999")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
 }
 
-/// Test user creation and cleanup
+/// Test renaming users: case-insensitive uniqueness, history, and sessions
+/// that stay signed in
 #[tokio::test]
 #[serial]
-async fn test_user_creation() {
+async fn test_change_username() {
     setup_test_env();
 
-    let test_db = TestDatabase::new().await;
-    test_db.cleanup().await; // Start with clean state
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("renamer", "renamer@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    let user = test_db
+        .create_test_user("oldname", "oldname@example.com", "password123")
+        .await;
+    test_db
+        .create_test_user("Taken", "taken@example.com", "password123")
+        .await;
+    let server = TestServer::new(test_db.create_full_app().await);
+    let admin_cookie = login_cookie(&server, "renamer", "password123").await;
+    let user_cookie = login_cookie(&server, "oldname", "password123").await;
+    let rename = |username: &'static str| {
+        server
+            .put(&format!("/api/admin/users/{}/username", user.id))
+            .add_header("cookie", &admin_cookie)
+            .json(&serde_json::json!({ "username": username }))
+    };
+
+    rename("taken").await.assert_status(StatusCode::BAD_REQUEST);
+    rename("has space")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    let response = rename("newname").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"]["username"], "newname");
 
-    // Create test user
+    // The renamed user's session still works, and the new name signs in
+    let response = server
+        .get("/profile")
+        .add_header("cookie", &user_cookie)
+        .await;
+    response.assert_status_ok();
+    login_cookie(&server, "newname", "password123").await;
+
+    // Changing only the case of one's own name is allowed, from the profile
+    let response = server
+        .post("/profile")
+        .add_header("cookie", &user_cookie)
+        .form(&[
+            ("action", "update_profile"),
+            ("username", "NewName"),
+            ("email", "oldname@example.com"),
+        ])
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+
+    let response = server
+        .get(&format!("/api/admin/users/{}/username-history", user.id))
+        .add_header("cookie", &admin_cookie)
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let history = body["data"].as_array().unwrap();
+    assert_eq!(history.len(), 2);
+    assert_eq!(history[0]["old_username"], "newname");
+    assert_eq!(history[0]["new_username"], "NewName");
+    assert_eq!(history[0]["changed_by"], user.id);
+    assert_eq!(history[1]["old_username"], "oldname");
+    assert_eq!(history[1]["changed_by"], admin.id);
+}
+
+/// Test users set a display name, bio, and profile data that the admin API returns
+#[tokio::test]
+#[serial]
+async fn test_extended_profile_fields() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
     let user = test_db
-        .create_test_user("testuser", "test@example.com", "password123")
+        .create_test_user("profiled", "profiled@example.com", "password123")
         .await;
 
-    assert_eq!(user.username, "testuser");
-    assert_eq!(user.email, "test@example.com");
-    assert!(user.is_active);
-    assert!(user.password_hash.map_or(false, |hash| hash.len() > 10)); // Should have hashed password
+    let app = test_db.create_full_app().await;
+    let server = TestServer::new(app);
+    let admin_cookie = login_cookie(&server, "admin", "password123").await;
+    let user_cookie = login_cookie(&server, "profiled", "password123").await;
 
-    // Verify user exists in database
-    let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE username = $1")
-        .bind("testuser")
-        .fetch_one(&test_db.pool)
+    let update = |display_name: &'static str, profile_data: &'static str| {
+        server
+            .post("/profile")
+            .add_header("cookie", &user_cookie)
+            .form(&[
+                ("action", "update_profile"),
+                ("username", "profiled"),
+                ("email", "profiled@example.com"),
+                ("display_name", display_name),
+                ("bio", "Likes tea."),
+                ("profile_data", profile_data),
+            ])
+    };
+    update(" Pro Filed ", r#"{"website": "https://example.com"}"#)
         .await
-        .expect("Should be able to count users");
+        .assert_status(StatusCode::SEE_OTHER);
 
-    assert_eq!(count.0, 1, "Should have exactly one test user");
+    let response = server
+        .get(&format!("/api/admin/users/{}", user.id))
+        .add_header("cookie", &admin_cookie)
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"]["display_name"], "Pro Filed");
+    assert_eq!(body["data"]["bio"], "Likes tea.");
+    assert_eq!(
+        body["data"]["profile_data"],
+        serde_json::json!({ "website": "https://example.com" })
+    );
 
-    // Clean up
-    test_db.cleanup().await;
+    let response = server
+        .get("/profile")
+        .add_header("cookie", &user_cookie)
+        .await;
+    response.assert_status_ok();
+    assert!(response.text().contains("Pro Filed"));
 
-    // Verify cleanup worked
-    let count_after: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
-        .fetch_one(&test_db.pool)
+    // Profile data that is not a JSON object is refused and nothing changes
+    update("Someone Else", "[1, 2, 3]")
+        .await
+        .assert_status(StatusCode::SEE_OTHER);
+    let display_name: Option<String> =
+        sqlx::query_scalar("SELECT display_name FROM users WHERE id = $1")
+            .bind(user.id)
+            .fetch_one(&test_db.pool)
+            .await
+            .unwrap();
+    assert_eq!(display_name.as_deref(), Some("Pro Filed"));
+}
+
+/// Test users read, change, and reset their preferences, and lists follow
+/// their page size
+#[tokio::test]
+#[serial]
+async fn test_user_preferences() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    test_db
+        .create_test_user("prefs", "prefs@example.com", "password123")
+        .await;
+
+    let app = test_db.create_full_app().await;
+    let server = TestServer::new(app);
+    server
+        .get("/api/preferences")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    let cookie = login_cookie(&server, "prefs", "password123").await;
+
+    let response = server
+        .get("/api/preferences")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["theme"], "system");
+    assert_eq!(json["data"]["locale"], "en");
+    assert_eq!(json["data"]["timezone"], serde_json::Value::Null);
+    assert_eq!(json["data"]["items_per_page"], 20);
+
+    let response = server
+        .put("/api/preferences")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "theme": "dark",
+            "timezone": "Europe/Berlin",
+            "items_per_page": 5
+        }))
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["theme"], "dark");
+    assert_eq!(json["data"]["timezone"], "Europe/Berlin");
+    assert_eq!(json["data"]["items_per_page"], 5);
+
+    // Nothing is saved when one value is invalid
+    let response = server
+        .put("/api/preferences")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "theme": "light", "items_per_page": 1000 }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    // Pages and lists pick the preferences up from the session
+    let response = server.get("/").add_header("cookie", &cookie).await;
+    assert!(response.text().contains(r#"data-theme="dark""#));
+    let response = server
+        .get("/api/categories")
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["meta"]["pagination"]["per_page"], 5);
+
+    let response = server
+        .delete("/api/preferences/theme")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["theme"], "system");
+    assert_eq!(json["data"]["items_per_page"], 5);
+    server
+        .delete("/api/preferences/font")
+        .add_header("cookie", &cookie)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test listing notifications, marking them read, and the navbar unread count
+#[tokio::test]
+#[serial]
+async fn test_notifications() {
+    use axum_base::models::NewNotification;
+    use axum_base::services::NotificationService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let user = test_db
+        .create_test_user("notified", "notified@example.com", "password123")
+        .await;
+    let other = test_db
+        .create_test_user("bystander", "bystander@example.com", "password123")
+        .await;
+    let notify = |user_id: i32, title: &str| {
+        let notification = NewNotification {
+            kind: "test".to_string(),
+            title: title.to_string(),
+            body: None,
+            link: Some("/profile".to_string()),
+        };
+        let pool = test_db.pool.clone();
+        async move {
+            NotificationService::create(&pool, user_id, &notification)
+                .await
+                .expect("Failed to create notification")
+        }
+    };
+    let first = notify(user.id, "First").await;
+    notify(user.id, "Second").await;
+    let elsewhere = notify(other.id, "Not yours").await;
+
+    let app = test_db.create_full_app().await;
+    let server = TestServer::new(app);
+    server
+        .get("/api/notifications")
         .await
-        .expect("Should be able to count users after cleanup");
+        .assert_status(StatusCode::UNAUTHORIZED);
+    let cookie = login_cookie(&server, "notified", "password123").await;
+
+    let response = server
+        .get("/api/notifications")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let titles: Vec<&str> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|notification| notification["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["Second", "First"]);
+
+    // Pages show the unread count on the navbar bell
+    let response = server
+        .get("/")
+        .add_header("cookie", &cookie)
+        .add_header("accept", "text/html")
+        .await;
+    assert!(response.text().contains("Notifications, 2 unread"));
+
+    let response = server
+        .post(&format!("/api/notifications/{}/read", first.id))
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["unread"], 1);
+
+    // Other users' notifications cannot be touched
+    server
+        .post(&format!("/api/notifications/{}/read", elsewhere.id))
+        .add_header("cookie", &cookie)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    let response = server
+        .get("/api/notifications?all=true")
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 2);
+    assert!(json["data"][1]["read_at"].is_string());
+
+    server
+        .post("/api/notifications/read-all")
+        .add_header("cookie", &cookie)
+        .await
+        .assert_status_ok();
+    let response = server
+        .get("/api/notifications/unread-count")
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["unread"], 0);
+    assert_eq!(
+        NotificationService::unread_count(&test_db.pool, other.id)
+            .await
+            .unwrap(),
+        1
+    );
+}
+
+/// Test the activity feed shows a user's own account events, filtered for privacy
+#[tokio::test]
+#[serial]
+async fn test_activity_feed() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    test_db
+        .create_test_user("active", "active@example.com", "password123")
+        .await;
+    test_db
+        .create_test_user("neighbor", "neighbor@example.com", "password123")
+        .await;
+
+    let app = test_db.create_full_app().await;
+    let server = TestServer::new(app);
+    server
+        .get("/api/profile/activity")
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    login_cookie(&server, "neighbor", "password123").await;
+    let cookie = login_cookie(&server, "active", "password123").await;
+    server
+        .put("/api/preferences")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "theme": "dark" }))
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .get("/api/profile/activity")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let summaries: Vec<&str> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["summary"].as_str().unwrap())
+        .collect();
+    assert_eq!(summaries, ["Changed your preferences", "Signed in"]);
+    assert_eq!(json["data"][0]["details"]["keys"][0], "theme");
+    assert!(json["data"][0].get("actor_id").is_none());
+
+    let response = server
+        .get("/profile/activity")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    assert!(response.text().contains("keys: theme"));
+}
+
+/// Test deactivating a user ends their sessions and reactivating lets them back in
+#[tokio::test]
+async fn test_deactivate_and_reactivate_user() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    let regular = test_db
+        .create_test_user("regular", "regular@example.com", "password123")
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let cookie = login_cookie(&server, "admin", "password123").await;
+    let regular_cookie = login_cookie(&server, "regular", "password123").await;
+
+    let count_sessions = || async {
+        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM user_sessions WHERE user_id = $1")
+            .bind(regular.id)
+            .fetch_one(&test_db.pool)
+            .await
+            .unwrap()
+    };
+    assert_eq!(count_sessions().await, 1);
+    server
+        .get("/api/uploads")
+        .add_header("cookie", &regular_cookie)
+        .await
+        .assert_status_ok();
+
+    // Admins cannot lock themselves out
+    server
+        .post(&format!("/api/admin/users/{}/deactivate", admin.id))
+        .add_header("cookie", &cookie)
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .post(&format!("/api/admin/users/{}/deactivate", regular.id))
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["is_active"], false);
+    assert_eq!(count_sessions().await, 0);
+    server
+        .get("/api/uploads")
+        .add_header("cookie", &regular_cookie)
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // Deactivated users cannot sign in again
+    let (csrf_cookie, token) = login_form(&server).await;
+    let response = server
+        .post("/login")
+        .add_header("cookie", &csrf_cookie)
+        .form(&[
+            ("username", "regular"),
+            ("password", "password123"),
+            ("csrf_token", token.as_str()),
+        ])
+        .await;
+    assert_ne!(response.status_code(), StatusCode::SEE_OTHER);
+
+    let response = server
+        .post(&format!("/api/admin/users/{}/reactivate", regular.id))
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["is_active"], true);
+    assert_eq!(json["message"], "User reactivated");
+    let regular_cookie = login_cookie(&server, "regular", "password123").await;
+    server
+        .get("/api/uploads")
+        .add_header("cookie", &regular_cookie)
+        .await
+        .assert_status_ok();
+
+    server
+        .post("/api/admin/users/999999/reactivate")
+        .add_header("cookie", &cookie)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    // Both are in the audit log
+    let response = server
+        .get("/api/admin/audit")
+        .add_query_param("target_type", "user")
+        .add_query_param("target_id", regular.id.to_string())
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    let actions: Vec<&str> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        actions,
+        ["admin.user_reactivated", "admin.user_deactivated"]
+    );
+    assert_eq!(json["data"][1]["details"]["sessions_ended"], 1);
+}
+
+/// Test the read-only SQL console API: access, results, CSV, read-only, audit
+#[tokio::test]
+async fn test_sql_console_api() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("sqladmin", "sqladmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    // Anonymous callers are rejected
+    let response = server
+        .post("/api/admin/sql")
+        .json(&serde_json::json!({ "query": "SELECT 1" }))
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    let cookie = login_cookie(&server, "sqladmin", "password123").await;
+
+    let response = server
+        .post("/api/admin/sql")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "query": "SELECT id, username FROM users ORDER BY id;"
+        }))
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(
+        json["data"]["columns"],
+        serde_json::json!(["id", "username"])
+    );
+    assert_eq!(json["data"]["rows"][0][1], "sqladmin");
+    assert_eq!(json["data"]["row_count"], 1);
+
+    let response = server
+        .post("/api/admin/sql")
+        .add_query_param("format", "csv")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "query": "SELECT username, email FROM users" }))
+        .await;
+    response.assert_status_ok();
+    assert!(
+        response
+            .header("content-type")
+            .to_str()
+            .unwrap()
+            .starts_with("text/csv")
+    );
+    assert_eq!(
+        response.text(),
+        "username,email\r\nsqladmin,sqladmin@example.com\r\n"
+    );
+
+    // Statements other than queries are refused outright
+    let response = server
+        .post("/api/admin/sql")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "query": "DELETE FROM users" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    // Side effects from inside a SELECT fail in the read-only transaction
+    let response = server
+        .post("/api/admin/sql")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "query": "SELECT nextval('users_id_seq')" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let json: serde_json::Value = response.json();
+    assert!(json["message"].as_str().unwrap().contains("read-only"));
+
+    // Stacked statements cannot escape the transaction
+    let response = server
+        .post("/api/admin/sql")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "query": "SELECT 1; DELETE FROM users" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let users: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(&test_db.pool)
+        .await
+        .unwrap();
+    assert_eq!(users, 1);
+
+    // Every attempt is audited, including the rejected ones
+    let logged: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sql_console_log WHERE user_id = $1")
+        .bind(admin.id)
+        .fetch_one(&test_db.pool)
+        .await
+        .unwrap();
+    assert_eq!(logged, 5);
+
+    // The per-admin rate limit eventually kicks in
+    let mut status = StatusCode::OK;
+    for _ in 0..10 {
+        let response = server
+            .post("/api/admin/sql")
+            .add_header("cookie", &cookie)
+            .json(&serde_json::json!({ "query": "SELECT 1" }))
+            .await;
+        status = response.status_code();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            assert!(response.maybe_header("retry-after").is_some());
+            break;
+        }
+    }
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+/// Server answering `GET /theme` with the theme the middleware resolved
+/// for the request's Host header: "<name> <primary color> <partials>"
+fn theme_probe(test_db: &TestDatabase) -> TestServer {
+    use axum::{Router, middleware, routing::get};
+    use axum_base::config::AppConfig;
+    use axum_base::state::AppState;
+    use axum_base::theming;
+    use std::sync::Arc;
+
+    let state = AppState::new(
+        test_db.pool.clone(),
+        AppConfig::default(),
+        Arc::new(tera::Tera::default()),
+    );
+    let probe = Router::new()
+        .route(
+            "/theme",
+            get(|| async {
+                match theming::current() {
+                    Some(theme) => format!(
+                        "{} {} {}",
+                        theme.name.clone().unwrap_or_default(),
+                        theme.primary_color.clone().unwrap_or_default(),
+                        theme.partials.keys().cloned().collect::<Vec<_>>().join(",")
+                    ),
+                    None => "none".to_string(),
+                }
+            }),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            theming::resolve_theme,
+        ))
+        .with_state(state);
+    TestServer::new(probe)
+}
+
+/// Test the tenant admin API and resolving a tenant's theme from the Host header
+#[tokio::test]
+async fn test_tenant_theming() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("tenantadmin", "tenantadmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/api/admin/tenants").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    let cookie = login_cookie(&server, "tenantadmin", "password123").await;
+
+    let response = server
+        .post("/api/admin/tenants")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "slug": "acme",
+            "name": "Acme Corp",
+            "primary_color": "#0f766e"
+        }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["slug"], "acme");
+    assert_eq!(json["data"]["primary_color"], "#0f766e");
+
+    // Slugs must be DNS labels, colors #rrggbb
+    let response = server
+        .post("/api/admin/tenants")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "slug": "Not A Label", "name": "Bad" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .put("/api/admin/tenants/acme/theme")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "primary_color": "red;}" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .put("/api/admin/tenants/acme/theme")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "primary_color": "#7c3aed",
+            "logo_url": "/static/acme.svg"
+        }))
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["primary_color"], "#7c3aed");
+    assert_eq!(json["data"]["logo_url"], "/static/acme.svg");
+
+    // Only themeable partials that parse are accepted
+    let response = server
+        .put("/api/admin/tenants/acme/partials/footer.html")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "body": "{% if %}" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .put("/api/admin/tenants/acme/partials/base.html")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "body": "<p>hi</p>" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .put("/api/admin/tenants/acme/partials/footer.html")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "body": "<p>{{ theme.name }} footer</p>" }))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .get("/api/admin/tenants")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+
+    // The middleware scopes the tenant's theme to requests on its subdomain
+    let probe = theme_probe(&test_db);
+
+    let response = probe
+        .get("/theme")
+        .add_header("host", "acme.localhost:3093")
+        .await;
+    assert_eq!(response.text(), "Acme Corp #7c3aed partials/footer.html");
+
+    let response = probe
+        .get("/theme")
+        .add_header("host", "localhost:3093")
+        .await;
+    assert_eq!(response.text(), "none");
+
+    let response = probe
+        .get("/theme")
+        .add_header("host", "unknown.localhost:3093")
+        .await;
+    assert_eq!(response.text(), "none");
+
+    let response = server
+        .delete("/api/admin/tenants/acme/partials/footer.html")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .delete("/api/admin/tenants/acme/partials/footer.html")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test saving snippets through the admin API and rendering them in the sandbox
+#[tokio::test]
+async fn test_snippets() {
+    use axum_base::snippets::{self, SnippetContext};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("snippetadmin", "snippetadmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/api/admin/snippets").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    let cookie = login_cookie(&server, "snippetadmin", "password123").await;
+
+    // Templates the sandbox rejects are not saved
+    for body in [
+        "{{ get_env(name='DATABASE_URL') }}",
+        "{% include \"base.html\" %}",
+        "{% if %}",
+    ] {
+        let response = server
+            .put("/api/admin/snippets/dashboard.welcome")
+            .add_header("cookie", &cookie)
+            .json(&serde_json::json!({ "body": body }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    let response = server
+        .put("/api/admin/snippets/Dashboard")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "body": "<p>hi</p>" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .put("/api/admin/snippets/dashboard.welcome")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "body": "<p>Hello {{ username | default(value='there') }}</p>",
+            "description": "Greeting on the home page"
+        }))
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["name"], "dashboard.welcome");
+    assert_eq!(json["data"]["updated_by"], admin.id);
+
+    let response = server
+        .get("/api/admin/snippets")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+
+    // Dashboard snippets render with only the context they are given
+    let context = SnippetContext {
+        service_name: "Axum Base".to_string(),
+        username: Some("<jane>".to_string()),
+        locale: "en".to_string(),
+    };
+    let widgets = snippets::render_all(&test_db.pool, snippets::DASHBOARD_PREFIX, &context).await;
+    assert_eq!(widgets.len(), 1);
+    assert_eq!(widgets[0].html, "<p>Hello &lt;jane&gt;</p>");
+
+    let response = server
+        .delete("/api/admin/snippets/dashboard.welcome")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .get("/api/admin/snippets/dashboard.welcome")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test attaching custom domains to tenants and routing verified ones by Host
+#[tokio::test]
+async fn test_tenant_custom_domains() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("domainadmin", "domainadmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let cookie = login_cookie(&server, "domainadmin", "password123").await;
+
+    let response = server
+        .post("/api/admin/tenants")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "slug": "acme", "name": "Acme Corp" }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+
+    let response = server
+        .post("/api/admin/tenants/acme/domains")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "domain": "Shop.Acme.test." }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["domain"], "shop.acme.test");
+    assert!(json["data"]["verified_at"].is_null());
+    let token = json["data"]["verification_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(
+        json["message"]
+            .as_str()
+            .unwrap()
+            .contains("_axum-base-verify.shop.acme.test")
+    );
+    assert!(json["message"].as_str().unwrap().contains(&token));
+
+    // Invalid names, the site's own hosts, and duplicates are rejected
+    for domain in [
+        "not a domain",
+        "localhost",
+        "acme.localhost",
+        "shop.acme.test",
+    ] {
+        let response = server
+            .post("/api/admin/tenants/acme/domains")
+            .add_header("cookie", &cookie)
+            .json(&serde_json::json!({ "domain": domain }))
+            .await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+    }
+
+    let response = server
+        .get("/api/admin/tenants/acme/domains")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+
+    // Unverified domains are not routed to the tenant
+    let probe = theme_probe(&test_db);
+    let response = probe
+        .get("/theme")
+        .add_header("host", "shop.acme.test")
+        .await;
+    assert_eq!(response.text(), "none");
+
+    // Mark it verified as if the TXT record had been found
+    sqlx::query("UPDATE tenant_domains SET verified_at = NOW() WHERE domain = $1")
+        .bind("shop.acme.test")
+        .execute(&test_db.pool)
+        .await
+        .unwrap();
+
+    let response = probe
+        .get("/theme")
+        .add_header("host", "shop.acme.test:443")
+        .await;
+    assert_eq!(response.text(), "Acme Corp  ");
+
+    let response = probe
+        .get("/theme")
+        .add_header("host", "other.acme.test")
+        .await;
+    assert_eq!(response.text(), "none");
+
+    // Verifying an already verified domain needs no DNS lookup
+    let response = server
+        .post("/api/admin/tenants/acme/domains/shop.acme.test/verify")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert!(json["data"]["verified_at"].is_string());
+
+    let response = server
+        .delete("/api/admin/tenants/acme/domains/shop.acme.test")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+
+    let response = probe
+        .get("/theme")
+        .add_header("host", "shop.acme.test")
+        .await;
+    assert_eq!(response.text(), "none");
+
+    let response = server
+        .delete("/api/admin/tenants/acme/domains/shop.acme.test")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test bounce/complaint webhooks, suppression of sends, and the outbox
+#[tokio::test]
+async fn test_email_bounces_and_outbox() {
+    use axum_base::config::AppConfig;
+    use axum_base::emails;
+    use axum_base::mailer::Email;
+    use axum_base::state::AppState;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("bounceadmin", "bounceadmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    let user = test_db
+        .create_test_user("bouncer", "bouncer@example.com", "password123")
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let notification = serde_json::json!({
+        "notificationType": "Bounce",
+        "bounce": {
+            "bounceType": "Permanent",
+            "bouncedRecipients": [{ "emailAddress": "Bouncer@Example.com" }]
+        }
+    });
+    let sns = serde_json::json!({ "Type": "Notification", "Message": notification.to_string() });
+
+    // The token is required
+    let response = server
+        .post("/webhooks/email/ses")
+        .text(sns.to_string())
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    let response = server
+        .post("/webhooks/email/ses")
+        .add_query_param("token", TestDatabase::WEBHOOK_TOKEN)
+        .text(sns.to_string())
+        .await;
+    response.assert_status_ok();
+
+    // The admin user view marks the address undeliverable
+    let cookie = login_cookie(&server, "bounceadmin", "password123").await;
+    let response = server
+        .get(&format!("/api/admin/users/{}", user.id))
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["email_undeliverable"], "bounce");
+
+    // Nothing is sent to a suppressed address; every attempt is in the outbox
+    let state = AppState::new(
+        test_db.pool.clone(),
+        AppConfig::default(),
+        std::sync::Arc::new(tera::Tera::default()),
+    );
+    let email = |to: &str| Email {
+        to: to.to_string(),
+        subject: "Hello".to_string(),
+        html: "<p>Hello</p>".to_string(),
+        text: None,
+        unsubscribe_url: None,
+    };
+    assert!(
+        emails::deliver(&state, "welcome", &email("bouncer@example.com"))
+            .await
+            .is_err()
+    );
+    assert!(
+        emails::deliver(&state, "welcome", &email("fine@example.com"))
+            .await
+            .is_ok()
+    );
+
+    let response = server
+        .get("/api/admin/email/outbox")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["meta"]["pagination"]["total"], 2);
+    assert_eq!(json["data"][0]["status"], "sent");
+    assert_eq!(json["data"][1]["status"], "suppressed");
+    assert_eq!(json["data"][1]["undeliverable"], "bounce");
+
+    // SendGrid spam reports become complaints
+    let response = server
+        .post("/webhooks/email/sendgrid")
+        .add_query_param("token", TestDatabase::WEBHOOK_TOKEN)
+        .json(&serde_json::json!([
+            { "email": "angry@example.com", "event": "spamreport" },
+            { "email": "fine@example.com", "event": "delivered" }
+        ]))
+        .await;
+    response.assert_status_ok();
+
+    let response = server
+        .get("/api/admin/email/suppressions")
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    let reasons: Vec<(String, String)> = json["data"]
+        .as_array()
+        .expect("suppressions array")
+        .iter()
+        .map(|s| {
+            let field = |key: &str| s[key].as_str().unwrap_or_default().to_string();
+            (field("email"), field("reason"))
+        })
+        .collect();
+    assert_eq!(reasons.len(), 2);
+    assert!(reasons.contains(&("angry@example.com".to_string(), "complaint".to_string())));
+
+    // Lifting a suppression lets mail through again
+    let response = server
+        .delete("/api/admin/email/suppressions/bouncer@example.com")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let response = server
+        .delete("/api/admin/email/suppressions/bouncer@example.com")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    assert!(
+        emails::deliver(&state, "welcome", &email("bouncer@example.com"))
+            .await
+            .is_ok()
+    );
+
+    // Every attempt is counted for /metrics by outcome
+    let metrics = state.metrics.source::<emails::EmailMetrics>();
+    assert_eq!(metrics.deliveries.get(&["sent"]), 2);
+    assert_eq!(metrics.deliveries.get(&["suppressed"]), 1);
+    assert!(
+        state
+            .metrics
+            .render()
+            .contains("email_deliveries_total{status=\"failed\"} 0\n")
+    );
+}
+
+/// Test email template versions and the fallback to the bundled files
+#[tokio::test]
+async fn test_email_template_versions() {
+    use axum_base::emails;
+    use axum_base::services::EmailTemplateService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("mailadmin", "mailadmin@example.com", "password123")
+        .await;
+
+    let templates = axum_base::web::load_templates().expect("Templates should load");
+    let spec = emails::find("welcome").expect("welcome email is registered");
+
+    // Never edited: the bundled files are used
+    let rendered = emails::render(
+        &test_db.pool,
+        &templates,
+        spec,
+        None,
+        &spec.sample_context(None),
+    )
+    .await
+    .expect("Bundled template should render");
+    assert_eq!(rendered.subject, "Welcome to Axum Base, jane");
+    assert!(rendered.html.contains("Welcome, jane!"));
+
+    // Each save adds a version and the newest one wins
+    for subject in ["Hi {{ username }}", "Hello {{ username }}"] {
+        EmailTemplateService::create_version(
+            &test_db.pool,
+            "welcome",
+            subject,
+            "<p>{{ service_name }}</p>",
+            admin.id,
+        )
+        .await
+        .expect("Should save a version");
+    }
+    let history = EmailTemplateService::history(&test_db.pool, "welcome")
+        .await
+        .expect("Should list versions");
+    let versions: Vec<i32> = history.iter().map(|v| v.version).collect();
+    assert_eq!(versions, vec![2, 1]);
+    assert_eq!(history[0].author.as_deref(), Some("mailadmin"));
+
+    let rendered = emails::render(
+        &test_db.pool,
+        &templates,
+        spec,
+        None,
+        &spec.sample_context(None),
+    )
+    .await
+    .expect("Saved template should render");
+    assert_eq!(rendered.subject, "Hello jane");
+    assert_eq!(rendered.html, "<p>Axum Base</p>");
+
+    // A saved version that no longer renders falls back to the bundled copy
+    EmailTemplateService::create_version(
+        &test_db.pool,
+        "welcome",
+        "Hi",
+        "{{ removed_variable }}",
+        admin.id,
+    )
+    .await
+    .expect("Should save a version");
+    let rendered = emails::render(
+        &test_db.pool,
+        &templates,
+        spec,
+        None,
+        &spec.sample_context(None),
+    )
+    .await
+    .expect("Should fall back to the bundled template");
+    assert_eq!(rendered.subject, "Welcome to Axum Base, jane");
+
+    let first = EmailTemplateService::get_version(&test_db.pool, "welcome", 1)
+        .await
+        .expect("Should fetch a version")
+        .expect("Version 1 exists");
+    assert_eq!(first.subject, "Hi {{ username }}");
+}
+
+/// Test that emails are rendered in the recipient's locale
+#[tokio::test]
+async fn test_localized_email() {
+    use axum_base::emails;
+    use axum_base::services::UserService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("localeadmin", "localeadmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let cookie = login_cookie(&server, "localeadmin", "password123").await;
+
+    // The locale is normalized when the account is created
+    let response = server
+        .post("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "username": "hans",
+            "email": "hans@example.com",
+            "password": "password123",
+            "locale": "de_at"
+        }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let json: serde_json::Value = response.json();
+    let user_id = json["data"]["id"].as_i64().unwrap() as i32;
+    let locale = UserService::get_locale(&test_db.pool, user_id)
+        .await
+        .expect("Should read the locale");
+    assert_eq!(locale.as_deref(), Some("de-AT"));
+
+    let response = server
+        .post("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "username": "nobody",
+            "email": "nobody@example.com",
+            "password": "password123",
+            "locale": "not a locale"
+        }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    // de-AT falls back to the German catalog; unknown locales to English
+    let templates = axum_base::web::load_templates().expect("Templates should load");
+    let spec = emails::find("welcome").expect("welcome email is registered");
+    let context = spec.sample_context(None);
+    let rendered = emails::render(&test_db.pool, &templates, spec, locale.as_deref(), &context)
+        .await
+        .expect("Should render in German");
+    assert_eq!(rendered.subject, "Willkommen bei Axum Base, jane");
+    assert!(rendered.html.contains("<html lang=\"de\">"));
+
+    let rendered = emails::render(&test_db.pool, &templates, spec, Some("fr"), &context)
+        .await
+        .expect("Should render in English");
+    assert_eq!(rendered.subject, "Welcome to Axum Base, jane");
+}
+
+/// Test email subscription choices and token unsubscribes
+#[tokio::test]
+async fn test_email_preferences() {
+    use axum_base::services::EmailPreferenceService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let user = test_db
+        .create_test_user("subscriber", "Subscriber@example.com", "password123")
+        .await;
+    let pool = &test_db.pool;
+    let subscribed = |email: &'static str, category: &'static str, default: bool| async move {
+        EmailPreferenceService::is_subscribed(pool, email, category, default)
+            .await
+            .expect("Should read the preference")
+    };
+
+    // Without a choice the caller's default applies, also for unknown addresses
+    assert!(subscribed("subscriber@example.com", "digests", true).await);
+    assert!(!subscribed("subscriber@example.com", "product_updates", false).await);
+    assert!(subscribed("stranger@example.com", "digests", true).await);
+
+    EmailPreferenceService::set(pool, user.id, "product_updates", true)
+        .await
+        .unwrap();
+    assert!(subscribed("subscriber@example.com", "product_updates", false).await);
+
+    // One-click unsubscribe by token; addresses match case-insensitively
+    let token = EmailPreferenceService::unsubscribe_token(pool, "SUBSCRIBER@example.com")
+        .await
+        .unwrap()
+        .expect("Every user has an unsubscribe token");
+    assert!(
+        EmailPreferenceService::unsubscribe(pool, token, "digests")
+            .await
+            .unwrap()
+    );
+    assert!(!subscribed("subscriber@example.com", "digests", true).await);
+    assert!(
+        !EmailPreferenceService::unsubscribe(pool, uuid::Uuid::new_v4(), "digests")
+            .await
+            .unwrap()
+    );
+
+    let choices = EmailPreferenceService::choices(pool, user.id)
+        .await
+        .unwrap();
+    assert_eq!(choices.get("digests"), Some(&false));
+    assert_eq!(choices.get("product_updates"), Some(&true));
+}
+
+/// Test file uploads: type and size limits, downloads, and access control
+#[tokio::test]
+async fn test_file_uploads() {
+    use axum_test::multipart::{MultipartForm, Part};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    test_db
+        .create_test_user("owner", "owner@example.com", "password123")
+        .await;
+    test_db
+        .create_test_user("other", "other@example.com", "password123")
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let owner = login_cookie(&server, "owner", "password123").await;
+    let other = login_cookie(&server, "other", "password123").await;
+    let admin = login_cookie(&server, "admin", "password123").await;
+
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec();
+    let form = |data: Vec<u8>, name: &str, mime: &str| {
+        MultipartForm::new().add_part("file", Part::bytes(data).file_name(name).mime_type(mime))
+    };
+
+    // Signing in is required
+    server
+        .post("/api/uploads")
+        .multipart(form(png.clone(), "a.png", "image/png"))
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // Types outside the allow list, contents that contradict the type, and
+    // files over the limit are rejected
+    for (data, name, mime, status) in [
+        (
+            b"<html>".to_vec(),
+            "a.html",
+            "text/html",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            b"<script>".to_vec(),
+            "a.png",
+            "image/png",
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            vec![b'a'; 65 * 1024],
+            "big.txt",
+            "text/plain",
+            StatusCode::PAYLOAD_TOO_LARGE,
+        ),
+    ] {
+        server
+            .post("/api/uploads")
+            .add_header("cookie", &owner)
+            .multipart(form(data, name, mime))
+            .await
+            .assert_status(status);
+    }
+
+    let response = server
+        .post("/api/uploads")
+        .add_header("cookie", &owner)
+        .multipart(form(png.clone(), "../cat picture.png", "image/png"))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"]["filename"], "cat picture.png");
+    assert_eq!(body["data"]["size_bytes"], png.len());
+    assert!(body["data"].get("storage_key").is_none());
+    let path = format!("/files/{}", body["data"]["id"].as_str().unwrap());
+
+    let response = server.get(&path).add_header("cookie", &owner).await;
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "image/png");
+    assert_eq!(response.header("x-content-type-options"), "nosniff");
+    assert!(
+        response
+            .header("content-disposition")
+            .to_str()
+            .unwrap()
+            .starts_with("inline; filename=\"cat picture.png\"")
+    );
+    assert_eq!(response.as_bytes().as_ref(), png.as_slice());
+
+    // Other users cannot tell the file exists; admins can read it
+    server
+        .get(&path)
+        .add_header("cookie", &other)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .delete(&path)
+        .add_header("cookie", &other)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .get(&path)
+        .add_header("cookie", &admin)
+        .await
+        .assert_status_ok();
+
+    let list: serde_json::Value = server
+        .get("/api/uploads")
+        .add_header("cookie", &owner)
+        .await
+        .json();
+    assert_eq!(list["data"].as_array().unwrap().len(), 1);
+
+    server
+        .delete(&path)
+        .add_header("cookie", &owner)
+        .await
+        .assert_status_ok();
+    server
+        .get(&path)
+        .add_header("cookie", &owner)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test item attachments: upload, listing on the item, downloads, and who
+/// may delete them
+#[tokio::test]
+async fn test_item_attachments() {
+    use axum_base::testing::{CategoryFactory, ItemFactory};
+    use axum_test::multipart::{MultipartForm, Part};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    let owner = test_db
+        .create_test_user("owner", "owner@example.com", "password123")
+        .await;
+    test_db.grant_role(owner.id, "admin").await;
+    test_db
+        .create_test_user("other", "other@example.com", "password123")
+        .await;
+    let item = ItemFactory::new()
+        .title("Manual")
+        .create(&test_db.pool)
+        .await;
+    let hidden = CategoryFactory::new().hidden().create(&test_db.pool).await;
+    let hidden_item = ItemFactory::new()
+        .category(&hidden)
+        .create(&test_db.pool)
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let owner_id = owner.id;
+    let owner = login_cookie(&server, "owner", "password123").await;
+    let other = login_cookie(&server, "other", "password123").await;
+    let admin = login_cookie(&server, "admin", "password123").await;
+
+    let form = |data: &[u8], name: &str, mime: &str| {
+        MultipartForm::new().add_part(
+            "file",
+            Part::bytes(data.to_vec()).file_name(name).mime_type(mime),
+        )
+    };
+    let attachments = format!("/api/items/{}/attachments", item.id);
+
+    // Uploading takes an admin, the item must exist, and upload rules apply
+    server
+        .post(&attachments)
+        .multipart(form(b"notes", "notes.txt", "text/plain"))
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    server
+        .post(&attachments)
+        .add_header("cookie", &other)
+        .multipart(form(b"notes", "notes.txt", "text/plain"))
+        .await
+        .assert_status(StatusCode::FORBIDDEN);
+    server
+        .post(&format!("/api/items/{}/attachments", i32::MAX))
+        .add_header("cookie", &owner)
+        .multipart(form(b"notes", "notes.txt", "text/plain"))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .post(&attachments)
+        .add_header("cookie", &owner)
+        .multipart(form(b"<script>", "a.png", "image/png"))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .post(&attachments)
+        .add_header("cookie", &owner)
+        .multipart(form(b"Step one", "manual.txt", "text/plain"))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"]["item_id"], item.id);
+    assert!(body["data"].get("storage_key").is_none());
+    let id = body["data"]["id"].as_str().unwrap().to_string();
+    server
+        .post(&attachments)
+        .add_header("cookie", &admin)
+        .multipart(form(b"Step two", "other.txt", "text/plain"))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    // The item detail lists attachments oldest first, for anyone
+    let response = server.get(&format!("/api/items/{}", item.id)).await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"]["title"], "Manual");
+    let names: Vec<&str> = body["data"]["attachments"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|attachment| attachment["filename"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["manual.txt", "other.txt"]);
+    server
+        .get(&format!("/api/items/{}", hidden_item.id))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .get(&format!("/api/items/{}", hidden_item.id))
+        .add_header("cookie", &admin)
+        .await
+        .assert_status_ok();
+
+    let download = format!("/attachments/{}", id);
+    let response = server.get(&download).await;
+    response.assert_status_ok();
+    assert_eq!(response.header("x-content-type-options"), "nosniff");
+    assert_eq!(response.as_bytes().as_ref(), b"Step one");
+
+    // Only the uploader and admins may delete, and only under its own item;
+    // the uploader keeps that after losing the admin role
+    sqlx::query("DELETE FROM user_roles WHERE user_id = $1")
+        .bind(owner_id)
+        .execute(&test_db.pool)
+        .await
+        .unwrap();
+    let path = format!("{}/{}", attachments, id);
+    server
+        .delete(&path)
+        .add_header("cookie", &other)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .delete(&format!("/api/items/{}/attachments/{}", hidden_item.id, id))
+        .add_header("cookie", &admin)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .delete(&path)
+        .add_header("cookie", &owner)
+        .await
+        .assert_status_ok();
+    server
+        .get(&download)
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test avatar uploads: resizing, public serving, replacement, and removal
+#[tokio::test]
+async fn test_avatars() {
+    use axum_base::services::UserService;
+    use axum_test::multipart::{MultipartForm, Part};
+    use image::{DynamicImage, ImageFormat, RgbImage};
+    use std::io::Cursor;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let user = test_db
+        .create_test_user("jane", "jane@example.com", "password123")
+        .await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let cookie = login_cookie(&server, "jane", "password123").await;
+
+    let mut jpeg = Cursor::new(Vec::new());
+    DynamicImage::ImageRgb8(RgbImage::new(400, 300))
+        .write_to(&mut jpeg, ImageFormat::Jpeg)
+        .unwrap();
+    let form = |data: Vec<u8>| {
+        MultipartForm::new().add_part(
+            "avatar",
+            Part::bytes(data)
+                .file_name("me.jpg")
+                .mime_type("image/jpeg"),
+        )
+    };
+
+    // Signed-out users are sent to log in
+    let response = server
+        .post("/profile/avatar")
+        .multipart(form(jpeg.get_ref().clone()))
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/login");
+
+    // Anything that does not decode as an image is refused
+    let response = server
+        .post("/profile/avatar")
+        .add_header("cookie", &cookie)
+        .multipart(form(b"<svg/>".to_vec()))
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/profile");
+    assert!(
+        UserService::get_avatar_key(&test_db.pool, user.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+
+    server
+        .post("/profile/avatar")
+        .add_header("cookie", &cookie)
+        .multipart(form(jpeg.get_ref().clone()))
+        .await
+        .assert_status(StatusCode::SEE_OTHER);
+    let key = UserService::get_avatar_key(&test_db.pool, user.id)
+        .await
+        .unwrap()
+        .expect("The avatar should be saved");
+
+    // Served to anyone as a square PNG
+    let response = server.get(&format!("/avatars/{}", key)).await;
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "image/png");
+    let avatar = image::load_from_memory(response.as_bytes()).unwrap();
+    assert_eq!((avatar.width(), avatar.height()), (256, 256));
+
+    // Replacing the avatar drops the old one
+    server
+        .post("/profile/avatar")
+        .add_header("cookie", &cookie)
+        .multipart(form(jpeg.get_ref().clone()))
+        .await
+        .assert_status(StatusCode::SEE_OTHER);
+    let new_key = UserService::get_avatar_key(&test_db.pool, user.id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(new_key, key);
+    server
+        .get(&format!("/avatars/{}", key))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    server
+        .post("/profile/avatar/remove")
+        .add_header("cookie", &cookie)
+        .await
+        .assert_status(StatusCode::SEE_OTHER);
+    assert!(
+        UserService::get_avatar_key(&test_db.pool, user.id)
+            .await
+            .unwrap()
+            .is_none()
+    );
+    server
+        .get(&format!("/avatars/{}", new_key))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test the typed API client against a running server
+#[cfg(feature = "client")]
+#[tokio::test]
+async fn test_api_client() {
+    use axum_base::client::{ApiClient, ClientError, PageRequest};
+    use axum_base::models::{CreateUserRequest, HealthVerdict, UserFilter};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = ApiClient::new(format!("http://{}", addr));
+    assert_eq!(
+        client.health().await.unwrap().status,
+        HealthVerdict::Healthy
+    );
+    let items = client.items().list(PageRequest::page(1, 5)).await.unwrap();
+    assert!(items.items.len() <= 5);
+    assert_eq!(items.pagination.unwrap().per_page, 5);
+
+    // Admin endpoints need a session
+    match client.users().get(admin.id).await {
+        Err(ClientError::Api { status, code, .. }) => {
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(code, "unauthorized");
+        }
+        other => panic!("Expected 401, got {:?}", other),
+    }
+
+    client.login("admin", "password123").await.unwrap();
+    let created = client
+        .users()
+        .create(&CreateUserRequest {
+            username: "from_client".to_string(),
+            email: "client@example.com".to_string(),
+            password: "password123".to_string(),
+            roles: vec![],
+            locale: None,
+        })
+        .await
+        .unwrap();
+    let roles = client
+        .users()
+        .assign_roles(created.id, &["editor"])
+        .await
+        .unwrap()
+        .roles;
+    assert_eq!(roles, vec!["editor".to_string()]);
+    let filter = UserFilter {
+        search: Some("from_client".to_string()),
+        ..UserFilter::default()
+    };
+    let page = client
+        .users()
+        .list(&filter, PageRequest::default())
+        .await
+        .unwrap();
+    assert_eq!(page.items.len(), 1);
+
+    let upload = client
+        .uploads()
+        .upload("notes.txt", "text/plain", b"hello".to_vec())
+        .await
+        .unwrap();
+    assert_eq!(
+        client.uploads().download(upload.id).await.unwrap(),
+        b"hello"
+    );
+    client.uploads().delete(upload.id).await.unwrap();
+    assert!(client.uploads().list().await.unwrap().is_empty());
+}
+
+/// Test that the job worker drains running jobs on shutdown and releases the rest
+#[tokio::test]
+async fn test_job_worker_graceful_shutdown() {
+    use axum_base::config::AppConfig;
+    use axum_base::jobs::{self, Worker};
+    use axum_base::services::JobService;
+    use axum_base::state::AppState;
+    use std::time::Duration;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+
+    let mut config = AppConfig::default();
+    config.jobs.poll_interval_ms = 20;
+    config.jobs.shutdown_grace_secs = 1;
+    // Room for every job at once, however few CPUs the host has
+    config.jobs.concurrency = Some(4);
+    let state = AppState::new(
+        test_db.pool.clone(),
+        config,
+        std::sync::Arc::new(tera::Tera::default()),
+    );
+    let pool = &test_db.pool;
+
+    let quick = jobs::enqueue(pool, "quick", &serde_json::json!({}))
+        .await
+        .unwrap();
+    let slow = jobs::enqueue(pool, "slow", &serde_json::json!({ "secs": 60 }))
+        .await
+        .unwrap();
+    let broken = jobs::enqueue(pool, "broken", &serde_json::json!({}))
+        .await
+        .unwrap();
+
+    let worker = Worker::new(state)
+        .register("quick", |_, _| async { Ok(()) })
+        .register("slow", |_, payload: serde_json::Value| async move {
+            let secs = payload["secs"].as_u64().unwrap_or_default();
+            tokio::time::sleep(Duration::from_secs(secs)).await;
+            Ok(())
+        })
+        .register("broken", |_, _| async { Err("no luck".to_string()) });
+    let worker_id = worker.id().to_string();
+    let (shutdown, shutdown_rx) = tokio::sync::watch::channel(false);
+    let handle = tokio::spawn(worker.run(shutdown_rx));
+
+    // Wait until every job has been picked up
+    let mut slow_job = None;
+    for _ in 0..100 {
+        let job = JobService::get(pool, slow).await.unwrap().unwrap();
+        if job.status == "running" {
+            slow_job = Some(job);
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    let slow_job = slow_job.expect("The slow job should be running");
+    assert_eq!(slow_job.attempts, 1);
+    assert_eq!(slow_job.locked_by.as_deref(), Some(worker_id.as_str()));
+
+    shutdown.send(true).unwrap();
+    // Enqueued while the worker drains: polling has stopped, so it stays queued
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let late = jobs::enqueue(pool, "quick", &serde_json::json!({}))
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(5), handle)
+        .await
+        .expect("The worker should stop after the grace period")
+        .unwrap();
+
+    let job = JobService::get(pool, quick).await.unwrap().unwrap();
+    assert_eq!(job.status, "done");
+    assert!(job.finished_at.is_some());
+
+    // Failed jobs are retried later with the error recorded
+    let job = JobService::get(pool, broken).await.unwrap().unwrap();
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.attempts, 1);
+    assert_eq!(job.last_error.as_deref(), Some("no luck"));
+    assert!(job.run_at > chrono::Utc::now());
+
+    // The interrupted job is back in the queue without losing an attempt
+    let job = JobService::get(pool, slow).await.unwrap().unwrap();
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.attempts, 0);
+    assert!(job.locked_by.is_none());
+
+    let job = JobService::get(pool, late).await.unwrap().unwrap();
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.attempts, 0);
+}
+
+/// Test that claims take urgent jobs first, skip jobs that are not due, and
+/// let long-waiting low-priority jobs overtake a stream of urgent ones
+#[tokio::test]
+async fn test_job_priorities_and_delays() {
+    use axum_base::jobs::{self, JobOptions, Priority};
+    use axum_base::services::JobService;
+    use std::time::Duration;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let payload = serde_json::json!({});
+    const AGING_SECS: u64 = 60;
+
+    let next = || async {
+        JobService::claim(pool, "worker", 1, AGING_SECS)
+            .await
+            .unwrap()
+            .first()
+            .map(|job| job.id)
+    };
+    let low = JobOptions::default().priority(Priority::Low);
+    let high = JobOptions::default().priority(Priority::High);
+
+    let digest = jobs::enqueue_with(pool, "digest", &payload, low)
+        .await
+        .unwrap();
+    let normal = jobs::enqueue(pool, "normal", &payload).await.unwrap();
+    let reset = jobs::enqueue_with(pool, "reset", &payload, high)
+        .await
+        .unwrap();
+    let later = jobs::enqueue_with(
+        pool,
+        "reset",
+        &payload,
+        high.delay(Duration::from_secs(3600)),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(next().await, Some(reset));
+    assert_eq!(next().await, Some(normal));
+    assert_eq!(next().await, Some(digest));
+
+    // Delayed jobs wait until they are due
+    assert_eq!(next().await, None);
+    let job = JobService::get(pool, later).await.unwrap().unwrap();
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.priority, Priority::High.level());
+    assert!(job.run_at > chrono::Utc::now() + chrono::Duration::minutes(59));
+
+    // A digest waiting three aging intervals outranks fresh urgent mail...
+    let waited = |intervals: i64| {
+        low.run_at(
+            chrono::Utc::now() - chrono::Duration::seconds(intervals * AGING_SECS as i64 + 1),
+        )
+    };
+    let starved = jobs::enqueue_with(pool, "digest", &payload, waited(3))
+        .await
+        .unwrap();
+    let recent = jobs::enqueue_with(pool, "digest", &payload, waited(1))
+        .await
+        .unwrap();
+    let mut urgent = Vec::new();
+    for _ in 0..3 {
+        urgent.push(
+            jobs::enqueue_with(pool, "reset", &payload, high)
+                .await
+                .unwrap(),
+        );
+    }
+    assert_eq!(next().await, Some(starved));
+
+    // ...while one that waited less still yields to it, oldest first
+    for id in urgent {
+        assert_eq!(next().await, Some(id));
+    }
+    assert_eq!(next().await, Some(recent));
+    assert_eq!(next().await, None);
+}
+
+/// Test that the watchdog takes back jobs of a stalled worker, alerts once,
+/// and that readiness and the jobs overview report worker liveness
+#[tokio::test]
+async fn test_job_watchdog_and_worker_liveness() {
+    use axum_base::config::AppConfig;
+    use axum_base::jobs;
+    use axum_base::services::JobService;
+    use axum_base::state::AppState;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let mut config = AppConfig::default();
+    config.jobs.alert_email = Some("ops@example.com".to_string());
+    let state = AppState::new(
+        pool.clone(),
+        config,
+        std::sync::Arc::new(tera::Tera::default()),
+    );
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    // No worker yet: not ready
+    let response = server.get("/health/ready").await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["ready"], false);
+    assert_eq!(json["data"]["database"], true);
+
+    // A worker claims a job, then goes silent
+    let job_id = jobs::enqueue(pool, "slow", &serde_json::json!({}))
+        .await
+        .unwrap();
+    let claimed = JobService::claim(pool, "dead-worker", 1, 60).await.unwrap();
+    assert_eq!(claimed[0].id, job_id);
+    JobService::heartbeat(pool, "dead-worker", "host-a", &[job_id])
+        .await
+        .unwrap();
+    sqlx::query(
+        "UPDATE job_workers SET heartbeat_at = NOW() - INTERVAL '5 minutes' WHERE id = 'dead-worker'",
+    )
+    .execute(pool)
+    .await
+    .unwrap();
+    sqlx::query("UPDATE jobs SET heartbeat_at = NOW() - INTERVAL '5 minutes' WHERE id = $1")
+        .bind(job_id)
+        .execute(pool)
+        .await
+        .unwrap();
+
+    let requeued = jobs::watchdog(&state).await.unwrap();
+    assert_eq!(requeued.len(), 1);
+    let job = JobService::get(pool, job_id).await.unwrap().unwrap();
+    assert_eq!(job.status, "queued");
+    assert_eq!(job.attempts, 1);
+    assert!(job.locked_by.is_none());
+    assert!(job.last_error.unwrap().contains("dead-worker"));
+
+    // The stalled worker cannot record a result for the job it lost
+    assert!(
+        !JobService::complete(pool, "dead-worker", job_id)
+            .await
+            .unwrap()
+    );
+
+    // The stall is alerted once
+    assert!(jobs::watchdog(&state).await.unwrap().is_empty());
+    let alerts: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM email_outbox WHERE template = $1 AND recipient = 'ops@example.com'",
+    )
+    .bind(jobs::ALERT_TEMPLATE)
+    .fetch_one(pool)
+    .await
+    .unwrap();
+    assert_eq!(alerts, 1);
+
+    // A live worker makes the service ready
+    JobService::heartbeat(pool, "live-worker", "host-b", &[])
+        .await
+        .unwrap();
+    let response = server.get("/health/ready").await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["ready"], true);
+    assert_eq!(json["data"]["workers"]["alive"], 1);
+    assert_eq!(json["data"]["workers"]["stalled"], 1);
+
+    let response = server.get("/api/admin/jobs").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    let cookie = login_cookie(&server, "admin", "password123").await;
+    let response = server
+        .get("/api/admin/jobs")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["queued"], 1);
+    let workers = json["data"]["workers"].as_array().unwrap();
+    assert_eq!(workers.len(), 2);
+    let dead = workers.iter().find(|w| w["id"] == "dead-worker").unwrap();
+    assert_eq!(dead["alive"], false);
+    assert_eq!(dead["hostname"], "host-a");
+
+    // A stopped worker is forgotten
+    JobService::deregister(pool, "live-worker").await.unwrap();
+    let response = server.get("/health/ready").await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+}
+
+/// Test that a job worker coming and going is reported as unstable, and that
+/// admins can read the probe history
+#[tokio::test]
+async fn test_health_history_and_flapping() {
+    use axum_base::services::JobService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/api/admin/health/history").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    // A steady worker is healthy
+    JobService::heartbeat(pool, "worker", "host-a", &[])
+        .await
+        .unwrap();
+    let response = server.get("/health/ready").await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["status"], "healthy");
+
+    // The worker bounces: down, up, down, up
+    for alive in [false, true, false, true] {
+        if alive {
+            JobService::heartbeat(pool, "worker", "host-a", &[])
+                .await
+                .unwrap();
+        } else {
+            JobService::deregister(pool, "worker").await.unwrap();
+        }
+        server.get("/health/ready").await;
+    }
+
+    // Up now, but still unstable, so not ready
+    let response = server.get("/health/ready").await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["ready"], false);
+    assert_eq!(json["data"]["status"], "unstable");
+    assert_eq!(json["data"]["workers"]["alive"], 1);
+
+    let cookie = login_cookie(&server, "admin", "password123").await;
+    let response = server
+        .get("/api/admin/health/history")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"]["status"], "unstable");
+    assert_eq!(json["data"]["database"], "healthy");
+    assert_eq!(json["data"]["workers"], "unstable");
+    let samples = json["data"]["samples"].as_array().unwrap();
+    // Six probes above and the one taken for this report
+    assert_eq!(samples.len(), 7);
+    assert_eq!(samples[1]["workers"], false);
+    assert_eq!(samples[6]["database"], true);
+}
+
+/// Test that sign-ins and admin actions are recorded in the audit log, and
+/// that admins can filter it
+#[tokio::test]
+async fn test_audit_log() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("admin", "admin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/api/admin/audit").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+
+    // A wrong password is recorded with the username tried
+    let (csrf_cookie, token) = login_form(&server).await;
+    server
+        .post("/login")
+        .add_header("cookie", &csrf_cookie)
+        .form(&[
+            ("username", "admin"),
+            ("password", "wrong-password"),
+            ("csrf_token", token.as_str()),
+        ])
+        .await;
+
+    let cookie = login_cookie(&server, "admin", "password123").await;
+    let response = server
+        .post("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({
+            "username": "audited",
+            "email": "audited@example.com",
+            "password": "password123",
+            "roles": ["editor"]
+        }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let user_id = response.json::<serde_json::Value>()["data"]["id"]
+        .as_i64()
+        .unwrap();
+    server
+        .put(&format!("/api/admin/users/{}/password", user_id))
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "password": "another-password" }))
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .get("/api/admin/audit")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let actions: Vec<&str> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["action"].as_str().unwrap())
+        .collect();
+    // Newest first
+    assert_eq!(
+        actions,
+        [
+            "admin.password_set",
+            "admin.user_created",
+            "login",
+            "login_failed"
+        ]
+    );
+    assert_eq!(json["meta"]["pagination"]["total"], 4);
+
+    let entries = json["data"].as_array().unwrap();
+    assert_eq!(entries[0]["actor_username"], "admin");
+    assert_eq!(entries[0]["target_type"], "user");
+    assert_eq!(entries[0]["target_id"], user_id.to_string());
+    assert!(
+        !entries[0]["details"]
+            .to_string()
+            .contains("another-password")
+    );
+    assert_eq!(entries[1]["details"]["roles"][0], "editor");
+    assert!(entries[3]["actor_id"].is_null());
+    assert_eq!(entries[3]["details"]["username"], "admin");
+    assert!(!entries[3]["details"].to_string().contains("wrong-password"));
+
+    // Filtered by action and by target
+    let response = server
+        .get("/api/admin/audit?action=login_failed")
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 1);
+
+    let response = server
+        .get(&format!(
+            "/api/admin/audit?target_type=user&target_id={}",
+            user_id
+        ))
+        .add_header("cookie", &cookie)
+        .await;
+    let json: serde_json::Value = response.json();
+    assert_eq!(json["data"].as_array().unwrap().len(), 2);
+}
+
+/// Test the OpenAPI document and Swagger UI page
+#[tokio::test]
+async fn test_openapi_docs() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/api/openapi.json").await;
+    response.assert_status_ok();
+    let json: serde_json::Value = response.json();
+    let version = json["openapi"].as_str().unwrap_or_default();
+    assert!(
+        version.starts_with("3."),
+        "Unexpected OpenAPI version: {}",
+        version
+    );
+    assert!(json["paths"]["/api/items"]["get"].is_object());
+    assert!(json["paths"]["/api/admin/users"]["post"].is_object());
+    assert!(json["components"]["schemas"]["UserResponse"].is_object());
+
+    let response = server.get("/api/docs").await;
+    response.assert_status_ok();
+    assert!(response.text().contains("/api/openapi.json"));
+}
+
+/// Test the well-known change-password redirect and unconfigured security.txt
+#[tokio::test]
+async fn test_well_known_endpoints() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/.well-known/change-password").await;
+    response.assert_status(StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/profile#change-password");
+
+    // No security contact is configured in tests, so security.txt is absent
+    let response = server.get("/.well-known/security.txt").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+
+    // WebFinger is disabled by default
+    let response = server
+        .get("/.well-known/webfinger")
+        .add_query_param("resource", "acct:testuser@localhost:3093")
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test ActivityPub actor and outbox documents
+#[cfg(feature = "activitypub")]
+#[tokio::test]
+async fn test_activitypub_actor_and_outbox() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    test_db
+        .create_test_user("fediuser", "fediuser@example.com", "password123")
+        .await;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/users/fediuser").await;
+    response.assert_status_ok();
+    assert_eq!(response.header("content-type"), "application/activity+json");
+    let actor: serde_json::Value = response.json();
+    assert_eq!(actor["type"], "Person");
+    assert_eq!(actor["preferredUsername"], "fediuser");
+    assert_eq!(actor["id"], "http://localhost:3093/users/fediuser");
+    assert_eq!(
+        actor["outbox"],
+        "http://localhost:3093/users/fediuser/outbox"
+    );
+
+    let response = server.get("/users/fediuser/outbox").await;
+    response.assert_status_ok();
+    let outbox: serde_json::Value = response.json();
+    assert_eq!(outbox["type"], "OrderedCollection");
+    assert!(outbox["orderedItems"].is_array());
+
+    let response = server.get("/users/nobody").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+/// Test the GraphQL endpoint: public queries, the viewer, and admin-only users
+#[cfg(feature = "graphql")]
+#[tokio::test]
+async fn test_graphql_queries() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("gqladmin", "gqladmin@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    test_db
+        .create_test_user("gqlreader", "gqlreader@example.com", "password123")
+        .await;
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+
+    let query = |query: &str| serde_json::json!({ "query": query });
+
+    // Signed out: public data only
+    let response = server
+        .post("/graphql")
+        .json(&query(
+            "{ me { username } categories { total items { name } } }",
+        ))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert!(body["errors"].is_null(), "unexpected errors: {}", body);
+    assert!(body["data"]["me"].is_null());
+    assert!(body["data"]["categories"]["items"].is_array());
+
+    let response = server
+        .post("/graphql")
+        .json(&query("{ users { total } }"))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["errors"][0]["message"], "Authentication required");
+    assert_eq!(body["errors"][0]["extensions"]["code"], "unauthorized");
+
+    // A reader sees themselves but not the user list
+    let cookie = login_cookie(&server, "gqlreader", "password123").await;
+    let response = server
+        .post("/graphql")
+        .add_header("cookie", &cookie)
+        .json(&query("{ me { username roles } }"))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["data"]["me"]["username"], "gqlreader");
+    assert_eq!(body["data"]["me"]["roles"], serde_json::json!([]));
+
+    let response = server
+        .post("/graphql")
+        .add_header("cookie", &cookie)
+        .json(&query("{ users { total } }"))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["errors"][0]["message"], "Admin role required");
+
+    // An admin lists users
+    let cookie = login_cookie(&server, "gqladmin", "password123").await;
+    let users = r#"{ users(search: "gql", perPage: 1) { total totalPages items { username } } }"#;
+    let response = server
+        .post("/graphql")
+        .add_header("cookie", &cookie)
+        .json(&query(users))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert!(body["errors"].is_null(), "unexpected errors: {}", body);
+    assert_eq!(body["data"]["users"]["total"], 2);
+    assert_eq!(body["data"]["users"]["totalPages"], 2);
+    assert_eq!(body["data"]["users"]["items"].as_array().unwrap().len(), 1);
+}
+
+/// Test the root endpoint serves HTML
+#[tokio::test]
+async fn test_root_endpoint() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let app = test_db.create_full_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/").await;
+    response.assert_status(StatusCode::OK);
+
+    let body = response.text();
+    // Verify it's HTML content
+    assert!(body.contains("<html") || body.contains("<!DOCTYPE"));
+    assert!(body.contains("</html>"));
+}
+
+/// Test HTML pages through the full production router
+#[tokio::test]
+#[serial]
+async fn test_login_page_endpoint() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let app = test_db.create_full_app().await;
+    let server = TestServer::new(app);
+
+    let response = server.get("/login").await;
+    response.assert_status(StatusCode::OK);
+
+    let body = response.text();
+    // Verify it's HTML content and likely contains form elements
+    assert!(body.contains("<html") || body.contains("<!DOCTYPE"));
+    assert!(body.contains(r#"name="password""#));
+
+    // Pages behind sign-in redirect
+    let response = server.get("/profile").await;
+    assert_eq!(response.header("location"), "/login");
+}
+
+/// Test the data factories: hidden categories stay out of the API, and
+/// factory users can sign in with their roles
+#[tokio::test]
+async fn test_data_factories() {
+    use axum_base::testing::{CategoryFactory, ItemFactory, UserFactory};
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let shown = CategoryFactory::new()
+        .display_name("Shown")
+        .create(pool)
+        .await;
+    let hidden = CategoryFactory::new().hidden().create(pool).await;
+    let item = ItemFactory::new()
+        .title("Factory item")
+        .category(&shown)
+        .create(pool)
+        .await;
+    assert_eq!(item.category_id, shown.id);
+    assert!(ItemFactory::new().create(pool).await.category_id > hidden.id);
+
+    let admin = UserFactory::new().admin().verified().create(pool).await;
+    assert!(admin.email.ends_with("@example.com"));
+    assert!(admin.email_verified);
+    let roles = axum_base::services::UserService::get_user_roles(pool, admin.id)
+        .await
+        .unwrap();
+    assert_eq!(roles, vec!["admin".to_string()]);
+
+    let app = test_db.create_test_app().await;
+    let server = TestServer::new(app);
+    let response = server.get("/api/categories?per_page=100").await;
+    response.assert_status(StatusCode::OK);
+    let json: serde_json::Value = serde_json::from_str(&response.text()).unwrap();
+    let ids: Vec<i64> = json["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|category| category["id"].as_i64().unwrap())
+        .collect();
+    assert!(ids.contains(&(shown.id as i64)));
+    assert!(!ids.contains(&(hidden.id as i64)));
+}
+
+/// Test database connection in test environment
+#[tokio::test]
+async fn test_database_connection() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+
+    // Test basic database connectivity
+    let result = sqlx::query("SELECT 1 as test")
+        .fetch_one(&test_db.pool)
+        .await;
+
+    assert!(result.is_ok(), "Database connection should work");
+}
+
+/// Test user creation and that isolated databases are separate
+#[tokio::test]
+async fn test_user_creation() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+
+    // Create test user
+    let user = test_db
+        .create_test_user("testuser", "test@example.com", "password123")
+        .await;
+
+    assert_eq!(user.username, "testuser");
+    assert_eq!(user.email, "test@example.com");
+    assert!(user.is_active);
+    assert!(user.password_hash.map_or(false, |hash| hash.len() > 10)); // Should have hashed password
+
+    // Verify user exists in database
+    let count: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users WHERE username = $1")
+        .bind("testuser")
+        .fetch_one(&test_db.pool)
+        .await
+        .expect("Should be able to count users");
+
+    assert_eq!(count.0, 1, "Should have exactly one test user");
+
+    // Another test's database does not see the user
+    let other_db = TestDatabase::isolated().await;
+    let count_elsewhere: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM users")
+        .fetch_one(&other_db.pool)
+        .await
+        .expect("Should be able to count users in another database");
+
+    assert_eq!(
+        count_elsewhere.0, 0,
+        "Isolated databases should not share users"
+    );
+}
+
+/// Test that a plugin's routes are served and that it hears about logins
+#[tokio::test]
+async fn test_plugins() {
+    use axum::{Router, routing::get};
+    use axum_base::prelude::*;
+    use axum_base::web::{handle_login, serve_login};
+    use std::sync::{Arc, Mutex};
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    /// Serves `/plugin/ping` and records who signs in
+    struct Recorder(Arc<Mutex<Vec<i32>>>);
+
+    #[async_trait::async_trait]
+    impl Plugin for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn routes(&self) -> Router<AppState> {
+            Router::new().route("/plugin/ping", get(|| async { "pong" }))
+        }
+
+        async fn on_event(&self, _state: &AppState, event: &Event) {
+            if let Event::UserLoggedIn { user_id } = event {
+                self.0.lock().unwrap().push(*user_id);
+            }
+        }
+    }
+
+    setup_test_env();
+    common::init_templates();
+
+    let test_db = TestDatabase::isolated().await;
+    let user = test_db
+        .create_test_user("pluginuser", "pluginuser@example.com", "password123")
+        .await;
+
+    let logins = Arc::new(Mutex::new(Vec::new()));
+    let plugins = PluginRegistry::new().with(Recorder(logins.clone()));
+    let state = AppState::new(
+        test_db.pool.clone(),
+        AppConfig::default(),
+        Arc::new(tera::Tera::default()),
+    )
+    .with_plugins(plugins);
+    let app = Router::new()
+        .route("/login", get(serve_login).post(handle_login))
+        .merge(state.plugins.routes())
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+        .with_state(state);
+    let server = TestServer::new(app);
+
+    let response = server.get("/plugin/ping").await;
+    response.assert_status(StatusCode::OK);
+    assert_eq!(response.text(), "pong");
+
+    login_cookie(&server, "pluginuser", "password123").await;
+    // Listeners run in the background
+    for _ in 0..50 {
+        if !logins.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(*logins.lock().unwrap(), [user.id]);
+}
+
+/// Test applying a migration set under its own namespace, once
+#[tokio::test]
+async fn test_namespaced_migrations() {
+    use axum_base::migrations::{self, MigrationError, MigrationSet};
+    use sqlx::migrate::{Migration, MigrationType, Migrator};
+    use std::borrow::Cow;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let namespace = "test-plugin";
+    let migrator = |sql: &'static str| Migrator {
+        migrations: Cow::Owned(vec![Migration::new(
+            1,
+            Cow::Borrowed("create plugin notes"),
+            MigrationType::Simple,
+            Cow::Borrowed(sql),
+            false,
+        )]),
+        ..Migrator::DEFAULT
+    };
+    let reset = || async {
+        sqlx::query("DROP TABLE IF EXISTS plugin_notes")
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM schema_migrations WHERE namespace = $1")
+            .bind(namespace)
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+    };
+    reset().await;
+
+    // Version 1 also exists in core; it does not clash with the plugin's
+    let set = MigrationSet::new(
+        namespace,
+        migrator("CREATE TABLE plugin_notes (id SERIAL PRIMARY KEY)"),
+    );
+    migrations::run(&test_db.pool, vec![set.clone()])
+        .await
+        .expect("Plugin migrations should apply");
+    migrations::run(&test_db.pool, vec![set])
+        .await
+        .expect("Applied migrations should be skipped");
+
+    let applied: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM schema_migrations WHERE namespace = $1")
+            .bind(namespace)
+            .fetch_one(&test_db.pool)
+            .await
+            .unwrap();
+    assert_eq!(applied, 1);
+    sqlx::query("SELECT id FROM plugin_notes")
+        .fetch_all(&test_db.pool)
+        .await
+        .expect("The plugin's table should exist");
+
+    // Editing an applied migration is refused
+    let edited = MigrationSet::new(
+        namespace,
+        migrator("CREATE TABLE plugin_notes (id BIGSERIAL PRIMARY KEY)"),
+    );
+    let result = migrations::run(&test_db.pool, vec![edited]).await;
+    assert!(matches!(
+        result,
+        Err(MigrationError::ChecksumMismatch { version: 1, .. })
+    ));
+
+    reset().await;
+}
+
+/// Test that the bootstrap admin is created once and kept in line with its settings
+#[tokio::test]
+async fn test_admin_bootstrap() {
+    use axum_base::bootstrap::{Bootstrap, bootstrap_admin};
+    use axum_base::config::BootstrapConfig;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let username = "bootstrap_admin";
+    let delete = || async {
+        sqlx::query("DELETE FROM users WHERE username = $1")
+            .bind(username)
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+    };
+    delete().await;
+
+    let mut config = BootstrapConfig::default();
+    assert_eq!(bootstrap_admin(&test_db.pool, &config).await, Ok(None));
+
+    config.admin_username = Some(username.to_string());
+    config.admin_password = Some("first password".to_string());
+    let Ok(Some(Bootstrap::Created { user_id })) = bootstrap_admin(&test_db.pool, &config).await
+    else {
+        panic!("The admin should be created");
+    };
+    assert_eq!(
+        bootstrap_admin(&test_db.pool, &config).await,
+        Ok(Some(Bootstrap::Unchanged { user_id }))
+    );
+
+    // Drift is corrected on the next start
+    sqlx::query("UPDATE users SET is_active = false WHERE id = $1")
+        .bind(user_id)
+        .execute(&test_db.pool)
+        .await
+        .unwrap();
+    sqlx::query("DELETE FROM user_roles WHERE user_id = $1")
+        .bind(user_id)
+        .execute(&test_db.pool)
+        .await
+        .unwrap();
+    config.admin_password = Some("second password".to_string());
+    assert_eq!(
+        bootstrap_admin(&test_db.pool, &config).await,
+        Ok(Some(Bootstrap::Updated {
+            user_id,
+            changes: vec!["password", "reactivated", "admin role"],
+        }))
+    );
+    let user =
+        axum_base::auth::AuthService::authenticate_user(&test_db.pool, username, "second password")
+            .await
+            .unwrap();
+    assert!(user.is_some(), "The new password should work");
+
+    delete().await;
+}
+
+/// Test that seeding adds missing fixtures once and leaves them alone after
+#[tokio::test]
+async fn test_seed_fixtures() {
+    use axum_base::seed::{Counts, Fixtures, seed};
+    use axum_base::services::UserService;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let delete = || async {
+        sqlx::query("DELETE FROM users WHERE username = 'seed_user'")
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+        sqlx::query("DELETE FROM category WHERE category_name = 'seed_category'")
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+    };
+    delete().await;
+
+    let fixtures = Fixtures::parse(
+        r#"
+        [[users]]
+        username = "seed_user"
+        email = "seed_user@example.com"
+        password = "seed-password"
+        roles = ["admin"]
+
+        [[categories]]
+        name = "seed_category"
+        display_name = "Seeded"
+
+        [[items]]
+        title = "Seeded item"
+        category = "seed_category"
+        data = { seeded = true }
+
+        [[items]]
+        title = "Welcome to Axum Base"
+        category = "general"
+        "#,
+    )
+    .unwrap();
+
+    let report = seed(&test_db.pool, &fixtures).await.unwrap();
+    assert_eq!(
+        report.users,
+        Counts {
+            created: 1,
+            existing: 0
+        }
+    );
+    assert_eq!(
+        report.categories,
+        Counts {
+            created: 1,
+            existing: 0
+        }
+    );
+    // The welcome item comes from the migrations
+    assert_eq!(
+        report.items,
+        Counts {
+            created: 1,
+            existing: 1
+        }
+    );
+
+    let report = seed(&test_db.pool, &fixtures).await.unwrap();
+    assert_eq!(
+        report.users,
+        Counts {
+            created: 0,
+            existing: 1
+        }
+    );
+    assert_eq!(
+        report.items,
+        Counts {
+            created: 0,
+            existing: 2
+        }
+    );
+
+    let user = axum_base::auth::AuthService::authenticate_user(
+        &test_db.pool,
+        "seed_user",
+        "seed-password",
+    )
+    .await
+    .unwrap()
+    .expect("The seeded password should work");
+    let roles = UserService::get_user_roles(&test_db.pool, user.id)
+        .await
+        .unwrap();
+    assert_eq!(roles, vec!["admin".to_string()]);
+
+    // Unknown categories fail the whole run
+    let broken =
+        Fixtures::parse("[[items]]\ntitle = \"Orphan\"\ncategory = \"no_such_category\"").unwrap();
+    assert!(seed(&test_db.pool, &broken).await.is_err());
+
+    delete().await;
+}
+
+/// Test that login posts without the form's token or with the honeypot
+/// filled in are turned away and counted
+#[tokio::test]
+async fn test_login_rejects_scripted_posts() {
+    use axum::{Router, routing::get};
+    use axum_base::config::AppConfig;
+    use axum_base::login_guard::LoginRejection;
+    use axum_base::metrics;
+    use axum_base::state::AppState;
+    use axum_base::web::{handle_login, serve_login};
+    use std::sync::Arc;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    setup_test_env();
+    common::init_templates();
+
+    let test_db = TestDatabase::isolated().await;
+    test_db
+        .create_test_user("botcheck", "botcheck@example.com", "password123")
+        .await;
+
+    let state = AppState::new(
+        test_db.pool.clone(),
+        AppConfig::default(),
+        Arc::new(tera::Tera::default()),
+    );
+    let app = Router::new()
+        .route("/login", get(serve_login).post(handle_login))
+        .merge(metrics::routes())
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+        .with_state(state.clone());
+    let server = TestServer::new(app);
+    let credentials = [("username", "botcheck"), ("password", "password123")];
+
+    // A script posting credentials straight to the endpoint
+    let response = server.post("/login").form(&credentials).await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert!(response.maybe_header("set-cookie").is_some());
+
+    let (cookie, token) = login_form(&server).await;
+    let response = server
+        .post("/login")
+        .add_header("cookie", &cookie)
+        .form(&[
+            ("username", "botcheck"),
+            ("password", "password123"),
+            ("csrf_token", "forged"),
+        ])
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server
+        .post("/login")
+        .add_header("cookie", &cookie)
+        .form(&[
+            ("username", "botcheck"),
+            ("password", "password123"),
+            ("csrf_token", token.as_str()),
+            ("website", "https://spam.example"),
+        ])
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+
+    for reason in LoginRejection::ALL {
+        assert_eq!(state.metrics.login_rejections(reason), 1, "{:?}", reason);
+    }
+    let response = server.get("/metrics").await;
+    response.assert_status(StatusCode::OK);
+    assert!(
+        response
+            .text()
+            .contains("login_rejected_total{reason=\"honeypot\"} 1")
+    );
+
+    // The form itself still signs in
+    login_cookie(&server, "botcheck", "password123").await;
+}
+
+/// Test that login posts are throttled per client address, taken from
+/// X-Forwarded-For only when a trusted proxy sent it
+#[tokio::test]
+async fn test_login_throttled_per_client_ip() {
+    use axum::extract::ConnectInfo;
+    use axum::{Extension, Router, routing::get};
+    use axum_base::config::AppConfig;
+    use axum_base::login_guard::RATE_LIMIT;
+    use axum_base::state::AppState;
+    use axum_base::web::{handle_login, serve_login};
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+    setup_test_env();
+    common::init_templates();
+
+    let test_db = TestDatabase::isolated().await;
+    let mut config = AppConfig::default();
+    config.server.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+    let state = AppState::new(
+        test_db.pool.clone(),
+        config,
+        Arc::new(tera::Tera::default()),
+    );
+    // Every request arrives through the proxy at 10.0.0.1
+    let app = Router::new()
+        .route("/login", get(serve_login).post(handle_login))
+        .layer(SessionManagerLayer::new(MemoryStore::default()))
+        .layer(Extension(ConnectInfo(SocketAddr::from((
+            [10, 0, 0, 1],
+            443,
+        )))))
+        .with_state(state);
+    let server = TestServer::new(app);
+    let (cookie, token) = login_form(&server).await;
+    let credentials = [
+        ("username", "nobody"),
+        ("password", "guess"),
+        ("csrf_token", token.as_str()),
+    ];
+    let attempt = |forwarded_for: &str| {
+        server
+            .post("/login")
+            .add_header("cookie", &cookie)
+            .add_header("x-forwarded-for", forwarded_for)
+            .form(&credentials)
+    };
+
+    for _ in 0..RATE_LIMIT {
+        let response = attempt("203.0.113.1").await;
+        assert_ne!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+    }
+    let response = attempt("203.0.113.1").await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    let retry_after: u64 = response
+        .header("retry-after")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!((1..=300).contains(&retry_after));
+
+    // A header the client sent itself does not change its address
+    let response = attempt("198.51.100.5, 203.0.113.1").await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    // Other clients behind the proxy are unaffected
+    let response = attempt("203.0.113.2").await;
+    assert_ne!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+/// Test that signing in and changing one's own roles give the session a new
+/// ID, so an ID planted or learned before stops working
+#[tokio::test]
+async fn test_session_id_renewed() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("fixation", "fixation@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    let server = TestServer::new(test_db.create_test_app().await);
+
+    // A failed sign-in leaves a session holding the error flash
+    let (csrf_cookie, token) = login_form(&server).await;
+    let response = server
+        .post("/login")
+        .add_header("cookie", &csrf_cookie)
+        .form(&[
+            ("username", "fixation"),
+            ("password", "wrong"),
+            ("csrf_token", token.as_str()),
+        ])
+        .await;
+    let planted = format!("id={}", response.cookie("id").value());
+
+    // Signing in on that session moves it to a new ID
+    let response = server
+        .post("/login")
+        .add_header("cookie", format!("{}; {}", csrf_cookie, planted))
+        .form(&[
+            ("username", "fixation"),
+            ("password", "password123"),
+            ("csrf_token", token.as_str()),
+        ])
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+    let cookie = format!("id={}", response.cookie("id").value());
+    assert_ne!(cookie, planted);
+    let response = server
+        .get("/api/admin/users")
+        .add_header("cookie", &planted)
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    let response = server
+        .get("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status_ok();
+
+    // So does a change to one's own roles
+    let response = server
+        .put(&format!("/api/admin/users/{}/roles", admin.id))
+        .add_header("cookie", &cookie)
+        .json(&serde_json::json!({ "roles": ["admin", "editor"] }))
+        .await;
+    response.assert_status_ok();
+    let renewed = format!("id={}", response.cookie("id").value());
+    assert_ne!(renewed, cookie);
+    let response = server
+        .get("/api/admin/users")
+        .add_header("cookie", &cookie)
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    let response = server
+        .get("/api/admin/users")
+        .add_header("cookie", &renewed)
+        .await;
+    response.assert_status_ok();
+}
+
+/// Test that signing out everywhere ends every session of the user only
+#[tokio::test]
+#[serial]
+async fn test_logout_everywhere() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let admin = test_db
+        .create_test_user("everywhere", "everywhere@example.com", "password123")
+        .await;
+    test_db.grant_role(admin.id, "admin").await;
+    let other = test_db
+        .create_test_user("bystander", "bystander@example.com", "password123")
+        .await;
+    test_db.grant_role(other.id, "admin").await;
+    let server = TestServer::new(test_db.create_full_app().await);
+
+    let laptop = login_cookie(&server, "everywhere", "password123").await;
+    let phone = login_cookie(&server, "everywhere", "password123").await;
+    let bystander = login_cookie(&server, "bystander", "password123").await;
+
+    let response = server
+        .post("/profile/logout-all")
+        .add_header("cookie", &laptop)
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/login");
+
+    for cookie in [&laptop, &phone] {
+        let response = server
+            .get("/api/admin/users")
+            .add_header("cookie", cookie)
+            .await;
+        response.assert_status(StatusCode::UNAUTHORIZED);
+    }
+    let response = server
+        .get("/api/admin/users")
+        .add_header("cookie", &bystander)
+        .await;
+    response.assert_status_ok();
+
+    // Without a session there is nothing to end
+    let response = server.post("/profile/logout-all").await;
+    assert_eq!(response.header("location"), "/login");
+}
+
+/// Test that the session cleanup task deletes expired sessions only
+#[tokio::test]
+#[serial]
+async fn test_session_cleanup_task() {
+    use axum_base::config::AppConfig;
+    use axum_base::scheduler::delete_expired_sessions;
+    use axum_base::state::AppState;
+    use std::sync::Arc;
+    use tower_sessions_sqlx_store::PostgresStore;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    PostgresStore::new(test_db.pool.clone())
+        .migrate()
+        .await
+        .expect("Session store should migrate");
+    sqlx::query(
+        "INSERT INTO tower_sessions.session (id, data, expiry_date) VALUES
+         ('cleanup-expired', '\\x', NOW() - INTERVAL '1 minute'),
+         ('cleanup-live', '\\x', NOW() + INTERVAL '1 day')
+         ON CONFLICT (id) DO UPDATE SET expiry_date = EXCLUDED.expiry_date",
+    )
+    .execute(&test_db.pool)
+    .await
+    .unwrap();
+
+    let state = AppState::new(
+        test_db.pool.clone(),
+        AppConfig::default(),
+        Arc::new(tera::Tera::default()),
+    );
+    delete_expired_sessions(state)
+        .await
+        .expect("Cleanup should succeed");
+
+    let left: Vec<String> = sqlx::query_scalar(
+        "SELECT id FROM tower_sessions.session WHERE id LIKE 'cleanup-%' ORDER BY id",
+    )
+    .fetch_all(&test_db.pool)
+    .await
+    .unwrap();
+    assert_eq!(left, ["cleanup-live"]);
+
+    sqlx::query("DELETE FROM tower_sessions.session WHERE id LIKE 'cleanup-%'")
+        .execute(&test_db.pool)
+        .await
+        .unwrap();
+}
+
+/// Test that signed-out visitors get cached home and landing pages while
+/// signed-in users always get fresh ones
+#[tokio::test]
+#[serial]
+async fn test_page_cache() {
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    test_db
+        .create_test_user("cached", "cached@example.com", "password123")
+        .await;
+
+    let mut config = TestDatabase::test_config();
+    config.environment = axum_base::config::Environment::Test;
+    let app = test_db.create_full_app_with(config).await;
+    let server = TestServer::new(app);
+    let x_cache = |response: &axum_test::TestResponse| {
+        response
+            .headers()
+            .get("x-cache")
+            .map(|value| value.to_str().unwrap().to_string())
+    };
+
+    for path in ["/", "/landing"] {
+        let first = server.get(path).await;
+        first.assert_status_ok();
+        assert_eq!(x_cache(&first).as_deref(), Some("MISS"));
+        let second = server.get(path).await;
+        second.assert_status_ok();
+        assert_eq!(x_cache(&second).as_deref(), Some("HIT"));
+        assert_eq!(second.text(), first.text());
+        assert!(
+            second
+                .header("content-type")
+                .to_str()
+                .unwrap()
+                .starts_with("text/html")
+        );
+    }
+
+    // Each language is cached apart
+    let german = server
+        .get("/")
+        .add_header("accept-language", "de-DE,de;q=0.9")
+        .await;
+    assert_eq!(x_cache(&german).as_deref(), Some("MISS"));
+
+    // A session cookie bypasses the cache both ways
+    let cookie = login_cookie(&server, "cached", "password123").await;
+    let signed_in = server.get("/").add_header("cookie", &cookie).await;
+    signed_in.assert_status_ok();
+    assert_eq!(x_cache(&signed_in), None);
+    assert!(signed_in.text().contains("Welcome back, cached!"));
+    let anonymous = server.get("/").await;
+    assert_eq!(x_cache(&anonymous).as_deref(), Some("HIT"));
+    assert!(!anonymous.text().contains("Welcome back"));
+
+    // Other methods are never cached
+    let head = server.method(axum::http::Method::HEAD, "/").await;
+    assert_eq!(x_cache(&head), None);
+}
+
+/// Test that service queries and pool connections are reported at /metrics
+#[tokio::test]
+#[serial]
+async fn test_db_metrics() {
+    use axum_base::routes::create_router;
+    use axum_base::state::AppState;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::isolated().await;
+    let mut config = TestDatabase::test_config();
+    config.server.metrics = true;
+    let state = AppState::new(test_db.pool.clone(), config, common::init_templates());
+    let server = TestServer::new(create_router(state).await);
+
+    server.get("/api/items").await.assert_status_ok();
 
-    assert_eq!(count_after.0, 0, "Should have no users after cleanup");
+    let response = server.get("/metrics").await;
+    response.assert_status_ok();
+    let text = response.text();
+    assert!(text.contains("db_query_duration_seconds_count{query=\"ItemService::list_items\"} 1"));
+    assert!(text.contains("db_pool_connections{pool=\"primary\",state=\"idle\"}"));
+    assert!(text.contains("db_pool_connections{pool=\"primary\",state=\"in_use\"}"));
+    assert!(!text.contains("pool=\"replica\""));
 }
@@ -0,0 +1,84 @@
+//! Benchmark of batch item inserts: the same rows go in once through
+//! `ItemService::create_item` per row and once through
+//! `ItemService::create_items`, and the timings are printed. It is ignored
+//! by default, as it writes a few thousand rows:
+//!
+//! ```bash
+//! cargo test --release --test batch_insert -- --ignored --nocapture
+//! ```
+//!
+//! `BENCH_ITEMS` sets the number of rows (default 5000). The crate's tests
+//! build with the `testing` feature, which shrinks `ITEM_INSERT_CHUNK`, so
+//! the batch path sends its rows in statements of 100 here.
+
+mod common;
+
+use axum_base::models::CreateItemRequest;
+use axum_base::services::ItemService;
+use axum_base::testing::CategoryFactory;
+use common::{TestDatabase, setup_test_env};
+use std::time::{Duration, Instant};
+
+/// The batch path must beat one insert per row by at least this much
+const MIN_SPEEDUP: f64 = 5.0;
+
+fn requests(count: usize, category_id: i32, prefix: &str) -> Vec<CreateItemRequest> {
+    (0..count)
+        .map(|n| CreateItemRequest {
+            title: format!("{} {}", prefix, n),
+            description: Some("Imported by the batch insert benchmark".to_string()),
+            data: Some(serde_json::json!({ "n": n })),
+            category_id,
+        })
+        .collect()
+}
+
+fn per_row(elapsed: Duration, rows: usize) -> f64 {
+    elapsed.as_secs_f64() * 1_000_000.0 / rows as f64
+}
+
+#[tokio::test]
+#[ignore = "benchmark; run with --ignored --nocapture"]
+async fn bench_create_items() {
+    setup_test_env();
+    let rows: usize = std::env::var("BENCH_ITEMS")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(5_000);
+
+    let test_db = TestDatabase::isolated().await;
+    let pool = &test_db.pool;
+    let category = CategoryFactory::new().create(pool).await;
+
+    let single = requests(rows, category.id, "Single");
+    let started = Instant::now();
+    for request in &single {
+        ItemService::create_item(pool, request).await.unwrap();
+    }
+    let single_elapsed = started.elapsed();
+
+    let batch = requests(rows, category.id, "Batch");
+    let started = Instant::now();
+    let created = ItemService::create_items(pool, &batch).await.unwrap();
+    let batch_elapsed = started.elapsed();
+    assert_eq!(created.len(), rows);
+
+    let speedup = single_elapsed.as_secs_f64() / batch_elapsed.as_secs_f64();
+    println!("{} rows", rows);
+    println!(
+        "create_item per row: {:>8.1?} ({:.1} µs/row)",
+        single_elapsed,
+        per_row(single_elapsed, rows)
+    );
+    println!(
+        "create_items:        {:>8.1?} ({:.1} µs/row)",
+        batch_elapsed,
+        per_row(batch_elapsed, rows)
+    );
+    println!("speedup:             {:.1}x", speedup);
+    assert!(
+        speedup >= MIN_SPEEDUP,
+        "create_items was only {:.1}x faster",
+        speedup
+    );
+}
@@ -25,8 +25,8 @@ async fn test_create_user_cli_non_interactive() {
             "testcli@example.com",
             "password123",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute create_user command");
 
@@ -37,7 +37,7 @@ async fn test_create_user_cli_non_interactive() {
     );
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("✅ User created successfully!"));
+    assert!(stdout.contains("User created successfully!"));
     assert!(stdout.contains("Username: testcli"));
     assert!(stdout.contains("Email: testcli@example.com"));
     assert!(stdout.contains("Password: Set"));
@@ -75,8 +75,8 @@ async fn test_create_user_cli_invalid_args() {
             "user",
             "email@test.com",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute create_user command");
 
@@ -84,6 +84,7 @@ async fn test_create_user_cli_invalid_args() {
         !output.status.success(),
         "create_user should fail with invalid args"
     );
+    assert_eq!(output.status.code(), Some(64));
 
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(stderr.contains("Usage:"));
@@ -109,8 +110,8 @@ async fn test_create_user_cli_empty_email() {
             "",
             "password123",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute create_user command");
 
@@ -119,8 +120,10 @@ async fn test_create_user_cli_empty_email() {
         "create_user should fail with empty email"
     );
 
+    assert_eq!(output.status.code(), Some(4));
+
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("Error: Email cannot be empty"));
+    assert!(stderr.contains("error: Email cannot be empty"));
 
     test_db.cleanup().await;
 }
@@ -175,8 +178,8 @@ async fn test_set_password_cli_success() {
             &user_id.to_string(),
             "newpassword123",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute set_password command");
 
@@ -187,7 +190,7 @@ async fn test_set_password_cli_success() {
     );
 
     let stdout = String::from_utf8_lossy(&output.stdout);
-    assert!(stdout.contains("✅ Password set successfully"));
+    assert!(stdout.contains("Password set successfully"));
     assert!(stdout.contains(&format!("user ID {}", user_id)));
 
     // Verify password was changed in database
@@ -207,6 +210,55 @@ async fn test_set_password_cli_success() {
     test_db.cleanup().await;
 }
 
+/// Test the set_password CLI binary generates a password and flags a change
+#[tokio::test]
+#[serial]
+async fn test_set_password_cli_generate() {
+    setup_test_env();
+
+    let test_db = TestDatabase::new().await;
+    test_db.cleanup().await;
+    let user = test_db
+        .create_test_user("resetuser", "resetuser@example.com", "oldpassword123")
+        .await;
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "set_password",
+            "--",
+            "--quiet",
+            &user.id.to_string(),
+            "--generate",
+            "--require-change",
+        ])
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
+        .output()
+        .expect("Failed to execute set_password command");
+
+    assert!(
+        output.status.success(),
+        "set_password --generate should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    // Only the password is printed under --quiet
+    let password = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    assert_eq!(password.len(), 20);
+
+    let row = sqlx::query("SELECT password_hash, must_change_password FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&test_db.pool)
+        .await
+        .expect("Should find updated user");
+    let hash = row.get::<String, _>("password_hash");
+    assert!(axum_base::auth::PasswordService::verify_password(&password, &hash).unwrap());
+    assert!(row.get::<bool, _>("must_change_password"));
+
+    test_db.cleanup().await;
+}
+
 /// Test the set_password CLI binary with invalid user ID
 #[tokio::test]
 #[serial]
@@ -226,8 +278,8 @@ async fn test_set_password_cli_invalid_user_id() {
             "99999",
             "newpassword123",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute set_password command");
 
@@ -236,8 +288,10 @@ async fn test_set_password_cli_invalid_user_id() {
         "set_password should fail with invalid user ID"
     );
 
+    assert_eq!(output.status.code(), Some(5));
+
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("❌ Failed to set password"));
+    assert!(stderr.contains("error: Failed to set password"));
 
     test_db.cleanup().await;
 }
@@ -251,8 +305,8 @@ async fn test_set_password_cli_invalid_args() {
     // Test with wrong number of arguments
     let output = Command::new("cargo")
         .args(&["run", "--bin", "set_password", "--", "123"])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute set_password command");
 
@@ -260,6 +314,7 @@ async fn test_set_password_cli_invalid_args() {
         !output.status.success(),
         "set_password should fail with invalid args"
     );
+    assert_eq!(output.status.code(), Some(64));
 
     let stderr = String::from_utf8_lossy(&output.stderr);
     assert!(stderr.contains("Usage:"));
@@ -281,8 +336,8 @@ async fn test_set_password_cli_non_numeric_user_id() {
             "notanumber",
             "password123",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute set_password command");
 
@@ -291,8 +346,10 @@ async fn test_set_password_cli_non_numeric_user_id() {
         "set_password should fail with non-numeric user ID"
     );
 
+    assert_eq!(output.status.code(), Some(4));
+
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("Error: User ID must be a valid number"));
+    assert!(stderr.contains("error: User ID must be a valid number"));
 }
 
 /// Test the set_password CLI binary with short password
@@ -318,8 +375,8 @@ async fn test_set_password_cli_short_password() {
             &user.id.to_string(),
             "short",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute set_password command");
 
@@ -328,8 +385,10 @@ async fn test_set_password_cli_short_password() {
         "set_password should fail with short password"
     );
 
+    assert_eq!(output.status.code(), Some(4));
+
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("Error: Password must be at least 8 characters long"));
+    assert!(stderr.contains("error: Password must be at least 8 characters long"));
 
     test_db.cleanup().await;
 }
@@ -365,8 +424,8 @@ async fn test_create_user_cli_duplicate_username() {
             "second@example.com",
             "password456",
         ])
-        .env("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test")
-        .env("DATABASE_URL", "postgresql://localhost/axum_base_test")
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
         .output()
         .expect("Failed to execute create_user command");
 
@@ -375,8 +434,473 @@ async fn test_create_user_cli_duplicate_username() {
         "create_user should fail with duplicate username"
     );
 
+    assert_eq!(output.status.code(), Some(4));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("error: Failed to create user"));
+
+    test_db.cleanup().await;
+}
+
+/// Test the admin CLI binary without a command
+#[tokio::test]
+#[serial]
+async fn test_admin_cli_invalid_args() {
+    setup_test_env();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "unknown-command"])
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert!(
+        !output.status.success(),
+        "admin should fail with an unknown command"
+    );
+    assert_eq!(output.status.code(), Some(64));
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Usage:"));
+    assert!(stderr.contains("export-static"));
+}
+
+/// Test the server binary rejects unknown run modes before starting
+#[tokio::test]
+#[serial]
+async fn test_server_cli_invalid_run_mode() {
+    setup_test_env();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "axum-base", "--", "--daemonize"])
+        .output()
+        .expect("Failed to execute axum-base");
+    assert_eq!(output.status.code(), Some(64));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Usage:"));
+    assert!(stderr.contains("--pid-file"));
+
+    // Services exist only on Windows
+    #[cfg(unix)]
+    {
+        let output = Command::new("cargo")
+            .args(&["run", "--bin", "axum-base", "--", "service", "install"])
+            .output()
+            .expect("Failed to execute axum-base");
+        assert_eq!(output.status.code(), Some(4));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("only available on Windows"));
+    }
+}
+
+/// Test that configuration and database failures have their own exit codes
+#[tokio::test]
+#[serial]
+async fn test_cli_exit_codes_for_config_and_database() {
+    setup_test_env();
+
+    // The URLs below would also reach the query macros if `cargo run`
+    // rebuilds, so any rebuild checks them against `.sqlx` instead
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "set_password", "--", "1", "password123"])
+        .env("DATABASE_URL", "mysql://localhost/axum_base_test")
+        .env("SQLX_OFFLINE", "true")
+        .output()
+        .expect("Failed to execute set_password command");
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error: Invalid configuration"));
+
+    // Nothing listens on port 1
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "set_password", "--", "1", "password123"])
+        .env("DATABASE_URL", "postgresql://localhost:1/axum_base_test")
+        .env("SQLX_OFFLINE", "true")
+        .output()
+        .expect("Failed to execute set_password command");
+    assert_eq!(output.status.code(), Some(3));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("error: Failed to connect"));
+}
+
+/// Test the admin export-static command writes public pages
+#[tokio::test]
+#[serial]
+async fn test_admin_export_static() {
+    setup_test_env();
+
+    let output_dir = std::env::temp_dir().join(format!("axum-base-export-{}", std::process::id()));
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "export-static"])
+        .arg(&output_dir)
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert!(
+        output.status.success(),
+        "export-static should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Exported"));
+    assert!(output_dir.join("index.html").exists());
+    assert!(output_dir.join("landing/index.html").exists());
+    assert!(!output_dir.join("login/index.html").exists());
+
+    std::fs::remove_dir_all(&output_dir).ok();
+}
+
+/// Test the admin templates check command passes on the shipped templates
+#[tokio::test]
+#[serial]
+async fn test_admin_templates_check() {
+    setup_test_env();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "templates", "check"])
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert!(
+        output.status.success(),
+        "templates check should pass. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    // Piped output is plain text
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(!stdout.contains('\x1b'));
+    assert!(stdout.contains("checked against handlers"));
+
+    // Quiet mode keeps only problems
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "admin",
+            "--",
+            "--quiet",
+            "templates",
+            "check",
+        ])
+        .output()
+        .expect("Failed to execute admin command");
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+/// Test the admin export-types command prints TypeScript declarations
+#[tokio::test]
+#[serial]
+async fn test_admin_export_types() {
+    setup_test_env();
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "admin",
+            "--",
+            "export-types",
+            "--lang",
+            "ts",
+        ])
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert!(
+        output.status.success(),
+        "export-types should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("export type Envelope<T> = {"));
+    assert!(stdout.contains("export type UserResponse = {"));
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "admin",
+            "--",
+            "export-types",
+            "--lang",
+            "go",
+        ])
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert_eq!(output.status.code(), Some(4));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unsupported language"));
+}
+
+/// Test the admin console runs piped commands against the service layer
+#[tokio::test]
+#[serial]
+async fn test_admin_console_commands() {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    setup_test_env();
+
+    let test_db = TestDatabase::new().await;
+    test_db.cleanup().await;
+    test_db
+        .create_test_user("consoleuser", "console@example.com", "password123")
+        .await;
+
+    let mut child = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "console"])
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to execute admin command");
+
+    child
+        .stdin
+        .take()
+        .expect("stdin should be piped")
+        .write_all(
+            b"user console@example.com\nverify consoleuser password123\nroles consoleuser editor\nfrobnicate\nexit\n",
+        )
+        .expect("Failed to write console input");
+
+    let output = child.wait_with_output().expect("Console should exit");
+    let stdout = String::from_utf8_lossy(&output.stdout);
     let stderr = String::from_utf8_lossy(&output.stderr);
-    assert!(stderr.contains("❌ Failed to create user"));
+
+    assert!(
+        output.status.success(),
+        "console should exit cleanly. stderr: {}",
+        stderr
+    );
+    assert!(stdout.contains("consoleuser <console@example.com>"));
+    assert!(stdout.contains("✅ Password matches for consoleuser"));
+    assert!(stdout.contains("consoleuser: editor"));
+    assert!(stderr.contains("Unknown command 'frobnicate'"));
 
     test_db.cleanup().await;
 }
+
+/// Test the admin users list command filters users and prints JSON pages
+#[tokio::test]
+#[serial]
+async fn test_admin_users_list() {
+    setup_test_env();
+
+    let test_db = TestDatabase::new().await;
+    test_db.cleanup().await;
+    test_db
+        .create_test_user("listed_one", "one@list.example", "password123")
+        .await;
+    test_db
+        .create_test_user("listed_two", "two@list.example", "password123")
+        .await;
+    test_db
+        .create_test_user("unlisted", "other@example.com", "password123")
+        .await;
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "admin",
+            "--",
+            "users",
+            "list",
+            "--email-like",
+            "%@list.example",
+            "--format",
+            "json",
+            "--per-page",
+            "1",
+        ])
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert!(
+        output.status.success(),
+        "users list should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let page: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("Output should be JSON");
+    assert_eq!(page["total"], 2);
+    assert_eq!(page["total_pages"], 2);
+    assert_eq!(page["items"].as_array().unwrap().len(), 1);
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run", "--bin", "admin", "--", "users", "list", "--page", "0",
+        ])
+        .output()
+        .expect("Failed to execute admin command");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--email-like"));
+
+    test_db.cleanup().await;
+}
+
+/// Test the admin users delete command deactivates, then deletes with --hard --yes
+#[tokio::test]
+#[serial]
+async fn test_admin_users_delete() {
+    setup_test_env();
+
+    let test_db = TestDatabase::new().await;
+    test_db.cleanup().await;
+    let user = test_db
+        .create_test_user("leaving", "leaving@example.com", "password123")
+        .await;
+
+    let admin = |args: &[&str]| {
+        Command::new("cargo")
+            .args(&["run", "--bin", "admin", "--", "users", "delete"])
+            .args(args)
+            .env("TEST_DATABASE_URL", common::test_database_url())
+            .env("DATABASE_URL", common::test_database_url())
+            .output()
+            .expect("Failed to execute admin command")
+    };
+
+    let output = admin(&["leaving"]);
+    assert!(
+        output.status.success(),
+        "soft delete should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let is_active: bool = sqlx::query_scalar("SELECT is_active FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&test_db.pool)
+        .await
+        .unwrap();
+    assert!(!is_active);
+
+    // --hard without --yes is refused before touching the database
+    let output = admin(&["leaving", "--hard"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--yes"));
+
+    let output = admin(&["leaving", "--hard", "--yes"]);
+    assert!(
+        output.status.success(),
+        "hard delete should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE id = $1")
+        .bind(user.id)
+        .fetch_one(&test_db.pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+
+    let output = admin(&["leaving"]);
+    assert_eq!(output.status.code(), Some(5));
+
+    test_db.cleanup().await;
+}
+
+/// Test the admin users export command writes CSV without password hashes
+#[tokio::test]
+#[serial]
+async fn test_admin_users_export() {
+    setup_test_env();
+
+    let test_db = TestDatabase::new().await;
+    test_db.cleanup().await;
+    test_db
+        .create_test_user("exported", "exported@export.example", "password123")
+        .await;
+    test_db
+        .create_test_user("skipped", "skipped@example.com", "password123")
+        .await;
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "admin",
+            "--",
+            "users",
+            "export",
+            "--email-like",
+            "%@export.example",
+        ])
+        .env("TEST_DATABASE_URL", common::test_database_url())
+        .env("DATABASE_URL", common::test_database_url())
+        .output()
+        .expect("Failed to execute admin command");
+
+    assert!(
+        output.status.success(),
+        "users export should succeed. stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let csv = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 2, "header and one user: {}", csv);
+    assert!(lines[0].starts_with("id,username,email,"));
+    assert!(lines[1].contains("exported@export.example"));
+    assert!(!csv.contains("$argon2"));
+
+    test_db.cleanup().await;
+}
+
+/// Test the admin db restore command checks its arguments before prompting
+#[tokio::test]
+#[serial]
+async fn test_admin_db_restore_arguments() {
+    setup_test_env();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "db", "restore"])
+        .output()
+        .expect("Failed to execute admin command");
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("db restore <file>"));
+
+    let output = Command::new("cargo")
+        .args(&[
+            "run",
+            "--bin",
+            "admin",
+            "--",
+            "db",
+            "restore",
+            "no-such-backup.dump",
+            "--yes",
+        ])
+        .output()
+        .expect("Failed to execute admin command");
+    assert_eq!(output.status.code(), Some(5));
+}
+
+/// Test the admin completions command prints a script and rejects other shells
+#[tokio::test]
+#[serial]
+async fn test_admin_completions() {
+    setup_test_env();
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "completions", "bash"])
+        .output()
+        .expect("Failed to execute admin command");
+    assert!(output.status.success());
+    let script = String::from_utf8_lossy(&output.stdout);
+    assert!(script.contains(r#""users ") words="list delete export" ;;"#));
+    assert!(script.contains("complete -o default -F _admin admin"));
+
+    let output = Command::new("cargo")
+        .args(&["run", "--bin", "admin", "--", "completions", "powershell"])
+        .output()
+        .expect("Failed to execute admin command");
+    assert_eq!(output.status.code(), Some(64));
+}
@@ -0,0 +1,70 @@
+//! Disposable Postgres for test runs without `TEST_DATABASE_URL`
+
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::{ImageExt, runners::SyncRunner};
+
+/// Tag of the `postgres` image the container runs
+const IMAGE_TAG: &str = "17-alpine";
+
+/// Database created in the container
+const DATABASE: &str = "axum_base_test";
+
+/// Start a Postgres container for this test binary and return its URL. It
+/// keeps running while the binary does and is removed after it exits.
+pub fn start() -> String {
+    // Called from inside test runtimes, which cannot block on another one
+    std::thread::spawn(|| {
+        let container = Postgres::default()
+            .with_db_name(DATABASE)
+            .with_tag(IMAGE_TAG)
+            .start()
+            .expect(
+                "Failed to start a Postgres container; start Docker, or set \
+                 TEST_DATABASE_URL to use an existing database",
+            );
+        let host = container.get_host().expect("Container should have a host");
+        let port = container
+            .get_host_port_ipv4(5432)
+            .expect("Container should publish Postgres");
+        remove_on_exit(container.id());
+        // Dropping it would stop the container before the tests are done
+        std::mem::forget(container);
+        format!(
+            "postgresql://postgres:postgres@{}:{}/{}",
+            host, port, DATABASE
+        )
+    })
+    .join()
+    .expect("Failed to start the test database")
+}
+
+/// Remove the container once this process has exited; statics are never
+/// dropped, so a watcher process does it
+#[cfg(unix)]
+fn remove_on_exit(container_id: &str) {
+    let watcher = format!(
+        "while kill -0 {} 2>/dev/null; do sleep 1; done; docker rm -f {} >/dev/null 2>&1",
+        std::process::id(),
+        container_id
+    );
+    let spawned = std::process::Command::new("sh")
+        .args(["-c", &watcher])
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn();
+    if let Err(e) = spawned {
+        eprintln!(
+            "Warning: container {} will outlive the tests: {}",
+            container_id, e
+        );
+    }
+}
+
+#[cfg(not(unix))]
+fn remove_on_exit(container_id: &str) {
+    eprintln!(
+        "Warning: remove the test database container when done: docker rm -f {}",
+        container_id
+    );
+}
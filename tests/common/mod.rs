@@ -1,15 +1,44 @@
+// Each test binary uses a different subset of these helpers
+#![allow(dead_code)]
+
+mod container;
+
 use axum::Router;
-use axum_base::{auth::PasswordService, database, models::User};
+use axum_base::testing::UserFactory;
+use axum_base::{database, models::User};
 use sqlx::PgPool;
-use std::sync::Once;
+use std::sync::{Arc, Once, OnceLock};
+use tera::Tera;
 
 static INIT: Once = Once::new();
+static TEMPLATES: OnceLock<Arc<Tera>> = OnceLock::new();
+
+/// Load the page templates once, for handlers that render pages such as the
+/// login form
+pub fn init_templates() -> Arc<Tera> {
+    TEMPLATES
+        .get_or_init(|| axum_base::web::init_templates().expect("Templates should load"))
+        .clone()
+}
 
 pub struct TestDatabase {
     pub pool: PgPool,
+    /// Schema of an isolated database, dropped along with it
+    schema: Option<String>,
+}
+
+/// URL of the test database server: `TEST_DATABASE_URL`, or else a
+/// disposable Postgres container started for this test binary
+pub fn test_database_url() -> String {
+    static URL: OnceLock<String> = OnceLock::new();
+    URL.get_or_init(|| std::env::var("TEST_DATABASE_URL").unwrap_or_else(|_| container::start()))
+        .clone()
 }
 
 impl TestDatabase {
+    /// `EMAIL_WEBHOOK_TOKEN` of the app built by `create_test_app`
+    pub const WEBHOOK_TOKEN: &str = "test-webhook-token";
+
     /// Initialize test database with migrations
     pub async fn new() -> Self {
         INIT.call_once(|| {
@@ -19,10 +48,54 @@ impl TestDatabase {
             }
         });
 
-        let database_url = std::env::var("TEST_DATABASE_URL")
-            .unwrap_or_else(|_| "postgresql://localhost/axum_base_test".to_string());
+        Self::connect(test_database_url(), None).await
+    }
 
-        let pool = database::init_pool_with_url(Some(&database_url))
+    /// A database of the test's own: a fresh schema with every migration
+    /// applied, dropped when this value is. Tests using it need neither
+    /// `cleanup()` nor `#[serial]`, unless they sign in through the full
+    /// router or read sessions: the session store always uses the shared
+    /// `tower_sessions.session` table, so those tests stay `#[serial]`.
+    pub async fn isolated() -> Self {
+        let schema = format!("test_{}", uuid::Uuid::new_v4().simple());
+        let server = PgPool::connect(&test_database_url())
+            .await
+            .expect("Failed to connect to test database");
+        // A migration creates its extension in the first schema on the path;
+        // with the extension already in public, it never lands in a test
+        // schema and disappears when that schema is dropped
+        static EXTENSIONS: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+        EXTENSIONS
+            .get_or_init(|| async {
+                sqlx::query("CREATE EXTENSION IF NOT EXISTS pg_trgm SCHEMA public")
+                    .execute(&server)
+                    .await
+                    .expect("Failed to create extensions");
+            })
+            .await;
+        sqlx::query(&format!("CREATE SCHEMA {}", schema))
+            .execute(&server)
+            .await
+            .expect("Failed to create test schema");
+        server.close().await;
+
+        // Extensions such as pg_trgm live in public, so it stays on the path
+        let separator = if test_database_url().contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let url = format!(
+            "{}{}options=-c%20search_path%3D{}%2Cpublic",
+            test_database_url(),
+            separator,
+            schema
+        );
+        Self::connect(url, Some(schema)).await
+    }
+
+    async fn connect(database_url: String, schema: Option<String>) -> Self {
+        let pool = database::init_pool_with_url(&database_url)
             .await
             .expect("Failed to connect to test database");
 
@@ -32,18 +105,33 @@ impl TestDatabase {
             .await
             .expect("Failed to run migrations on test database");
 
-        Self { pool }
+        Self { pool, schema }
     }
 
-    /// Clean all tables for test isolation
+    /// Clean all tables for test isolation; isolated databases start empty
+    /// and are dropped instead
     pub async fn cleanup(&self) {
+        if self.schema.is_some() {
+            return;
+        }
         // Clean tables in reverse dependency order
         let tables = [
             "trip_cards",
             "travelers",
             "dog_sitters",
             "sessions",
+            "email_templates",
+            "email_outbox",
+            "email_suppressions",
+            "email_preferences",
+            "uploads",
+            "jobs",
+            "job_workers",
+            "audit_log",
+            "snippets",
+            "user_sessions",
             "users",
+            "tenants",
             "categories",
         ];
 
@@ -67,40 +155,161 @@ impl TestDatabase {
         }
     }
 
-    /// Create a test user and return the User struct
+    /// Create a test user and return the User struct; shorthand for a
+    /// `UserFactory` with these fields
     pub async fn create_test_user(&self, username: &str, email: &str, password: &str) -> User {
-        let password_hash =
-            PasswordService::hash_password(password).expect("Failed to hash password");
-
-        // Use a regular query to avoid type conversion issues
-        sqlx::query_as::<_, User>(
-            "INSERT INTO users (username, email, password_hash, email_verified, is_active, created_at, updated_at)
-             VALUES ($1, $2, $3, false, true, NOW(), NOW())
-             RETURNING id, username, email, password_hash, email_verified, is_active, 
-                       created_at, updated_at, last_login"
+        UserFactory::new()
+            .username(username)
+            .email(email)
+            .with_password(password)
+            .create(&self.pool)
+            .await
+    }
+
+    /// Grant a role to a user
+    pub async fn grant_role(&self, user_id: i32, role: &str) {
+        sqlx::query("INSERT INTO user_roles (user_id, role) VALUES ($1, $2)")
+            .bind(user_id)
+            .bind(role)
+            .execute(&self.pool)
+            .await
+            .expect("Failed to grant role");
+    }
+
+    /// Configuration of the apps built by `create_test_app` and
+    /// `create_full_app`
+    pub fn test_config() -> axum_base::config::AppConfig {
+        let mut config = axum_base::config::AppConfig::default();
+        config.mail.webhook_token = Some(Self::WEBHOOK_TOKEN.to_string());
+        config.uploads.dir = std::env::temp_dir()
+            .join("axum-base-test-uploads")
+            .display()
+            .to_string();
+        config.uploads.max_bytes = 64 * 1024;
+        config
+    }
+
+    /// Build the production router, HTML pages included, around its own
+    /// template engine loaded from the crate's `templates/` directory. Its
+    /// sessions live outside any isolated schema; tests using it are
+    /// `#[serial]`.
+    pub async fn create_full_app(&self) -> Router {
+        self.create_full_app_with(Self::test_config()).await
+    }
+
+    /// [`create_full_app`](Self::create_full_app) with `config` instead of
+    /// the test defaults
+    pub async fn create_full_app_with(&self, config: axum_base::config::AppConfig) -> Router {
+        let templates = axum_base::web::load_templates_from(
+            &std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("templates"),
         )
-        .bind(username)
-        .bind(email)
-        .bind(password_hash)
-        .fetch_one(&self.pool)
-        .await
-        .expect("Failed to create test user")
+        .expect("Templates should load");
+        let state = axum_base::state::AppState::new(self.pool.clone(), config, Arc::new(templates));
+        axum_base::routes::create_router(state).await
     }
 
     /// Create a testable Axum app instance with test database  
     /// This creates a test router with only API endpoints to avoid template issues
     pub async fn create_test_app(&self) -> Router {
         use axum::{Router, routing::get};
-        use axum_base::api::{api_hello, health_check};
-        use axum_base::web::handler_404;
+        use axum_base::admin;
+        use axum_base::api::{
+            api_hello, get_item, health_check, health_live, health_ready, list_categories,
+            list_items,
+        };
+        use axum_base::attachments;
+        use axum_base::audit;
+        use axum_base::avatars;
+        use axum_base::bounces;
+        #[cfg(feature = "graphql")]
+        use axum_base::config::AppConfig;
+        use axum_base::health;
+        use axum_base::jobs;
+        use axum_base::openapi;
+        use axum_base::search;
+        use axum_base::snippets;
+        use axum_base::sql_console;
+        use axum_base::state::AppState;
+        use axum_base::suggest;
+        use axum_base::tenants;
+        use axum_base::uploads;
+        use axum_base::web::{handle_login, handler_404, serve_login};
+        use axum_base::well_known;
+        use tower_sessions::{MemoryStore, SessionManagerLayer};
+
+        let config = Self::test_config();
+        let upload_routes = uploads::routes(&config.uploads);
+        let avatar_routes = avatars::routes(&config.uploads);
+        let attachment_routes = attachments::routes(&config.uploads);
+        let well_known_routes = well_known::routes(&config);
+        let state = AppState::new(self.pool.clone(), config, Arc::new(tera::Tera::default()));
+        // The login form hands out the token a login post must carry
+        init_templates();
 
         // Create a simplified router for testing that doesn't require templates
         // API endpoints should only return JSON, not HTML
-        Router::new()
+        let router = Router::new()
             .route("/health", get(health_check))
+            .route("/health/live", get(health_live))
+            .route("/health/ready", get(health_ready))
             .route("/api/hello", get(api_hello))
+            .route("/api/items", get(list_items))
+            .route("/api/items/{id}", get(get_item))
+            .route("/api/categories", get(list_categories))
+            .merge(suggest::routes())
+            .merge(search::routes())
+            // Login, so session-protected APIs can be tested
+            .route("/login", get(serve_login).post(handle_login))
+            .merge(openapi::routes())
+            .merge(admin::routes())
+            .merge(audit::routes())
+            .merge(sql_console::routes())
+            .merge(tenants::routes())
+            .merge(snippets::routes())
+            .merge(bounces::routes())
+            .merge(jobs::routes())
+            .merge(health::routes())
+            .merge(upload_routes)
+            .merge(avatar_routes)
+            .merge(attachment_routes)
+            .merge(well_known_routes);
+
+        #[cfg(feature = "activitypub")]
+        let router = router.merge(axum_base::activitypub::routes());
+        #[cfg(feature = "graphql")]
+        let router = router.merge(axum_base::graphql::routes(&AppConfig::default()));
+
+        router
             .fallback(handler_404)
-            .with_state(self.pool.clone())
+            .layer(SessionManagerLayer::new(MemoryStore::default()))
+            .with_state(state)
+    }
+}
+
+impl Drop for TestDatabase {
+    fn drop(&mut self) {
+        let Some(schema) = self.schema.take() else {
+            return;
+        };
+        // The test's runtime may be the one dropping us, so the schema is
+        // dropped from a thread with a runtime of its own
+        let dropped = std::thread::spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to start a runtime");
+            runtime.block_on(async {
+                let server = PgPool::connect(&test_database_url()).await?;
+                sqlx::query(&format!("DROP SCHEMA IF EXISTS {} CASCADE", schema))
+                    .execute(&server)
+                    .await
+                    .map(|_| ())
+            })
+        })
+        .join();
+        if let Ok(Err(e)) = dropped {
+            eprintln!("Warning: Failed to drop test schema: {}", e);
+        }
     }
 }
 
@@ -121,9 +330,15 @@ pub fn assert_json_response_structure(body: &str, expected_fields: &[&str]) {
 
 /// Create a default test .env configuration
 pub fn setup_test_env() {
-    unsafe {
-        std::env::set_var("DATABASE_URL", "postgresql://localhost/axum_base_test");
-        std::env::set_var("TEST_DATABASE_URL", "postgresql://localhost/axum_base_test");
-        std::env::set_var("PORT", "0"); // Use random available port for tests
-    }
+    // Once, as tests running in parallel may be reading the environment
+    static ENV: Once = Once::new();
+    ENV.call_once(|| {
+        // Binaries run by the CLI tests connect through DATABASE_URL
+        let url = test_database_url();
+        unsafe {
+            std::env::set_var("DATABASE_URL", &url);
+            std::env::set_var("TEST_DATABASE_URL", &url);
+            std::env::set_var("PORT", "0"); // Use random available port for tests
+        }
+    });
 }
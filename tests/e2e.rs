@@ -0,0 +1,225 @@
+//! Browser tests against the full server: real templates, sessions, the login
+//! form guard, and the page scripts, which the API tests never load.
+//!
+//! Built only with the `e2e` feature, and they need a WebDriver server:
+//!
+//! ```bash
+//! chromedriver --port=4444 &
+//! cargo test --features e2e --test e2e
+//! ```
+//!
+//! `WEBDRIVER_URL` points at another server (default
+//! `http://localhost:4444`); the browser runs headless. The database is the
+//! one the API tests use, `TEST_DATABASE_URL`.
+
+mod common;
+
+use axum_base::config::AppConfig;
+use axum_base::routes::create_router;
+use axum_base::state::AppState;
+use common::{TestDatabase, init_templates, setup_test_env};
+use fantoccini::{Client, ClientBuilder, Locator};
+use serde_json::json;
+use serial_test::serial;
+
+const USERNAME: &str = "alice";
+const PASSWORD: &str = "password123";
+
+/// A running server and a browser pointed at it
+struct Browser {
+    client: Client,
+    base_url: String,
+    test_db: TestDatabase,
+}
+
+impl Browser {
+    /// Seed a user, serve the app on a random port, and open a browser
+    async fn start() -> Self {
+        setup_test_env();
+        let test_db = TestDatabase::new().await;
+        test_db.cleanup().await;
+        test_db
+            .create_test_user(USERNAME, "alice@example.com", PASSWORD)
+            .await;
+
+        let mut config = AppConfig::default();
+        config.server.live_reload = Some(false);
+        let state = AppState::new(test_db.pool.clone(), config, init_templates());
+        let app = create_router(state).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let webdriver =
+            std::env::var("WEBDRIVER_URL").unwrap_or_else(|_| "http://localhost:4444".to_string());
+        let mut capabilities = serde_json::Map::new();
+        capabilities.insert(
+            "goog:chromeOptions".to_string(),
+            json!({ "args": ["--headless=new", "--no-sandbox", "--window-size=1280,900"] }),
+        );
+        capabilities.insert(
+            "moz:firefoxOptions".to_string(),
+            json!({ "args": ["-headless"] }),
+        );
+        let client = ClientBuilder::rustls()
+            .expect("Failed to set up TLS for the WebDriver client")
+            .capabilities(capabilities)
+            .connect(&webdriver)
+            .await
+            .unwrap_or_else(|e| panic!("No WebDriver server at {}: {}", webdriver, e));
+
+        Self {
+            client,
+            base_url,
+            test_db,
+        }
+    }
+
+    async fn goto(&self, path: &str) {
+        self.client
+            .goto(&format!("{}{}", self.base_url, path))
+            .await
+            .unwrap();
+    }
+
+    /// Path of the page the browser is on
+    async fn path(&self) -> String {
+        self.client.current_url().await.unwrap().path().to_string()
+    }
+
+    /// Visible text of the page
+    async fn text(&self) -> String {
+        self.client
+            .find(Locator::Css("body"))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Replace the value of the input matching `selector`
+    async fn fill(&self, selector: &str, value: &str) {
+        let input = self.client.find(Locator::Css(selector)).await.unwrap();
+        input.clear().await.unwrap();
+        input.send_keys(value).await.unwrap();
+    }
+
+    /// Sign in through the login form
+    async fn log_in(&self, password: &str) {
+        self.goto("/login").await;
+        self.fill("#username", USERNAME).await;
+        self.fill("#password", password).await;
+        self.client
+            .find(Locator::Css("form[action='/login'] button[type='submit']"))
+            .await
+            .unwrap()
+            .click()
+            .await
+            .unwrap();
+    }
+
+    async fn finish(self) {
+        self.client.close().await.unwrap();
+        self.test_db.cleanup().await;
+    }
+}
+
+/// Signing in lands on the home page with the user in the navbar
+#[tokio::test]
+#[serial]
+async fn test_login() {
+    let browser = Browser::start().await;
+
+    browser.log_in("wrong password").await;
+    assert_eq!(browser.path().await, "/login");
+    assert!(
+        browser
+            .text()
+            .await
+            .contains("Invalid username or password")
+    );
+
+    browser.log_in(PASSWORD).await;
+    assert_eq!(browser.path().await, "/");
+    let text = browser.text().await;
+    assert!(text.contains("Welcome back, alice!"));
+    let menu = browser
+        .client
+        .find(Locator::Id("profileMenuButton"))
+        .await
+        .unwrap();
+    assert!(menu.text().await.unwrap().contains(USERNAME));
+
+    browser.finish().await;
+}
+
+/// The profile form saves the new email and says so
+#[tokio::test]
+#[serial]
+async fn test_profile_update() {
+    let browser = Browser::start().await;
+    browser.log_in(PASSWORD).await;
+
+    browser.goto("/profile").await;
+    browser.fill("#email", "alice@example.org").await;
+    browser
+        .client
+        .form(Locator::XPath("//form[input[@value='update_profile']]"))
+        .await
+        .unwrap()
+        .submit()
+        .await
+        .unwrap();
+
+    assert_eq!(browser.path().await, "/profile");
+    assert!(
+        browser
+            .text()
+            .await
+            .contains("Profile updated successfully!")
+    );
+    let email: String = sqlx::query_scalar("SELECT email FROM users WHERE username = $1")
+        .bind(USERNAME)
+        .fetch_one(&browser.test_db.pool)
+        .await
+        .unwrap();
+    assert_eq!(email, "alice@example.org");
+
+    browser.finish().await;
+}
+
+/// Logging out from the profile menu ends the session
+#[tokio::test]
+#[serial]
+async fn test_logout() {
+    let browser = Browser::start().await;
+    browser.log_in(PASSWORD).await;
+
+    // The menu is opened by the page script
+    browser
+        .client
+        .find(Locator::Id("profileMenuButton"))
+        .await
+        .unwrap()
+        .click()
+        .await
+        .unwrap();
+    browser
+        .client
+        .find(Locator::Css("#profileMenu form[action='/logout'] button"))
+        .await
+        .unwrap()
+        .click()
+        .await
+        .unwrap();
+
+    assert_eq!(browser.path().await, "/login");
+    assert!(browser.text().await.contains("You have been signed out."));
+
+    // Signed-out visitors are sent back to the login page
+    browser.goto("/profile").await;
+    assert_eq!(browser.path().await, "/login");
+
+    browser.finish().await;
+}
@@ -0,0 +1,135 @@
+//! Guards the stable API in `axum_base::prelude`.
+//!
+//! `tests/public-api.txt` lists the public modules and prelude exports. A
+//! change to either fails `test_public_api_snapshot` until the list is
+//! regenerated with `UPDATE_PUBLIC_API=1 cargo test --test public_api`, so
+//! breaking changes show up in review. The signature checks below fail to
+//! compile when a prelude item changes shape.
+
+use std::collections::BTreeSet;
+
+use axum_base::prelude::*;
+
+const SNAPSHOT: &str = "tests/public-api.txt";
+
+/// `mod` lines for public modules not hidden from the docs, and `use` lines
+/// for every prelude export, sorted
+fn public_api() -> String {
+    let lib = std::fs::read_to_string("src/lib.rs").expect("Failed to read src/lib.rs");
+    let prelude = std::fs::read_to_string("src/prelude.rs").expect("Failed to read prelude");
+
+    let mut items = BTreeSet::new();
+    let mut attributes = Vec::new();
+    for line in lib.lines().map(str::trim) {
+        if line.starts_with("#[") {
+            attributes.push(line);
+            continue;
+        }
+        if let Some(name) = line
+            .strip_prefix("pub mod ")
+            .and_then(|rest| rest.strip_suffix(';'))
+            && !attributes.contains(&"#[doc(hidden)]")
+        {
+            let feature = attributes
+                .iter()
+                .find_map(|attribute| attribute.strip_prefix("#[cfg(feature = \""))
+                .and_then(|rest| rest.strip_suffix("\")]"));
+            items.insert(match feature {
+                Some(feature) => format!("mod {} (feature {})", name, feature),
+                None => format!("mod {}", name),
+            });
+        }
+        attributes.clear();
+    }
+
+    let code: String = prelude
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    for statement in code.split(';') {
+        let Some(path) = statement.trim().strip_prefix("pub use ") else {
+            continue;
+        };
+        let path: String = path.split_whitespace().collect();
+        match path.split_once('{') {
+            Some((prefix, names)) => {
+                for name in names.trim_end_matches('}').split(',') {
+                    if !name.is_empty() {
+                        items.insert(format!("use {}{}", prefix, name));
+                    }
+                }
+            }
+            None => {
+                items.insert(format!("use {}", path));
+            }
+        }
+    }
+
+    items.into_iter().map(|item| item + "\n").collect()
+}
+
+#[test]
+fn test_public_api_snapshot() {
+    let current = public_api();
+    if std::env::var_os("UPDATE_PUBLIC_API").is_some() {
+        std::fs::write(SNAPSHOT, &current).expect("Failed to write snapshot");
+        return;
+    }
+
+    let expected = std::fs::read_to_string(SNAPSHOT).unwrap_or_default();
+    assert!(
+        current == expected,
+        "The public API changed. If that is intended, run \
+         `UPDATE_PUBLIC_API=1 cargo test --test public_api` and commit {}.\n\
+         Expected:\n{}\nFound:\n{}",
+        SNAPSHOT,
+        expected,
+        current
+    );
+}
+
+/// Never called; it only has to compile
+#[allow(dead_code, unused_variables)]
+fn prelude_signatures(pool: sqlx::PgPool, config: AppConfig, state: AppState) {
+    let _: fn(&str) -> Result<String, argon2::password_hash::Error> =
+        PasswordService::hash_password;
+    let _: fn(&str, &str) -> Result<bool, argon2::password_hash::Error> =
+        PasswordService::verify_password;
+    let _: fn() -> Result<AppConfig, ConfigError> = AppConfig::load;
+    let _: fn(&AppConfig) -> Result<(), ConfigError> = AppConfig::validate;
+    let _: fn(sqlx::PgPool, AppConfig, std::sync::Arc<tera::Tera>) -> AppState = AppState::new;
+    let _: &str = ADMIN_ROLE;
+    let _: u32 = ENVELOPE_VERSION;
+
+    let _: LocalStorage = LocalStorage::new("uploads");
+    let _: Envelope<Vec<Item>> = Envelope::success(Vec::new());
+    let _: AppResult<()> = Err(AppError::NotFound(String::new()));
+    let _: JobOptions = JobOptions::default().priority(Priority::High);
+    let _: Environment = config.environment;
+    let _: std::sync::Arc<dyn StorageBackend> = state.storage.clone();
+    let _: std::sync::Arc<Mailer> = state.mailer.clone();
+    let _: std::sync::Arc<dyn Authenticator> = state.auth.clone();
+    let _: AppState = state
+        .clone()
+        .with_authenticator(InMemoryAuthenticator::new());
+    let _: AppState = state.clone().with_plugins(
+        PluginRegistry::new()
+            .with_migrations(MigrationSet::new("app", sqlx::migrate!()).after("billing")),
+    );
+    let _: ScheduledTask = ScheduledTask::new(
+        "task",
+        std::time::Duration::from_secs(60),
+        |_: AppState| async { Ok(()) },
+    );
+
+    // Built but never awaited: it only has to type-check
+    let _check = async move {
+        let _: Result<Option<User>, sqlx::Error> = UserService::get_user_by_id(&pool, 1).await;
+        let _: Result<Option<AuthenticatedUser>, sqlx::Error> =
+            AuthService::authenticate_user(&pool, "", "").await;
+        let _: Result<sqlx::PgPool, sqlx::Error> = init_pool(&config.database).await;
+        let _: axum::Router = create_router(state).await;
+        let _: () = start_server_with(PluginRegistry::new()).await;
+    };
+}
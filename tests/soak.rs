@@ -0,0 +1,211 @@
+//! Soak test for the tables that grow with traffic: sessions, jobs, the
+//! email outbox, and the audit log must level off while the core cleanup
+//! tasks run, however long the traffic goes on.
+//!
+//! Each round is a simulated day of sign-ins, sign-outs, jobs, and emails,
+//! after which every timestamp is moved back a day and the core tasks run.
+//! It is ignored by default, as it signs in a few hundred times:
+//!
+//! ```bash
+//! cargo test --test soak -- --ignored
+//! ```
+//!
+//! `SOAK_ROUNDS` sets the number of days (default 40; sessions live 30).
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_base::config::AppConfig;
+use axum_base::login_guard::CSRF_COOKIE;
+use axum_base::routes::create_router;
+use axum_base::scheduler::{self, Retained};
+use axum_base::services::{EmailDeliveryService, JobService};
+use axum_base::state::AppState;
+use axum_test::TestServer;
+use common::{TestDatabase, init_templates, setup_test_env};
+use serial_test::serial;
+use sqlx::PgPool;
+
+const USERNAME: &str = "alice";
+const PASSWORD: &str = "password123";
+const SIGN_INS_PER_ROUND: usize = 5;
+const JOBS_PER_ROUND: usize = 20;
+const EMAILS_PER_ROUND: usize = 20;
+/// Retention configured for jobs, the outbox, and the audit log
+const RETENTION_DAYS: u64 = 3;
+/// Days a session lives without activity, as set in `create_router`
+const SESSION_DAYS: u64 = 30;
+
+/// Sign in through the form and return the session cookie
+async fn sign_in(server: &TestServer) -> String {
+    let form = server.get("/login").await;
+    form.assert_status(StatusCode::OK);
+    let token = form.cookie(CSRF_COOKIE).value().to_string();
+
+    let response = server
+        .post("/login")
+        .add_header("cookie", format!("{}={}", CSRF_COOKIE, token))
+        .form(&[
+            ("username", USERNAME),
+            ("password", PASSWORD),
+            ("csrf_token", token.as_str()),
+        ])
+        .await;
+    response.assert_status(StatusCode::SEE_OTHER);
+    format!("id={}", response.cookie("id").value())
+}
+
+/// A day of traffic: sign-ins (some signed out again), finished jobs, and
+/// sent emails
+async fn simulate_day(server: &TestServer, pool: &PgPool) {
+    for visitor in 0..SIGN_INS_PER_ROUND {
+        let cookie = sign_in(server).await;
+        if visitor % 2 == 0 {
+            server
+                .post("/logout")
+                .add_header("cookie", cookie)
+                .await
+                .assert_status(StatusCode::SEE_OTHER);
+        }
+    }
+
+    for n in 0..JOBS_PER_ROUND {
+        axum_base::jobs::enqueue(pool, "soak", &n).await.unwrap();
+    }
+    let claimed = JobService::claim(pool, "soak-worker", JOBS_PER_ROUND as i64, 60)
+        .await
+        .unwrap();
+    for job in claimed {
+        assert!(
+            JobService::complete(pool, "soak-worker", job.id)
+                .await
+                .unwrap()
+        );
+    }
+
+    for n in 0..EMAILS_PER_ROUND {
+        EmailDeliveryService::record(
+            pool,
+            "welcome",
+            &format!("user{}@example.com", n),
+            "Welcome",
+            "sent",
+            None,
+        )
+        .await
+        .unwrap();
+    }
+}
+
+/// Move every timestamp the cleanup tasks look at back a day
+async fn advance_day(pool: &PgPool) {
+    for statement in [
+        "UPDATE tower_sessions.session SET expiry_date = expiry_date - INTERVAL '1 day'",
+        "UPDATE jobs SET run_at = run_at - INTERVAL '1 day', created_at = created_at - INTERVAL '1 day',
+                finished_at = finished_at - INTERVAL '1 day'",
+        "UPDATE email_outbox SET created_at = created_at - INTERVAL '1 day'",
+        "UPDATE audit_log SET created_at = created_at - INTERVAL '1 day'",
+    ] {
+        sqlx::query(statement).execute(pool).await.unwrap();
+    }
+}
+
+async fn counts(pool: &PgPool) -> Vec<u64> {
+    let mut counts = Vec::new();
+    for table in Retained::ALL {
+        counts.push(table.count(pool).await.unwrap());
+    }
+    counts
+}
+
+/// Days a row of `table` outlives the day it was written
+fn window(table: Retained) -> u64 {
+    match table {
+        Retained::Sessions | Retained::UserSessions => SESSION_DAYS,
+        Retained::Jobs | Retained::EmailOutbox | Retained::AuditLog => RETENTION_DAYS,
+    }
+}
+
+/// Under steady traffic, no table holds more than its retention window's
+/// worth of rows after cleanup, and the metrics report what was pruned
+#[tokio::test]
+#[serial]
+#[ignore = "long-running; run with --ignored"]
+async fn test_tables_level_off_under_traffic() {
+    setup_test_env();
+    let rounds: u64 = std::env::var("SOAK_ROUNDS")
+        .ok()
+        .and_then(|rounds| rounds.parse().ok())
+        .unwrap_or(40);
+
+    let test_db = TestDatabase::new().await;
+    test_db.cleanup().await;
+    test_db
+        .create_test_user(USERNAME, "alice@example.com", PASSWORD)
+        .await;
+    let pool = test_db.pool.clone();
+
+    let mut config = AppConfig::default();
+    config.server.live_reload = Some(false);
+    config.retention.jobs_days = RETENTION_DAYS;
+    config.retention.outbox_days = RETENTION_DAYS;
+    config.retention.audit_days = RETENTION_DAYS;
+    let state = AppState::new(pool.clone(), config, init_templates());
+    let server = TestServer::new(create_router(state.clone()).await);
+    sqlx::query("DELETE FROM tower_sessions.session")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let tasks = scheduler::core_tasks();
+    let mut per_day = Vec::new();
+    for round in 0..rounds {
+        let before = counts(&pool).await;
+        simulate_day(&server, &pool).await;
+        if round == 0 {
+            per_day = counts(&pool)
+                .await
+                .iter()
+                .zip(&before)
+                .map(|(after, before)| after - before)
+                .collect();
+        }
+
+        advance_day(&pool).await;
+        for task in &tasks {
+            task.run(state.clone()).await.unwrap();
+        }
+
+        let left = counts(&pool).await;
+        for (i, table) in Retained::ALL.into_iter().enumerate() {
+            let bound = per_day[i] * (window(table) + 1);
+            assert!(
+                left[i] <= bound,
+                "{} holds {} rows on day {}, more than {} days of traffic ({})",
+                table.as_str(),
+                left[i],
+                round + 1,
+                window(table) + 1,
+                bound
+            );
+            assert_eq!(state.metrics.retained_rows(table), left[i]);
+        }
+    }
+
+    // Everything written outlived its window, so every table was pruned
+    for table in Retained::ALL {
+        if rounds > window(table) + 1 {
+            assert!(
+                state.metrics.pruned_rows(table) > 0,
+                "nothing was pruned from {}",
+                table.as_str()
+            );
+        }
+    }
+
+    sqlx::query("DELETE FROM tower_sessions.session")
+        .execute(&pool)
+        .await
+        .unwrap();
+    test_db.cleanup().await;
+}